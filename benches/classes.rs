@@ -0,0 +1,161 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dex::{CacheConfig, DexReader};
+
+#[path = "../tests/generator/mod.rs"]
+mod generator;
+
+use generator::Preset;
+
+/// Enumerates the classes of the example dex without touching their annotations.
+fn enumerate_classes(c: &mut Criterion) {
+    let data = std::fs::read("resources/classes.dex").expect("cannot read dex file");
+    let mut group = c.benchmark_group("classes");
+    for eager in [false, true] {
+        let name = if eager {
+            "eager-annotations"
+        } else {
+            "lazy-annotations"
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let dex = DexReader::builder()
+                    .eager_annotations(eager)
+                    .read_vec(data.clone())
+                    .expect("cannot open dex");
+                dex.classes().filter(|class| class.is_ok()).count()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Enumerates the classes of the largest generated preset on the current thread and on
+/// the threads of the global rayon pool, which share the locked caches of the `Dex`.
+#[cfg(feature = "rayon")]
+fn parallel_classes(c: &mut Criterion) {
+    use rayon::prelude::*;
+
+    let data = Preset::ALL
+        .iter()
+        .map(|preset| preset.generate(0))
+        .max_by_key(Vec::len)
+        .expect("no preset");
+    let mut group = c.benchmark_group("parallel");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let dex = DexReader::from_vec(data.clone()).expect("cannot open dex");
+            dex.classes().filter(|class| class.is_ok()).count()
+        })
+    });
+    group.bench_function(format!("{}-threads", rayon::current_num_threads()), |b| {
+        b.iter(|| {
+            let dex = DexReader::from_vec(data.clone()).expect("cannot open dex");
+            dex.par_classes().filter(|class| class.is_ok()).count()
+        })
+    });
+    group.finish();
+}
+
+#[cfg(not(feature = "rayon"))]
+fn parallel_classes(_: &mut Criterion) {}
+
+/// Resolves every type of the example dex, through the type cache and with the caches
+/// bypassed so that each descriptor is read and checked again.
+fn resolve_types(c: &mut Criterion) {
+    let data = std::fs::read("resources/classes.dex").expect("cannot read dex file");
+    let dex = DexReader::from_vec(data).expect("cannot open dex");
+    let types = dex.header().type_ids_size();
+    let resolve = |dex: &dex::Dex<Vec<u8>>| {
+        (0..types)
+            .filter(|&type_id| dex.get_type(type_id).expect("bad type").is_class())
+            .count()
+    };
+    let mut group = c.benchmark_group("types");
+    group.bench_function("cached", |b| b.iter(|| resolve(&dex)));
+    group.bench_function("uncached", |b| b.iter(|| dex.with_cache_bypassed(resolve)));
+    group.finish();
+}
+
+/// Enumerates the classes and decodes the instructions of the generated presets.
+fn generated_presets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generated");
+    group.sample_size(10);
+    for preset in &Preset::ALL {
+        let data = preset.generate(0);
+        group.bench_function(format!("{:?}", preset), |b| {
+            b.iter(|| {
+                let dex = DexReader::from_vec(data.clone()).expect("cannot open dex");
+                dex.classes()
+                    .filter_map(|class| class.ok())
+                    .flat_map(|class| {
+                        class
+                            .methods()
+                            .filter_map(|method| method.code())
+                            .map(|code| code.instructions().count())
+                            .collect::<Vec<_>>()
+                    })
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Reads the annotations of every class of the annotation heavy preset, each followed by
+/// one of a hot set of 100 classes, with the caches bounded by count or by weight, and
+/// prints the hit rates.
+fn cache_configs(c: &mut Criterion) {
+    let preset = Preset::AnnotationHeavy;
+    let classes = preset.config(0).classes;
+    let data = preset.generate(0);
+    let descriptor = |i: usize| format!("Lgen/p{}/C{};", i / 100, i);
+    let configs = [
+        ("count", CacheConfig::new().annotations_directories(128)),
+        ("weight", CacheConfig::new().max_bytes(Some(128 * 1024))),
+    ];
+    let read_annotations = |config: CacheConfig| {
+        let dex = DexReader::builder()
+            .cache_config(config)
+            .read_vec(data.clone())
+            .expect("cannot open dex");
+        for i in 0..classes {
+            for class in &[descriptor(i), descriptor(i % 100)] {
+                let class = dex.find_class_by_name(class).expect("bad class");
+                let _ = class.expect("no class").annotations();
+            }
+        }
+        dex.cache_stats()
+    };
+    let mut group = c.benchmark_group("cache");
+    group.sample_size(10);
+    for (name, config) in configs {
+        let stats = read_annotations(config);
+        for (cache, usage) in [
+            ("strings", stats.strings()),
+            ("types", stats.types()),
+            ("annotations directories", stats.annotations_directories()),
+        ] {
+            eprintln!(
+                "{}: {} hit rate {:.3}, {} entries, {} bytes",
+                name,
+                cache,
+                usage.hit_rate(),
+                usage.entries(),
+                usage.weight()
+            );
+        }
+        group.bench_function(name, |b| b.iter(|| read_annotations(config)));
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    enumerate_classes,
+    parallel_classes,
+    resolve_types,
+    generated_presets,
+    cache_configs
+);
+criterion_main!(benches);
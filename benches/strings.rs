@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dex::{container::DexContainer, DexReader};
+
+/// Deduplicates the strings of a multidex container made of copies of the example dex.
+fn deduplicate_strings(c: &mut Criterion) {
+    let data = std::fs::read("resources/classes.dex").expect("cannot read dex file");
+    let container = DexContainer::new(
+        (0..4)
+            .map(|_| DexReader::from_vec(data.clone()).expect("cannot open dex"))
+            .collect(),
+    );
+    let mut group = c.benchmark_group("strings");
+    group.bench_function("shared-string-index", |b| {
+        b.iter(|| {
+            container
+                .shared_string_index()
+                .expect("cannot build index")
+                .len()
+        })
+    });
+    group.bench_function("hash-set-union", |b| {
+        b.iter(|| {
+            let mut strings = HashSet::new();
+            for dex in container.dexes() {
+                for string in dex.strings() {
+                    strings.insert(string.expect("bad string").1.to_string());
+                }
+            }
+            strings.len()
+        })
+    });
+    group.finish();
+}
+
+/// Counts the strings containing a substring, decoding them or scanning their bytes.
+fn scan_strings(c: &mut Criterion) {
+    let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+    let mut group = c.benchmark_group("substring");
+    group.bench_function("decoded-uncached", |b| {
+        b.iter(|| {
+            (0..dex.header().string_ids_size())
+                .filter(|id| {
+                    dex.get_string_uncached(*id)
+                        .expect("bad string")
+                        .contains("Launcher")
+                })
+                .count()
+        })
+    });
+    group.bench_function("raw", |b| {
+        b.iter(|| {
+            (0..dex.header().string_ids_size())
+                .filter(|id| {
+                    dex.get_str_raw(*id)
+                        .expect("bad string")
+                        .as_bytes()
+                        .windows(8)
+                        .any(|window| window == b"Launcher")
+                })
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, deduplicate_strings, scan_strings);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use dex::DexReader;
+use dex::prelude::*;
 
 use std::io;
 
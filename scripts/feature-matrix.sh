@@ -0,0 +1,24 @@
+#!/bin/sh
+# Builds, lints and runs the unit tests of every documented feature combination: no
+# features, each subsystem alone, the defaults and all of them. Extra arguments, such as
+# `--offline`, are passed to cargo. `tests/features.rs` checks that every feature of
+# `Cargo.toml` appears here.
+set -eu
+
+run() {
+    echo "== $*"
+    cargo build "$@" $CARGO_ARGS
+    cargo clippy --all-targets "$@" $CARGO_ARGS -- -D warnings
+    cargo test --lib "$@" $CARGO_ARGS
+}
+
+CARGO_ARGS="$*"
+
+run --no-default-features
+for feature in mmap decoder debug-info signature annotations-extras analysis writer container-zip \
+    apk parse-timing cache-diagnostics rayon test-util async tracing json regex \
+    profile; do
+    run --no-default-features --features "$feature"
+done
+run
+run --all-features
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Runs the unit tests under Miri, on the default features without `mmap`: Miri can't map
+# files, and without `mmap` the crate itself forbids unsafe code, so this checks the
+# unsafe code of the dependencies as the crate uses it. Needs a nightly toolchain with the
+# `miri` component. Extra arguments are passed to cargo.
+set -eu
+
+# the tests read their fixtures from `resources`
+MIRIFLAGS="${MIRIFLAGS:-} -Zmiri-disable-isolation" \
+    cargo +nightly miri test --lib --no-default-features \
+    --features "decoder debug-info signature" "$@"
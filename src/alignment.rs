@@ -0,0 +1,48 @@
+//! Items which are not aligned as the format requires.
+//!
+//! The items made of 32-bit values, such as code items, type lists and annotation sets,
+//! must start at a 4 byte aligned offset. Crafted files break the rule and ART loads some
+//! of them anyway, so by default they are read with a warning, see
+//! `DexReaderBuilder::strict_alignment`. The values are read with `scroll`, which doesn't
+//! require aligned offsets, and views of the file reinterpreting its bytes as wider values
+//! must copy them instead when their item is misaligned.
+use std::fmt;
+
+use getset::CopyGetters;
+
+use crate::{dex::ItemType, uint};
+
+/// An item which doesn't start at an offset aligned for its type, see
+/// `Dex::misaligned_items`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, CopyGetters)]
+#[get_copy = "pub"]
+pub struct MisalignedItem {
+    /// The type of the item.
+    item_type: ItemType,
+    /// Offset of the item from the start of the file.
+    offset: uint,
+}
+
+impl MisalignedItem {
+    pub(crate) fn new(item_type: ItemType, offset: uint) -> Self {
+        Self { item_type, offset }
+    }
+
+    /// Number of bytes past the last aligned offset.
+    pub fn excess(&self) -> uint {
+        self.offset % self.item_type.alignment()
+    }
+}
+
+impl fmt::Display for MisalignedItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at offset {} is {} bytes past a {} byte boundary",
+            self.item_type,
+            self.offset,
+            self.excess(),
+            self.item_type.alignment()
+        )
+    }
+}
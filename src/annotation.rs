@@ -1,21 +1,30 @@
 //! Structures for Annotations on a `Class`, `Method`, `MethodParams` and `Field`s.
 use scroll::{ctx, Pread, Uleb128};
-use std::ops::Deref;
+use std::{collections::HashSet, fmt, ops::Deref};
+
+use crate::sync::{OnceCell, Shared};
 
 use getset::{CopyGetters, Getters};
 
 use crate::{
+    diagnostic::{Diagnostic, DiagnosticCode, Severity},
     encoded_value::EncodedValue,
     error::Error,
     field::FieldId,
     jtype::{Type, TypeId},
-    method::MethodId,
+    leb::read_uleb128_index,
+    method::{MethodId, Proto},
+    source::DexSource,
     string::{DexString, StringId},
     ubyte, uint,
 };
 
+#[cfg(feature = "annotations-extras")]
+use crate::utils::StableHasher;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+#[cfg(feature = "annotations-extras")]
+use std::hash::{Hash, Hasher};
 
 /// Contains the type and parameters of an Annotation.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#encoded-annotation)
@@ -29,10 +38,44 @@ pub struct EncodedAnnotation {
 }
 
 impl EncodedAnnotation {
+    /// An annotation which is not read from a file, see `mock`.
+    #[cfg(feature = "test-util")]
+    pub fn new(jtype: Type, elements: Vec<AnnotationElement>) -> Self {
+        Self { jtype, elements }
+    }
+
+    /// Index of the type of the annotation in the `TypeId`s list.
+    pub fn type_idx(&self) -> TypeId {
+        self.jtype.id()
+    }
+
     /// Find element with the `name`
     pub fn find_element(&self, name: &str) -> Option<&AnnotationElement> {
         self.elements().iter().find(|e| e.name() == name)
     }
+
+    /// Feeds the type and the elements, ordered by name, to `hasher`, see
+    /// `AnnotationItem::canonical_hash`.
+    #[cfg(feature = "annotations-extras")]
+    pub(crate) fn hash_canonical<S, H>(
+        &self,
+        dex: &super::Dex<S>,
+        hasher: &mut H,
+    ) -> super::Result<()>
+    where
+        S: DexSource,
+        H: Hasher,
+    {
+        (**self.jtype.type_descriptor()).hash(hasher);
+        let mut elements: Vec<&AnnotationElement> = self.elements.iter().collect();
+        elements.sort_by(|a, b| a.name.cmp(&b.name));
+        elements.len().hash(hasher);
+        for element in elements {
+            (*element.name).hash(hasher);
+            element.value.hash_canonical(dex, hasher)?;
+        }
+        Ok(())
+    }
 }
 
 impl Deref for EncodedAnnotation {
@@ -45,17 +88,17 @@ impl Deref for EncodedAnnotation {
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for EncodedAnnotation
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
 
     fn try_from_ctx(source: &'a [u8], ctx: &super::Dex<S>) -> super::Result<(Self, Self::Size)> {
         let offset = &mut 0;
-        let type_idx = Uleb128::read(source, offset)?;
-        let jtype = ctx.get_type(type_idx as TypeId)?;
+        let type_idx = read_uleb128_index(source, offset)?;
+        let jtype = ctx.get_type(type_idx)?;
         let size = Uleb128::read(source, offset)?;
-        debug!(target: "encoded-annotation", "type: {}, size: {}", jtype, size);
+        dex_debug!(ctx.log_label(), target: "encoded-annotation", "type: {}, size: {}", jtype, size);
         let elements = try_gread_vec_with!(source, offset, size, ctx);
         Ok((Self { jtype, elements }, *offset))
     }
@@ -64,30 +107,53 @@ where
 /// Represents a parameter of an annotation. For example, if `@Author(name = "Benjamin Franklin")`, is
 /// the annotation, this structure represents `name = "Benjamin Franklin"`.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#annotation-element)
-#[derive(Debug, Getters, PartialEq)]
-#[get = "pub"]
+#[derive(Debug, Getters, CopyGetters, PartialEq)]
 pub struct AnnotationElement {
+    /// Index of the name in the `StringId`s list.
+    #[get_copy = "pub"]
+    name_idx: StringId,
     /// Name of the element. Should conform to the syntax defined
     /// [here](https://source.android.com/devices/tech/dalvik/dex-format#membername)
+    #[get = "pub"]
     name: DexString,
     /// Value corresponding to the name.
+    #[get = "pub"]
     value: EncodedValue,
 }
 
+impl AnnotationElement {
+    /// An element which is not read from a file, see `mock`. Its `name_idx` is `NO_INDEX`.
+    #[cfg(feature = "test-util")]
+    pub fn new(name: &str, value: EncodedValue) -> Self {
+        Self {
+            name_idx: crate::NO_INDEX,
+            name: DexString::from(name.to_string()),
+            value,
+        }
+    }
+}
+
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for AnnotationElement
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
 
     fn try_from_ctx(source: &'a [u8], ctx: &super::Dex<S>) -> super::Result<(Self, Self::Size)> {
         let offset = &mut 0;
-        let name_idx = Uleb128::read(source, offset)?;
-        let name = ctx.get_string(name_idx as StringId)?;
-        debug!(target: "annotation-element", "annotation element: {}", name_idx);
+        let name_idx = read_uleb128_index(source, offset)?;
+        let name = ctx.get_string(name_idx)?;
+        dex_debug!(ctx.log_label(), target: "annotation-element", "annotation element: {}", name_idx);
         let value = source.gread_with(offset, ctx)?;
-        Ok((Self { name, value }, *offset))
+        Ok((
+            Self {
+                name_idx,
+                name,
+                value,
+            },
+            *offset,
+        ))
     }
 }
 
@@ -115,6 +181,53 @@ pub struct AnnotationItem {
     annotation: EncodedAnnotation,
 }
 
+impl AnnotationItem {
+    /// An annotation which is not read from a file, see `mock`.
+    #[cfg(feature = "test-util")]
+    pub fn new(visibility: Visibility, annotation: EncodedAnnotation) -> Self {
+        Self {
+            visibility,
+            annotation,
+        }
+    }
+}
+
+impl AnnotationItem {
+    /// A hash of the visibility, the type and the elements of the annotation, with the
+    /// items the values refer to resolved to their names and descriptors. Annotations with
+    /// the same content hash the same wherever they are stored, in this file or another,
+    /// and the hash is stable between runs, see [stable hashes](crate#stable-hashes). It
+    /// is not collision resistant: annotations with the same hash most likely have the
+    /// same content, but can be crafted not to.
+    #[cfg(feature = "annotations-extras")]
+    pub fn canonical_hash<S: DexSource>(&self, dex: &super::Dex<S>) -> super::Result<u64> {
+        let mut hasher = StableHasher::new();
+        self.feed_canonical(dex, &mut hasher)?;
+        Ok(hasher.finish())
+    }
+
+    /// The bytes `canonical_hash` hashes, which are equal exactly when the content is.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn canonical_bytes<S: DexSource>(
+        &self,
+        dex: &super::Dex<S>,
+    ) -> super::Result<Vec<u8>> {
+        let mut hasher = StableHasher::recording();
+        self.feed_canonical(dex, &mut hasher)?;
+        Ok(hasher.into_bytes())
+    }
+
+    #[cfg(feature = "annotations-extras")]
+    fn feed_canonical<S: DexSource>(
+        &self,
+        dex: &super::Dex<S>,
+        hasher: &mut StableHasher,
+    ) -> super::Result<()> {
+        (self.visibility as ubyte).hash(hasher);
+        self.annotation.hash_canonical(dex, hasher)
+    }
+}
+
 impl Deref for AnnotationItem {
     type Target = EncodedAnnotation;
 
@@ -125,7 +238,7 @@ impl Deref for AnnotationItem {
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for AnnotationItem
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -133,7 +246,7 @@ where
     fn try_from_ctx(source: &'a [u8], ctx: &super::Dex<S>) -> super::Result<(Self, Self::Size)> {
         let offset = &mut 0;
         let visibility: ubyte = source.gread_with(offset, ctx.get_endian())?;
-        debug!(target: "annotation-item", "visibility: {:?}", visibility);
+        dex_debug!(ctx.log_label(), target: "annotation-item", "visibility: {:?}", visibility);
         let visibility: Visibility = FromPrimitive::from_u8(visibility)
             .ok_or_else(|| Error::InvalidId("Invalid visibility for annotation".to_owned()))?;
         let annotation = source.gread_with(offset, ctx)?;
@@ -150,9 +263,38 @@ where
 /// List of Annotation Sets. Used for method parameter annotations.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#set-ref-list)
 #[derive(Debug, Default, Getters)]
-#[get = "pub"]
 pub struct AnnotationSetRefList {
+    #[get = "pub"]
     annotation_set_list: Vec<AnnotationSetItem>,
+    /// Set of the parameters missing from the list, see `aligned_to`.
+    empty_set: AnnotationSetItem,
+}
+
+impl AnnotationSetRefList {
+    /// A list which is not read from a file, see `mock`.
+    #[cfg(feature = "test-util")]
+    pub fn new(annotation_set_list: Vec<AnnotationSetItem>) -> Self {
+        Self {
+            annotation_set_list,
+            empty_set: Default::default(),
+        }
+    }
+
+    /// The sets of the `param_count` parameters of the method, see
+    /// `ParameterAnnotations::aligned_iter`.
+    pub(crate) fn aligned_to(
+        &self,
+        method_idx: MethodId,
+        param_count: usize,
+    ) -> AlignedParameterAnnotations<'_> {
+        AlignedParameterAnnotations {
+            method_idx,
+            sets: &self.annotation_set_list,
+            empty_set: &self.empty_set,
+            param_count,
+            next: 0,
+        }
+    }
 }
 
 impl Deref for AnnotationSetRefList {
@@ -165,7 +307,7 @@ impl Deref for AnnotationSetRefList {
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for AnnotationSetRefList
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -174,7 +316,7 @@ where
         let offset = &mut 0;
         let endian = ctx.get_endian();
         let size: uint = source.gread_with(offset, endian)?;
-        debug!(target: "annotation-set-ref-list", "annotation set ref list size: {}", size);
+        dex_debug!(ctx.log_label(), target: "annotation-set-ref-list", "annotation set ref list size: {}", size);
         let annotation_ref_items: Vec<uint> = try_gread_vec_with!(source, offset, size, endian);
         Ok((
             Self {
@@ -184,18 +326,128 @@ where
                         ctx.get_annotation_set_item(*annotation_set_item_off)
                     })
                     .collect::<super::Result<_>>()?,
+                empty_set: Default::default(),
             },
             *offset,
         ))
     }
 }
 
+/// The annotation sets of the parameters of a method, one per parameter whatever the
+/// length of the list in the file, see `ParameterAnnotations::aligned_iter`.
+///
+/// Lists shorter than the parameters are common: javac leaves out the synthetic parameters
+/// of the constructors of inner classes and enums. The missing trailing sets are empty.
+/// Longer lists are malformed: the surplus sets are not yielded but kept in `extras`.
+#[derive(Debug, Clone)]
+pub struct AlignedParameterAnnotations<'a> {
+    method_idx: MethodId,
+    sets: &'a [AnnotationSetItem],
+    empty_set: &'a AnnotationSetItem,
+    param_count: usize,
+    next: usize,
+}
+
+impl<'a> AlignedParameterAnnotations<'a> {
+    /// The sets listed after the last parameter.
+    pub fn extras(&self) -> &'a [AnnotationSetItem] {
+        self.sets.get(self.param_count..).unwrap_or_default()
+    }
+
+    /// The number of trailing parameters without a set in the list.
+    pub fn padded(&self) -> usize {
+        self.param_count.saturating_sub(self.sets.len())
+    }
+
+    /// Reports a list which is shorter or longer than the parameters: shorter lists are
+    /// `Severity::Info`, longer ones `Severity::Warning`.
+    pub fn diagnostic(&self) -> Option<Diagnostic> {
+        let severity = match self.sets.len() {
+            len if len < self.param_count => Severity::Info,
+            len if len > self.param_count => Severity::Warning,
+            _ => return None,
+        };
+        let message = format!(
+            "{} parameter annotation sets for {} parameters",
+            self.sets.len(),
+            self.param_count
+        );
+        Some(
+            Diagnostic::new(severity, DiagnosticCode::ParameterAnnotationCount, message)
+                .with_context(format!("method {}", self.method_idx)),
+        )
+    }
+}
+
+impl<'a> Iterator for AlignedParameterAnnotations<'a> {
+    type Item = &'a AnnotationSetItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.param_count {
+            return None;
+        }
+        let set = self.sets.get(self.next).unwrap_or(self.empty_set);
+        self.next += 1;
+        Some(set)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.param_count - self.next;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for AlignedParameterAnnotations<'_> {}
+
 /// A set of annotations on an element.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#annotation-set-item)
 #[derive(Debug, Default, Getters)]
-#[get = "pub"]
 pub struct AnnotationSetItem {
+    #[get = "pub"]
     annotations: Vec<AnnotationItem>,
+    /// Offsets of the annotations, as stored in the file.
+    item_offsets: Vec<uint>,
+}
+
+impl AnnotationSetItem {
+    /// A set which is not read from a file, see `mock`. It has no item offsets.
+    #[cfg(feature = "test-util")]
+    pub fn new(annotations: Vec<AnnotationItem>) -> Self {
+        Self {
+            annotations,
+            item_offsets: Vec::new(),
+        }
+    }
+
+    /// The offsets of the annotations of the set, as stored in the file. Sets with the
+    /// same offsets have the same annotations, without reading them.
+    pub fn item_offsets(&self) -> &[uint] {
+        &self.item_offsets
+    }
+
+    /// A hash of the `AnnotationItem::canonical_hash`es of the annotations in sorted order:
+    /// sets with the same annotations hash the same even when they are stored apart or
+    /// their annotations are. Like them, it is a 64-bit FNV-1a hash and is not collision
+    /// resistant.
+    #[cfg(feature = "annotations-extras")]
+    pub fn content_hash<S: DexSource>(&self, dex: &super::Dex<S>) -> super::Result<u64> {
+        let mut hashes = self
+            .annotations
+            .iter()
+            .map(|annotation| annotation.canonical_hash(dex))
+            .collect::<super::Result<Vec<_>>>()?;
+        hashes.sort_unstable();
+        let mut hasher = StableHasher::new();
+        hashes.hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Returns the annotation of the type with the `descriptor`, if any.
+    pub fn find(&self, descriptor: &str) -> Option<&AnnotationItem> {
+        self.annotations
+            .iter()
+            .find(|annotation| *annotation.jtype() == *descriptor)
+    }
 }
 
 impl Deref for AnnotationSetItem {
@@ -208,7 +460,7 @@ impl Deref for AnnotationSetItem {
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for AnnotationSetItem
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -217,7 +469,7 @@ where
         let offset = &mut 0;
         let endian = ctx.get_endian();
         let size: uint = source.gread_with(offset, endian)?;
-        debug!(target: "annotation-set-item", "annotation set items size: {}", size);
+        dex_debug!(ctx.log_label(), target: "annotation-set-item", "annotation set items size: {}", size);
         let annotation_items_offs: Vec<uint> = try_gread_vec_with!(source, offset, size, endian);
         Ok((
             Self {
@@ -225,6 +477,7 @@ where
                     .iter()
                     .map(|annotation_off| ctx.get_annotation_item(*annotation_off))
                     .collect::<super::Result<_>>()?,
+                item_offsets: annotation_items_offs,
             },
             *offset,
         ))
@@ -237,14 +490,24 @@ where
 pub struct ParameterAnnotations {
     /// The method this parameter belongs to.
     #[get_copy = "pub"]
-    method_idx: MethodId,
+    pub(crate) method_idx: MethodId,
     /// The list of annotation sets for the parameters.
+    #[get = "pub"]
     pub(crate) annotations: AnnotationSetRefList,
 }
 
+impl ParameterAnnotations {
+    /// Returns the sets of the parameters of the method, exactly one for each parameter of
+    /// `proto`, see `AlignedParameterAnnotations`.
+    pub fn aligned_iter(&self, proto: &Proto) -> AlignedParameterAnnotations<'_> {
+        self.annotations
+            .aligned_to(self.method_idx, proto.param_count())
+    }
+}
+
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for ParameterAnnotations
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -254,7 +517,7 @@ where
         let endian = ctx.get_endian();
         let method_idx: uint = source.gread_with(offset, endian)?;
         let annotation_set_ref_list_off: uint = source.gread_with(offset, endian)?;
-        debug!(target: "parameter-annotation", "annotation set ref list offset: {}", annotation_set_ref_list_off);
+        dex_debug!(ctx.log_label(), target: "parameter-annotation", "annotation set ref list offset: {}", annotation_set_ref_list_off);
         Ok((
             Self {
                 method_idx: MethodId::from(method_idx),
@@ -270,13 +533,14 @@ where
 #[derive(Debug, Getters, CopyGetters)]
 pub struct MethodAnnotations {
     #[get_copy = "pub"]
-    method_idx: MethodId,
+    pub(crate) method_idx: MethodId,
+    #[get = "pub"]
     pub(crate) annotations: AnnotationSetItem,
 }
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for MethodAnnotations
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -285,7 +549,7 @@ where
         let offset = &mut 0;
         let method_idx: uint = source.gread_with(offset, ctx.get_endian())?;
         let annotation_set_item_off: uint = source.gread_with(offset, ctx.get_endian())?;
-        debug!(target: "method-annotation", "annotation set item offset: {}", annotation_set_item_off);
+        dex_debug!(ctx.log_label(), target: "method-annotation", "annotation set item offset: {}", annotation_set_item_off);
         Ok((
             Self {
                 method_idx: MethodId::from(method_idx),
@@ -301,14 +565,14 @@ where
 #[derive(Debug, Getters, CopyGetters)]
 pub struct FieldAnnotations {
     #[get_copy = "pub"]
-    field_idx: FieldId,
+    pub(crate) field_idx: FieldId,
     #[get = "pub"]
     pub(crate) annotations: AnnotationSetItem,
 }
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for FieldAnnotations
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -317,7 +581,7 @@ where
         let offset = &mut 0;
         let field_idx: uint = source.gread_with(offset, ctx.get_endian())?;
         let annotation_set_item_off: uint = source.gread_with(offset, ctx.get_endian())?;
-        debug!(target: "field-annotation", "annotation set item offset: {}", annotation_set_item_off);
+        dex_debug!(ctx.log_label(), target: "field-annotation", "annotation set item offset: {}", annotation_set_item_off);
         Ok((
             Self {
                 field_idx: FieldId::from(field_idx),
@@ -338,9 +602,177 @@ pub struct AnnotationsDirectoryItem {
     pub(crate) parameter_annotations: Vec<ParameterAnnotations>,
 }
 
+/// Classes, fields and methods, which can have annotations.
+pub trait Annotated {
+    /// Annotations of the item.
+    fn annotations(&self) -> super::Result<&AnnotationSetItem>;
+
+    /// Returns the annotation of the type with the `descriptor`, if any.
+    fn find_annotation(&self, descriptor: &str) -> super::Result<Option<&AnnotationItem>> {
+        Ok(self.annotations()?.find(descriptor))
+    }
+
+    /// Returns `true` if the item has an annotation of the type with the `descriptor`.
+    fn has_annotation(&self, descriptor: &str) -> super::Result<bool> {
+        Ok(self.find_annotation(descriptor)?.is_some())
+    }
+}
+
+/// Entries of an annotations directory which refer to fields or methods that are not
+/// defined in the class owning the directory. Only found in malformed files.
+#[derive(Debug, Default, Getters)]
+#[get = "pub"]
+pub struct OrphanAnnotations<'a> {
+    /// Annotations of fields not defined in the class.
+    fields: Vec<&'a FieldAnnotations>,
+    /// Annotations of methods not defined in the class.
+    methods: Vec<&'a MethodAnnotations>,
+    /// Parameter annotations of methods not defined in the class.
+    parameters: Vec<&'a ParameterAnnotations>,
+}
+
+impl OrphanAnnotations<'_> {
+    /// Returns `true` if all the entries of the directory belong to the class.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.methods.is_empty() && self.parameters.is_empty()
+    }
+}
+
+/// Reads the annotations directory at an offset.
+pub(crate) type DirectoryLoader =
+    Shared<dyn Fn(uint) -> super::Result<Shared<AnnotationsDirectoryItem>> + Send + Sync>;
+
+/// The annotations directory of a class, shared by the class and its members. Unless the
+/// `Dex` is read with eager annotations, the directory is only read on first access.
+pub(crate) struct ClassAnnotations {
+    /// Offset of the annotations directory.
+    offset: uint,
+    loader: Option<DirectoryLoader>,
+    directory: OnceCell<Shared<AnnotationsDirectoryItem>>,
+    empty_set: AnnotationSetItem,
+    empty_ref_list: AnnotationSetRefList,
+}
+
+impl ClassAnnotations {
+    /// Annotations from a directory that is already read.
+    pub(crate) fn loaded(offset: uint, directory: Shared<AnnotationsDirectoryItem>) -> Self {
+        let annotations = Self::new(offset, None);
+        let _ = annotations.directory.set(directory);
+        annotations
+    }
+
+    /// Annotations from the directory at `offset`, read with `loader` on first access.
+    pub(crate) fn lazy(offset: uint, loader: DirectoryLoader) -> Self {
+        Self::new(offset, Some(loader))
+    }
+
+    fn new(offset: uint, loader: Option<DirectoryLoader>) -> Self {
+        Self {
+            offset,
+            loader,
+            directory: OnceCell::new(),
+            empty_set: Default::default(),
+            empty_ref_list: Default::default(),
+        }
+    }
+
+    pub(crate) fn directory(&self) -> super::Result<&AnnotationsDirectoryItem> {
+        if let Some(directory) = self.directory.get() {
+            return Ok(directory);
+        }
+        let loader = self
+            .loader
+            .as_ref()
+            .expect("annotations without a directory have a loader");
+        let directory = loader(self.offset).map_err(|e| match e {
+            Error::MalFormed(msg) => {
+                Error::MalFormed(format!("Annotations directory at {}: {}", self.offset, msg))
+            }
+            Error::InvalidId(msg) => {
+                Error::InvalidId(format!("Annotations directory at {}: {}", self.offset, msg))
+            }
+            Error::BadOffset(offset, msg) => Error::BadOffset(
+                offset,
+                format!("Annotations directory at {}: {}", self.offset, msg),
+            ),
+            e => e,
+        })?;
+        Ok(self.directory.get_or_init(|| directory))
+    }
+
+    /// Annotations of the class itself.
+    pub(crate) fn class(&self) -> super::Result<&AnnotationSetItem> {
+        Ok(&self.directory()?.class_annotations)
+    }
+
+    /// Annotations of the field with `field_id`.
+    pub(crate) fn field(&self, field_id: FieldId) -> super::Result<&AnnotationSetItem> {
+        let fields = &self.directory()?.field_annotations;
+        Ok(fields
+            .binary_search_by_key(&field_id, |f| f.field_idx)
+            .map(|index| &fields[index].annotations)
+            .unwrap_or(&self.empty_set))
+    }
+
+    /// Annotations of the method with `method_id`.
+    pub(crate) fn method(&self, method_id: MethodId) -> super::Result<&AnnotationSetItem> {
+        let methods = &self.directory()?.method_annotations;
+        Ok(methods
+            .binary_search_by_key(&method_id, |m| m.method_idx)
+            .map(|index| &methods[index].annotations)
+            .unwrap_or(&self.empty_set))
+    }
+
+    /// Annotations of the parameters of the method with `method_id`.
+    pub(crate) fn parameters(&self, method_id: MethodId) -> super::Result<&AnnotationSetRefList> {
+        let parameters = &self.directory()?.parameter_annotations;
+        Ok(parameters
+            .binary_search_by_key(&method_id, |p| p.method_idx)
+            .map(|index| &parameters[index].annotations)
+            .unwrap_or(&self.empty_ref_list))
+    }
+
+    /// Entries of the directory which don't belong to any of the given fields and methods.
+    pub(crate) fn orphans(
+        &self,
+        field_ids: &HashSet<FieldId>,
+        method_ids: &HashSet<MethodId>,
+    ) -> super::Result<OrphanAnnotations<'_>> {
+        let directory = self.directory()?;
+        Ok(OrphanAnnotations {
+            fields: directory
+                .field_annotations
+                .iter()
+                .filter(|f| !field_ids.contains(&f.field_idx))
+                .collect(),
+            methods: directory
+                .method_annotations
+                .iter()
+                .filter(|m| !method_ids.contains(&m.method_idx))
+                .collect(),
+            parameters: directory
+                .parameter_annotations
+                .iter()
+                .filter(|p| !method_ids.contains(&p.method_idx))
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Debug for ClassAnnotations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ClassAnnotations {{ offset: {}, loaded: {} }}",
+            self.offset,
+            self.directory.get().is_some()
+        )
+    }
+}
+
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for AnnotationsDirectoryItem
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -352,13 +784,19 @@ where
         let fields_size: uint = source.gread_with(offset, endian)?;
         let annotated_method_size: uint = source.gread_with(offset, endian)?;
         let annotated_parameters_size: uint = source.gread_with(offset, endian)?;
-        debug!(target: "annotations directory", "fields size: {}, annotated method size: {}, annotated params size: {}",
+        dex_debug!(ctx.log_label(), target: "annotations directory", "fields size: {}, annotated method size: {}, annotated params size: {}",
             fields_size, annotated_method_size, annotated_parameters_size);
         let class_annotations = ctx.get_annotation_set_item(class_annotations_off)?;
-        let field_annotations = try_gread_vec_with!(source, offset, fields_size, ctx);
-        let method_annotations = try_gread_vec_with!(source, offset, annotated_method_size, ctx);
-        let parameter_annotations =
+        let mut field_annotations: Vec<FieldAnnotations> =
+            try_gread_vec_with!(source, offset, fields_size, ctx);
+        let mut method_annotations: Vec<MethodAnnotations> =
+            try_gread_vec_with!(source, offset, annotated_method_size, ctx);
+        let mut parameter_annotations: Vec<ParameterAnnotations> =
             try_gread_vec_with!(source, offset, annotated_parameters_size, ctx);
+        // the lists are sorted in well formed files, lookups by id rely on it.
+        field_annotations.sort_by_key(|f| f.field_idx);
+        method_annotations.sort_by_key(|m| m.method_idx);
+        parameter_annotations.sort_by_key(|p| p.method_idx);
         Ok((
             Self {
                 class_annotations,
@@ -370,3 +808,122 @@ where
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AnnotationSetItem, AnnotationSetRefList, ParameterAnnotations};
+    use crate::{
+        diagnostic::{DiagnosticCode, Severity},
+        encoded_value::EncodedValue,
+        DexReader,
+    };
+
+    #[test]
+    fn test_aligned_parameter_annotations() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let proto_with = |count| {
+            dex.protos()
+                .map(|proto| proto.expect("bad proto").1)
+                .find(|proto| proto.param_count() == count)
+                .expect("no proto")
+        };
+        let parameters = ParameterAnnotations {
+            method_idx: 7,
+            annotations: AnnotationSetRefList {
+                annotation_set_list: vec![AnnotationSetItem::default(), Default::default()],
+                empty_set: Default::default(),
+            },
+        };
+        let sets = parameters.annotations().annotation_set_list();
+
+        // exact
+        let aligned = parameters.aligned_iter(&proto_with(2));
+        assert!(aligned.extras().is_empty());
+        assert_eq!(aligned.padded(), 0);
+        assert!(aligned.diagnostic().is_none());
+        let yielded: Vec<_> = aligned.collect();
+        assert!(yielded.iter().zip(sets).all(|(a, b)| std::ptr::eq(*a, b)));
+        assert_eq!(yielded.len(), 2);
+
+        // shorter than the parameters
+        let aligned = parameters.aligned_iter(&proto_with(3));
+        assert_eq!(aligned.len(), 3);
+        assert_eq!(aligned.padded(), 1);
+        assert!(aligned.extras().is_empty());
+        let diagnostic = aligned.diagnostic().expect("no diagnostic");
+        assert_eq!(diagnostic.severity(), Severity::Info);
+        assert_eq!(diagnostic.code(), DiagnosticCode::ParameterAnnotationCount);
+        assert_eq!(diagnostic.context().as_deref(), Some("method 7"));
+        let yielded: Vec<_> = aligned.collect();
+        assert!(std::ptr::eq(yielded[1], &sets[1]));
+        assert!(yielded[2].annotations().is_empty());
+        assert!(!std::ptr::eq(yielded[2], &sets[0]) && !std::ptr::eq(yielded[2], &sets[1]));
+
+        // longer than the parameters
+        let aligned = parameters.aligned_iter(&proto_with(1));
+        assert_eq!(aligned.padded(), 0);
+        assert_eq!(aligned.extras().len(), 1);
+        assert!(std::ptr::eq(&aligned.extras()[0], &sets[1]));
+        let diagnostic = aligned.diagnostic().expect("no diagnostic");
+        assert_eq!(diagnostic.severity(), Severity::Warning);
+        assert_eq!(
+            diagnostic.message(),
+            "2 parameter annotation sets for 1 parameters"
+        );
+        let yielded: Vec<_> = aligned.collect();
+        assert_eq!(yielded.len(), 1);
+        assert!(std::ptr::eq(yielded[0], &sets[0]));
+    }
+
+    #[test]
+    fn test_method_parameters() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let parameters = method.parameters().expect("bad parameters");
+                assert_eq!(parameters.len(), method.params().len());
+                let sets = method.param_annotations().expect("bad annotations");
+                for (index, parameter) in parameters.iter().enumerate() {
+                    assert_eq!(parameter.jtype(), &method.params()[index]);
+                    match sets.get(index) {
+                        Some(set) => assert!(std::ptr::eq(parameter.annotations(), set)),
+                        None => assert!(parameter.annotations().annotations().is_empty()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_raw_indices() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut strings = 0;
+        for class in dex.classes() {
+            let class = class.expect("cannot read class");
+            for annotation in class.annotations().expect("cannot read annotations").iter() {
+                let jtype = dex.get_type(annotation.type_idx()).expect("bad type");
+                assert_eq!(
+                    jtype.type_descriptor(),
+                    annotation.jtype().type_descriptor()
+                );
+                for element in annotation.elements() {
+                    let name = dex.get_string(element.name_idx()).expect("bad name");
+                    assert_eq!(&name, element.name());
+                    let values = match element.value() {
+                        EncodedValue::Array(values) => values.iter().collect(),
+                        value => vec![value],
+                    };
+                    for value in values {
+                        if let EncodedValue::String { idx, value: string } = value {
+                            assert_eq!(value.raw_index(), Some(u64::from(*idx)));
+                            assert_eq!(&dex.get_string(*idx).expect("bad string"), string);
+                            strings += 1;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(strings > 0);
+    }
+}
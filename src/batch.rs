@@ -0,0 +1,237 @@
+//! Parallel scanning of many dex files, with the `rayon` feature.
+//!
+//! Each file is opened and processed on its own, so one malformed file, or one that makes
+//! the processing panic, only fails its own result.
+use std::{
+    any::Any,
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+
+use crate::{error::Error, limits::ParserLimits, Dex, DexReader, FileSource, Result};
+
+/// Options of `scan_files_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOptions {
+    max_open_files: usize,
+    max_file_size: u64,
+    time_budget: Option<Duration>,
+    limits: ParserLimits,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            max_open_files: rayon::current_num_threads(),
+            max_file_size: u64::from(u32::MAX),
+            time_budget: None,
+            limits: ParserLimits::default(),
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of files open at the same time, which is also the number of files
+    /// processed in parallel. Defaults to the number of threads of the global rayon pool.
+    pub fn max_open_files(mut self, max: usize) -> Self {
+        self.max_open_files = max.max(1);
+        self
+    }
+
+    /// Maximum size of a file, in bytes. Larger files are not mapped and fail with
+    /// `Error::LimitExceeded`. Defaults to 4 GiB, the largest size a dex file can declare.
+    pub fn max_file_size(mut self, max: u64) -> Self {
+        self.max_file_size = max;
+        self
+    }
+
+    /// Time allowed to open and process a file. The processing is not interrupted, but
+    /// a file which took longer fails with `Error::LimitExceeded` so that slow samples are
+    /// reported. Unlimited by default.
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Limits used to read each file, see `DexReaderBuilder::limits`.
+    pub fn limits(mut self, limits: ParserLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// Opens each of `paths` and calls `per_file` with it, in parallel, with the default
+/// options. See `scan_files_with`.
+pub fn scan_files<I, P, T, F>(paths: I, per_file: F) -> Vec<(PathBuf, Result<T>)>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+    T: Send,
+    F: Fn(&Dex<FileSource>) -> Result<T> + Sync,
+{
+    scan_files_with(paths, &BatchOptions::default(), per_file)
+}
+
+/// Opens each of `paths` and calls `per_file` with it, in parallel, returning the result
+/// of each file in the order of `paths`.
+///
+/// Each call gets its own `Dex`, nothing is cached across files. Errors opening a file,
+/// errors returned by `per_file` and panics are all reported as the result of the file, a
+/// panic as `Error::Panicked`.
+pub fn scan_files_with<I, P, T, F>(
+    paths: I,
+    options: &BatchOptions,
+    per_file: F,
+) -> Vec<(PathBuf, Result<T>)>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+    T: Send,
+    F: Fn(&Dex<FileSource>) -> Result<T> + Sync,
+{
+    let paths: Vec<PathBuf> = paths.into_iter().map(Into::into).collect();
+    let pool = match rayon::ThreadPoolBuilder::new()
+        .num_threads(options.max_open_files)
+        .build()
+    {
+        Ok(pool) => pool,
+        Err(e) => {
+            let message = e.to_string();
+            return paths
+                .into_iter()
+                .map(|path| {
+                    let error = io::Error::other(message.clone());
+                    (path, Err(Error::IO(error)))
+                })
+                .collect();
+        }
+    };
+    pool.install(|| {
+        paths
+            .into_par_iter()
+            .map(|path| {
+                let result = scan_file(&path, options, &per_file);
+                if let Err(ref e) = result {
+                    dex_debug!(target: "batch", "{}: {}", path.display(), e);
+                }
+                (path, result)
+            })
+            .collect()
+    })
+}
+
+fn scan_file<T, F>(path: &Path, options: &BatchOptions, per_file: &F) -> Result<T>
+where
+    F: Fn(&Dex<FileSource>) -> Result<T>,
+{
+    let start = Instant::now();
+    let size = std::fs::metadata(path)?.len();
+    if size > options.max_file_size {
+        return Err(Error::LimitExceeded(format!(
+            "file size {} exceeds the limit of {}",
+            size, options.max_file_size
+        )));
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let dex = DexReader::builder()
+            .limits(options.limits)
+            .read_file(path)?;
+        per_file(&dex)
+    }))
+    .unwrap_or_else(|payload| Err(Error::Panicked(panic_message(payload))))?;
+    if let Some(budget) = options.time_budget {
+        ParserLimits::check(
+            "milliseconds spent",
+            start.elapsed().as_millis() as usize,
+            budget.as_millis() as usize,
+        )?;
+    }
+    Ok(result)
+}
+
+/// The message passed to `panic!`, if it has one.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scan_files, scan_files_with, BatchOptions};
+    use crate::{test_utils::minimal_dex, ErrorKind};
+
+    #[test]
+    fn test_scan_files() {
+        let dir = tempfile::TempDir::new().expect("cannot create temporary directory");
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let valid = dir.path().join("valid.dex");
+        std::fs::write(&valid, &example).expect("cannot write file");
+        let corrupt = dir.path().join("corrupt.dex");
+        std::fs::write(&corrupt, &example[..example.len() / 2]).expect("cannot write file");
+        // the closure panics on the files with this string
+        let panicking = dir.path().join("panicking.dex");
+        std::fs::write(&panicking, minimal_dex(&["panic"], 0)).expect("cannot write file");
+        let missing = dir.path().join("missing.dex");
+        let paths = vec![
+            valid.clone(),
+            corrupt.clone(),
+            panicking.clone(),
+            missing.clone(),
+            valid.clone(),
+        ];
+
+        let per_file = |dex: &crate::Dex<crate::FileSource>| {
+            if dex
+                .strings()
+                .any(|s| s.map(|(_, s)| s == "panic").unwrap_or(false))
+            {
+                panic!("injected panic");
+            }
+            Ok(dex.header().class_defs_size())
+        };
+        let results = scan_files(paths.clone(), per_file);
+        let found: Vec<_> = results.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(found, paths);
+        assert_eq!(*results[0].1.as_ref().expect("valid file failed"), 323);
+        assert!(results[1].1.is_err());
+        match &results[2].1 {
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::Panicked);
+                assert_eq!(e.to_string(), "Panicked: injected panic");
+            }
+            Ok(_) => panic!("the panic was not reported"),
+        }
+        assert_eq!(
+            results[3].1.as_ref().map_err(|e| e.kind()).unwrap_err(),
+            ErrorKind::Io
+        );
+        assert_eq!(*results[4].1.as_ref().expect("valid file failed"), 323);
+
+        let options = BatchOptions::new()
+            .max_open_files(1)
+            .max_file_size(example.len() as u64 - 1);
+        let results = scan_files_with(vec![valid, panicking], &options, per_file);
+        assert_eq!(
+            results[0].1.as_ref().map_err(|e| e.kind()).unwrap_err(),
+            ErrorKind::LimitExceeded
+        );
+        assert_eq!(
+            results[1].1.as_ref().map_err(|e| e.kind()).unwrap_err(),
+            ErrorKind::Panicked
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Boundaries of the variable-length items in the data section.
+use std::ops::Range;
+
+use crate::sync::OnceCell;
+
+use crate::{error::Error, uint, Result};
+
+/// Start offsets of all the items of a kind. A well formed item never extends into the next
+/// item of the same kind or past the end of its section, so the start offsets give an upper
+/// bound for the bytes an item can be parsed from.
+pub(crate) struct ItemBoundaries {
+    /// Extent of the section as declared in the map list.
+    section: Option<Range<uint>>,
+    /// Sorted start offsets of the items, computed on first use.
+    starts: OnceCell<Vec<uint>>,
+}
+
+impl ItemBoundaries {
+    pub(crate) fn new(section: Option<Range<uint>>) -> Self {
+        Self {
+            section,
+            starts: OnceCell::new(),
+        }
+    }
+
+    /// Computes the start offsets of the items with `init`, unless already computed.
+    pub(crate) fn init<F>(&self, init: F) -> Result<&[uint]>
+    where
+        F: FnOnce() -> Result<Vec<uint>>,
+    {
+        if self.starts.get().is_none() {
+            let mut starts = init()?;
+            starts.sort_unstable();
+            starts.dedup();
+            let _ = self.starts.set(starts);
+        }
+        Ok(self.starts.get().expect("item boundaries are initialized"))
+    }
+
+    /// Returns `true` if the start offsets are computed.
+    pub(crate) fn is_initialized(&self) -> bool {
+        self.starts.get().is_some()
+    }
+
+    /// Returns the offset at which the item starting at `offset` must end. `limit` is used when
+    /// no other boundary is known and `init` computes the start offsets of all the items.
+    pub(crate) fn end<F>(&self, offset: uint, limit: uint, init: F) -> Result<uint>
+    where
+        F: FnOnce() -> Result<Vec<uint>>,
+    {
+        let starts = self.init(init)?;
+        let next = match starts.binary_search(&offset) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+        let mut end = starts.get(next).cloned().unwrap_or(limit);
+        if let Some(ref section) = self.section {
+            if section.contains(&offset) {
+                end = end.min(section.end);
+            }
+        }
+        let end = end.min(limit);
+        if end < offset {
+            return Err(Error::BadOffset(
+                offset as usize,
+                "Item starts past the end of the data section".to_string(),
+            ));
+        }
+        Ok(end)
+    }
+}
+
+/// Converts errors caused by reading past the end of a bounded item into `Error::MalFormed`.
+pub(crate) fn bounded<T>(result: Result<T>, item: &str, bounds: Range<usize>) -> Result<T> {
+    result.map_err(|e| match e {
+        Error::Scroll(e) => Error::MalFormed(format!(
+            "{} at {} overruns its boundary at {}: {}",
+            item, bounds.start, bounds.end, e
+        )),
+        e => e,
+    })
+}
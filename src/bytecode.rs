@@ -0,0 +1,1175 @@
+//! Typed decoding of Dalvik instructions.
+//! [Android docs](https://source.android.com/devices/tech/dalvik/dalvik-bytecode)
+//!
+//! `decode_instructions` turns the code units of a method into `Insn`s, one variant per
+//! opcode of `opcode::OPCODES`, each holding the operands of its format. The operand types
+//! are named after the formats and their fields after the Dalvik documentation: `a`, `b`
+//! and `c` are the registers `vA`, `vB` and `vC`, `literal` a constant, `offset` a branch
+//! target in code units relative to the instruction and `index` a reference to an item.
+use std::{convert::TryFrom, ops::Deref, ops::Range};
+
+use crate::{
+    code::{FILL_ARRAY_DATA_PAYLOAD, PACKED_SWITCH_PAYLOAD, SPARSE_SWITCH_PAYLOAD},
+    error::Error,
+    field::FieldId,
+    jtype::TypeId,
+    method::{MethodHandleId, MethodId, ProtoId},
+    opcode::{Instruction, InstructionKind, Instructions, Opcode, OPCODES},
+    string::StringId,
+    uint, ushort, Result,
+};
+
+/// An instruction decoded with `decode_instructions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInsn {
+    /// Address of the instruction, in code units from the start of the method, as used by
+    /// the try blocks.
+    pub addr: usize,
+    /// Number of code units of the instruction.
+    pub len: usize,
+    /// The opcode and operands of the instruction.
+    pub insn: Insn,
+}
+
+/// Decodes the instructions `insns`, the code units of a method. Fails with
+/// `Error::MalFormed` if an instruction is truncated or has an unused opcode.
+pub fn decode_instructions(insns: &[ushort]) -> Result<Vec<DecodedInsn>> {
+    Instructions::new(insns)
+        .map(|instruction| {
+            let instruction = instruction?;
+            Ok(DecodedInsn {
+                addr: instruction.addr,
+                len: instruction.units.len(),
+                insn: Insn::decode(&instruction)?,
+            })
+        })
+        .collect()
+}
+
+/// Lazy iterator over the decoded instructions of a method and their addresses in code
+/// units, see `CodeItem::decoded_instructions`. Unlike `decode_instructions` it allocates
+/// nothing up front, and it skips the payloads of switches and `fill-array-data`: they are
+/// data the instructions point to, not instructions. A malformed instruction is returned
+/// as an error and ends the iteration.
+pub struct InstructionIterator<'a> {
+    instructions: Instructions<'a>,
+    failed: bool,
+}
+
+impl<'a> InstructionIterator<'a> {
+    /// Iterates over the instructions in `insns`, the code units of a method.
+    pub fn new(insns: &'a [ushort]) -> Self {
+        Self {
+            instructions: Instructions::new(insns),
+            failed: false,
+        }
+    }
+}
+
+impl Iterator for InstructionIterator<'_> {
+    type Item = Result<(usize, Insn)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        for instruction in &mut self.instructions {
+            let result = instruction.and_then(|instruction| match instruction.kind {
+                InstructionKind::Op(_) => {
+                    Insn::decode(&instruction).map(|insn| Some((instruction.addr, insn)))
+                }
+                _ => Ok(None),
+            });
+            match result {
+                Ok(None) => continue,
+                Ok(Some(item)) => return Some(Ok(item)),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The registers `vC`, `vD`, `vE`, `vF` and `vG` of the formats `35c` and `45cc`, as many as
+/// the instruction passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegisterList {
+    len: u8,
+    registers: [u8; 5],
+}
+
+impl RegisterList {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        let len = (units[0] >> 12) as u8;
+        if len > 5 {
+            return Err(Error::MalFormed(format!(
+                "Instruction passes {} registers, at most 5 are allowed",
+                len
+            )));
+        }
+        let registers = [
+            nibble(units[2], 0),
+            nibble(units[2], 1),
+            nibble(units[2], 2),
+            nibble(units[2], 3),
+            nibble(units[0], 2),
+        ];
+        Ok(Self { len, registers })
+    }
+}
+
+impl Deref for RegisterList {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.registers[..self.len as usize]
+    }
+}
+
+/// The consecutive registers of the formats `3rc` and `4rcc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegisterRange {
+    /// The first register, `vCCCC`.
+    pub first: u16,
+    /// Number of registers.
+    pub count: u8,
+}
+
+impl RegisterRange {
+    fn decode(units: &[ushort]) -> Self {
+        Self {
+            first: units[2],
+            count: high_byte(units[0]),
+        }
+    }
+
+    /// The registers of the range.
+    pub fn registers(&self) -> Range<uint> {
+        uint::from(self.first)..uint::from(self.first) + uint::from(self.count)
+    }
+}
+
+/// Operands of `move` and of the unary and `/2addr` operations: `vA, vB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F12x {
+    pub a: u8,
+    pub b: u8,
+}
+
+/// Operands of `const/4`: `vA, #+B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F11n {
+    pub a: u8,
+    pub literal: i8,
+}
+
+/// Operands of the instructions with a single register: `vAA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F11x {
+    pub a: u8,
+}
+
+/// Operands of `goto`: `+AA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F10t {
+    pub offset: i32,
+}
+
+/// Operands of `goto/16`: `+AAAA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F20t {
+    pub offset: i32,
+}
+
+/// Operands of the `/from16` moves: `vAA, vBBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F22x {
+    pub a: u8,
+    pub b: u16,
+}
+
+/// Operands of the comparisons with zero: `vAA, +BBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F21t {
+    pub a: u8,
+    pub offset: i32,
+}
+
+/// Operands of `const/16` and `const-wide/16`: `vAA, #+BBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F21s {
+    pub a: u8,
+    pub literal: i16,
+}
+
+/// Operands of `const/high16` and `const-wide/high16`: `vAA, #+BBBB0000` or
+/// `vAA, #+BBBB000000000000`. The literal is the value loaded, with its low bits zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F21h<L> {
+    pub a: u8,
+    pub literal: L,
+}
+
+/// Operands of the instructions with a register and a reference: `vAA, kind@BBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F21c<I> {
+    pub a: u8,
+    pub index: I,
+}
+
+/// Operands of the comparisons and binary operations: `vAA, vBB, vCC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F23x {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+}
+
+/// Operands of the `/lit8` operations: `vAA, vBB, #+CC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F22b {
+    pub a: u8,
+    pub b: u8,
+    pub literal: i8,
+}
+
+/// Operands of the comparisons of two registers: `vA, vB, +CCCC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F22t {
+    pub a: u8,
+    pub b: u8,
+    pub offset: i32,
+}
+
+/// Operands of the `/lit16` operations: `vA, vB, #+CCCC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F22s {
+    pub a: u8,
+    pub b: u8,
+    pub literal: i16,
+}
+
+/// Operands of the instructions with two registers and a reference: `vA, vB, kind@CCCC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F22c<I> {
+    pub a: u8,
+    pub b: u8,
+    pub index: I,
+}
+
+/// Operands of the `/16` moves: `vAAAA, vBBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F32x {
+    pub a: u16,
+    pub b: u16,
+}
+
+/// Operands of `goto/32`: `+AAAAAAAA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F30t {
+    pub offset: i32,
+}
+
+/// Operands of the switches and `fill-array-data`: `vAA, +BBBBBBBB`, the offset of the
+/// payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F31t {
+    pub a: u8,
+    pub offset: i32,
+}
+
+/// Operands of `const` and `const-wide/32`: `vAA, #+BBBBBBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F31i {
+    pub a: u8,
+    pub literal: i32,
+}
+
+/// Operands of `const-string/jumbo`: `vAA, string@BBBBBBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F31c<I> {
+    pub a: u8,
+    pub index: I,
+}
+
+/// Operands of the invokes and `filled-new-array`: `{vC, vD, vE, vF, vG}, kind@BBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F35c<I> {
+    pub args: RegisterList,
+    pub index: I,
+}
+
+/// Operands of the `/range` invokes and `filled-new-array/range`:
+/// `{vCCCC .. vNNNN}, kind@BBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F3rc<I> {
+    pub args: RegisterRange,
+    pub index: I,
+}
+
+/// Operands of `invoke-polymorphic`: `{vC, vD, vE, vF, vG}, meth@BBBB, proto@HHHH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F45cc {
+    pub args: RegisterList,
+    pub method: MethodId,
+    pub proto: ProtoId,
+}
+
+/// Operands of `invoke-polymorphic/range`: `{vCCCC .. vNNNN}, meth@BBBB, proto@HHHH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F4rcc {
+    pub args: RegisterRange,
+    pub method: MethodId,
+    pub proto: ProtoId,
+}
+
+/// Operands of `const-wide`: `vAA, #+BBBBBBBBBBBBBBBB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct F51l {
+    pub a: u8,
+    pub literal: i64,
+}
+
+/// The `n`th 4 bits of `unit`, from the lowest.
+fn nibble(unit: ushort, n: u32) -> u8 {
+    (unit >> (4 * n) & 0xf) as u8
+}
+
+fn high_byte(unit: ushort) -> u8 {
+    (unit >> 8) as u8
+}
+
+/// The 32 bits starting at `units[0]`, low half first.
+fn int(units: &[ushort]) -> uint {
+    uint::from(units[0]) | uint::from(units[1]) << 16
+}
+
+/// Operands decoded from the code units of an instruction, which are as many as the
+/// format needs.
+trait Operands: Sized {
+    fn decode(units: &[ushort]) -> Result<Self>;
+}
+
+impl Operands for F12x {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: nibble(units[0], 2),
+            b: nibble(units[0], 3),
+        })
+    }
+}
+
+impl Operands for F11n {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: nibble(units[0], 2),
+            literal: (units[0] as i16 >> 12) as i8,
+        })
+    }
+}
+
+impl Operands for F11x {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+        })
+    }
+}
+
+impl Operands for F10t {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            offset: i32::from(high_byte(units[0]) as i8),
+        })
+    }
+}
+
+impl Operands for F20t {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            offset: i32::from(units[1] as i16),
+        })
+    }
+}
+
+impl Operands for F22x {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            b: units[1],
+        })
+    }
+}
+
+impl Operands for F21t {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            offset: i32::from(units[1] as i16),
+        })
+    }
+}
+
+impl Operands for F21s {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            literal: units[1] as i16,
+        })
+    }
+}
+
+impl Operands for F21h<i32> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            literal: i32::from(units[1] as i16) << 16,
+        })
+    }
+}
+
+impl Operands for F21h<i64> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            literal: i64::from(units[1] as i16) << 48,
+        })
+    }
+}
+
+impl<I: From<ushort>> Operands for F21c<I> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            index: I::from(units[1]),
+        })
+    }
+}
+
+impl Operands for F23x {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            b: units[1] as u8,
+            c: high_byte(units[1]),
+        })
+    }
+}
+
+impl Operands for F22b {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            b: units[1] as u8,
+            literal: high_byte(units[1]) as i8,
+        })
+    }
+}
+
+impl Operands for F22t {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: nibble(units[0], 2),
+            b: nibble(units[0], 3),
+            offset: i32::from(units[1] as i16),
+        })
+    }
+}
+
+impl Operands for F22s {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: nibble(units[0], 2),
+            b: nibble(units[0], 3),
+            literal: units[1] as i16,
+        })
+    }
+}
+
+impl<I: From<ushort>> Operands for F22c<I> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: nibble(units[0], 2),
+            b: nibble(units[0], 3),
+            index: I::from(units[1]),
+        })
+    }
+}
+
+impl Operands for F32x {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: units[1],
+            b: units[2],
+        })
+    }
+}
+
+impl Operands for F30t {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            offset: int(&units[1..]) as i32,
+        })
+    }
+}
+
+impl Operands for F31t {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            offset: int(&units[1..]) as i32,
+        })
+    }
+}
+
+impl Operands for F31i {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            literal: int(&units[1..]) as i32,
+        })
+    }
+}
+
+impl<I: From<uint>> Operands for F31c<I> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            a: high_byte(units[0]),
+            index: I::from(int(&units[1..])),
+        })
+    }
+}
+
+impl<I: From<ushort>> Operands for F35c<I> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            args: RegisterList::decode(units)?,
+            index: I::from(units[1]),
+        })
+    }
+}
+
+impl<I: From<ushort>> Operands for F3rc<I> {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            args: RegisterRange::decode(units),
+            index: I::from(units[1]),
+        })
+    }
+}
+
+impl Operands for F45cc {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            args: RegisterList::decode(units)?,
+            method: MethodId::from(units[1]),
+            proto: ProtoId::from(units[3]),
+        })
+    }
+}
+
+impl Operands for F4rcc {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        Ok(Self {
+            args: RegisterRange::decode(units),
+            method: MethodId::from(units[1]),
+            proto: ProtoId::from(units[3]),
+        })
+    }
+}
+
+impl Operands for F51l {
+    fn decode(units: &[ushort]) -> Result<Self> {
+        let literal = units[1..5]
+            .iter()
+            .rev()
+            .fold(0u64, |literal, unit| literal << 16 | u64::from(*unit));
+        Ok(Self {
+            a: high_byte(units[0]),
+            literal: literal as i64,
+        })
+    }
+}
+
+macro_rules! insns {
+    ($($value:literal => $variant:ident $(($operands:ty))?;)*) => {
+        /// A decoded instruction: one variant for each opcode in use, named after its
+        /// mnemonic, and one for each payload.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum Insn {
+            $($variant $(($operands))?,)*
+            /// Targets of a `packed-switch`, for the consecutive keys from `first_key`.
+            PackedSwitchPayload { first_key: i32, targets: Vec<i32> },
+            /// Keys of a `sparse-switch`, in ascending order, and their targets.
+            SparseSwitchPayload { keys: Vec<i32>, targets: Vec<i32> },
+            /// Elements of a `fill-array-data`, `element_width` bytes each, little-endian.
+            FillArrayDataPayload { element_width: ushort, data: Vec<u8> },
+        }
+
+        impl Insn {
+            /// The opcode of the instruction, `None` for a payload.
+            pub fn opcode(&self) -> Option<&'static Opcode> {
+                let value: usize = match self {
+                    $(Insn::$variant $((insns!(@any $operands)))? => $value,)*
+                    Insn::PackedSwitchPayload { .. }
+                    | Insn::SparseSwitchPayload { .. }
+                    | Insn::FillArrayDataPayload { .. } => return None,
+                };
+                Some(&OPCODES[value])
+            }
+
+            /// Decodes the operands of an instruction with an opcode.
+            fn decode_op(opcode: &Opcode, units: &[ushort]) -> Result<Self> {
+                Ok(match opcode.value {
+                    $($value => Insn::$variant $((<$operands as Operands>::decode(units)?))?,)*
+                    _ => return Err(Error::MalFormed(format!("Unused opcode {}", opcode.mnemonic))),
+                })
+            }
+        }
+    };
+    (@any $operands:ty) => { _ };
+}
+
+insns! {
+    0x00 => Nop;
+    0x01 => Move(F12x);
+    0x02 => MoveFrom16(F22x);
+    0x03 => Move16(F32x);
+    0x04 => MoveWide(F12x);
+    0x05 => MoveWideFrom16(F22x);
+    0x06 => MoveWide16(F32x);
+    0x07 => MoveObject(F12x);
+    0x08 => MoveObjectFrom16(F22x);
+    0x09 => MoveObject16(F32x);
+    0x0a => MoveResult(F11x);
+    0x0b => MoveResultWide(F11x);
+    0x0c => MoveResultObject(F11x);
+    0x0d => MoveException(F11x);
+    0x0e => ReturnVoid;
+    0x0f => Return(F11x);
+    0x10 => ReturnWide(F11x);
+    0x11 => ReturnObject(F11x);
+    0x12 => Const4(F11n);
+    0x13 => Const16(F21s);
+    0x14 => Const(F31i);
+    0x15 => ConstHigh16(F21h<i32>);
+    0x16 => ConstWide16(F21s);
+    0x17 => ConstWide32(F31i);
+    0x18 => ConstWide(F51l);
+    0x19 => ConstWideHigh16(F21h<i64>);
+    0x1a => ConstString(F21c<StringId>);
+    0x1b => ConstStringJumbo(F31c<StringId>);
+    0x1c => ConstClass(F21c<TypeId>);
+    0x1d => MonitorEnter(F11x);
+    0x1e => MonitorExit(F11x);
+    0x1f => CheckCast(F21c<TypeId>);
+    0x20 => InstanceOf(F22c<TypeId>);
+    0x21 => ArrayLength(F12x);
+    0x22 => NewInstance(F21c<TypeId>);
+    0x23 => NewArray(F22c<TypeId>);
+    0x24 => FilledNewArray(F35c<TypeId>);
+    0x25 => FilledNewArrayRange(F3rc<TypeId>);
+    0x26 => FillArrayData(F31t);
+    0x27 => Throw(F11x);
+    0x28 => Goto(F10t);
+    0x29 => Goto16(F20t);
+    0x2a => Goto32(F30t);
+    0x2b => PackedSwitch(F31t);
+    0x2c => SparseSwitch(F31t);
+    0x2d => CmplFloat(F23x);
+    0x2e => CmpgFloat(F23x);
+    0x2f => CmplDouble(F23x);
+    0x30 => CmpgDouble(F23x);
+    0x31 => CmpLong(F23x);
+    0x32 => IfEq(F22t);
+    0x33 => IfNe(F22t);
+    0x34 => IfLt(F22t);
+    0x35 => IfGe(F22t);
+    0x36 => IfGt(F22t);
+    0x37 => IfLe(F22t);
+    0x38 => IfEqz(F21t);
+    0x39 => IfNez(F21t);
+    0x3a => IfLtz(F21t);
+    0x3b => IfGez(F21t);
+    0x3c => IfGtz(F21t);
+    0x3d => IfLez(F21t);
+    0x44 => Aget(F23x);
+    0x45 => AgetWide(F23x);
+    0x46 => AgetObject(F23x);
+    0x47 => AgetBoolean(F23x);
+    0x48 => AgetByte(F23x);
+    0x49 => AgetChar(F23x);
+    0x4a => AgetShort(F23x);
+    0x4b => Aput(F23x);
+    0x4c => AputWide(F23x);
+    0x4d => AputObject(F23x);
+    0x4e => AputBoolean(F23x);
+    0x4f => AputByte(F23x);
+    0x50 => AputChar(F23x);
+    0x51 => AputShort(F23x);
+    0x52 => Iget(F22c<FieldId>);
+    0x53 => IgetWide(F22c<FieldId>);
+    0x54 => IgetObject(F22c<FieldId>);
+    0x55 => IgetBoolean(F22c<FieldId>);
+    0x56 => IgetByte(F22c<FieldId>);
+    0x57 => IgetChar(F22c<FieldId>);
+    0x58 => IgetShort(F22c<FieldId>);
+    0x59 => Iput(F22c<FieldId>);
+    0x5a => IputWide(F22c<FieldId>);
+    0x5b => IputObject(F22c<FieldId>);
+    0x5c => IputBoolean(F22c<FieldId>);
+    0x5d => IputByte(F22c<FieldId>);
+    0x5e => IputChar(F22c<FieldId>);
+    0x5f => IputShort(F22c<FieldId>);
+    0x60 => Sget(F21c<FieldId>);
+    0x61 => SgetWide(F21c<FieldId>);
+    0x62 => SgetObject(F21c<FieldId>);
+    0x63 => SgetBoolean(F21c<FieldId>);
+    0x64 => SgetByte(F21c<FieldId>);
+    0x65 => SgetChar(F21c<FieldId>);
+    0x66 => SgetShort(F21c<FieldId>);
+    0x67 => Sput(F21c<FieldId>);
+    0x68 => SputWide(F21c<FieldId>);
+    0x69 => SputObject(F21c<FieldId>);
+    0x6a => SputBoolean(F21c<FieldId>);
+    0x6b => SputByte(F21c<FieldId>);
+    0x6c => SputChar(F21c<FieldId>);
+    0x6d => SputShort(F21c<FieldId>);
+    0x6e => InvokeVirtual(F35c<MethodId>);
+    0x6f => InvokeSuper(F35c<MethodId>);
+    0x70 => InvokeDirect(F35c<MethodId>);
+    0x71 => InvokeStatic(F35c<MethodId>);
+    0x72 => InvokeInterface(F35c<MethodId>);
+    0x74 => InvokeVirtualRange(F3rc<MethodId>);
+    0x75 => InvokeSuperRange(F3rc<MethodId>);
+    0x76 => InvokeDirectRange(F3rc<MethodId>);
+    0x77 => InvokeStaticRange(F3rc<MethodId>);
+    0x78 => InvokeInterfaceRange(F3rc<MethodId>);
+    0x7b => NegInt(F12x);
+    0x7c => NotInt(F12x);
+    0x7d => NegLong(F12x);
+    0x7e => NotLong(F12x);
+    0x7f => NegFloat(F12x);
+    0x80 => NegDouble(F12x);
+    0x81 => IntToLong(F12x);
+    0x82 => IntToFloat(F12x);
+    0x83 => IntToDouble(F12x);
+    0x84 => LongToInt(F12x);
+    0x85 => LongToFloat(F12x);
+    0x86 => LongToDouble(F12x);
+    0x87 => FloatToInt(F12x);
+    0x88 => FloatToLong(F12x);
+    0x89 => FloatToDouble(F12x);
+    0x8a => DoubleToInt(F12x);
+    0x8b => DoubleToLong(F12x);
+    0x8c => DoubleToFloat(F12x);
+    0x8d => IntToByte(F12x);
+    0x8e => IntToChar(F12x);
+    0x8f => IntToShort(F12x);
+    0x90 => AddInt(F23x);
+    0x91 => SubInt(F23x);
+    0x92 => MulInt(F23x);
+    0x93 => DivInt(F23x);
+    0x94 => RemInt(F23x);
+    0x95 => AndInt(F23x);
+    0x96 => OrInt(F23x);
+    0x97 => XorInt(F23x);
+    0x98 => ShlInt(F23x);
+    0x99 => ShrInt(F23x);
+    0x9a => UshrInt(F23x);
+    0x9b => AddLong(F23x);
+    0x9c => SubLong(F23x);
+    0x9d => MulLong(F23x);
+    0x9e => DivLong(F23x);
+    0x9f => RemLong(F23x);
+    0xa0 => AndLong(F23x);
+    0xa1 => OrLong(F23x);
+    0xa2 => XorLong(F23x);
+    0xa3 => ShlLong(F23x);
+    0xa4 => ShrLong(F23x);
+    0xa5 => UshrLong(F23x);
+    0xa6 => AddFloat(F23x);
+    0xa7 => SubFloat(F23x);
+    0xa8 => MulFloat(F23x);
+    0xa9 => DivFloat(F23x);
+    0xaa => RemFloat(F23x);
+    0xab => AddDouble(F23x);
+    0xac => SubDouble(F23x);
+    0xad => MulDouble(F23x);
+    0xae => DivDouble(F23x);
+    0xaf => RemDouble(F23x);
+    0xb0 => AddInt2addr(F12x);
+    0xb1 => SubInt2addr(F12x);
+    0xb2 => MulInt2addr(F12x);
+    0xb3 => DivInt2addr(F12x);
+    0xb4 => RemInt2addr(F12x);
+    0xb5 => AndInt2addr(F12x);
+    0xb6 => OrInt2addr(F12x);
+    0xb7 => XorInt2addr(F12x);
+    0xb8 => ShlInt2addr(F12x);
+    0xb9 => ShrInt2addr(F12x);
+    0xba => UshrInt2addr(F12x);
+    0xbb => AddLong2addr(F12x);
+    0xbc => SubLong2addr(F12x);
+    0xbd => MulLong2addr(F12x);
+    0xbe => DivLong2addr(F12x);
+    0xbf => RemLong2addr(F12x);
+    0xc0 => AndLong2addr(F12x);
+    0xc1 => OrLong2addr(F12x);
+    0xc2 => XorLong2addr(F12x);
+    0xc3 => ShlLong2addr(F12x);
+    0xc4 => ShrLong2addr(F12x);
+    0xc5 => UshrLong2addr(F12x);
+    0xc6 => AddFloat2addr(F12x);
+    0xc7 => SubFloat2addr(F12x);
+    0xc8 => MulFloat2addr(F12x);
+    0xc9 => DivFloat2addr(F12x);
+    0xca => RemFloat2addr(F12x);
+    0xcb => AddDouble2addr(F12x);
+    0xcc => SubDouble2addr(F12x);
+    0xcd => MulDouble2addr(F12x);
+    0xce => DivDouble2addr(F12x);
+    0xcf => RemDouble2addr(F12x);
+    0xd0 => AddIntLit16(F22s);
+    0xd1 => RsubInt(F22s);
+    0xd2 => MulIntLit16(F22s);
+    0xd3 => DivIntLit16(F22s);
+    0xd4 => RemIntLit16(F22s);
+    0xd5 => AndIntLit16(F22s);
+    0xd6 => OrIntLit16(F22s);
+    0xd7 => XorIntLit16(F22s);
+    0xd8 => AddIntLit8(F22b);
+    0xd9 => RsubIntLit8(F22b);
+    0xda => MulIntLit8(F22b);
+    0xdb => DivIntLit8(F22b);
+    0xdc => RemIntLit8(F22b);
+    0xdd => AndIntLit8(F22b);
+    0xde => OrIntLit8(F22b);
+    0xdf => XorIntLit8(F22b);
+    0xe0 => ShlIntLit8(F22b);
+    0xe1 => ShrIntLit8(F22b);
+    0xe2 => UshrIntLit8(F22b);
+    0xfa => InvokePolymorphic(F45cc);
+    0xfb => InvokePolymorphicRange(F4rcc);
+    0xfc => InvokeCustom(F35c<uint>);
+    0xfd => InvokeCustomRange(F3rc<uint>);
+    0xfe => ConstMethodHandle(F21c<MethodHandleId>);
+    0xff => ConstMethodType(F21c<ProtoId>);
+}
+
+impl Insn {
+    /// Decodes an instruction returned by `opcode::Instructions`.
+    pub fn decode(instruction: &Instruction<'_>) -> Result<Self> {
+        let units = instruction.units;
+        let truncated = || {
+            Error::MalFormed(format!(
+                "Truncated instruction at {}: {} code units",
+                instruction.addr,
+                units.len()
+            ))
+        };
+        let payload =
+            |start: usize, len: usize| units.get(start..start + len).ok_or_else(truncated);
+        let ints = |units: &[ushort]| units.chunks_exact(2).map(|unit| int(unit) as i32).collect();
+        match instruction.kind {
+            InstructionKind::Op(opcode) => {
+                if units.len() < opcode.format.units() {
+                    return Err(truncated());
+                }
+                Insn::decode_op(opcode, units).map_err(|e| match e {
+                    Error::MalFormed(message) => {
+                        Error::MalFormed(format!("{} at {}", message, instruction.addr))
+                    }
+                    e => e,
+                })
+            }
+            InstructionKind::PackedSwitchPayload => {
+                let header = payload(0, 4)?;
+                debug_assert_eq!(header[0], PACKED_SWITCH_PAYLOAD);
+                Ok(Insn::PackedSwitchPayload {
+                    first_key: int(&header[2..]) as i32,
+                    targets: ints(payload(4, usize::from(header[1]) * 2)?),
+                })
+            }
+            InstructionKind::SparseSwitchPayload => {
+                let header = payload(0, 2)?;
+                debug_assert_eq!(header[0], SPARSE_SWITCH_PAYLOAD);
+                let size = usize::from(header[1]) * 2;
+                Ok(Insn::SparseSwitchPayload {
+                    keys: ints(payload(2, size)?),
+                    targets: ints(payload(2 + size, size)?),
+                })
+            }
+            InstructionKind::FillArrayDataPayload => {
+                let header = payload(0, 4)?;
+                debug_assert_eq!(header[0], FILL_ARRAY_DATA_PAYLOAD);
+                let element_width = header[1];
+                let len = usize::try_from(u64::from(element_width) * u64::from(int(&header[2..])))
+                    .map_err(|_| truncated())?;
+                let data: Vec<u8> = payload(4, len.div_ceil(2))?
+                    .iter()
+                    .flat_map(|unit| unit.to_le_bytes())
+                    .take(len)
+                    .collect();
+                Ok(Insn::FillArrayDataPayload {
+                    element_width,
+                    data,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_instructions, F10t, F11n, F12x, F21c, F21h, F22b, F22t, F35c, F3rc, F45cc, F51l,
+        Insn, InstructionIterator, RegisterRange,
+    };
+    use crate::{
+        error::Error,
+        opcode::{Instruction, InstructionKind, OPCODES},
+        DexReader,
+    };
+
+    fn decode(units: &[u16]) -> crate::Result<Insn> {
+        let mut decoded = decode_instructions(units)?;
+        assert_eq!(decoded.len(), 1, "{:?}", decoded);
+        Ok(decoded.remove(0).insn)
+    }
+
+    #[test]
+    fn test_every_opcode_has_a_variant() {
+        let mut used = 0;
+        for opcode in OPCODES.iter() {
+            let mut units = vec![0; opcode.format.units()];
+            units[0] = u16::from(opcode.value);
+            let result = decode(&units);
+            if opcode.is_unused() {
+                assert!(matches!(result, Err(Error::MalFormed(_))), "{:?}", result);
+                continue;
+            }
+            let insn = result.expect("cannot decode");
+            assert_eq!(insn.opcode(), Some(opcode));
+            let variant: String = opcode
+                .mnemonic
+                .split(['-', '/'])
+                .map(|part| part[..1].to_uppercase() + &part[1..])
+                .collect();
+            let debug = format!("{:?}", insn);
+            assert!(
+                debug.starts_with(&variant),
+                "{} is {}",
+                opcode.mnemonic,
+                debug
+            );
+            used += 1;
+        }
+        assert_eq!(used, 224);
+    }
+
+    #[test]
+    fn test_decode_operands() {
+        // move v1, v2
+        assert_eq!(decode(&[0x2101]).unwrap(), Insn::Move(F12x { a: 1, b: 2 }));
+        // const/4 v0, -1
+        assert_eq!(
+            decode(&[0xf012]).unwrap(),
+            Insn::Const4(F11n { a: 0, literal: -1 })
+        );
+        // const/high16 v3, 0x7f010000, const-wide/high16 v3, 0x8000000000000000
+        assert_eq!(
+            decode(&[0x0315, 0x7f01]).unwrap(),
+            Insn::ConstHigh16(F21h {
+                a: 3,
+                literal: 0x7f01_0000
+            })
+        );
+        assert_eq!(
+            decode(&[0x0319, 0x8000]).unwrap(),
+            Insn::ConstWideHigh16(F21h {
+                a: 3,
+                literal: i64::MIN
+            })
+        );
+        // const-wide v4, 0x0123456789abcdef
+        assert_eq!(
+            decode(&[0x0418, 0xcdef, 0x89ab, 0x4567, 0x0123]).unwrap(),
+            Insn::ConstWide(F51l {
+                a: 4,
+                literal: 0x0123_4567_89ab_cdef
+            })
+        );
+        // const-string v5, string@0x1234; sget-object v0, field@2
+        assert_eq!(
+            decode(&[0x051a, 0x1234]).unwrap(),
+            Insn::ConstString(F21c {
+                a: 5,
+                index: 0x1234
+            })
+        );
+        assert_eq!(
+            decode(&[0x0062, 0x0002]).unwrap(),
+            Insn::SgetObject(F21c { a: 0, index: 2 })
+        );
+        // add-int/lit8 v0, v1, -2; if-ne v1, v2, -3
+        assert_eq!(
+            decode(&[0x00d8, 0xfe01]).unwrap(),
+            Insn::AddIntLit8(F22b {
+                a: 0,
+                b: 1,
+                literal: -2
+            })
+        );
+        assert_eq!(
+            decode(&[0x2133, 0xfffd]).unwrap(),
+            Insn::IfNe(F22t {
+                a: 1,
+                b: 2,
+                offset: -3
+            })
+        );
+        // invoke-direct {v1, v2, v3, v4, v5}, method@7
+        let insn = decode(&[0x5570, 0x0007, 0x4321]).unwrap();
+        match insn {
+            Insn::InvokeDirect(F35c { args, index: 7 }) => assert_eq!(*args, [1, 2, 3, 4, 5]),
+            insn => panic!("{:?}", insn),
+        }
+        // invoke-static/range {v16 .. v18}, method@9
+        assert_eq!(
+            decode(&[0x0377, 0x0009, 0x0010]).unwrap(),
+            Insn::InvokeStaticRange(F3rc {
+                args: RegisterRange {
+                    first: 16,
+                    count: 3
+                },
+                index: 9
+            })
+        );
+        // invoke-polymorphic {v0}, method@3, proto@4
+        match decode(&[0x10fa, 0x0003, 0x0000, 0x0004]).unwrap() {
+            Insn::InvokePolymorphic(F45cc {
+                args,
+                method: 3,
+                proto: 4,
+            }) => assert_eq!(*args, [0]),
+            insn => panic!("{:?}", insn),
+        }
+    }
+
+    #[test]
+    fn test_decode_payloads() {
+        let decoded = decode_instructions(&[
+            // packed-switch-payload with keys 10 and 11
+            0x0100, 0x0002, 0x000a, 0x0000, 0x0005, 0x0000, 0xfffe, 0xffff,
+            // sparse-switch-payload with the key -1
+            0x0200, 0x0001, 0xffff, 0xffff, 0x0003, 0x0000,
+            // fill-array-data-payload with 3 bytes, padded
+            0x0300, 0x0001, 0x0003, 0x0000, 0x0201, 0x0003,
+        ])
+        .expect("cannot decode");
+        let addrs: Vec<_> = decoded.iter().map(|d| (d.addr, d.len)).collect();
+        assert_eq!(addrs, [(0, 8), (8, 6), (14, 6)]);
+        assert_eq!(
+            decoded[0].insn,
+            Insn::PackedSwitchPayload {
+                first_key: 10,
+                targets: vec![5, -2]
+            }
+        );
+        assert_eq!(
+            decoded[1].insn,
+            Insn::SparseSwitchPayload {
+                keys: vec![-1],
+                targets: vec![3]
+            }
+        );
+        assert_eq!(
+            decoded[2].insn,
+            Insn::FillArrayDataPayload {
+                element_width: 1,
+                data: vec![1, 2, 3]
+            }
+        );
+        assert_eq!(decoded[2].insn.opcode(), None);
+    }
+
+    #[test]
+    fn test_iterator_skips_payloads() {
+        let insns = [
+            // packed-switch v0, +4; return-void
+            0x002b, 0x0004, 0x0000, 0x000e,
+            // packed-switch-payload with the key 0 in the middle of the code, then goto -1
+            0x0100, 0x0001, 0x0000, 0x0000, 0xfffe, 0xffff, 0xff28,
+        ];
+        let items: Vec<_> = InstructionIterator::new(&insns)
+            .map(|item| item.expect("cannot decode"))
+            .collect();
+        let addrs: Vec<_> = items.iter().map(|(addr, _)| *addr).collect();
+        assert_eq!(addrs, [0, 3, 10]);
+        assert_eq!(items[2].1, Insn::Goto(F10t { offset: -1 }));
+
+        // const-string with its index cut off
+        let mut iterator = InstructionIterator::new(&[0x000e, 0x001a]);
+        assert!(matches!(iterator.next(), Some(Ok((0, Insn::ReturnVoid)))));
+        assert!(matches!(iterator.next(), Some(Err(Error::MalFormed(_)))));
+        assert!(iterator.next().is_none());
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        // a truncated const-string, and an invoke passing 6 registers
+        assert!(matches!(decode(&[0x001a]), Err(Error::MalFormed(_))));
+        assert!(matches!(
+            decode(&[0x6070, 0x0000, 0x0000]),
+            Err(Error::MalFormed(_))
+        ));
+        // an instruction shorter than its format
+        let instruction = Instruction {
+            addr: 2,
+            kind: InstructionKind::Op(&OPCODES[0x14]),
+            units: &[0x0014, 0x0000],
+        };
+        assert!(matches!(
+            Insn::decode(&instruction),
+            Err(Error::MalFormed(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_example_methods() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut instructions = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let code = match method.code() {
+                    Some(code) => code,
+                    None => continue,
+                };
+                let decoded = code.decode_instructions().expect("cannot decode");
+                let lazy: Vec<_> = code
+                    .decoded_instructions()
+                    .map(|item| item.expect("cannot decode"))
+                    .collect();
+                let ops = decoded.iter().filter(|d| d.insn.opcode().is_some());
+                assert!(ops
+                    .map(|d| (d.addr, &d.insn))
+                    .eq(lazy.iter().map(|(addr, insn)| (*addr, insn))));
+                let raw: Vec<_> = code.instructions().map(|i| i.unwrap()).collect();
+                assert_eq!(decoded.len(), raw.len());
+                for (decoded, raw) in decoded.iter().zip(&raw) {
+                    assert_eq!((decoded.addr, decoded.len), (raw.addr, raw.units.len()));
+                    if let InstructionKind::Op(opcode) = raw.kind {
+                        assert_eq!(decoded.insn.opcode(), Some(opcode));
+                    }
+                }
+                instructions += decoded.len();
+            }
+        }
+        assert!(instructions > 10_000);
+    }
+}
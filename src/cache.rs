@@ -1,31 +1,258 @@
-use std::{cell::RefCell, cmp::Eq, hash::Hash, num::NonZeroUsize, rc::Rc};
+//! LRU caches of the items of a `Dex`, bounded by their number of entries and optionally
+//! by their approximate size in memory.
+use std::{cmp::Eq, hash::Hash, mem, num::NonZeroUsize};
 
+use getset::CopyGetters;
 use lru::LruCache;
 
+use crate::{
+    annotation::{AnnotationSetItem, AnnotationsDirectoryItem, EncodedAnnotation},
+    encoded_value::EncodedValue,
+    jtype::{Type, TypeId},
+    string::DexString,
+    sync::{PerThread, RefCell, Shared},
+};
+
+/// Bounds of the caches of a `Dex`, see `DexReaderBuilder::cache_config`. By default the
+/// caches are only bounded by their number of entries, which is a poor proxy for their
+/// memory use: an annotations directory can weigh kilobytes while a string weighs tens of
+/// bytes. `max_bytes` adds a bound on the approximate weight of each cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    pub(crate) strings: usize,
+    pub(crate) types: usize,
+    pub(crate) annotations_directories: usize,
+    pub(crate) descriptors: usize,
+    pub(crate) max_bytes: Option<usize>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            strings: 4096,
+            types: 4096,
+            annotations_directories: 1024,
+            descriptors: 1024,
+            max_bytes: None,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Returns the default bounds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of cached strings. Defaults to 4096, 0 is treated as 1.
+    pub fn strings(mut self, max: usize) -> Self {
+        self.strings = max;
+        self
+    }
+
+    /// Maximum number of cached types. Defaults to 4096, 0 is treated as 1.
+    pub fn types(mut self, max: usize) -> Self {
+        self.types = max;
+        self
+    }
+
+    /// Maximum number of cached annotations directories. Defaults to 1024, 0 is treated
+    /// as 1.
+    pub fn annotations_directories(mut self, max: usize) -> Self {
+        self.annotations_directories = max;
+        self
+    }
+
+    /// Maximum number of cached lookups of class descriptors, see
+    /// `Dex::find_class_by_descriptor`. Defaults to 1024, 0 is treated as 1.
+    pub fn descriptors(mut self, max: usize) -> Self {
+        self.descriptors = max;
+        self
+    }
+
+    /// Maximum total weight of the entries of each cache, in bytes, as estimated by
+    /// `CacheWeight`. The least recently used entries are evicted until the weight of the
+    /// cache fits, and an entry heavier than the bound is not cached at all, so each
+    /// cache stays under it at all times. The numbers of entries stay bounded too.
+    /// Defaults to `None`, no bound on the weight.
+    pub fn max_bytes(mut self, max: Option<usize>) -> Self {
+        self.max_bytes = max;
+        self
+    }
+}
+
+/// Approximate number of bytes of memory held by a cached item, including the item
+/// itself, see `CacheConfig::max_bytes`.
+pub trait CacheWeight {
+    /// The approximate weight of the item, in bytes.
+    fn cache_weight(&self) -> usize;
+}
+
+/// Counters of one of the caches of a `Dex`, see `Dex::cache_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct CacheUsage {
+    /// Number of cached entries.
+    entries: usize,
+    /// Total weight of the cached entries, in bytes, as estimated by `CacheWeight`.
+    weight: usize,
+    /// Number of lookups which found the entry.
+    hits: u64,
+    /// Number of lookups which didn't find the entry.
+    misses: u64,
+    /// Number of entries evicted to make room for others.
+    evictions: u64,
+}
+
+impl CacheUsage {
+    /// Fraction of the lookups which found the entry, 0 if there was no lookup.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// Counters of the caches of a `Dex`, see `Dex::cache_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct CacheStats {
+    /// The cache of the strings.
+    strings: CacheUsage,
+    /// The cache of the types.
+    types: CacheUsage,
+    /// The cache of the annotations directories.
+    annotations_directories: CacheUsage,
+    /// The cache of the lookups of class descriptors.
+    descriptors: CacheUsage,
+}
+
+impl CacheStats {
+    pub(crate) fn new(
+        strings: CacheUsage,
+        types: CacheUsage,
+        annotations_directories: CacheUsage,
+        descriptors: CacheUsage,
+    ) -> Self {
+        Self {
+            strings,
+            types,
+            annotations_directories,
+            descriptors,
+        }
+    }
+}
+
+struct Entries<K, V> {
+    /// The entries with their weight.
+    lru: LruCache<K, (V, usize)>,
+    max_bytes: Option<usize>,
+    usage: CacheUsage,
+}
+
 /// LRU cache that provides interior mutability
 pub(crate) struct Cache<K, V> {
-    inner: Rc<RefCell<LruCache<K, V>>>,
+    inner: Shared<RefCell<Entries<K, V>>>,
+    bypass: Bypass,
 }
 
-impl<K: Hash + Eq, V: Clone> Cache<K, V> {
-    /// Get a new instance of cache with the given capacity
-    pub(crate) fn new(cap: NonZeroUsize) -> Self {
+impl<K: Hash + Eq, V: Clone + CacheWeight> Cache<K, V> {
+    /// Get a new instance of cache holding at most `cap` entries weighing at most
+    /// `max_bytes` in total, which acts as if empty while `bypass` is enabled.
+    pub(crate) fn new(cap: usize, max_bytes: Option<usize>, bypass: Bypass) -> Self {
+        let cap = NonZeroUsize::new(cap).unwrap_or(NonZeroUsize::MIN);
         Self {
-            inner: Rc::new(RefCell::new(LruCache::new(cap))),
+            inner: Shared::new(RefCell::new(Entries {
+                lru: LruCache::new(cap),
+                max_bytes,
+                usage: CacheUsage::default(),
+            })),
+            bypass,
         }
     }
 
     /// Get a reference to the value at key from the cache, if found
-    pub(crate) fn get(&self, key: &K) -> Option<V> {
-        self.inner
-            .borrow_mut()
-            .get(key)
-            .map(std::clone::Clone::clone)
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.bypass.is_enabled() {
+            return None;
+        }
+        let mut inner = self.inner.borrow_mut();
+        let value = inner.lru.get(key).map(|(value, _)| value.clone());
+        if value.is_some() {
+            inner.usage.hits += 1;
+        } else {
+            inner.usage.misses += 1;
+        }
+        value
     }
 
-    /// Insert a new key value pair into the cache
+    /// Insert a new key value pair into the cache, evicting the least recently used
+    /// entries while the cache is full or too heavy.
     pub(crate) fn put(&self, key: K, value: V) {
-        self.inner.borrow_mut().put(key, value);
+        if self.bypass.is_enabled() {
+            return;
+        }
+        let mut inner = self.inner.borrow_mut();
+        let inner = &mut *inner;
+        if let Some((_, weight)) = inner.lru.pop(&key) {
+            inner.usage.weight -= weight;
+        }
+        let weight = mem::size_of::<K>() + value.cache_weight();
+        if inner.max_bytes.is_none_or(|max| weight <= max) {
+            if let Some((_, (_, evicted))) = inner.lru.push(key, (value, weight)) {
+                inner.usage.weight -= evicted;
+                inner.usage.evictions += 1;
+            }
+            inner.usage.weight += weight;
+        }
+        while inner.max_bytes.is_some_and(|max| inner.usage.weight > max) {
+            match inner.lru.pop_lru() {
+                Some((_, (_, evicted))) => {
+                    inner.usage.weight -= evicted;
+                    inner.usage.evictions += 1;
+                }
+                None => break,
+            }
+        }
+        inner.usage.entries = inner.lru.len();
+    }
+
+    /// Removes all the entries. The counters of lookups and evictions are kept.
+    pub(crate) fn clear(&self) {
+        let entries = {
+            let mut inner = self.inner.borrow_mut();
+            inner.usage.entries = 0;
+            inner.usage.weight = 0;
+            let cap = inner.lru.cap();
+            mem::replace(&mut inner.lru, LruCache::new(cap))
+        };
+        drop(entries);
+    }
+
+    /// The counters of the cache.
+    pub(crate) fn usage(&self) -> CacheUsage {
+        self.inner.borrow().usage
+    }
+
+    /// Cached entries, from the most to the least recently used. Doesn't update the usage.
+    #[cfg(any(test, feature = "cache-diagnostics"))]
+    pub(crate) fn entries(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+    {
+        self.inner
+            .borrow()
+            .lru
+            .iter()
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect()
     }
 }
 
@@ -33,6 +260,236 @@ impl<K, V> Clone for Cache<K, V> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            bypass: self.bypass.clone(),
+        }
+    }
+}
+
+/// Weight of the `Shared` allocation around a value: the strong and weak counts.
+const RC_COUNTS: usize = 2 * mem::size_of::<usize>();
+
+impl<T: CacheWeight> CacheWeight for Shared<T> {
+    fn cache_weight(&self) -> usize {
+        RC_COUNTS + T::cache_weight(self)
+    }
+}
+
+/// A string weighs its `Shared<String>` and its characters.
+impl CacheWeight for DexString {
+    fn cache_weight(&self) -> usize {
+        mem::size_of::<Self>() + RC_COUNTS + mem::size_of::<String>() + self.len()
+    }
+}
+
+/// A type weighs its descriptor, which it shares with the cache of the strings.
+impl CacheWeight for Type {
+    fn cache_weight(&self) -> usize {
+        mem::size_of::<Self>() - mem::size_of::<DexString>() + self.type_descriptor().cache_weight()
+    }
+}
+
+/// A lookup of a class descriptor weighs the `TypeId` found, if any. The descriptor
+/// keying it is weighed as a `String`, without its characters.
+impl CacheWeight for Option<TypeId> {
+    fn cache_weight(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
+/// The sets weigh their annotations, and the annotations the count and the contents of
+/// their elements.
+impl CacheWeight for AnnotationsDirectoryItem {
+    fn cache_weight(&self) -> usize {
+        let fields = self
+            .field_annotations
+            .iter()
+            .map(|field| mem::size_of_val(field) + set_heap(&field.annotations));
+        let methods = self
+            .method_annotations
+            .iter()
+            .map(|method| mem::size_of_val(method) + set_heap(&method.annotations));
+        let parameters = self.parameter_annotations.iter().map(|parameters| {
+            mem::size_of_val(parameters)
+                + parameters
+                    .annotations
+                    .annotation_set_list()
+                    .iter()
+                    .map(|set| mem::size_of_val(set) + set_heap(set))
+                    .sum::<usize>()
+        });
+        mem::size_of::<Self>()
+            + set_heap(&self.class_annotations)
+            + fields.chain(methods).chain(parameters).sum::<usize>()
+    }
+}
+
+/// Weight of the annotations of a set, without the set itself.
+fn set_heap(set: &AnnotationSetItem) -> usize {
+    set.annotations()
+        .iter()
+        .map(|item| mem::size_of_val(item) + annotation_heap(item.annotation()))
+        .sum()
+}
+
+/// Weight of the type and elements of an annotation, without the annotation itself.
+fn annotation_heap(annotation: &EncodedAnnotation) -> usize {
+    annotation.jtype().type_descriptor().len()
+        + annotation
+            .elements()
+            .iter()
+            .map(|element| {
+                mem::size_of_val(element) + element.name().len() + value_heap(element.value())
+            })
+            .sum::<usize>()
+}
+
+/// Weight of the contents of a value, without the value itself.
+fn value_heap(value: &EncodedValue) -> usize {
+    match value {
+        EncodedValue::String { value, .. } => value.len(),
+        EncodedValue::Array(values) => values
+            .iter()
+            .map(|value| mem::size_of_val(value) + value_heap(value))
+            .sum(),
+        EncodedValue::Annotation(annotation) => annotation_heap(annotation),
+        _ => 0,
+    }
+}
+
+/// Switch shared by the caches of a `Dex` to read items afresh without reading or
+/// updating the caches, set per thread.
+#[derive(Clone, Default)]
+pub(crate) struct Bypass {
+    enabled: Shared<PerThread<bool>>,
+}
+
+impl Bypass {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    /// Enables the bypass for the current thread until the returned guard is dropped.
+    pub(crate) fn enable(&self) -> BypassGuard {
+        BypassGuard {
+            enabled: self.enabled.clone(),
+            previous: self.enabled.replace(true),
+        }
+    }
+}
+
+/// Restores the previous state of the bypass when dropped.
+pub(crate) struct BypassGuard {
+    enabled: Shared<PerThread<bool>>,
+    previous: bool,
+}
+
+impl Drop for BypassGuard {
+    fn drop(&mut self) {
+        self.enabled.set(self.previous);
+    }
+}
+
+/// Kinds of cached items, see `Dex::compare_cached_vs_fresh`.
+#[cfg(feature = "cache-diagnostics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Strings, keyed by `StringId`.
+    Strings,
+    /// Types, keyed by `TypeId`.
+    Types,
+    /// Annotations directories, keyed by offset.
+    AnnotationsDirectories,
+}
+
+/// A cached item that differs from the item read afresh.
+#[cfg(feature = "cache-diagnostics")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheMismatch {
+    /// Key of the item in its cache.
+    pub key: u64,
+    /// Debug form of the cached item.
+    pub cached: String,
+    /// Debug form of the item read afresh, or of the error reading it.
+    pub fresh: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bypass, Cache, CacheWeight};
+
+    /// A value weighing its number of bytes.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Weighted(usize);
+
+    impl CacheWeight for Weighted {
+        fn cache_weight(&self) -> usize {
+            self.0
+        }
+    }
+
+    /// Weight of the `u8` keys of the test caches.
+    const KEY: usize = 1;
+
+    fn keys(cache: &Cache<u8, Weighted>) -> Vec<u8> {
+        let mut keys: Vec<u8> = cache.entries().into_iter().map(|(key, _)| key).collect();
+        keys.reverse();
+        keys
+    }
+
+    #[test]
+    fn test_count_bound() {
+        let cache = Cache::new(2, None, Bypass::default());
+        for key in 0..3 {
+            cache.put(key, Weighted(1000));
+        }
+        assert_eq!(keys(&cache), [1, 2]);
+        let usage = cache.usage();
+        assert_eq!(usage.entries(), 2);
+        assert_eq!(usage.weight(), 2 * (KEY + 1000));
+        assert_eq!(usage.evictions(), 1);
+    }
+
+    #[test]
+    fn test_weight_bound() {
+        let cache = Cache::new(100, Some(100), Bypass::default());
+        cache.put(0, Weighted(39));
+        cache.put(1, Weighted(29));
+        cache.put(2, Weighted(19));
+        assert_eq!(cache.usage().weight(), 90);
+        // 0 becomes the most recently used
+        assert_eq!(cache.get(&0), Some(Weighted(39)));
+        cache.put(3, Weighted(29));
+        assert_eq!(keys(&cache), [2, 0, 3]);
+        assert_eq!(cache.usage().weight(), 90);
+        cache.put(4, Weighted(59));
+        assert_eq!(keys(&cache), [3, 4]);
+        assert_eq!(cache.usage().evictions(), 3);
+        // replacing an entry updates the weight without evicting others
+        cache.put(4, Weighted(9));
+        assert_eq!(keys(&cache), [3, 4]);
+        assert_eq!(cache.usage().weight(), 40);
+        // heavier than the whole bound, not cached
+        cache.put(5, Weighted(100));
+        assert_eq!(keys(&cache), [3, 4]);
+        let usage = cache.usage();
+        assert!(usage.weight() <= 100);
+        assert_eq!(usage.entries(), 2);
+        assert_eq!((usage.hits(), usage.misses()), (1, 0));
+    }
+
+    #[test]
+    fn test_bypass() {
+        let bypass = Bypass::default();
+        let cache = Cache::new(2, Some(100), bypass.clone());
+        cache.put(0, Weighted(9));
+        {
+            let _guard = bypass.enable();
+            assert_eq!(cache.get(&0), None);
+            cache.put(1, Weighted(9));
         }
+        assert_eq!(keys(&cache), [0]);
+        assert_eq!(cache.get(&1), None);
+        let usage = cache.usage();
+        assert_eq!((usage.hits(), usage.misses()), (0, 1));
     }
 }
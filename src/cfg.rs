@@ -0,0 +1,484 @@
+//! Control-flow graphs of methods.
+//!
+//! `ControlFlowGraph::build` splits the instructions of a `CodeItem` into basic blocks,
+//! runs of instructions entered only at their first instruction and left only after their
+//! last one, linked by the edges control can take between them. Blocks are also split at
+//! the boundaries of the try blocks, so a block is either entirely covered by a try block
+//! or not at all, and the blocks holding a throwing instruction get an edge to each of the
+//! handlers of their try block. Switch and `fill-array-data` payloads are data, they are
+//! not part of any block.
+use std::{collections::BTreeSet, convert::TryFrom};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    code::CodeItem,
+    error::Error,
+    opcode::{Instruction, InstructionKind, Instructions, OpcodeFlags},
+    Result,
+};
+
+/// How control reaches the target of an edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction.
+    FallThrough,
+    /// A conditional branch, `if-*`, is taken.
+    Branch,
+    /// An unconditional branch, `goto`, `goto/16` or `goto/32`.
+    Goto,
+    /// A case of a `packed-switch` or `sparse-switch` is taken.
+    Switch,
+    /// An exception is caught by a handler of the try block covering the block.
+    Exception,
+}
+
+/// An edge from a basic block to one of its successors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    /// Index of the successor in `ControlFlowGraph::blocks`.
+    pub target: usize,
+    /// How control reaches the successor.
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions executed in sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct BasicBlock {
+    /// Address of the first instruction, in code units from the start of the method.
+    #[get_copy = "pub"]
+    start: usize,
+    /// Address just past the last instruction, in code units from the start of the method.
+    #[get_copy = "pub"]
+    end: usize,
+    /// The edges to the successors of the block, without duplicates.
+    #[get = "pub"]
+    edges: Vec<Edge>,
+}
+
+impl BasicBlock {
+    /// Indices of the successors of the block in `ControlFlowGraph::blocks`. A successor
+    /// reached through several kinds of edges is listed once per kind.
+    pub fn successors(&self) -> impl Iterator<Item = usize> + '_ {
+        self.edges.iter().map(|edge| edge.target)
+    }
+
+    /// Returns `true` if the instruction at `addr` is in the block.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    fn add_edge(&mut self, target: usize, kind: EdgeKind) {
+        let edge = Edge { target, kind };
+        if !self.edges.contains(&edge) {
+            self.edges.push(edge);
+        }
+    }
+}
+
+/// The basic blocks of a method and the edges between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    /// Builds the control-flow graph of `code`. Fails with `Error::MalFormed` if an
+    /// instruction is truncated, or if a branch, a switch case, a handler or a try block
+    /// boundary doesn't fall on the start of an instruction.
+    pub fn build(code: &CodeItem) -> Result<Self> {
+        let instructions = Instructions::new(&code.insns).collect::<Result<Vec<_>>>()?;
+        let starts: BTreeSet<usize> = instructions
+            .iter()
+            .filter(|instruction| is_op(instruction))
+            .map(|instruction| instruction.addr)
+            .collect();
+        let check_target = |from: usize, target: i64| -> Result<usize> {
+            usize::try_from(target)
+                .ok()
+                .filter(|target| starts.contains(target))
+                .ok_or_else(|| {
+                    Error::MalFormed(format!(
+                        "Branch at {} targets {} which is not the start of an instruction",
+                        from, target
+                    ))
+                })
+        };
+
+        let mut leaders = BTreeSet::new();
+        let mut targets = Vec::with_capacity(instructions.len());
+        for instruction in &instructions {
+            let flags = match instruction.kind {
+                InstructionKind::Op(opcode) => opcode.flags,
+                _ => {
+                    targets.push(Vec::new());
+                    continue;
+                }
+            };
+            let mut instruction_targets = Vec::new();
+            if let Some(offset) = instruction.branch_offset() {
+                let addr = instruction.addr as i64;
+                if flags.contains(OpcodeFlags::SWITCH) {
+                    let payload = u32::try_from(addr + i64::from(offset)).map_err(|_| {
+                        Error::MalFormed(format!(
+                            "Switch at {} has no payload at offset {}",
+                            instruction.addr, offset
+                        ))
+                    })?;
+                    for (_, target) in code.resolve_switch(payload)?.entries() {
+                        let target = check_target(instruction.addr, addr + i64::from(target))?;
+                        instruction_targets.push((target, EdgeKind::Switch));
+                    }
+                } else {
+                    let kind = if flags.contains(OpcodeFlags::CONTINUE) {
+                        EdgeKind::Branch
+                    } else {
+                        EdgeKind::Goto
+                    };
+                    let target = check_target(instruction.addr, addr + i64::from(offset))?;
+                    instruction_targets.push((target, kind));
+                }
+            }
+            leaders.extend(instruction_targets.iter().map(|(target, _)| *target));
+            if is_terminator(instruction) {
+                leaders.insert(instruction.addr + instruction.units.len());
+            }
+            targets.push(instruction_targets);
+        }
+
+        let boundaries: BTreeSet<usize> = instructions
+            .iter()
+            .map(|instruction| instruction.addr)
+            .chain(std::iter::once(code.insns.len()))
+            .collect();
+        for try_block in code.tries.iter() {
+            let start = try_block.start_addr() as usize;
+            let end = start + usize::from(try_block.insn_count());
+            for addr in [start, end] {
+                if !boundaries.contains(&addr) {
+                    return Err(Error::MalFormed(format!(
+                        "Try block at {} has a boundary at {} which is not the start of an instruction",
+                        start, addr
+                    )));
+                }
+            }
+            leaders.insert(start);
+            leaders.insert(end);
+            for handler in try_block.catch_handlers() {
+                leaders.insert(check_target(start, handler.addr() as i64)?);
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        let mut block_targets: Vec<Vec<(usize, EdgeKind)>> = Vec::new();
+        let mut falls_through: Vec<bool> = Vec::new();
+        let mut open = false;
+        for (instruction, instruction_targets) in instructions.iter().zip(targets) {
+            if !is_op(instruction) {
+                open = false;
+                continue;
+            }
+            if !open || leaders.contains(&instruction.addr) {
+                blocks.push(BasicBlock {
+                    start: instruction.addr,
+                    end: instruction.addr,
+                    edges: Vec::new(),
+                });
+                block_targets.push(Vec::new());
+                falls_through.push(false);
+            }
+            let last = blocks.len() - 1;
+            blocks[last].end = instruction.addr + instruction.units.len();
+            block_targets[last] = instruction_targets;
+            falls_through[last] = can_continue(instruction);
+            open = !is_terminator(instruction);
+        }
+
+        let mut graph = Self { blocks };
+        for index in 0..graph.blocks.len() {
+            let end = graph.blocks[index].end;
+            if falls_through[index] {
+                if let Some(next) = graph.block_starting_at(end) {
+                    graph.blocks[index].add_edge(next, EdgeKind::FallThrough);
+                }
+            }
+            for (target, kind) in &block_targets[index] {
+                let target = graph
+                    .block_starting_at(*target)
+                    .expect("branch targets start blocks");
+                graph.blocks[index].add_edge(target, *kind);
+            }
+        }
+        for try_block in code.tries.iter() {
+            let start = try_block.start_addr() as usize;
+            let end = start + usize::from(try_block.insn_count());
+            let handlers: Vec<usize> = try_block
+                .catch_handlers()
+                .iter()
+                .map(|handler| {
+                    graph
+                        .block_starting_at(handler.addr() as usize)
+                        .expect("handlers start blocks")
+                })
+                .collect();
+            for index in 0..graph.blocks.len() {
+                let block = &graph.blocks[index];
+                if block.start < start || block.end > end || !throws(&instructions, block) {
+                    continue;
+                }
+                for handler in &handlers {
+                    graph.blocks[index].add_edge(*handler, EdgeKind::Exception);
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    /// The basic blocks of the method, in order of address. The entry block, if the method
+    /// has any instruction, is the first one.
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// Index of the block holding the instruction at `addr`, if any.
+    pub fn block_at(&self, addr: usize) -> Option<usize> {
+        let index = self
+            .blocks
+            .partition_point(|block| block.start <= addr)
+            .checked_sub(1)?;
+        Some(index).filter(|index| self.blocks[*index].contains(addr))
+    }
+
+    /// All the edges of the graph, as `(source, target, kind)` triples of block indices,
+    /// e.g. to build a `petgraph` graph with `Graph::from_edges`.
+    pub fn edges(&self) -> impl Iterator<Item = (usize, usize, EdgeKind)> + '_ {
+        self.blocks.iter().enumerate().flat_map(|(index, block)| {
+            block
+                .edges
+                .iter()
+                .map(move |edge| (index, edge.target, edge.kind))
+        })
+    }
+
+    /// The successors of each block, each once and in increasing order, indexed like
+    /// `blocks`.
+    pub fn adjacency_list(&self) -> Vec<Vec<usize>> {
+        self.blocks
+            .iter()
+            .map(|block| {
+                let mut successors: Vec<usize> = block.successors().collect();
+                successors.sort_unstable();
+                successors.dedup();
+                successors
+            })
+            .collect()
+    }
+
+    fn block_starting_at(&self, addr: usize) -> Option<usize> {
+        self.blocks
+            .binary_search_by_key(&addr, |block| block.start)
+            .ok()
+    }
+}
+
+fn is_op(instruction: &Instruction<'_>) -> bool {
+    matches!(instruction.kind, InstructionKind::Op(_))
+}
+
+fn can_continue(instruction: &Instruction<'_>) -> bool {
+    match instruction.kind {
+        InstructionKind::Op(opcode) => opcode.can_continue(),
+        _ => false,
+    }
+}
+
+/// Returns `true` if no instruction of the same block can follow `instruction`.
+fn is_terminator(instruction: &Instruction<'_>) -> bool {
+    match instruction.kind {
+        InstructionKind::Op(opcode) => opcode.is_branch() || !opcode.can_continue(),
+        _ => true,
+    }
+}
+
+fn throws(instructions: &[Instruction<'_>], block: &BasicBlock) -> bool {
+    let first = instructions.partition_point(|instruction| instruction.addr < block.start);
+    instructions[first..]
+        .iter()
+        .take_while(|instruction| instruction.addr < block.end)
+        .any(|instruction| match instruction.kind {
+            InstructionKind::Op(opcode) => opcode.can_throw(),
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlFlowGraph, Edge, EdgeKind};
+    use crate::{code::CodeItem, ushort, DexReader};
+    use scroll::{Pwrite, LE};
+
+    /// A code item with `insns` and a single try block covering `try_range` with a
+    /// catch-all handler at `handler`.
+    fn code_item(insns: &[ushort], try_range: (u32, u16), handler: u8) -> Vec<u8> {
+        let mut data = vec![0u8; 16];
+        data.pwrite_with(1u16, 6, LE).unwrap();
+        data.pwrite_with(insns.len() as u32, 12, LE).unwrap();
+        data.extend(insns.iter().flat_map(|unit| unit.to_le_bytes()));
+        if insns.len() % 2 == 1 {
+            data.extend([0, 0]);
+        }
+        data.extend(try_range.0.to_le_bytes());
+        data.extend(try_range.1.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        // one handler list, of size 0 with a catch-all
+        data.extend([1, 0, handler]);
+        data
+    }
+
+    fn edges(graph: &ControlFlowGraph, index: usize) -> Vec<(usize, EdgeKind)> {
+        graph.blocks()[index]
+            .edges()
+            .iter()
+            .map(|Edge { target, kind }| (*target, *kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_build() {
+        let insns: [ushort; 8] = [
+            0x0012, // 0: const/4 v0, 0
+            0x00b3, // 1: div-int/2addr v0, v0, in the try block
+            0x0038, 4,      // 2: if-eqz v0, +4
+            0x0328, // 4: goto +3
+            0x000e, // 5: return-void, the handler
+            0x0012, // 6: const/4 v0, 0
+            0x000e, // 7: return-void
+        ];
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let data = code_item(&insns, (1, 1), 5);
+        let code: CodeItem =
+            scroll::Pread::pread_with(data.as_slice(), 0, &dex).expect("bad code item");
+        let graph = ControlFlowGraph::build(&code).expect("cannot build graph");
+
+        let ranges: Vec<_> = graph
+            .blocks()
+            .iter()
+            .map(|block| (block.start(), block.end()))
+            .collect();
+        assert_eq!(
+            ranges,
+            [(0, 1), (1, 2), (2, 4), (4, 5), (5, 6), (6, 7), (7, 8)]
+        );
+        assert_eq!(edges(&graph, 0), [(1, EdgeKind::FallThrough)]);
+        assert_eq!(
+            edges(&graph, 1),
+            [(2, EdgeKind::FallThrough), (4, EdgeKind::Exception)]
+        );
+        assert_eq!(
+            edges(&graph, 2),
+            [(3, EdgeKind::FallThrough), (5, EdgeKind::Branch)]
+        );
+        assert_eq!(edges(&graph, 3), [(6, EdgeKind::Goto)]);
+        assert!(edges(&graph, 4).is_empty());
+        assert_eq!(edges(&graph, 5), [(6, EdgeKind::FallThrough)]);
+        assert!(edges(&graph, 6).is_empty());
+        assert_eq!(graph.adjacency_list()[2], [3, 5]);
+        assert_eq!(graph.edges().count(), 7);
+        assert_eq!(graph.block_at(3), Some(2));
+        assert_eq!(graph.block_at(8), None);
+
+        // goto -1 lands in the middle of the if-eqz
+        let mut insns = insns;
+        insns[4] = 0xff28;
+        let data = code_item(&insns, (1, 1), 5);
+        let code: CodeItem =
+            scroll::Pread::pread_with(data.as_slice(), 0, &dex).expect("bad code item");
+        let err = ControlFlowGraph::build(&code).expect_err("built a bad graph");
+        assert!(err.to_string().contains("targets 3"), "{}", err);
+    }
+
+    #[test]
+    fn test_build_switch() {
+        // packed-switch v0 with cases at 3 and 4, then its payload at 6
+        let insns: [ushort; 14] = [
+            0x002b, 6, 0,      // 0: packed-switch v0, +6
+            0x000e, // 3: return-void
+            0x0012, // 4: const/4 v0, 0
+            0x000e, // 5: return-void
+            0x0100, 2, 0, 0, 3, 0, 4, 0,
+        ];
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let data = code_item(&insns, (4, 1), 5);
+        let code: CodeItem =
+            scroll::Pread::pread_with(data.as_slice(), 0, &dex).expect("bad code item");
+        let graph = ControlFlowGraph::build(&code).expect("cannot build graph");
+        let ranges: Vec<_> = graph
+            .blocks()
+            .iter()
+            .map(|block| (block.start(), block.end()))
+            .collect();
+        assert_eq!(ranges, [(0, 3), (3, 4), (4, 5), (5, 6)]);
+        assert_eq!(
+            edges(&graph, 0),
+            [
+                (1, EdgeKind::FallThrough),
+                (1, EdgeKind::Switch),
+                (2, EdgeKind::Switch)
+            ]
+        );
+        assert_eq!(graph.adjacency_list()[0], [1, 2]);
+        // const/4 can't throw, so the try block adds no edge
+        assert_eq!(edges(&graph, 2), [(3, EdgeKind::FallThrough)]);
+        assert_eq!(graph.block_at(8), None);
+    }
+
+    #[test]
+    fn test_adjacency_list() {
+        // packed-switch v0 with cases at 5 and 4 after a division which can throw to 5
+        let insns: [ushort; 14] = [
+            0x00b3, // 0: div-int/2addr v0, v0
+            0x002b, 5, 0,      // 1: packed-switch v0, +5
+            0x000e, // 4: return-void
+            0x000e, // 5: return-void, the handler
+            0x0100, 2, 0, 0, 4, 0, 3, 0,
+        ];
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let data = code_item(&insns, (0, 4), 5);
+        let code: CodeItem =
+            scroll::Pread::pread_with(data.as_slice(), 0, &dex).expect("bad code item");
+        let graph = ControlFlowGraph::build(&code).expect("cannot build graph");
+        assert_eq!(
+            edges(&graph, 0),
+            [
+                (1, EdgeKind::FallThrough),
+                (2, EdgeKind::Switch),
+                (1, EdgeKind::Switch),
+                (2, EdgeKind::Exception)
+            ]
+        );
+        assert_eq!(graph.adjacency_list(), [vec![1, 2], vec![], vec![]]);
+    }
+
+    #[test]
+    fn test_build_classes() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut count = 0;
+        for class in dex.classes() {
+            let class = class.expect("cannot read class");
+            for method in class.methods() {
+                let code = match method.code() {
+                    Some(code) => code,
+                    None => continue,
+                };
+                let graph = ControlFlowGraph::build(code).expect("cannot build graph");
+                assert_eq!(graph.blocks()[0].start(), 0);
+                for (source, target, _) in graph.edges() {
+                    assert!(source < graph.blocks().len());
+                    assert!(target < graph.blocks().len());
+                }
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+}
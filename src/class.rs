@@ -1,19 +1,24 @@
 //! Dex `Class` and supporting structures.
-use std::clone::Clone;
+use std::{clone::Clone, collections::HashSet, time::Instant};
 
 use getset::{CopyGetters, Getters};
 use scroll::{ctx, Pread, Uleb128};
 
 use crate::{
-    annotation::{AnnotationSetItem, AnnotationsDirectoryItem},
-    encoded_item::EncodedItemArrayCtx,
+    annotation::{Annotated, AnnotationSetItem, ClassAnnotations, OrphanAnnotations},
+    class_kind::{self, ClassHeuristics, ClassKind},
+    diagnostic::Span,
+    encoded_item::{EncodedItem, EncodedItemArrayCtx},
+    encoded_value::EncodedArray,
     error::Error,
-    field::{EncodedFieldArray, Field},
-    jtype::Type,
-    method::{EncodedMethodArray, Method},
-    source::Source,
+    field::{EncodedField, EncodedFieldArray, Field},
+    jtype::{Type, TypeId},
+    method::{EncodedMethod, EncodedMethodArray, Method},
+    source::{DexSource, Source},
     string::DexString,
-    uint, utils,
+    sync::{OnceCell, Shared},
+    timing::Category,
+    uint, ulong, utils, well_known,
 };
 
 /// `ClassId` is an index into the Types section. The corresponding `Type` denotes the type of
@@ -36,33 +41,62 @@ bitflags! {
     }
 }
 
+impl_access_flags!(
+    uint,
+    PUBLIC => "public",
+    PRIVATE => "private",
+    PROTECTED => "protected",
+    STATIC => "static",
+    FINAL => "final",
+    INTERFACE => "interface",
+    ABSTRACT => "abstract",
+    SYNTHETIC => "synthetic",
+    ANNOTATION => "annotation",
+    ENUM => "enum",
+);
+
 /// A `Dex` Class. This is constructed from a `ClassDefItem` and a `ClassDataItem`.
-#[derive(Debug)]
+#[derive(Debug, Getters, CopyGetters)]
 pub struct Class {
     /// Index into `TypeId`s. TypeId should refer to a class type.
+    #[get_copy = "pub"]
     pub id: ClassId,
     /// Type of this class.
+    #[get = "pub"]
     pub jtype: Type,
     /// Access flags for the class (public, final etc.)
     /// Check [here](https://source.android.com/devices/tech/dalvik/dex-format#access-flags) for
     /// full reference.
+    #[get_copy = "pub"]
     pub access_flags: AccessFlags,
     /// Index into the `TypeId`s for the super class, if there is one.
+    #[get_copy = "pub"]
     pub super_class: Option<ClassId>,
+    /// Type of the super class, if there is one.
+    pub(crate) superclass: Option<Type>,
     /// List of the interfaces implemented by this class.
+    #[get = "pub"]
     pub interfaces: Vec<Type>,
     /// The file in which this class is found in the source code.
     pub source_file: Option<DexString>,
     /// Static fields defined in the class.
+    #[get = "pub"]
     pub static_fields: Vec<Field>,
     /// Instance fields defined in the class.
+    #[get = "pub"]
     pub instance_fields: Vec<Field>,
     /// List of static, private methods and constructors defined in the class.
+    #[get = "pub"]
     pub direct_methods: Vec<Method>,
     /// List of parent class methods overriden by this class.
+    #[get = "pub"]
     pub virtual_methods: Vec<Method>,
-    /// Annotations of the class.
-    pub annotations: AnnotationSetItem,
+    /// Annotations of the class and its members.
+    pub(crate) annotations: Shared<ClassAnnotations>,
+    /// Which members were read, see `Class::parse_completeness`.
+    pub(crate) parse_completeness: ParseCompleteness,
+    /// Where the static values are stored, see `Class::static_values_span`.
+    pub(crate) static_values_span: Option<StaticValuesSpan>,
 }
 
 impl Class {
@@ -77,21 +111,141 @@ impl Class {
     gen_is_flag_set!(is_annotation, ANNOTATION);
     gen_is_flag_set!(is_enum, ENUM);
 
+    /// Best-effort kind of the class, with all the heuristics recognizing generated
+    /// classes, see `ClassKind`.
+    pub fn kind(&self) -> super::Result<ClassKind> {
+        self.kind_with(ClassHeuristics::default())
+    }
+
+    /// Best-effort kind of the class, with only the `heuristics` recognizing generated
+    /// classes. Reads the annotations of the class only to recognize Kotlin facades.
+    pub fn kind_with(&self, heuristics: ClassHeuristics) -> super::Result<ClassKind> {
+        let kind =
+            class_kind::classify(self.jtype.type_descriptor(), self.access_flags, heuristics);
+        if kind == ClassKind::Regular
+            && heuristics.contains(ClassHeuristics::KOTLIN_FACADE)
+            && class_kind::kotlin_facade(self.annotations()?)
+        {
+            return Ok(ClassKind::KotlinFacade);
+        }
+        Ok(kind)
+    }
+
     /// Returns the value of `dalvik.annotation.Signature`.
     pub fn signature(&self) -> super::Result<Option<String>> {
-        utils::get_signature(&self.annotations)
+        utils::get_signature(self.annotations()?)
     }
 
-    /// The file in which this class is found in the source code.
+    /// Returns the value of `dalvik.annotation.SourceDebugExtension`, a source map which can
+    /// be parsed with `Smap::parse`.
+    pub fn source_debug_extension(&self) -> super::Result<Option<DexString>> {
+        utils::get_source_debug_extension(self.annotations()?)
+    }
+
+    /// Annotations of the class. The annotations are read on first access unless the
+    /// `Dex` was read with eager annotations.
+    pub fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        self.annotations.class()
+    }
+
+    /// Entries of the class's annotations directory referring to fields or methods
+    /// that are not defined in the class.
+    pub fn orphan_annotations(&self) -> super::Result<OrphanAnnotations<'_>> {
+        let field_ids = self.fields().map(|f| f.id()).collect();
+        let method_ids = self.methods().map(|m| m.id()).collect();
+        self.annotations.orphans(&field_ids, &method_ids)
+    }
+
+    /// The file in which this class is found in the source code, `None` if the class
+    /// doesn't record it.
     pub fn source_file(&self) -> Option<&DexString> {
         self.source_file.as_ref()
     }
 
+    /// Type of the super class, `None` for `java.lang.Object`.
+    pub fn superclass(&self) -> Option<&Type> {
+        self.superclass.as_ref()
+    }
+
+    /// Iterator over the ancestors of the class, from its superclass up. The ancestors
+    /// defined in `dex` are loaded; the first one which is not ends the chain as an
+    /// `Ancestor::External`, as `java.lang.Object` does for the classes of an app. A
+    /// class met twice, which only happens in malformed files, ends the chain with
+    /// `Error::MalFormed`.
+    pub fn superclass_chain<'a, T>(&self, dex: &'a super::Dex<T>) -> SuperclassChain<'a, T>
+    where
+        T: DexSource + 'static,
+    {
+        SuperclassChain {
+            dex,
+            next: self.super_class,
+            visited: std::iter::once(self.id).collect(),
+        }
+    }
+
+    /// The interfaces the class implements, directly or through its ancestors and the
+    /// interfaces they extend, each once. The interfaces are listed depth first, in the
+    /// order of the interfaces lists, those of the class before those of its superclass.
+    /// The interfaces not defined in `dex` are listed without the interfaces they extend.
+    /// An interface extending itself, directly or not, which only happens in malformed
+    /// files, fails with `Error::MalFormed`.
+    pub fn all_interfaces<T>(&self, dex: &super::Dex<T>) -> super::Result<Vec<Type>>
+    where
+        T: DexSource + 'static,
+    {
+        let mut all_interfaces = Vec::new();
+        let mut seen = HashSet::new();
+        collect_interfaces(
+            dex,
+            &self.interfaces,
+            &mut vec![self.id],
+            &mut seen,
+            &mut all_interfaces,
+        )?;
+        for ancestor in self.superclass_chain(dex) {
+            match ancestor? {
+                Ancestor::Class(class) => collect_interfaces(
+                    dex,
+                    &class.interfaces,
+                    &mut vec![class.id],
+                    &mut seen,
+                    &mut all_interfaces,
+                )?,
+                Ancestor::External(_) => break,
+            }
+        }
+        Ok(all_interfaces)
+    }
+
     /// List of fields defined in this class.
     pub fn fields(&self) -> impl Iterator<Item = &Field> + '_ {
-        self.static_fields
+        self.static_fields.iter().chain(self.instance_fields.iter())
+    }
+
+    /// The static initializer of the class, `<clinit>`, if it has one.
+    pub fn clinit(&self) -> Option<&Method> {
+        self.direct_methods
             .iter()
-            .chain(self.instance_fields.iter())
+            .find(|method| method.is_static() && *method.name() == *well_known::STATIC_INITIALIZER)
+    }
+
+    /// Returns `true` if the class has a static initializer.
+    pub fn has_clinit(&self) -> bool {
+        self.clinit().is_some()
+    }
+
+    /// Which members of the class were read, by group. Only the classes of a `Dex` read
+    /// with `DexReaderBuilder::lenient_class_data` can miss some, the others fail to load
+    /// instead.
+    pub fn parse_completeness(&self) -> &ParseCompleteness {
+        &self.parse_completeness
+    }
+
+    /// Where the static values of the class are stored, with a hash of their bytes. Only
+    /// the classes of a `Dex` read with `DexReaderBuilder::track_spans` record it, it is
+    /// `None` for the others and for classes without static values.
+    pub fn static_values_span(&self) -> Option<&StaticValuesSpan> {
+        self.static_values_span.as_ref()
     }
 
     /// List of methods defined in this class.
@@ -101,108 +255,306 @@ impl Class {
             .chain(self.virtual_methods.iter())
     }
 
-    pub(crate) fn try_from_dex<T: AsRef<[u8]>>(
+    pub(crate) fn try_from_dex<T: DexSource + 'static>(
         dex: &super::Dex<T>,
         class_def: &ClassDefItem,
     ) -> super::Result<Self> {
-        debug!(target: "class", "trying to load class: {}", class_def.class_idx);
+        let _timer = dex.timers.start(Category::ClassData);
+        dex_debug!(dex.log_label(), target: "class", "trying to load class: {}", class_def.class_idx);
         let jtype = dex.get_type(class_def.class_idx)?;
 
-        debug!(target: "class", "class: {}, jtype: {}", class_def.class_idx, jtype);
+        dex_debug!(dex.log_label(), target: "class", "class: {}, jtype: {}", class_def.class_idx, jtype);
 
         let data_off = class_def.class_data_off;
 
-        let AnnotationsDirectoryItem {
-            class_annotations,
-            mut field_annotations,
-            mut method_annotations,
-            mut parameter_annotations,
-        } = dex.get_annotations_directory_item(class_def.annotations_off)?;
+        let annotations = dex.get_class_annotations(class_def.annotations_off)?;
         let static_values = dex.get_static_values(class_def.static_values_off)?;
-        let (static_fields, instance_fields, direct_methods, virtual_methods) = dex
-            .get_class_data(data_off)?
-            .map(move |c| {
-                let mut static_values = static_values.into_inner();
-                // the order of static values corresponds to the fields list.
-                // reversing the values so that the pop below returns values in
-                // correct order.
-                static_values.reverse();
-                Ok((
-                    try_from_item!(c.static_fields, |encoded_field| {
-                        dex.get_field(
-                            &encoded_field,
-                            static_values.pop(),
-                            field_annotations
-                                .binary_search_by_key(&encoded_field.field_id(), |f| f.field_idx())
-                                .map(|index| field_annotations.remove(index).annotations)
-                                .unwrap_or_else(|_| Default::default()),
-                        )
-                    }),
-                    try_from_item!(c.instance_fields, |encoded_field| {
-                        dex.get_field(
-                            &encoded_field,
-                            None,
-                            field_annotations
-                                .binary_search_by_key(&encoded_field.field_id(), |f| f.field_idx())
-                                .map(|index| field_annotations.remove(index).annotations)
-                                .unwrap_or_else(|_| Default::default()),
-                        )
-                    }),
-                    try_from_item!(c.direct_methods, |encoded_method| {
-                        let method_annotations = method_annotations
-                            .binary_search_by_key(&encoded_method.method_id(), |m| m.method_idx())
-                            .map(|index| method_annotations.remove(index).annotations)
-                            .unwrap_or_else(|_| Default::default());
-                        let parameter_annotations = parameter_annotations
-                            .binary_search_by_key(&encoded_method.method_id(), |m| m.method_idx())
-                            .map(|index| parameter_annotations.remove(index).annotations)
-                            .unwrap_or_else(|_| Default::default());
-                        dex.get_method(&encoded_method, method_annotations, parameter_annotations)
-                    }),
-                    try_from_item!(c.virtual_methods, |encoded_method| {
-                        let method_annotations = method_annotations
-                            .binary_search_by_key(&encoded_method.method_id(), |m| m.method_idx())
-                            .map(|index| method_annotations.remove(index).annotations)
-                            .unwrap_or_else(|_| Default::default());
-                        let parameter_annotations = parameter_annotations
-                            .binary_search_by_key(&encoded_method.method_id(), |m| m.method_idx())
-                            .map(|index| parameter_annotations.remove(index).annotations)
-                            .unwrap_or_else(|_| Default::default());
-                        dex.get_method(&encoded_method, method_annotations, parameter_annotations)
-                    }),
-                ))
+        let static_values_span = if dex.track_spans() && class_def.static_values_off != 0 {
+            let (size, bytes_hash) = static_values.byte_len_and_hash()?;
+            Some(StaticValuesSpan {
+                span: Span::new(class_def.static_values_off, size as uint),
+                bytes_hash,
             })
-            .unwrap_or_else(|| Ok::<_, Error>(Default::default()))?;
-
-        debug!(target: "class", "super class id: {}", class_def.superclass_idx);
-        let super_class = if class_def.superclass_idx != super::NO_INDEX {
-            Some(class_def.superclass_idx)
         } else {
             None
         };
-        debug!(target: "class", "access flags: {}", class_def.access_flags);
+        let mut parse_completeness = ParseCompleteness::default();
+        let (static_fields, instance_fields, direct_methods, virtual_methods) = if dex
+            .lenient_class_data()
+        {
+            match dex.get_class_data_lenient(data_off)? {
+                Some(c) => {
+                    let (statics, instances, directs, virtuals, completeness) =
+                        c.resolve(dex, &static_values, &annotations);
+                    for (group, status) in completeness.iter() {
+                        if let GroupStatus::Truncated { index, error } = status {
+                            dex_warn!(dex.log_label(), target: "class", "class {}: {:?} truncated at member {}: {}",
+                                class_def.class_idx, group, index, error);
+                        }
+                    }
+                    parse_completeness = completeness;
+                    (statics, instances, directs, virtuals)
+                }
+                None => Default::default(),
+            }
+        } else {
+            dex.get_class_data(data_off)?
+                .map(|c| {
+                    // the order of static values corresponds to the fields list.
+                    let mut static_values = static_values.iter();
+                    Ok((
+                        try_from_item!(c.static_fields, |encoded_field| {
+                            dex.get_field(
+                                &encoded_field,
+                                static_values.next().transpose()?,
+                                annotations.clone(),
+                            )
+                        }),
+                        try_from_item!(c.instance_fields, |encoded_field| {
+                            dex.get_field(&encoded_field, None, annotations.clone())
+                        }),
+                        try_from_item!(c.direct_methods, |encoded_method| {
+                            dex.get_method(&encoded_method, annotations.clone())
+                        }),
+                        try_from_item!(c.virtual_methods, |encoded_method| {
+                            dex.get_method(&encoded_method, annotations.clone())
+                        }),
+                    ))
+                })
+                .unwrap_or_else(|| Ok::<_, Error>(Default::default()))?
+        };
+
+        dex_debug!(dex.log_label(), target: "class", "super class id: {}", class_def.superclass_idx);
+        let (super_class, superclass) = if class_def.superclass_idx != super::NO_INDEX {
+            (
+                Some(class_def.superclass_idx),
+                Some(dex.get_type(class_def.superclass_idx)?),
+            )
+        } else {
+            (None, None)
+        };
+        dex_debug!(dex.log_label(), target: "class", "access flags: {}", class_def.access_flags);
 
         Ok(Class {
             id: class_def.class_idx,
             jtype,
             super_class,
+            superclass,
             interfaces: dex.get_interfaces(class_def.interfaces_off)?,
-            access_flags: AccessFlags::from_bits(class_def.access_flags).ok_or_else(|| {
-                Error::InvalidId(format!(
-                    "Invalid Access flags in class {}",
-                    class_def.class_idx
-                ))
-            })?,
+            access_flags: AccessFlags::from_bits_retain(class_def.access_flags),
             source_file: dex.get_source_file(class_def.source_file_idx)?,
             static_fields,
             instance_fields,
             direct_methods,
             virtual_methods,
-            annotations: class_annotations,
+            annotations,
+            parse_completeness,
+            static_values_span,
+        })
+    }
+}
+
+/// Where the static values of a class are stored, see `Class::static_values_span`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct StaticValuesSpan {
+    /// Offset and size of the encoded array of the static values.
+    span: Span,
+    /// 64-bit FNV-1a hash of the bytes of the encoded array, stable between runs, see
+    /// [stable hashes](crate#stable-hashes). The values refer to
+    /// strings, types and members by index, so equal values hash differently when these
+    /// were renumbered. The hash is not collision resistant, compare the bytes to be sure
+    /// that two arrays are equal.
+    bytes_hash: u64,
+}
+
+/// An ancestor of a class, see `Class::superclass_chain`.
+#[derive(Debug)]
+pub enum Ancestor {
+    /// A class defined in the file.
+    Class(Box<Class>),
+    /// The type of a class not defined in the file, such as a class of the platform.
+    External(Type),
+}
+
+/// Iterator over the ancestors of a class, see `Class::superclass_chain`.
+pub struct SuperclassChain<'a, T> {
+    dex: &'a super::Dex<T>,
+    /// Type of the next ancestor, `None` once the chain ended.
+    next: Option<TypeId>,
+    /// Types of the classes met so far.
+    visited: HashSet<TypeId>,
+}
+
+impl<T> Iterator for SuperclassChain<'_, T>
+where
+    T: DexSource + 'static,
+{
+    type Item = super::Result<Ancestor>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let type_id = self.next.take()?;
+        if !self.visited.insert(type_id) {
+            return Some(Err(cycle(self.dex, "superclass chain", type_id)));
+        }
+        Some(match self.dex.get_class_by_type(type_id) {
+            Ok(Some(class)) => {
+                self.next = class.super_class;
+                Ok(Ancestor::Class(Box::new(class)))
+            }
+            Ok(None) => self.dex.get_type(type_id).map(Ancestor::External),
+            Err(e) => Err(e),
         })
     }
 }
 
+/// Adds the `interfaces` not seen yet to `all_interfaces`, each followed by the interfaces
+/// it extends. `path` holds the types whose interfaces are being collected.
+fn collect_interfaces<T>(
+    dex: &super::Dex<T>,
+    interfaces: &[Type],
+    path: &mut Vec<TypeId>,
+    seen: &mut HashSet<TypeId>,
+    all_interfaces: &mut Vec<Type>,
+) -> super::Result<()>
+where
+    T: DexSource + 'static,
+{
+    for interface in interfaces {
+        if path.contains(&interface.id) {
+            return Err(cycle(dex, "interfaces", interface.id));
+        }
+        if !seen.insert(interface.id) {
+            continue;
+        }
+        all_interfaces.push(interface.clone());
+        if let Some(class) = dex.get_class_by_type(interface.id)? {
+            path.push(interface.id);
+            collect_interfaces(dex, &class.interfaces, path, seen, all_interfaces)?;
+            path.pop();
+        }
+    }
+    Ok(())
+}
+
+fn cycle<T: DexSource>(dex: &super::Dex<T>, what: &str, type_id: TypeId) -> Error {
+    let name = dex
+        .get_type(type_id)
+        .map(|jtype| jtype.to_string())
+        .unwrap_or_else(|_| type_id.to_string());
+    Error::MalFormed(format!("Cycle in the {} at class {}", what, name))
+}
+
+/// A class definition whose class data is only parsed on request, see
+/// `Dex::class_handles`. The class_def row is read when the handle is created; the type,
+/// superclass and source file are read from the pools on each call, through the caches of
+/// the `Dex`. The fields, methods and annotations are parsed by `load`, once: the class is
+/// kept in the handle and the items it shares with classes loaded by `Dex::classes`, such
+/// as types, strings and annotations directories, are read from the same caches.
+pub struct ClassHandle<'a, T> {
+    dex: &'a super::Dex<T>,
+    index: uint,
+    class_def: ClassDefItem,
+    class: OnceCell<Class>,
+}
+
+impl<'a, T> ClassHandle<'a, T>
+where
+    T: DexSource + 'static,
+{
+    pub(crate) fn new(dex: &'a super::Dex<T>, index: uint, class_def: ClassDefItem) -> Self {
+        Self {
+            dex,
+            index,
+            class_def,
+            class: OnceCell::new(),
+        }
+    }
+
+    /// Index of the class definition in the class_defs section.
+    pub fn index(&self) -> uint {
+        self.index
+    }
+
+    /// The class definition, with the offsets of the class data, annotations directory
+    /// and static values.
+    pub fn class_def(&self) -> &ClassDefItem {
+        &self.class_def
+    }
+
+    /// Id of the type of the class.
+    pub fn id(&self) -> ClassId {
+        self.class_def.class_idx
+    }
+
+    /// Type of the class.
+    pub fn jtype(&self) -> super::Result<Type> {
+        self.dex.get_type(self.class_def.class_idx)
+    }
+
+    /// Type of the superclass, `None` for `java.lang.Object`.
+    pub fn super_class(&self) -> super::Result<Option<Type>> {
+        if self.class_def.superclass_idx == super::NO_INDEX {
+            return Ok(None);
+        }
+        self.dex.get_type(self.class_def.superclass_idx).map(Some)
+    }
+
+    /// Access flags of the class, with the reserved bits set if any.
+    pub fn access_flags(&self) -> AccessFlags {
+        AccessFlags::from_bits_retain(self.class_def.access_flags)
+    }
+
+    /// The file in which the class is found in the source code.
+    pub fn source_file(&self) -> super::Result<Option<DexString>> {
+        self.dex.get_source_file(self.class_def.source_file_idx)
+    }
+
+    /// Returns `true` if the class has been loaded.
+    pub fn is_loaded(&self) -> bool {
+        self.class.get().is_some()
+    }
+
+    /// Parses the class data, annotations and static values of the class on the first
+    /// call, and returns the class. A class which can't be loaded fails every call.
+    pub fn load(&self) -> super::Result<&Class> {
+        if let Some(class) = self.class.get() {
+            return Ok(class);
+        }
+        let class = Class::try_from_dex(self.dex, &self.class_def)?;
+        Ok(self.class.get_or_init(|| class))
+    }
+
+    /// The class, loaded unless it already was.
+    pub fn into_class(self) -> super::Result<Class> {
+        match self.class.into_inner() {
+            Some(class) => Ok(class),
+            None => Class::try_from_dex(self.dex, &self.class_def),
+        }
+    }
+
+    /// List of fields defined in the class, loading it.
+    pub fn fields(&self) -> super::Result<impl Iterator<Item = &Field> + '_> {
+        Ok(self.load()?.fields())
+    }
+
+    /// List of methods defined in the class, loading it.
+    pub fn methods(&self) -> super::Result<impl Iterator<Item = &Method> + '_> {
+        Ok(self.load()?.methods())
+    }
+
+    /// Annotations of the class, loading it.
+    pub fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        self.load()?.annotations()
+    }
+}
+
+impl Annotated for Class {
+    fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        Class::annotations(self)
+    }
+}
+
 /// Contains the details about fields and methods of a class.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#class-data-item)
 #[derive(Getters)]
@@ -241,7 +593,7 @@ impl ClassDataItem {
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for ClassDataItem
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -253,7 +605,7 @@ where
         let direct_methods_size = Uleb128::read(source, offset)?;
         let virtual_methods_size = Uleb128::read(source, offset)?;
 
-        debug!(target: "class data", "static-fields: {}, instance-fields: {}, direct-methods: {}, virtual-methods: {}",
+        dex_debug!(dex.log_label(), target: "class data", "static-fields: {}, instance-fields: {}, direct-methods: {}, virtual-methods: {}",
             static_field_size, instance_field_size, direct_methods_size, virtual_methods_size);
 
         Ok((
@@ -268,6 +620,242 @@ where
     }
 }
 
+/// A group of members of a class, in the order of the class data item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemberGroup {
+    StaticFields,
+    InstanceFields,
+    DirectMethods,
+    VirtualMethods,
+}
+
+impl MemberGroup {
+    /// All the groups, in the order of the class data item.
+    pub const ALL: [MemberGroup; 4] = [
+        MemberGroup::StaticFields,
+        MemberGroup::InstanceFields,
+        MemberGroup::DirectMethods,
+        MemberGroup::VirtualMethods,
+    ];
+}
+
+/// How much of a group of members was read, see `Class::parse_completeness`.
+#[derive(Debug)]
+pub enum GroupStatus {
+    /// Every member of the group was read.
+    Complete,
+    /// The member at `index` of the group failed with `error`. The members before it were
+    /// kept, the ones after it dropped.
+    Truncated { index: usize, error: Error },
+    /// The group wasn't read: an earlier member couldn't be decoded, so where the group
+    /// starts is unknown.
+    NotReached,
+}
+
+impl GroupStatus {
+    /// Returns `true` if every member of the group was read.
+    pub fn is_complete(&self) -> bool {
+        matches!(self, GroupStatus::Complete)
+    }
+}
+
+/// Which members of a class were read, by group.
+///
+/// The class data item starts with the number of members of each group, but not their
+/// size in bytes, and members are encoded with variable length integers. A member whose
+/// encoding can't be decoded, because it is truncated or overlong, leaves the start of the
+/// next one unknown: its group is `Truncated` and the groups after it are `NotReached`. A
+/// member which is decoded but refers to an invalid item, such as a field id out of range or
+/// a bad code offset, only truncates its own group, as the next group can still be found.
+#[derive(Debug)]
+pub struct ParseCompleteness {
+    groups: [GroupStatus; 4],
+}
+
+impl Default for ParseCompleteness {
+    fn default() -> Self {
+        Self {
+            groups: [
+                GroupStatus::Complete,
+                GroupStatus::Complete,
+                GroupStatus::Complete,
+                GroupStatus::Complete,
+            ],
+        }
+    }
+}
+
+impl ParseCompleteness {
+    /// The status of a group.
+    pub fn status(&self, group: MemberGroup) -> &GroupStatus {
+        &self.groups[group as usize]
+    }
+
+    /// The status of every group, in the order of the class data item.
+    pub fn iter(&self) -> impl Iterator<Item = (MemberGroup, &GroupStatus)> + '_ {
+        MemberGroup::ALL.iter().copied().zip(self.groups.iter())
+    }
+
+    /// Returns `true` if every member of the class was read.
+    pub fn is_complete(&self) -> bool {
+        self.groups.iter().all(GroupStatus::is_complete)
+    }
+
+    /// Builds the members of a group from their decoded encoding with `f`, up to the first
+    /// one failing, which truncates the group.
+    fn resolve<T, U, F>(&mut self, group: MemberGroup, encoded: Vec<T>, mut f: F) -> Vec<U>
+    where
+        F: FnMut(&T) -> super::Result<U>,
+    {
+        let mut members = Vec::with_capacity(encoded.len());
+        for (index, encoded) in encoded.iter().enumerate() {
+            match f(encoded) {
+                Ok(member) => members.push(member),
+                Err(error) => {
+                    // comes before any failure to decode the group
+                    self.groups[group as usize] = GroupStatus::Truncated { index, error };
+                    break;
+                }
+            }
+        }
+        members
+    }
+}
+
+/// The encoded members of a class data item, read one by one and kept up to the first one
+/// which can't be decoded. See `DexReaderBuilder::lenient_class_data`.
+pub(crate) struct LenientClassData {
+    static_fields: Vec<EncodedField>,
+    instance_fields: Vec<EncodedField>,
+    direct_methods: Vec<EncodedMethod>,
+    virtual_methods: Vec<EncodedMethod>,
+    completeness: ParseCompleteness,
+}
+
+impl LenientClassData {
+    /// Builds the members, truncating each group at its first member failing.
+    #[allow(clippy::type_complexity)]
+    fn resolve<T: DexSource + 'static>(
+        self,
+        dex: &super::Dex<T>,
+        static_values: &EncodedArray,
+        annotations: &Shared<ClassAnnotations>,
+    ) -> (
+        Vec<Field>,
+        Vec<Field>,
+        Vec<Method>,
+        Vec<Method>,
+        ParseCompleteness,
+    ) {
+        let mut completeness = self.completeness;
+        // the order of static values corresponds to the fields list.
+        let mut static_values = static_values.iter();
+        let static_fields =
+            completeness.resolve(MemberGroup::StaticFields, self.static_fields, |field| {
+                dex.get_field(
+                    field,
+                    static_values.next().transpose()?,
+                    annotations.clone(),
+                )
+            });
+        let instance_fields =
+            completeness.resolve(MemberGroup::InstanceFields, self.instance_fields, |field| {
+                dex.get_field(field, None, annotations.clone())
+            });
+        let direct_methods =
+            completeness.resolve(MemberGroup::DirectMethods, self.direct_methods, |method| {
+                dex.get_method(method, annotations.clone())
+            });
+        let virtual_methods = completeness.resolve(
+            MemberGroup::VirtualMethods,
+            self.virtual_methods,
+            |method| dex.get_method(method, annotations.clone()),
+        );
+        (
+            static_fields,
+            instance_fields,
+            direct_methods,
+            virtual_methods,
+            completeness,
+        )
+    }
+}
+
+/// Decodes `size` members at `offset`, stopping at the first one which can't be decoded
+/// and returning its index and error.
+fn read_members<'a, T>(
+    source: &'a [u8],
+    offset: &mut usize,
+    size: ulong,
+    members: &mut Vec<T>,
+) -> Result<(), (usize, Error)>
+where
+    T: EncodedItem + ctx::TryFromCtx<'a, ulong, Size = usize, Error = Error>,
+{
+    let mut prev = 0;
+    for index in 0..size as usize {
+        let member: T = source
+            .gread_with(offset, prev)
+            .map_err(|error| (index, error))?;
+        prev = member.id();
+        members.push(member);
+    }
+    Ok(())
+}
+
+impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for LenientClassData
+where
+    S: DexSource,
+{
+    type Error = Error;
+    type Size = usize;
+
+    fn try_from_ctx(source: &'a [u8], dex: &super::Dex<S>) -> super::Result<(Self, Self::Size)> {
+        let offset = &mut 0;
+        let mut sizes = [0; 4];
+        for size in &mut sizes {
+            *size = Uleb128::read(source, offset)?;
+        }
+        dex_debug!(dex.log_label(), target: "class data", "lenient sizes: {:?}", sizes);
+
+        let mut data = LenientClassData {
+            static_fields: Vec::new(),
+            instance_fields: Vec::new(),
+            direct_methods: Vec::new(),
+            virtual_methods: Vec::new(),
+            completeness: ParseCompleteness::default(),
+        };
+        let mut reached = true;
+        for group in MemberGroup::ALL {
+            let status = &mut data.completeness.groups[group as usize];
+            if !reached {
+                *status = GroupStatus::NotReached;
+                continue;
+            }
+            let size = sizes[group as usize];
+            let result = match group {
+                MemberGroup::StaticFields => {
+                    read_members(source, offset, size, &mut data.static_fields)
+                }
+                MemberGroup::InstanceFields => {
+                    read_members(source, offset, size, &mut data.instance_fields)
+                }
+                MemberGroup::DirectMethods => {
+                    read_members(source, offset, size, &mut data.direct_methods)
+                }
+                MemberGroup::VirtualMethods => {
+                    read_members(source, offset, size, &mut data.virtual_methods)
+                }
+            };
+            if let Err((index, error)) = result {
+                *status = GroupStatus::Truncated { index, error };
+                reached = false;
+            }
+        }
+        Ok((data, *offset))
+    }
+}
+
 /// Defines the locations of the contents of a `Class`.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#class-def-item)
 #[derive(Copy, Clone, Debug, Pread, CopyGetters)]
@@ -300,35 +888,692 @@ pub struct ClassDefItem {
 pub(crate) struct ClassDefItemIter<T> {
     /// Source file of the parent `Dex`.
     source: Source<T>,
+    /// Offset of the first class definition.
     offset: usize,
+    /// Index of the next class definition.
+    position: uint,
     len: uint,
     endian: super::Endian,
+    /// Deadline checked before each class definition but the first, see
+    /// `OperationOptions::deadline`.
+    deadline: Option<Instant>,
+    started: bool,
 }
 
 impl<T> ClassDefItemIter<T> {
-    pub(crate) fn new(source: Source<T>, offset: uint, len: uint, endian: super::Endian) -> Self {
+    /// Iterates over the `len` class definitions at `offset`, from the one at `position`.
+    pub(crate) fn new(
+        source: Source<T>,
+        offset: uint,
+        len: uint,
+        endian: super::Endian,
+        position: uint,
+        deadline: Option<Instant>,
+    ) -> Self {
         Self {
             source,
             offset: offset as usize,
+            position: position.min(len),
             len,
             endian,
+            deadline,
+            started: false,
         }
     }
 }
 
-impl<T: AsRef<[u8]>> Iterator for ClassDefItemIter<T> {
+impl<T: DexSource> Iterator for ClassDefItemIter<T> {
     type Item = super::Result<ClassDefItem>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.len == 0 {
+        if self.position == self.len {
             return None;
         }
+        if self.started
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            let processed = self.position as usize;
+            self.position = self.len;
+            return Some(Err(Error::DeadlineExceeded(processed, self.len as usize)));
+        }
+        self.started = true;
+        // read each class definition at its own offset, so that a failed read doesn't
+        // shift the following ones
+        let offset = self.offset + self.position as usize * 32;
         let class_item: super::Result<ClassDefItem> = self
             .source
-            .as_ref()
-            .gread_with(&mut self.offset, self.endian)
+            .pread_with(offset, self.endian)
             .map_err(Error::from);
-        self.len -= 1;
+        self.position += 1;
         Some(class_item)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.len - self.position) as usize;
+        // a passed deadline ends the iteration after the `Error::DeadlineExceeded` it
+        // yields
+        match self.deadline {
+            Some(_) => (remaining.min(1), Some(remaining)),
+            None => (remaining, Some(remaining)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scroll::{ctx::TryFromCtx, Pread, Pwrite, Uleb128, LE};
+
+    use super::{ClassDataItem, GroupStatus, LenientClassData, MemberGroup};
+    use crate::{test_utils::mutated_example_dex, DexReader, DexReaderBuilder};
+
+    #[test]
+    fn test_class_handles() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let handles: Vec<_> = dex.class_handles().map(Result::unwrap).collect();
+        assert_eq!(handles.len(), 323);
+        for (handle, class) in handles.iter().zip(dex.classes()) {
+            let class = class.unwrap();
+            assert_eq!(handle.jtype().unwrap(), *class.jtype());
+            assert_eq!(handle.access_flags(), class.access_flags());
+            assert_eq!(handle.source_file().unwrap().as_ref(), class.source_file());
+            assert_eq!(
+                handle.super_class().unwrap().map(|jtype| jtype.to_string()),
+                class
+                    .super_class()
+                    .map(|id| dex.get_type(id).unwrap().to_string())
+            );
+            assert!(!handle.is_loaded());
+        }
+
+        // only the classes of a package are loaded
+        let launcher: Vec<_> = handles
+            .iter()
+            .filter(|handle| {
+                handle
+                    .jtype()
+                    .unwrap()
+                    .to_string()
+                    .starts_with("Lorg/adw/launcher/")
+            })
+            .collect();
+        assert!(!launcher.is_empty() && launcher.len() < handles.len());
+        for handle in &launcher {
+            let methods = handle.methods().unwrap().count();
+            assert!(handle.is_loaded());
+            assert_eq!(handle.load().unwrap().methods().count(), methods);
+        }
+        assert_eq!(
+            handles.iter().filter(|handle| handle.is_loaded()).count(),
+            launcher.len()
+        );
+
+        // a class data offset outside of the file only fails loading the class
+        let data = mutated_example_dex(|data| {
+            let class_defs_off = data.pread::<u32>(0x64).unwrap() as usize;
+            data.pwrite_with(u32::MAX, class_defs_off + 7 * 32 + 24, LE)
+                .unwrap();
+        });
+        let dex = DexReader::from_vec(data).expect("cannot open dex");
+        let handle = dex.class_handles().nth(7).unwrap().unwrap();
+        assert_eq!(handle.index(), 7);
+        assert!(handle.jtype().is_ok());
+        assert!(handle.load().is_err());
+        assert!(handle.methods().is_err());
+        assert!(handle.into_class().is_err());
+    }
+
+    #[test]
+    fn test_superclass_source_file_and_interfaces() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for class in dex.classes() {
+            let class = class.unwrap();
+            let super_class = class.super_class().map(|id| dex.get_type(id).unwrap());
+            assert_eq!(class.superclass(), super_class.as_ref());
+            assert!(class.source_file().is_some());
+        }
+        let launcher = dex
+            .find_class_by_name("org.adw.launcher.Launcher")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            launcher.superclass().unwrap().to_string(),
+            "Landroid/app/Activity;"
+        );
+        assert_eq!(launcher.source_file().unwrap(), "Launcher.java");
+        assert!(!launcher.interfaces().is_empty());
+
+        // NO_INDEX stands for no superclass and no source file, other invalid ids fail
+        let read = |superclass_idx: u32, source_file_idx: u32| {
+            let data = mutated_example_dex(|data| {
+                let class_defs_off = data.pread::<u32>(0x64).unwrap() as usize;
+                data.pwrite_with(superclass_idx, class_defs_off + 8, LE)
+                    .unwrap();
+                data.pwrite_with(source_file_idx, class_defs_off + 16, LE)
+                    .unwrap();
+            });
+            let dex = DexReader::from_vec(data).expect("cannot open dex");
+            let class = dex.classes().next().unwrap();
+            class
+        };
+        let class = read(crate::NO_INDEX, crate::NO_INDEX).unwrap();
+        assert!(class.super_class().is_none() && class.superclass().is_none());
+        assert!(class.source_file().is_none());
+        assert!(read(crate::NO_INDEX - 1, crate::NO_INDEX).is_err());
+        assert!(read(crate::NO_INDEX, crate::NO_INDEX - 1).is_err());
+    }
+
+    #[test]
+    fn test_access_flags() {
+        use super::AccessFlags;
+        use crate::{field, method};
+
+        let flags =
+            field::AccessFlags::PUBLIC | field::AccessFlags::STATIC | field::AccessFlags::FINAL;
+        assert_eq!(flags.to_modifier_string(), "public static final");
+        assert_eq!(AccessFlags::empty().to_modifier_string(), "");
+        // 0x40 is volatile for fields but bridge for methods
+        assert_eq!(
+            field::AccessFlags::from_bits_retain(0x40).to_modifier_string(),
+            "volatile"
+        );
+        assert_eq!(
+            method::AccessFlags::from_bits_retain(0x40).to_modifier_string(),
+            "bridge"
+        );
+        let flags = method::AccessFlags::from_bits_retain(0x10009);
+        assert_eq!(flags.to_modifier_string(), "public static constructor");
+
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for class in dex.classes() {
+            let class = class.unwrap();
+            assert_eq!(class.access_flags().unknown_bits(), 0);
+            assert_eq!(
+                class.is_public(),
+                class
+                    .access_flags()
+                    .to_modifier_string()
+                    .starts_with("public")
+            );
+            for method in class.methods() {
+                assert_eq!(
+                    method.is_native(),
+                    method
+                        .access_flags()
+                        .to_modifier_string()
+                        .contains("native")
+                );
+            }
+        }
+
+        // reserved bits are kept and printed instead of failing the load
+        let data = mutated_example_dex(|data| {
+            let class_defs_off = data.pread::<u32>(0x64).unwrap() as usize;
+            let access_flags: u32 = data.pread_with(class_defs_off + 4, LE).unwrap();
+            data.pwrite_with(access_flags | 0x20_0000, class_defs_off + 4, LE)
+                .unwrap();
+        });
+        let dex = DexReader::from_vec(data).expect("cannot open dex");
+        let class = dex.classes().next().unwrap().unwrap();
+        assert_eq!(class.access_flags().unknown_bits(), 0x20_0000);
+        assert!(class
+            .access_flags()
+            .to_modifier_string()
+            .ends_with(" 0x200000"));
+        assert_eq!(
+            dex.class_handles().next().unwrap().unwrap().access_flags(),
+            class.access_flags()
+        );
+    }
+
+    #[test]
+    fn test_superclass_chain_and_all_interfaces() {
+        use super::Ancestor;
+
+        let names = |types: &[crate::jtype::Type]| -> Vec<String> {
+            types.iter().map(ToString::to_string).collect()
+        };
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let class = |name: &str| dex.find_class_by_name(name).unwrap().unwrap();
+        let chain: Vec<_> = class("org.adw.launcher.LiveFolderIcon")
+            .superclass_chain(&dex)
+            .map(|ancestor| match ancestor.unwrap() {
+                Ancestor::Class(class) => class.jtype().to_string(),
+                Ancestor::External(jtype) => format!("external {}", jtype),
+            })
+            .collect();
+        assert_eq!(
+            chain,
+            [
+                "Lorg/adw/launcher/FolderIcon;",
+                "Lorg/adw/launcher/BubbleTextView;",
+                "Lorg/adw/launcher/CounterTextView;",
+                "external Landroid/widget/TextView;"
+            ]
+        );
+
+        // inherited from the superclass, once
+        let user_folder = class("org.adw.launcher.UserFolder");
+        assert_eq!(
+            names(&user_folder.all_interfaces(&dex).unwrap()),
+            [
+                "Lorg/adw/launcher/DropTarget;",
+                "Lorg/adw/launcher/DragSource;",
+                "Landroid/widget/AdapterView$OnItemLongClickListener;",
+                "Landroid/widget/AdapterView$OnItemClickListener;",
+                "Landroid/view/View$OnClickListener;",
+                "Landroid/view/View$OnLongClickListener;"
+            ]
+        );
+        // extended by an interface of the file
+        let favorites = class("org.adw.launcher.LauncherSettings$Favorites");
+        assert_eq!(
+            names(&favorites.all_interfaces(&dex).unwrap()),
+            [
+                "Lorg/adw/launcher/LauncherSettings$BaseLauncherColumns;",
+                "Landroid/provider/BaseColumns;"
+            ]
+        );
+        for class in dex.classes() {
+            let class = class.unwrap();
+            let ancestors: Vec<_> = class
+                .superclass_chain(&dex)
+                .map(|ancestor| ancestor.unwrap())
+                .collect();
+            assert!(matches!(ancestors.last(), Some(Ancestor::External(_))));
+            let all_interfaces = class.all_interfaces(&dex).unwrap();
+            for interface in class.interfaces() {
+                assert!(all_interfaces.contains(interface));
+            }
+        }
+
+        let class_def_offset = |data: &[u8], name: &str| {
+            let class_defs_off = data.pread::<u32>(0x64).unwrap() as usize;
+            let index = dex
+                .class_defs()
+                .position(|class_def| {
+                    dex.get_type(class_def.unwrap().class_idx)
+                        .unwrap()
+                        .type_descriptor()
+                        == name
+                })
+                .unwrap();
+            class_defs_off + index * 32
+        };
+        let read = |mutate: &dyn Fn(&mut [u8])| {
+            DexReader::from_vec(mutated_example_dex(mutate)).expect("cannot open dex")
+        };
+        // Folder extends UserFolder, which extends Folder
+        let cyclic = read(&|data| {
+            let user_folder = class_def_offset(data, "Lorg/adw/launcher/UserFolder;");
+            let folder = class_def_offset(data, "Lorg/adw/launcher/Folder;");
+            let class_idx: u32 = data.pread_with(user_folder, LE).unwrap();
+            data.pwrite_with(class_idx, folder + 8, LE).unwrap();
+        });
+        let user_folder = cyclic
+            .find_class_by_name("org.adw.launcher.UserFolder")
+            .unwrap()
+            .unwrap();
+        let chain: Vec<_> = user_folder.superclass_chain(&cyclic).collect();
+        assert_eq!(chain.len(), 2);
+        assert!(matches!(chain[0], Ok(Ancestor::Class(_))));
+        assert!(matches!(chain[1], Err(crate::Error::MalFormed(_))));
+        assert!(user_folder.all_interfaces(&cyclic).is_err());
+
+        // BaseLauncherColumns extends itself
+        let cyclic = read(&|data| {
+            let favorites = class_def_offset(data, "Lorg/adw/launcher/LauncherSettings$Favorites;");
+            let columns = class_def_offset(
+                data,
+                "Lorg/adw/launcher/LauncherSettings$BaseLauncherColumns;",
+            );
+            let interfaces_off: u32 = data.pread_with(favorites + 12, LE).unwrap();
+            data.pwrite_with(interfaces_off, columns + 12, LE).unwrap();
+        });
+        let favorites = cyclic
+            .find_class_by_name("org.adw.launcher.LauncherSettings$Favorites")
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            favorites.all_interfaces(&cyclic),
+            Err(crate::Error::MalFormed(_))
+        ));
+    }
+
+    #[test]
+    fn test_static_values_span() {
+        use crate::{diagnostic::Span, encoded_value::skip_encoded_value};
+
+        // the first class with a string among its static values, with the offset of the
+        // string index and the span of the values
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dex = DexReader::from_vec(data.clone()).expect("cannot open dex");
+        let (class_id, string_index_off, span) = dex
+            .class_defs()
+            .find_map(|class_def| {
+                let class_def = class_def.expect("bad class def");
+                let start = class_def.static_values_off as usize;
+                if start == 0 {
+                    return None;
+                }
+                let offset = &mut { start };
+                let len = Uleb128::read(&data, offset).expect("bad size");
+                let mut string_index_off = None;
+                for _ in 0..len {
+                    if data[*offset] & 0x1f == 0x17 && string_index_off.is_none() {
+                        string_index_off = Some(*offset + 1);
+                    }
+                    skip_encoded_value(&data, offset).expect("bad value");
+                }
+                let span = Span::new(start as u32, (*offset - start) as u32);
+                string_index_off.map(|off| (class_def.class_idx, off, span))
+            })
+            .expect("no class with a static string");
+
+        let read = |data| {
+            DexReaderBuilder::new()
+                .track_spans(true)
+                .read_vec(data)
+                .expect("cannot open dex")
+        };
+        let old = read(data.clone());
+        let new = read(mutated_example_dex(|data| data[string_index_off] ^= 0x1));
+        let mut changed = Vec::new();
+        let mut tracked = 0;
+        for (old_class, new_class) in old.classes().zip(new.classes()) {
+            let old_class = old_class.expect("cannot load class");
+            let new_class = new_class.expect("cannot load class");
+            let (old_span, new_span) = (
+                old_class.static_values_span().copied(),
+                new_class.static_values_span().copied(),
+            );
+            assert_eq!(old_span.map(|s| s.span()), new_span.map(|s| s.span()));
+            if old_class.id() == class_id {
+                assert_eq!(old_span.map(|s| s.span()), Some(span));
+            }
+            if old_span != new_span {
+                changed.push(old_class.id());
+            }
+            tracked += old_span.is_some() as usize;
+        }
+        assert_eq!(changed, vec![class_id]);
+        let with_static_values = dex
+            .class_defs()
+            .filter(|c| c.as_ref().expect("bad class def").static_values_off != 0)
+            .count();
+        assert_eq!(tracked, with_static_values);
+
+        // spans are only tracked on request
+        assert!(dex
+            .classes()
+            .all(|c| c.expect("cannot load class").static_values_span().is_none()));
+    }
+
+    /// Finds a class with members in every group, returning its `ClassDefItem` index, class
+    /// data offset and the number of members of each group.
+    fn class_with_all_groups() -> (usize, usize, [usize; 4]) {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for (index, class_def) in dex.class_defs().enumerate() {
+            let class_def = class_def.expect("bad class def");
+            let data = match dex.get_class_data(class_def.class_data_off).unwrap() {
+                Some(data) => data,
+                None => continue,
+            };
+            let sizes = [
+                data.static_fields().map_or(0, |a| a.len()),
+                data.instance_fields().map_or(0, |a| a.len()),
+                data.direct_methods().map_or(0, |a| a.len()),
+                data.virtual_methods().map_or(0, |a| a.len()),
+            ];
+            if sizes.iter().all(|size| *size > 1) {
+                return (index, class_def.class_data_off as usize, sizes);
+            }
+        }
+        panic!("no class with members in every group in the example dex");
+    }
+
+    #[test]
+    fn test_lenient_class_data_truncated_at_each_position() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let (_, offset, sizes) = class_with_all_groups();
+        let (_, len) = ClassDataItem::try_from_ctx(&data[offset..], &dex).expect("bad data");
+        let mut prefix_len = 0;
+        for _ in 0..4 {
+            Uleb128::read(&data[offset..], &mut prefix_len).unwrap();
+        }
+
+        let mut last_read = 0;
+        for cut in 0..=len {
+            let result = LenientClassData::try_from_ctx(&data[offset..offset + cut], &dex);
+            let lenient = match result {
+                Ok((lenient, _)) => lenient,
+                Err(_) => {
+                    assert!(cut < prefix_len, "failed at {}", cut);
+                    continue;
+                }
+            };
+            let read = [
+                lenient.static_fields.len(),
+                lenient.instance_fields.len(),
+                lenient.direct_methods.len(),
+                lenient.virtual_methods.len(),
+            ];
+            let mut reached = true;
+            for (group, status) in lenient.completeness.iter() {
+                let (read, size) = (read[group as usize], sizes[group as usize]);
+                match status {
+                    GroupStatus::Complete => assert!(reached && read == size),
+                    GroupStatus::Truncated { index, .. } => {
+                        assert!(reached && read == *index && read < size);
+                        reached = false;
+                    }
+                    GroupStatus::NotReached => assert!(!reached && read == 0),
+                }
+            }
+            assert_eq!(lenient.completeness.is_complete(), cut == len, "at {}", cut);
+            let total = read.iter().sum();
+            assert!(total >= last_read);
+            last_read = total;
+        }
+        assert_eq!(last_read, sizes.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn test_lenient_class_data_skips_to_next_group() {
+        let (index, offset, sizes) = class_with_all_groups();
+        // finds the encoding of the code offset of the second direct method
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let mut position = offset;
+        // the sizes, the fields, the first direct method and the index and flags of the second
+        for _ in 0..4 + 2 * (sizes[0] + sizes[1]) + 3 + 2 {
+            Uleb128::read(&data, &mut position).unwrap();
+        }
+        let code_off = position;
+        Uleb128::read(&data, &mut position).unwrap();
+        let code_len = position - code_off;
+        assert!(code_len >= 3);
+
+        // the largest offset with the same encoded length, past the end of the file
+        let data = mutated_example_dex(|data| {
+            data[code_off..code_off + code_len].fill(0xff);
+            data[code_off + code_len - 1] = 0x7f;
+        });
+        let strict = DexReader::from_vec(data.clone()).expect("cannot open dex");
+        let class_def = strict.class_defs().nth(index).unwrap().unwrap();
+        assert!(super::Class::try_from_dex(&strict, &class_def).is_err());
+
+        let lenient = DexReaderBuilder::new()
+            .lenient_class_data(true)
+            .read_vec(data)
+            .expect("cannot open dex");
+        let class = super::Class::try_from_dex(&lenient, &class_def).expect("bad class");
+        let completeness = class.parse_completeness();
+        assert!(!completeness.is_complete());
+        assert!(matches!(
+            completeness.status(MemberGroup::DirectMethods),
+            GroupStatus::Truncated {
+                index: 1,
+                error: crate::Error::BadOffset(..)
+            }
+        ));
+        for group in [
+            MemberGroup::StaticFields,
+            MemberGroup::InstanceFields,
+            MemberGroup::VirtualMethods,
+        ] {
+            assert!(completeness.status(group).is_complete());
+        }
+        assert_eq!(class.static_fields().len(), sizes[0]);
+        assert_eq!(class.instance_fields().len(), sizes[1]);
+        assert_eq!(class.direct_methods().len(), 1);
+        assert_eq!(class.virtual_methods().len(), sizes[3]);
+
+        for class in lenient.classes() {
+            let class = class.expect("bad class");
+            assert_eq!(
+                class.parse_completeness().is_complete(),
+                class.id() != class_def.class_idx()
+            );
+        }
+    }
+
+    /// Finds a class in which only some of the methods are annotated, returning its
+    /// `ClassDefItem` index and annotations directory offset.
+    fn partially_annotated_class() -> (usize, usize) {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for (index, class_def) in dex.class_defs().enumerate() {
+            let class_def = class_def.expect("bad class def");
+            let directory = dex
+                .get_annotations_directory_item(class_def.annotations_off)
+                .expect("bad annotations directory");
+            if directory.method_annotations.is_empty() {
+                continue;
+            }
+            let class = super::Class::try_from_dex(&dex, &class_def).expect("bad class");
+            if class.methods().any(|m| m.annotations().unwrap().is_empty()) {
+                return (index, class_def.annotations_off as usize);
+            }
+        }
+        panic!("no partially annotated class in the example dex");
+    }
+
+    #[test]
+    fn test_method_annotations_attached() {
+        let (index, _) = partially_annotated_class();
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let class_def = dex.class_defs().nth(index).unwrap().unwrap();
+        let directory = dex
+            .get_annotations_directory_item(class_def.annotations_off)
+            .unwrap();
+        let class = super::Class::try_from_dex(&dex, &class_def).unwrap();
+        for method in class.methods() {
+            let entry = directory
+                .method_annotations
+                .iter()
+                .find(|m| m.method_idx() == method.id());
+            match entry {
+                Some(entry) => {
+                    assert!(!method.annotations().unwrap().is_empty());
+                    assert_eq!(
+                        format!("{:?}", method.annotations().unwrap()),
+                        format!("{:?}", entry.annotations())
+                    );
+                }
+                None => assert!(method.annotations().unwrap().is_empty()),
+            }
+        }
+        assert!(class.orphan_annotations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_orphan_method_annotations() {
+        const ORPHAN_ID: u32 = 0xffff_fff0;
+        let (index, directory_off) = partially_annotated_class();
+        let data = mutated_example_dex(|data| {
+            let fields_size: u32 = data.pread_with(directory_off + 4, LE).unwrap();
+            let methods_size: u32 = data.pread_with(directory_off + 8, LE).unwrap();
+            // the last method entry, so that the list stays sorted
+            let entry = directory_off + 16 + 8 * (fields_size + methods_size - 1) as usize;
+            data.pwrite_with(ORPHAN_ID, entry, LE).unwrap();
+        });
+        let dex = DexReader::from_vec(data).expect("cannot open dex");
+        let class_def = dex.class_defs().nth(index).unwrap().unwrap();
+        let class = super::Class::try_from_dex(&dex, &class_def).expect("bad class");
+        let orphans = class.orphan_annotations().expect("bad annotations");
+        assert_eq!(orphans.methods().len(), 1);
+        assert_eq!(orphans.methods()[0].method_idx(), u64::from(ORPHAN_ID));
+        assert!(orphans.fields().is_empty());
+        assert!(!orphans.methods()[0].annotations().is_empty());
+    }
+
+    #[test]
+    fn test_lazy_annotations_match_eager() {
+        let lazy = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let eager = DexReader::builder()
+            .eager_annotations(true)
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        for (lazy_class, eager_class) in lazy.classes().zip(eager.classes()) {
+            let (lazy_class, eager_class) = (lazy_class.unwrap(), eager_class.unwrap());
+            assert_eq!(
+                format!("{:?}", lazy_class.annotations().unwrap()),
+                format!("{:?}", eager_class.annotations().unwrap())
+            );
+            for (lazy_method, eager_method) in lazy_class.methods().zip(eager_class.methods()) {
+                assert_eq!(
+                    format!("{:?}", lazy_method.annotations().unwrap()),
+                    format!("{:?}", eager_method.annotations().unwrap())
+                );
+                assert_eq!(
+                    format!("{:?}", lazy_method.param_annotations().unwrap()),
+                    format!("{:?}", eager_method.param_annotations().unwrap())
+                );
+            }
+            for (lazy_field, eager_field) in lazy_class.fields().zip(eager_class.fields()) {
+                assert_eq!(
+                    format!("{:?}", lazy_field.annotations().unwrap()),
+                    format!("{:?}", eager_field.annotations().unwrap())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lazy_annotations_error_at_accessor() {
+        let (index, directory_off) = partially_annotated_class();
+        let data = mutated_example_dex(|data| {
+            // class_annotations_off pointing outside of the data section
+            data.pwrite_with(0xffff_fff0u32, directory_off, LE).unwrap();
+        });
+
+        let dex = DexReader::from_vec(data.clone()).expect("cannot open dex");
+        let class_def = dex.class_defs().nth(index).unwrap().unwrap();
+        let class = super::Class::try_from_dex(&dex, &class_def).expect("class loads lazily");
+        match class.annotations() {
+            Err(crate::Error::BadOffset(_, msg)) => {
+                assert!(msg.contains(&format!("Annotations directory at {}", directory_off)))
+            }
+            other => panic!("expected a bad offset error, got: {:?}", other),
+        }
+        assert!(class.methods().all(|m| m.annotations().is_err()));
+
+        let dex = DexReader::builder()
+            .eager_annotations(true)
+            .read_vec(data)
+            .expect("cannot open dex");
+        let class_def = dex.class_defs().nth(index).unwrap().unwrap();
+        assert!(super::Class::try_from_dex(&dex, &class_def).is_err());
+    }
+
+    #[test]
+    fn test_source_debug_extension_absent() {
+        // the example dex is built from Java sources, which have no source maps
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            assert_eq!(class.source_debug_extension().expect("bad SMAP"), None);
+        }
+    }
 }
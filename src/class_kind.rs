@@ -0,0 +1,334 @@
+//! Best-effort classification of classes, to leave out the classes generated by the build
+//! tools before an analysis, see `Class::kind` and `Dex::classes_filtered`.
+//!
+//! Interfaces, annotations and enums are told apart by their access flags, which is exact.
+//! The generated classes are recognized by heuristics, each of which can be turned off with
+//! `ClassHeuristics`. The heuristics are conservative: a class they miss is a `Regular`
+//! class, and a class they match looks generated by its name and flags alone.
+use crate::{
+    annotation::AnnotationSetItem, class::AccessFlags, encoded_value::EncodedValue, well_known,
+};
+
+/// Kind of a class, see `Class::kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClassKind {
+    /// A class of none of the other kinds.
+    Regular,
+    /// An interface which is not an annotation type.
+    Interface,
+    /// An annotation type.
+    Annotation,
+    /// An enum.
+    Enum,
+    /// The `R` class of the resources of a package, or one of its member classes such as
+    /// `R$layout`.
+    RClass,
+    /// The `BuildConfig` class generated by the Android Gradle plugin.
+    BuildConfig,
+    /// A class generated by data binding or view binding, such as `DataBinderMapperImpl`,
+    /// `BR` or the binding classes of the `databinding` packages.
+    DataBinding,
+    /// A class implementing a lambda, generated by D8's desugaring.
+    SyntheticLambda,
+    /// A Kotlin file facade, holding the top-level declarations of a Kotlin file.
+    KotlinFacade,
+}
+
+impl ClassKind {
+    /// The mask holding only this kind.
+    pub fn mask(self) -> ClassKindMask {
+        match self {
+            ClassKind::Regular => ClassKindMask::REGULAR,
+            ClassKind::Interface => ClassKindMask::INTERFACE,
+            ClassKind::Annotation => ClassKindMask::ANNOTATION,
+            ClassKind::Enum => ClassKindMask::ENUM,
+            ClassKind::RClass => ClassKindMask::R_CLASS,
+            ClassKind::BuildConfig => ClassKindMask::BUILD_CONFIG,
+            ClassKind::DataBinding => ClassKindMask::DATA_BINDING,
+            ClassKind::SyntheticLambda => ClassKindMask::SYNTHETIC_LAMBDA,
+            ClassKind::KotlinFacade => ClassKindMask::KOTLIN_FACADE,
+        }
+    }
+}
+
+bitflags! {
+    /// A set of `ClassKind`s, see `Dex::classes_filtered`.
+    pub struct ClassKindMask: u16 {
+        const REGULAR = 0x1;
+        const INTERFACE = 0x2;
+        const ANNOTATION = 0x4;
+        const ENUM = 0x8;
+        const R_CLASS = 0x10;
+        const BUILD_CONFIG = 0x20;
+        const DATA_BINDING = 0x40;
+        const SYNTHETIC_LAMBDA = 0x80;
+        const KOTLIN_FACADE = 0x100;
+        /// The kinds of the classes generated by the build tools.
+        const GENERATED = Self::R_CLASS.bits
+            | Self::BUILD_CONFIG.bits
+            | Self::DATA_BINDING.bits
+            | Self::SYNTHETIC_LAMBDA.bits
+            | Self::KOTLIN_FACADE.bits;
+    }
+}
+
+bitflags! {
+    /// The heuristics recognizing generated classes, see `ClassKind`. Defaults to all of
+    /// them.
+    pub struct ClassHeuristics: u8 {
+        /// A class named `R`, or a member class of `R` with a lowercase name such as
+        /// `R$layout` or `R$styleable`, in a named package.
+        const R_CLASS = 0x1;
+        /// A class named `BuildConfig` in a named package.
+        const BUILD_CONFIG = 0x2;
+        /// A class named `DataBinderMapperImpl`, `DataBindingComponent` or `BR` in a named
+        /// package, or a class in a `databinding` package whose name ends with `Binding` or
+        /// `BindingImpl`.
+        const DATA_BINDING = 0x4;
+        /// A synthetic class whose name has `$$Lambda$` or `$$ExternalSyntheticLambda`.
+        const SYNTHETIC_LAMBDA = 0x8;
+        /// A class whose `kotlin.Metadata` annotation has a kind (`k`) of 2, a file facade,
+        /// or 4 and 5, the facade and the parts of a multi-file class. This heuristic reads
+        /// the annotations of the class.
+        const KOTLIN_FACADE = 0x10;
+    }
+}
+
+impl Default for ClassHeuristics {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Kinds of `kotlin.Metadata` of the facades of files and multi-file classes.
+const KOTLIN_FACADE_KINDS: [i32; 3] = [2, 4, 5];
+
+/// Classifies a class by its descriptor and access flags only. Classes which are neither
+/// interfaces, annotations, enums nor generated by the name heuristics are `Regular`, and
+/// may still be Kotlin facades, see `kotlin_facade`.
+pub(crate) fn classify(
+    descriptor: &str,
+    access_flags: AccessFlags,
+    heuristics: ClassHeuristics,
+) -> ClassKind {
+    if access_flags.contains(AccessFlags::ANNOTATION) {
+        return ClassKind::Annotation;
+    }
+    if access_flags.contains(AccessFlags::INTERFACE) {
+        return ClassKind::Interface;
+    }
+    if access_flags.contains(AccessFlags::ENUM) {
+        return ClassKind::Enum;
+    }
+    let name = descriptor
+        .strip_prefix('L')
+        .and_then(|name| name.strip_suffix(';'))
+        .unwrap_or(descriptor);
+    let (package, simple_name) = match name.rfind('/') {
+        Some(slash) => (&name[..slash], &name[slash + 1..]),
+        None => return ClassKind::Regular,
+    };
+    if heuristics.contains(ClassHeuristics::R_CLASS) && is_r_class(simple_name) {
+        return ClassKind::RClass;
+    }
+    if heuristics.contains(ClassHeuristics::BUILD_CONFIG) && simple_name == "BuildConfig" {
+        return ClassKind::BuildConfig;
+    }
+    if heuristics.contains(ClassHeuristics::DATA_BINDING) && is_data_binding(package, simple_name) {
+        return ClassKind::DataBinding;
+    }
+    if heuristics.contains(ClassHeuristics::SYNTHETIC_LAMBDA)
+        && access_flags.contains(AccessFlags::SYNTHETIC)
+        && (simple_name.contains("$$Lambda$") || simple_name.contains("$$ExternalSyntheticLambda"))
+    {
+        return ClassKind::SyntheticLambda;
+    }
+    ClassKind::Regular
+}
+
+fn is_r_class(simple_name: &str) -> bool {
+    match simple_name.strip_prefix("R$") {
+        Some(member) => {
+            !member.is_empty()
+                && member
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        }
+        None => simple_name == "R",
+    }
+}
+
+fn is_data_binding(package: &str, simple_name: &str) -> bool {
+    if ["DataBinderMapperImpl", "DataBindingComponent", "BR"].contains(&simple_name) {
+        return true;
+    }
+    let in_databinding = package == "databinding" || package.ends_with("/databinding");
+    in_databinding
+        && !simple_name.contains('$')
+        && (simple_name.ends_with("Binding") || simple_name.ends_with("BindingImpl"))
+}
+
+/// Returns `true` if the class annotations have a `kotlin.Metadata` annotation of a file
+/// or multi-file class facade.
+pub(crate) fn kotlin_facade(annotations: &AnnotationSetItem) -> bool {
+    let kind = annotations
+        .find(well_known::KOTLIN_METADATA)
+        .and_then(|metadata| metadata.annotation().find_element("k"))
+        .map(|element| element.value());
+    matches!(kind, Some(EncodedValue::Int(kind)) if KOTLIN_FACADE_KINDS.contains(kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, ClassHeuristics, ClassKind, ClassKindMask};
+    use crate::class::AccessFlags;
+
+    #[test]
+    fn test_classify() {
+        let public = AccessFlags::PUBLIC;
+        let lambda = AccessFlags::FINAL | AccessFlags::SYNTHETIC;
+        let fixtures = [
+            ("Lcom/example/Main;", public, ClassKind::Regular),
+            (
+                "Lcom/example/Api;",
+                AccessFlags::INTERFACE,
+                ClassKind::Interface,
+            ),
+            (
+                "Lcom/example/Slow;",
+                AccessFlags::INTERFACE | AccessFlags::ANNOTATION,
+                ClassKind::Annotation,
+            ),
+            ("Lcom/example/Color;", AccessFlags::ENUM, ClassKind::Enum),
+            // flags win over names
+            (
+                "Lcom/example/R;",
+                AccessFlags::INTERFACE,
+                ClassKind::Interface,
+            ),
+            ("Lcom/example/R;", public, ClassKind::RClass),
+            ("Lcom/example/R$layout;", public, ClassKind::RClass),
+            ("Lcom/example/R$style_able2;", public, ClassKind::RClass),
+            ("Lcom/example/R$Layout;", public, ClassKind::Regular),
+            ("Lcom/example/R$;", public, ClassKind::Regular),
+            ("Lcom/example/Radio;", public, ClassKind::Regular),
+            ("Lcom/example/R$id$Inner;", public, ClassKind::Regular),
+            ("LR;", public, ClassKind::Regular),
+            ("Lcom/example/BuildConfig;", public, ClassKind::BuildConfig),
+            ("Lcom/example/BuildConfigs;", public, ClassKind::Regular),
+            ("LBuildConfig;", public, ClassKind::Regular),
+            (
+                "Lcom/example/DataBinderMapperImpl;",
+                public,
+                ClassKind::DataBinding,
+            ),
+            ("Lcom/example/BR;", public, ClassKind::DataBinding),
+            (
+                "Lcom/example/databinding/ActivityMainBinding;",
+                public,
+                ClassKind::DataBinding,
+            ),
+            (
+                "Lcom/example/databinding/ActivityMainBindingImpl;",
+                public,
+                ClassKind::DataBinding,
+            ),
+            (
+                "Lcom/example/databinding/Helper;",
+                public,
+                ClassKind::Regular,
+            ),
+            (
+                "Lcom/example/ui/ActivityMainBinding;",
+                public,
+                ClassKind::Regular,
+            ),
+            (
+                "Lcom/example/-$$Lambda$Main$Xq2M7TLkuYoj2ajQrbcmVbZKXG0;",
+                lambda,
+                ClassKind::SyntheticLambda,
+            ),
+            (
+                "Lcom/example/Main$$ExternalSyntheticLambda0;",
+                lambda,
+                ClassKind::SyntheticLambda,
+            ),
+            // D8 marks its lambda classes synthetic
+            (
+                "Lcom/example/Main$$ExternalSyntheticLambda0;",
+                public,
+                ClassKind::Regular,
+            ),
+            ("Lcom/example/Main$Lambda;", lambda, ClassKind::Regular),
+        ];
+        for (descriptor, flags, kind) in fixtures.iter() {
+            assert_eq!(
+                classify(descriptor, *flags, ClassHeuristics::default()),
+                *kind,
+                "{}",
+                descriptor
+            );
+            // without the heuristics, only the flags classify
+            let exact = classify(descriptor, *flags, ClassHeuristics::empty());
+            if kind.mask().intersects(ClassKindMask::GENERATED) {
+                assert_eq!(exact, ClassKind::Regular, "{}", descriptor);
+            } else {
+                assert_eq!(exact, *kind, "{}", descriptor);
+            }
+        }
+        // each heuristic only turns off its own kind
+        let all = ClassHeuristics::all();
+        let heuristics = [
+            (ClassHeuristics::R_CLASS, "Lcom/example/R$id;", public),
+            (
+                ClassHeuristics::BUILD_CONFIG,
+                "Lcom/example/BuildConfig;",
+                public,
+            ),
+            (ClassHeuristics::DATA_BINDING, "Lcom/example/BR;", public),
+            (
+                ClassHeuristics::SYNTHETIC_LAMBDA,
+                "Lcom/example/Main$$ExternalSyntheticLambda0;",
+                lambda,
+            ),
+        ];
+        for (heuristic, descriptor, flags) in heuristics.iter() {
+            assert_eq!(
+                classify(descriptor, *flags, all - *heuristic),
+                ClassKind::Regular
+            );
+            for (other, other_descriptor, other_flags) in heuristics.iter() {
+                if other != heuristic {
+                    assert_ne!(
+                        classify(other_descriptor, *other_flags, all - *heuristic),
+                        ClassKind::Regular
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kind_masks() {
+        let kinds = [
+            ClassKind::Regular,
+            ClassKind::Interface,
+            ClassKind::Annotation,
+            ClassKind::Enum,
+            ClassKind::RClass,
+            ClassKind::BuildConfig,
+            ClassKind::DataBinding,
+            ClassKind::SyntheticLambda,
+            ClassKind::KotlinFacade,
+        ];
+        let masks = kinds
+            .iter()
+            .fold(ClassKindMask::empty(), |masks, kind| masks | kind.mask());
+        assert_eq!(masks, ClassKindMask::all());
+        let generated = kinds
+            .iter()
+            .filter(|kind| kind.mask().intersects(ClassKindMask::GENERATED))
+            .count();
+        assert_eq!(generated, 5);
+    }
+}
@@ -0,0 +1,151 @@
+//! Static initializers of classes, for startup analysis, see `Dex::class_initializers`.
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    field::FieldId,
+    jtype::TypeId,
+    method::MethodRef,
+    opcode::{IndexOperand, InstructionKind, Instructions, OpcodeFlags},
+    uint, ushort, Result,
+};
+
+/// Opcodes of the instructions loading a constant number or string into a register.
+const CONST_OPCODES: [u8; 10] = [
+    0x12, // const/4
+    0x13, // const/16
+    0x14, // const
+    0x15, // const/high16
+    0x16, // const-wide/16
+    0x17, // const-wide/32
+    0x18, // const-wide
+    0x19, // const-wide/high16
+    0x1a, // const-string
+    0x1b, // const-string/jumbo
+];
+
+/// Opcodes of the `sput` instructions, from `sput` to `sput-short`.
+const SPUT_OPCODES: std::ops::RangeInclusive<u8> = 0x67..=0x6d;
+
+const RETURN_VOID: u8 = 0x0e;
+
+/// The `<clinit>` method of a class with cheap metrics of its code.
+#[derive(Debug, Getters, CopyGetters)]
+pub struct ClassInitializer {
+    /// The static initializer.
+    #[get = "pub"]
+    method: MethodRef,
+    /// Size of the code of the initializer, in code units.
+    #[get_copy = "pub"]
+    insns_size: uint,
+    /// Number of `invoke-*` instructions.
+    #[get_copy = "pub"]
+    invokes: usize,
+    /// `true` if the initializer only loads constant numbers and strings, stores them in
+    /// static fields of its own class and returns. Running such an initializer has no
+    /// side effect outside of the class. Any other instruction, including `const-class`
+    /// and stores to fields of other classes, makes the initializer non-trivial.
+    #[get_copy = "pub"]
+    trivial: bool,
+}
+
+impl ClassInitializer {
+    /// Computes the metrics of the initializer of the class `class` from its code units.
+    /// `field_class` returns the class defining a field.
+    pub(crate) fn new<F>(
+        method: MethodRef,
+        class: TypeId,
+        insns: &[ushort],
+        field_class: F,
+    ) -> Result<Self>
+    where
+        F: Fn(FieldId) -> Result<TypeId>,
+    {
+        let (invokes, trivial) = analyze(class, insns, field_class)?;
+        Ok(Self {
+            method,
+            insns_size: insns.len() as uint,
+            invokes,
+            trivial,
+        })
+    }
+}
+
+/// Counts the invoke instructions of the code of an initializer of `class` and tells if it
+/// is trivial.
+fn analyze<F>(class: TypeId, insns: &[ushort], field_class: F) -> Result<(usize, bool)>
+where
+    F: Fn(FieldId) -> Result<TypeId>,
+{
+    let mut invokes = 0;
+    let mut trivial = !insns.is_empty();
+    for instruction in Instructions::new(insns) {
+        let instruction = instruction?;
+        let opcode = match instruction.kind {
+            InstructionKind::Op(opcode) => opcode,
+            _ => {
+                trivial = false;
+                continue;
+            }
+        };
+        if opcode.flags.contains(OpcodeFlags::INVOKE) {
+            invokes += 1;
+        }
+        if !trivial {
+            continue;
+        }
+        trivial = if CONST_OPCODES.contains(&opcode.value) || opcode.value == RETURN_VOID {
+            true
+        } else if SPUT_OPCODES.contains(&opcode.value) {
+            match instruction.index_operand() {
+                Some(IndexOperand::Field(field)) => field_class(field)? == class,
+                _ => false,
+            }
+        } else {
+            false
+        };
+    }
+    Ok((invokes, trivial))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+
+    const CLASS: u32 = 3;
+
+    fn field_class(field: u64) -> crate::Result<u32> {
+        // field 0 belongs to the class, the others to other classes
+        Ok(if field == 0 { CLASS } else { CLASS + 1 })
+    }
+
+    #[test]
+    fn test_analyze() {
+        let cases: &[(&[u16], usize, bool)] = &[
+            // const/4 v0, 1; sput v0, field@0; return-void
+            (&[0x1012, 0x0067, 0x0000, 0x000e], 0, true),
+            // const-string v0, string@2; sput-object v0, field@0; return-void
+            (&[0x001a, 0x0002, 0x0069, 0x0000, 0x000e], 0, true),
+            // const-wide/16 v0, 5; sput-wide v0, field@0; return-void
+            (&[0x0016, 0x0005, 0x0068, 0x0000, 0x000e], 0, true),
+            // const/4 v0, 1; sput v0, field@1 of another class; return-void
+            (&[0x1012, 0x0067, 0x0001, 0x000e], 0, false),
+            // const-class v0, type@0; return-void
+            (&[0x001c, 0x0000, 0x000e], 0, false),
+            // invoke-static {}, method@0; return-void
+            (&[0x0071, 0x0000, 0x0000, 0x000e], 1, false),
+            // new-array v0, v0, type@0; return-void; invoke-virtual {}, method@0
+            (&[0x0023, 0x0000, 0x000e, 0x006e, 0x0000, 0x0000], 1, false),
+            // an unused opcode
+            (&[0x003e, 0x000e], 0, false),
+            (&[], 0, false),
+        ];
+        for (insns, invokes, trivial) in cases {
+            assert_eq!(
+                analyze(CLASS, insns, field_class).expect("cannot decode"),
+                (*invokes, *trivial),
+                "{:04x?}",
+                insns
+            );
+        }
+    }
+}
@@ -1,32 +1,46 @@
 //! Structures defining the contents of a `Method`'s code.
-use scroll::{ctx, Pread, Uleb128};
+use scroll::{ctx, Pread, LE};
 use std::{fmt, ops::Deref};
 
 use getset::{CopyGetters, Getters};
 
+#[cfg(feature = "decoder")]
 use crate::{
-    encoded_item::EncodedCatchHandlers, error::Error, jtype::Type, string::DexString, uint, ulong,
-    ushort,
+    bytecode::{self, DecodedInsn, InstructionIterator},
+    opcode::{self, Instructions},
+};
+use crate::{
+    encoded_item::EncodedCatchHandlers,
+    error::Error,
+    jtype::Type,
+    limits::ParserLimits,
+    source::DexSource,
+    sync::{OnceCell, Shared},
+    uint, ulong, ushort,
 };
 
-/// Debug Info of a method.
-/// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#debug-info-item)
-#[derive(Debug, Getters, CopyGetters)]
-pub struct DebugInfoItem {
-    /// Initial value for the state machines's line register.
-    #[get_copy = "pub"]
-    line_start: usize,
-    /// Names of the incoming parameters.
-    #[get = "pub"]
-    parameter_names: Vec<Option<DexString>>,
-}
+#[cfg(feature = "debug-info")]
+mod debug_info;
+
+#[cfg(feature = "debug-info")]
+pub(crate) use self::debug_info::scan_debug_info;
+#[cfg(feature = "debug-info")]
+pub use self::debug_info::{DebugInfoItem, LocalVar, LocalVariable, Locals, Parameter};
+
+/// Identifies the pseudo-instructions holding the data of switches and `fill-array-data`.
+pub(crate) const PACKED_SWITCH_PAYLOAD: ushort = 0x0100;
+pub(crate) const SPARSE_SWITCH_PAYLOAD: ushort = 0x0200;
+pub(crate) const FILL_ARRAY_DATA_PAYLOAD: ushort = 0x0300;
 
 /// Code and Debug Info of a method.
 pub struct CodeItem {
     /// The number of registers the method must use.
     pub registers_size: ushort,
     /// Line number and source file information.
+    #[cfg(feature = "debug-info")]
     pub debug_info_item: Option<DebugInfoItem>,
+    /// Offset of the debug info, 0 if the method has none.
+    debug_info_off: uint,
     /// Number of words for incoming arguments to this method.
     pub ins_size: ushort,
     /// Number of words for outgoing arguments required for invocation.
@@ -35,19 +49,529 @@ pub struct CodeItem {
     pub insns: Vec<ushort>,
     /// Try, Exception handling information of this method.
     pub tries: Tries,
+    /// Maximum number of entries of the payloads read from the instructions, see
+    /// `ParserLimits::max_payload_entries`.
+    max_payload_entries: usize,
 }
 
 impl CodeItem {
     /// Line number and source file information.
+    #[cfg(feature = "debug-info")]
     pub fn debug_info_item(&self) -> Option<&DebugInfoItem> {
         self.debug_info_item.as_ref()
     }
+
+    /// Returns the address and source line of each position entry of the debug info, see
+    /// `DebugInfoItem::line_number_table`. The table is empty if the method has no debug
+    /// info.
+    #[cfg(feature = "debug-info")]
+    pub fn line_number_table(&self) -> Vec<(uint, uint)> {
+        self.debug_info_item
+            .as_ref()
+            .map(DebugInfoItem::line_number_table)
+            .unwrap_or_default()
+    }
+
+    /// The distinct types of the exceptions caught by the handlers of the try blocks, in
+    /// order of first appearance. A catch-all handler is listed once as
+    /// `ExceptionType::BaseException`, apart from any handler of `java.lang.Throwable`.
+    pub fn caught_exception_types(&self) -> Vec<ExceptionType> {
+        let mut types: Vec<ExceptionType> = Vec::new();
+        for try_block in self.tries.iter() {
+            for handler in try_block.catch_handlers() {
+                if !types.contains(&handler.exception) {
+                    types.push(handler.exception.clone());
+                }
+            }
+        }
+        types
+    }
+
+    /// Returns the sparse-switch payload starting at `payload_addr`, in code units from the
+    /// start of the instructions.
+    pub fn sparse_switch(&self, payload_addr: usize) -> super::Result<SparseSwitchView<'_>> {
+        SparseSwitchView::new(&self.insns, payload_addr)
+    }
+
+    /// Reads the packed-switch or sparse-switch payload starting at `payload_offset`, in
+    /// code units from the start of the instructions.
+    pub fn resolve_switch(&self, payload_offset: u32) -> super::Result<SwitchPayload> {
+        self.read_payload(payload_offset)
+    }
+
+    /// Reads the fill-array-data payload starting at `payload_offset`, in code units from
+    /// the start of the instructions.
+    pub fn fill_array_data(&self, payload_offset: u32) -> super::Result<FillArrayDataPayload> {
+        self.read_payload(payload_offset)
+    }
+
+    /// Reads the payload starting at `payload_offset`, copying only the code units its
+    /// header declares. A payload overrunning the instructions is copied up to their end,
+    /// for the parser to report it.
+    fn read_payload<P>(&self, payload_offset: u32) -> super::Result<P>
+    where
+        P: for<'a> ctx::TryFromCtx<'a, scroll::Endian, Error = Error, Size = usize>,
+    {
+        let units = self.insns.get(payload_offset as usize..).ok_or_else(|| {
+            Error::MalFormed(format!("Payload at {} is out of bounds", payload_offset))
+        })?;
+        let wrap = |e| match e {
+            Error::MalFormed(message) => {
+                Error::MalFormed(format!("{} at {}", message, payload_offset))
+            }
+            e => e,
+        };
+        // an unknown ident is copied alone for the parser to report it
+        let (len, entries) = payload_size(units).unwrap_or(Ok((1, 0))).map_err(wrap)?;
+        ParserLimits::check(
+            "Payload entry count",
+            entries as usize,
+            self.max_payload_entries,
+        )?;
+        let len = len.min(units.len() as u64);
+        let bytes: Vec<u8> = units[..len as usize]
+            .iter()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        bytes.pread_with(0, LE).map_err(wrap)
+    }
+
+    /// Iterator over the instructions of the method.
+    #[cfg(feature = "decoder")]
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions::new(&self.insns)
+    }
+
+    /// Decodes the instructions of the method with their operands, see
+    /// `bytecode::decode_instructions`.
+    #[cfg(feature = "decoder")]
+    pub fn decode_instructions(&self) -> super::Result<Vec<DecodedInsn>> {
+        bytecode::decode_instructions(&self.insns)
+    }
+
+    /// Lazily decodes the instructions of the method with their addresses, skipping the
+    /// payloads, see `bytecode::InstructionIterator`.
+    #[cfg(feature = "decoder")]
+    pub fn decoded_instructions(&self) -> InstructionIterator<'_> {
+        InstructionIterator::new(&self.insns)
+    }
+
+    /// Returns `true` if a branch or switch of the method targets an instruction at or
+    /// before itself, which means the method has a loop. See `opcode::has_backward_branch`.
+    #[cfg(feature = "decoder")]
+    pub fn has_backward_branch(&self) -> super::Result<bool> {
+        opcode::has_backward_branch(&self.insns)
+    }
+
+    /// Number of try blocks and of distinct catch handlers they use. Compilers emit about
+    /// as many handlers as try blocks, many try blocks sharing few handlers is unusual.
+    pub fn handler_reuse_stats(&self) -> HandlerReuseStats {
+        let mut handler_offs: Vec<ushort> = self.tries.iter().map(|t| t.handler_off).collect();
+        handler_offs.sort_unstable();
+        handler_offs.dedup();
+        HandlerReuseStats {
+            tries: self.tries.len(),
+            distinct_handlers: handler_offs.len(),
+        }
+    }
+}
+
+/// Reuse of catch handlers by the try blocks of a code item, see
+/// `CodeItem::handler_reuse_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct HandlerReuseStats {
+    /// Number of try blocks.
+    tries: usize,
+    /// Number of distinct catch handlers used by the try blocks.
+    distinct_handlers: usize,
+}
+
+/// A sparse-switch payload read in place from the instructions of a method, without
+/// copying its keys and targets.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/dalvik-bytecode#sparse-switch)
+pub struct SparseSwitchView<'a> {
+    /// The code units of the keys followed by the code units of the targets.
+    entries: &'a [ushort],
+    /// Index of the first key smaller than or equal to its predecessor, computed on first use.
+    unordered_key: OnceCell<Option<usize>>,
+}
+
+impl<'a> SparseSwitchView<'a> {
+    /// Reads the header of the payload at `payload_addr` and checks that the keys and
+    /// targets fit in `insns`.
+    pub fn new(insns: &'a [ushort], payload_addr: usize) -> super::Result<Self> {
+        let out_of_bounds = || {
+            Error::MalFormed(format!(
+                "Switch payload at {} is out of bounds",
+                payload_addr
+            ))
+        };
+        // 2 code units for the ident and the number of entries
+        let start = payload_addr.checked_add(2).ok_or_else(out_of_bounds)?;
+        let header = insns.get(payload_addr..start).ok_or_else(out_of_bounds)?;
+        if header[0] != SPARSE_SWITCH_PAYLOAD {
+            return Err(Error::MalFormed(format!(
+                "No sparse-switch payload at {}: found {:#x}",
+                payload_addr, header[0]
+            )));
+        }
+        // 2 code units for each key and each target
+        let end = start + header[1] as usize * 4;
+        let entries = insns.get(start..end).ok_or_else(|| {
+            Error::MalFormed(format!(
+                "Sparse-switch payload at {} with {} entries overruns the instructions",
+                payload_addr, header[1]
+            ))
+        })?;
+        Ok(Self {
+            entries,
+            unordered_key: OnceCell::new(),
+        })
+    }
+
+    /// Number of entries of the switch.
+    pub fn len(&self) -> usize {
+        self.entries.len() / 4
+    }
+
+    /// Returns `true` if the switch has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The key of the entry at `index`.
+    pub fn key(&self, index: usize) -> i32 {
+        self.int(index)
+    }
+
+    /// The branch target of the entry at `index`, relative to the switch instruction.
+    pub fn target(&self, index: usize) -> i32 {
+        self.int(self.len() + index)
+    }
+
+    /// Iterator over the keys and their branch targets, in payload order.
+    pub fn entries(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (0..self.len()).map(move |index| (self.key(index), self.target(index)))
+    }
+
+    /// Iterator over the branch targets, in payload order.
+    pub fn targets(&self) -> impl Iterator<Item = i32> + '_ {
+        (0..self.len()).map(move |index| self.target(index))
+    }
+
+    /// Returns the index of the first key that is not greater than the key before it. The
+    /// keys must be sorted in ascending order; ART rejects methods where they are not.
+    pub fn unordered_key(&self) -> Option<usize> {
+        *self
+            .unordered_key
+            .get_or_init(|| (1..self.len()).find(|&index| self.key(index - 1) >= self.key(index)))
+    }
+
+    /// Returns the branch target for `key`. The keys are binary searched when they are
+    /// sorted and scanned otherwise.
+    pub fn get(&self, key: i32) -> Option<i32> {
+        let index = if self.unordered_key().is_none() {
+            let (mut start, mut end) = (0, self.len());
+            loop {
+                if start >= end {
+                    break None;
+                }
+                let mid = start + (end - start) / 2;
+                match self.key(mid).cmp(&key) {
+                    std::cmp::Ordering::Equal => break Some(mid),
+                    std::cmp::Ordering::Less => start = mid + 1,
+                    std::cmp::Ordering::Greater => end = mid,
+                }
+            }
+        } else {
+            (0..self.len()).find(|&index| self.key(index) == key)
+        };
+        index.map(|index| self.target(index))
+    }
+
+    fn int(&self, index: usize) -> i32 {
+        let low = self.entries[index * 2];
+        let high = self.entries[index * 2 + 1];
+        (u32::from(low) | u32::from(high) << 16) as i32
+    }
+}
+
+impl fmt::Debug for SparseSwitchView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+/// The payload of a `packed-switch` instruction, whose keys are consecutive.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/dalvik-bytecode#packed-switch)
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct PackedSwitchPayload {
+    /// The key of the first target.
+    #[get_copy = "pub"]
+    first_key: i32,
+    /// The branch targets, relative to the switch instruction.
+    #[get = "pub"]
+    targets: Vec<i32>,
+}
+
+impl PackedSwitchPayload {
+    /// Iterator over the keys and their branch targets, in payload order.
+    pub fn entries(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.targets
+            .iter()
+            .enumerate()
+            .map(move |(index, &target)| (self.first_key.wrapping_add(index as i32), target))
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for PackedSwitchPayload {
+    type Error = Error;
+    type Size = usize;
+
+    fn try_from_ctx(source: &'a [u8], endian: scroll::Endian) -> super::Result<(Self, Self::Size)> {
+        let offset = &mut 0;
+        expect_ident(
+            source,
+            offset,
+            endian,
+            PACKED_SWITCH_PAYLOAD,
+            "packed-switch",
+        )?;
+        let size: ushort = source.gread_with(offset, endian)?;
+        let first_key = source.gread_with(offset, endian)?;
+        let targets = try_gread_vec_with!(source, offset, size, endian);
+        Ok((Self { first_key, targets }, *offset))
+    }
+}
+
+/// The payload of a `sparse-switch` instruction, see `SparseSwitchView` to read one in
+/// place.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/dalvik-bytecode#sparse-switch)
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[get = "pub"]
+pub struct SparseSwitchPayload {
+    /// The keys, which should be sorted in ascending order.
+    keys: Vec<i32>,
+    /// The branch target of each key, relative to the switch instruction.
+    targets: Vec<i32>,
+}
+
+impl SparseSwitchPayload {
+    /// Iterator over the keys and their branch targets, in payload order.
+    pub fn entries(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.keys.iter().copied().zip(self.targets.iter().copied())
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for SparseSwitchPayload {
+    type Error = Error;
+    type Size = usize;
+
+    fn try_from_ctx(source: &'a [u8], endian: scroll::Endian) -> super::Result<(Self, Self::Size)> {
+        let offset = &mut 0;
+        expect_ident(
+            source,
+            offset,
+            endian,
+            SPARSE_SWITCH_PAYLOAD,
+            "sparse-switch",
+        )?;
+        let size: ushort = source.gread_with(offset, endian)?;
+        let keys = try_gread_vec_with!(source, offset, size, endian);
+        let targets = try_gread_vec_with!(source, offset, size, endian);
+        Ok((Self { keys, targets }, *offset))
+    }
+}
+
+/// Number of code units of the payload at the start of `units` and number of entries or
+/// elements it declares, read from its header. `None` if `units` don't start with a
+/// payload ident.
+pub(crate) fn payload_size(units: &[ushort]) -> Option<super::Result<(u64, u64)>> {
+    let ident = *units.first()?;
+    let unit = |index: usize| {
+        units
+            .get(index)
+            .map(|unit| u64::from(*unit))
+            .ok_or_else(|| Error::MalFormed("Truncated payload header".to_string()))
+    };
+    let size = || -> super::Result<(u64, u64)> {
+        Ok(match ident {
+            // ident, size, first key, then a target per entry
+            PACKED_SWITCH_PAYLOAD => (4 + unit(1)? * 2, unit(1)?),
+            // ident, size, then a key and a target per entry
+            SPARSE_SWITCH_PAYLOAD => (2 + unit(1)? * 4, unit(1)?),
+            // ident, element width, size, then the elements padded to whole code units
+            _ => {
+                let len = unit(2)? | unit(3)? << 16;
+                (4 + (unit(1)? * len).div_ceil(2), len)
+            }
+        })
+    };
+    match ident {
+        PACKED_SWITCH_PAYLOAD | SPARSE_SWITCH_PAYLOAD | FILL_ARRAY_DATA_PAYLOAD => Some(size()),
+        _ => None,
+    }
+}
+
+/// Reads the `ident` of a payload, which must be `expected`.
+fn expect_ident(
+    source: &[u8],
+    offset: &mut usize,
+    endian: scroll::Endian,
+    expected: ushort,
+    name: &str,
+) -> super::Result<()> {
+    let ident: ushort = source.gread_with(offset, endian)?;
+    if ident != expected {
+        return Err(Error::MalFormed(format!(
+            "No {} payload: found {:#x}",
+            name, ident
+        )));
+    }
+    Ok(())
+}
+
+/// The payload of a switch instruction, see `CodeItem::resolve_switch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchPayload {
+    /// The payload of a `packed-switch`.
+    Packed(PackedSwitchPayload),
+    /// The payload of a `sparse-switch`.
+    Sparse(SparseSwitchPayload),
+}
+
+impl SwitchPayload {
+    /// The keys and their branch targets, relative to the switch instruction, in payload
+    /// order.
+    pub fn entries(&self) -> Vec<(i32, i32)> {
+        match self {
+            SwitchPayload::Packed(payload) => payload.entries().collect(),
+            SwitchPayload::Sparse(payload) => payload.entries().collect(),
+        }
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for SwitchPayload {
+    type Error = Error;
+    type Size = usize;
+
+    fn try_from_ctx(source: &'a [u8], endian: scroll::Endian) -> super::Result<(Self, Self::Size)> {
+        let ident: ushort = source.pread_with(0, endian)?;
+        match ident {
+            PACKED_SWITCH_PAYLOAD => {
+                let (payload, size) = PackedSwitchPayload::try_from_ctx(source, endian)?;
+                Ok((SwitchPayload::Packed(payload), size))
+            }
+            SPARSE_SWITCH_PAYLOAD => {
+                let (payload, size) = SparseSwitchPayload::try_from_ctx(source, endian)?;
+                Ok((SwitchPayload::Sparse(payload), size))
+            }
+            _ => Err(Error::MalFormed(format!(
+                "No switch payload: found {:#x}",
+                ident
+            ))),
+        }
+    }
+}
+
+/// The payload of a `fill-array-data` instruction, holding the elements of an array.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/dalvik-bytecode#fill-array)
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct FillArrayDataPayload {
+    /// Number of bytes of each element.
+    #[get_copy = "pub"]
+    element_width: ushort,
+    /// Number of elements.
+    #[get_copy = "pub"]
+    size: uint,
+    /// The elements, little-endian.
+    #[get = "pub"]
+    data: Vec<u8>,
+}
+
+/// The elements of a `FillArrayDataPayload`, by element width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArrayValues {
+    /// Elements of 1 byte, such as `byte` and `boolean` arrays.
+    Bytes(Vec<i8>),
+    /// Elements of 2 bytes, such as `short` and `char` arrays.
+    Shorts(Vec<i16>),
+    /// Elements of 4 bytes, such as `int` and `float` arrays.
+    Ints(Vec<i32>),
+    /// Elements of 8 bytes, such as `long` and `double` arrays.
+    Longs(Vec<i64>),
+}
+
+impl FillArrayDataPayload {
+    /// Splits the data into elements of `element_width` bytes, usually `element_width()`.
+    /// The width must be 1, 2, 4 or 8 and divide the length of the data.
+    pub fn as_values(&self, element_width: ushort) -> super::Result<ArrayValues> {
+        let width = usize::from(element_width);
+        if !matches!(width, 1 | 2 | 4 | 8) || !self.data.len().is_multiple_of(width) {
+            return Err(Error::MalFormed(format!(
+                "Cannot split {} bytes of array data into elements of {} bytes",
+                self.data.len(),
+                width
+            )));
+        }
+        let data = &self.data[..];
+        let offset = &mut 0;
+        let count = data.len() / width;
+        Ok(match width {
+            1 => ArrayValues::Bytes(try_gread_vec_with!(data, offset, count, LE)),
+            2 => ArrayValues::Shorts(try_gread_vec_with!(data, offset, count, LE)),
+            4 => ArrayValues::Ints(try_gread_vec_with!(data, offset, count, LE)),
+            _ => ArrayValues::Longs(try_gread_vec_with!(data, offset, count, LE)),
+        })
+    }
+}
+
+impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for FillArrayDataPayload {
+    type Error = Error;
+    type Size = usize;
+
+    fn try_from_ctx(source: &'a [u8], endian: scroll::Endian) -> super::Result<(Self, Self::Size)> {
+        let offset = &mut 0;
+        expect_ident(
+            source,
+            offset,
+            endian,
+            FILL_ARRAY_DATA_PAYLOAD,
+            "fill-array-data",
+        )?;
+        let element_width: ushort = source.gread_with(offset, endian)?;
+        let size: uint = source.gread_with(offset, endian)?;
+        let len = u64::from(element_width) * u64::from(size);
+        let data = source
+            .get(*offset..)
+            .filter(|data| data.len() as u64 >= len)
+            .map(|data| data[..len as usize].to_vec())
+            .ok_or_else(|| {
+                Error::MalFormed(format!(
+                    "fill-array-data payload with {} elements of {} bytes overruns the {} remaining bytes",
+                    size,
+                    element_width,
+                    source.len().saturating_sub(*offset)
+                ))
+            })?;
+        // the data is padded to a whole number of code units
+        *offset += data.len() + data.len() % 2;
+        Ok((
+            Self {
+                element_width,
+                size,
+                data,
+            },
+            *offset,
+        ))
+    }
 }
 
 impl fmt::Debug for CodeItem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "CodeItem {{ registers_size: {}, debug_info: {}, ins_size: {}, outs_size: {}, tries: {} }}",
-            self.registers_size, self.debug_info_item.is_some(), self.ins_size, self.outs_size, self.tries.len())
+            self.registers_size, self.debug_info_off != 0, self.ins_size, self.outs_size, self.tries.len())
     }
 }
 
@@ -65,9 +589,9 @@ pub(crate) struct TryItem {
     handler_off: ushort,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExceptionType {
-    /// The `Exception` class.
+    /// Any exception, caught by a catch-all handler.
     BaseException,
     /// Sub-types of the `Exception` class.
     Ty(Type),
@@ -92,9 +616,19 @@ pub struct TryCatchHandlers {
     /// Number of instructions covered by this try block.
     #[get_copy = "pub"]
     insn_count: ushort,
+    /// Offset of the catch handlers from the start of the handler list of the code item.
+    #[get_copy = "pub"]
+    handler_off: ushort,
+    /// List of catch handlers for this try block, shared with the try blocks using the same
+    /// handlers.
+    catch_handlers: Shared<[CatchHandler]>,
+}
+
+impl TryCatchHandlers {
     /// List of catch handlers for this try block.
-    #[get = "pub"]
-    catch_handlers: Vec<CatchHandler>,
+    pub fn catch_handlers(&self) -> &[CatchHandler] {
+        &self.catch_handlers
+    }
 }
 
 /// List of try-catch blocks found in this method.
@@ -114,7 +648,7 @@ impl Deref for Tries {
 
 impl<'a, S> ctx::TryFromCtx<'a, (usize, &super::Dex<S>)> for Tries
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -130,14 +664,15 @@ where
         let tries: super::Result<Vec<_>> = tries
             .into_iter()
             .map(|c| {
-                let encoded_handler =
+                let catch_handlers =
                     encoded_catch_handlers.find(c.handler_off).ok_or_else(|| {
                         Error::InvalidId(format!("Invalid catch handler: {}", c.handler_off))
                     })?;
                 Ok(TryCatchHandlers {
                     start_addr: c.start_addr,
                     insn_count: c.insn_count,
-                    catch_handlers: encoded_handler.handlers(),
+                    handler_off: c.handler_off,
+                    catch_handlers,
                 })
             })
             .collect();
@@ -150,42 +685,30 @@ where
     }
 }
 
-impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for DebugInfoItem
-where
-    S: AsRef<[u8]>,
-{
-    type Error = Error;
-    type Size = usize;
-
-    fn try_from_ctx(
-        source: &'a [u8],
-        dex: &super::Dex<S>,
-    ) -> Result<(Self, Self::Size), Self::Error> {
-        let offset = &mut 0;
-        let line_start = Uleb128::read(source, offset)? as usize;
-        let parameters_size = Uleb128::read(source, offset)?;
-        let mut parameter_names = Vec::with_capacity(parameters_size as usize);
-        for _ in 0..parameters_size {
-            let string_id = Uleb128::read(source, offset)? + 1;
-            parameter_names.push(if string_id != u64::from(crate::NO_INDEX) {
-                Some(dex.get_string(string_id as uint)?)
-            } else {
-                None
-            });
-        }
-        Ok((
-            Self {
-                line_start,
-                parameter_names,
-            },
-            *offset,
-        ))
+/// Checks the `insns_size` of a code item against the limits and the `available` bytes
+/// following it, before its instructions are read.
+pub(crate) fn check_insns_size(
+    insns_size: uint,
+    available: usize,
+    limits: &ParserLimits,
+) -> super::Result<()> {
+    ParserLimits::check(
+        "Instruction size",
+        insns_size as usize,
+        limits.max_insns_units,
+    )?;
+    if insns_size as usize * 2 > available {
+        return Err(Error::MalFormed(format!(
+            "Instructions of {} code units run past the end of the file",
+            insns_size
+        )));
     }
+    Ok(())
 }
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for CodeItem
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -200,15 +723,18 @@ where
         let ins_size = source.gread_with(offset, endian)?;
         let outs_size = source.gread_with(offset, endian)?;
         let tries_size: ushort = source.gread_with(offset, endian)?;
+        ParserLimits::check("Try count", tries_size as usize, dex.limits.max_tries)?;
         let debug_info_off = source.gread_with(offset, endian)?;
-        let debug_info_item = if debug_info_off != 0 {
+        #[cfg(feature = "debug-info")]
+        let debug_info_item = if debug_info_off != 0 && !dex.skip_debug_info() {
             Some(dex.get_debug_info_item(debug_info_off)?)
         } else {
             None
         };
         let insns_size: uint = source.gread_with(offset, endian)?;
+        check_insns_size(insns_size, source.len() - *offset, &dex.limits)?;
         let insns: Vec<ushort> = try_gread_vec_with!(source, offset, insns_size, endian);
-        if insns_size % 2 != 0 && tries_size != 0 {
+        if !insns_size.is_multiple_of(2) && tries_size != 0 {
             source.gread_with::<ushort>(offset, endian)?;
         }
         let tries: Tries = if tries_size != 0 {
@@ -219,13 +745,282 @@ where
         Ok((
             Self {
                 registers_size,
+                #[cfg(feature = "debug-info")]
                 debug_info_item,
+                debug_info_off,
                 ins_size,
                 outs_size,
                 insns,
                 tries,
+                max_payload_entries: dex.limits.max_payload_entries,
             },
             *offset,
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ArrayValues, CodeItem, FillArrayDataPayload, PackedSwitchPayload, SparseSwitchView,
+        SwitchPayload,
+    };
+    use crate::sync::Shared;
+    use crate::{ushort, DexReader, ErrorKind, ParserLimits};
+    use scroll::{Pread, Pwrite, LE};
+
+    /// Instructions holding a nop followed by a sparse-switch payload with the given keys,
+    /// where the target of each key is its index.
+    fn sparse_switch(keys: impl ExactSizeIterator<Item = i32>) -> Vec<ushort> {
+        let len = keys.len();
+        let mut insns = vec![0, 0x0200, len as ushort];
+        for key in keys {
+            insns.extend_from_slice(&[key as ushort, (key >> 16) as ushort]);
+        }
+        for target in 0..len as i32 {
+            insns.extend_from_slice(&[target as ushort, (target >> 16) as ushort]);
+        }
+        insns
+    }
+
+    #[test]
+    fn test_sparse_switch_largest_table() {
+        // the entry count is 16 bit, so this is the largest table a payload can declare
+        let keys = (0..0xffff).map(|index| index * 3 - 0x10000);
+        let insns = sparse_switch(keys.clone());
+        let switch = SparseSwitchView::new(&insns, 1).expect("bad payload");
+        assert_eq!(switch.len(), 0xffff);
+        assert_eq!(switch.unordered_key(), None);
+        for (index, key) in keys.enumerate() {
+            assert_eq!(switch.get(key), Some(index as i32));
+            assert_eq!(switch.get(key + 1), None);
+        }
+        assert_eq!(switch.targets().last(), Some(0xfffe));
+
+        assert!(SparseSwitchView::new(&insns[..insns.len() - 1], 1).is_err());
+        assert!(SparseSwitchView::new(&insns, 0).is_err());
+        let error = SparseSwitchView::new(&insns, usize::MAX).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Malformed entity: Switch payload at {} is out of bounds",
+                usize::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn test_sparse_switch_unordered_keys() {
+        let insns = sparse_switch(vec![10, 30, 20, -5, 30].into_iter());
+        let switch = SparseSwitchView::new(&insns, 1).expect("bad payload");
+        assert_eq!(switch.unordered_key(), Some(2));
+        assert_eq!(switch.get(20), Some(2));
+        assert_eq!(switch.get(-5), Some(3));
+        // the first of duplicate keys wins
+        assert_eq!(switch.get(30), Some(1));
+        assert_eq!(switch.get(0), None);
+        assert_eq!(
+            switch.entries().collect::<Vec<_>>(),
+            vec![(10, 0), (30, 1), (20, 2), (-5, 3), (30, 4)]
+        );
+    }
+
+    /// A code item without registers, try blocks or debug info holding `insns`.
+    fn code_item(insns: &[ushort]) -> Vec<u8> {
+        let mut data = vec![0u8; 16];
+        data.pwrite_with(insns.len() as u32, 12, LE).unwrap();
+        data.extend(insns.iter().flat_map(|unit| unit.to_le_bytes()));
+        data
+    }
+
+    #[test]
+    fn test_resolve_switch() {
+        // packed-switch v0 with its payload at 4, then a sparse-switch payload at 13
+        let mut insns: Vec<ushort> = vec![0x002b, 4, 0, 0];
+        insns.extend_from_slice(&[0x0100, 2, 0xfffe, 0xffff, 3, 0, 0xfffd, 0xffff]);
+        insns.extend(sparse_switch(vec![5, -7].into_iter()));
+        let data = code_item(&insns);
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+
+        let packed = code.resolve_switch(4).expect("bad packed-switch payload");
+        assert_eq!(packed.entries(), vec![(-2, 3), (-1, -3)]);
+        match packed {
+            SwitchPayload::Packed(payload) => {
+                assert_eq!(payload.first_key(), -2);
+                assert_eq!(payload.targets(), &[3, -3]);
+            }
+            payload => panic!("not a packed-switch: {:?}", payload),
+        }
+        let sparse = code.resolve_switch(13).expect("bad sparse-switch payload");
+        assert_eq!(sparse.entries(), vec![(5, 0), (-7, 1)]);
+        match sparse {
+            SwitchPayload::Sparse(payload) => {
+                assert_eq!(payload.keys(), &[5, -7]);
+                assert_eq!(payload.targets(), &[0, 1]);
+            }
+            payload => panic!("not a sparse-switch: {:?}", payload),
+        }
+
+        let error = code.resolve_switch(0).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MalFormed);
+        assert_eq!(
+            error.to_string(),
+            "Malformed entity: No switch payload: found 0x2b at 0"
+        );
+        assert!(code.resolve_switch(insns.len() as u32 + 1).is_err());
+        // the payloads check their own ident
+        let bytes = &data[16 + 13 * 2..];
+        assert!(bytes.pread_with::<PackedSwitchPayload>(0, LE).is_err());
+        let offset = &mut 0;
+        let _: SwitchPayload = bytes.gread_with(offset, LE).expect("bad payload");
+        assert_eq!(*offset, (2 + 2 * 4) * 2);
+        // a truncated payload is an error, not a shorter table
+        let truncated = &data[16 + 4 * 2..16 + 11 * 2];
+        assert!(truncated.pread_with::<SwitchPayload>(0, LE).is_err());
+        // the entries declared by a payload are checked against the limits
+        let limited = DexReader::builder()
+            .limits(ParserLimits::new().max_payload_entries(1))
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        let code: CodeItem = data.pread_with(0, &limited).expect("bad code item");
+        let error = code.resolve_switch(4).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::LimitExceeded);
+        assert_eq!(
+            error.to_string(),
+            "Limit exceeded: Payload entry count 2 exceeds the limit of 1"
+        );
+    }
+
+    #[test]
+    fn test_fill_array_data() {
+        // fill-array-data v0 with an int array at 3, then a byte array of odd length at 11
+        let mut insns: Vec<ushort> = vec![0x0026, 3, 0];
+        insns.extend_from_slice(&[0x0300, 4, 2, 0, 0x5678, 0x1234, 0xffff, 0xffff]);
+        insns.extend_from_slice(&[0x0300, 1, 3, 0, 0x0201, 0x00ff]);
+        let data = code_item(&insns);
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+
+        let ints = code.fill_array_data(3).expect("bad payload");
+        assert_eq!(ints.element_width(), 4);
+        assert_eq!(ints.size(), 2);
+        assert_eq!(ints.data().len(), 8);
+        assert_eq!(
+            ints.as_values(4).unwrap(),
+            ArrayValues::Ints(vec![0x1234_5678, -1])
+        );
+        assert_eq!(
+            ints.as_values(2).unwrap(),
+            ArrayValues::Shorts(vec![0x5678, 0x1234, -1, -1])
+        );
+        assert_eq!(
+            ints.as_values(8).unwrap(),
+            ArrayValues::Longs(vec![-0xedcb_a988])
+        );
+        assert!(ints.as_values(3).is_err());
+
+        let bytes = code.fill_array_data(11).expect("bad payload");
+        assert_eq!(
+            bytes.as_values(1).unwrap(),
+            ArrayValues::Bytes(vec![1, 2, -1])
+        );
+        assert!(bytes.as_values(2).is_err());
+        // the size of the payload counts the padding of the data
+        let offset = &mut 0;
+        let _: FillArrayDataPayload = data[16 + 11 * 2..].gread_with(offset, LE).unwrap();
+        assert_eq!(*offset, 12);
+
+        let error = code.fill_array_data(0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Malformed entity: No fill-array-data payload: found 0x26 at 0"
+        );
+        // 3 elements of 4 bytes don't fit in the remaining instructions
+        insns[12] = 4;
+        let data = code_item(&insns);
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        let error = code.fill_array_data(11).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MalFormed);
+        assert!(error.to_string().contains("3 elements of 4 bytes overruns"));
+    }
+
+    /// Builds a code item with `tries` try blocks using 3 catch handlers in turn, each
+    /// catching 2 exception types and everything else.
+    fn shared_handlers_code_item(tries: usize) -> Vec<u8> {
+        let mut data = vec![0u8; 16 + 4 + tries * 8];
+        data.pwrite_with(1u16, 0, LE).unwrap();
+        data.pwrite_with(tries as u16, 6, LE).unwrap();
+        // nop, return-void
+        data.pwrite_with(2u32, 12, LE).unwrap();
+        data.pwrite_with(0x0eu16, 18, LE).unwrap();
+        let handler_offs = [1u16, 7, 13];
+        for i in 0..tries {
+            let offset = 20 + i * 8;
+            data.pwrite_with(1u16, offset + 4, LE).unwrap();
+            data.pwrite_with(handler_offs[i % 3], offset + 6, LE)
+                .unwrap();
+        }
+        data.push(3);
+        for _ in 0..3 {
+            // sleb128 -2, (type, addr) pairs and the catch-all addr
+            data.extend_from_slice(&[0x7e, 0, 1, 1, 1, 1]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_shared_catch_handlers() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let data = shared_handlers_code_item(10_000);
+        let start = std::time::Instant::now();
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        assert!(
+            start.elapsed().as_secs() < 2,
+            "parsing took {:?}",
+            start.elapsed()
+        );
+        assert_eq!(code.tries.len(), 10_000);
+        let stats = code.handler_reuse_stats();
+        assert_eq!((stats.tries(), stats.distinct_handlers()), (10_000, 3));
+        // the handlers are parsed once and shared, not copied for each try block
+        for (i, try_block) in code.tries.iter().enumerate() {
+            let shared = &code.tries[i % 3].catch_handlers;
+            assert!(Shared::ptr_eq(&try_block.catch_handlers, shared));
+            assert_eq!(try_block.catch_handlers().len(), 3);
+        }
+
+        let limited = DexReader::builder()
+            .limits(ParserLimits::new().max_tries(9_999))
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        let error = data.pread_with::<CodeItem>(0, &limited).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::LimitExceeded);
+        let limited = DexReader::builder()
+            .limits(ParserLimits::new().max_handler_types(1))
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        let error = data.pread_with::<CodeItem>(0, &limited).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::LimitExceeded);
+    }
+
+    #[test]
+    fn test_insns_size() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        // a code item declaring the largest instructions, followed by two code units
+        let mut data = vec![0u8; 20];
+        data.pwrite_with(u32::MAX, 12, LE).unwrap();
+        let error = data.pread_with::<CodeItem>(0, &dex).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MalFormed);
+
+        data.pwrite_with(2u32, 12, LE).unwrap();
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        assert_eq!(code.insns, [0, 0]);
+        let limited = DexReader::builder()
+            .limits(ParserLimits::new().max_insns_units(1))
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        let error = data.pread_with::<CodeItem>(0, &limited).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::LimitExceeded);
+    }
+}
@@ -0,0 +1,708 @@
+//! Debug info of methods: parameter names, local variables and line numbers.
+use scroll::{ctx, Pread, Sleb128, Uleb128};
+use std::{collections::HashMap, convert::TryFrom, ops::Deref};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    error::Error,
+    jtype::Type,
+    leb::{read_uleb128_index, LebScanner},
+    source::DexSource,
+    string::DexString,
+    uint, ulong,
+};
+
+/// Debug Info of a method.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#debug-info-item)
+#[derive(Debug, Getters, CopyGetters)]
+pub struct DebugInfoItem {
+    /// Initial value for the state machines's line register.
+    #[get_copy = "pub"]
+    line_start: usize,
+    /// Names of the incoming parameters.
+    #[get = "pub"]
+    parameter_names: Vec<Option<DexString>>,
+    /// Local variable records of the state machine, in address order.
+    local_events: Vec<LocalEvent>,
+    /// Address and line of the position entries emitted by the state machine.
+    positions: Vec<(uint, uint)>,
+}
+
+/// A local variable record emitted by the debug info state machine.
+#[derive(Debug)]
+enum LocalEvent {
+    Start {
+        addr: ulong,
+        register: ulong,
+        name: Option<DexString>,
+        jtype: Option<Type>,
+        signature: Option<DexString>,
+    },
+    End {
+        addr: ulong,
+        register: ulong,
+    },
+    Restart {
+        addr: ulong,
+        register: ulong,
+    },
+}
+
+/// A local variable of a method, with the address ranges in which it is live.
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct LocalVariable {
+    /// Register holding the variable.
+    #[get_copy = "pub"]
+    register: ulong,
+    /// Name of the variable.
+    #[get = "pub"]
+    name: Option<DexString>,
+    /// Type of the variable.
+    #[get = "pub"]
+    jtype: Option<Type>,
+    /// Generic type signature of the variable.
+    #[get = "pub"]
+    signature: Option<DexString>,
+    /// Half-open ranges of code unit addresses in which the variable is live, in order.
+    #[get = "pub"]
+    live_ranges: Vec<(ulong, ulong)>,
+}
+
+/// A live range of a local variable as recorded by the debug info state machine, see
+/// `DebugInfoItem::local_variables`.
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct LocalVar {
+    /// Register holding the variable.
+    #[get_copy = "pub"]
+    register: ulong,
+    /// Name of the variable.
+    #[get = "pub"]
+    name: Option<DexString>,
+    /// Type of the variable.
+    #[get = "pub"]
+    jtype: Option<Type>,
+    /// Generic type signature of the variable.
+    #[get = "pub"]
+    signature: Option<DexString>,
+    /// Address, in code units, at which the variable becomes live.
+    #[get_copy = "pub"]
+    start_addr: ulong,
+    /// Address, in code units, at which the variable stops being live, exclusive. The
+    /// `insns_size` of the method if the variable is live until its end, as in `locals`.
+    #[get_copy = "pub"]
+    end_addr: ulong,
+}
+
+/// The local variables of a method, see `DebugInfoItem::locals`.
+#[derive(Debug, Default)]
+pub struct Locals {
+    variables: Vec<LocalVariable>,
+}
+
+impl Locals {
+    /// Returns the variable held in `register` at the address.
+    pub fn local_at(&self, register: ulong, addr: ulong) -> Option<&LocalVariable> {
+        self.variables.iter().find(|variable| {
+            variable.register == register
+                && variable
+                    .live_ranges
+                    .iter()
+                    .any(|&(start, end)| (start..end).contains(&addr))
+        })
+    }
+}
+
+impl Deref for Locals {
+    type Target = Vec<LocalVariable>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.variables
+    }
+}
+
+/// A parameter of a method, live in a register from the start of the method.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    /// Register holding the parameter.
+    pub register: ulong,
+    /// Name of the parameter.
+    pub name: DexString,
+    /// Type of the parameter.
+    pub jtype: Type,
+}
+
+impl DebugInfoItem {
+    /// Returns the address, in code units, and the source line of each position entry of
+    /// the line number program, in the order they are emitted. Only the special opcodes emit
+    /// entries: `DBG_ADVANCE_PC` and `DBG_ADVANCE_LINE` only move the registers, and the
+    /// prologue and epilogue markers are ignored.
+    pub fn line_number_table(&self) -> Vec<(uint, uint)> {
+        self.positions.clone()
+    }
+
+    /// Returns a record for each range in which a local variable is live, ordered by start
+    /// address. A `DBG_RESTART_LOCAL` opens a new range with the name and type of the
+    /// variable last ended in the register. Parameters are only included if the program
+    /// starts a local for them; use `locals` to merge them in. Variables still live at the
+    /// end of the method end at `insns_size`, its number of code units, as in `locals`.
+    pub fn local_variables(&self, insns_size: ulong) -> Vec<LocalVar> {
+        let mut variables: Vec<LocalVar> = self
+            .locals(&[], insns_size)
+            .iter()
+            .flat_map(|variable| {
+                variable
+                    .live_ranges
+                    .iter()
+                    .map(move |&(start, end)| LocalVar {
+                        register: variable.register,
+                        name: variable.name.clone(),
+                        jtype: variable.jtype.clone(),
+                        signature: variable.signature.clone(),
+                        start_addr: start,
+                        end_addr: end,
+                    })
+            })
+            .collect();
+        variables.sort_by_key(|variable| variable.start_addr);
+        variables
+    }
+
+    /// Returns the local variables of the method, given its parameters and the number of
+    /// code units of its instructions.
+    ///
+    /// A variable ended and then restarted is a single variable with several live ranges.
+    /// Variables still live at the end of the method are live until `insns_size`.
+    pub fn locals(&self, parameters: &[Parameter], insns_size: ulong) -> Locals {
+        let mut locals = LocalsBuilder::default();
+        for parameter in parameters {
+            locals.start(
+                0,
+                parameter.register,
+                Some(parameter.name.clone()),
+                Some(parameter.jtype.clone()),
+                None,
+            );
+        }
+        for event in &self.local_events {
+            match *event {
+                LocalEvent::Start {
+                    addr,
+                    register,
+                    ref name,
+                    ref jtype,
+                    ref signature,
+                } => locals.start(
+                    addr,
+                    register,
+                    name.clone(),
+                    jtype.clone(),
+                    signature.clone(),
+                ),
+                LocalEvent::End { addr, register } => locals.end(addr, register),
+                LocalEvent::Restart { addr, register } => locals.restart(addr, register),
+            }
+        }
+        locals.finish(insns_size)
+    }
+}
+
+/// Merges the local variable records into variables with live ranges.
+#[derive(Default)]
+struct LocalsBuilder {
+    variables: Vec<LocalVariable>,
+    /// Index of the variable live in each register. The last range of a live variable is
+    /// open, its end is set when it is ended.
+    live: HashMap<ulong, usize>,
+    /// Index of the variable last started in each register, for restarts.
+    last: HashMap<ulong, usize>,
+}
+
+impl LocalsBuilder {
+    fn start(
+        &mut self,
+        addr: ulong,
+        register: ulong,
+        name: Option<DexString>,
+        jtype: Option<Type>,
+        signature: Option<DexString>,
+    ) {
+        self.end(addr, register);
+        let index = self.variables.len();
+        self.variables.push(LocalVariable {
+            register,
+            name,
+            jtype,
+            signature,
+            live_ranges: vec![(addr, addr)],
+        });
+        self.live.insert(register, index);
+        self.last.insert(register, index);
+    }
+
+    fn end(&mut self, addr: ulong, register: ulong) {
+        if let Some(index) = self.live.remove(&register) {
+            if let Some(range) = self.variables[index].live_ranges.last_mut() {
+                range.1 = addr;
+            }
+        }
+    }
+
+    fn restart(&mut self, addr: ulong, register: ulong) {
+        if self.live.contains_key(&register) {
+            return;
+        }
+        let index = match self.last.get(&register) {
+            Some(&index) => index,
+            None => {
+                dex_warn!(target: "debug-info", "restart of register {} at {} without a started local", register, addr);
+                return;
+            }
+        };
+        let ranges = &mut self.variables[index].live_ranges;
+        match ranges.last_mut() {
+            // reopen a range ended at the same address
+            Some(range) if range.1 == addr => {}
+            _ => ranges.push((addr, addr)),
+        }
+        self.live.insert(register, index);
+    }
+
+    fn finish(mut self, insns_size: ulong) -> Locals {
+        let live: Vec<ulong> = self.live.keys().cloned().collect();
+        for register in live {
+            self.end(insns_size, register);
+        }
+        let mut variables = self.variables;
+        for variable in &mut variables {
+            variable.live_ranges.retain(|&(start, end)| start < end);
+        }
+        variables.retain(|variable| !variable.live_ranges.is_empty());
+        Locals { variables }
+    }
+}
+
+impl<'a, S> ctx::TryFromCtx<'a, &crate::Dex<S>> for DebugInfoItem
+where
+    S: DexSource,
+{
+    type Error = Error;
+    type Size = usize;
+
+    fn try_from_ctx(
+        source: &'a [u8],
+        dex: &crate::Dex<S>,
+    ) -> Result<(Self, Self::Size), Self::Error> {
+        let offset = &mut 0;
+        let line_start = Uleb128::read(source, offset)? as usize;
+        let parameters_size = Uleb128::read(source, offset)?;
+        let mut parameter_names = Vec::with_capacity(parameters_size as usize);
+        for _ in 0..parameters_size {
+            parameter_names.push(read_string_p1(source, offset, dex)?);
+        }
+        let mut local_events = Vec::new();
+        let mut positions = Vec::new();
+        let mut addr: uint = 0;
+        let mut line = line_start as uint;
+        loop {
+            let opcode: u8 = source.gread(offset)?;
+            match opcode {
+                DBG_END_SEQUENCE => break,
+                DBG_ADVANCE_PC => addr = advance(addr, Uleb128::read(source, offset)?)?,
+                DBG_ADVANCE_LINE => {
+                    line = line.wrapping_add(Sleb128::read(source, offset)? as uint);
+                }
+                DBG_START_LOCAL | DBG_START_LOCAL_EXTENDED => {
+                    let register = Uleb128::read(source, offset)?;
+                    let name = read_string_p1(source, offset, dex)?;
+                    let jtype = match read_uleb128p1(source, offset)? {
+                        Some(type_id) => Some(dex.get_type(type_id)?),
+                        None => None,
+                    };
+                    let signature = if opcode == DBG_START_LOCAL_EXTENDED {
+                        read_string_p1(source, offset, dex)?
+                    } else {
+                        None
+                    };
+                    local_events.push(LocalEvent::Start {
+                        addr: ulong::from(addr),
+                        register,
+                        name,
+                        jtype,
+                        signature,
+                    });
+                }
+                DBG_END_LOCAL => local_events.push(LocalEvent::End {
+                    addr: ulong::from(addr),
+                    register: Uleb128::read(source, offset)?,
+                }),
+                DBG_RESTART_LOCAL => local_events.push(LocalEvent::Restart {
+                    addr: ulong::from(addr),
+                    register: Uleb128::read(source, offset)?,
+                }),
+                DBG_SET_PROLOGUE_END | DBG_SET_EPILOGUE_BEGIN => {}
+                DBG_SET_FILE => {
+                    Uleb128::read(source, offset)?;
+                }
+                // special opcodes advance the line and the address and emit a position
+                _ => {
+                    let adjusted = opcode - DBG_FIRST_SPECIAL;
+                    addr = advance(addr, ulong::from(adjusted) / DBG_LINE_RANGE)?;
+                    line = line.wrapping_add(
+                        (DBG_LINE_BASE + i32::from(adjusted) % DBG_LINE_RANGE as i32) as uint,
+                    );
+                    positions.push((addr, line));
+                }
+            }
+        }
+        Ok((
+            Self {
+                line_start,
+                parameter_names,
+                local_events,
+                positions,
+            },
+            *offset,
+        ))
+    }
+}
+
+const DBG_END_SEQUENCE: u8 = 0x00;
+const DBG_ADVANCE_PC: u8 = 0x01;
+const DBG_ADVANCE_LINE: u8 = 0x02;
+const DBG_START_LOCAL: u8 = 0x03;
+const DBG_START_LOCAL_EXTENDED: u8 = 0x04;
+const DBG_END_LOCAL: u8 = 0x05;
+const DBG_RESTART_LOCAL: u8 = 0x06;
+const DBG_SET_PROLOGUE_END: u8 = 0x07;
+const DBG_SET_EPILOGUE_BEGIN: u8 = 0x08;
+const DBG_SET_FILE: u8 = 0x09;
+const DBG_FIRST_SPECIAL: u8 = 0x0a;
+const DBG_LINE_BASE: i32 = -4;
+const DBG_LINE_RANGE: ulong = 15;
+
+/// Skips the debug info item at `offset`, reading its LEB128 values with `scanner`, see
+/// `Dex::nonminimal_leb_sites`.
+pub(crate) fn scan_debug_info(
+    scanner: &mut LebScanner<'_>,
+    offset: &mut usize,
+) -> crate::Result<()> {
+    // line_start, parameters_size and the names of the parameters
+    scanner.uleb128(offset)?;
+    let parameters_size = scanner.uleb128(offset)?;
+    for _ in 0..parameters_size {
+        scanner.uleb128(offset)?;
+    }
+    loop {
+        let operands = match scanner.byte(offset)? {
+            DBG_END_SEQUENCE => return Ok(()),
+            DBG_ADVANCE_LINE => {
+                scanner.sleb128(offset)?;
+                0
+            }
+            DBG_START_LOCAL => 3,
+            DBG_START_LOCAL_EXTENDED => 4,
+            DBG_ADVANCE_PC | DBG_END_LOCAL | DBG_RESTART_LOCAL | DBG_SET_FILE => 1,
+            _ => 0,
+        };
+        for _ in 0..operands {
+            scanner.uleb128(offset)?;
+        }
+    }
+}
+
+/// Advances the address register of the state machine by `delta` code units. Addresses
+/// past the largest `insns_size` are malformed.
+fn advance(addr: uint, delta: ulong) -> crate::Result<uint> {
+    uint::try_from(delta)
+        .ok()
+        .and_then(|delta| addr.checked_add(delta))
+        .ok_or_else(|| {
+            Error::MalFormed(format!(
+                "Address overflow in debug info: {} + {}",
+                addr, delta
+            ))
+        })
+}
+
+/// Reads a uleb128p1 encoded index, where `NO_INDEX` is encoded as 0.
+fn read_uleb128p1(source: &[u8], offset: &mut usize) -> crate::Result<Option<uint>> {
+    Ok(read_uleb128_index(source, offset)?.checked_sub(1))
+}
+
+/// Reads a uleb128p1 encoded `StringId` and returns the string.
+fn read_string_p1<S: DexSource>(
+    source: &[u8],
+    offset: &mut usize,
+    dex: &crate::Dex<S>,
+) -> crate::Result<Option<DexString>> {
+    read_uleb128p1(source, offset)?
+        .map(|string_id| dex.get_string(string_id))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DebugInfoItem, LocalEvent, Parameter};
+    use crate::{
+        jtype::{PrimitiveType, Type, TypeKind},
+        string::DexString,
+        DexReader,
+    };
+
+    fn string(value: &str) -> DexString {
+        DexString::from(value.to_string())
+    }
+
+    fn start(addr: u64, register: u64, name: &str) -> LocalEvent {
+        LocalEvent::Start {
+            addr,
+            register,
+            name: Some(string(name)),
+            jtype: None,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_locals_merged() {
+        let debug_info = DebugInfoItem {
+            line_start: 1,
+            parameter_names: vec![Some(string("p"))],
+            positions: Vec::new(),
+            local_events: vec![
+                start(0, 1, "a"),
+                start(0, 2, "c"),
+                LocalEvent::End {
+                    addr: 2,
+                    register: 2,
+                },
+                // restart of a register that never held a variable
+                LocalEvent::Restart {
+                    addr: 3,
+                    register: 3,
+                },
+                // reuse of a register ends the variable it held
+                start(4, 1, "b"),
+                LocalEvent::Restart {
+                    addr: 5,
+                    register: 2,
+                },
+                LocalEvent::End {
+                    addr: 6,
+                    register: 4,
+                },
+                LocalEvent::End {
+                    addr: 7,
+                    register: 2,
+                },
+                LocalEvent::Restart {
+                    addr: 7,
+                    register: 2,
+                },
+                LocalEvent::End {
+                    addr: 8,
+                    register: 1,
+                },
+                // a variable with an empty live range is dropped
+                start(9, 5, "empty"),
+                LocalEvent::End {
+                    addr: 9,
+                    register: 5,
+                },
+            ],
+        };
+        let parameter = Parameter {
+            register: 4,
+            name: string("p"),
+            jtype: Type::new(0, string("I"), TypeKind::Primitive(PrimitiveType::Int)),
+        };
+        let locals = debug_info.locals(&[parameter], 10);
+        let ranges: Vec<_> = locals
+            .iter()
+            .map(|local| {
+                let name = local.name().as_ref().unwrap().to_string();
+                (name, local.register(), local.live_ranges().clone())
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                ("p".to_string(), 4, vec![(0, 6)]),
+                ("a".to_string(), 1, vec![(0, 4)]),
+                ("c".to_string(), 2, vec![(0, 2), (5, 10)]),
+                ("b".to_string(), 1, vec![(4, 8)]),
+            ]
+        );
+        assert_eq!(
+            &**locals.local_at(1, 3).unwrap().name().as_ref().unwrap(),
+            "a"
+        );
+        assert_eq!(
+            &**locals.local_at(1, 4).unwrap().name().as_ref().unwrap(),
+            "b"
+        );
+        assert!(locals.local_at(1, 8).is_none());
+        assert!(locals.local_at(2, 3).is_none());
+        assert!(locals.local_at(2, 9).is_some());
+        assert!(locals.local_at(3, 3).is_none());
+    }
+
+    #[test]
+    fn test_local_variables() {
+        let debug_info = DebugInfoItem {
+            line_start: 1,
+            parameter_names: Vec::new(),
+            positions: Vec::new(),
+            local_events: vec![
+                start(0, 1, "a"),
+                start(1, 2, "b"),
+                LocalEvent::End {
+                    addr: 3,
+                    register: 1,
+                },
+                LocalEvent::Restart {
+                    addr: 5,
+                    register: 1,
+                },
+                LocalEvent::End {
+                    addr: 6,
+                    register: 2,
+                },
+            ],
+        };
+        let variables: Vec<_> = debug_info
+            .local_variables(8)
+            .iter()
+            .map(|local| {
+                let name = local.name().as_ref().unwrap().to_string();
+                (name, local.register(), local.start_addr(), local.end_addr())
+            })
+            .collect();
+        assert_eq!(
+            variables,
+            vec![
+                ("a".to_string(), 1, 0, 3),
+                ("b".to_string(), 2, 1, 6),
+                ("a".to_string(), 1, 5, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_locals_of_example_methods() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let (mut this, mut named) = (0, 0);
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let locals = match method.locals() {
+                    Some(locals) => locals,
+                    None => continue,
+                };
+                let insns_size = method.code().unwrap().insns.len() as u64;
+                let debug_info = method.code().unwrap().debug_info_item().unwrap();
+                for variable in debug_info.local_variables(insns_size) {
+                    assert!(variable.start_addr() < variable.end_addr());
+                    assert!(variable.end_addr() <= insns_size);
+                    if variable.end_addr() == insns_size {
+                        assert!(locals
+                            .local_at(variable.register(), insns_size - 1)
+                            .is_some());
+                    }
+                }
+                for local in locals.iter() {
+                    let ranges = local.live_ranges();
+                    assert!(!ranges.is_empty());
+                    assert!(ranges.iter().all(|&(start, end)| start < end));
+                    assert!(ranges.windows(2).all(|w| w[0].1 < w[1].0));
+                    assert!(ranges.last().unwrap().1 <= insns_size);
+                    match local.name().as_ref().map(|name| &**name) {
+                        Some("this") => this += 1,
+                        Some(_) => named += 1,
+                        None => {}
+                    }
+                }
+            }
+        }
+        assert!(this > 0);
+        assert!(named > 0);
+    }
+
+    #[test]
+    fn test_line_number_table() {
+        use scroll::Pread;
+
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let program: &[u8] = &[
+            10,   // line_start
+            0,    // parameters_size
+            0x07, // DBG_SET_PROLOGUE_END
+            0x0e, // line + 0, address + 0
+            0x01, 3,    // DBG_ADVANCE_PC 3
+            0x0f, // line + 1, address + 0
+            0x02, 0x7e, // DBG_ADVANCE_LINE -2
+            0x08, // DBG_SET_EPILOGUE_BEGIN
+            0x2c, // line + 0, address + 2
+            0x00, // DBG_END_SEQUENCE
+        ];
+        let debug_info: DebugInfoItem = program.pread_with(0, &dex).expect("bad debug info");
+        assert_eq!(debug_info.line_number_table(), [(0, 10), (3, 11), (5, 9)]);
+
+        let mut with_lines = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for code in class.methods().filter_map(|method| method.code()) {
+                let table = code.line_number_table();
+                if code.debug_info_item().is_none() {
+                    assert!(table.is_empty());
+                    continue;
+                }
+                assert!(table.windows(2).all(|w| w[0].0 <= w[1].0));
+                assert!(table
+                    .iter()
+                    .all(|&(addr, _)| (addr as usize) < code.insns.len()));
+                if !table.is_empty() {
+                    with_lines += 1;
+                }
+            }
+        }
+        assert!(with_lines > 0);
+    }
+
+    #[test]
+    fn test_address_overflow() {
+        use crate::test_utils::mutated_example_dex;
+
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let launcher = dex
+            .find_class_by_name("org.adw.launcher.Launcher")
+            .expect("bad class")
+            .expect("class not found");
+        let debug_info_off = launcher
+            .methods()
+            .filter_map(|method| method.code())
+            .map(|code| code.debug_info_off)
+            .max()
+            .expect("no debug info");
+        let programs: &[&[u8]] = &[
+            // DBG_ADVANCE_PC 2^32
+            &[1, 0, 0x01, 0x80, 0x80, 0x80, 0x80, 0x10, 0x00],
+            // DBG_ADVANCE_PC 2^32 - 1, then a special opcode advancing the address by 1
+            &[1, 0, 0x01, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x19, 0x00],
+        ];
+        for program in programs {
+            let data = mutated_example_dex(|data| {
+                let start = debug_info_off as usize;
+                data[start..start + program.len()].copy_from_slice(program);
+            });
+            let dex = DexReader::from_vec(data).expect("cannot open dex");
+            let error = dex.get_debug_info_item(debug_info_off).unwrap_err();
+            assert!(
+                error.to_string().contains("Address overflow in debug info"),
+                "{}",
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,670 @@
+//! Groups of dex files loaded together, such as the `classesN.dex` files of an APK.
+//!
+//! Split APK sets and backups often hold byte-identical copies of a dex file. A container
+//! built with `DexContainer::from_sources` parses each distinct content once: every
+//! source is kept as an entry, and identical entries share the same `Dex`.
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    convert::TryFrom,
+    sync::Arc,
+};
+#[cfg(feature = "container-zip")]
+use std::{
+    fs::File,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{class::Class, string::StringId, Dex, DexReader, Result};
+#[cfg(feature = "profile")]
+use crate::{
+    method::MethodId,
+    profile::{self, MethodHotness, Profile, ProfileRef},
+};
+
+/// The dex files of a multidex application. Each distinct dex is identified by its index
+/// in `dexes`, and each source the container was built from by its index in `entries`.
+pub struct DexContainer<T> {
+    dexes: Vec<Dex<T>>,
+    entries: Vec<ContainerEntry>,
+}
+
+/// A source of a `DexContainer`, such as an entry of an APK.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ContainerEntry {
+    /// Name of the source, such as `base.apk!classes.dex`.
+    #[get = "pub"]
+    name: String,
+    /// Index of the dex parsed from the source in `DexContainer::dexes`.
+    #[get_copy = "pub"]
+    dex_index: usize,
+}
+
+/// A class found in a `DexContainer`, with the names of all the entries holding its dex.
+#[derive(Getters, CopyGetters)]
+pub struct ContainerClass<'a> {
+    /// The class.
+    #[get = "pub"]
+    class: Class,
+    /// Index of the dex defining the class in `DexContainer::dexes`.
+    #[get_copy = "pub"]
+    dex_index: usize,
+    /// Names of the entries holding the dex, in the order of the entries.
+    #[get = "pub"]
+    sources: Vec<&'a str>,
+}
+
+impl<T> DexContainer<T>
+where
+    T: AsRef<[u8]> + Send + Sync,
+{
+    /// Creates a container of the given dex files, in order. Each dex is its own entry,
+    /// named by its index.
+    pub fn new(dexes: Vec<Dex<T>>) -> Self {
+        let entries = (0..dexes.len())
+            .map(|dex_index| ContainerEntry {
+                name: dex_index.to_string(),
+                dex_index,
+            })
+            .collect();
+        Self { dexes, entries }
+    }
+
+    /// Creates a container of the named sources, in order, parsing identical contents
+    /// only once.
+    pub fn from_sources<I>(sources: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, T)>,
+    {
+        Self::from_sources_with(sources, DexReader::from_vec)
+    }
+
+    /// Creates a container of the dex files in `sources`, in order, parsing identical
+    /// contents only once. The entries are named like the dex files of an APK:
+    /// `classes.dex`, `classes2.dex` and so on.
+    pub fn from_buffers<I>(sources: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Self::from_sources(
+            sources
+                .into_iter()
+                .enumerate()
+                .map(|(index, data)| (dex_entry_name(index), data)),
+        )
+    }
+
+    /// Creates a container like `from_sources`, parsing each distinct content with
+    /// `parse`, for instance to read the files with a `DexReaderBuilder`.
+    ///
+    /// Contents are identified by the SHA-1 signature in their header and their length,
+    /// and only shared if their bytes are equal, so that files with forged signatures are
+    /// still parsed separately.
+    pub fn from_sources_with<I, F>(sources: I, mut parse: F) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, T)>,
+        F: FnMut(T) -> Result<Dex<T>>,
+    {
+        let mut dexes: Vec<Dex<T>> = Vec::new();
+        let mut entries = Vec::new();
+        let mut by_content: HashMap<ContentKey, Vec<usize>> = HashMap::new();
+        for (name, data) in sources {
+            let key = content_key(data.as_ref());
+            let shared =
+                key.as_ref().and_then(|key| {
+                    by_content.get(key)?.iter().copied().find(|dex_index| {
+                        dexes[*dex_index].source.inner().as_ref() == data.as_ref()
+                    })
+                });
+            let dex_index = match shared {
+                Some(dex_index) => {
+                    dex_debug!(target: "container", "{} is identical to dex {}", name, dex_index);
+                    dex_index
+                }
+                None => {
+                    dexes.push(parse(data)?);
+                    let dex_index = dexes.len() - 1;
+                    if let Some(key) = key {
+                        by_content.entry(key).or_default().push(dex_index);
+                    }
+                    dex_index
+                }
+            };
+            entries.push(ContainerEntry { name, dex_index });
+        }
+        Ok(Self { dexes, entries })
+    }
+
+    /// The distinct dex files of the container.
+    pub fn dexes(&self) -> &[Dex<T>] {
+        &self.dexes
+    }
+
+    /// The sources of the container, in order.
+    pub fn entries(&self) -> &[ContainerEntry] {
+        &self.entries
+    }
+
+    /// Returns the dex of the entry at `index`.
+    pub fn get(&self, index: usize) -> Option<&Dex<T>> {
+        self.entries
+            .get(index)
+            .map(|entry| &self.dexes[entry.dex_index])
+    }
+
+    /// Number of entries of the container, counting identical copies. Same as
+    /// `entry_count`.
+    pub fn len(&self) -> usize {
+        self.entry_count()
+    }
+
+    /// Returns `true` if the container has no dex files.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of sources the container was built from.
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of distinct dex files, each parsed once.
+    pub fn unique_dex_count(&self) -> usize {
+        self.dexes.len()
+    }
+
+    /// Returns the first dex with `checksum` in its header, the way binary ART profiles
+    /// name dex files.
+    pub fn find_by_checksum(&self, checksum: u32) -> Option<&Dex<T>> {
+        self.dexes.iter().find(|dex| dex.checksum() == checksum)
+    }
+
+    /// Finds the methods of `profile` in the dex files of the container, in the order of
+    /// the profile. A method named by its descriptor is found in the first dex defining its
+    /// class. Methods of dex files missing from the container and descriptors no dex
+    /// defines, such as the rules of text profiles with wildcards, are skipped.
+    #[cfg(feature = "profile")]
+    pub fn annotate_hotness(&self, profile: &Profile) -> Result<Vec<MethodHotness>> {
+        let mut defined_methods = vec![None; self.dexes.len()];
+        let mut annotated = Vec::new();
+        for method in profile.methods() {
+            let found = match method.reference() {
+                ProfileRef::Index { checksum, index } => self
+                    .dexes
+                    .iter()
+                    .position(|dex| dex.checksum() == *checksum)
+                    .map(|dex_index| (dex_index, MethodId::from(*index))),
+                ProfileRef::Descriptor(descriptor) => {
+                    let mut found = None;
+                    for (dex_index, dex) in self.dexes.iter().enumerate() {
+                        if defined_methods[dex_index].is_none() {
+                            defined_methods[dex_index] = Some(profile::defined_methods(dex)?);
+                        }
+                        if let Some(id) = defined_methods[dex_index]
+                            .as_ref()
+                            .and_then(|methods| methods.get(descriptor))
+                        {
+                            found = Some((dex_index, *id));
+                            break;
+                        }
+                    }
+                    found
+                }
+            };
+            match found {
+                Some((dex_index, method_id)) => annotated.push(MethodHotness::new(
+                    dex_index,
+                    self.dexes[dex_index].get_method_ref(method_id)?,
+                    method.flags(),
+                )),
+                None => {
+                    dex_debug!(target: "profile", "{:?} is not in the container", method.reference())
+                }
+            }
+        }
+        Ok(annotated)
+    }
+
+    /// Names of the entries holding the dex at `dex_index`, in order.
+    pub fn sources(&self, dex_index: usize) -> impl Iterator<Item = &str> + '_ {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.dex_index == dex_index)
+            .map(|entry| entry.name.as_str())
+    }
+
+    /// Finds the class named `name` in the first dex defining it, as Android resolves
+    /// classes of multidex applications. The name may be in Java or smali format, see
+    /// `Dex::find_class_by_name`.
+    pub fn find_class_by_name(&self, name: &str) -> Result<Option<ContainerClass<'_>>>
+    where
+        T: 'static,
+    {
+        self.find_class(|dex| dex.find_class_by_name(name))
+    }
+
+    /// Finds the class with the `type_descriptor` in the first dex defining it.
+    pub fn find_class_by_descriptor(
+        &self,
+        type_descriptor: &str,
+    ) -> Result<Option<ContainerClass<'_>>>
+    where
+        T: 'static,
+    {
+        self.find_class(|dex| dex.find_class_by_descriptor(type_descriptor))
+    }
+
+    fn find_class<F>(&self, find: F) -> Result<Option<ContainerClass<'_>>>
+    where
+        F: Fn(&Dex<T>) -> Result<Option<Class>>,
+    {
+        for (dex_index, dex) in self.dexes.iter().enumerate() {
+            if let Some(class) = find(dex)? {
+                return Ok(Some(self.container_class(class, dex_index)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Iterator over the classes of the distinct dex files. Classes of identical entries
+    /// are only returned once, with all the entries as their sources.
+    pub fn classes(&self) -> impl Iterator<Item = Result<ContainerClass<'_>>> + '_
+    where
+        T: 'static,
+    {
+        self.dexes
+            .iter()
+            .enumerate()
+            .flat_map(move |(dex_index, dex)| {
+                dex.classes()
+                    .map(move |class| Ok(self.container_class(class?, dex_index)))
+            })
+    }
+
+    fn container_class(&self, class: Class, dex_index: usize) -> ContainerClass<'_> {
+        ContainerClass {
+            class,
+            dex_index,
+            sources: self.sources(dex_index).collect(),
+        }
+    }
+
+    /// Builds an index of the distinct strings of all the dex files, with the dex and
+    /// `StringId` of each occurrence.
+    ///
+    /// The string pools are sorted, so the index is built by merging them in a single pass.
+    /// Pools that are not sorted are still indexed correctly, at the cost of a final sort.
+    pub fn shared_string_index(&self) -> Result<SharedStringIndex> {
+        let mut pools: Vec<_> = self.dexes.iter().map(|dex| dex.strings()).collect();
+        let mut heap = BinaryHeap::with_capacity(pools.len());
+        for (dex, pool) in pools.iter_mut().enumerate() {
+            if let Some(string) = pool.next() {
+                let (id, string) = string?;
+                heap.push(Reverse((string, dex, id)));
+            }
+        }
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut sorted = true;
+        while let Some(Reverse((string, dex, id))) = heap.pop() {
+            match entries.last_mut() {
+                Some((last, occurrences)) if **last == *string => occurrences.push((dex, id)),
+                last => {
+                    sorted &= last.is_none_or(|(last, _)| **last < *string);
+                    entries.push((Arc::from(&*string), vec![(dex, id)]));
+                }
+            }
+            if let Some(string) = pools[dex].next() {
+                let (id, string) = string?;
+                heap.push(Reverse((string, dex, id)));
+            }
+        }
+        if !sorted {
+            dex_debug!(target: "shared-string-index", "string pools are not sorted, sorting the index");
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries.dedup_by(|(value, occurrences), (kept, kept_occurrences)| {
+                let duplicate = value == kept;
+                if duplicate {
+                    kept_occurrences.append(occurrences);
+                }
+                duplicate
+            });
+            for (_, occurrences) in &mut entries {
+                occurrences.sort_unstable();
+            }
+        }
+        Ok(SharedStringIndex { entries })
+    }
+}
+
+#[cfg(feature = "container-zip")]
+impl DexContainer<Vec<u8>> {
+    /// Reads the dex files of the APK at `path`: `classes.dex`, then `classes2.dex` and so
+    /// on up to the first missing one, as Android loads them. The entries are named after
+    /// the dex files. An APK without `classes.dex` gives an empty container.
+    pub fn from_apk<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let entries = read_apk_entries(File::open(path)?)?;
+        Self::from_sources(entries)
+    }
+
+    /// Reads the dex files of an APK held in memory or in any seekable reader, such as a
+    /// downloaded APK, like `from_apk`.
+    pub fn from_apk_reader<R: Read + Seek>(reader: R) -> Result<Self> {
+        Self::from_sources(read_apk_entries(reader)?)
+    }
+
+    /// Reads the dex files of the APK at `path` like `from_apk`, opening and extracting
+    /// the entries on the blocking thread pool of the tokio runtime. Only the parsing of
+    /// the headers runs on the calling task, as with `DexReader::from_file_async`.
+    #[cfg(feature = "async")]
+    pub async fn from_apk_async<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = tokio::task::spawn_blocking(move || read_apk_entries(File::open(path)?))
+            .await
+            .map_err(|e| crate::Error::Panicked(e.to_string()))??;
+        Self::from_sources(entries)
+    }
+
+    /// Reads the dex files of a split APK set, such as `base.apk` and its configuration
+    /// splits: the dex files of each APK as in `from_apk`, in the order of `paths`.
+    /// Entries are named `<path>!classesN.dex`, and identical dex files found in several
+    /// APKs are parsed once.
+    pub fn from_apks<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut entries = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            entries.extend(
+                read_apk_entries(File::open(path)?)?
+                    .into_iter()
+                    .map(|(name, data)| (format!("{}!{}", path.display(), name), data)),
+            );
+        }
+        Self::from_sources(entries)
+    }
+}
+
+/// Extracts the `classesN.dex` entries of an APK with their names, in order.
+#[cfg(feature = "container-zip")]
+fn read_apk_entries<R: Read + Seek>(reader: R) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let mut entries = Vec::new();
+    loop {
+        let name = dex_entry_name(entries.len());
+        match read_entry(&mut archive, &name)? {
+            Some(data) => entries.push((name, data)),
+            None => break,
+        }
+    }
+    Ok(entries)
+}
+
+/// Extracts the entry `name` of an APK, stored or deflated. A missing entry is an
+/// `Error::IO` of kind `NotFound`.
+#[cfg(feature = "container-zip")]
+pub(crate) fn read_apk_entry<R: Read + Seek>(reader: R, name: &str) -> Result<Vec<u8>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    read_entry(&mut archive, name)?.ok_or_else(|| {
+        crate::Error::IO(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No entry {} in the APK", name),
+        ))
+    })
+}
+
+/// Extracts the entry `name` of an archive, or `None` if it has none.
+#[cfg(feature = "container-zip")]
+fn read_entry<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Option<Vec<u8>>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    dex_debug!(target: "apk", "{}: {} bytes", name, entry.size());
+    let mut data = Vec::with_capacity(entry.size().min(u64::from(u32::MAX)) as usize);
+    entry.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Name of the dex file at `index` in an APK, `classes.dex` then `classesN.dex` from 2.
+fn dex_entry_name(index: usize) -> String {
+    match index {
+        0 => "classes.dex".to_string(),
+        n => format!("classes{}.dex", n + 1),
+    }
+}
+
+/// The SHA-1 signature in the header of a dex and its length.
+type ContentKey = ([u8; 20], usize);
+
+/// Returns the key identifying the content of a dex, or `None` if it is too short to
+/// have a header.
+fn content_key(bytes: &[u8]) -> Option<ContentKey> {
+    let signature = <[u8; 20]>::try_from(bytes.get(12..32)?).ok()?;
+    Some((signature, bytes.len()))
+}
+
+/// A distinct string with the dex index and `StringId` of each of its occurrences.
+type Entry = (Arc<str>, Vec<(usize, StringId)>);
+
+/// Distinct strings of the dex files of a `DexContainer`, sorted, each stored once.
+#[derive(Debug, Clone, Default)]
+pub struct SharedStringIndex {
+    entries: Vec<Entry>,
+}
+
+impl SharedStringIndex {
+    /// Returns the shared copy of `value` if any dex contains it.
+    pub fn lookup(&self, value: &str) -> Option<&Arc<str>> {
+        self.find(value).map(|(string, _)| string)
+    }
+
+    /// Returns the dex index and `StringId` of each occurrence of `value`, sorted by dex
+    /// index.
+    pub fn occurrences(&self, value: &str) -> &[(usize, StringId)] {
+        self.find(value)
+            .map(|(_, occurrences)| occurrences.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Iterator over the strings and their occurrences, sorted by string.
+    pub fn iter(&self) -> impl Iterator<Item = (&Arc<str>, &[(usize, StringId)])> {
+        self.entries
+            .iter()
+            .map(|(string, occurrences)| (string, occurrences.as_slice()))
+    }
+
+    /// Number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no dex contains any string.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn find(&self, value: &str) -> Option<&Entry> {
+        self.entries
+            .binary_search_by(|(string, _)| (**string).cmp(value))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DexContainer;
+    use crate::{test_utils::minimal_dex, DexReader};
+
+    fn container(pools: &[&[&str]]) -> DexContainer<Vec<u8>> {
+        DexContainer::new(
+            pools
+                .iter()
+                .map(|strings| DexReader::from_vec(minimal_dex(strings, 0)).expect("bad dex"))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_shared_string_index() {
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let example = DexReader::from_vec(example).expect("cannot open dex");
+        let object_id = example
+            .strings()
+            .map(|s| s.expect("bad string"))
+            .find(|(_, s)| **s == *"Ljava/lang/Object;")
+            .expect("no Object string")
+            .0;
+        let strings_len = example.header().string_ids_size() as usize;
+        let minimal = minimal_dex(&["", "Ljava/lang/Object;", "~only"], 0);
+        let minimal = DexReader::from_vec(minimal).expect("cannot open dex");
+        let container = DexContainer::new(vec![minimal, example]);
+
+        let index = container.shared_string_index().expect("cannot build index");
+        assert_eq!(index.len(), strings_len + 1);
+        assert_eq!(
+            index.occurrences("Ljava/lang/Object;"),
+            &[(0, 1), (1, object_id)]
+        );
+        assert_eq!(index.occurrences("~only"), &[(0, 2)]);
+        assert_eq!(index.occurrences("~absent"), &[]);
+        assert_eq!(index.occurrences(""), &[(0, 0), (1, 0)]);
+        assert_eq!(
+            index.lookup("Ljava/lang/Object;").map(|s| &**s),
+            Some("Ljava/lang/Object;")
+        );
+        let strings: Vec<_> = index.iter().map(|(s, _)| s.clone()).collect();
+        assert!(strings.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_shared_string_index_unsorted_pool() {
+        let container = container(&[&["b", "a"], &["a", "c"], &[]]);
+        let index = container.shared_string_index().expect("cannot build index");
+        let strings: Vec<_> = index.iter().map(|(s, o)| (&**s, o.to_vec())).collect();
+        assert_eq!(
+            strings,
+            vec![
+                ("a", vec![(0, 1), (1, 0)]),
+                ("b", vec![(0, 0)]),
+                ("c", vec![(1, 1)])
+            ]
+        );
+        assert!(DexContainer::<Vec<u8>>::new(Vec::new())
+            .shared_string_index()
+            .expect("cannot build index")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_from_buffers() {
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let sources = vec![minimal_dex(&["a"], 0), example.clone(), example];
+        let container = DexContainer::from_buffers(sources).expect("cannot build container");
+        let names: Vec<_> = container
+            .entries()
+            .iter()
+            .map(|e| e.name().as_str())
+            .collect();
+        assert_eq!(names, ["classes.dex", "classes2.dex", "classes3.dex"]);
+        assert_eq!(container.unique_dex_count(), 2);
+        let class = container
+            .find_class_by_descriptor("Lorg/adw/launcher/Launcher;")
+            .expect("cannot find class")
+            .expect("no class");
+        assert_eq!(class.dex_index(), 1);
+        assert_eq!(class.sources(), &["classes2.dex", "classes3.dex"]);
+        assert!(DexContainer::from_buffers(vec![b"not a dex".to_vec()]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "container-zip")]
+    fn test_from_apk_reader() {
+        use std::io::{Cursor, Write};
+        use zip::{write::SimpleFileOptions, ZipWriter};
+
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let minimal = minimal_dex(&["a"], 0);
+        let mut apk = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in [
+            ("classes2.dex", &minimal),
+            ("classes.dex", &example),
+            ("classes4.dex", &minimal),
+        ] {
+            apk.start_file(name, SimpleFileOptions::default())
+                .expect("cannot add entry");
+            apk.write_all(data).expect("cannot write entry");
+        }
+        let apk = apk.finish().expect("cannot write APK");
+
+        let container = DexContainer::from_apk_reader(apk).expect("cannot read APK");
+        let names: Vec<_> = container
+            .entries()
+            .iter()
+            .map(|e| e.name().as_str())
+            .collect();
+        // classes4.dex is not loaded as there is no classes3.dex
+        assert_eq!(names, ["classes.dex", "classes2.dex"]);
+        assert_eq!(container.unique_dex_count(), 2);
+        assert_eq!(
+            container.dexes()[0].header().class_defs_size(),
+            container.classes().count() as u32
+        );
+    }
+
+    #[test]
+    fn test_identical_sources_parsed_once() {
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        // same length and signature as each other, different contents
+        let forged = [minimal_dex(&["a"], 0), minimal_dex(&["b"], 0)];
+        assert_eq!(forged[0].len(), forged[1].len());
+        assert_eq!(forged[0][12..32], forged[1][12..32]);
+        let sources = vec![
+            ("base.apk!classes.dex".to_string(), example.clone()),
+            ("a.apk".to_string(), forged[0].clone()),
+            ("split.apk!classes.dex".to_string(), example),
+            ("b.apk".to_string(), forged[1].clone()),
+        ];
+        let mut parses = 0;
+        let container = DexContainer::from_sources_with(sources, |data| {
+            parses += 1;
+            DexReader::from_vec(data)
+        })
+        .expect("cannot build container");
+        assert_eq!(parses, 3);
+        assert_eq!(container.entry_count(), 4);
+        assert_eq!(container.len(), 4);
+        assert_eq!(container.unique_dex_count(), 3);
+        let dex_indexes: Vec<_> = container.entries().iter().map(|e| e.dex_index()).collect();
+        assert_eq!(dex_indexes, [0, 1, 0, 2]);
+        assert!(std::ptr::eq(
+            container.get(0).expect("no entry"),
+            container.get(2).expect("no entry")
+        ));
+
+        let class = container
+            .find_class_by_name("Lorg/adw/launcher/Launcher;")
+            .expect("cannot find class")
+            .expect("no class");
+        assert_eq!(class.dex_index(), 0);
+        assert_eq!(
+            class.sources(),
+            &["base.apk!classes.dex", "split.apk!classes.dex"]
+        );
+        let classes: Vec<_> = container
+            .classes()
+            .collect::<crate::Result<_>>()
+            .expect("cannot load classes");
+        assert_eq!(
+            classes.len(),
+            container.dexes()[0].header().class_defs_size() as usize
+        );
+        assert!(classes.iter().all(|class| class.sources().len() == 2));
+        assert_eq!(container.sources(1).collect::<Vec<_>>(), ["a.apk"]);
+    }
+}
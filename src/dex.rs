@@ -1,34 +1,82 @@
-use std::{fs::File, io::BufReader, num::NonZeroUsize, ops::Range};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    io::Read,
+    ops::Range,
+    time::Instant,
+};
 
-use adler32;
 use getset::{CopyGetters, Getters};
-use memmap2::{Mmap, MmapOptions};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use scroll::{ctx, Pread};
+use scroll::{ctx, Pread, Sleb128, Uleb128};
 
 use super::Result;
+#[cfg(feature = "cache-diagnostics")]
+use crate::cache::{CacheKind, CacheMismatch};
 use crate::{
+    alignment::MisalignedItem,
     annotation::{
         AnnotationItem, AnnotationSetItem, AnnotationSetRefList, AnnotationsDirectoryItem,
+        ClassAnnotations, DirectoryLoader,
     },
-    class::{Class, ClassDataItem, ClassDefItem, ClassDefItemIter},
-    code::{CodeItem, DebugInfoItem},
-    encoded_value::{EncodedArray, EncodedValue},
+    bounds::{bounded, ItemBoundaries},
+    cache::{Bypass, Cache, CacheConfig, CacheStats},
+    class::{Class, ClassDataItem, ClassDefItem, ClassDefItemIter, ClassHandle, LenientClassData},
+    class_kind::{self, ClassHeuristics, ClassKind, ClassKindMask},
+    code::CodeItem,
+    diagnostic::{Diagnostic, DiagnosticCode, Diagnostics, Severity, Span},
+    encoded_item::EncodedTypeAddrPair,
+    encoded_value::{self, DexArraySource, EncodedArray, EncodedValue},
     error::{self, Error},
-    field::{EncodedField, Field, FieldId, FieldIdItem},
-    jtype::{Type, TypeId},
+    field::{EncodedField, Field, FieldId, FieldIdItem, FieldRef},
+    jtype::{Type, TypeId, TypeKind},
+    leb::{LebScanner, LebSite},
+    limits::ParserLimits,
     method::{
-        EncodedMethod, Method, MethodHandleId, MethodHandleItem, MethodId, MethodIdItem, ProtoId,
-        ProtoIdItem,
+        EncodedMethod, Method, MethodHandleId, MethodHandleItem, MethodId, MethodIdItem, MethodRef,
+        Proto, ProtoId, ProtoIdItem,
     },
+    operation::OperationOptions,
     search::Section,
-    source::Source,
-    string::{DexString, StringId, Strings, StringsIter},
-    ubyte, uint, ulong, ushort, utils, Endian, ENDIAN_CONSTANT, NO_INDEX, REVERSE_ENDIAN_CONSTANT,
+    sharing::{Owner, OwnerRecorder, SharedItemKind, SharedOffsetsReport},
+    snapshot::{SnapshotKinds, Snapshots},
+    source::{DexSource, Source},
+    string::{DexStr, DexString, StringId, Strings, StringsIter},
+    sync::{OnceCell, PerThread, RefCell, Shared},
+    timing::{Category, Timers},
+    ubyte, uint, ulong, ushort, utils,
+    validate::{self, DescriptorError, InvalidName},
+    Endian, ENDIAN_CONSTANT, NO_INDEX, REVERSE_ENDIAN_CONSTANT,
 };
 use std::path::Path;
 
+#[cfg(feature = "debug-info")]
+use crate::code::DebugInfoItem;
+#[cfg(feature = "decoder")]
+use crate::opcode::IndexOperand;
+#[cfg(feature = "analysis")]
+use crate::{
+    clinit::ClassInitializer,
+    features::{DexFeature, FeatureLocation, FeatureReport},
+    fingerprint::{fixed_item_size, FingerprintRecorder, ProducerFingerprint},
+    indicator::{self, Indicator, IndicatorOptions},
+    marker::{CompilerMarker, MARKER_PREFIX},
+    opcode::{self, InstructionKind, Instructions},
+    permission::PermissionMatcher,
+    sharing::DuplicateAnnotationSets,
+    usage::{
+        self, AnnotationTarget, AnnotationTypeUsage, AnnotationUsage, ConstStringSite, ElementUse,
+        MemberUsage, Retention, StringUsages, UsageCounts,
+    },
+    well_known,
+};
+#[cfg(feature = "analysis")]
+use std::collections::BTreeMap;
+
 /// Dex file header
 #[derive(Debug, Pread, CopyGetters)]
 #[get_copy = "pub"]
@@ -93,10 +141,100 @@ pub struct Header {
     data_off: uint,
 }
 
+/// A version of the dex format, from the magic of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DexVersion {
+    /// The version every reader supports.
+    V035,
+    /// Adds default and static interface methods, Android 7.0.
+    V037,
+    /// Adds `invoke-polymorphic`, `invoke-custom` and call sites, Android 8.0.
+    V038,
+    /// Adds `const-method-handle` and `const-method-type`, Android 9.
+    V039,
+    /// Allows more characters in simple names, Android 11.
+    V040,
+    /// Allows several dex files in a container, Android 15.
+    V041,
+}
+
+impl DexVersion {
+    /// All the versions, oldest first.
+    pub const ALL: [DexVersion; 6] = [
+        DexVersion::V035,
+        DexVersion::V037,
+        DexVersion::V038,
+        DexVersion::V039,
+        DexVersion::V040,
+        DexVersion::V041,
+    ];
+
+    /// The three digits of the version in the magic, such as `035`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DexVersion::V035 => "035",
+            DexVersion::V037 => "037",
+            DexVersion::V038 => "038",
+            DexVersion::V039 => "039",
+            DexVersion::V040 => "040",
+            DexVersion::V041 => "041",
+        }
+    }
+}
+
+impl fmt::Display for DexVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl Header {
+    /// The version of the format in the magic. Fails with `Error::MalFormed` if the magic
+    /// isn't `dex\n`, a known version and a NUL byte. The file is parsed whatever its
+    /// version, so this is where an unknown one shows.
+    pub fn dex_version(&self) -> Result<DexVersion> {
+        let (prefix, version) = self.magic.split_at(4);
+        DexVersion::ALL
+            .iter()
+            .copied()
+            .find(|known| {
+                prefix == b"dex\n" && *version == [known.as_str().as_bytes(), b"\0"].concat()
+            })
+            .ok_or_else(|| {
+                Error::MalFormed(format!(
+                    "Unknown dex magic {:?}",
+                    String::from_utf8_lossy(&self.magic)
+                ))
+            })
+    }
+
     fn data_section(&self) -> Range<uint> {
         self.data_off..self.data_off + self.data_size
     }
+
+    /// Warnings for the id sections which are empty but have a nonzero offset. The offset
+    /// of an empty section is ignored.
+    fn empty_section_warnings(&self) -> Vec<Diagnostic> {
+        [
+            ("string_ids", self.string_ids_size, self.string_ids_off),
+            ("type_ids", self.type_ids_size, self.type_ids_off),
+            ("proto_ids", self.proto_ids_size, self.proto_ids_off),
+            ("field_ids", self.field_ids_size, self.field_ids_off),
+            ("method_ids", self.method_ids_size, self.method_ids_off),
+            ("class_defs", self.class_defs_size, self.class_defs_off),
+        ]
+        .iter()
+        .filter(|(_, size, offset)| *size == 0 && *offset != 0)
+        .map(|(section, _, offset)| {
+            Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::EmptySection,
+                format!("{} section is empty but has offset {}", section, offset),
+            )
+            .with_span(Span::new(*offset, 0))
+        })
+        .collect()
+    }
 }
 
 /// Wrapper type for Dex
@@ -110,6 +248,12 @@ pub(crate) struct DexInner {
     map_list: MapList,
     #[get_copy = "pub"]
     endian: Endian,
+    /// Problems found while reading the file which don't prevent using it.
+    #[get = "pub"]
+    diagnostics: Diagnostics,
+    /// Included in the logs, see `DexReaderBuilder::log_label`.
+    #[get = "pub(crate)"]
+    log_label: Option<Shared<str>>,
 }
 
 impl DexInner {
@@ -161,7 +305,7 @@ impl DexInner {
         self.header.type_ids_size
     }
 
-    fn data_section(&self) -> Range<uint> {
+    pub(crate) fn data_section(&self) -> Range<uint> {
         self.header.data_section()
     }
 
@@ -174,52 +318,100 @@ impl DexInner {
     }
 }
 
-// TODO: this should be try_from_dex
-impl<'a> ctx::TryFromCtx<'a, ()> for DexInner {
-    type Error = error::Error;
-    type Size = usize;
-
-    fn try_from_ctx(source: &'a [u8], _: ()) -> Result<(Self, Self::Size)> {
+impl DexInner {
+    /// Reads the header and the map list of the file, after verifying its integrity as
+    /// `verify` requires.
+    fn read<T: DexSource>(
+        source: &Source<T>,
+        verify: VerifyMode,
+        log_label: Option<&str>,
+    ) -> Result<Self> {
         if source.len() <= 44 {
-            debug!("malformed dex: size < minimum header size");
+            dex_debug!(log_label, target: "initialization", "malformed dex: size < minimum header size");
             return Err(Error::MalFormed("Invalid dex file".to_string()));
         }
-        let endian_tag = &source[40..44];
+        let endian_tag = source.slice(40..44);
         let endian = match (endian_tag[0], endian_tag[1], endian_tag[2], endian_tag[3]) {
             ENDIAN_CONSTANT => scroll::BE,
             REVERSE_ENDIAN_CONSTANT => scroll::LE,
             _ => return Err(error::Error::MalFormed("Bad endian tag".to_string())),
         };
-        let header = source.pread_with::<Header>(0, endian)?;
+        let header: Header = source.pread_with(0, endian)?;
         if !header.data_section().contains(&header.map_off) {
             return Err(error::Error::BadOffset(
                 header.map_off as usize,
                 "map_list not in data section".to_string(),
             ));
         }
-        let found = header.checksum();
-        let computed = adler32::adler32(BufReader::new(&source[12..]))?;
-        if computed != found {
+        if (source.len() as u64) < u64::from(header.file_size) {
             return Err(Error::MalFormed(format!(
-                "File corrupted, adler32 checksum doesn't match: computed: {}, found: {}",
-                computed, found
+                "File truncated, header declares {} bytes but found {}",
+                header.file_size,
+                source.len()
             )));
         }
+        if verify != VerifyMode::None {
+            let found = header.checksum();
+            let computed = compute_checksum(source);
+            if computed != found {
+                return Err(Error::MalFormed(format!(
+                    "File corrupted, adler32 checksum doesn't match: computed: {}, found: {}",
+                    computed, found
+                )));
+            }
+        }
+        #[cfg(feature = "signature")]
+        if verify == VerifyMode::Full {
+            let found = header.signature();
+            let computed = compute_signature(source);
+            if computed != found {
+                return Err(Error::MalFormed(format!(
+                    "File corrupted, SHA-1 signature doesn't match: computed: {}, found: {}",
+                    hex(&computed),
+                    hex(&found)
+                )));
+            }
+        }
 
-        let map_list = source.pread_with(header.map_off as usize, endian)?;
-        debug!(target: "initialization", "header: {:?}, endian-ness: {:?}", header, endian);
-        debug!(target: "initialization", "map_list: {:?}", map_list);
-        Ok((
-            DexInner {
-                header,
-                map_list,
-                endian,
-            },
-            0,
-        ))
+        let map_list: MapList = source.pread_with(header.map_off as usize, endian)?;
+        dex_debug!(log_label, target: "initialization", "header: {:?}, endian-ness: {:?}", header, endian);
+        dex_debug!(log_label, target: "initialization", "map_list: {:?}", map_list);
+        let mut diagnostics: Diagnostics = header.empty_section_warnings().into_iter().collect();
+        diagnostics.extend(map_list.empty_item_warnings());
+        diagnostics.extend(map_list.misaligned_item_warnings());
+        for diagnostic in &diagnostics {
+            dex_warn!(log_label, target: "initialization", "{}", diagnostic.message());
+        }
+        Ok(DexInner {
+            header,
+            map_list,
+            endian,
+            diagnostics,
+            log_label: log_label.map(Shared::from),
+        })
     }
 }
 
+/// Adler32 checksum of the file from offset 12, after the magic and the checksum.
+fn compute_checksum<T: DexSource>(source: &Source<T>) -> uint {
+    let mut checksum = adler32::RollingAdler32::new();
+    source.for_each_piece(12..source.len(), |piece| checksum.update_buffer(piece));
+    checksum.hash()
+}
+
+/// SHA-1 signature of the file from offset 32, after the magic, checksum and signature.
+#[cfg(feature = "signature")]
+fn compute_signature<T: DexSource>(source: &Source<T>) -> [ubyte; 20] {
+    let mut signature = sha1_smol::Sha1::new();
+    source.for_each_piece(32..source.len(), |piece| signature.update(piece));
+    signature.digest().bytes()
+}
+
+#[cfg(feature = "signature")]
+fn hex(bytes: &[ubyte]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 /// List of the entire contents of a file, in order. A given type must appear at most
 /// once in a map, entries must be ordered by initial offset and must not overlap.
 #[derive(Debug)]
@@ -244,14 +436,66 @@ impl<'a> ctx::TryFromCtx<'a, Endian> for MapList {
 }
 
 impl MapList {
-    /// Returns the `MapItem` corresponding to the `ItemType`.
+    /// Returns the `MapItem` corresponding to the `ItemType`. Items with a size of `0`
+    /// are treated as absent, whatever their offset.
     pub fn get(&self, item_type: ItemType) -> Option<MapItem> {
         self.map_items
             .iter()
-            .find(|map_item| map_item.item_type == item_type)
+            .find(|map_item| map_item.item_type == item_type && map_item.size != 0)
             .cloned()
     }
 
+    /// Warnings for the items which are empty but have a nonzero offset.
+    fn empty_item_warnings(&self) -> Vec<Diagnostic> {
+        self.map_items
+            .iter()
+            .filter(|map_item| map_item.size == 0 && map_item.offset != 0)
+            .map(|map_item| {
+                Diagnostic::new(
+                    Severity::Warning,
+                    DiagnosticCode::EmptyMapItem,
+                    format!(
+                        "map_list item {} is empty but has offset {}",
+                        map_item.item_type, map_item.offset
+                    ),
+                )
+                .with_span(Span::new(map_item.offset, 0))
+            })
+            .collect()
+    }
+
+    /// Warnings for the items which don't start at an offset aligned for their type.
+    fn misaligned_item_warnings(&self) -> Vec<Diagnostic> {
+        self.misaligned_items()
+            .map(|(item_type, offset)| {
+                Diagnostic::new(
+                    Severity::Warning,
+                    DiagnosticCode::MisalignedItem,
+                    format!(
+                        "map_list item {:?} is misaligned at offset {}",
+                        item_type, offset
+                    ),
+                )
+                .with_span(Span::at(offset))
+            })
+            .collect()
+    }
+
+    /// The type and offset of the items which don't start at an offset aligned for their
+    /// type. The alignment of unknown types is not checked.
+    fn misaligned_items(&self) -> impl Iterator<Item = (ItemType, uint)> + '_ {
+        self.map_items
+            .iter()
+            .filter(|map_item| map_item.size != 0)
+            .filter_map(|map_item| Some((map_item.item_type.known()?, map_item.offset)))
+            .filter(|(item_type, offset)| !item_type.is_aligned(*offset))
+    }
+
+    /// The items of the map, in the order they are listed.
+    pub fn items(&self) -> &[MapItem] {
+        &self.map_items
+    }
+
     /// Returns the offset of the item corresponding to the `ItemType`.
     pub fn get_offset(&self, item_type: ItemType) -> Option<uint> {
         self.get(item_type).map(|map_item| map_item.offset)
@@ -261,10 +505,25 @@ impl MapList {
     pub fn get_len(&self, item_type: ItemType) -> Option<uint> {
         self.get(item_type).map(|map_item| map_item.size)
     }
+
+    /// Returns the extent of the section of `ItemType`. The section ends where the next
+    /// section starts, or at `end` if it is the last one.
+    pub(crate) fn section(&self, item_type: ItemType, end: uint) -> Option<Range<uint>> {
+        let start = self.get_offset(item_type)?;
+        let section_end = self
+            .map_items
+            .iter()
+            .filter(|map_item| map_item.size != 0)
+            .map(|map_item| map_item.offset)
+            .filter(|offset| *offset > start)
+            .min()
+            .unwrap_or(end);
+        Some(start..section_end.min(end).max(start))
+    }
 }
 
 /// ItemType that appear in MapList
-#[derive(FromPrimitive, Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(FromPrimitive, Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum ItemType {
     Header = 0x0,
     StringIdItem = 0x1,
@@ -286,6 +545,81 @@ pub enum ItemType {
     AnnotationItem = 0x2004,
     EncodedArrayItem = 0x2005,
     AnnotationsDirectoryItem = 0x2006,
+    HiddenapiClassDataItem = 0xF000,
+}
+
+impl ItemType {
+    /// Alignment of the items of the type, in bytes. The items read as a sequence of
+    /// 32-bit values must be 4 byte aligned, the others have no alignment.
+    pub fn alignment(self) -> uint {
+        match self {
+            ItemType::ClassDataItem
+            | ItemType::StringDataItem
+            | ItemType::DebugInfoItem
+            | ItemType::AnnotationItem
+            | ItemType::EncodedArrayItem => 1,
+            _ => 4,
+        }
+    }
+
+    /// Returns `true` if an item of the type can start at the offset.
+    pub fn is_aligned(self, offset: uint) -> bool {
+        offset.is_multiple_of(self.alignment())
+    }
+}
+
+/// Type of an item of the `MapList`: one of the `ItemType`s of the format, or a type code
+/// which the format doesn't document, kept as found.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MapItemType {
+    Known(ItemType),
+    Unknown(ushort),
+}
+
+impl MapItemType {
+    /// Returns the type with the code found in the map list.
+    pub fn from_code(code: ushort) -> Self {
+        ItemType::from_u16(code)
+            .map(MapItemType::Known)
+            .unwrap_or(MapItemType::Unknown(code))
+    }
+
+    /// The code of the type in the map list.
+    pub fn code(self) -> ushort {
+        match self {
+            MapItemType::Known(item_type) => item_type as ushort,
+            MapItemType::Unknown(code) => code,
+        }
+    }
+
+    /// Returns the `ItemType`, unless the type is unknown.
+    pub fn known(self) -> Option<ItemType> {
+        match self {
+            MapItemType::Known(item_type) => Some(item_type),
+            MapItemType::Unknown(_) => None,
+        }
+    }
+}
+
+impl From<ItemType> for MapItemType {
+    fn from(item_type: ItemType) -> Self {
+        MapItemType::Known(item_type)
+    }
+}
+
+impl PartialEq<ItemType> for MapItemType {
+    fn eq(&self, other: &ItemType) -> bool {
+        *self == MapItemType::Known(*other)
+    }
+}
+
+impl fmt::Display for MapItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapItemType::Known(item_type) => write!(f, "{:?}", item_type),
+            MapItemType::Unknown(code) => write!(f, "Unknown({:#06x})", code),
+        }
+    }
 }
 
 /// Single item of the MapList.
@@ -293,7 +627,7 @@ pub enum ItemType {
 #[get_copy = "pub"]
 pub struct MapItem {
     /// Type of the current item
-    item_type: ItemType,
+    item_type: MapItemType,
     /// Count of the number of items to be found at the indicated offset
     size: uint,
     /// Offset from the start of the file to the current item type
@@ -306,10 +640,7 @@ impl<'a> ctx::TryFromCtx<'a, Endian> for MapItem {
 
     fn try_from_ctx(source: &'a [u8], endian: Endian) -> Result<(Self, Self::Size)> {
         let offset = &mut 0;
-        let item_type: ushort = source.gread_with(offset, endian)?;
-        let item_type = ItemType::from_u16(item_type).ok_or_else(|| {
-            Error::InvalidId(format!("Invalid item type in map_list: {}", item_type))
-        })?;
+        let item_type = MapItemType::from_code(source.gread_with(offset, endian)?);
         let _: ushort = source.gread_with(offset, endian)?;
         let size: uint = source.gread_with(offset, endian)?;
         let item_offset: uint = source.gread_with(offset, endian)?;
@@ -324,32 +655,381 @@ impl<'a> ctx::TryFromCtx<'a, Endian> for MapItem {
     }
 }
 
+/// Lists of an annotations directory that map member ids to annotation sets.
+#[derive(Clone, Copy)]
+enum DirectoryList {
+    Fields,
+    Methods,
+}
+
+/// An entry of the field or method list of an annotations directory.
+#[derive(Debug, Pread)]
+struct DirectoryEntry {
+    idx: uint,
+    annotations_off: uint,
+}
+
+/// Indexes whose build was interrupted, with the position of the next class_def to add.
+#[derive(Default)]
+struct PartialIndexes {
+    class_defs_by_type: Option<(HashMap<TypeId, uint>, uint)>,
+    class_names: Option<(HashMap<String, TypeId>, uint)>,
+    hierarchy: Option<(HashMap<uint, ClassEdges>, uint)>,
+}
+
+/// The type, superclass and interfaces of a class_def.
+struct ClassEdges {
+    class_idx: TypeId,
+    superclass: Option<TypeId>,
+    interfaces: Vec<TypeId>,
+}
+
+/// The direct subclasses and implementers of each type, as positions of class_defs in
+/// ascending order, see `Dex::subclasses_of`.
+#[derive(Default)]
+struct Hierarchy {
+    /// Type of the class of each class_def, by position.
+    class_types: Vec<TypeId>,
+    subclasses: HashMap<TypeId, Vec<uint>>,
+    implementers: HashMap<TypeId, Vec<uint>>,
+}
+
+impl Hierarchy {
+    fn new(edges: HashMap<uint, ClassEdges>) -> Self {
+        let mut hierarchy = Hierarchy {
+            class_types: vec![0; edges.len()],
+            ..Default::default()
+        };
+        let mut edges: Vec<_> = edges.into_iter().collect();
+        edges.sort_unstable_by_key(|(position, _)| *position);
+        for (position, edges) in edges {
+            hierarchy.class_types[position as usize] = edges.class_idx;
+            if let Some(superclass) = edges.superclass {
+                hierarchy
+                    .subclasses
+                    .entry(superclass)
+                    .or_default()
+                    .push(position);
+            }
+            for interface in edges.interfaces {
+                let implementers = hierarchy.implementers.entry(interface).or_default();
+                // an interface listed twice
+                if implementers.last() != Some(&position) {
+                    implementers.push(position);
+                }
+            }
+        }
+        hierarchy
+    }
+
+    /// Positions of the class_defs below `type_id`: its direct subclasses or implementers,
+    /// and with `transitive` the classes below those, in ascending order.
+    fn below(&self, type_id: TypeId, implementers: bool, transitive: bool) -> BTreeSet<uint> {
+        let direct = |type_id: &TypeId, implementers: bool| {
+            let edges = if implementers {
+                &self.implementers
+            } else {
+                &self.subclasses
+            };
+            edges.get(type_id).map(Vec::as_slice).unwrap_or_default()
+        };
+        let mut found: BTreeSet<uint> = direct(&type_id, implementers).iter().copied().collect();
+        if !transitive {
+            return found;
+        }
+        let mut pending: Vec<uint> = found.iter().copied().collect();
+        while let Some(position) = pending.pop() {
+            let class_type = self.class_types[position as usize];
+            // the implementers of an interface extending it, and the subclasses of a class
+            // implementing it
+            let below = direct(&class_type, false).iter().chain(if implementers {
+                direct(&class_type, true)
+            } else {
+                &[]
+            });
+            for &position in below {
+                if found.insert(position) {
+                    pending.push(position);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Counts of the items of a file, see `Dex::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct DexStats {
+    /// Number of strings in the string_ids section.
+    strings: uint,
+    /// Number of types in the type_ids section.
+    types: uint,
+    /// Number of prototypes in the proto_ids section.
+    protos: uint,
+    /// Number of fields in the field_ids section.
+    fields: uint,
+    /// Number of methods in the method_ids section.
+    methods: uint,
+    /// Number of classes in the class_defs section.
+    class_defs: uint,
+    /// Number of distinct code items used by the methods of the classes.
+    code_items: usize,
+    /// Total number of 16-bit code units in the instructions of the code items.
+    code_units: ulong,
+    /// Number of classes that declare at least one native method.
+    classes_with_native_methods: usize,
+}
+
 /// Represents a Dex file
 pub struct Dex<T> {
     /// Source from which this Dex file is loaded from.
     pub(crate) source: Source<T>,
     /// Items in string_ids section are cached here.
     pub(crate) strings: Strings<T>,
-    pub(crate) inner: DexInner,
+    pub(crate) inner: Shared<DexInner>,
+    /// Boundaries of the items in the annotation_item section.
+    pub(crate) annotation_items: Shared<ItemBoundaries>,
+    /// Boundaries of the items in the encoded_array_item section.
+    pub(crate) encoded_arrays: Shared<ItemBoundaries>,
+    /// Types are cached here, by id.
+    types: Cache<TypeId, Type>,
+    /// Annotations directories are cached here, by offset.
+    annotations_directories: Cache<uint, Shared<AnnotationsDirectoryItem>>,
+    /// Types found by `find_class_by_descriptor` are cached here, by descriptor, with
+    /// `None` for the descriptors of no type.
+    descriptors: Cache<String, Option<TypeId>>,
+    /// Reads annotations directories for classes with lazily loaded annotations.
+    directory_loader: OnceCell<DirectoryLoader>,
+    /// Read the annotations of a class when the class is loaded.
+    eager_annotations: bool,
+    /// Fail reading misaligned items instead of logging a warning.
+    strict_alignment: bool,
+    /// Fail reading types with an invalid descriptor instead of reporting a diagnostic.
+    strict_descriptors: bool,
+    /// Keep the members of a class read before a corrupt one, see `Class::parse_completeness`.
+    lenient_class_data: bool,
+    /// Record the spans of the static values of classes, see `Class::static_values_span`.
+    track_spans: bool,
+    /// Load methods without their code, see `DexReaderBuilder::skip_code`.
+    skip_code: bool,
+    /// Read code items without their debug info, see `DexReaderBuilder::skip_debug_info`.
+    #[cfg(feature = "debug-info")]
+    skip_debug_info: bool,
+    /// Load classes without their annotations, see `DexReaderBuilder::skip_annotations`.
+    skip_annotations: bool,
+    /// Limits checked while parsing items.
+    pub(crate) limits: ParserLimits,
+    /// Index of the class_def of each class defined in the file, by `TypeId`.
+    class_defs_by_type: Shared<OnceCell<HashMap<TypeId, uint>>>,
+    /// Type of each class defined in the file, by descriptor. Only built on request.
+    class_names: Shared<OnceCell<HashMap<String, TypeId>>>,
+    /// Subclasses and implementers of each type.
+    hierarchy: Shared<OnceCell<Hierarchy>>,
+    /// All the proto ids, in order. Only read on request.
+    proto_ids: Shared<OnceCell<Vec<ProtoIdItem>>>,
+    /// The entries of the indexes whose build was interrupted.
+    partial_indexes: Shared<RefCell<PartialIndexes>>,
+    /// Problems found after reading the file, while building the indexes.
+    found_diagnostics: Shared<RefCell<Diagnostics>>,
+    /// Deadline of the operation running, see `Dex::run_with`.
+    deadline: Shared<PerThread<Option<Instant>>>,
+    /// Makes the caches act as if empty, see `Dex::with_cache_bypassed`.
+    cache_bypass: Bypass,
+    /// Copies of the items read from a source which can change, see
+    /// `DexReaderBuilder::snapshot_on_access`.
+    snapshots: Snapshots,
+    pub(crate) timers: Timers,
+}
+
+/// Cloning a `Dex` is cheap: the clone shares the source, the caches and the indexes with
+/// the original, so items read or indexes built through one of them are reused by the
+/// others, and every clone sees the same items. Clones also share the diagnostics, the
+/// deadline of `Dex::run_with` and the bypass of `Dex::with_cache_bypassed`, which apply
+/// to the clones used by the thread which set them, and `Dex::clear_caches` empties the
+/// caches of all of them. Each clone keeps the shared parts alive, so dropping the
+/// original leaves its clones usable.
+impl<T> Clone for Dex<T> {
+    fn clone(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            strings: self.strings.clone(),
+            inner: self.inner.clone(),
+            annotation_items: self.annotation_items.clone(),
+            encoded_arrays: self.encoded_arrays.clone(),
+            types: self.types.clone(),
+            annotations_directories: self.annotations_directories.clone(),
+            descriptors: self.descriptors.clone(),
+            directory_loader: self.directory_loader.clone(),
+            eager_annotations: self.eager_annotations,
+            strict_alignment: self.strict_alignment,
+            strict_descriptors: self.strict_descriptors,
+            lenient_class_data: self.lenient_class_data,
+            track_spans: self.track_spans,
+            skip_code: self.skip_code,
+            #[cfg(feature = "debug-info")]
+            skip_debug_info: self.skip_debug_info,
+            skip_annotations: self.skip_annotations,
+            limits: self.limits,
+            class_defs_by_type: self.class_defs_by_type.clone(),
+            class_names: self.class_names.clone(),
+            hierarchy: self.hierarchy.clone(),
+            proto_ids: self.proto_ids.clone(),
+            partial_indexes: self.partial_indexes.clone(),
+            found_diagnostics: self.found_diagnostics.clone(),
+            deadline: self.deadline.clone(),
+            cache_bypass: self.cache_bypass.clone(),
+            snapshots: self.snapshots.clone(),
+            timers: self.timers.clone(),
+        }
+    }
 }
 
 impl<T> Dex<T>
 where
-    T: AsRef<[u8]>,
+    T: DexSource,
 {
     /// The Header section
     pub fn header(&self) -> &Header {
         self.inner.header()
     }
 
+    /// The adler32 checksum stored in the header, which ART profiles use to identify the
+    /// file.
+    pub fn checksum(&self) -> uint {
+        self.header().checksum()
+    }
+
+    /// The adler32 checksum of the file from offset 12, after the magic and the checksum,
+    /// to compare with the stored `checksum` when logging a mismatch. Hashes the whole
+    /// file on each call.
+    pub fn compute_checksum(&self) -> uint {
+        compute_checksum(&self.source)
+    }
+
+    /// Returns `true` if the adler32 checksum of the file from offset 12 matches
+    /// `Header::checksum`, see `compute_checksum`. Files read with `VerifyMode::Checksum`,
+    /// the default, always match; this is for files read with `VerifyMode::None`.
+    pub fn verify_checksum(&self) -> Result<bool> {
+        Ok(self.compute_checksum() == self.header().checksum())
+    }
+
+    /// The SHA-1 signature stored in the header, which identifies the file unless it was
+    /// modified without being signed again, see `verify_signature`.
+    pub fn signature(&self) -> &[ubyte; 20] {
+        &self.header().signature
+    }
+
+    /// Returns `true` if the SHA-1 signature of the file from offset 32 matches
+    /// `Header::signature`. Tools that modify a file without signing it again, as some
+    /// repackagers do, fix the checksum but leave a stale signature, which this detects.
+    /// Hashes the whole file on each call.
+    #[cfg(feature = "signature")]
+    pub fn verify_signature(&self) -> Result<bool> {
+        Ok(compute_signature(&self.source) == self.header().signature())
+    }
+
+    /// The map list, read from `Header::map_off` when the file is opened. Items of unknown
+    /// types are kept as `MapItemType::Unknown`.
     pub fn map_list(&self) -> &MapList {
         &self.inner.map_list
     }
 
+    /// The label included in the logs of the file, see `DexReaderBuilder::log_label`.
+    pub fn log_label(&self) -> Option<&str> {
+        self.inner.log_label.as_deref()
+    }
+
+    /// Problems found in the file which don't prevent using it: the ones found while
+    /// reading it, such as empty sections with a nonzero offset, then the ones found since
+    /// while building its indexes, such as classes defined twice.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut diagnostics = self.inner.diagnostics().clone();
+        diagnostics.extend(self.found_diagnostics.borrow().iter().cloned());
+        diagnostics
+    }
+
+    /// The messages of `Dex::diagnostics`.
+    pub fn warnings(&self) -> Vec<String> {
+        self.diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.message().clone())
+            .collect()
+    }
+
+    /// Time spent parsing each kind of item so far.
+    #[cfg(feature = "parse-timing")]
+    pub fn parse_timings(&self) -> crate::ParseTimings {
+        self.timers.timings()
+    }
+
     pub(crate) fn is_offset_in_data_section(&self, offset: uint) -> bool {
         self.inner.data_section().contains(&offset)
     }
 
+    /// Checks that an item of the type can start at the offset, see
+    /// `DexReaderBuilder::strict_alignment`.
+    pub(crate) fn check_alignment(&self, item_type: ItemType, offset: uint) -> Result<()> {
+        if item_type.is_aligned(offset) {
+            return Ok(());
+        }
+        if self.strict_alignment {
+            return Err(Error::Misaligned(item_type, offset));
+        }
+        dex_warn!(self.log_label(), target: "alignment", "{:?} at offset {} is misaligned", item_type, offset);
+        Ok(())
+    }
+
+    /// Offset past which no item of the data section can extend.
+    fn data_section_end(&self) -> uint {
+        self.inner.data_section().end.min(self.source.len() as uint)
+    }
+
+    /// Start offsets of all the annotation items, gathered from the annotation sets.
+    fn annotation_item_offsets(&self) -> Result<Vec<uint>> {
+        let mut offsets = Vec::new();
+        let map_list = self.map_list();
+        if let (Some(sets_off), Some(sets_len)) = (
+            map_list.get_offset(ItemType::AnnotationSetItem),
+            map_list.get_len(ItemType::AnnotationSetItem),
+        ) {
+            let source = &self.source;
+            let endian = self.get_endian();
+            let offset = &mut (sets_off as usize);
+            for _ in 0..sets_len {
+                *offset = (*offset + 3) & !3;
+                let size: uint = source.gread_with(offset, endian)?;
+                for _ in 0..size {
+                    offsets.push(source.gread_with(offset, endian)?);
+                }
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// Start offsets of all the encoded arrays, referenced by the class definitions
+    /// and the call site ids.
+    fn encoded_array_offsets(&self) -> Result<Vec<uint>> {
+        let mut offsets = Vec::new();
+        for class_def in self.class_defs() {
+            let static_values_off = class_def?.static_values_off;
+            if static_values_off != 0 {
+                offsets.push(static_values_off);
+            }
+        }
+        let map_list = self.map_list();
+        if let (Some(call_sites_off), Some(call_sites_len)) = (
+            map_list.get_offset(ItemType::CallSiteIdItem),
+            map_list.get_len(ItemType::CallSiteIdItem),
+        ) {
+            let offset = &mut (call_sites_off as usize);
+            for _ in 0..call_sites_len {
+                offsets.push(self.source.gread_with(offset, self.get_endian())?);
+            }
+        }
+        Ok(offsets)
+    }
+
     /// Source file name in which a class is defined.
     pub fn get_source_file(&self, file_id: StringId) -> Result<Option<DexString>> {
         Ok(if file_id == NO_INDEX {
@@ -370,6 +1050,22 @@ where
         self.strings.get(string_id)
     }
 
+    /// Returns the MUTF-8 bytes of the string represented by the given id, without decoding
+    /// them or allocating when the source stores them contiguously, as memory mapped and
+    /// in-memory files do. Neither reads nor fills the string cache, which makes it suited
+    /// to scanning all the strings of a file, for instance for a substring.
+    pub fn get_str_raw(&self, string_id: StringId) -> Result<DexStr<'_>> {
+        self.strings.get_raw(string_id)
+    }
+
+    /// Returns the string represented by the given id, with each sequence of bytes which
+    /// is not valid MUTF-8 replaced with U+FFFD, where `Dex::get_string` fails. Neither
+    /// reads nor fills the string cache.
+    pub fn get_string_lossy(&self, string_id: StringId) -> Result<DexString> {
+        let raw = self.get_str_raw(string_id)?;
+        Ok(DexString::from(raw.to_str_lossy().into_owned()))
+    }
+
     /// Returns the `Type` corresponding to the descriptor.
     pub fn get_type_from_descriptor(&self, descriptor: &str) -> Result<Option<Type>> {
         if let Some(string_id) = self.strings.get_id(descriptor)? {
@@ -382,19 +1078,51 @@ where
 
     /// Returns the `Type` represented by the give type_id.
     pub fn get_type(&self, type_id: TypeId) -> Result<Type> {
-        let max_offset = self.inner.type_ids_offset() + (self.inner.type_ids_len() - 1) * 4;
-        let offset = self.inner.type_ids_offset() + type_id * 4;
-        if offset > max_offset {
+        let _timer = self.timers.start(Category::Types);
+        if type_id >= self.inner.type_ids_len() {
             return Err(Error::InvalidId(format!("Invalid type id: {}", type_id)));
         }
-        let string_id = self
-            .source
-            .as_ref()
-            .pread_with(offset as usize, self.get_endian())?;
-        self.get_string(string_id).map(|type_descriptor| Type {
-            id: type_id,
-            type_descriptor,
-        })
+        if let Some(jtype) = self.types.get(&type_id) {
+            return Ok(jtype);
+        }
+        let offset = self.inner.type_ids_offset() + type_id * 4;
+        let string_id = self.source.pread_with(offset as usize, self.get_endian())?;
+        let type_descriptor = self.get_string(string_id)?;
+        let kind = match TypeKind::parse(&type_descriptor) {
+            Ok(kind) => kind,
+            Err(error) if self.strict_descriptors => {
+                return Err(Error::InvalidDescriptor(
+                    type_id,
+                    type_descriptor.to_string(),
+                    error,
+                ))
+            }
+            Err(error) => {
+                self.report_invalid_descriptor(type_id, &type_descriptor, error);
+                TypeKind::classify(&type_descriptor)
+            }
+        };
+        let jtype = Type::new(type_id, type_descriptor, kind);
+        self.types.put(type_id, jtype.clone());
+        Ok(jtype)
+    }
+
+    /// Reports a type whose descriptor doesn't match the grammar, read anyway with the kind
+    /// given by its shape, see `TypeKind::Invalid`.
+    fn report_invalid_descriptor(
+        &self,
+        type_id: TypeId,
+        descriptor: &DexString,
+        error: DescriptorError,
+    ) {
+        let name = InvalidName::new(ItemType::TypeIdItem, type_id, descriptor.to_string(), error);
+        let diagnostic = Diagnostic::from(name)
+            .with_span(Span::new(self.inner.type_ids_offset() + type_id * 4, 4));
+        dex_warn!(self.log_label(), target: "types", "{}", diagnostic);
+        let mut found = self.found_diagnostics.borrow_mut();
+        if !found.contains(&diagnostic) {
+            found.push(diagnostic);
+        }
     }
 
     pub(crate) fn get_type_id(&self, string_id: StringId) -> Result<Option<TypeId>> {
@@ -408,57 +1136,135 @@ where
             .map(|s| s as TypeId))
     }
 
-    pub(crate) fn type_ids_section(&self) -> Section {
+    pub(crate) fn type_ids_section(&self) -> Section<'_> {
+        if self.inner.type_ids_len() == 0 {
+            return Section::new(Cow::Borrowed(&[]), self.log_label());
+        }
         let type_ids_offset = self.inner.type_ids_offset() as usize;
         let (start, end) = (
             type_ids_offset,
             type_ids_offset + self.inner.type_ids_len() as usize * 4,
         );
-        let type_ids_section = &self.source[start..end];
-        Section::new(type_ids_section)
+        let type_ids_section = self.source.slice(start..end);
+        Section::new(type_ids_section, self.log_label())
     }
 
     #[allow(unused)]
-    pub(crate) fn class_defs_section(&self) -> Section {
+    pub(crate) fn class_defs_section(&self) -> Section<'_> {
+        if self.inner.class_defs_len() == 0 {
+            return Section::new(Cow::Borrowed(&[]), self.log_label());
+        }
         let class_defs_offset = self.inner.class_defs_offset() as usize;
         let (start, end) = (
             class_defs_offset,
             class_defs_offset + self.inner.class_defs_len() as usize * 32,
         );
-        let class_defs_section = &self.source[start..end];
-        Section::new(class_defs_section)
+        let class_defs_section = self.source.slice(start..end);
+        Section::new(class_defs_section, self.log_label())
     }
 
-    pub(crate) fn find_class_by_type(&self, type_id: TypeId) -> Result<Option<Class>> {
-        for class_def in self.class_defs() {
-            let class_def = class_def?;
-            if class_def.class_idx == type_id {
-                return Ok(Some(Class::try_from_dex(self, &class_def)?));
-            }
+    /// Returns the class defining the type, or `None` if the type is not defined in this
+    /// file. The class_def is found with an index by `TypeId` built on first use from the
+    /// class_defs alone, so lookups after the first don't walk the class_defs.
+    pub fn get_class_by_type(&self, type_id: TypeId) -> Result<Option<Class>>
+    where
+        T: 'static,
+    {
+        match self.get_class_def_by_type(type_id)? {
+            Some(class_def) => Ok(Some(Class::try_from_dex(self, &class_def)?)),
+            None => Ok(None),
         }
-        Ok(None)
     }
 
-    /// Finds `Class` by the given class name. The name should be in smali format.
-    /// This method uses binary search to find the class definition using the property
-    /// that the strings, type ids and class defs sections are in sorted.
-    pub fn find_class_by_name(&self, type_descriptor: &str) -> Result<Option<Class>> {
-        let string_id = self.strings.get_id(type_descriptor)?;
-        if string_id.is_none() {
-            debug!(target: "find-class-by-name", "class name: {} not found in strings", type_descriptor);
+    /// Finds `Class` by the given class name. The name may be in Java format, such as
+    /// `com.example.Foo$Inner`, or a descriptor in smali format, such as
+    /// `Lcom/example/Foo$Inner;`. Array types are never defined by a class_def, so their
+    /// names, `int[]` or `[I`, give `None`. See `find_class_by_descriptor`.
+    pub fn find_class_by_name(&self, name: &str) -> Result<Option<Class>>
+    where
+        T: 'static,
+    {
+        if name.ends_with("[]") {
+            dex_debug!(self.log_label(), target: "find-class-by-name", "class name: {} is an array", name);
             return Ok(None);
         }
-        let type_id = self.get_type_id(string_id.unwrap())?;
-        if type_id.is_none() {
-            debug!(target: "find-class-by-name", "no type id found for string id: {}", string_id.unwrap());
+        if name.starts_with('[') || (name.starts_with('L') && name.ends_with(';')) {
+            return self.find_class_by_descriptor(name);
+        }
+        self.find_class_by_descriptor(&format!("L{};", name.replace('.', "/")))
+    }
+
+    /// Finds the class defined by the type with the descriptor, such as
+    /// `Lcom/example/Foo;`. Returns `None` for the types the file refers to without
+    /// defining them, such as the framework classes. See `find_class_by_descriptor`.
+    pub fn find_class(&self, descriptor: &str) -> Result<Option<Class>>
+    where
+        T: 'static,
+    {
+        self.find_class_by_descriptor(descriptor)
+    }
+
+    /// Finds `Class` by the given type descriptor, such as `Lcom/example/Foo;`.
+    /// This method uses binary search to find the type of the class using the property
+    /// that the strings and type ids sections are sorted, and then reads the class_def
+    /// defining it, unless the class name index is built, see
+    /// `EagerSections::CLASS_NAME_INDEX`. The type found for each descriptor is cached,
+    /// see `CacheConfig::descriptors`. Returns `None` if the type doesn't exist or is
+    /// not defined in this file, as for the framework classes the file refers to.
+    pub fn find_class_by_descriptor(&self, type_descriptor: &str) -> Result<Option<Class>>
+    where
+        T: 'static,
+    {
+        if type_descriptor.starts_with('[') {
+            dex_debug!(self.log_label(), target: "find-class-by-name", "descriptor: {} is an array type", type_descriptor);
             return Ok(None);
         }
-        self.find_class_by_type(type_id.unwrap())
+        if let Some(class_names) = self.class_names.get() {
+            return match class_names.get(type_descriptor) {
+                Some(&type_id) => self.get_class_by_type(type_id),
+                None => Ok(None),
+            };
+        }
+        let type_id = match self.descriptors.get(type_descriptor) {
+            Some(type_id) => type_id,
+            None => {
+                let type_id = self.find_type_id(type_descriptor)?;
+                self.descriptors.put(type_descriptor.to_string(), type_id);
+                type_id
+            }
+        };
+        match type_id {
+            Some(type_id) => self.get_class_by_type(type_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds the type with the descriptor by binary search in the strings, then in the
+    /// type_ids.
+    fn find_type_id(&self, type_descriptor: &str) -> Result<Option<TypeId>> {
+        let string_id = match self.strings.get_id(type_descriptor)? {
+            Some(string_id) => string_id,
+            None => {
+                dex_debug!(self.log_label(), target: "find-class-by-name", "class name: {} not found in strings", type_descriptor);
+                return Ok(None);
+            }
+        };
+        let type_id = self.get_type_id(string_id)?;
+        if type_id.is_none() {
+            dex_debug!(self.log_label(), target: "find-class-by-name", "no type id found for string id: {}", string_id);
+        }
+        Ok(type_id)
     }
 
     /// Returns the list of types which represent the interfaces of a class.
     pub fn get_interfaces(&self, offset: uint) -> Result<Vec<Type>> {
-        debug!(target: "interfaces", "interfaces offset: {}", offset);
+        let type_ids = self.get_interface_ids(offset)?;
+        utils::get_types(self, &type_ids)
+    }
+
+    /// Returns the type ids of the interfaces list at `offset`.
+    fn get_interface_ids(&self, offset: uint) -> Result<Vec<ushort>> {
+        dex_debug!(self.log_label(), target: "interfaces", "interfaces offset: {}", offset);
         if offset == 0 {
             return Ok(Default::default());
         }
@@ -468,24 +1274,56 @@ where
                 "Interfaces offset not in data section".to_string(),
             ));
         }
+        self.check_alignment(ItemType::TypeList, offset)?;
         let mut offset = offset as usize;
         let source = &self.source;
         let endian = self.get_endian();
-        let len = source.gread_with::<uint>(&mut offset, endian)?;
-        debug!(target: "interfaces", "interfaces length: {}", len);
+        let len: uint = source.gread_with(&mut offset, endian)?;
+        dex_debug!(self.log_label(), target: "interfaces", "interfaces length: {}", len);
         let offset = &mut offset;
+        Ok(try_gread_vec_with!(source, offset, len, endian))
+    }
+
+    /// Returns the parameter types of the prototype.
+    pub fn get_proto_params(&self, proto_item: &ProtoIdItem) -> Result<Vec<Type>> {
+        let params_off = proto_item.params_off();
+        if params_off == 0 {
+            return Ok(Default::default());
+        }
+        if !self.is_offset_in_data_section(params_off) {
+            return Err(Error::BadOffset(
+                params_off as usize,
+                format!(
+                    "Params offset not in data section for proto_item: {:?}",
+                    proto_item
+                ),
+            ));
+        }
+        self.check_alignment(ItemType::TypeList, params_off)?;
+        let offset = &mut (params_off as usize);
+        let source = &self.source;
+        let endian = self.get_endian();
+        let len: uint = source.gread_with(offset, endian)?;
         let type_ids: Vec<ushort> = try_gread_vec_with!(source, offset, len, endian);
         utils::get_types(self, &type_ids)
     }
 
+    /// Returns the `MethodRef` represented by `MethodId`.
+    pub fn get_method_ref(&self, method_id: MethodId) -> Result<MethodRef> {
+        MethodRef::try_from_dex(self, &self.get_method_item(method_id)?)
+    }
+
+    /// Returns the `FieldRef` represented by `FieldId`.
+    pub fn get_field_ref(&self, field_id: FieldId) -> Result<FieldRef> {
+        FieldRef::try_from_dex(self, &self.get_field_item(field_id)?)
+    }
+
     /// Returns the `FieldIdItem` represented by a `FieldId`.
     pub fn get_field_item(&self, field_id: FieldId) -> Result<FieldIdItem> {
         let offset = ulong::from(self.inner.field_ids_offset()) + field_id * 8;
-        let max_offset = self.inner.field_ids_offset() + (self.inner.field_ids_len() - 1) * 8;
-        let max_offset = ulong::from(max_offset);
-        debug!(target: "field-id-item", "current offset: {}, min_offset: {}, max_offset: {}",
-                offset, self.inner.field_ids_offset(), max_offset);
-        if offset > max_offset {
+        dex_debug!(self.log_label(), target: "field-id-item", "current offset: {}, min_offset: {}, len: {}",
+                offset, self.inner.field_ids_offset(), self.inner.field_ids_len());
+        if field_id >= ulong::from(self.inner.field_ids_len()) {
             return Err(error::Error::InvalidId(format!(
                 "Invalid field id: {}",
                 field_id
@@ -497,27 +1335,31 @@ where
     /// Returns the `ProtoIdItem` represented by `ProtoId`.
     pub fn get_proto_item(&self, proto_id: ProtoId) -> Result<ProtoIdItem> {
         let offset = ulong::from(self.inner.proto_ids_offset()) + proto_id * 12;
-        let max_offset = ulong::from(self.inner.proto_ids_offset())
-            + ulong::from((self.inner.proto_ids_len() - 1) * 12);
-        debug!(target: "proto-item", "proto item current offset: {}, min_offset: {}, max_offset: {}",
-            offset, self.inner.proto_ids_offset(), max_offset);
-        if offset > max_offset {
+        dex_debug!(self.log_label(), target: "proto-item", "proto item current offset: {}, min_offset: {}, len: {}",
+            offset, self.inner.proto_ids_offset(), self.inner.proto_ids_len());
+        if proto_id >= ulong::from(self.inner.proto_ids_len()) {
             return Err(error::Error::InvalidId(format!(
                 "Invalid proto id: {}",
                 proto_id
             )));
         }
+        if let Some(proto_ids) = self.proto_ids.get() {
+            return Ok(proto_ids[proto_id as usize].clone());
+        }
         ProtoIdItem::try_from_dex(self, offset)
     }
 
+    /// Returns the prototype represented by `ProtoId` with its types resolved.
+    pub fn get_proto(&self, proto_id: ProtoId) -> Result<Proto> {
+        Proto::try_from_dex(self, &self.get_proto_item(proto_id)?)
+    }
+
     /// Returns the `MethodIdItem` represented by `MethodId`.
     pub fn get_method_item(&self, method_id: MethodId) -> Result<MethodIdItem> {
         let offset = ulong::from(self.inner.method_ids_offset()) + method_id * 8;
-        let max_offset = self.inner.method_ids_offset() + (self.inner.method_ids_len() - 1) * 8;
-        let max_offset = ulong::from(max_offset);
-        debug!(target: "method-item", "method item current offset: {}, min_offset: {}, max_offset: {}",
-            offset, self.inner.method_ids_offset(), max_offset);
-        if offset > max_offset {
+        dex_debug!(self.log_label(), target: "method-item", "method item current offset: {}, min_offset: {}, len: {}",
+            offset, self.inner.method_ids_offset(), self.inner.method_ids_len());
+        if method_id >= ulong::from(self.inner.method_ids_len()) {
             return Err(error::Error::InvalidId(format!(
                 "Invalid method id: {}",
                 method_id
@@ -526,39 +1368,55 @@ where
         MethodIdItem::try_from_dex(self, offset, method_id)
     }
 
-    /// Iterator over the strings
-    pub fn strings(&self) -> impl Iterator<Item = Result<DexString>> {
-        StringsIter::new(self.strings.clone(), self.inner.strings_len() as usize)
+    /// Iterator over the strings of the string pool with their ids, in the order of the
+    /// string_ids. The strings are read lazily through the string cache.
+    pub fn strings(&self) -> StringsIter<T> {
+        StringsIter::new(self.strings.clone(), self.strings_count())
+    }
+
+    /// Number of strings in the string pool, as declared by the header: the number of
+    /// items `Dex::strings` yields.
+    pub fn strings_count(&self) -> usize {
+        self.inner.strings_len() as usize
     }
 
     /// Returns a `Field` given its component items.
-    pub fn get_field(
+    pub(crate) fn get_field(
         &self,
         encoded_field: &EncodedField,
         initial_value: Option<EncodedValue>,
-        annotations: AnnotationSetItem,
+        annotations: Shared<ClassAnnotations>,
     ) -> Result<Field> {
         Field::try_from_dex(self, encoded_field, initial_value, annotations)
     }
 
     /// Returns a `Method` given its component items.
-    pub fn get_method(
+    pub(crate) fn get_method(
         &self,
         encoded_method: &EncodedMethod,
-        method_annotations: AnnotationSetItem,
-        parameter_annotations: AnnotationSetRefList,
+        annotations: Shared<ClassAnnotations>,
     ) -> Result<Method> {
-        Method::try_from_dex(
-            self,
-            encoded_method,
-            method_annotations,
-            parameter_annotations,
-        )
+        Method::try_from_dex(self, encoded_method, annotations)
     }
 
     /// Returns the `ClassDataItem` at the given offset.
     pub fn get_class_data(&self, offset: uint) -> Result<Option<ClassDataItem>> {
-        debug!(target: "class-data", "class data offset: {}", offset);
+        dex_debug!(self.log_label(), target: "class-data", "class data offset: {}", offset);
+        if offset == 0 {
+            return Ok(None);
+        }
+        if !self.is_offset_in_data_section(offset) {
+            return Err(Error::BadOffset(
+                offset as usize,
+                "ClassData offset not in data section".to_string(),
+            ));
+        }
+        Ok(Some(self.source.pread_with(offset as usize, self)?))
+    }
+
+    /// Returns the members of the class data at the given offset up to the first corrupt
+    /// one, see `DexReaderBuilder::lenient_class_data`. Only the group sizes must be read.
+    pub(crate) fn get_class_data_lenient(&self, offset: uint) -> Result<Option<LenientClassData>> {
         if offset == 0 {
             return Ok(None);
         }
@@ -571,6 +1429,65 @@ where
         Ok(Some(self.source.pread_with(offset as usize, self)?))
     }
 
+    /// Returns `true` if classes are loaded with the members read before a corrupt one.
+    pub(crate) fn lenient_class_data(&self) -> bool {
+        self.lenient_class_data
+    }
+
+    /// Returns `true` if classes record the spans of their static values.
+    pub(crate) fn track_spans(&self) -> bool {
+        self.track_spans
+    }
+
+    /// Returns `true` if methods are loaded without their code.
+    pub(crate) fn skip_code(&self) -> bool {
+        self.skip_code
+    }
+
+    /// Returns `true` if code items are read without their debug info.
+    #[cfg(feature = "debug-info")]
+    pub(crate) fn skip_debug_info(&self) -> bool {
+        self.skip_debug_info
+    }
+
+    /// Checks that the index operand of an instruction refers to an item of its pool,
+    /// returning `Error::InvalidId` otherwise. See `Instruction::index_operand`.
+    #[cfg(feature = "decoder")]
+    pub fn check_index_operand(&self, operand: IndexOperand) -> Result<()> {
+        let inner = &self.inner;
+        let (kind, index, len) = match operand {
+            IndexOperand::String(id) => ("string", ulong::from(id), inner.strings_len()),
+            IndexOperand::Type(id) => ("type", ulong::from(id), inner.type_ids_len()),
+            IndexOperand::Field(id) => ("field", id, inner.field_ids_len()),
+            IndexOperand::Method(id) => ("method", id, inner.method_ids_len()),
+            IndexOperand::MethodAndProto(method_id, proto_id) => {
+                self.check_index_operand(IndexOperand::Method(method_id))?;
+                ("proto", proto_id, inner.proto_ids_len())
+            }
+            IndexOperand::CallSite(id) => (
+                "call site",
+                ulong::from(id),
+                inner
+                    .map_list()
+                    .get_len(ItemType::CallSiteIdItem)
+                    .unwrap_or(0),
+            ),
+            IndexOperand::MethodHandle(id) => (
+                "method handle",
+                ulong::from(id),
+                inner.method_handles_len().unwrap_or(0),
+            ),
+            IndexOperand::Proto(id) => ("proto", id, inner.proto_ids_len()),
+        };
+        if index >= ulong::from(len) {
+            return Err(Error::InvalidId(format!(
+                "Invalid {} id: {}, the pool has {} items",
+                kind, index, len
+            )));
+        }
+        Ok(())
+    }
+
     /// Returns the `MethodHandleItem` represented by the `MethodHandleId`.
     pub fn get_method_handle_item(
         &self,
@@ -579,11 +1496,10 @@ where
         let err = || Error::InvalidId(format!("Invalid method handle id: {}", method_handle_id));
         let offset = self.inner.method_handles_offset().ok_or_else(err)?;
         let len = self.inner.method_handles_len().ok_or_else(err)?;
-        let max_offset = offset + (len - 1) * 8;
-        let offset = offset + method_handle_id * 8;
-        if offset > max_offset {
+        if method_handle_id >= len {
             return Err(err());
         }
+        let offset = offset + method_handle_id * 8;
         self.source.gread_with(&mut (offset as usize), self)
     }
 
@@ -594,17 +1510,36 @@ where
 
     /// Iterator over the class_defs section.
     pub fn class_defs(&self) -> impl Iterator<Item = Result<ClassDefItem>> + '_ {
-        let defs_len = self.inner.class_defs_len();
-        let defs_offset = self.inner.class_defs_offset();
-        let source = self.source.clone();
-        let endian = self.get_endian();
-        ClassDefItemIter::new(source, defs_offset, defs_len, endian)
+        self.class_defs_from(0)
+    }
+
+    /// Iterator over the class_defs section from the class_def at `position`, which stops
+    /// with `Error::DeadlineExceeded` after the deadline of the operation running.
+    fn class_defs_from(&self, position: uint) -> ClassDefItemIter<T> {
+        ClassDefItemIter::new(
+            self.source.clone(),
+            self.inner.class_defs_offset(),
+            self.inner.class_defs_len(),
+            self.get_endian(),
+            position,
+            self.deadline.get(),
+        )
     }
 
-    /// Iterator over the type_ids section.
-    pub fn types(&self) -> impl Iterator<Item = Result<Type>> + '_ {
+    /// Iterator over the types of the type_ids section with their ids.
+    pub fn types(&self) -> impl ExactSizeIterator<Item = Result<(TypeId, Type)>> + '_ {
         let type_ids_len = self.inner.type_ids_len();
-        (0..type_ids_len).map(move |type_id| self.get_type(type_id))
+        (0..type_ids_len).map(move |type_id| Ok((type_id, self.get_type(type_id)?)))
+    }
+
+    /// Iterator over the prototypes of the proto_ids section with their ids, see
+    /// `get_proto`.
+    pub fn protos(&self) -> impl ExactSizeIterator<Item = Result<(ProtoId, Proto)>> + '_ {
+        let proto_ids_len = self.inner.proto_ids_len();
+        (0..proto_ids_len).map(move |proto_id| {
+            let proto_id = ProtoId::from(proto_id);
+            Ok((proto_id, self.get_proto(proto_id)?))
+        })
     }
 
     /// Iterator over the proto_ids section.
@@ -625,22 +1560,469 @@ where
         (0..method_ids_len).map(move |method_id| self.get_method_item(MethodId::from(method_id)))
     }
 
-    /// Iterator over the method_handles section.
-    pub fn method_handles(&self) -> impl Iterator<Item = Result<MethodHandleItem>> + '_ {
-        let method_handles_len = self.inner.method_handles_len().unwrap_or(0);
-        (0..method_handles_len).map(move |method_handle_id| {
-            self.get_method_handle_item(MethodHandleId::from(method_handle_id))
-        })
+    /// Iterator over the fields of the field_ids section with their class, name and type
+    /// resolved, see `get_field_ref`. This includes the fields referenced but not defined
+    /// in the file, see `is_field_defined`. The fields are resolved lazily, their strings
+    /// through the string cache.
+    pub fn field_refs(&self) -> impl ExactSizeIterator<Item = Result<FieldRef>> + '_ {
+        let field_ids_len = self.inner.field_ids_len();
+        (0..field_ids_len).map(move |field_id| self.get_field_ref(FieldId::from(field_id)))
     }
 
-    /// Iterator over the classes
-    pub fn classes(&self) -> impl Iterator<Item = Result<Class>> + '_ {
-        self.class_defs()
-            .map(move |class_def_item| Class::try_from_dex(&self, &class_def_item?))
+    /// Iterator over the methods of the method_ids section with their class, name and
+    /// prototype resolved, see `get_method_ref`. This includes the methods referenced but
+    /// not defined in the file, such as the framework APIs called, see `is_defined`. The
+    /// methods are resolved lazily, their strings through the string cache.
+    pub fn method_refs(&self) -> impl ExactSizeIterator<Item = Result<MethodRef>> + '_ {
+        let method_ids_len = self.inner.method_ids_len();
+        (0..method_ids_len).map(move |method_id| self.get_method_ref(MethodId::from(method_id)))
+    }
+
+    /// Returns `true` if the method is defined in the file, in the class data of its
+    /// class, and `false` if it is only referenced. A method of a class defined in the file
+    /// can still be only referenced, such as an inherited method called through the class.
+    pub fn is_defined(&self, method_id: MethodId) -> Result<bool> {
+        let method_item = self.get_method_item(method_id)?;
+        let class_data = match self.get_class_data_by_type(method_item.class_idx().into())? {
+            Some(class_data) => class_data,
+            None => return Ok(false),
+        };
+        Ok([class_data.direct_methods(), class_data.virtual_methods()]
+            .iter()
+            .flatten()
+            .any(|methods| {
+                methods
+                    .as_slice()
+                    .iter()
+                    .any(|method| method.method_id == method_id)
+            }))
+    }
+
+    /// Returns `true` if the field is defined in the file, in the class data of its class,
+    /// and `false` if it is only referenced, see `is_defined`.
+    pub fn is_field_defined(&self, field_id: FieldId) -> Result<bool> {
+        let field_item = self.get_field_item(field_id)?;
+        let class_data = match self.get_class_data_by_type(field_item.class_idx().into())? {
+            Some(class_data) => class_data,
+            None => return Ok(false),
+        };
+        Ok([class_data.static_fields(), class_data.instance_fields()]
+            .iter()
+            .flatten()
+            .any(|fields| {
+                fields
+                    .as_slice()
+                    .iter()
+                    .any(|field| field.field_id == field_id)
+            }))
+    }
+
+    /// Iterator over the method_handles section.
+    pub fn method_handles(&self) -> impl Iterator<Item = Result<MethodHandleItem>> + '_ {
+        let method_handles_len = self.inner.method_handles_len().unwrap_or(0);
+        (0..method_handles_len).map(move |method_handle_id| {
+            self.get_method_handle_item(MethodHandleId::from(method_handle_id))
+        })
+    }
+
+    /// Iterator over the classes, one per class definition, in the order of the
+    /// class_defs section. A class which can't be loaded yields an `Error::ClassDef` with
+    /// the index of its class definition, and the iteration continues with the next
+    /// one. There are `class_defs_size` classes, see the header, unless the deadline of an
+    /// operation passes, see `OperationOptions::deadline`: the iteration then ends early,
+    /// after the `Error::DeadlineExceeded`.
+    pub fn classes(&self) -> impl Iterator<Item = Result<Class>> + '_
+    where
+        T: 'static,
+    {
+        self.class_defs_from(0)
+            .enumerate()
+            .map(move |(index, class_def_item)| match class_def_item {
+                Err(error @ Error::DeadlineExceeded(..)) => Err(error),
+                class_def_item => class_def_item
+                    .and_then(|class_def_item| Class::try_from_dex(self, &class_def_item))
+                    .map_err(|error| Error::ClassDef(index as uint, Box::new(error))),
+            })
+    }
+
+    /// Iterator over handles to the classes, one per class definition, which only parse
+    /// the class data of a class when it is loaded, see `ClassHandle`. For indexes which
+    /// filter the classes by their type or flags first. Errors are reported like by
+    /// `classes`.
+    pub fn class_handles(&self) -> impl Iterator<Item = Result<ClassHandle<'_, T>>> + '_
+    where
+        T: 'static,
+    {
+        self.class_defs_from(0)
+            .enumerate()
+            .map(move |(index, class_def_item)| match class_def_item {
+                Err(error @ Error::DeadlineExceeded(..)) => Err(error),
+                class_def_item => class_def_item
+                    .map(|class_def_item| ClassHandle::new(self, index as uint, class_def_item))
+                    .map_err(|error| Error::ClassDef(index as uint, Box::new(error))),
+            })
+    }
+
+    /// Handles to the classes whose superclass is the type with the `descriptor`, such as
+    /// `Landroid/content/BroadcastReceiver;`, and with `transitive` to their subclasses in
+    /// turn, in the order of the class definitions. Only the classes defined in this file
+    /// are found. The classes are found with an index built on first use from the
+    /// class_defs alone, see `EagerSections::HIERARCHY_INDEX`.
+    pub fn subclasses_of(
+        &self,
+        descriptor: &str,
+        transitive: bool,
+    ) -> Result<Vec<ClassHandle<'_, T>>>
+    where
+        T: 'static,
+    {
+        self.classes_below(descriptor, false, transitive)
+    }
+
+    /// Handles to the classes whose interfaces list holds the type with the `descriptor`,
+    /// which includes the interfaces extending it, in the order of the class definitions.
+    /// With `transitive`, the classes implementing it through their superclass or another
+    /// interface are found too. Only the classes defined in this file are found. The
+    /// classes are found with the same index as `Dex::subclasses_of`.
+    pub fn implementers_of(
+        &self,
+        descriptor: &str,
+        transitive: bool,
+    ) -> Result<Vec<ClassHandle<'_, T>>>
+    where
+        T: 'static,
+    {
+        self.classes_below(descriptor, true, transitive)
+    }
+
+    fn classes_below(
+        &self,
+        descriptor: &str,
+        implementers: bool,
+        transitive: bool,
+    ) -> Result<Vec<ClassHandle<'_, T>>>
+    where
+        T: 'static,
+    {
+        let jtype = match self.get_type_from_descriptor(descriptor)? {
+            Some(jtype) => jtype,
+            None => return Ok(Vec::new()),
+        };
+        self.hierarchy()?
+            .below(jtype.id(), implementers, transitive)
+            .into_iter()
+            .map(|position| {
+                let offset = self.inner.class_defs_offset() as usize + position as usize * 32;
+                let class_def = self.source.pread_with(offset, self.get_endian())?;
+                Ok(ClassHandle::new(self, position, class_def))
+            })
+            .collect()
+    }
+
+    /// Parallel iterator over the classes, one per class definition, which loads them on
+    /// the threads of the current rayon pool. The classes and their errors are the ones of
+    /// `classes`, in the same order when collected. The threads share the caches of the
+    /// `Dex`, behind locks, see the `sync` module.
+    ///
+    /// The threads also run with the deadline and the cache bypass of the calling thread.
+    /// Once the deadline has passed, each class not loaded yet is an
+    /// `Error::DeadlineExceeded` with the index of its class definition.
+    #[cfg(feature = "rayon")]
+    pub fn par_classes(
+        &self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = Result<Class>> + '_
+    where
+        T: 'static,
+    {
+        use rayon::prelude::*;
+
+        let len = self.inner.class_defs_len();
+        let deadline = self.deadline.get();
+        let bypassed = self.cache_bypass.is_enabled();
+        (0..len).into_par_iter().map(move |index| {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::DeadlineExceeded(index as usize, len as usize));
+            }
+            let _bypass = if bypassed {
+                Some(self.cache_bypass.enable())
+            } else {
+                None
+            };
+            let class_def_item = self
+                .class_defs_from(index)
+                .next()
+                .expect("class_def index out of bounds");
+            match class_def_item {
+                Err(error @ Error::DeadlineExceeded(..)) => Err(error),
+                class_def_item => class_def_item
+                    .and_then(|class_def_item| Class::try_from_dex(self, &class_def_item))
+                    .map_err(|error| Error::ClassDef(index, Box::new(error))),
+            }
+        })
+    }
+
+    /// Iterator over the classes which can be loaded, leaving out the others, see
+    /// `classes`. For best-effort scans of corrupted or obfuscated files. The only error
+    /// yielded is the `Error::DeadlineExceeded` of a passed deadline, which ends the
+    /// iteration, so that a truncated scan can be told from a complete one.
+    pub fn classes_lossy(&self) -> impl Iterator<Item = Result<Class>> + '_
+    where
+        T: 'static,
+    {
+        self.classes().filter(|class| match class {
+            Ok(_) | Err(Error::DeadlineExceeded(..)) => true,
+            Err(_) => false,
+        })
+    }
+
+    /// Iterator over the classes of the `kinds`, with all the heuristics recognizing
+    /// generated classes, see `classes_filtered_with`. For example
+    /// `classes_filtered(!ClassKindMask::GENERATED)` leaves out the generated classes.
+    pub fn classes_filtered(&self, kinds: ClassKindMask) -> impl Iterator<Item = Result<Class>> + '_
+    where
+        T: 'static,
+    {
+        self.classes_filtered_with(kinds, ClassHeuristics::default())
+    }
+
+    /// Iterator over the classes of the `kinds`, see `Class::kind_with`. The kind of a class
+    /// is found from its class_def and its type before the class is loaded, so the classes
+    /// left out cost no class data parsing. Their annotations are only read when Kotlin
+    /// facades must be told apart from regular classes, that is when `kinds` has only one
+    /// of the two.
+    pub fn classes_filtered_with(
+        &self,
+        kinds: ClassKindMask,
+        heuristics: ClassHeuristics,
+    ) -> impl Iterator<Item = Result<Class>> + '_
+    where
+        T: 'static,
+    {
+        let facades = heuristics.contains(ClassHeuristics::KOTLIN_FACADE)
+            && kinds.contains(ClassKindMask::REGULAR)
+                != kinds.contains(ClassKindMask::KOTLIN_FACADE);
+        self.class_defs().filter_map(move |class_def| {
+            let kind = class_def.and_then(|class_def| {
+                let kind = self.class_def_kind(&class_def, heuristics, facades)?;
+                Ok((class_def, kind))
+            });
+            match kind {
+                Ok((class_def, kind)) if kinds.contains(kind.mask()) => {
+                    Some(Class::try_from_dex(self, &class_def))
+                }
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
+
+    /// Iterator over the classes of the package `package`, such as `com/example`, and of its
+    /// subpackages, in the order of the class_defs section. Errors are reported like by
+    /// `classes`. An empty package yields all the classes.
+    ///
+    /// The pools are sorted, so the types of the package are found by binary search, and
+    /// only the class definitions of these types are loaded: the others cost reading their
+    /// class_def. In a file whose pools are not sorted, some classes may be missed.
+    pub fn classes_in_package(&self, package: &str) -> impl Iterator<Item = Result<Class>> + '_
+    where
+        T: 'static,
+    {
+        let package = package.trim_end_matches('/');
+        let types = if package.is_empty() {
+            Ok(0..self.inner.type_ids_len())
+        } else {
+            self.type_id_range(&format!("L{}/", package))
+        };
+        let (types, error) = match types {
+            Ok(types) => (types, None),
+            Err(error) => (0..0, Some(error)),
+        };
+        error
+            .map(Err)
+            .into_iter()
+            .chain(self.class_defs_from(0).enumerate().filter_map(
+                move |(index, class_def_item)| {
+                    match class_def_item {
+                        Err(error @ Error::DeadlineExceeded(..)) => Some(Err(error)),
+                        Ok(class_def_item) if !types.contains(&class_def_item.class_idx) => None,
+                        class_def_item => Some(
+                            class_def_item
+                                .and_then(|class_def_item| {
+                                    Class::try_from_dex(self, &class_def_item)
+                                })
+                                .map_err(|error| Error::ClassDef(index as uint, Box::new(error))),
+                        ),
+                    }
+                },
+            ))
+    }
+
+    /// The ids of the strings starting with `prefix`, found by binary search as the pool is
+    /// sorted by UTF-16 code units.
+    fn string_id_range(&self, prefix: &str) -> Result<Range<StringId>> {
+        let prefix: Vec<u16> = prefix.encode_utf16().collect();
+        let compare = |string: &DexString| {
+            string
+                .encode_utf16()
+                .take(prefix.len())
+                .cmp(prefix.iter().copied())
+        };
+        let start = self.partition_point(self.inner.strings_len(), |string_id| {
+            Ok(compare(&self.get_string(string_id)?) == Ordering::Less)
+        })?;
+        let end = self.partition_point(self.inner.strings_len(), |string_id| {
+            Ok(compare(&self.get_string(string_id)?) != Ordering::Greater)
+        })?;
+        Ok(start..end)
+    }
+
+    /// The ids of the types whose descriptor starts with `prefix`. The type_ids section is
+    /// sorted by string id, so they follow each other.
+    fn type_id_range(&self, prefix: &str) -> Result<Range<TypeId>> {
+        let strings = self.string_id_range(prefix)?;
+        let types = self.type_ids_section();
+        let endian = self.get_endian();
+        let descriptor = |type_id: TypeId| -> Result<StringId> {
+            Ok(types.as_ref().pread_with(type_id as usize * 4, endian)?)
+        };
+        let len = self.inner.type_ids_len();
+        let start =
+            self.partition_point(len, |type_id| Ok(descriptor(type_id)? < strings.start))?;
+        let end = self.partition_point(len, |type_id| Ok(descriptor(type_id)? < strings.end))?;
+        Ok(start..end)
+    }
+
+    /// The first index below `len` for which `below` is `false`, `below` being `true` for a
+    /// prefix of the indexes and `false` for the rest.
+    fn partition_point<F>(&self, len: uint, below: F) -> Result<uint>
+    where
+        F: Fn(uint) -> Result<bool>,
+    {
+        let (mut start, mut end) = (0, len);
+        while start < end {
+            let mid = start + (end - start) / 2;
+            if below(mid)? {
+                start = mid + 1;
+            } else {
+                end = mid;
+            }
+        }
+        Ok(start)
+    }
+
+    /// Kind of the class of `class_def`, telling Kotlin facades apart from regular classes
+    /// only if `facades`.
+    fn class_def_kind(
+        &self,
+        class_def: &ClassDefItem,
+        heuristics: ClassHeuristics,
+        facades: bool,
+    ) -> Result<ClassKind> {
+        let jtype = self.get_type(class_def.class_idx)?;
+        let access_flags = crate::class::AccessFlags::from_bits_truncate(class_def.access_flags);
+        let kind = class_kind::classify(jtype.type_descriptor(), access_flags, heuristics);
+        if kind == ClassKind::Regular && facades && class_def.annotations_off != 0 {
+            let directory =
+                self.get_shared_annotations_directory_item(class_def.annotations_off)?;
+            if class_kind::kotlin_facade(&directory.class_annotations) {
+                return Ok(ClassKind::KotlinFacade);
+            }
+        }
+        Ok(kind)
+    }
+
+    /// Returns the permissions implied by the framework APIs this `Dex` references, along with
+    /// the methods of this `Dex` calling the APIs which imply each permission. `mapping` is a
+    /// list of (smali descriptor, permission) pairs, such as the data published by Axplorer or
+    /// ARCADE. Descriptors are either exact methods
+    /// (`Landroid/telephony/SmsManager;->sendTextMessage(...)V`), whole classes
+    /// (`Landroid/telephony/SmsManager;`) or class prefixes (`Landroid/bluetooth/*`).
+    ///
+    /// Only references to methods of classes not defined in this `Dex` are considered. The
+    /// permissions are found from the method_ids section alone; the calling methods are then
+    /// found by scanning the code of every method for invoke instructions, in the order of the
+    /// class definitions, each calling method listed once per permission. A permission whose
+    /// APIs are referenced but never invoked, for example only through a method handle, has no
+    /// calling methods.
+    #[cfg(feature = "analysis")]
+    pub fn implied_permissions(
+        &self,
+        mapping: &[(&str, &str)],
+    ) -> Result<BTreeMap<String, Vec<MethodRef>>> {
+        let matcher = PermissionMatcher::new(mapping);
+        let defined_classes = self
+            .class_defs()
+            .map(|class_def| Ok(class_def?.class_idx))
+            .collect::<Result<HashSet<TypeId>>>()?;
+        let mut implied: BTreeMap<String, Vec<MethodRef>> = BTreeMap::new();
+        let mut api_permissions: HashMap<MethodId, Vec<&str>> = HashMap::new();
+        for method_item in self.method_ids() {
+            let method_item = method_item?;
+            if defined_classes.contains(&TypeId::from(method_item.class_idx())) {
+                continue;
+            }
+            let method_ref = MethodRef::try_from_dex(self, &method_item)?;
+            let permissions = matcher.permissions(&method_ref);
+            for permission in &permissions {
+                dex_debug!(self.log_label(), target: "implied-permissions", "{} implies {}", method_ref, permission);
+                implied.entry(permission.to_string()).or_default();
+            }
+            if !permissions.is_empty() {
+                api_permissions.insert(method_ref.id(), permissions);
+            }
+        }
+        if api_permissions.is_empty() {
+            return Ok(implied);
+        }
+
+        let mut attributed: HashSet<(&str, MethodId)> = HashSet::new();
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            let class_data = match self.get_class_data(class_def.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods
+                .iter()
+                .flatten()
+                .flat_map(|methods| methods.inner().iter())
+            {
+                let insns = self.read_insns(*method.code_offset() as usize)?;
+                for instruction in Instructions::new(&insns) {
+                    let instruction = instruction?;
+                    let invoke = match instruction.kind {
+                        InstructionKind::Op(opcode) => {
+                            opcode.flags.contains(opcode::OpcodeFlags::INVOKE)
+                        }
+                        _ => false,
+                    };
+                    let api = match instruction.index_operand() {
+                        Some(IndexOperand::Method(api))
+                        | Some(IndexOperand::MethodAndProto(api, _))
+                            if invoke =>
+                        {
+                            api
+                        }
+                        _ => continue,
+                    };
+                    for permission in api_permissions.get(&api).into_iter().flatten() {
+                        if attributed.insert((permission, method.method_id())) {
+                            implied
+                                .get_mut(*permission)
+                                .expect("permission without entry")
+                                .push(self.get_method_ref(method.method_id())?);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(implied)
     }
 
     /// Returns the `CodeItem` at the offset.
     pub fn get_code_item(&self, code_off: ulong) -> Result<Option<CodeItem>> {
+        let _timer = self.timers.start(Category::CodeItems);
         if code_off == 0 {
             return Ok(None);
         }
@@ -650,19 +2032,85 @@ where
                 "CodeItem offset not in data section".to_string(),
             ));
         }
+        self.check_alignment(ItemType::CodeItem, code_off as uint)?;
         Ok(Some(self.source.pread_with(code_off as usize, self)?))
     }
 
+    /// Counts the items of the pools and the code of the classes. Only the class data of
+    /// each class and the header of each code item are read, the instructions are not.
+    pub fn stats(&self) -> Result<DexStats> {
+        let native = crate::method::AccessFlags::NATIVE.bits();
+        let mut code_offs = HashSet::new();
+        let mut code_units = 0;
+        let mut classes_with_native_methods = 0;
+        for class_def in self.class_defs() {
+            let class_data = match self.get_class_data(class_def?.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = class_data
+                .direct_methods()
+                .into_iter()
+                .chain(class_data.virtual_methods())
+                .flat_map(|methods| methods.inner());
+            let mut has_native = false;
+            for method in methods {
+                has_native |= method.access_flags() & native != 0;
+                let code_off = *method.code_offset();
+                if code_off == 0 || !code_offs.insert(code_off) {
+                    continue;
+                }
+                if !self.is_offset_in_data_section(code_off as uint) {
+                    return Err(Error::BadOffset(
+                        code_off as usize,
+                        "CodeItem offset not in data section".to_string(),
+                    ));
+                }
+                let insns_size: uint = self
+                    .source
+                    .pread_with(code_off as usize + 12, self.get_endian())?;
+                code_units += ulong::from(insns_size);
+            }
+            if has_native {
+                classes_with_native_methods += 1;
+            }
+        }
+        let inner = &self.inner;
+        Ok(DexStats {
+            strings: inner.strings_len(),
+            types: inner.type_ids_len(),
+            protos: inner.proto_ids_len(),
+            fields: inner.field_ids_len(),
+            methods: inner.method_ids_len(),
+            class_defs: inner.class_defs_len(),
+            code_items: code_offs.len(),
+            code_units,
+            classes_with_native_methods,
+        })
+    }
+
     /// Returns the `AnnotationItem` at the offset.
     pub fn get_annotation_item(&self, annotation_off: uint) -> Result<AnnotationItem> {
-        debug!(target: "annotaion-item", "annotation item offset: {}", annotation_off);
+        let _timer = self.timers.start(Category::Annotations);
+        dex_debug!(self.log_label(), target: "annotaion-item", "annotation item offset: {}", annotation_off);
         if !self.is_offset_in_data_section(annotation_off) {
             return Err(Error::BadOffset(
                 annotation_off as usize,
                 "AnnotationItem offset not in data section".to_string(),
             ));
         }
-        Ok(self.source.pread_with(annotation_off as usize, self)?)
+        let end = self
+            .annotation_items
+            .end(annotation_off, self.data_section_end(), || {
+                self.annotation_item_offsets()
+            })?;
+        let bounds = annotation_off as usize..end as usize;
+        let bytes = self.snapshots.bytes(
+            SnapshotKinds::ANNOTATION_ITEMS,
+            self.source.inner(),
+            bounds.clone(),
+        );
+        bounded(bytes.pread_with(0, self), "AnnotationItem", bounds)
     }
 
     /// Returns the `AnnotationSetItem` at the offset.
@@ -670,7 +2118,7 @@ where
         &self,
         annotation_set_item_off: uint,
     ) -> Result<AnnotationSetItem> {
-        debug!(target: "annotation-set-item", "annotation set item offset: {}", annotation_set_item_off);
+        dex_debug!(self.log_label(), target: "annotation-set-item", "annotation set item offset: {}", annotation_set_item_off);
         if annotation_set_item_off == 0 {
             return Ok(Default::default());
         }
@@ -680,6 +2128,7 @@ where
                 "AnnotationSetItem offset not in data section".to_string(),
             ));
         }
+        self.check_alignment(ItemType::AnnotationSetItem, annotation_set_item_off)?;
         self.source
             .pread_with(annotation_set_item_off as usize, self)
     }
@@ -695,14 +2144,17 @@ where
                 "AnnotationSetRefList offset not in data section".to_string(),
             ));
         }
-        Ok(self
-            .source
-            .pread_with(annotation_set_ref_list_off as usize, self)?)
+        self.check_alignment(ItemType::AnnotationSetRefList, annotation_set_ref_list_off)?;
+        self.source
+            .pread_with(annotation_set_ref_list_off as usize, self)
     }
 
     /// Returns the `EncodedArray` representing the static values of a class at the given offset.
-    pub fn get_static_values(&self, static_values_off: uint) -> Result<EncodedArray> {
-        debug!(target: "class", "static values offset: {}", static_values_off);
+    pub fn get_static_values(&self, static_values_off: uint) -> Result<EncodedArray>
+    where
+        T: 'static,
+    {
+        dex_debug!(self.log_label(), target: "class", "static values offset: {}", static_values_off);
         if static_values_off == 0 {
             return Ok(Default::default());
         }
@@ -712,7 +2164,70 @@ where
                 "Class static values offset not in data section".to_string(),
             ));
         }
-        self.source.pread_with(static_values_off as usize, self)
+        self.get_encoded_array(static_values_off)
+    }
+
+    /// Returns the `EncodedArray` of the call site: the bootstrap method handle, the method
+    /// name, the method type and the extra arguments of the bootstrap method.
+    pub fn get_call_site(&self, call_site_id: uint) -> Result<EncodedArray>
+    where
+        T: 'static,
+    {
+        let map_list = self.map_list();
+        let (call_sites_off, call_sites_len) = (
+            map_list.get_offset(ItemType::CallSiteIdItem).unwrap_or(0),
+            map_list.get_len(ItemType::CallSiteIdItem).unwrap_or(0),
+        );
+        if call_site_id >= call_sites_len {
+            return Err(Error::InvalidId(format!(
+                "Invalid call site id: {}",
+                call_site_id
+            )));
+        }
+        let offset = call_sites_off as usize + call_site_id as usize * 4;
+        let call_site_off: uint = self.source.pread_with(offset, self.get_endian())?;
+        if !self.is_offset_in_data_section(call_site_off) {
+            return Err(Error::BadOffset(
+                call_site_off as usize,
+                "Call site offset not in data section".to_string(),
+            ));
+        }
+        self.get_encoded_array(call_site_off)
+    }
+
+    /// Returns the encoded array item at the offset, reading only its size.
+    fn get_encoded_array(&self, encoded_array_off: uint) -> Result<EncodedArray>
+    where
+        T: 'static,
+    {
+        let end = self
+            .encoded_arrays
+            .end(encoded_array_off, self.data_section_end(), || {
+                self.encoded_array_offsets()
+            })?;
+        let bounds = encoded_array_off as usize..end as usize;
+        let bytes = self.snapshots.bytes(
+            SnapshotKinds::ENCODED_ARRAYS,
+            self.source.inner(),
+            bounds.clone(),
+        );
+        let first_offset = &mut 0;
+        let len = bounded(
+            Uleb128::read(&bytes, first_offset).map_err(Error::from),
+            "EncodedArrayItem",
+            bounds.clone(),
+        )?;
+        dex_debug!(self.log_label(), target: "encoded-array", "encoded array size: {}", len);
+        let source = DexArraySource {
+            dex: self.clone(),
+            bounds,
+            snapshot: bytes.into_snapshot(),
+        };
+        Ok(EncodedArray::new(
+            len as usize,
+            *first_offset,
+            Shared::new(source),
+        ))
     }
 
     /// Returns the `AnnotationsDirectoryItem` at the offset.
@@ -720,7 +2235,7 @@ where
         &self,
         annotations_directory_item_off: uint,
     ) -> Result<AnnotationsDirectoryItem> {
-        debug!(target: "class", "annotations directory offset: {}", annotations_directory_item_off);
+        dex_debug!(self.log_label(), target: "class", "annotations directory offset: {}", annotations_directory_item_off);
         if annotations_directory_item_off == 0 {
             return Ok(Default::default());
         }
@@ -730,126 +2245,4563 @@ where
                 "Annotations directory offset not in data section".to_string(),
             ));
         }
+        self.check_alignment(
+            ItemType::AnnotationsDirectoryItem,
+            annotations_directory_item_off,
+        )?;
         self.source
             .pread_with(annotations_directory_item_off as usize, self)
     }
 
-    /// Returns the `DebugInfoItem` at the offset.
-    pub fn get_debug_info_item(&self, debug_info_off: uint) -> Result<DebugInfoItem> {
-        if !self.is_offset_in_data_section(debug_info_off) {
-            return Err(Error::BadOffset(
-                debug_info_off as usize,
-                "DebugInfoItem offset not in data section".to_string(),
-            ));
+    /// Returns the `AnnotationsDirectoryItem` at the offset, sharing it with earlier
+    /// calls for the same offset.
+    fn get_shared_annotations_directory_item(
+        &self,
+        annotations_directory_item_off: uint,
+    ) -> Result<Shared<AnnotationsDirectoryItem>> {
+        let _timer = self.timers.start(Category::Annotations);
+        if let Some(directory) = self
+            .annotations_directories
+            .get(&annotations_directory_item_off)
+        {
+            return Ok(directory);
         }
+        let directory =
+            Shared::new(self.get_annotations_directory_item(annotations_directory_item_off)?);
+        self.annotations_directories
+            .put(annotations_directory_item_off, directory.clone());
+        Ok(directory)
+    }
 
-        Ok(self.source.pread_with(debug_info_off as usize, self)?)
+    /// Returns the annotations of a class with its annotations directory at the offset.
+    pub(crate) fn get_class_annotations(
+        &self,
+        annotations_directory_item_off: uint,
+    ) -> Result<Shared<ClassAnnotations>>
+    where
+        T: 'static,
+    {
+        let annotations_directory_item_off = if self.skip_annotations {
+            0
+        } else {
+            annotations_directory_item_off
+        };
+        let annotations = if self.eager_annotations || annotations_directory_item_off == 0 {
+            ClassAnnotations::loaded(
+                annotations_directory_item_off,
+                self.get_shared_annotations_directory_item(annotations_directory_item_off)?,
+            )
+        } else {
+            let loader = self.directory_loader.get_or_init(|| {
+                let dex = self.clone();
+                Shared::new(move |offset| dex.get_shared_annotations_directory_item(offset))
+            });
+            ClassAnnotations::lazy(annotations_directory_item_off, loader.clone())
+        };
+        Ok(Shared::new(annotations))
     }
-}
 
-/// Reader facade for loading a `Dex`
-pub struct DexReader;
+    /// Runs `f` with the caches of this `Dex` bypassed: every item `f` reads is read afresh
+    /// from the file, and the caches are neither read nor updated. The bypass only applies to
+    /// the calling thread: other threads using this `Dex` or its clones meanwhile use the
+    /// caches, see the `sync` module.
+    pub fn with_cache_bypassed<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Self) -> R,
+    {
+        let _bypass = self.cache_bypass.enable();
+        f(self)
+    }
 
-impl DexReader {
-    /// Try to read a `Dex` from the given path, returns error if
-    /// the file is not a dex or in case of I/O errors
-    pub fn from_file<P: AsRef<Path>>(file: P) -> Result<Dex<Mmap>> {
-        let map = unsafe { MmapOptions::new().map(&File::open(file.as_ref())?)? };
-        let inner: DexInner = map.pread(0)?;
-        let endian = inner.endian();
-        let source = Source::new(map);
-        let cache = Strings::new(
-            source.clone(),
-            endian,
-            inner.strings_offset(),
-            inner.strings_len(),
-            NonZeroUsize::new(4096).unwrap(),
-            inner.data_section(),
-        );
-        Ok(Dex {
-            source: source.clone(),
-            strings: cache,
-            inner,
+    /// Returns the string at `string_id` read afresh, without reading or updating the
+    /// string cache.
+    pub fn get_string_uncached(&self, string_id: StringId) -> Result<DexString> {
+        self.with_cache_bypassed(|dex| dex.get_string(string_id))
+    }
+
+    /// Returns the annotations directory at the offset read afresh, without reading or
+    /// updating the cache of annotations directories.
+    pub fn get_annotations_directory_item_uncached(
+        &self,
+        annotations_directory_item_off: uint,
+    ) -> Result<Shared<AnnotationsDirectoryItem>> {
+        self.with_cache_bypassed(|dex| {
+            dex.get_shared_annotations_directory_item(annotations_directory_item_off)
         })
     }
 
-    /// Loads a `Dex` from a `Vec<u8>`
-    pub fn from_vec<B: AsRef<[u8]>>(buf: B) -> Result<Dex<B>> {
-        let inner: DexInner = buf.as_ref().pread(0)?;
-        let endian = inner.endian();
-        let source = Source::new(buf);
-        let cache = Strings::new(
-            source.clone(),
-            endian,
-            inner.strings_offset(),
-            inner.strings_len(),
-            NonZeroUsize::new(4096).unwrap(),
-            inner.data_section(),
+    /// Returns the counters of the caches: their number of entries, their approximate
+    /// weight and the hits, misses and evictions since the `Dex` was read. The caches are
+    /// shared with the classes, so their lookups count too.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats::new(
+            self.strings.cache_usage(),
+            self.types.usage(),
+            self.annotations_directories.usage(),
+            self.descriptors.usage(),
+        )
+    }
+
+    /// Empties the caches of strings, types, annotations directories and descriptor lookups and drops the indexes
+    /// built so far, to release their memory at a time of the caller's choosing rather
+    /// than when the `Dex` is dropped. Items already handed out stay valid.
+    ///
+    /// The caches are shared with the clones of this `Dex`, the handles on the file kept by
+    /// the iterators and the classes read from it, and are emptied for all of them. The indexes are only
+    /// dropped from this `Dex`: their memory is released once no other handle uses them,
+    /// and this `Dex` builds them again on use. The counters of `Dex::cache_stats` are
+    /// kept, apart from the number and weight of the entries.
+    pub fn clear_caches(&mut self) {
+        self.strings.clear_cache();
+        self.types.clear();
+        self.annotations_directories.clear();
+        self.descriptors.clear();
+        self.directory_loader = OnceCell::new();
+        self.class_defs_by_type = Shared::default();
+        self.class_names = Shared::default();
+        self.hierarchy = Shared::default();
+        self.proto_ids = Shared::default();
+    }
+
+    /// Drops this `Dex` on a new thread and returns immediately, so that the caller doesn't
+    /// wait for its caches and buffers to be freed. Joining the returned handle waits until
+    /// they are; dropping it detaches the thread.
+    ///
+    /// Like dropping the `Dex`, this only releases what no other handle uses: the caches,
+    /// indexes and source shared with clones of this `Dex`, iterators or classes are freed
+    /// with the last of them, on the thread which drops it.
+    pub fn into_background_drop(self) -> std::thread::JoinHandle<()>
+    where
+        T: 'static,
+    {
+        std::thread::spawn(move || drop(self))
+    }
+
+    /// Reads every cached item of the kind afresh and returns the ones that differ from
+    /// the cached copy. Meant for diagnosing cache bugs.
+    #[cfg(feature = "cache-diagnostics")]
+    pub fn compare_cached_vs_fresh(&self, kind: CacheKind) -> Vec<CacheMismatch> {
+        match kind {
+            CacheKind::Strings => self.strings.compare_cached_vs_fresh(),
+            CacheKind::Types => {
+                let mut mismatches = Vec::new();
+                for (type_id, cached) in self.types.entries() {
+                    let fresh = self.with_cache_bypassed(|dex| dex.get_type(type_id));
+                    let cached = format!("{:?}", cached);
+                    let fresh = match fresh {
+                        Ok(fresh) => format!("{:?}", fresh),
+                        Err(e) => format!("{:?}", e),
+                    };
+                    if cached != fresh {
+                        mismatches.push(CacheMismatch {
+                            key: u64::from(type_id),
+                            cached,
+                            fresh,
+                        });
+                    }
+                }
+                mismatches
+            }
+            CacheKind::AnnotationsDirectories => {
+                let mut mismatches = Vec::new();
+                for (offset, cached) in self.annotations_directories.entries() {
+                    let fresh = self.get_annotations_directory_item(offset);
+                    let cached = format!("{:?}", cached);
+                    let fresh = match fresh {
+                        Ok(fresh) => format!("{:?}", fresh),
+                        Err(e) => format!("{:?}", e),
+                    };
+                    if cached != fresh {
+                        mismatches.push(CacheMismatch {
+                            key: u64::from(offset),
+                            cached,
+                            fresh,
+                        });
+                    }
+                }
+                mismatches
+            }
+        }
+    }
+
+    /// Runs `operation` on the file with the `options`. Walks of the class definitions
+    /// started by the operation, such as `Dex::classes`, `Dex::build_indexes`,
+    /// `Dex::feature_report` or `Dex::producer_fingerprint`, stop with
+    /// `Error::DeadlineExceeded` once the deadline has passed, with the number of class
+    /// definitions processed. Iterators created by the operation keep its deadline.
+    ///
+    /// Nested operations run with the earliest of their deadlines. The deadline only applies
+    /// to the calling thread: other threads using this `Dex` or its clones meanwhile run
+    /// with their own deadlines, see the `sync` module.
+    pub fn run_with<R, F>(&self, options: &OperationOptions, operation: F) -> Result<R>
+    where
+        F: FnOnce(&Self) -> Result<R>,
+    {
+        /// Restores the deadline of the enclosing operation, even if `operation` panics.
+        struct Restore<'a>(&'a PerThread<Option<Instant>>, Option<Instant>);
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.1);
+            }
+        }
+
+        let enclosing = self.deadline.get();
+        let deadline = match (enclosing, options.get_deadline()) {
+            (Some(enclosing), Some(deadline)) => Some(enclosing.min(deadline)),
+            (enclosing, deadline) => enclosing.or(deadline),
+        };
+        let _restore = Restore(&self.deadline, enclosing);
+        self.deadline.set(deadline);
+        operation(self)
+    }
+
+    /// Builds the indexes in `sections` which are not built yet. Indexes are otherwise built
+    /// on first use, except for the class name index and the proto ids which are only used
+    /// when built.
+    ///
+    /// When the build of the indexes by class is interrupted, by the deadline of
+    /// `Dex::run_with` or an error, the entries added so far are kept and the next build
+    /// resumes from them. Such indexes are listed by `Dex::interrupted_indexes` and not used
+    /// until complete.
+    pub fn build_indexes(&self, sections: EagerSections) -> Result<()> {
+        if sections.contains(EagerSections::TYPE_TO_CLASS_DEF) {
+            self.class_defs_by_type()?;
+        }
+        if sections.contains(EagerSections::CLASS_NAME_INDEX) && self.class_names.get().is_none() {
+            let mut partial = self.partial_indexes.borrow_mut().class_names.take();
+            let index = self.index_class_defs(
+                &mut partial,
+                |_, class_def| {
+                    let class_idx = class_def.class_idx;
+                    let descriptor = self.get_type(class_idx)?.type_descriptor().to_string();
+                    Ok((descriptor, class_idx))
+                },
+                |_, _, _| {},
+            );
+            self.partial_indexes.borrow_mut().class_names = partial;
+            let _ = self.class_names.set(index?);
+        }
+        if sections.contains(EagerSections::STRING_META) {
+            self.strings.build_string_data_boundaries()?;
+        }
+        if sections.contains(EagerSections::HIERARCHY_INDEX) {
+            self.hierarchy()?;
+        }
+        if sections.contains(EagerSections::PROTO_CACHE_FULL) && self.proto_ids.get().is_none() {
+            let proto_ids = self.proto_ids().collect::<Result<Vec<_>>>()?;
+            let _ = self.proto_ids.set(proto_ids);
+        }
+        Ok(())
+    }
+
+    /// The indexes built so far.
+    pub fn built_indexes(&self) -> EagerSections {
+        let mut built = EagerSections::empty();
+        built.set(
+            EagerSections::TYPE_TO_CLASS_DEF,
+            self.class_defs_by_type.get().is_some(),
         );
-        Ok(Dex {
-            source: source.clone(),
-            strings: cache,
-            inner,
-        })
+        built.set(
+            EagerSections::CLASS_NAME_INDEX,
+            self.class_names.get().is_some(),
+        );
+        built.set(
+            EagerSections::STRING_META,
+            self.strings.has_string_data_boundaries(),
+        );
+        built.set(
+            EagerSections::PROTO_CACHE_FULL,
+            self.proto_ids.get().is_some(),
+        );
+        built.set(
+            EagerSections::HIERARCHY_INDEX,
+            self.hierarchy.get().is_some(),
+        );
+        built
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// The indexes whose build was interrupted, see `Dex::build_indexes`. Their entries so
+    /// far are kept for the next build.
+    pub fn interrupted_indexes(&self) -> EagerSections {
+        let partial = self.partial_indexes.borrow();
+        let mut interrupted = EagerSections::empty();
+        interrupted.set(
+            EagerSections::TYPE_TO_CLASS_DEF,
+            partial.class_defs_by_type.is_some(),
+        );
+        interrupted.set(
+            EagerSections::CLASS_NAME_INDEX,
+            partial.class_names.is_some(),
+        );
+        interrupted.set(EagerSections::HIERARCHY_INDEX, partial.hierarchy.is_some());
+        interrupted
+    }
 
-    use memmap2::MmapOptions;
-    use std::fs::File;
-    use super::Result;
-    use std::path::Path;
+    /// Adds an entry for each class_def to an index, resuming from the entries and the
+    /// position in `partial`. When the walk fails, they are saved back in `partial`.
+    fn index_class_defs<K, V, F, D>(
+        &self,
+        partial: &mut Option<(HashMap<K, V>, uint)>,
+        entry: F,
+        mut on_duplicate: D,
+    ) -> Result<HashMap<K, V>>
+    where
+        K: Eq + std::hash::Hash,
+        F: Fn(uint, &ClassDefItem) -> Result<(K, V)>,
+        D: FnMut(uint, &K, &V),
+    {
+        let (mut index, mut position) = partial.take().unwrap_or_default();
+        let mut add = || -> Result<()> {
+            for class_def in self.class_defs_from(position) {
+                let (key, value) = entry(position, &class_def?)?;
+                match index.entry(key) {
+                    Entry::Occupied(first) => on_duplicate(position, first.key(), first.get()),
+                    Entry::Vacant(vacant) => {
+                        vacant.insert(value);
+                    }
+                }
+                position += 1;
+            }
+            Ok(())
+        };
+        match add() {
+            Ok(()) => Ok(index),
+            Err(e) => {
+                *partial = Some((index, position));
+                Err(e)
+            }
+        }
+    }
 
-    #[test]
-    fn test_find_class_by_name() {
-        let dex =
-            super::DexReader::from_file("resources/classes.dex").expect("cannot open dex file");
-        let mut count = 0;
-        for class_def in dex.class_defs() {
-            let class_def = class_def.expect("can't load class");
-            let jtype = dex.get_type(class_def.class_idx()).expect("bad type");
-            let result = dex.find_class_by_name(&jtype.type_descriptor().to_string());
-            assert!(result.is_ok());
-            assert!(result.unwrap().is_some());
-            count += 1;
+    /// Index of the class_def of each class defined in the file, by `TypeId`. Only the
+    /// class_defs are read. A type defined by several class_defs, which ART rejects, is
+    /// indexed by the first one, and the others are reported in `Dex::diagnostics`.
+    fn class_defs_by_type(&self) -> Result<&HashMap<TypeId, uint>> {
+        if self.class_defs_by_type.get().is_none() {
+            let mut partial = self.partial_indexes.borrow_mut().class_defs_by_type.take();
+            let index = self.index_class_defs(
+                &mut partial,
+                |position, class_def| Ok((class_def.class_idx, position)),
+                |position, &type_id, &first| {
+                    self.report_duplicate_class_def(position, type_id, first)
+                },
+            );
+            self.partial_indexes.borrow_mut().class_defs_by_type = partial;
+            let _ = self.class_defs_by_type.set(index?);
         }
-        assert!(count > 0);
+        Ok(self
+            .class_defs_by_type
+            .get()
+            .expect("class def index is built"))
     }
 
-    fn load_example_dex_as_vec<P: AsRef<Path>>(file: P) -> Result<Vec<u8>> {
-        let map = unsafe { MmapOptions::new().map(&File::open(file.as_ref())?)? };
-        let data = map.to_vec();
-        Ok(data)
+    /// The subclasses and implementers of each type. Only the class_defs and the
+    /// interfaces lists are read.
+    fn hierarchy(&self) -> Result<&Hierarchy> {
+        if self.hierarchy.get().is_none() {
+            let mut partial = self.partial_indexes.borrow_mut().hierarchy.take();
+            let index = self.index_class_defs(
+                &mut partial,
+                |position, class_def| {
+                    let superclass = Some(class_def.superclass_idx).filter(|idx| *idx != NO_INDEX);
+                    let interfaces = self.get_interface_ids(class_def.interfaces_off)?;
+                    Ok((
+                        position,
+                        ClassEdges {
+                            class_idx: class_def.class_idx,
+                            superclass,
+                            interfaces: interfaces.into_iter().map(TypeId::from).collect(),
+                        },
+                    ))
+                },
+                |_, _, _| {},
+            );
+            self.partial_indexes.borrow_mut().hierarchy = partial;
+            let _ = self.hierarchy.set(Hierarchy::new(index?));
+        }
+        Ok(self.hierarchy.get().expect("hierarchy index is built"))
     }
 
-    #[test]
-    fn test_find_class_by_name_from_vec() {
-        let data: Vec<u8> = load_example_dex_as_vec("resources/classes.dex")
-            .expect("Cannot load example file to a vec");
-        let dex = super::DexReader::from_vec(data).expect("Cannot parse dex from vec");
-        let mut count = 0;
-        for class_def in dex.class_defs() {
-            let class_def = class_def.expect("can't load class");
-            let jtype = dex.get_type(class_def.class_idx()).expect("bad type");
-            let result = dex.find_class_by_name(&jtype.type_descriptor().to_string());
-            assert!(result.is_ok());
-            assert!(result.unwrap().is_some());
-            count += 1;
+    fn report_duplicate_class_def(&self, position: uint, type_id: TypeId, first: uint) {
+        let descriptor = match self.get_type(type_id) {
+            Ok(jtype) => jtype.type_descriptor().to_string(),
+            Err(_) => format!("type {}", type_id),
+        };
+        let diagnostic = Diagnostic::new(
+            Severity::Warning,
+            DiagnosticCode::DuplicateClassDef,
+            format!(
+                "class_def {} defines the class of class_def {} again",
+                position, first
+            ),
+        )
+        .with_span(Span::new(
+            self.inner.class_defs_offset() + position * 32,
+            32,
+        ))
+        .with_context(descriptor);
+        dex_warn!(self.log_label(), target: "class-defs", "{}", diagnostic);
+        let mut found = self.found_diagnostics.borrow_mut();
+        if !found.contains(&diagnostic) {
+            found.push(diagnostic);
         }
-        assert!(count > 0);
     }
 
-    #[test]
-    fn test_get_type_from_descriptor() {
-        let dex =
-            super::DexReader::from_file("resources/classes.dex").expect("cannot open dex file");
-        let jtype = dex.get_type_from_descriptor("Lorg/adw/launcher/Launcher;");
-        assert!(jtype.is_ok());
-        let jtype = jtype.unwrap();
-        assert!(jtype.is_some());
-        let jtype = jtype.unwrap();
-        assert_eq!(jtype.type_descriptor(), "Lorg/adw/launcher/Launcher;")
+    /// Returns the class_def defining the type, if the type is defined in this file.
+    fn get_class_def_by_type(&self, type_id: TypeId) -> Result<Option<ClassDefItem>> {
+        let position = match self.class_defs_by_type()?.get(&type_id) {
+            Some(&position) => position,
+            None => return Ok(None),
+        };
+        let offset = self.inner.class_defs_offset() as usize + position as usize * 32;
+        Ok(Some(self.source.pread_with(offset, self.get_endian())?))
+    }
+
+    /// Returns the class data of the class defining the type, if the type is defined in
+    /// this file and has class data.
+    fn get_class_data_by_type(&self, type_id: TypeId) -> Result<Option<ClassDataItem>> {
+        match self.get_class_def_by_type(type_id)? {
+            Some(class_def) => self.get_class_data(class_def.class_data_off),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the annotations of the field, reading only the annotations directory of
+    /// its class. Returns `None` if the class is not defined in this file or has no
+    /// annotations directory.
+    pub fn annotations_for_field(&self, field_id: FieldId) -> Result<Option<AnnotationSetItem>> {
+        let class_idx = self.get_field_item(field_id)?.class_idx();
+        self.member_annotations(TypeId::from(class_idx), DirectoryList::Fields, field_id)
+    }
+
+    /// Returns the annotations of the method, reading only the annotations directory of
+    /// its class. Returns `None` if the class is not defined in this file or has no
+    /// annotations directory.
+    pub fn annotations_for_method(&self, method_id: MethodId) -> Result<Option<AnnotationSetItem>> {
+        let class_idx = self.get_method_item(method_id)?.class_idx();
+        self.member_annotations(TypeId::from(class_idx), DirectoryList::Methods, method_id)
+    }
+
+    /// Looks up the annotation set of a member in one of the lists of the annotations
+    /// directory of `class_idx` without parsing the rest of the directory.
+    fn member_annotations(
+        &self,
+        class_idx: TypeId,
+        list: DirectoryList,
+        member_id: ulong,
+    ) -> Result<Option<AnnotationSetItem>> {
+        let _timer = self.timers.start(Category::Annotations);
+        let directory_off = match self.get_class_def_by_type(class_idx)? {
+            Some(class_def) if class_def.annotations_off != 0 => class_def.annotations_off,
+            _ => return Ok(None),
+        };
+        if !self.is_offset_in_data_section(directory_off) {
+            return Err(Error::BadOffset(
+                directory_off as usize,
+                "Annotations directory offset not in data section".to_string(),
+            ));
+        }
+        let endian = self.get_endian();
+        let offset = &mut (directory_off as usize + 4);
+        let fields_size: uint = self.source.gread_with(offset, endian)?;
+        let methods_size: uint = self.source.gread_with(offset, endian)?;
+        let _parameters_size: uint = self.source.gread_with(offset, endian)?;
+        let (skipped, len) = match list {
+            DirectoryList::Fields => (0, fields_size),
+            DirectoryList::Methods => (fields_size, methods_size),
+        };
+        let start = *offset + skipped as usize * 8;
+        let end = start + len as usize * 8;
+        if end > self.source.len() {
+            return Err(Error::BadOffset(
+                directory_off as usize,
+                "Annotations directory extends past the end of the file".to_string(),
+            ));
+        }
+        let entries = Section::new(self.source.slice(start..end), self.log_label());
+        let index =
+            entries.binary_search(&member_id, endian, |entry: &DirectoryEntry, member_id| {
+                Ok(member_id.cmp(&ulong::from(entry.idx)))
+            })?;
+        Ok(Some(match index {
+            Some(index) => {
+                let entry: DirectoryEntry = self.source.pread_with(start + index * 8, endian)?;
+                self.get_annotation_set_item(entry.annotations_off)?
+            }
+            None => Default::default(),
+        }))
+    }
+
+    /// Scans the string data, class data, catch handler lists, annotation items and encoded
+    /// arrays, which hold the static values and the call sites, for LEB128 values encoded
+    /// with more bytes than needed. With the `debug-info` feature, the debug info items are
+    /// scanned too. Such encodings are valid but only produced by tools that want to trip
+    /// up parsers. The items are scanned on each call, parsing doesn't look for them.
+    pub fn nonminimal_leb_sites(&self) -> Result<Vec<LebSite>> {
+        let source = self.source.all();
+        let mut scanner = LebScanner::new(&source, self.log_label());
+        for string_data_off in self.strings.string_data_offsets()? {
+            scanner.uleb128(&mut (string_data_off as usize))?;
+        }
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            if class_def.class_data_off == 0 {
+                continue;
+            }
+            let offset = &mut (class_def.class_data_off as usize);
+            let mut sizes = [0; 4];
+            for size in &mut sizes {
+                *size = scanner.uleb128(offset)?;
+            }
+            let [static_fields, instance_fields, direct_methods, virtual_methods] = sizes;
+            // field_idx_diff and access_flags
+            for _ in 0..(static_fields + instance_fields) * 2 {
+                scanner.uleb128(offset)?;
+            }
+            for _ in 0..direct_methods + virtual_methods {
+                // method_idx_diff and access_flags
+                scanner.uleb128(offset)?;
+                scanner.uleb128(offset)?;
+                let code_off = scanner.uleb128(offset)?;
+                if code_off != 0 {
+                    self.scan_catch_handlers(&mut scanner, code_off as usize)?;
+                }
+            }
+        }
+        let mut scan_section =
+            |item_type, scan: fn(&mut LebScanner<'_>, &mut usize) -> Result<()>| {
+                if let Some(section) = self.map_list().get(item_type) {
+                    let offset = &mut (section.offset() as usize);
+                    for _ in 0..section.size() {
+                        scan(&mut scanner, offset)?;
+                    }
+                }
+                Ok::<_, Error>(())
+            };
+        scan_section(ItemType::AnnotationItem, |scanner, offset| {
+            // the visibility, then the annotation
+            *offset += 1;
+            encoded_value::scan_encoded_annotation(scanner, offset)
+        })?;
+        scan_section(
+            ItemType::EncodedArrayItem,
+            encoded_value::scan_encoded_array,
+        )?;
+        #[cfg(feature = "debug-info")]
+        scan_section(ItemType::DebugInfoItem, crate::code::scan_debug_info)?;
+        Ok(scanner.into_sites())
+    }
+
+    /// Iterator over the static initializers of the classes, in the order of the class
+    /// definitions, with metrics of their code. Classes without a `<clinit>` are skipped.
+    ///
+    /// Only the class data, the names of the candidate methods and the instructions of the
+    /// initializers are read: no class is built, so this is much cheaper than loading the
+    /// classes and calling `Class::clinit`.
+    #[cfg(feature = "analysis")]
+    pub fn class_initializers(&self) -> impl Iterator<Item = Result<ClassInitializer>> + '_ {
+        self.class_defs().filter_map(move |class_def| {
+            class_def
+                .and_then(|class_def| self.class_initializer(&class_def))
+                .transpose()
+        })
+    }
+
+    #[cfg(feature = "analysis")]
+    fn class_initializer(&self, class_def: &ClassDefItem) -> Result<Option<ClassInitializer>> {
+        let static_constructor =
+            (crate::method::AccessFlags::STATIC | crate::method::AccessFlags::CONSTRUCTOR).bits();
+        let class_data = match self.get_class_data(class_def.class_data_off)? {
+            Some(class_data) => class_data,
+            None => return Ok(None),
+        };
+        for method in class_data
+            .direct_methods()
+            .iter()
+            .flat_map(|methods| methods.inner())
+        {
+            if *method.access_flags() & static_constructor != static_constructor {
+                continue;
+            }
+            let method_ref = self.get_method_ref(method.method_id())?;
+            if *method_ref.name() != *well_known::STATIC_INITIALIZER {
+                continue;
+            }
+            let insns = self.read_insns(*method.code_offset() as usize)?;
+            let initializer =
+                ClassInitializer::new(method_ref, class_def.class_idx, &insns, |field_id| {
+                    Ok(TypeId::from(self.get_field_item(field_id)?.class_idx()))
+                })?;
+            return Ok(Some(initializer));
+        }
+        Ok(None)
+    }
+
+    /// Iterator over the methods with a loop, that is a branch or switch targeting an
+    /// instruction at or before itself, in the order of the class definitions. Methods with
+    /// fewer than `min_insns_size` code units are skipped without being decoded.
+    ///
+    /// Only the class data and the instructions of the methods are read, one method at a
+    /// time: no class is built and no basic block is computed.
+    #[cfg(feature = "analysis")]
+    pub fn looping_methods(
+        &self,
+        min_insns_size: uint,
+    ) -> impl Iterator<Item = Result<MethodRef>> + '_ {
+        self.class_defs()
+            .map(move |class_def| self.class_looping_methods(&class_def?, min_insns_size))
+            .flat_map(|methods| match methods {
+                Ok(methods) => methods.into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            })
+    }
+
+    #[cfg(feature = "analysis")]
+    fn class_looping_methods(
+        &self,
+        class_def: &ClassDefItem,
+        min_insns_size: uint,
+    ) -> Result<Vec<MethodRef>> {
+        let mut methods = Vec::new();
+        let class_data = match self.get_class_data(class_def.class_data_off)? {
+            Some(class_data) => class_data,
+            None => return Ok(methods),
+        };
+        let encoded_methods = [class_data.direct_methods(), class_data.virtual_methods()];
+        for method in encoded_methods
+            .iter()
+            .flatten()
+            .flat_map(|methods| methods.inner())
+        {
+            let code_off = *method.code_offset() as usize;
+            if code_off == 0 {
+                continue;
+            }
+            let insns_size: uint = self.source.pread_with(code_off + 12, self.get_endian())?;
+            if insns_size < min_insns_size {
+                continue;
+            }
+            if opcode::has_backward_branch(&self.read_insns(code_off)?)? {
+                methods.push(self.get_method_ref(method.method_id())?);
+            }
+        }
+        Ok(methods)
+    }
+
+    /// Reads the instructions of the code item at `code_off` without its debug info and
+    /// try blocks.
+    #[cfg(feature = "analysis")]
+    fn read_insns(&self, code_off: usize) -> Result<Vec<ushort>> {
+        if code_off == 0 {
+            return Ok(Vec::new());
+        }
+        let endian = self.get_endian();
+        let insns_size: uint = self.source.pread_with(code_off + 12, endian)?;
+        let source = &self.source;
+        let offset = &mut (code_off + 16);
+        crate::code::check_insns_size(
+            insns_size,
+            source.len().saturating_sub(*offset),
+            &self.limits,
+        )?;
+        Ok(try_gread_vec_with!(source, offset, insns_size, endian))
+    }
+
+    /// Finds the methods with a handler catching exactly the exception type with the
+    /// `descriptor`, in the order of the class definitions. Catch-all handlers don't count,
+    /// nor do handlers of its super classes.
+    ///
+    /// Only the handler lists of the code items with try blocks are read: the instructions
+    /// are skipped, and no class, method or type is built, so this is much cheaper than
+    /// loading the classes and calling `Method::caught_exception_types`.
+    pub fn classes_catching(&self, descriptor: &str) -> Result<Vec<MethodId>> {
+        let mut methods = Vec::new();
+        let type_id = match self.get_type_from_descriptor(descriptor)? {
+            Some(jtype) => jtype.id(),
+            None => return Ok(methods),
+        };
+        for class_def in self.class_defs() {
+            let class_data = match self.get_class_data(class_def?.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let encoded_methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in encoded_methods
+                .iter()
+                .flatten()
+                .flat_map(|methods| methods.inner())
+            {
+                let code_off = *method.code_offset() as usize;
+                if code_off != 0 && self.code_catches(code_off, type_id)? {
+                    methods.push(method.method_id());
+                }
+            }
+        }
+        dex_debug!(self.log_label(), target: "classes-catching", "{} methods catching {}", methods.len(), descriptor);
+        Ok(methods)
+    }
+
+    /// Returns `true` if a handler of the code item at `code_off` catches `type_id`,
+    /// reading only the header of the code item and its handler list.
+    fn code_catches(&self, code_off: usize, type_id: TypeId) -> Result<bool> {
+        let endian = self.get_endian();
+        let tries_size: ushort = self.source.pread_with(code_off + 6, endian)?;
+        if tries_size == 0 {
+            return Ok(false);
+        }
+        let insns_size: uint = self.source.pread_with(code_off + 12, endian)?;
+        // the tries are 4 byte aligned, after the header and the instructions
+        let tries_off = code_off + 16 + (insns_size as usize + insns_size as usize % 2) * 2;
+        let handlers_off = tries_off + tries_size as usize * 8;
+        self.source
+            .parse_at(handlers_off, |source, offset| -> Result<bool> {
+                let offset = &mut offset.clone();
+                let handlers = Uleb128::read(source, offset)?;
+                for _ in 0..handlers {
+                    let size = Sleb128::read(source, offset)?;
+                    for _ in 0..size.unsigned_abs() {
+                        let pair: EncodedTypeAddrPair = source.gread(offset)?;
+                        if pair.type_id == type_id {
+                            return Ok(true);
+                        }
+                    }
+                    if size <= 0 {
+                        // catch_all_addr
+                        Uleb128::read(source, offset)?;
+                    }
+                }
+                Ok(false)
+            })
+    }
+
+    /// Scans the encoded_catch_handler_list of the code item at `code_off`.
+    fn scan_catch_handlers(&self, scanner: &mut LebScanner<'_>, code_off: usize) -> Result<()> {
+        let endian = self.get_endian();
+        let tries_size: ushort = self.source.pread_with(code_off + 6, endian)?;
+        if tries_size == 0 {
+            return Ok(());
+        }
+        let insns_size: uint = self.source.pread_with(code_off + 12, endian)?;
+        // the tries are 4 byte aligned, after the header and the instructions
+        let tries_off = code_off + 16 + (insns_size as usize + insns_size as usize % 2) * 2;
+        let offset = &mut (tries_off + tries_size as usize * 8);
+        let handlers = scanner.uleb128(offset)?;
+        for _ in 0..handlers {
+            let size = scanner.sleb128(offset)?;
+            // type_idx and addr of each typed handler
+            for _ in 0..size.unsigned_abs() * 2 {
+                scanner.uleb128(offset)?;
+            }
+            if size <= 0 {
+                // catch_all_addr
+                scanner.uleb128(offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks all the classes and reports the data section items referred to by more than
+    /// one class, field or method, such as annotation sets deduplicated by the compiler or
+    /// code shared by methods of different classes. The items are only read to find their
+    /// owners, the caches are left untouched.
+    pub fn shared_offsets_report(&self) -> Result<SharedOffsetsReport> {
+        Ok(self.record_owners()?.into_report(self.log_label()))
+    }
+
+    /// Groups the annotation sets of the file which are stored at different offsets but
+    /// have the same content, such as the copies some shrinkers leave instead of sharing
+    /// one set. Sets are compared by content, not by `AnnotationSetItem::content_hash`, so
+    /// sets crafted to have the same hash are not grouped. Every set of the map list is
+    /// read, including the sets of parameter annotations; the groups are ordered by their
+    /// first offset.
+    #[cfg(feature = "analysis")]
+    pub fn duplicate_annotation_sets(&self) -> Result<Vec<DuplicateAnnotationSets>> {
+        let section = match self.map_list().get(ItemType::AnnotationSetItem) {
+            Some(section) => section,
+            None => return Ok(Vec::new()),
+        };
+        let mut sets: HashMap<Vec<Vec<u8>>, (u64, Vec<uint>)> = HashMap::new();
+        let mut offset = section.offset();
+        for _ in 0..section.size() {
+            let set = self.get_annotation_set_item(offset)?;
+            let mut content = set
+                .annotations()
+                .iter()
+                .map(|annotation| annotation.canonical_bytes(self))
+                .collect::<Result<Vec<_>>>()?;
+            content.sort_unstable();
+            match sets.entry(content) {
+                Entry::Occupied(mut entry) => entry.get_mut().1.push(offset),
+                Entry::Vacant(entry) => {
+                    entry.insert((set.content_hash(self)?, vec![offset]));
+                }
+            }
+            let size = 4 + 4 * set.item_offsets().len();
+            offset = uint::try_from(size)
+                .ok()
+                .and_then(|size| offset.checked_add(size))
+                .ok_or_else(|| {
+                    Error::MalFormed(format!("Annotation set at {} is too large", offset))
+                })?;
+        }
+        let recorder = self.record_owners()?;
+        let mut duplicates: Vec<_> = sets
+            .into_values()
+            .filter(|(_, offsets)| offsets.len() > 1)
+            .map(|(hash, offsets)| DuplicateAnnotationSets::new(hash, offsets, &recorder))
+            .collect();
+        duplicates.sort_by_key(|duplicate| duplicate.offsets()[0]);
+        Ok(duplicates)
+    }
+
+    /// Records the owners of the data section items referred to by the classes, their
+    /// fields and methods.
+    fn record_owners(&self) -> Result<OwnerRecorder> {
+        let mut recorder = OwnerRecorder::default();
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            let class = class_def.class_idx;
+            let owner = Owner::Class(class);
+            recorder.record(SharedItemKind::TypeList, class_def.interfaces_off, owner);
+            recorder.record(
+                SharedItemKind::AnnotationsDirectory,
+                class_def.annotations_off,
+                owner,
+            );
+            recorder.record(SharedItemKind::ClassData, class_def.class_data_off, owner);
+            recorder.record(
+                SharedItemKind::EncodedArray,
+                class_def.static_values_off,
+                owner,
+            );
+            if class_def.annotations_off != 0 {
+                self.record_directory_owners(&mut recorder, class, class_def.annotations_off)?;
+            }
+            let class_data = match self.get_class_data(class_def.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                let code_off = uint::try_from(*method.code_offset()).map_err(|_| {
+                    Error::MalFormed(format!("Code offset too large: {}", method.code_offset()))
+                })?;
+                let owner = Owner::Method {
+                    class,
+                    method: method.method_id(),
+                };
+                recorder.record(SharedItemKind::Code, code_off, owner);
+            }
+        }
+        Ok(recorder)
+    }
+
+    /// Lists the sections of the map list and the items referred to by offset which are
+    /// not aligned as the format requires, ordered by offset. Unlike the checks done when
+    /// reading items, this walks the whole file on each call and finds all of them, whether
+    /// the alignment is strict or not.
+    pub fn misaligned_items(&self) -> Result<Vec<MisalignedItem>> {
+        let mut items = BTreeSet::new();
+        for (item_type, offset) in self.map_list().misaligned_items() {
+            items.insert((offset, item_type));
+        }
+        let recorder = self.record_owners()?;
+        let mut ref_lists = Vec::new();
+        let mut offsets: Vec<(ItemType, uint)> = recorder
+            .items()
+            .map(|(kind, offset)| {
+                if kind == SharedItemKind::AnnotationSetRefList {
+                    ref_lists.push(offset);
+                }
+                (kind.item_type(), offset)
+            })
+            .collect();
+        for proto_id in self.proto_ids() {
+            offsets.push((ItemType::TypeList, proto_id?.params_off()));
+        }
+        let endian = self.get_endian();
+        for ref_list_off in ref_lists {
+            let offset = &mut (ref_list_off as usize);
+            let size: uint = self.source.gread_with(offset, endian)?;
+            for _ in 0..size {
+                offsets.push((
+                    ItemType::AnnotationSetItem,
+                    self.source.gread_with(offset, endian)?,
+                ));
+            }
+        }
+        for (item_type, offset) in offsets {
+            if offset != 0 && !item_type.is_aligned(offset) {
+                items.insert((offset, item_type));
+            }
+        }
+        dex_debug!(self.log_label(), target: "alignment", "{} misaligned items", items.len());
+        Ok(items
+            .into_iter()
+            .map(|(offset, item_type)| MisalignedItem::new(item_type, offset))
+            .collect())
+    }
+
+    /// Checks the names and descriptors of the pools against the grammars of the format:
+    /// the descriptors of the types and of the defined classes, the names of the fields and
+    /// methods and the shorties of the prototypes. The names using characters which the
+    /// version of the file doesn't allow yet are reported as `Severity::ArtTolerated`. The
+    /// pools are walked on each call.
+    pub fn invalid_names(&self) -> Result<Vec<InvalidName>> {
+        let version_040 = &self.header().magic[4..7] >= b"040";
+        let mut names = Vec::new();
+        let mut check = |item_type,
+                         index,
+                         value: DexString,
+                         valid: std::result::Result<(), DescriptorError>| {
+            let value = value.to_string();
+            let error = match valid {
+                Err(error) => Some(error),
+                Ok(()) if !version_040 => validate::since_040(&value),
+                Ok(()) => None,
+            };
+            if let Some(error) = error {
+                names.push(InvalidName::new(item_type, index, value, error));
+            }
+        };
+        for jtype in self.types() {
+            let (_, jtype) = jtype?;
+            let valid = validate::validate_type_descriptor(jtype.type_descriptor());
+            check(
+                ItemType::TypeIdItem,
+                jtype.id(),
+                jtype.type_descriptor,
+                valid,
+            );
+        }
+        for (index, class_def) in self.class_defs().enumerate() {
+            let descriptor = self.get_type(class_def?.class_idx)?.type_descriptor;
+            let valid = validate::validate_class_descriptor(&descriptor);
+            check(ItemType::ClassDefItem, index as uint, descriptor, valid);
+        }
+        for field_id in self.field_ids() {
+            let field_id = field_id?;
+            let name = self.get_string(field_id.name_idx())?;
+            let valid = validate::validate_member_name(&name);
+            check(ItemType::FieldIdItem, field_id.id() as uint, name, valid);
+        }
+        for method_id in self.method_ids() {
+            let method_id = method_id?;
+            let name = self.get_string(method_id.name_idx())?;
+            let valid = validate::validate_member_name(&name);
+            check(ItemType::MethodIdItem, method_id.id() as uint, name, valid);
+        }
+        for (index, proto_id) in self.proto_ids().enumerate() {
+            let shorty = self.get_string(proto_id?.shorty())?;
+            let valid = validate::validate_shorty(&shorty);
+            check(ItemType::ProtoIdItem, index as uint, shorty, valid);
+        }
+        dex_debug!(self.log_label(), target: "validate", "{} invalid names", names.len());
+        Ok(names)
+    }
+
+    /// Finds everywhere the string `value` is used: its index in the `StringId`s list, the
+    /// type whose descriptor it is, the fields and methods named by it and the annotation
+    /// elements using it as name or value. With `scan_code`, the `const-string`
+    /// instructions loading it are searched too.
+    ///
+    /// The string and the type are found by binary search and the member names by
+    /// comparing the indices of the `FieldId`s and `MethodId`s lists, which is cheap. The
+    /// annotations of all the classes are read, and scanning the code decodes the
+    /// instructions of every method, on each call.
+    #[cfg(feature = "analysis")]
+    pub fn string_usages(&self, value: &str, scan_code: bool) -> Result<StringUsages> {
+        let string_id = match self.strings.get_id(value)? {
+            Some(string_id) => string_id,
+            None => {
+                let mut usages = StringUsages::new(None, None);
+                if scan_code {
+                    usages.set_const_strings(Vec::new());
+                }
+                return Ok(usages);
+            }
+        };
+        let mut usages = StringUsages::new(Some(string_id), self.get_type_id(string_id)?);
+        for field_id in self.field_ids() {
+            let field_id = field_id?;
+            if field_id.name_idx() == string_id {
+                let class = self.get_type(field_id.class_idx().into())?;
+                let reference = FieldRef::try_from_dex(self, &field_id)?.to_string();
+                usages.push_field(MemberUsage::new(
+                    field_id.id(),
+                    class.type_descriptor().to_string(),
+                    reference,
+                ));
+            }
+        }
+        for method_id in self.method_ids() {
+            let method_id = method_id?;
+            if method_id.name_idx() == string_id {
+                let method = self.get_method_ref(method_id.id())?;
+                usages.push_method(MemberUsage::new(
+                    method.id(),
+                    method.class().type_descriptor().to_string(),
+                    method.to_string(),
+                ));
+            }
+        }
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            if class_def.annotations_off != 0 {
+                self.annotation_usages(&mut usages, &class_def, string_id)?;
+            }
+        }
+        if scan_code {
+            let sites = self.const_string_sites(string_id)?;
+            usages.set_const_strings(sites);
+        }
+        dex_debug!(self.log_label(), target: "string-usages", "usages of {:?}: {:?}", value, usages);
+        Ok(usages)
+    }
+
+    /// Adds the elements of the annotations of the class, its fields, methods and
+    /// parameters which use the string.
+    #[cfg(feature = "analysis")]
+    fn annotation_usages(
+        &self,
+        usages: &mut StringUsages,
+        class_def: &ClassDefItem,
+        string_id: StringId,
+    ) -> Result<()> {
+        let directory = self.get_annotations_directory_item(class_def.annotations_off)?;
+        let class = Owner::Class(class_def.class_idx);
+        let mut sets = vec![(class, None, &directory.class_annotations)];
+        for field in &directory.field_annotations {
+            let owner = Owner::Field {
+                class: class_def.class_idx,
+                field: field.field_idx,
+            };
+            sets.push((owner, None, &field.annotations));
+        }
+        for method in &directory.method_annotations {
+            let owner = Owner::Method {
+                class: class_def.class_idx,
+                method: method.method_idx,
+            };
+            sets.push((owner, None, &method.annotations));
+        }
+        for method in &directory.parameter_annotations {
+            let owner = Owner::Method {
+                class: class_def.class_idx,
+                method: method.method_idx,
+            };
+            for (parameter, set) in method.annotations.annotation_set_list().iter().enumerate() {
+                sets.push((owner, Some(parameter), set));
+            }
+        }
+        for (owner, parameter, set) in sets {
+            for item in set.annotations() {
+                let annotation = item.annotation();
+                for element in annotation.elements() {
+                    let element_use = if element.name_idx() == string_id {
+                        ElementUse::Name
+                    } else if usage::value_uses(element.value(), string_id) {
+                        ElementUse::Value
+                    } else {
+                        continue;
+                    };
+                    let location = match owner {
+                        Owner::Class(class) => self.get_type(class)?.to_string(),
+                        Owner::Field { field, .. } => self.get_field_ref(field)?.to_string(),
+                        Owner::Method { method, .. } => self.get_method_ref(method)?.to_string(),
+                    };
+                    usages.push_annotation(AnnotationUsage::new(
+                        owner,
+                        parameter,
+                        location,
+                        annotation.jtype().to_string(),
+                        element.name().to_string(),
+                        element_use,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// For each annotation type used in the file, whether the file defines it, its
+    /// retention and the number of classes, methods, fields and parameters it annotates,
+    /// sorted by descriptor. An annotation set shared by several items counts once per
+    /// item. The annotations directories of all the classes are read on each call.
+    #[cfg(feature = "analysis")]
+    pub fn annotation_usage_summary(&self) -> Result<Vec<AnnotationTypeUsage>> {
+        // the counts of each type, and the retention of its uses, `None` once they disagree
+        let mut types: BTreeMap<TypeId, (UsageCounts, Option<Option<Retention>>)> = BTreeMap::new();
+        let mut add = |set: &AnnotationSetItem, target| {
+            for item in set.annotations() {
+                let entry = types.entry(item.annotation().type_idx()).or_default();
+                entry.0.add(target);
+                let retention = Retention::of_visibility(item.visibility());
+                entry.1 = match entry.1 {
+                    Some(observed) if observed != retention => Some(None),
+                    _ => Some(retention),
+                };
+            }
+        };
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            if class_def.annotations_off == 0 {
+                continue;
+            }
+            let directory = self.get_annotations_directory_item(class_def.annotations_off)?;
+            add(&directory.class_annotations, AnnotationTarget::Class);
+            for field in &directory.field_annotations {
+                add(&field.annotations, AnnotationTarget::Field);
+            }
+            for method in &directory.method_annotations {
+                add(&method.annotations, AnnotationTarget::Method);
+            }
+            for method in &directory.parameter_annotations {
+                for set in method.annotations.annotation_set_list() {
+                    add(set, AnnotationTarget::Parameter);
+                }
+            }
+        }
+
+        let mut summary = Vec::with_capacity(types.len());
+        for (type_id, (counts, observed)) in types {
+            let descriptor = self.get_type(type_id)?.to_string();
+            let (defined_locally, retention) = match self.get_class_def_by_type(type_id)? {
+                Some(class_def) => (true, self.declared_retention(&class_def)?),
+                None => (false, observed.flatten()),
+            };
+            summary.push(AnnotationTypeUsage::new(
+                descriptor,
+                defined_locally,
+                retention,
+                counts,
+            ));
+        }
+        summary.sort_by(|a, b| a.descriptor().cmp(b.descriptor()));
+        Ok(summary)
+    }
+
+    /// The retention declared by the `Retention` annotation of an annotation type defined in
+    /// the file, `Retention::Class` without one. `None` if the value isn't a known policy.
+    #[cfg(feature = "analysis")]
+    fn declared_retention(&self, class_def: &ClassDefItem) -> Result<Option<Retention>> {
+        if class_def.annotations_off == 0 {
+            return Ok(Some(Retention::Class));
+        }
+        let directory = self.get_annotations_directory_item(class_def.annotations_off)?;
+        let item = match directory.class_annotations.find(well_known::RETENTION) {
+            Some(item) => item,
+            None => return Ok(Some(Retention::Class)),
+        };
+        let value = item
+            .annotation()
+            .find_element(well_known::VALUE_ELEMENT)
+            .map(|element| element.value());
+        Ok(match value {
+            Some(EncodedValue::Enum(field)) => {
+                Retention::from_id(&self.get_string(field.name_idx())?.to_string())
+            }
+            _ => None,
+        })
+    }
+
+    /// Finds the `const-string` instructions loading the string by decoding the
+    /// instructions of all the methods.
+    #[cfg(feature = "analysis")]
+    fn const_string_sites(&self, string_id: StringId) -> Result<Vec<ConstStringSite>> {
+        let mut sites = Vec::new();
+        for class_def in self.class_defs() {
+            let class_data = match self.get_class_data(class_def?.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                let insns = self.read_insns(*method.code_offset() as usize)?;
+                for instruction in Instructions::new(&insns) {
+                    let instruction = instruction?;
+                    if instruction.index_operand() == Some(IndexOperand::String(string_id)) {
+                        let location = self.get_method_ref(method.method_id())?.to_string();
+                        sites.push(ConstStringSite::new(
+                            method.method_id(),
+                            location,
+                            instruction.addr,
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(sites)
+    }
+
+    /// Finds the URLs, IP addresses, domain names, package names, file paths and intent
+    /// actions in the string pool, in the order of the strings, see `IndicatorKind` for
+    /// the forms recognized. Each string is read once; with
+    /// `IndicatorOptions::referencing_methods` the code of every method is then scanned
+    /// once for the `const-string` instructions loading the strings found.
+    #[cfg(feature = "analysis")]
+    pub fn extract_indicators(&self, options: &IndicatorOptions) -> Result<Vec<Indicator>> {
+        let string_data_offs = self.strings.string_data_offsets()?;
+        let mut indicators = Vec::new();
+        for string in self.strings() {
+            let (string_id, string) = string?;
+            let string = string.to_string();
+            for (kind, range) in indicator::find_in_str(&string, options.selected_kinds()) {
+                indicators.push(Indicator::new(
+                    kind,
+                    &string,
+                    string_id,
+                    string_data_offs[string_id as usize],
+                    range,
+                ));
+            }
+        }
+        if options.scans_code() && !indicators.is_empty() {
+            let string_ids = indicators.iter().map(Indicator::string_id).collect();
+            let mut users = self.const_string_users(&string_ids)?;
+            for indicator in &mut indicators {
+                if let Some(methods) = users.get_mut(&indicator.string_id()) {
+                    indicator.set_referencing_methods(methods.clone());
+                }
+            }
+        }
+        Ok(indicators)
+    }
+
+    /// The methods loading each of the `string_ids` with `const-string`, in the order of
+    /// the class definitions, without duplicates.
+    #[cfg(feature = "analysis")]
+    fn const_string_users(
+        &self,
+        string_ids: &HashSet<StringId>,
+    ) -> Result<HashMap<StringId, Vec<MethodRef>>> {
+        let mut users: HashMap<StringId, Vec<MethodRef>> = HashMap::new();
+        for class_def in self.class_defs() {
+            let class_data = match self.get_class_data(class_def?.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                let insns = self.read_insns(*method.code_offset() as usize)?;
+                let mut loaded = HashSet::new();
+                for instruction in Instructions::new(&insns) {
+                    if let Some(IndexOperand::String(string_id)) = instruction?.index_operand() {
+                        if string_ids.contains(&string_id) {
+                            loaded.insert(string_id);
+                        }
+                    }
+                }
+                if loaded.is_empty() {
+                    continue;
+                }
+                let method_ref = self.get_method_ref(method.method_id())?;
+                for string_id in loaded {
+                    users.entry(string_id).or_default().push(method_ref.clone());
+                }
+            }
+        }
+        Ok(users)
+    }
+
+    /// The marker strings of D8, R8 and L8 found in the string pool, with the version,
+    /// min-api and compilation mode they record. A marker whose JSON is malformed is
+    /// returned with its `parse_error` set. Without the `json` feature only the raw strings
+    /// and the tools are available.
+    ///
+    /// The pool is sorted, so the strings starting with `~~` are found by binary search
+    /// and only they are read. In a file whose pool is not sorted, some may be missed.
+    #[cfg(feature = "analysis")]
+    pub fn compiler_markers(&self) -> Result<Vec<CompilerMarker>> {
+        Ok(self
+            .marker_strings()?
+            .iter()
+            .filter_map(|string| CompilerMarker::parse(string))
+            .collect())
+    }
+
+    /// The strings starting with `~~`, in the order of the pool.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn marker_strings(&self) -> Result<Vec<DexString>> {
+        self.string_id_range(MARKER_PREFIX)?
+            .map(|string_id| self.get_string(string_id))
+            .collect()
+    }
+
+    /// Lists the dex features the file uses, with the first `max_examples` places using
+    /// each, and the lowest and highest opcodes of its instructions: what a re-assembler
+    /// needs to pick the API level and the dex version to target. The instructions of every
+    /// method are decoded, but no class is built.
+    #[cfg(feature = "analysis")]
+    pub fn feature_report(&self, max_examples: usize) -> Result<FeatureReport> {
+        let version = self.header().magic[4..7]
+            .iter()
+            .map(|byte| char::from(*byte))
+            .collect();
+        let mut report = FeatureReport::new(version, max_examples);
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            let is_interface =
+                crate::class::AccessFlags::from_bits_truncate(class_def.access_flags)
+                    .contains(crate::class::AccessFlags::INTERFACE);
+            let class_data = match self.get_class_data(class_def.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = [
+                (true, class_data.direct_methods()),
+                (false, class_data.virtual_methods()),
+            ];
+            for (direct, method) in methods
+                .iter()
+                .filter_map(|(direct, methods)| methods.map(|methods| (*direct, methods)))
+                .flat_map(|(direct, methods)| methods.inner().iter().map(move |m| (direct, m)))
+            {
+                self.add_method_features(&mut report, method, direct, is_interface)?;
+            }
+        }
+        if let Some(map_item) = self.map_list().get(ItemType::HiddenapiClassDataItem) {
+            report.add(DexFeature::HiddenApiData, || {
+                Ok(FeatureLocation::Section {
+                    offset: map_item.offset(),
+                })
+            })?;
+        }
+        Ok(report)
+    }
+
+    #[cfg(feature = "analysis")]
+    fn add_method_features(
+        &self,
+        report: &mut FeatureReport,
+        method: &EncodedMethod,
+        direct: bool,
+        is_interface: bool,
+    ) -> Result<()> {
+        use crate::method::AccessFlags;
+        let method_ref = || self.get_method_ref(method.method_id());
+        let flags = AccessFlags::from_bits_truncate(*method.access_flags());
+        let code_off = *method.code_offset() as usize;
+        if is_interface {
+            let feature = if direct {
+                let is_static = flags.contains(AccessFlags::STATIC)
+                    && !flags.contains(AccessFlags::CONSTRUCTOR);
+                Some(DexFeature::StaticInterfaceMethod).filter(|_| is_static)
+            } else {
+                Some(DexFeature::DefaultInterfaceMethod).filter(|_| code_off != 0)
+            };
+            if let Some(feature) = feature {
+                report.add(feature, || Ok(FeatureLocation::Method(method_ref()?)))?;
+            }
+        }
+        if flags.contains(AccessFlags::STATIC | AccessFlags::SYNTHETIC)
+            && self
+                .get_method_item(method.method_id())
+                .and_then(|item| self.get_string(item.name_idx()))?
+                .to_string()
+                .starts_with("access$")
+        {
+            report.add(DexFeature::SyntheticAccessor, || {
+                Ok(FeatureLocation::Method(method_ref()?))
+            })?;
+        }
+        for instruction in Instructions::new(&self.read_insns(code_off)?) {
+            let instruction = instruction?;
+            let opcode = match instruction.kind {
+                InstructionKind::Op(opcode) => opcode,
+                _ => continue,
+            };
+            report.add_opcode(opcode.value);
+            if let Some(feature) = DexFeature::of_opcode(opcode) {
+                report.add(feature, || {
+                    Ok(FeatureLocation::Instruction {
+                        method: method_ref()?,
+                        addr: instruction.addr,
+                    })
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the quirks left in the file by the tool which produced it: the values of
+    /// padding bytes, the order of the map list, the raw visibilities of annotations, the
+    /// reserved access flags, the non-minimal LEB128 values and the D8 and R8 markers. The
+    /// whole file is walked on each call.
+    #[cfg(feature = "analysis")]
+    pub fn producer_fingerprint(&self) -> Result<ProducerFingerprint> {
+        let source = self.source.all();
+        let source = &*source;
+        let endian = self.get_endian();
+        let mut recorder = FingerprintRecorder::default();
+
+        let mut sections: Vec<MapItem> = self
+            .map_list()
+            .items()
+            .iter()
+            .filter(|item| item.size != 0)
+            .cloned()
+            .collect();
+        sections.sort_by_key(|item| item.offset);
+        for pair in sections.windows(2) {
+            let (item, next) = (pair[0], pair[1]);
+            let len = match item.item_type.known() {
+                Some(ItemType::Header) => Some(ulong::from(self.header().header_size())),
+                Some(ItemType::MapList) => Some(4 + 12 * ulong::from(item.size)),
+                Some(item_type) => fixed_item_size(item_type)
+                    .map(|size| ulong::from(size) * ulong::from(item.size)),
+                None => None,
+            };
+            let end = match len {
+                Some(len) => ulong::from(item.offset) + len,
+                None => continue,
+            };
+            let next = ulong::from(next.offset);
+            // larger gaps are not padding
+            if end <= next && next - end < 4 {
+                if let Some(bytes) = source.get(end as usize..next as usize) {
+                    recorder.padding(bytes);
+                }
+            }
+        }
+
+        let class_flags = crate::class::AccessFlags::all().bits();
+        let field_flags = crate::field::AccessFlags::all().bits();
+        let method_flags = crate::method::AccessFlags::all().bits();
+        let mut code_offs = BTreeSet::new();
+        for class_def in self.class_defs() {
+            let class_def = class_def?;
+            recorder.access_flags(u64::from(class_def.access_flags), u64::from(class_flags));
+            let class_data = match self.get_class_data(class_def.class_data_off)? {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let fields = [class_data.static_fields(), class_data.instance_fields()];
+            for field in fields.iter().flatten().flat_map(|fields| fields.inner()) {
+                recorder.access_flags(field.access_flags(), field_flags);
+            }
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                recorder.access_flags(*method.access_flags(), method_flags);
+                if *method.code_offset() != 0 {
+                    code_offs.insert(*method.code_offset() as usize);
+                }
+            }
+        }
+        for code_off in code_offs {
+            let tries_size: ushort = source.pread_with(code_off + 6, endian)?;
+            let insns_size: uint = source.pread_with(code_off + 12, endian)?;
+            // the try blocks are 4 byte aligned, after two bytes of padding if needed
+            if tries_size != 0 && insns_size % 2 == 1 {
+                let padding = code_off + 16 + insns_size as usize * 2;
+                if let Some(bytes) = source.get(padding..padding + 2) {
+                    recorder.padding(bytes);
+                }
+            }
+        }
+
+        if let Some(sets) = self.map_list().get(ItemType::AnnotationSetItem) {
+            let mut annotation_offs = BTreeSet::new();
+            let offset = &mut (sets.offset as usize);
+            for _ in 0..sets.size {
+                *offset += (4 - *offset % 4) % 4;
+                let size: uint = source.gread_with(offset, endian)?;
+                for _ in 0..size {
+                    let annotation_off: uint = source.gread_with(offset, endian)?;
+                    annotation_offs.insert(annotation_off as usize);
+                }
+            }
+            for annotation_off in annotation_offs {
+                let visibility: ubyte = source.pread(annotation_off)?;
+                recorder.visibility(visibility);
+            }
+        }
+
+        recorder.markers = self.marker_strings()?;
+
+        let map_items: Vec<(ItemType, uint)> = self
+            .map_list()
+            .items()
+            .iter()
+            .filter_map(|item| Some((item.item_type.known()?, item.offset)))
+            .collect();
+        let header = self.header();
+        Ok(recorder.into_fingerprint(
+            header.magic(),
+            header.header_size(),
+            header.endian_tag(),
+            &map_items,
+            self.nonminimal_leb_sites()?.len(),
+            self.log_label(),
+        ))
+    }
+
+    /// Records the owners of the annotation sets listed in the annotations directory of
+    /// `class`, without reading the sets.
+    fn record_directory_owners(
+        &self,
+        recorder: &mut OwnerRecorder,
+        class: TypeId,
+        directory_off: uint,
+    ) -> Result<()> {
+        if !self.is_offset_in_data_section(directory_off) {
+            return Err(Error::BadOffset(
+                directory_off as usize,
+                "Annotations directory offset not in data section".to_string(),
+            ));
+        }
+        let endian = self.get_endian();
+        let offset = &mut (directory_off as usize);
+        let class_annotations_off: uint = self.source.gread_with(offset, endian)?;
+        let fields_size: uint = self.source.gread_with(offset, endian)?;
+        let methods_size: uint = self.source.gread_with(offset, endian)?;
+        let parameters_size: uint = self.source.gread_with(offset, endian)?;
+        recorder.record(
+            SharedItemKind::AnnotationSet,
+            class_annotations_off,
+            Owner::Class(class),
+        );
+        for _ in 0..fields_size {
+            let entry: DirectoryEntry = self.source.gread_with(offset, endian)?;
+            let owner = Owner::Field {
+                class,
+                field: FieldId::from(entry.idx),
+            };
+            recorder.record(SharedItemKind::AnnotationSet, entry.annotations_off, owner);
+        }
+        for &(kind, len) in &[
+            (SharedItemKind::AnnotationSet, methods_size),
+            (SharedItemKind::AnnotationSetRefList, parameters_size),
+        ] {
+            for _ in 0..len {
+                let entry: DirectoryEntry = self.source.gread_with(offset, endian)?;
+                let owner = Owner::Method {
+                    class,
+                    method: MethodId::from(entry.idx),
+                };
+                recorder.record(kind, entry.annotations_off, owner);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `DebugInfoItem` at the offset.
+    #[cfg(feature = "debug-info")]
+    pub fn get_debug_info_item(&self, debug_info_off: uint) -> Result<DebugInfoItem> {
+        let _timer = self.timers.start(Category::DebugInfo);
+        if !self.is_offset_in_data_section(debug_info_off) {
+            return Err(Error::BadOffset(
+                debug_info_off as usize,
+                "DebugInfoItem offset not in data section".to_string(),
+            ));
+        }
+
+        self.source.pread_with(debug_info_off as usize, self)
+    }
+}
+
+/// Contents of a file read with `DexReader::from_file`: a read-only memory map of the file
+/// with the `mmap` feature, the contents read into memory without it.
+#[cfg(feature = "mmap")]
+pub type FileSource = memmap2::Mmap;
+/// Contents of a file read with `DexReader::from_file`: a read-only memory map of the file
+/// with the `mmap` feature, the contents read into memory without it.
+#[cfg(not(feature = "mmap"))]
+pub type FileSource = Vec<u8>;
+
+/// Reader facade for loading a `Dex`
+pub struct DexReader;
+
+impl DexReader {
+    /// Try to read a `Dex` from the given path, returns error if
+    /// the file is not a dex or in case of I/O errors
+    pub fn from_file<P: AsRef<Path>>(file: P) -> Result<Dex<FileSource>> {
+        DexReaderBuilder::new().read_file(file)
+    }
+
+    /// Loads a `Dex` from a `Vec<u8>`
+    pub fn from_vec<B: AsRef<[u8]> + Send + Sync>(buf: B) -> Result<Dex<B>> {
+        DexReaderBuilder::new().read_vec(buf)
+    }
+
+    /// Reads a `Dex` from a reader, see `DexReaderBuilder::read_reader`.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Dex<Vec<u8>>> {
+        DexReaderBuilder::new().read_reader(reader)
+    }
+
+    /// Reads the dex file `entry_name`, such as `classes2.dex`, out of the APK at `path`,
+    /// see `DexReaderBuilder::read_apk`.
+    #[cfg(feature = "container-zip")]
+    pub fn from_apk<P: AsRef<Path>>(path: P, entry_name: &str) -> Result<Dex<Vec<u8>>> {
+        DexReaderBuilder::new().read_apk(path, entry_name)
+    }
+
+    /// Loads a `Dex` from any `DexSource`, see `DexReaderBuilder::read_source`.
+    pub fn from_source<S: DexSource>(source: S) -> Result<Dex<S>> {
+        DexReaderBuilder::new().read_source(source)
+    }
+
+    /// Reads a `Dex` from the given path without blocking the tokio runtime, see
+    /// `DexReaderBuilder::read_file_async`.
+    #[cfg(feature = "async")]
+    pub async fn from_file_async<P: AsRef<Path>>(file: P) -> Result<Dex<Vec<u8>>> {
+        DexReaderBuilder::new().read_file_async(file).await
+    }
+
+    /// Returns a builder for reading a `Dex` with non-default options.
+    pub fn builder() -> DexReaderBuilder {
+        DexReaderBuilder::new()
+    }
+}
+
+bitflags! {
+    /// Indexes that `DexReaderBuilder` can build before returning a `Dex`.
+    #[derive(Default)]
+    pub struct EagerSections: uint {
+        /// The class_def of each type defined in the file, used to find classes by type.
+        const TYPE_TO_CLASS_DEF = 0x1;
+        /// The type of each class by descriptor, used by `Dex::find_class_by_descriptor`
+        /// instead of binary searching the strings and types.
+        const CLASS_NAME_INDEX = 0x2;
+        /// The boundaries of the string data items, needed to read any string.
+        const STRING_META = 0x4;
+        /// All the proto ids, used by `Dex::get_proto_item` instead of reading the file.
+        const PROTO_CACHE_FULL = 0x8;
+        /// The subclasses and implementers of each type, used by `Dex::subclasses_of` and
+        /// `Dex::implementers_of`.
+        const HIERARCHY_INDEX = 0x10;
+    }
+}
+
+/// How much of the integrity of a file `DexReaderBuilder::verify` checks before reading
+/// it. Modes depend on features, such as `Full` on `signature`, so matches need a
+/// wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum VerifyMode {
+    /// Nothing is verified, for files known to have a stale checksum. Reading the items
+    /// of a corrupted file can still fail, see `Dex::verify_checksum`.
+    None,
+    /// The adler32 checksum is verified, which detects corruption.
+    #[default]
+    Checksum,
+    /// The checksum and the SHA-1 signature are verified, which also detects files that
+    /// were modified and whose checksum was fixed without signing them again.
+    #[cfg(feature = "signature")]
+    Full,
+}
+
+/// Reads a `Dex` with non-default options.
+#[derive(Debug, Clone, Default)]
+pub struct DexReaderBuilder {
+    eager_annotations: bool,
+    strict_alignment: bool,
+    strict_descriptors: bool,
+    lenient_class_data: bool,
+    track_spans: bool,
+    skip_code: bool,
+    #[cfg(feature = "debug-info")]
+    skip_debug_info: bool,
+    skip_annotations: bool,
+    verify: VerifyMode,
+    eager: EagerSections,
+    limits: ParserLimits,
+    log_label: Option<String>,
+    cache_config: CacheConfig,
+    snapshot: SnapshotKinds,
+}
+
+impl DexReaderBuilder {
+    /// Returns a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the annotations of a class, its fields and methods when the class is loaded
+    /// instead of on first access, so that malformed annotations fail loading the class.
+    /// Defaults to `false`.
+    pub fn eager_annotations(mut self, eager: bool) -> Self {
+        self.eager_annotations = eager;
+        self
+    }
+
+    /// Fail with `Error::Misaligned` when a section of the map list or an item read from the
+    /// file is not aligned as the format requires. Otherwise misaligned items are read
+    /// anyway, as ART sometimes does, with a warning in `Dex::warnings` for the sections
+    /// and a log message for the items. Defaults to `false`.
+    pub fn strict_alignment(mut self, strict: bool) -> Self {
+        self.strict_alignment = strict;
+        self
+    }
+
+    /// Fail with `Error::InvalidDescriptor` when reading a type whose descriptor doesn't
+    /// match the `TypeDescriptor` grammar. Otherwise the type is read anyway, with the kind
+    /// given by the shape of its descriptor, `TypeKind::Invalid` if it is neither a
+    /// primitive, an array nor `L…;`, and the descriptor is reported once
+    /// in `Dex::diagnostics` and logged. Defaults to `false`.
+    pub fn strict_descriptors(mut self, strict: bool) -> Self {
+        self.strict_descriptors = strict;
+        self
+    }
+
+    /// Load a class whose class data has a corrupt member with the members read before it,
+    /// instead of failing, and report what is missing in `Class::parse_completeness`. Each
+    /// truncated group is also logged. Defaults to `false`.
+    pub fn lenient_class_data(mut self, lenient: bool) -> Self {
+        self.lenient_class_data = lenient;
+        self
+    }
+
+    /// Record where the static values of each class are stored, with a hash of their bytes,
+    /// in `Class::static_values_span`, to tell which classes only had their static values
+    /// changed between two versions of a file without comparing the values. This reads the
+    /// static values twice when loading a class. Defaults to `false`.
+    pub fn track_spans(mut self, track: bool) -> Self {
+        self.track_spans = track;
+        self
+    }
+
+    /// Load methods without reading their code item, for callers which only need the
+    /// declarations of the classes: `Method::code` is then `None` for every method, and
+    /// corrupt code items don't fail loading their class. The code items can still be read
+    /// with `Dex::get_code_item`. Defaults to `false`.
+    pub fn skip_code(mut self, skip: bool) -> Self {
+        self.skip_code = skip;
+        self
+    }
+
+    /// Read code items without their debug info: `CodeItem::debug_info_item` is then
+    /// `None`, and corrupt debug info doesn't fail reading the code. The debug info can
+    /// still be read with `Dex::get_debug_info_item`. Defaults to `false`.
+    #[cfg(feature = "debug-info")]
+    pub fn skip_debug_info(mut self, skip: bool) -> Self {
+        self.skip_debug_info = skip;
+        self
+    }
+
+    /// Load classes as if they had no annotations: the annotations of the classes, their
+    /// fields, methods and parameters are empty, and their annotations directories are
+    /// never read. The queries of `Dex` reading annotations directly, such as
+    /// `Dex::annotations_for_field`, are not affected. Defaults to `false`.
+    pub fn skip_annotations(mut self, skip: bool) -> Self {
+        self.skip_annotations = skip;
+        self
+    }
+
+    /// How much of the integrity of the file to verify before reading it, files failing
+    /// the verification are rejected with `Error::MalFormed`. Defaults to
+    /// `VerifyMode::Checksum`.
+    pub fn verify(mut self, mode: VerifyMode) -> Self {
+        self.verify = mode;
+        self
+    }
+
+    /// Limits checked while parsing items. Defaults to `ParserLimits::default()`, which
+    /// accepts any well formed file.
+    pub fn limits(mut self, limits: ParserLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Bounds of the caches of the strings and annotations directories. Defaults to
+    /// `CacheConfig::default()`, which bounds them by their number of entries only.
+    pub fn cache_config(mut self, config: CacheConfig) -> Self {
+        self.cache_config = config;
+        self
+    }
+
+    /// Kinds of items whose bytes are copied the first time they are read, before being
+    /// parsed, for sources whose bytes can change between reads, such as the memory of a
+    /// live process. Each item is then parsed from a consistent copy, and reading the same
+    /// offset again returns the first copy, even after the item is evicted from the caches.
+    /// The copies are kept as long as the `Dex`. Defaults to none: the items are parsed in
+    /// place.
+    pub fn snapshot_on_access(mut self, kinds: SnapshotKinds) -> Self {
+        self.snapshot = kinds;
+        self
+    }
+
+    /// Indexes to build before the `Dex` is returned, so that the first queries using them
+    /// don't pay for building them. Defaults to none.
+    pub fn eager(mut self, sections: EagerSections) -> Self {
+        self.eager = sections;
+        self
+    }
+
+    /// A label, such as the name of the file, included in the logs emitted while reading
+    /// the `Dex` to tell them apart from the logs of other files read concurrently. The
+    /// logs are prefixed with it, or with the `tracing` feature, emitted in a `dex` span
+    /// with it as `label` field. Defaults to none.
+    pub fn log_label<S: Into<String>>(mut self, label: S) -> Self {
+        self.log_label = Some(label.into());
+        self
+    }
+
+    /// Try to read a `Dex` from the given path, returns error if
+    /// the file is not a dex or in case of I/O errors
+    pub fn read_file<P: AsRef<Path>>(&self, file: P) -> Result<Dex<FileSource>> {
+        #[cfg(feature = "mmap")]
+        let map = crate::mmap::map_file(file.as_ref())?;
+        #[cfg(not(feature = "mmap"))]
+        let map = std::fs::read(file.as_ref())?;
+        self.read_source(map)
+    }
+
+    /// Reads the file at the given path on the blocking thread pool of the tokio runtime,
+    /// then loads a `Dex` from its contents like `read_vec`.
+    ///
+    /// Only the IO is moved off the runtime: the `Dex` is built on the calling task, which
+    /// verifies the checksum of the whole file. Parsing items later is CPU-bound too,
+    /// callers with large files can read and process the `Dex` in `spawn_blocking` instead.
+    #[cfg(feature = "async")]
+    pub async fn read_file_async<P: AsRef<Path>>(&self, file: P) -> Result<Dex<Vec<u8>>> {
+        let data = tokio::fs::read(file.as_ref()).await?;
+        self.read_vec(data)
+    }
+
+    /// Loads a `Dex` from a `Vec<u8>`
+    pub fn read_vec<B: AsRef<[u8]> + Send + Sync>(&self, buf: B) -> Result<Dex<B>> {
+        self.read_source(buf)
+    }
+
+    /// Reads the contents of `reader` to its end into memory, then loads a `Dex` from them
+    /// like `read_vec`, for files which don't come from the filesystem, such as downloads.
+    /// Contents shorter than the size declared by the header, such as a truncated
+    /// download, fail with `Error::MalFormed` before the checksum is computed.
+    pub fn read_reader<R: Read>(&self, mut reader: R) -> Result<Dex<Vec<u8>>> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.read_vec(data)
+    }
+
+    /// Extracts the dex file `entry_name`, such as `classes2.dex`, out of the APK at `path`
+    /// into memory, whether the entry is stored or deflated, then loads a `Dex` from it
+    /// like `read_vec`. A missing entry fails with an `Error::IO` of kind `NotFound`. To
+    /// read all the dex files of an APK, see `DexContainer::from_apk`.
+    #[cfg(feature = "container-zip")]
+    pub fn read_apk<P: AsRef<Path>>(&self, path: P, entry_name: &str) -> Result<Dex<Vec<u8>>> {
+        let data = crate::container::read_apk_entry(std::fs::File::open(path)?, entry_name)?;
+        self.read_vec(data)
+    }
+
+    /// Loads a `Dex` from any `DexSource`, such as a `ChunkedSource` which doesn't store
+    /// the file contiguously.
+    pub fn read_source<S: DexSource>(&self, source: S) -> Result<Dex<S>> {
+        let source = Source::new(source);
+        let inner = DexInner::read(&source, self.verify, self.log_label.as_deref())?;
+        self.build(source, inner)
+    }
+
+    fn build<T: DexSource>(&self, source: Source<T>, inner: DexInner) -> Result<Dex<T>> {
+        let file_end = source.len() as uint;
+        let map_list = inner.map_list();
+        if self.strict_alignment {
+            if let Some((item_type, offset)) = map_list.misaligned_items().next() {
+                return Err(Error::Misaligned(item_type, offset));
+            }
+        }
+        let timers = Timers::new();
+        let cache_bypass = Bypass::default();
+        let snapshots = Snapshots::new(self.snapshot, cache_bypass.clone());
+        let cache = Strings::new(
+            source.clone(),
+            &inner,
+            &self.cache_config,
+            cache_bypass.clone(),
+            snapshots.clone(),
+            timers.clone(),
+        );
+        let annotation_items =
+            ItemBoundaries::new(map_list.section(ItemType::AnnotationItem, file_end));
+        let encoded_arrays =
+            ItemBoundaries::new(map_list.section(ItemType::EncodedArrayItem, file_end));
+        let dex = Dex {
+            source,
+            strings: cache,
+            inner: Shared::new(inner),
+            annotation_items: Shared::new(annotation_items),
+            encoded_arrays: Shared::new(encoded_arrays),
+            types: Cache::new(
+                self.cache_config.types,
+                self.cache_config.max_bytes,
+                cache_bypass.clone(),
+            ),
+            annotations_directories: Cache::new(
+                self.cache_config.annotations_directories,
+                self.cache_config.max_bytes,
+                cache_bypass.clone(),
+            ),
+            descriptors: Cache::new(
+                self.cache_config.descriptors,
+                self.cache_config.max_bytes,
+                cache_bypass.clone(),
+            ),
+            directory_loader: OnceCell::new(),
+            eager_annotations: self.eager_annotations,
+            strict_alignment: self.strict_alignment,
+            strict_descriptors: self.strict_descriptors,
+            lenient_class_data: self.lenient_class_data,
+            track_spans: self.track_spans,
+            skip_code: self.skip_code,
+            #[cfg(feature = "debug-info")]
+            skip_debug_info: self.skip_debug_info,
+            skip_annotations: self.skip_annotations,
+            limits: self.limits,
+            class_defs_by_type: Shared::new(OnceCell::new()),
+            class_names: Shared::new(OnceCell::new()),
+            hierarchy: Shared::new(OnceCell::new()),
+            proto_ids: Shared::new(OnceCell::new()),
+            partial_indexes: Shared::new(RefCell::new(PartialIndexes::default())),
+            found_diagnostics: Shared::default(),
+            deadline: Shared::default(),
+            cache_bypass,
+            snapshots,
+            timers,
+        };
+        dex.build_indexes(self.eager)?;
+        Ok(dex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Result;
+    use crate::test_utils::{fix_checksum, minimal_dex, mutated_example_dex, uleb128_len};
+    use scroll::{Pread, Pwrite};
+    use std::path::Path;
+
+    #[test]
+    fn test_find_class_by_name() {
+        let dex =
+            super::DexReader::from_file("resources/classes.dex").expect("cannot open dex file");
+        let mut count = 0;
+        for class_def in dex.class_defs() {
+            let class_def = class_def.expect("can't load class");
+            let jtype = dex.get_type(class_def.class_idx()).expect("bad type");
+            let result = dex.find_class_by_name(&jtype.type_descriptor().to_string());
+            assert!(result.is_ok());
+            assert!(result.unwrap().is_some());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_find_class_by_descriptor_cached() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let find = |descriptor: &str| {
+            dex.find_class(descriptor)
+                .expect("bad class")
+                .map(|class| class.jtype().to_string())
+        };
+        let counts = |dex: &super::Dex<_>| {
+            let stats = dex.cache_stats().descriptors();
+            (stats.entries(), stats.hits(), stats.misses())
+        };
+        let launcher = "Lorg/adw/launcher/Launcher;";
+        assert_eq!(find(launcher).as_deref(), Some(launcher));
+        // a framework type exists but has no class_def, a missing one isn't even a string
+        assert_eq!(find("Landroid/app/Activity;"), None);
+        assert_eq!(find("Lcom/example/Missing;"), None);
+        assert_eq!(counts(&dex), (3, 0, 3));
+
+        assert_eq!(find(launcher).as_deref(), Some(launcher));
+        assert_eq!(find("Landroid/app/Activity;"), None);
+        assert_eq!(find("Lcom/example/Missing;"), None);
+        assert_eq!(counts(&dex), (3, 3, 3));
+
+        let bypassed = dex.with_cache_bypassed(|dex| dex.find_class(launcher));
+        assert!(bypassed.expect("bad class").is_some());
+        assert_eq!(counts(&dex), (3, 3, 3));
+        let mut dex = dex;
+        dex.clear_caches();
+        assert_eq!(counts(&dex), (0, 3, 3));
+    }
+
+    #[test]
+    fn test_header_version() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let header = dex.header();
+        assert_eq!(&header.magic(), b"dex\n035\0");
+        assert_eq!(header.dex_version().unwrap(), super::DexVersion::V035);
+        assert_eq!(header.dex_version().unwrap().to_string(), "035");
+        assert_eq!(header.endian_tag(), [0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(header.header_size(), 0x70);
+        assert_eq!(header.file_size() as usize, dex.source.len());
+        assert!(std::ptr::eq(header, dex.header()));
+
+        for (magic, version) in [
+            (b"dex\n039\0", Some(super::DexVersion::V039)),
+            (b"dex\n041\0", Some(super::DexVersion::V041)),
+            (b"dex\n036\0", None),
+            (b"dex\n04\0\0", None),
+            (b"dey\n035\0", None),
+        ] {
+            let data = mutated_example_dex(|data| data[..8].copy_from_slice(magic));
+            let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+            match version {
+                Some(version) => assert_eq!(dex.header().dex_version().unwrap(), version),
+                None => assert_malformed(dex.header().dex_version()),
+            }
+        }
+    }
+
+    fn load_example_dex_as_vec<P: AsRef<Path>>(file: P) -> Result<Vec<u8>> {
+        Ok(std::fs::read(file)?)
+    }
+
+    #[test]
+    fn test_find_class_by_name_from_vec() {
+        let data: Vec<u8> = load_example_dex_as_vec("resources/classes.dex")
+            .expect("Cannot load example file to a vec");
+        let dex = super::DexReader::from_vec(data).expect("Cannot parse dex from vec");
+        let mut count = 0;
+        for class_def in dex.class_defs() {
+            let class_def = class_def.expect("can't load class");
+            let jtype = dex.get_type(class_def.class_idx()).expect("bad type");
+            let result = dex.find_class_by_name(&jtype.type_descriptor().to_string());
+            assert!(result.is_ok());
+            assert!(result.unwrap().is_some());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_find_class_by_java_name() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let find = |name: &str| {
+            dex.find_class_by_name(name)
+                .expect("bad class")
+                .map(|class| class.jtype().to_string())
+        };
+        for name in &[
+            "org.adw.launcher.Launcher",
+            "org.adw.launcher.Launcher$10",
+            "org/adw/launcher/Launcher$10",
+            "Lorg/adw/launcher/Launcher$10;",
+        ] {
+            assert_eq!(
+                find(name).as_deref(),
+                Some(if name.contains('$') {
+                    "Lorg/adw/launcher/Launcher$10;"
+                } else {
+                    "Lorg/adw/launcher/Launcher;"
+                }),
+                "{}",
+                name
+            );
+        }
+        // the type exists but is defined in another file
+        assert!(dex
+            .get_type_from_descriptor("Ljava/lang/Object;")
+            .unwrap()
+            .is_some());
+        assert_eq!(find("java.lang.Object"), None);
+        assert_eq!(find("org.adw.launcher.Missing"), None);
+        // arrays are never defined by a class_def
+        assert!(dex.get_type_from_descriptor("[I").unwrap().is_some());
+        assert_eq!(find("[I"), None);
+        assert_eq!(find("int[]"), None);
+        assert_eq!(find("org.adw.launcher.Launcher[]"), None);
+        assert!(dex
+            .find_class_by_descriptor("[Lorg/adw/launcher/Launcher;")
+            .unwrap()
+            .is_none());
+        assert!(dex
+            .find_class_by_descriptor("org.adw.launcher.Launcher")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_get_type_from_descriptor() {
+        let dex =
+            super::DexReader::from_file("resources/classes.dex").expect("cannot open dex file");
+        let jtype = dex.get_type_from_descriptor("Lorg/adw/launcher/Launcher;");
+        assert!(jtype.is_ok());
+        let jtype = jtype.unwrap();
+        assert!(jtype.is_some());
+        let jtype = jtype.unwrap();
+        assert_eq!(jtype.type_descriptor(), "Lorg/adw/launcher/Launcher;")
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_implied_permissions() {
+        let dex =
+            super::DexReader::from_file("resources/classes.dex").expect("cannot open dex file");
+        let mapping = [
+            (
+                "Landroid/app/WallpaperManager;->setBitmap(Landroid/graphics/Bitmap;)V",
+                "android.permission.SET_WALLPAPER",
+            ),
+            (
+                "Landroid/app/WallpaperManager;->setResource(I)V",
+                "android.permission.SET_WALLPAPER",
+            ),
+            (
+                "Landroid/app/WallpaperManager;->setResource(J)V",
+                "android.permission.UNUSED",
+            ),
+            ("Landroid/net/Uri$*", "android.permission.URI_BUILDER"),
+            ("Landroid/net/Uri;", "android.permission.URI"),
+            ("Landroid/os/Vibrator;", "android.permission.VIBRATE"),
+            // classes defined in the dex never imply permissions
+            ("Lorg/adw/launcher/*", "android.permission.INTERNAL"),
+        ];
+        let implied = dex.implied_permissions(&mapping).expect("failed to match");
+        assert_eq!(
+            implied.keys().collect::<Vec<_>>(),
+            [
+                "android.permission.SET_WALLPAPER",
+                "android.permission.URI",
+                "android.permission.URI_BUILDER"
+            ]
+        );
+        let wallpaper: Vec<_> = implied["android.permission.SET_WALLPAPER"]
+            .iter()
+            .map(|method| method.to_string())
+            .collect();
+        // the methods calling the APIs, not the APIs themselves
+        assert_eq!(
+            wallpaper,
+            [
+                "Lorg/adw/launcher/MyLauncherSettings;->applyTheme(Landroid/view/View;)V",
+                "Lorg/adw/launcher/WallpaperChooser;->selectWallpaper(I)V"
+            ]
+        );
+        let uri_builder: Vec<_> = implied["android.permission.URI_BUILDER"]
+            .iter()
+            .map(|method| method.to_string())
+            .collect();
+        assert_eq!(
+            uri_builder,
+            ["Lorg/adw/launcher/LiveFolder;->onItemClick(Landroid/widget/AdapterView;Landroid/view/View;IJ)V"]
+        );
+        let uri = &implied["android.permission.URI"];
+        assert_eq!(uri.len(), 24);
+        assert!(uri.iter().any(|method| method.to_string()
+            == "Lorg/adw/launcher/LauncherProvider;->sendNotify(Landroid/net/Uri;)V"));
+        // each calling method is listed once, even with several calls
+        let mut ids: Vec<_> = uri.iter().map(|method| method.id()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), uri.len());
+        assert!(uri
+            .iter()
+            .all(|method| !method.class().type_descriptor().starts_with("Landroid/")));
+    }
+
+    const FIRST_ANNOTATION_ITEM: usize = 0x805fd;
+    const FIRST_ENCODED_ARRAY_ITEM: usize = 0x81bf9;
+
+    fn assert_malformed<T: std::fmt::Debug>(result: Result<T>) {
+        match result {
+            Err(super::Error::MalFormed(_)) => {}
+            other => panic!("expected a malformed item error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_annotation_item_bounded() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        assert!(dex
+            .get_annotation_item(FIRST_ANNOTATION_ITEM as u32)
+            .is_ok());
+
+        let data = mutated_example_dex(|data| {
+            // visibility, type_idx, then the element count
+            let size_off = FIRST_ANNOTATION_ITEM + 1 + uleb128_len(data, FIRST_ANNOTATION_ITEM + 1);
+            assert_eq!(uleb128_len(data, size_off), 1);
+            data[size_off] = 0x7f;
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        assert_malformed(dex.get_annotation_item(FIRST_ANNOTATION_ITEM as u32));
+    }
+
+    /// Debug form of the annotations of every annotated field and method of the file, as
+    /// listed in the annotations directories.
+    fn member_annotations(dex: &super::Dex<Vec<u8>>) -> Vec<(bool, u64, String)> {
+        let mut annotations = Vec::new();
+        for class_def in dex.class_defs() {
+            let class_def = class_def.expect("bad class def");
+            if class_def.annotations_off == 0 {
+                continue;
+            }
+            let directory = dex
+                .get_annotations_directory_item(class_def.annotations_off)
+                .expect("bad annotations directory");
+            for field in &directory.field_annotations {
+                annotations.push((
+                    true,
+                    field.field_idx(),
+                    format!("{:?}", field.annotations()),
+                ));
+            }
+            for method in &directory.method_annotations {
+                annotations.push((
+                    false,
+                    method.method_idx(),
+                    format!("{:?}", method.annotations()),
+                ));
+            }
+        }
+        annotations
+    }
+
+    #[test]
+    fn test_member_annotations_skip_class_data() {
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let expected = member_annotations(&dex);
+        assert!(expected.iter().any(|(is_field, _, _)| !is_field));
+
+        // point every class at class data outside of the file: loading any class fails, but
+        // member annotations only need the annotations directory.
+        let data = mutated_example_dex(|data| {
+            let header: super::Header = data.pread(0).unwrap();
+            for i in 0..header.class_defs_size() as usize {
+                let off = header.class_defs_off() as usize + i * 32 + 24;
+                data[off..off + 4].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+            }
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        assert!(dex.classes().all(|class| class.is_err()));
+        for (is_field, id, annotations) in expected {
+            let found = if is_field {
+                dex.annotations_for_field(id)
+            } else {
+                dex.annotations_for_method(id)
+            };
+            let found = found.expect("bad annotations").expect("no annotations");
+            assert_eq!(format!("{:?}", found), annotations);
+        }
+    }
+
+    #[test]
+    fn test_shared_offsets_report() {
+        use crate::sharing::{Owner, SharedItemKind, Sharing};
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let report = dex.shared_offsets_report().expect("bad report");
+        assert_eq!(report.suspicious().count(), 0);
+        let set = report
+            .entries()
+            .iter()
+            .find(|entry| entry.kind() == SharedItemKind::AnnotationSet)
+            .expect("no shared annotation set");
+        assert_eq!(set.sharing(), Sharing::Deduplicated);
+        assert!(set.owners().len() > 1);
+        assert_eq!(report.get(set.kind(), set.offset()), Some(set));
+
+        // make the second class with class data share the class data of the first one
+        let mut classes: Vec<u32> = Vec::new();
+        let data = mutated_example_dex(|data| {
+            let header: super::Header = data.pread(0).unwrap();
+            let offsets: Vec<usize> = (0..header.class_defs_size() as usize)
+                .map(|i| header.class_defs_off() as usize + i * 32)
+                .filter(|off| data.pread::<u32>(off + 24).unwrap() != 0)
+                .take(2)
+                .collect();
+            let class_data_off: u32 = data.pread(offsets[0] + 24).unwrap();
+            data[offsets[1] + 24..offsets[1] + 28].copy_from_slice(&class_data_off.to_le_bytes());
+            classes = offsets
+                .iter()
+                .map(|off| data.pread(*off).unwrap())
+                .collect();
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let report = dex.shared_offsets_report().expect("bad report");
+        let class_data = report
+            .suspicious()
+            .find(|entry| entry.kind() == SharedItemKind::ClassData)
+            .expect("shared class data not reported");
+        assert_eq!(
+            class_data.owners(),
+            &[Owner::Class(classes[0]), Owner::Class(classes[1])]
+        );
+        let code = report
+            .suspicious()
+            .find(|entry| entry.kind() == SharedItemKind::Code)
+            .expect("shared code not reported");
+        let owners: Vec<_> = code.owners().iter().map(|owner| owner.class()).collect();
+        assert_eq!(owners, classes);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_producer_fingerprint() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let fingerprint = dex.producer_fingerprint().expect("bad fingerprint");
+        assert_eq!(fingerprint.version(), "035");
+        assert_eq!(fingerprint.header_size(), 0x70);
+        assert_eq!(fingerprint.endian_tag(), [0x78, 0x56, 0x34, 0x12]);
+        assert!(fingerprint.map_sorted());
+        assert_eq!(
+            fingerprint.map_order_signature(),
+            "0,1,2,3,4,5,6,1003,2001,2006,1001,2002,2003,2004,2005,2000,1000"
+        );
+        assert_eq!(fingerprint.padding_bytes().keys().collect::<Vec<_>>(), [&0]);
+        assert_eq!(fingerprint.visibilities().get(&2), Some(&389));
+        assert_eq!(fingerprint.reserved_access_flags(), 0);
+        assert_eq!(fingerprint.nonminimal_lebs(), 0);
+        assert!(fingerprint.markers().is_empty());
+        let text = fingerprint.to_string();
+        assert_eq!(text, dex.producer_fingerprint().unwrap().to_string());
+        assert!(text.contains("visibilities: 0x01=2,0x02=389\n"));
+
+        // list the map items 7 and 8 out of order and set a reserved flag of the first class
+        let reserved = !crate::class::AccessFlags::all().bits();
+        let reserved = reserved & reserved.wrapping_neg();
+        let data = mutated_example_dex(|data| {
+            let header: super::Header = data.pread(0).unwrap();
+            let items = header.map_off() as usize + 4;
+            let (first, second) = data[items + 7 * 12..items + 9 * 12].split_at_mut(12);
+            first.swap_with_slice(second);
+            let flags = header.class_defs_off() as usize + 4;
+            let access_flags: u32 = data.pread(flags).unwrap();
+            data[flags..flags + 4].copy_from_slice(&(access_flags | reserved).to_le_bytes());
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let mutated = dex.producer_fingerprint().expect("bad fingerprint");
+        assert!(!mutated.map_sorted());
+        assert!(mutated.map_order_signature().contains("6,2001,1003,2006"));
+        assert_eq!(mutated.reserved_access_flags(), 1);
+        assert_eq!(mutated.padding_bytes(), fingerprint.padding_bytes());
+    }
+
+    #[test]
+    fn test_misaligned_items() {
+        use super::{DexReaderBuilder, ItemType};
+        use crate::{uint, ulong, ushort, ErrorKind};
+
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dex = super::DexReader::from_vec(example.clone()).expect("cannot open dex");
+        assert!(dex.misaligned_items().expect("bad walk").is_empty());
+        let (index, class_def) = dex
+            .class_defs()
+            .map(|class_def| class_def.expect("bad class_def"))
+            .enumerate()
+            .find(|(_, class_def)| class_def.interfaces_off != 0 && class_def.annotations_off != 0)
+            .expect("no class with interfaces and annotations");
+        let class_data = dex
+            .get_class_data(class_def.class_data_off)
+            .unwrap()
+            .expect("no class data");
+        let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+        let code_off = methods
+            .iter()
+            .flatten()
+            .flat_map(|methods| methods.inner())
+            .map(|method| *method.code_offset())
+            .find(|code_off| *code_off != 0)
+            .expect("no method with code") as usize;
+        let code = dex.get_code_item(code_off as ulong).unwrap().unwrap();
+        let interfaces = dex.get_interfaces(class_def.interfaces_off).unwrap();
+        let directory = dex
+            .get_annotations_directory_item(class_def.annotations_off)
+            .unwrap();
+
+        // copy the items at the end of the data section, 2 bytes past a 4 byte boundary
+        let mut data = example.clone();
+        let append = |data: &mut Vec<u8>, start: usize, len: usize| {
+            data.resize(data.len().div_ceil(4) * 4 + 2, 0);
+            let offset = data.len();
+            data.extend_from_slice(&example[start..start + len]);
+            offset as uint
+        };
+        let code_len = 16 + code.insns.len() * 2;
+        assert!(code.tries.is_empty());
+        let misaligned_code = append(&mut data, code_off, code_len);
+        let interfaces_off = class_def.interfaces_off as usize;
+        let type_list = append(&mut data, interfaces_off, 4 + 2 * interfaces.len());
+        let directory_off = class_def.annotations_off as usize;
+        let directory_len = 16
+            + 8 * (directory.field_annotations.len()
+                + directory.method_annotations.len()
+                + directory.parameter_annotations.len());
+        let misaligned_directory = append(&mut data, directory_off, directory_len);
+        let set_off: uint = example.pread(directory_off).unwrap();
+        assert_ne!(set_off, 0);
+        let set_len = 4 + 4 * directory.class_annotations.annotations().len();
+        let misaligned_set = append(&mut data, set_off as usize, set_len);
+        data.pwrite(misaligned_set, misaligned_directory as usize)
+            .unwrap();
+        let class_def_off = dex.header().class_defs_off() as usize + index * 32;
+        data.pwrite(type_list, class_def_off + 12).unwrap();
+        data.pwrite(misaligned_directory, class_def_off + 20)
+            .unwrap();
+        data.resize(data.len().div_ceil(4) * 4, 0);
+        let file_size = data.len() as uint;
+        data.pwrite(file_size, 32).unwrap();
+        data.pwrite(file_size - dex.header().data_off(), 104)
+            .unwrap();
+        fix_checksum(&mut data);
+
+        // lenient by default
+        let dex = super::DexReader::from_vec(data.clone()).expect("cannot open dex");
+        assert!(dex.warnings().is_empty());
+        let copy = dex
+            .get_code_item(misaligned_code as ulong)
+            .unwrap()
+            .unwrap();
+        assert_eq!(copy.insns, code.insns);
+        assert_eq!(dex.get_interfaces(type_list).unwrap(), interfaces);
+        let class = dex
+            .get_class_by_type(class_def.class_idx)
+            .unwrap()
+            .expect("class not found");
+        assert_eq!(
+            class.annotations().unwrap().annotations().len(),
+            directory.class_annotations.annotations().len()
+        );
+        let misaligned: Vec<_> = dex
+            .misaligned_items()
+            .unwrap()
+            .iter()
+            .map(|item| (item.item_type(), item.offset(), item.excess()))
+            .collect();
+        assert_eq!(
+            misaligned,
+            [
+                (ItemType::TypeList, type_list, 2),
+                (ItemType::AnnotationsDirectoryItem, misaligned_directory, 2),
+                (ItemType::AnnotationSetItem, misaligned_set, 2),
+            ]
+        );
+
+        // strict
+        let strict = DexReaderBuilder::new()
+            .strict_alignment(true)
+            .read_vec(data.clone())
+            .expect("cannot open dex");
+        let e = strict.get_code_item(misaligned_code as ulong).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Misaligned);
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "CodeItem at offset {} is not 4 byte aligned",
+                misaligned_code
+            )
+        );
+        assert!(matches!(
+            strict.get_interfaces(type_list),
+            Err(super::Error::Misaligned(ItemType::TypeList, offset)) if offset == type_list
+        ));
+        assert!(matches!(
+            strict.get_annotation_set_item(misaligned_set),
+            Err(super::Error::Misaligned(ItemType::AnnotationSetItem, _))
+        ));
+        assert_eq!(strict.misaligned_items().unwrap().len(), misaligned.len());
+        assert!(strict.get_code_item(code_off as ulong).is_ok());
+
+        // a misaligned section of the map list is a warning, or an error in strict mode
+        let data = mutated_example_dex(|data| {
+            let header: super::Header = data.pread(0).unwrap();
+            let items = header.map_off() as usize + 4;
+            let count: uint = data.pread(header.map_off() as usize).unwrap();
+            let code_items = (0..count as usize)
+                .map(|index| items + index * 12)
+                .find(|item| data.pread::<ushort>(*item).unwrap() == 0x2001)
+                .unwrap();
+            let offset: uint = data.pread(code_items + 8).unwrap();
+            data.pwrite(offset + 2, code_items + 8).unwrap();
+        });
+        let dex = super::DexReader::from_vec(data.clone()).expect("cannot open dex");
+        assert_eq!(dex.warnings().len(), 1);
+        assert!(dex.warnings()[0].starts_with("map_list item CodeItem is misaligned at offset "));
+        assert_eq!(
+            dex.misaligned_items().unwrap()[0].item_type(),
+            ItemType::CodeItem
+        );
+        let e = DexReaderBuilder::new()
+            .strict_alignment(true)
+            .read_vec(data.clone())
+            .err()
+            .expect("misaligned section accepted");
+        assert_eq!(e.kind(), ErrorKind::Misaligned);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_class_initializers() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let initializers: Vec<_> = dex
+            .class_initializers()
+            .collect::<super::Result<_>>()
+            .expect("bad initializers");
+        let find = |descriptor: &str| {
+            initializers
+                .iter()
+                .find(|initializer| *initializer.method().class() == descriptor)
+        };
+        let trivial = find("Lorg/adw/launcher/LauncherActions;").expect("no initializer");
+        assert!(trivial.trivial());
+        assert_eq!(trivial.insns_size(), 4);
+        assert_eq!(trivial.invokes(), 0);
+        let non_trivial = find("Lorg/metalev/multitouch/controller/MultiTouchController;")
+            .expect("no initializer");
+        assert!(!non_trivial.trivial());
+        assert_eq!(non_trivial.invokes(), 13);
+        assert!(find("Lorg/adw/launcher/ActionButton;").is_none());
+
+        let mut with_clinit = Vec::new();
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            assert_eq!(class.has_clinit(), class.clinit().is_some());
+            if let Some(clinit) = class.clinit() {
+                assert_eq!(*clinit.name(), *crate::well_known::STATIC_INITIALIZER);
+                let insns = clinit.code().map_or(0, |code| code.insns.len());
+                with_clinit.push((class.jtype().to_string(), insns as u32));
+            }
+        }
+        let expected: Vec<_> = initializers
+            .iter()
+            .map(|i| (i.method().class().to_string(), i.insns_size()))
+            .collect();
+        assert_eq!(with_clinit, expected);
+        assert_eq!(with_clinit.len(), 24);
+    }
+
+    #[test]
+    fn test_classes_catching() {
+        use crate::code::ExceptionType;
+        use std::collections::{BTreeMap, BTreeSet};
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut catching: BTreeMap<String, BTreeSet<u64>> = BTreeMap::new();
+        let mut catch_all = 0;
+        let mut code_offsets = Vec::new();
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let types = method.caught_exception_types();
+                for (index, exception) in types.iter().enumerate() {
+                    assert!(!types[index + 1..].contains(exception), "duplicate type");
+                    match exception {
+                        ExceptionType::Ty(jtype) => {
+                            catching
+                                .entry(jtype.type_descriptor().to_string())
+                                .or_default()
+                                .insert(method.id());
+                        }
+                        ExceptionType::BaseException => catch_all += 1,
+                    }
+                }
+            }
+        }
+        assert!(catch_all > 0);
+        let (descriptor, expected) = catching
+            .iter()
+            .max_by_key(|(_, methods)| methods.len())
+            .expect("no handlers");
+        assert!(expected.len() > 1);
+        let found = dex.classes_catching(descriptor).expect("cannot scan");
+        assert_eq!(found.iter().cloned().collect::<BTreeSet<_>>(), *expected);
+        for (descriptor, expected) in &catching {
+            assert_eq!(
+                dex.classes_catching(descriptor).unwrap().len(),
+                expected.len()
+            );
+        }
+        assert!(dex.classes_catching("Lno/Such;").unwrap().is_empty());
+
+        // the instructions are never read: overwriting them doesn't change the result
+        for class_def in dex.class_defs() {
+            if let Some(class_data) = dex
+                .get_class_data(class_def.unwrap().class_data_off)
+                .unwrap()
+            {
+                let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+                for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                    if *method.code_offset() != 0 {
+                        code_offsets.push(*method.code_offset() as usize);
+                    }
+                }
+            }
+        }
+        let poisoned = mutated_example_dex(|data| {
+            for code_off in code_offsets {
+                let insns_size: u32 = data.pread(code_off + 12).unwrap();
+                let insns = code_off + 16..code_off + 16 + insns_size as usize * 2;
+                data[insns].iter_mut().for_each(|byte| *byte = 0xff);
+            }
+        });
+        let poisoned = super::DexReader::from_vec(poisoned).expect("cannot open dex");
+        assert_eq!(poisoned.classes_catching(descriptor).unwrap(), found);
+        #[cfg(feature = "parse-timing")]
+        assert_eq!(
+            poisoned.parse_timings().code_items,
+            std::time::Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_eager_sections() {
+        use super::EagerSections;
+        let name = "Lorg/adw/launcher/Launcher;";
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        assert_eq!(dex.built_indexes(), EagerSections::empty());
+        let class = dex.find_class_by_name(name).expect("bad class");
+        assert!(class.is_some());
+        let built = dex.built_indexes();
+        assert!(built.contains(EagerSections::TYPE_TO_CLASS_DEF | EagerSections::STRING_META));
+        assert!(
+            !built.intersects(EagerSections::CLASS_NAME_INDEX | EagerSections::PROTO_CACHE_FULL)
+        );
+
+        let eager = super::DexReader::builder()
+            .eager(EagerSections::all())
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        assert_eq!(eager.built_indexes(), EagerSections::all());
+        let eager_class = eager.find_class_by_name(name).expect("bad class");
+        assert_eq!(eager_class.map(|c| c.id()), class.map(|c| c.id()));
+        // a type which is not defined in the file is found in the strings, but not the index
+        assert!(dex
+            .get_type_from_descriptor("Ljava/lang/Object;")
+            .unwrap()
+            .is_some());
+        assert!(eager
+            .find_class_by_name("Ljava/lang/Object;")
+            .unwrap()
+            .is_none());
+        let protos = dex
+            .proto_ids()
+            .collect::<Result<Vec<_>>>()
+            .expect("bad protos");
+        for (id, proto) in protos.iter().enumerate() {
+            assert_eq!(&eager.get_proto_item(id as u64).expect("bad proto"), proto);
+        }
+        assert!(eager.get_proto_item(protos.len() as u64).is_err());
+    }
+
+    #[test]
+    fn test_member_annotations_external_class() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let defined: std::collections::HashSet<_> = dex
+            .class_defs()
+            .map(|class_def| class_def.expect("bad class def").class_idx)
+            .collect();
+        let external = dex
+            .method_ids()
+            .map(|method| method.expect("bad method id"))
+            .find(|method| !defined.contains(&u32::from(method.class_idx())))
+            .expect("no external method");
+        assert!(dex
+            .annotations_for_method(external.id())
+            .expect("bad annotations")
+            .is_none());
+        let unannotated = dex
+            .class_defs()
+            .map(|class_def| class_def.expect("bad class def"))
+            .find(|class_def| class_def.annotations_off == 0)
+            .expect("no class without annotations");
+        let method = dex
+            .method_ids()
+            .map(|method| method.expect("bad method id"))
+            .find(|method| u32::from(method.class_idx()) == unannotated.class_idx)
+            .expect("no method of the class");
+        assert!(dex
+            .annotations_for_method(method.id())
+            .expect("bad annotations")
+            .is_none());
+    }
+
+    #[test]
+    fn test_nonminimal_leb_sites() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        assert!(dex.nonminimal_leb_sites().expect("scan failed").is_empty());
+
+        let mut data = minimal_dex(&["a", "bc", "d"], 0);
+        let string_data_off = data.pread_with::<u32>(0x70 + 4, scroll::LE).unwrap() as usize;
+        // "bc" becomes "b" with its length padded to two bytes
+        assert_eq!(&data[string_data_off..string_data_off + 4], b"\x02bc\0");
+        data[string_data_off..string_data_off + 4].copy_from_slice(b"\x81\x00b\0");
+        fix_checksum(&mut data);
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        assert_eq!(&*dex.get_string(1).expect("bad string"), "b");
+        let sites = dex.nonminimal_leb_sites().expect("scan failed");
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].offset(), string_data_off);
+        assert_eq!((sites[0].encoded_len(), sites[0].minimal_len()), (2, 1));
+
+        // an annotation item, an encoded array and a debug info item, appended after the
+        // map list with their own map entries
+        let items: [(u16, &[u8]); 3] = [
+            // visibility, type_idx padded to two bytes, no elements
+            (0x2004, &[0x01, 0x80, 0x00, 0x00]),
+            // one element padded to two bytes: an array of no elements padded as well
+            (0x2005, &[0x81, 0x00, 0x1c, 0x80, 0x00]),
+            // line_start, no parameters, DBG_ADVANCE_LINE -1 padded to two bytes, the end
+            (0x2003, &[0x01, 0x00, 0x02, 0xff, 0x7f, 0x00]),
+        ];
+        let mut data = minimal_dex(&["a"], 0);
+        let map_off = data.pread_with::<u32>(52, scroll::LE).unwrap() as usize;
+        let map_len = data.pread_with::<u32>(map_off, scroll::LE).unwrap() as usize;
+        let entries_end = map_off + 4 + 12 * map_len;
+        assert_eq!(entries_end, data.len());
+        data.pwrite_with((map_len + items.len()) as u32, map_off, scroll::LE)
+            .unwrap();
+        let mut item_off = entries_end + 12 * items.len();
+        let mut item_offs = Vec::new();
+        for (item_type, bytes) in &items {
+            let mut entry = [0u8; 12];
+            entry.pwrite_with(*item_type, 0, scroll::LE).unwrap();
+            entry.pwrite_with(1u32, 4, scroll::LE).unwrap();
+            entry.pwrite_with(item_off as u32, 8, scroll::LE).unwrap();
+            data.extend_from_slice(&entry);
+            item_offs.push(item_off);
+            item_off += bytes.len();
+        }
+        for (_, bytes) in &items {
+            data.extend_from_slice(bytes);
+        }
+        let data_off = data.pread_with::<u32>(108, scroll::LE).unwrap() as usize;
+        let file_size = data.len() as u32;
+        data.pwrite_with(file_size, 32, scroll::LE).unwrap();
+        data.pwrite_with(file_size - data_off as u32, 104, scroll::LE)
+            .unwrap();
+        fix_checksum(&mut data);
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let sites: Vec<_> = dex
+            .nonminimal_leb_sites()
+            .expect("scan failed")
+            .iter()
+            .map(|site| (site.offset(), site.signed()))
+            .collect();
+        let mut expected = vec![
+            (item_offs[0] + 1, false),
+            (item_offs[1], false),
+            (item_offs[1] + 3, false),
+        ];
+        if cfg!(feature = "debug-info") {
+            expected.push((item_offs[2] + 3, true));
+        }
+        assert_eq!(sites, expected);
+    }
+
+    #[test]
+    fn test_into_background_drop() {
+        use crate::{sync::Shared, test_utils::minimal_dex, CacheConfig};
+        use std::time::Instant;
+
+        let strings: Vec<String> = (0..200_000).map(|i| format!("string{:06}", i)).collect();
+        let strings: Vec<&str> = strings.iter().map(String::as_str).collect();
+        let data = minimal_dex(&strings, 0);
+        let warmed = || {
+            let dex = super::DexReaderBuilder::new()
+                .cache_config(CacheConfig::new().strings(strings.len()))
+                .read_vec(data.clone())
+                .expect("cannot open dex");
+            for string in dex.strings() {
+                string.expect("bad string");
+            }
+            assert_eq!(dex.cache_stats().strings().entries(), strings.len());
+            dex
+        };
+
+        // freeing the caches takes longer than handing them over to another thread
+        let dex = warmed();
+        let start = Instant::now();
+        drop(dex);
+        let synchronous = start.elapsed();
+        let dex = warmed();
+        let inner = Shared::downgrade(&dex.inner);
+        let start = Instant::now();
+        let dropped = dex.into_background_drop();
+        let foreground = start.elapsed();
+        dropped.join().expect("drop panicked");
+        assert!(inner.upgrade().is_none());
+        assert!(
+            foreground < synchronous,
+            "{:?} >= {:?}",
+            foreground,
+            synchronous
+        );
+
+        // the parts shared with another handle outlive the background drop
+        let dex = warmed();
+        let clone = dex.clone();
+        dex.into_background_drop().join().expect("drop panicked");
+        assert_eq!(clone.cache_stats().strings().entries(), strings.len());
+        assert_eq!(&*clone.get_string(5).expect("bad string"), "string000005");
+    }
+
+    #[test]
+    fn test_clear_caches() {
+        use super::EagerSections;
+        let mut dex =
+            super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let kept = dex.get_string(100).expect("bad string");
+        for string in dex.strings() {
+            string.expect("bad string");
+        }
+        for class in dex.classes() {
+            class.expect("bad class");
+        }
+        dex.build_indexes(EagerSections::CLASS_NAME_INDEX | EagerSections::PROTO_CACHE_FULL)
+            .expect("cannot build indexes");
+        let before = dex.cache_stats();
+        assert!(before.strings().entries() > 0);
+        assert!(before.annotations_directories().entries() > 0);
+
+        dex.clear_caches();
+        let after = dex.cache_stats();
+        for (before, after) in [
+            (before.strings(), after.strings()),
+            (
+                before.annotations_directories(),
+                after.annotations_directories(),
+            ),
+        ] {
+            assert_eq!(after.entries(), 0);
+            assert_eq!(after.weight(), 0);
+            assert_eq!(after.hits(), before.hits());
+            assert_eq!(after.misses(), before.misses());
+        }
+        assert_eq!(dex.strings.cached_len(), 0);
+        assert!(!dex
+            .built_indexes()
+            .contains(EagerSections::CLASS_NAME_INDEX));
+        assert!(!dex
+            .built_indexes()
+            .contains(EagerSections::PROTO_CACHE_FULL));
+
+        // handed out items stay valid and the caches fill again
+        assert_eq!(dex.get_string(100).expect("bad string"), kept);
+        assert_eq!(
+            dex.cache_stats().strings().misses(),
+            before.strings().misses() + 1
+        );
+        assert_eq!(dex.cache_stats().strings().entries(), 1);
+        assert!(dex.classes().all(|class| class.is_ok()));
+    }
+
+    #[test]
+    fn test_cache_bypass() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let cached = dex.get_string(100).expect("bad string");
+        assert_eq!(dex.strings.cached_len(), 1);
+        for id in 0..50 {
+            let fresh = dex.get_string_uncached(id).expect("bad string");
+            assert_eq!(fresh, dex.get_string(id).expect("bad string"));
+        }
+        assert_eq!(dex.strings.cached_len(), 51);
+
+        let directory_off = dex
+            .class_defs()
+            .map(|class_def| class_def.expect("bad class def").annotations_off)
+            .find(|&off| off != 0)
+            .expect("no annotations directory");
+        let fresh = dex
+            .get_annotations_directory_item_uncached(directory_off)
+            .expect("bad directory");
+        assert!(dex.annotations_directories.entries().is_empty());
+        let shared = dex
+            .get_shared_annotations_directory_item(directory_off)
+            .expect("bad directory");
+        assert!(!crate::sync::Shared::ptr_eq(&fresh, &shared));
+
+        // nested scopes restore the enclosing state
+        let cached_len = dex.strings.cached_len();
+        dex.with_cache_bypassed(|dex| {
+            dex.with_cache_bypassed(|dex| dex.get_string(200).expect("bad string"));
+            assert!(dex.cache_bypass.is_enabled());
+            assert!(!crate::sync::Shared::ptr_eq(
+                &dex.get_shared_annotations_directory_item(directory_off)
+                    .expect("bad directory"),
+                &shared
+            ));
+        });
+        assert!(!dex.cache_bypass.is_enabled());
+        assert_eq!(dex.strings.cached_len(), cached_len);
+        assert_eq!(dex.get_string(100).expect("bad string"), cached);
+    }
+
+    #[test]
+    fn test_subclasses_and_implementers() {
+        use crate::class::Ancestor;
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let names = |handles: Vec<super::ClassHandle<'_, _>>| -> Vec<String> {
+            handles
+                .iter()
+                .map(|handle| handle.jtype().expect("bad type").to_string())
+                .collect()
+        };
+        let classes: Vec<_> = dex
+            .classes()
+            .map(|class| class.expect("bad class"))
+            .collect();
+        let expected = |descriptor: &str, implementers: bool, transitive: bool| -> Vec<String> {
+            classes
+                .iter()
+                .filter(|class| match (implementers, transitive) {
+                    (false, false) => class.superclass().is_some_and(|jtype| jtype == descriptor),
+                    (false, true) => class.superclass_chain(&dex).any(|ancestor| {
+                        match ancestor.expect("bad ancestor") {
+                            Ancestor::Class(class) => class.jtype() == descriptor,
+                            Ancestor::External(jtype) => jtype == descriptor,
+                        }
+                    }),
+                    (true, false) => class.interfaces().iter().any(|jtype| jtype == descriptor),
+                    (true, true) => class
+                        .all_interfaces(&dex)
+                        .expect("bad interfaces")
+                        .iter()
+                        .any(|jtype| jtype == descriptor),
+                })
+                .map(|class| class.jtype().to_string())
+                .collect()
+        };
+
+        assert!(!dex
+            .built_indexes()
+            .contains(super::EagerSections::HIERARCHY_INDEX));
+        let receivers = names(
+            dex.subclasses_of("Landroid/content/BroadcastReceiver;", false)
+                .expect("bad index"),
+        );
+        assert_eq!(receivers.len(), 8);
+        assert!(dex
+            .built_indexes()
+            .contains(super::EagerSections::HIERARCHY_INDEX));
+        for descriptor in &[
+            "Landroid/content/BroadcastReceiver;",
+            "Lorg/adw/launcher/ItemInfo;",
+            "Ljava/lang/Object;",
+        ] {
+            for &transitive in &[false, true] {
+                let found = names(
+                    dex.subclasses_of(descriptor, transitive)
+                        .expect("bad index"),
+                );
+                assert_eq!(found, expected(descriptor, false, transitive));
+            }
+        }
+        // through FolderInfo
+        assert!(
+            dex.subclasses_of("Lorg/adw/launcher/ItemInfo;", true)
+                .expect("bad index")
+                .len()
+                > dex
+                    .subclasses_of("Lorg/adw/launcher/ItemInfo;", false)
+                    .expect("bad index")
+                    .len()
+        );
+        for descriptor in &[
+            "Lorg/adw/launcher/DropTarget;",
+            "Landroid/provider/BaseColumns;",
+            "Landroid/view/View$OnClickListener;",
+        ] {
+            for &transitive in &[false, true] {
+                let found = names(
+                    dex.implementers_of(descriptor, transitive)
+                        .expect("bad index"),
+                );
+                assert_eq!(found, expected(descriptor, true, transitive));
+            }
+        }
+        assert!(
+            dex.implementers_of("Lorg/adw/launcher/DropTarget;", true)
+                .expect("bad index")
+                .iter()
+                .any(|handle| handle.jtype().expect("bad type")
+                    == "Lorg/adw/launcher/LiveFolderIcon;")
+        );
+        assert!(dex
+            .subclasses_of("Lcom/example/Missing;", true)
+            .expect("bad index")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_clones_share_caches() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let clone = dex.clone();
+        let string = dex.get_string(100).expect("bad string");
+        assert_eq!(clone.cache_stats().strings().entries(), 1);
+        assert_eq!(clone.get_string(100).expect("bad string"), string);
+        assert_eq!(dex.cache_stats().strings().hits(), 1);
+
+        dex.build_indexes(super::EagerSections::CLASS_NAME_INDEX)
+            .expect("cannot build indexes");
+        assert!(clone.class_names.get().is_some());
+        let classes: Vec<_> = dex
+            .classes()
+            .map(|class| class.expect("bad class").jtype().to_string())
+            .collect();
+        let entries = dex.cache_stats();
+
+        // the clone outlives the original and reads the same items, from the caches
+        drop(dex);
+        let clone_classes: Vec<_> = clone
+            .classes()
+            .map(|class| class.expect("bad class").jtype().to_string())
+            .collect();
+        assert_eq!(clone_classes, classes);
+        let stats = clone.cache_stats();
+        assert_eq!(stats.types().misses(), entries.types().misses());
+        assert!(stats.types().hits() > entries.types().hits());
+        let class = clone
+            .find_class_by_descriptor(&classes[0])
+            .expect("bad class")
+            .expect("class not found");
+        assert_eq!(class.jtype().to_string(), classes[0]);
+    }
+
+    #[test]
+    fn test_cache_max_bytes() {
+        use crate::CacheConfig;
+
+        let read = |config: CacheConfig| {
+            let dex = super::DexReader::builder()
+                .eager_annotations(true)
+                .cache_config(config)
+                .read_file("resources/classes.dex")
+                .expect("cannot open dex");
+            for class in dex.classes() {
+                class.expect("bad class");
+            }
+            dex.cache_stats()
+        };
+        let unbounded = read(CacheConfig::new());
+        let directories = unbounded.annotations_directories();
+        assert!(directories.entries() > 0);
+        assert_eq!(directories.evictions(), 0);
+
+        let max = directories.weight() / 2;
+        let bounded = read(CacheConfig::new().max_bytes(Some(max)));
+        for usage in &[bounded.strings(), bounded.annotations_directories()] {
+            assert!(usage.weight() <= max);
+        }
+        assert!(bounded.annotations_directories().evictions() > 0);
+        assert!(bounded.annotations_directories().entries() < directories.entries());
+    }
+
+    #[cfg(feature = "cache-diagnostics")]
+    #[test]
+    fn test_compare_cached_vs_fresh() {
+        use super::{CacheKind, CacheMismatch};
+        let dex = super::DexReader::builder()
+            .eager_annotations(true)
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        for class in dex.classes() {
+            class.expect("bad class");
+        }
+        assert!(dex.compare_cached_vs_fresh(CacheKind::Strings).is_empty());
+        assert!(dex
+            .compare_cached_vs_fresh(CacheKind::AnnotationsDirectories)
+            .is_empty());
+
+        // store a directory under the offset of another one
+        let mut offsets = dex.annotations_directories.entries().into_iter();
+        let (first, _) = offsets.next().expect("no cached directory");
+        let (_, other) = offsets.next().expect("no cached directory");
+        dex.annotations_directories.put(first, other);
+        let mismatches = dex.compare_cached_vs_fresh(CacheKind::AnnotationsDirectories);
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|CacheMismatch { key, .. }| *key)
+                .collect::<Vec<_>>(),
+            vec![u64::from(first)]
+        );
+    }
+
+    #[test]
+    fn test_encoded_array_bounded() {
+        let data = mutated_example_dex(|data| {
+            assert_eq!(uleb128_len(data, FIRST_ENCODED_ARRAY_ITEM), 1);
+            data[FIRST_ENCODED_ARRAY_ITEM] = 0x7f;
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let values = dex
+            .get_static_values(FIRST_ENCODED_ARRAY_ITEM as u32)
+            .expect("bad static values");
+        assert_eq!(values.len(), 0x7f);
+        assert_malformed(values.to_vec());
+    }
+
+    #[test]
+    fn test_string_data_bounded() {
+        let data = mutated_example_dex(|data| {
+            let string_ids_off: u32 = data.pread_with(0x3c, scroll::LE).unwrap();
+            let string_data_off: u32 = data
+                .pread_with(string_ids_off as usize, scroll::LE)
+                .unwrap();
+            // skip the utf16_size
+            let start = string_data_off as usize + uleb128_len(data, string_data_off as usize);
+            let nul = start
+                + data[start..]
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .expect("unterminated string");
+            data[nul] = b'a';
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        assert_malformed(dex.get_string(0));
+        assert!(dex.get_string(1).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_type_descriptor() {
+        use crate::{
+            diagnostic::{DiagnosticCode, Span},
+            error::ErrorKind,
+            jtype::TypeKind,
+        };
+
+        let mut type_ids_off = 0;
+        let data = mutated_example_dex(|data| {
+            type_ids_off = data.pread_with::<u32>(0x44, scroll::LE).unwrap();
+            let string_ids_off: u32 = data.pread_with(0x3c, scroll::LE).unwrap();
+            let string_id: u32 = data.pread_with(type_ids_off as usize, scroll::LE).unwrap();
+            let string_data_off: u32 = data
+                .pread_with((string_ids_off + string_id * 4) as usize, scroll::LE)
+                .unwrap();
+            let start = string_data_off as usize + uleb128_len(data, string_data_off as usize);
+            data[start] = b'X';
+        });
+
+        let dex = super::DexReader::from_vec(data.clone()).expect("cannot open dex");
+        let jtype = dex.get_type(0).expect("lenient by default");
+        assert!(jtype.type_descriptor().starts_with('X'));
+        assert_eq!(jtype.kind(), TypeKind::Invalid);
+        assert!(!jtype.is_class() && !jtype.is_reference());
+        assert_eq!(dex.get_type(0).unwrap().kind(), TypeKind::Invalid);
+        let stats = dex.cache_stats().types();
+        assert!(stats.hits() > 0);
+        assert!(stats.entries() > 0);
+        let diagnostics = dex.diagnostics();
+        let found: Vec<_> = diagnostics.with_code(DiagnosticCode::InvalidName).collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].span(), Some(Span::new(type_ids_off, 4)));
+
+        let strict = super::DexReader::builder()
+            .strict_descriptors(true)
+            .read_vec(data)
+            .expect("cannot open dex");
+        match strict.get_type(0) {
+            Err(error @ super::Error::InvalidDescriptor(0, _, _)) => {
+                assert_eq!(error.kind(), ErrorKind::InvalidDescriptor);
+                assert_eq!(error.code(), 200);
+            }
+            other => panic!("invalid descriptor read: {:?}", other),
+        }
+        assert!(strict.get_type(1).is_ok());
+    }
+
+    /// A source whose bytes switch between the example file and a mutated copy of it, like
+    /// the memory of a live process.
+    struct ChurningSource {
+        original: Vec<u8>,
+        mutated: Vec<u8>,
+        is_mutated: crate::sync::Shared<std::sync::atomic::AtomicBool>,
+    }
+
+    impl AsRef<[u8]> for ChurningSource {
+        fn as_ref(&self) -> &[u8] {
+            if self.is_mutated.load(std::sync::atomic::Ordering::SeqCst) {
+                &self.mutated
+            } else {
+                &self.original
+            }
+        }
+    }
+
+    #[test]
+    fn test_snapshot_on_access() {
+        use crate::{CacheConfig, SnapshotKinds};
+
+        let mutated = mutated_example_dex(|data| {
+            let string_ids_off: u32 = data.pread_with(0x3c, scroll::LE).unwrap();
+            // the first string is empty
+            let string_data_off: u32 = data
+                .pread_with(string_ids_off as usize + 4, scroll::LE)
+                .unwrap();
+            let start = string_data_off as usize + uleb128_len(data, string_data_off as usize);
+            data[start] = if data[start] == b'a' { b'b' } else { b'a' };
+            // from build to runtime or system
+            assert!(data[FIRST_ANNOTATION_ITEM] <= 2);
+            data[FIRST_ANNOTATION_ITEM] = (data[FIRST_ANNOTATION_ITEM] + 1) % 3;
+            // the first value is a string index, 0x11f5
+            assert_eq!(data[FIRST_ENCODED_ARRAY_ITEM + 1], 0x37);
+            data[FIRST_ENCODED_ARRAY_ITEM + 2] -= 1;
+        });
+        let read = |kinds: SnapshotKinds| {
+            let is_mutated = crate::sync::Shared::new(std::sync::atomic::AtomicBool::new(false));
+            let source = ChurningSource {
+                original: std::fs::read("resources/classes.dex").expect("cannot read dex"),
+                mutated: mutated.clone(),
+                is_mutated: is_mutated.clone(),
+            };
+            let dex = super::DexReader::builder()
+                .cache_config(CacheConfig::new().strings(1))
+                .snapshot_on_access(kinds)
+                .read_vec(source)
+                .expect("cannot open dex");
+            let read_items = || {
+                // reading another string evicts the first one from the cache
+                let string = dex.get_string(1).expect("bad string").to_string();
+                dex.get_string(2).expect("bad string");
+                let visibility = dex
+                    .get_annotation_item(FIRST_ANNOTATION_ITEM as u32)
+                    .expect("bad annotation")
+                    .visibility();
+                let array = dex
+                    .get_static_values(FIRST_ENCODED_ARRAY_ITEM as u32)
+                    .expect("bad static values");
+                (string, visibility, array)
+            };
+            let (string, visibility, array) = read_items();
+            let value = array.get(0).expect("bad value");
+            is_mutated.store(true, std::sync::atomic::Ordering::SeqCst);
+            // the array read before the mutation decodes its values lazily
+            let (mutated_string, mutated_visibility, _) = read_items();
+            let lazy_value = array.get(0).expect("bad value");
+            (
+                string == mutated_string,
+                visibility == mutated_visibility,
+                value == lazy_value,
+            )
+        };
+
+        assert_eq!(read(SnapshotKinds::empty()), (false, false, false));
+        assert_eq!(read(SnapshotKinds::all()), (true, true, true));
+        assert_eq!(
+            read(SnapshotKinds::STRING_DATA | SnapshotKinds::ENCODED_ARRAYS),
+            (true, false, true)
+        );
+    }
+
+    #[test]
+    fn test_unknown_map_item_type() {
+        use super::{ItemType, MapItemType};
+
+        let example = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let items = example.map_list().items();
+        assert!(items.windows(2).all(|w| w[0].offset() < w[1].offset()));
+        let position = items
+            .iter()
+            .position(|item| item.item_type() == ItemType::DebugInfoItem)
+            .expect("no debug info");
+        let data = mutated_example_dex(|data| {
+            let map_off: u32 = data.pread_with(0x34, scroll::LE).unwrap();
+            let entry = map_off as usize + 4 + position * 12;
+            data.pwrite_with(0x7777u16, entry, scroll::LE).unwrap();
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let item = dex.map_list().items()[position];
+        assert_eq!(item.item_type(), MapItemType::Unknown(0x7777));
+        assert_eq!(item.item_type().code(), 0x7777);
+        assert_eq!(item.item_type().known(), None);
+        assert_eq!(item.item_type().to_string(), "Unknown(0x7777)");
+        assert_eq!(item.offset(), items[position].offset());
+        assert_eq!(item.size(), items[position].size());
+        assert!(dex.map_list().get(ItemType::DebugInfoItem).is_none());
+        assert_eq!(dex.classes().count(), example.classes().count());
+
+        for item in items {
+            let item_type = item.item_type();
+            assert_eq!(MapItemType::from_code(item_type.code()), item_type);
+            assert!(item_type.known().is_some());
+        }
+    }
+
+    #[test]
+    fn test_zero_class_dex() {
+        let dex = super::DexReader::from_vec(minimal_dex(&["a", "b"], 0x70)).expect("bad dex");
+        assert_eq!(dex.classes().count(), 0);
+        assert_eq!(dex.class_defs().count(), 0);
+        assert_eq!(dex.types().count(), 0);
+        assert_eq!(dex.method_ids().count(), 0);
+        assert_eq!(dex.field_ids().count(), 0);
+        let strings: Vec<_> = dex.strings().map(|s| s.unwrap().1.to_string()).collect();
+        assert_eq!(strings, ["a", "b"]);
+        assert!(matches!(dex.get_type(0), Err(super::Error::InvalidId(_))));
+        assert!(matches!(
+            dex.get_method_item(0),
+            Err(super::Error::InvalidId(_))
+        ));
+        assert!(dex.find_class_by_descriptor("a").unwrap().is_none());
+        assert_eq!(
+            dex.warnings(),
+            [
+                "type_ids section is empty but has offset 112",
+                "proto_ids section is empty but has offset 112",
+                "field_ids section is empty but has offset 112",
+                "method_ids section is empty but has offset 112",
+                "class_defs section is empty but has offset 112",
+                "map_list item TypeIdItem is empty but has offset 112",
+            ]
+        );
+        assert!(dex.map_list().get(super::ItemType::TypeIdItem).is_none());
+    }
+
+    #[test]
+    fn test_types_and_protos() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let types = dex.types();
+        assert_eq!(types.len(), dex.header().type_ids_size() as usize);
+        for (index, jtype) in types.enumerate() {
+            let (type_id, jtype) = jtype.expect("bad type");
+            assert_eq!(type_id as usize, index);
+            assert_eq!(jtype.id(), type_id);
+        }
+
+        let protos = dex.protos();
+        assert_eq!(protos.len(), dex.header().proto_ids_size() as usize);
+        let mut without_params = 0;
+        for (index, proto) in protos.enumerate() {
+            let (proto_id, proto) = proto.expect("bad proto");
+            assert_eq!(proto_id as usize, index);
+            let proto_item = dex.get_proto_item(proto_id).expect("bad proto item");
+            assert_eq!(
+                *proto.shorty(),
+                dex.get_string(proto_item.shorty()).unwrap()
+            );
+            assert_eq!(proto.return_type().id(), proto_item.return_type());
+            assert_eq!(proto.params().len() + 1, proto.shorty().chars().count());
+            if proto_item.params_off() == 0 {
+                assert!(proto.params().is_empty());
+                without_params += 1;
+            }
+        }
+        assert!(without_params > 0);
+        assert!(dex.protos().any(|proto| {
+            let (_, proto) = proto.expect("bad proto");
+            *proto.shorty() == *"V" && *proto.return_type() == "V" && proto.params().is_empty()
+        }));
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let file = std::fs::File::open("resources/classes.dex").expect("cannot open dex");
+        let dex = super::DexReader::from_reader(file).expect("bad dex");
+        let from_vec = super::DexReader::from_vec(data.clone()).expect("bad dex");
+        assert_eq!(dex.header().checksum(), from_vec.header().checksum());
+        assert_eq!(dex.classes().count(), from_vec.classes().count());
+        assert!(dex
+            .classes()
+            .zip(from_vec.classes())
+            .all(|(a, b)| a.unwrap().jtype() == b.unwrap().jtype()));
+
+        let truncated = &data[..data.len() - 10];
+        match super::DexReader::from_reader(truncated) {
+            Err(super::Error::MalFormed(message)) => assert_eq!(
+                message,
+                format!(
+                    "File truncated, header declares {} bytes but found {}",
+                    data.len(),
+                    truncated.len()
+                )
+            ),
+            other => panic!("truncated file read: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_classes_recover_from_bad_class_defs() {
+        let data = mutated_example_dex(|data| {
+            // an invalid class type, then a class data offset outside of the file
+            let class_defs_off = data.pread::<u32>(0x64).unwrap() as usize;
+            data.pwrite_with(0xffffu32, class_defs_off + 5 * 32, scroll::LE)
+                .unwrap();
+            data.pwrite_with(u32::MAX, class_defs_off + 7 * 32 + 24, scroll::LE)
+                .unwrap();
+        });
+        let dex = super::DexReader::from_vec(data).expect("bad dex");
+        let classes = dex.classes();
+        let class_defs_size = dex.header().class_defs_size() as usize;
+        assert_eq!(
+            classes.size_hint(),
+            (class_defs_size, Some(class_defs_size))
+        );
+        let failed: Vec<_> = classes
+            .enumerate()
+            .filter_map(|(index, class)| class.err().map(|error| (index, error)))
+            .collect();
+        assert_eq!(failed.len(), 2);
+        for ((index, error), kind) in failed
+            .iter()
+            .zip(&[crate::ErrorKind::InvalidId, crate::ErrorKind::BadOffset])
+        {
+            match error {
+                super::Error::ClassDef(class_def, _) => assert_eq!(*class_def as usize, *index),
+                other => panic!("unexpected error: {}", other),
+            }
+            assert_eq!(error.kind(), *kind);
+            assert!(error.is_recoverable());
+        }
+        assert_eq!(failed[0].0, 5);
+        assert_eq!(failed[1].0, 7);
+        assert!(failed[0]
+            .1
+            .to_string()
+            .starts_with("Cannot load class definition 5: "));
+        assert_eq!(dex.classes_lossy().map(Result::unwrap).count(), 321);
+    }
+
+    #[test]
+    fn test_classes_loaded_across_threads() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        assert_send_sync(&dex);
+        let len = dex.header().class_defs_size();
+        let loaded: Vec<usize> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|thread| {
+                    let dex = &dex;
+                    scope.spawn(move || {
+                        dex.class_defs()
+                            .skip(thread)
+                            .step_by(4)
+                            .map(|class_def| {
+                                let class_def = class_def.unwrap();
+                                let class = super::Class::try_from_dex(dex, &class_def).unwrap();
+                                assert_send_sync(&class);
+                                class.methods().count()
+                            })
+                            .sum()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        let methods: usize = dex
+            .classes()
+            .map(|class| class.unwrap().methods().count())
+            .sum();
+        assert_eq!(loaded.iter().sum::<usize>(), methods);
+        assert_eq!(dex.class_defs().count(), len as usize);
+    }
+
+    #[test]
+    fn test_deadline_and_bypass_per_thread() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let expired = crate::operation::OperationOptions::new().deadline(std::time::Instant::now());
+        let on_other_thread = |f: &(dyn Fn() + Sync)| {
+            std::thread::scope(|scope| {
+                scope.spawn(f).join().unwrap();
+            })
+        };
+
+        let classes = dex.run_with(&expired, |dex| {
+            // another thread, even with a clone, runs without the deadline
+            let clone = dex.clone();
+            on_other_thread(&|| {
+                assert!(clone.classes().all(|class| class.is_ok()));
+            });
+            Ok(dex.classes().count())
+        });
+        assert_eq!(classes.expect("bad operation"), 2);
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        dex.with_cache_bypassed(|dex| {
+            dex.get_string(200).expect("bad string");
+            assert_eq!(dex.strings.cached_len(), 0);
+            // another thread uses the caches meanwhile
+            on_other_thread(&|| {
+                dex.get_string(200).expect("bad string");
+            });
+            assert_eq!(dex.strings.cached_len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_classes_in_package() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let in_package = |package: &str| -> Vec<String> {
+            dex.classes_in_package(package)
+                .map(|class| class.expect("cannot load class").jtype().to_string())
+                .collect()
+        };
+        let with_prefix = |prefix: &str| -> Vec<String> {
+            dex.classes()
+                .map(|class| class.expect("cannot load class").jtype().to_string())
+                .filter(|descriptor| descriptor.starts_with(prefix))
+                .collect()
+        };
+        let launcher = in_package("org/adw/launcher");
+        assert!(!launcher.is_empty());
+        assert_eq!(launcher, with_prefix("Lorg/adw/launcher/"));
+        assert_eq!(in_package("org/adw/launcher/"), launcher);
+        // subpackages are included, packages sharing a prefix are not
+        assert_eq!(in_package("org/adw"), with_prefix("Lorg/adw/"));
+        assert!(in_package("org/adw").len() >= launcher.len());
+        assert!(!with_prefix("Lcom/devoteam/quick").is_empty());
+        assert!(in_package("com/devoteam/quick").is_empty());
+        assert!(in_package("org/adw/launcher/Launcher").is_empty());
+        assert_eq!(in_package("").len(), 323);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_classes() {
+        use rayon::prelude::*;
+
+        let data = mutated_example_dex(|data| {
+            let class_defs_off = data.pread::<u32>(0x64).unwrap() as usize;
+            data.pwrite_with(0xffffu32, class_defs_off + 5 * 32, scroll::LE)
+                .unwrap();
+        });
+        let dex = super::DexReader::from_vec(data).expect("bad dex");
+        let parallel: Vec<_> = dex.par_classes().collect();
+        let sequential: Vec<_> = dex.classes().collect();
+        assert_eq!(parallel.len(), sequential.len());
+        for (parallel, sequential) in parallel.iter().zip(&sequential) {
+            match (parallel, sequential) {
+                (Ok(parallel), Ok(sequential)) => {
+                    assert_eq!(parallel.jtype(), sequential.jtype());
+                    assert_eq!(parallel.methods().count(), sequential.methods().count());
+                }
+                (Err(parallel), Err(sequential)) => {
+                    assert_eq!(parallel.to_string(), sequential.to_string())
+                }
+                _ => panic!("the parallel and sequential classes differ"),
+            }
+        }
+        assert!(parallel[5].is_err());
+
+        // the threads loading the classes inherit the deadline and the cache bypass
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let expired = crate::operation::OperationOptions::new().deadline(std::time::Instant::now());
+        let classes = dex.run_with(&expired, |dex| Ok(dex.par_classes().collect::<Vec<_>>()));
+        let classes = classes.expect("bad operation");
+        assert_eq!(classes.len(), dex.header().class_defs_size() as usize);
+        assert!(classes
+            .iter()
+            .all(|class| matches!(class, Err(crate::error::Error::DeadlineExceeded(..)))));
+        let stats = dex.cache_stats();
+        dex.with_cache_bypassed(|dex| {
+            assert!(dex.par_classes().all(|class| class.is_ok()));
+        });
+        assert_eq!(dex.cache_stats(), stats);
+    }
+
+    #[test]
+    fn test_stats() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let stats = dex.stats().expect("cannot compute stats");
+        let header = dex.header();
+        assert_eq!(stats.strings(), header.string_ids_size());
+        assert_eq!(stats.types(), header.type_ids_size());
+        assert_eq!(stats.methods(), header.method_ids_size());
+        assert_eq!(stats.class_defs(), 323);
+
+        // the same counts from the parsed classes, no code item is shared in the example
+        let mut code_items = 0;
+        let mut code_units = 0;
+        let mut classes_with_native_methods = 0;
+        for class in dex.classes() {
+            let class = class.expect("cannot load class");
+            for code in class.methods().filter_map(|method| method.code()) {
+                code_items += 1;
+                code_units += code.insns.len() as u64;
+            }
+            if class.methods().any(|method| method.is_native()) {
+                classes_with_native_methods += 1;
+            }
+        }
+        assert!(stats.code_units() > 0);
+        assert_eq!(stats.code_items(), code_items);
+        assert_eq!(stats.code_units(), code_units);
+        assert_eq!(
+            stats.classes_with_native_methods(),
+            classes_with_native_methods
+        );
+        assert_eq!(
+            Some(stats.code_items() as u32),
+            dex.map_list().get_len(super::ItemType::CodeItem)
+        );
+    }
+
+    #[test]
+    fn test_skip_code_debug_info_and_annotations() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let skipping = super::DexReaderBuilder::new()
+            .skip_code(true)
+            .skip_annotations(true)
+            .read_file("resources/classes.dex")
+            .expect("bad dex");
+        let mut annotated = 0;
+        for (class, skipped) in dex.classes().zip(skipping.classes()) {
+            let (class, skipped) = (class.unwrap(), skipped.unwrap());
+            assert_eq!(class.jtype(), skipped.jtype());
+            assert_eq!(class.methods().count(), skipped.methods().count());
+            assert!(skipped.methods().all(|method| method.code().is_none()));
+            assert!(skipped.annotations().unwrap().is_empty());
+            if !class.annotations().unwrap().is_empty() {
+                annotated += 1;
+            }
+        }
+        assert!(annotated > 0);
+
+        #[cfg(feature = "debug-info")]
+        {
+            let without_debug_info = super::DexReaderBuilder::new()
+                .skip_debug_info(true)
+                .read_file("resources/classes.dex")
+                .expect("bad dex");
+            let name = "Lorg/adw/launcher/Launcher;";
+            let class = dex.find_class_by_name(name).unwrap().unwrap();
+            let skipped = without_debug_info
+                .find_class_by_name(name)
+                .unwrap()
+                .unwrap();
+            assert!(class
+                .methods()
+                .filter_map(|method| method.code())
+                .any(|code| code.debug_info_item().is_some()));
+            assert!(skipped
+                .methods()
+                .filter_map(|method| method.code())
+                .all(|code| code.debug_info_item().is_none()));
+        }
+    }
+
+    #[test]
+    fn test_verify_checksum_and_signature() {
+        use super::VerifyMode;
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        assert!(dex.verify_checksum().expect("cannot verify checksum"));
+        #[cfg(feature = "signature")]
+        assert!(dex.verify_signature().expect("cannot verify signature"));
+        let signature = *dex.signature();
+        assert_eq!(
+            &signature[..],
+            &std::fs::read("resources/classes.dex").unwrap()[12..32]
+        );
+
+        // a repackaged file: a modified string with a fixed checksum but a stale signature
+        let data = mutated_example_dex(|data| {
+            let string_data_off = data.pread::<u32>(0x70).unwrap() as usize;
+            data[string_data_off + 1] ^= 0x1;
+        });
+        let dex = super::DexReader::from_vec(data.clone()).expect("bad dex");
+        assert!(dex.verify_checksum().expect("cannot verify checksum"));
+        assert_eq!(*dex.signature(), signature);
+        #[cfg(feature = "signature")]
+        {
+            assert!(!dex.verify_signature().expect("cannot verify signature"));
+            let builder = super::DexReader::builder().verify(VerifyMode::Full);
+            match builder.read_vec(data.clone()) {
+                Err(super::Error::MalFormed(message)) => {
+                    assert!(message.starts_with("File corrupted, SHA-1 signature doesn't match"))
+                }
+                other => panic!("tampered file read: {:?}", other.map(|_| ())),
+            }
+        }
+
+        // a corrupted file is only read without verification
+        let mut corrupted = data;
+        corrupted[8] ^= 0x1;
+        assert!(super::DexReader::from_vec(corrupted.clone()).is_err());
+        let builder = super::DexReader::builder().verify(VerifyMode::None);
+        let dex = builder.read_vec(corrupted.clone()).expect("bad dex");
+        assert!(!dex.verify_checksum().expect("cannot verify checksum"));
+        assert_ne!(dex.compute_checksum(), dex.checksum());
+        fix_checksum(&mut corrupted);
+        assert_eq!(dex.compute_checksum(), corrupted.pread::<u32>(8).unwrap());
+        assert_eq!(VerifyMode::default(), VerifyMode::Checksum);
+    }
+
+    #[test]
+    fn test_classes_filtered() {
+        use crate::class_kind::{ClassHeuristics, ClassKind, ClassKindMask};
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        let mut r_classes = Vec::new();
+        let mut others = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            let kind = class.kind().expect("bad class kind");
+            match kind {
+                ClassKind::RClass => r_classes.push(class.jtype().to_string()),
+                ClassKind::Regular | ClassKind::Interface | ClassKind::Enum => others += 1,
+                kind => panic!("{} is {:?}", class.jtype(), kind),
+            }
+            assert_eq!(
+                class
+                    .kind_with(ClassHeuristics::empty())
+                    .expect("bad class kind"),
+                if kind == ClassKind::RClass {
+                    ClassKind::Regular
+                } else {
+                    kind
+                }
+            );
+        }
+        assert!(r_classes.contains(&"Lorg/adw/launcher/R;".to_string()));
+        assert!(r_classes.contains(&"Lorg/adw/launcher/R$layout;".to_string()));
+        assert!(r_classes
+            .iter()
+            .all(|name| name.starts_with("Lorg/adw/launcher/R")));
+
+        let kept: Vec<_> = dex
+            .classes_filtered(!ClassKindMask::GENERATED)
+            .map(|class| class.expect("bad class").jtype().to_string())
+            .collect();
+        assert_eq!(kept.len(), others);
+        assert!(kept.iter().all(|name| !r_classes.contains(name)));
+        let generated: Vec<_> = dex
+            .classes_filtered(ClassKindMask::GENERATED)
+            .map(|class| class.expect("bad class").jtype().to_string())
+            .collect();
+        assert_eq!(generated, r_classes);
+        let unfiltered =
+            dex.classes_filtered_with(!ClassKindMask::GENERATED, ClassHeuristics::empty());
+        assert_eq!(unfiltered.count(), others + r_classes.len());
+        assert_eq!(dex.classes_filtered(ClassKindMask::empty()).count(), 0);
+        // reads the annotations of the regular classes, none of which is a Kotlin facade
+        assert_eq!(
+            dex.classes_filtered(ClassKindMask::KOTLIN_FACADE).count(),
+            0
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "container-zip")]
+    fn test_from_apk() {
+        use std::io::Write;
+        use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+        let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dir = tempfile::TempDir::new().expect("cannot create temporary directory");
+        let path = dir.path().join("app.apk");
+        let mut apk = ZipWriter::new(std::fs::File::create(&path).expect("cannot create APK"));
+        for (name, method) in &[
+            ("classes.dex", CompressionMethod::Stored),
+            ("classes2.dex", CompressionMethod::Deflated),
+        ] {
+            let options = SimpleFileOptions::default().compression_method(*method);
+            apk.start_file(*name, options).expect("cannot add entry");
+            apk.write_all(&example).expect("cannot write entry");
+        }
+        apk.finish().expect("cannot write APK");
+
+        for name in &["classes.dex", "classes2.dex"] {
+            let dex = super::DexReader::from_apk(&path, name).expect("cannot read dex");
+            assert_eq!(dex.header().class_defs_size(), 323);
+            #[cfg(feature = "signature")]
+            assert!(dex.verify_signature().expect("cannot verify signature"));
+        }
+        match super::DexReader::from_apk(&path, "classes3.dex") {
+            Err(super::Error::IO(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+                assert_eq!(e.to_string(), "No entry classes3.dex in the APK");
+            }
+            other => panic!("missing entry read: {:?}", other.map(|_| ())),
+        }
+        let not_apk = super::DexReader::from_apk("resources/classes.dex", "classes.dex");
+        assert_eq!(
+            not_apk.err().map(|e| e.kind()),
+            Some(crate::ErrorKind::MalFormed)
+        );
+    }
+
+    #[test]
+    fn test_get_class_by_type() {
+        use super::{ClassDefItem, EagerSections};
+        use crate::diagnostic::{DiagnosticCode, Span};
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        for class_def in dex.class_defs() {
+            let class_def = class_def.expect("bad class def");
+            let class = dex
+                .get_class_by_type(class_def.class_idx)
+                .expect("bad class")
+                .expect("class not found");
+            assert_eq!(class.id(), class_def.class_idx);
+        }
+        assert!(dex
+            .built_indexes()
+            .contains(EagerSections::TYPE_TO_CLASS_DEF));
+        let string_type = dex
+            .get_type_from_descriptor("Ljava/lang/String;")
+            .expect("bad type")
+            .expect("no type");
+        assert!(dex.get_class_by_type(string_type.id()).unwrap().is_none());
+        assert!(dex.diagnostics().is_empty());
+
+        // the second class_def defines the class of the first one again
+        let class_defs_off = dex.header().class_defs_off();
+        let first: ClassDefItem = dex
+            .source
+            .pread_with(class_defs_off as usize, dex.get_endian())
+            .unwrap();
+        let data = mutated_example_dex(|data| {
+            data.pwrite_with(first.class_idx, class_defs_off as usize + 32, scroll::LE)
+                .unwrap();
+        });
+        let mut dex = super::DexReader::from_vec(data).expect("bad dex");
+        let class = dex
+            .get_class_by_type(first.class_idx)
+            .expect("bad class")
+            .expect("class not found");
+        assert_eq!(class.id(), first.class_idx);
+        let check = |diagnostics: crate::diagnostic::Diagnostics| {
+            let duplicates: Vec<_> = diagnostics
+                .with_code(DiagnosticCode::DuplicateClassDef)
+                .cloned()
+                .collect();
+            assert_eq!(duplicates.len(), 1);
+            assert_eq!(
+                duplicates[0].span(),
+                Some(Span::new(class_defs_off + 32, 32))
+            );
+            assert_eq!(
+                duplicates[0].message(),
+                "class_def 1 defines the class of class_def 0 again"
+            );
+        };
+        check(dex.diagnostics());
+        dex.clear_caches();
+        dex.build_indexes(EagerSections::TYPE_TO_CLASS_DEF)
+            .expect("cannot build index");
+        check(dex.diagnostics());
+    }
+
+    #[test]
+    fn test_member_refs() {
+        use std::collections::HashSet;
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut defined_methods = HashSet::new();
+        let mut defined_fields = HashSet::new();
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            defined_methods.extend(class.methods().map(|method| method.id()));
+            defined_fields.extend(class.fields().map(|field| field.id()));
+        }
+
+        let methods = dex.method_refs();
+        assert_eq!(methods.len(), dex.header().method_ids_size() as usize);
+        let mut referenced = 0;
+        for (index, method) in methods.enumerate() {
+            let method = method.expect("bad method ref");
+            assert_eq!(method.id() as usize, index);
+            let defined = dex.is_defined(method.id()).expect("bad method id");
+            assert_eq!(
+                defined,
+                defined_methods.contains(&method.id()),
+                "{}",
+                method
+            );
+            if !defined {
+                referenced += 1;
+            }
+        }
+        assert!(referenced > 0);
+        assert!(dex.method_refs().any(|method| {
+            method.expect("bad method ref").to_string() == "Ljava/lang/Object;-><init>()V"
+        }));
+
+        let fields = dex.field_refs();
+        assert_eq!(fields.len(), dex.header().field_ids_size() as usize);
+        for (index, field) in fields.enumerate() {
+            let field = field.expect("bad field ref");
+            assert_eq!(field.id() as usize, index);
+            assert_eq!(
+                field.to_string(),
+                format!("{}->{}:{}", field.class(), field.name(), field.jtype())
+            );
+            assert_eq!(
+                dex.is_field_defined(field.id()).expect("bad field id"),
+                defined_fields.contains(&field.id()),
+                "{}",
+                field
+            );
+        }
+        assert!(dex
+            .is_defined(u64::from(dex.header().method_ids_size()))
+            .is_err());
+        assert!(dex
+            .get_field_ref(u64::from(dex.header().field_ids_size()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_method_descriptors() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut without_params = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let method_ref = dex.get_method_ref(method.id()).expect("bad method ref");
+                assert_eq!(method.descriptor(), *method_ref.proto());
+                assert_eq!(method.parameter_types(), method.params().as_slice());
+                assert_eq!(
+                    method.parameter_types().len() + 1,
+                    method.shorty().chars().count()
+                );
+                if method.parameter_types().is_empty() {
+                    assert!(method.descriptor().starts_with("()"));
+                    without_params += 1;
+                }
+            }
+        }
+        assert!(without_params > 0);
+
+        let class = dex
+            .find_class_by_name("Lorg/adw/launcher/Launcher;")
+            .expect("cannot read class")
+            .expect("class not found");
+        let init = class
+            .methods()
+            .find(|method| *method.name() == "<init>")
+            .expect("no constructor");
+        assert_eq!(init.descriptor(), "()V");
+        assert_eq!(*init.return_type(), "V");
+
+        for proto in dex.protos() {
+            let (_, proto) = proto.expect("bad proto");
+            let params: String = proto
+                .params()
+                .iter()
+                .map(|jtype| jtype.type_descriptor().to_string())
+                .collect();
+            assert_eq!(
+                proto.descriptor(),
+                format!("({}){}", params, proto.return_type().type_descriptor())
+            );
+        }
+    }
+
+    #[test]
+    fn test_strings_with_ids() {
+        let data = mutated_example_dex(|data| {
+            let string_ids_off: u32 = data.pread_with(0x3c, scroll::LE).unwrap();
+            data.pwrite_with(0u32, string_ids_off as usize + 3 * 4, scroll::LE)
+                .unwrap();
+        });
+        let dex = super::DexReader::builder()
+            .cache_config(crate::CacheConfig::new().strings(8192))
+            .read_vec(data)
+            .expect("cannot open dex");
+        let len = dex.header().string_ids_size() as usize;
+        assert_eq!(dex.strings_count(), len);
+        let mut strings = dex.strings();
+        assert_eq!(strings.len(), len);
+        strings.next();
+        assert_eq!(strings.len(), len - 1);
+
+        let strings: Vec<_> = dex.strings().collect();
+        assert_eq!(strings.len(), len);
+        for (id, string) in strings.iter().enumerate() {
+            match string {
+                Ok((string_id, string)) => {
+                    assert_ne!(id, 3);
+                    assert_eq!(*string_id as usize, id);
+                    assert_eq!(*string, dex.get_string(*string_id).expect("bad string"));
+                }
+                Err(e) => {
+                    assert_eq!(id, 3);
+                    assert!(matches!(e, super::Error::BadOffset(0, _)));
+                }
+            }
+        }
+        assert_eq!(dex.strings.cached_len(), len - 1);
+        let misses = dex.cache_stats().strings().misses();
+        assert_eq!(dex.strings().filter(Result::is_ok).count(), len - 1);
+        assert_eq!(dex.cache_stats().strings().misses(), misses + 1);
+    }
+
+    #[test]
+    fn test_zero_string_dex() {
+        let dex = super::DexReader::from_vec(minimal_dex(&[], 0x70)).expect("bad dex");
+        assert_eq!(dex.strings().count(), 0);
+        assert_eq!(dex.classes().count(), 0);
+        assert!(matches!(dex.get_string(0), Err(super::Error::InvalidId(_))));
+        assert!(dex.get_type_from_descriptor("La;").unwrap().is_none());
+        assert_eq!(dex.warnings().len(), 7);
+        assert_eq!(
+            dex.warnings()[0],
+            "string_ids section is empty but has offset 112"
+        );
+        let diagnostics = dex.diagnostics();
+        assert_eq!(
+            diagnostics
+                .with_code(crate::diagnostic::DiagnosticCode::EmptySection)
+                .count(),
+            6
+        );
+        assert_eq!(
+            diagnostics[0].span(),
+            Some(crate::diagnostic::Span::new(112, 0))
+        );
+        assert_eq!(
+            diagnostics
+                .at_least(crate::diagnostic::Severity::Warning)
+                .count(),
+            7
+        );
+        assert!(!diagnostics.has_errors());
+
+        let dex = super::DexReader::from_vec(minimal_dex(&[], 0)).expect("bad dex");
+        assert!(dex.warnings().is_empty());
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        assert!(dex.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        use crate::validate::Severity;
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("bad dex");
+        assert!(dex.invalid_names().expect("bad walk").is_empty());
+        let method_id = dex
+            .method_ids()
+            .map(|method_id| method_id.unwrap())
+            .find(|method_id| {
+                !dex.get_string(method_id.name_idx())
+                    .unwrap()
+                    .starts_with('<')
+            })
+            .expect("no method");
+        let field_id = dex
+            .field_ids()
+            .map(|field_id| field_id.unwrap())
+            .find(|field_id| field_id.name_idx() != method_id.name_idx())
+            .expect("no field");
+
+        // replace the first character of the names, after their uleb128 length
+        let data = mutated_example_dex(|data| {
+            for (name_idx, replacement) in
+                [(method_id.name_idx(), b'.'), (field_id.name_idx(), b' ')]
+            {
+                let string_ids_off = dex.header().string_ids_off() as usize;
+                let offset: u32 = data.pread(string_ids_off + name_idx as usize * 4).unwrap();
+                let offset = offset as usize;
+                data[offset + uleb128_len(data, offset)] = replacement;
+            }
+        });
+        let dex = super::DexReader::from_vec(data).expect("bad dex");
+        let names = dex.invalid_names().expect("bad walk");
+        let method = names
+            .iter()
+            .find(|name| name.item_type() == super::ItemType::MethodIdItem)
+            .expect("method name not reported");
+        assert_eq!(method.error().position(), 0);
+        assert_eq!(method.error().severity(), Severity::Invalid);
+        assert!(method.value().starts_with('.'));
+        let field = names
+            .iter()
+            .find(|name| name.item_type() == super::ItemType::FieldIdItem)
+            .expect("field name not reported");
+        assert_eq!(field.index(), field_id.id() as u32);
+        assert_eq!(field.error().severity(), Severity::ArtTolerated);
+        assert!(names
+            .iter()
+            .all(|name| name.value().starts_with(['.', ' '])));
+
+        let field_context = format!("FieldIdItem {}", field_id.id());
+        let diagnostics: crate::diagnostic::Diagnostics =
+            names.into_iter().map(Into::into).collect();
+        assert!(diagnostics.has_errors());
+        let tolerated: Vec<_> = diagnostics
+            .with_code(crate::diagnostic::DiagnosticCode::ArtToleratedName)
+            .collect();
+        assert!(!tolerated.is_empty());
+        assert!(tolerated
+            .iter()
+            .any(|diagnostic| diagnostic.context().as_deref() == Some(field_context.as_str())));
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_annotation_usage_summary() {
+        use crate::usage::Retention;
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let summary = dex.annotation_usage_summary().expect("bad summary");
+        let lines: Vec<String> = summary.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "Landroid/view/ViewDebug$ExportedProperty; external RUNTIME class: 0 method: 0 field: 6 parameter: 0",
+                "Ldalvik/annotation/EnclosingClass; external unknown class: 165 method: 0 field: 0 parameter: 0",
+                "Ldalvik/annotation/EnclosingMethod; external unknown class: 64 method: 0 field: 0 parameter: 0",
+                "Ldalvik/annotation/InnerClass; external unknown class: 229 method: 0 field: 0 parameter: 0",
+                "Ldalvik/annotation/MemberClasses; external unknown class: 54 method: 0 field: 0 parameter: 0",
+                "Ldalvik/annotation/Signature; external unknown class: 20 method: 47 field: 51 parameter: 0",
+                "Ldalvik/annotation/Throws; external unknown class: 0 method: 55 field: 0 parameter: 0",
+                "Ljava/lang/Deprecated; external RUNTIME class: 0 method: 0 field: 1 parameter: 0",
+            ]
+        );
+        // each annotated item counts, whether or not its set is shared
+        let mut attached = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            attached += class.annotations().unwrap().len();
+            attached += class
+                .fields()
+                .map(|f| f.annotations().unwrap().len())
+                .sum::<usize>();
+            attached += class
+                .methods()
+                .map(|m| m.annotations().unwrap().len())
+                .sum::<usize>();
+        }
+        let total: usize = summary
+            .iter()
+            .map(|usage| usage.usage_counts().total())
+            .sum();
+        assert_eq!(total, attached);
+
+        // a class definition turned into the definition of `Deprecated`, without `Retention`
+        let deprecated = dex
+            .string_usages("Ljava/lang/Deprecated;", false)
+            .expect("bad usages")
+            .type_id()
+            .expect("no type");
+        let class_defs_off = dex.header().class_defs_off() as usize;
+        let data = mutated_example_dex(|data| {
+            data.pwrite_with(deprecated, class_defs_off, scroll::LE)
+                .unwrap();
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot open dex");
+        let summary = dex.annotation_usage_summary().expect("bad summary");
+        let usage = summary
+            .iter()
+            .find(|usage| usage.descriptor() == "Ljava/lang/Deprecated;")
+            .expect("no usage");
+        assert!(usage.defined_locally());
+        assert_eq!(usage.retention(), Some(Retention::Class));
+        assert_eq!(usage.usage_counts().field(), 1);
+        assert_eq!(
+            summary
+                .iter()
+                .filter(|usage| usage.defined_locally())
+                .count(),
+            1
+        );
+        #[cfg(feature = "json")]
+        assert_eq!(
+            usage.to_json(),
+            serde_json::json!({
+                "descriptor": "Ljava/lang/Deprecated;",
+                "defined_locally": true,
+                "retention": "CLASS",
+                "usage_counts": { "class": 0, "method": 0, "field": 1, "parameter": 0 },
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_string_usages() {
+        use crate::usage::ElementUse;
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let container = dex.string_usages("container", false).expect("bad usages");
+        assert_eq!(container.type_id(), None);
+        assert_eq!(container.fields().len(), 8);
+        assert!(container
+            .fields()
+            .iter()
+            .any(|field| field.reference() == "Lorg/adw/launcher/ItemInfo;->container:J"));
+        assert_eq!(container.const_strings(), &None);
+        let scanned = dex.string_usages("container", true).expect("bad usages");
+        assert_eq!(scanned.const_strings().as_ref().map(Vec::len), Some(10));
+
+        let inner = dex
+            .string_usages("SetBoundOnClickIntent", false)
+            .expect("bad usages");
+        assert_eq!(inner.methods().len(), 1);
+        assert_eq!(inner.annotations().len(), 1);
+        let annotation = &inner.annotations()[0];
+        assert_eq!(annotation.element(), "name");
+        assert_eq!(annotation.element_use(), ElementUse::Value);
+        assert_eq!(
+            annotation.location(),
+            "Lmobi/intuitit/android/widget/BoundRemoteViews$SetBoundOnClickIntent;"
+        );
+
+        let missing = dex
+            .string_usages("no such string", true)
+            .expect("bad usages");
+        assert_eq!(missing.string_id(), None);
+        assert!(missing.is_unused());
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_string_usages_type_and_const_string() {
+        const DESCRIPTOR: &str = "Landroid/view/View;";
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let descriptor = dex.string_usages(DESCRIPTOR, true).expect("bad usages");
+        let string_id = descriptor.string_id().expect("no string");
+        assert!(descriptor.type_id().is_some());
+        assert_eq!(descriptor.const_strings().as_ref().map(Vec::len), Some(0));
+        // make the only `const-string` loading "ic_delete" load the descriptor instead
+        let usages = dex.string_usages("ic_delete", true).expect("bad usages");
+        let site = usages.const_strings().as_ref().expect("not scanned")[0].clone();
+        let mut code_off = None;
+        for class_def in dex.class_defs() {
+            let class_data = dex.get_class_data(class_def.expect("bad class def").class_data_off);
+            let class_data = match class_data.expect("bad class data") {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                if method.method_id() == site.method() {
+                    code_off = Some(*method.code_offset() as usize);
+                }
+            }
+        }
+        let operand = code_off.expect("no code") + 16 + site.addr() * 2 + 2;
+        let data = mutated_example_dex(|data| {
+            data.pwrite_with(string_id as u16, operand, scroll::LE)
+                .expect("cannot write");
+        });
+        let dex = super::DexReader::from_vec(data).expect("cannot load dex");
+
+        let usages = dex.string_usages(DESCRIPTOR, true).expect("bad usages");
+        assert_eq!(usages.type_id(), descriptor.type_id());
+        assert_eq!(usages.annotations(), descriptor.annotations());
+        assert_eq!(usages.const_strings(), &Some(vec![site]));
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_looping_methods() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut expected = Vec::new();
+        let mut sizes = Vec::new();
+        for class in dex.classes() {
+            for method in class.expect("bad class").methods() {
+                if let Some(code) = method.code() {
+                    if code.has_backward_branch().expect("cannot decode") {
+                        expected.push(method.id());
+                        sizes.push(code.insns.len() as u32);
+                    }
+                }
+            }
+        }
+        assert!(!expected.is_empty());
+        let looping = |min_insns_size| {
+            let mut methods: Vec<_> = dex
+                .looping_methods(min_insns_size)
+                .map(|method| method.expect("bad method").id())
+                .collect();
+            methods.sort_unstable();
+            methods
+        };
+        expected.sort_unstable();
+        assert_eq!(looping(0), expected);
+
+        let min = sizes[sizes.len() / 2];
+        let large = sizes.iter().filter(|size| **size >= min).count();
+        assert!(large < expected.len());
+        assert_eq!(looping(min).len(), large);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_extract_indicators() {
+        use crate::indicator::{IndicatorKind, IndicatorKinds, IndicatorOptions};
+
+        let mut strings = vec![
+            "1.0.0.1",
+            "10.0.2.2",
+            "2.3.4.5",
+            "192.168.0.1",
+            "3.14",
+            "4.0.3",
+            "::",
+            "2001:db8::1",
+            "android.intent.action.SEND",
+            "com.example.app",
+            "java.io",
+            "/system/bin/su",
+            "/not a path",
+            "evil-cdn.com",
+            "Lcom/example/Foo;",
+            "version 1.2.3.4",
+        ];
+        strings.sort_unstable();
+        let dex = super::DexReader::from_vec(minimal_dex(&strings, 0)).expect("cannot load dex");
+        let indicators = dex
+            .extract_indicators(&IndicatorOptions::new())
+            .expect("bad indicators");
+        let found: Vec<(IndicatorKind, &str)> = indicators
+            .iter()
+            .map(|indicator| (indicator.kind(), &**indicator.value()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (IndicatorKind::FilePath, "/system/bin/su"),
+                (IndicatorKind::Ipv4, "10.0.2.2"),
+                (IndicatorKind::Ipv4, "192.168.0.1"),
+                (IndicatorKind::Ipv6, "2001:db8::1"),
+                (IndicatorKind::IntentAction, "android.intent.action.SEND"),
+                (IndicatorKind::PackageName, "com.example.app"),
+                (IndicatorKind::Domain, "evil-cdn.com"),
+            ]
+        );
+        let string_data_offs = dex.strings.string_data_offsets().expect("bad offsets");
+        for indicator in &indicators {
+            let string_id = indicator.string_id();
+            assert_eq!(strings[string_id as usize], indicator.value());
+            assert_eq!(
+                indicator.string_data_off(),
+                string_data_offs[string_id as usize]
+            );
+            assert!(indicator.referencing_methods().is_empty());
+        }
+
+        let ips = dex
+            .extract_indicators(&IndicatorOptions::new().kinds(IndicatorKinds::IPV4))
+            .expect("bad indicators");
+        assert_eq!(ips.len(), 2);
+
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let options = IndicatorOptions::new()
+            .kinds(IndicatorKinds::FILE_PATH)
+            .referencing_methods(true);
+        let paths = dex.extract_indicators(&options).expect("bad indicators");
+        assert_eq!(paths[0].value(), "/databases/launcher.db");
+        let methods: Vec<String> = paths[0]
+            .referencing_methods()
+            .iter()
+            .map(|method| method.class().type_descriptor().to_string())
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                "Lorg/adw/launcher/MyLauncherSettings$ExportDatabaseTask;",
+                "Lorg/adw/launcher/MyLauncherSettings$ImportDatabaseTask;",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_compiler_markers() {
+        const D8: &str = r#"~~D8{"compilation-mode":"release","min-api":21,"version":"1.2.48"}"#;
+        const R8: &str = r#"~~R8{"backend":"dex","compilation-mode":"release","has-checksums":false,"min-api":24,"version":"8.2.42"}"#;
+        const MALFORMED: &str = "~~R8{version";
+        let mut strings = vec!["Lfoo;", "a", "~", "~only", D8, R8, MALFORMED, "\u{7f}"];
+        strings.sort_unstable();
+        let dex = super::DexReader::from_vec(minimal_dex(&strings, 0)).expect("cannot load dex");
+        let markers = dex.compiler_markers().expect("bad markers");
+        let raw: Vec<&str> = markers.iter().map(|marker| &**marker.raw()).collect();
+        assert_eq!(raw, [D8, R8, MALFORMED]);
+        assert_eq!(markers[2].tool(), "R8");
+        #[cfg(feature = "json")]
+        {
+            assert!(markers[2].parse_error().is_some());
+            assert_eq!(markers[0].min_api(), Some(21));
+            assert_eq!(markers[1].backend().as_deref(), Some("dex"));
+        }
+
+        for strings in &[&["a", "b"][..], &[D8][..], &[][..]] {
+            let dex = super::DexReader::from_vec(minimal_dex(strings, 0)).expect("bad dex");
+            let markers = dex.compiler_markers().expect("bad markers");
+            assert_eq!(
+                markers.len(),
+                strings.iter().filter(|s| s.starts_with("~~")).count()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_feature_report_plain_035() {
+        use crate::features::DexFeature;
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let report = dex.feature_report(2).expect("bad report");
+        assert_eq!(report.version(), "035");
+        assert_eq!(report.min_version(), "035");
+        assert_eq!(report.min_api(), None);
+        assert_eq!(report.min_opcode(), Some(0x00));
+        assert!(report.max_opcode().expect("no code") < 0xfa);
+        let features: Vec<_> = report
+            .features()
+            .iter()
+            .map(|usage| usage.feature())
+            .collect();
+        assert_eq!(features, [DexFeature::SyntheticAccessor]);
+        let accessors = report
+            .usage(DexFeature::SyntheticAccessor)
+            .expect("no accessors");
+        assert!(accessors.count() > 2);
+        assert_eq!(accessors.examples().len(), 2);
+        assert!(accessors.examples()[0].to_string().contains("->access$"));
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_feature_report_detects_features() {
+        use crate::features::{DexFeature, FeatureLocation};
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        // the first invoke-static and const-string, by file offset of their opcode, and a
+        // class with a static method and a virtual method with code
+        let (mut invoke_static, mut const_string, mut interface) = (None, None, None);
+        for (index, class_def) in dex.class_defs().enumerate() {
+            let class_def = class_def.expect("bad class def");
+            let class_data = match dex
+                .get_class_data(class_def.class_data_off)
+                .expect("bad data")
+            {
+                Some(class_data) => class_data,
+                None => continue,
+            };
+            let has_static = class_data
+                .direct_methods()
+                .iter()
+                .flat_map(|m| m.inner())
+                .any(|m| {
+                    let flags = crate::method::AccessFlags::from_bits_truncate(*m.access_flags());
+                    flags.contains(crate::method::AccessFlags::STATIC)
+                        && !flags.contains(crate::method::AccessFlags::CONSTRUCTOR)
+                });
+            let has_default = class_data
+                .virtual_methods()
+                .iter()
+                .flat_map(|m| m.inner())
+                .any(|m| *m.code_offset() != 0);
+            if has_static && has_default && interface.is_none() {
+                interface = Some(index);
+            }
+            let methods = [class_data.direct_methods(), class_data.virtual_methods()];
+            for method in methods.iter().flatten().flat_map(|methods| methods.inner()) {
+                let code_off = *method.code_offset() as usize;
+                let insns = dex.read_insns(code_off).expect("bad code");
+                for instruction in super::Instructions::new(&insns) {
+                    let instruction = instruction.expect("bad instruction");
+                    let (opcode, found) = match instruction.kind {
+                        super::InstructionKind::Op(opcode) => {
+                            (opcode, (code_off, method.method_id(), instruction.addr))
+                        }
+                        _ => continue,
+                    };
+                    match opcode.mnemonic {
+                        "invoke-static" => invoke_static = invoke_static.or(Some(found)),
+                        "const-string" => const_string = const_string.or(Some(found)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let (invoke_static, const_string, interface) = (
+            invoke_static.expect("no invoke-static"),
+            const_string.expect("no const-string"),
+            interface.expect("no candidate interface"),
+        );
+        let opcode_off =
+            |(code_off, _, addr): (usize, super::MethodId, usize)| code_off + 16 + 2 * addr;
+        let class_defs_off = dex.inner.class_defs_offset() as usize;
+        let map_off = dex.header().map_off as usize;
+        let data = mutated_example_dex(|data| {
+            // same formats: 35c and 21c
+            data[opcode_off(invoke_static)] = 0xfc;
+            data[opcode_off(const_string)] = 0xfe;
+            let flags_off = class_defs_off + 32 * interface + 4;
+            let flags: u32 = data.pread_with(flags_off, scroll::LE).unwrap();
+            data.pwrite_with(flags | 0x200 | 0x400, flags_off, scroll::LE)
+                .unwrap();
+            let map_size: u32 = data.pread_with(map_off, scroll::LE).unwrap();
+            let debug_info = (0..map_size as usize)
+                .map(|index| map_off + 4 + 12 * index)
+                .find(|item| data.pread_with::<u16>(*item, scroll::LE).unwrap() == 0x2003)
+                .expect("no debug info");
+            data.pwrite_with(0xf000u16, debug_info, scroll::LE).unwrap();
+        });
+        let mutated = super::DexReader::from_vec(data).expect("cannot open mutated dex");
+        let report = mutated.feature_report(1).expect("bad report");
+
+        for feature in &[
+            DexFeature::InvokeCustom,
+            DexFeature::ConstMethodHandle,
+            DexFeature::DefaultInterfaceMethod,
+            DexFeature::StaticInterfaceMethod,
+            DexFeature::HiddenApiData,
+        ] {
+            assert!(report.uses(*feature), "{:?} not detected", feature);
+        }
+        assert!(!report.uses(DexFeature::InvokePolymorphic));
+        assert!(!report.uses(DexFeature::ConstMethodType));
+        assert_eq!(report.max_opcode(), Some(0xfe));
+        assert_eq!(report.min_api(), Some(28));
+        assert_eq!(report.min_version(), "039");
+        let expected_site = FeatureLocation::Instruction {
+            method: dex.get_method_ref(invoke_static.1).expect("bad method"),
+            addr: invoke_static.2,
+        };
+        let invoke_custom = report.usage(DexFeature::InvokeCustom).expect("no usage");
+        assert_eq!(invoke_custom.examples(), &[expected_site]);
+        let text = report.to_string();
+        assert!(text.contains("min_version: 039\n"));
+        assert!(text.contains("hiddenapi-data: 1 ["));
+        #[cfg(feature = "json")]
+        assert_eq!(report.to_json()["features"]["invoke-custom"]["count"], 1);
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_duplicate_annotation_sets() {
+        let dex = super::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        assert!(dex
+            .duplicate_annotation_sets()
+            .expect("bad sets")
+            .is_empty());
+        let section = dex
+            .map_list()
+            .get(super::ItemType::AnnotationSetItem)
+            .expect("no annotation sets");
+        // the first two sets of the same size, which can't have the same annotations
+        let mut by_size = std::collections::HashMap::new();
+        let mut offset = section.offset();
+        let (first, second) = loop {
+            let set = dex.get_annotation_set_item(offset).expect("bad set");
+            let size = set.item_offsets().len();
+            assert_eq!(set.item_offsets().len(), set.annotations().len());
+            if let Some(first) = by_size.insert(size, offset) {
+                break (first, offset);
+            }
+            offset += 4 + 4 * size as u32;
+        };
+        let recorder = dex.record_owners().expect("bad owners");
+        let owners: Vec<_> = [first, second]
+            .iter()
+            .flat_map(|offset| recorder.owners(super::SharedItemKind::AnnotationSet, *offset))
+            .cloned()
+            .collect();
+        let data = mutated_example_dex(|data| {
+            let len = 4 + 4 * dex
+                .get_annotation_set_item(first)
+                .expect("bad set")
+                .item_offsets()
+                .len();
+            let (first, second) = (first as usize, second as usize);
+            let copy = data[first..first + len].to_vec();
+            data[second..second + len].copy_from_slice(&copy);
+        });
+        let mutated = super::DexReader::from_vec(data).expect("cannot open mutated dex");
+        let duplicates = mutated.duplicate_annotation_sets().expect("bad sets");
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].offsets(), &[first, second]);
+        assert_eq!(duplicates[0].owners(), &owners[..]);
+        let set = mutated.get_annotation_set_item(second).expect("bad set");
+        assert_eq!(
+            set.content_hash(&mutated).expect("bad hash"),
+            duplicates[0].content_hash()
+        );
     }
 }
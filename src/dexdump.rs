@@ -0,0 +1,359 @@
+//! Renders annotations the way `dexdump -a` prints them, to diff the parser against the
+//! AOSP tooling. The rendering follows `dexdump.cc`: chars are printed as unsigned
+//! decimals, floats and doubles with C's `%g`, fields, enums and methods by their names,
+//! and strings escaped and quoted. Strings are printed from their decoded form, so
+//! strings holding NULs or supplementary characters differ from dexdump's raw MUTF-8
+//! output.
+use std::fmt::Write;
+
+use crate::{
+    annotation::{
+        AnnotationItem, AnnotationSetItem, AnnotationsDirectoryItem, EncodedAnnotation, Visibility,
+    },
+    encoded_value::EncodedValue,
+    source::DexSource,
+    Dex, Result,
+};
+
+impl EncodedValue {
+    /// Renders the value the way `dexdump` prints the values of annotation elements.
+    pub fn to_dexdump_string<S: DexSource>(&self, dex: &Dex<S>) -> Result<String> {
+        let mut out = String::new();
+        write_value(self, dex, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl AnnotationItem {
+    /// Renders the annotation the way `dexdump -a` prints it: the visibility, the type
+    /// and the `name=value` elements, e.g. `VISIBILITY_RUNTIME LFoo; value=1`.
+    pub fn to_dexdump_string<S: DexSource>(&self, dex: &Dex<S>) -> Result<String> {
+        let mut out = String::new();
+        write_item(self, dex, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl AnnotationSetItem {
+    /// Renders the annotations, one per line and indented by two spaces, or
+    /// `empty-annotation-set` if there are none.
+    pub fn to_dexdump_string<S: DexSource>(&self, dex: &Dex<S>) -> Result<String> {
+        let mut out = String::new();
+        write_set(self, dex, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl AnnotationsDirectoryItem {
+    /// Renders the directory the way `dexdump -a` prints the annotations of a class,
+    /// without the leading `Class #N annotations:` line, which depends on the index of
+    /// the class. The output ends with an empty line.
+    pub fn to_dexdump_string<S: DexSource>(&self, dex: &Dex<S>) -> Result<String> {
+        let mut out = String::new();
+        if !self.class_annotations.is_empty() {
+            out.push_str("Annotations on class\n");
+            write_set(&self.class_annotations, dex, &mut out)?;
+        }
+        for field in &self.field_annotations {
+            let name = dex.get_string(dex.get_field_item(field.field_idx)?.name_idx())?;
+            let _ = writeln!(out, "Annotations on field #{} '{}'", field.field_idx, name);
+            write_set(&field.annotations, dex, &mut out)?;
+        }
+        for method in &self.method_annotations {
+            let name = dex.get_string(dex.get_method_item(method.method_idx)?.name_idx())?;
+            let _ = writeln!(
+                out,
+                "Annotations on method #{} '{}'",
+                method.method_idx, name
+            );
+            write_set(&method.annotations, dex, &mut out)?;
+        }
+        for parameters in &self.parameter_annotations {
+            let name = dex.get_string(dex.get_method_item(parameters.method_idx)?.name_idx())?;
+            let _ = writeln!(
+                out,
+                "Annotations on method #{} '{}' parameters",
+                parameters.method_idx, name
+            );
+            for (index, set) in parameters.annotations.iter().enumerate() {
+                let _ = writeln!(out, "#{}", index);
+                write_set(set, dex, &mut out)?;
+            }
+        }
+        out.push('\n');
+        Ok(out)
+    }
+}
+
+fn write_set<S: DexSource>(set: &AnnotationSetItem, dex: &Dex<S>, out: &mut String) -> Result<()> {
+    if set.is_empty() {
+        out.push_str("  empty-annotation-set\n");
+        return Ok(());
+    }
+    for item in set.iter() {
+        out.push_str("  ");
+        write_item(item, dex, out)?;
+        out.push('\n');
+    }
+    Ok(())
+}
+
+fn write_item<S: DexSource>(item: &AnnotationItem, dex: &Dex<S>, out: &mut String) -> Result<()> {
+    out.push_str(match item.visibility() {
+        Visibility::Build => "VISIBILITY_BUILD ",
+        Visibility::Runtime => "VISIBILITY_RUNTIME ",
+        Visibility::System => "VISIBILITY_SYSTEM ",
+    });
+    write_annotation(item.annotation(), dex, out)
+}
+
+fn write_annotation<S: DexSource>(
+    annotation: &EncodedAnnotation,
+    dex: &Dex<S>,
+    out: &mut String,
+) -> Result<()> {
+    out.push_str(annotation.jtype().type_descriptor());
+    for element in annotation.elements() {
+        let _ = write!(out, " {}=", element.name());
+        write_value(element.value(), dex, out)?;
+    }
+    Ok(())
+}
+
+fn write_value<S: DexSource>(value: &EncodedValue, dex: &Dex<S>, out: &mut String) -> Result<()> {
+    match value {
+        EncodedValue::Byte(b) => {
+            let _ = write!(out, "{}", b);
+        }
+        EncodedValue::Short(s) => {
+            let _ = write!(out, "{}", s);
+        }
+        EncodedValue::Char(c) => {
+            let _ = write!(out, "{}", c);
+        }
+        EncodedValue::Int(i) => {
+            let _ = write!(out, "{}", i);
+        }
+        EncodedValue::Long(l) => {
+            let _ = write!(out, "{}", l);
+        }
+        EncodedValue::Float(f) => out.push_str(&format_g(f64::from(*f))),
+        EncodedValue::Double(d) => out.push_str(&format_g(*d)),
+        EncodedValue::String { value, .. } => write_escaped(&value.to_string(), out),
+        EncodedValue::Type(jtype) => out.push_str(jtype.type_descriptor()),
+        EncodedValue::Field(field) | EncodedValue::Enum(field) => {
+            out.push_str(&dex.get_string(field.name_idx())?.to_string())
+        }
+        EncodedValue::Method(method) => {
+            out.push_str(&dex.get_string(method.name_idx())?.to_string())
+        }
+        EncodedValue::MethodType { value, .. } => {
+            out.push('(');
+            for param in dex.get_proto_params(value)? {
+                out.push_str(param.type_descriptor());
+            }
+            out.push(')');
+            out.push_str(dex.get_type(value.return_type())?.type_descriptor());
+        }
+        EncodedValue::MethodHandle { idx, .. } => {
+            let _ = write!(out, "method_handle@{}", idx);
+        }
+        EncodedValue::Array(values) => {
+            out.push('{');
+            for value in values {
+                out.push(' ');
+                write_value(value, dex, out)?;
+            }
+            out.push_str(" }");
+        }
+        EncodedValue::Annotation(annotation) => write_annotation(annotation, dex, out)?,
+        EncodedValue::Null => out.push_str("null"),
+        EncodedValue::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+    }
+    Ok(())
+}
+
+/// Quotes the string, escaping backslashes, quotes, tabs, newlines and carriage returns.
+fn write_escaped(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Formats the value like C's `printf("%g")`: six significant digits, trailing zeros
+/// removed, and the exponent form for exponents below -4 or above 5.
+fn format_g(value: f64) -> String {
+    const PRECISION: i32 = 6;
+    if value.is_nan() {
+        return if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .to_string();
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    // the exponent after rounding to the precision
+    let scientific = format!("{:.*e}", (PRECISION - 1) as usize, value);
+    let (mantissa, exponent) = scientific.split_once('e').expect("exponent form");
+    let exponent: i32 = exponent.parse().expect("integer exponent");
+    if (-4..PRECISION).contains(&exponent) {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exponent) as usize, value);
+        strip_zeros(&fixed).to_string()
+    } else {
+        format!(
+            "{}e{}{:02}",
+            strip_zeros(mantissa),
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        )
+    }
+}
+
+/// Removes the trailing zeros of the fraction and the decimal point if nothing is left.
+fn strip_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_g;
+    use crate::{encoded_value::EncodedValue, method::MethodHandleItem, DexReader};
+    use scroll::Pread;
+
+    #[test]
+    fn test_format_g() {
+        let cases: &[(f64, &str)] = &[
+            (0.0, "0"),
+            (-0.0, "-0"),
+            (1.0, "1"),
+            (1.5, "1.5"),
+            (-2.25, "-2.25"),
+            (100000.0, "100000"),
+            (1000000.0, "1e+06"),
+            (123456789.0, "1.23457e+08"),
+            (0.0001, "0.0001"),
+            (0.00001, "1e-05"),
+            (1.23456789, "1.23457"),
+            (999999.5, "1e+06"),
+            (1e100, "1e+100"),
+            (f64::from(0.1f32), "0.1"),
+            (f64::from(f32::MAX), "3.40282e+38"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "nan"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(format_g(*value), *expected, "formatting {}", value);
+        }
+    }
+
+    #[test]
+    fn test_every_value_kind() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let field_name = dex
+            .get_string(dex.get_field_item(0).expect("bad field").name_idx())
+            .expect("bad string")
+            .to_string();
+        let method_name = dex
+            .get_string(dex.get_method_item(0).expect("bad method").name_idx())
+            .expect("bad string")
+            .to_string();
+        let proto = dex.get_proto_item(0).expect("bad proto");
+        let signature = format!(
+            "({}){}",
+            dex.get_proto_params(&proto)
+                .expect("bad params")
+                .iter()
+                .map(|t| t.type_descriptor().to_string())
+                .collect::<String>(),
+            dex.get_type(proto.return_type()).expect("bad type")
+        );
+        let jtype = dex.get_type(0).expect("bad type");
+        // a method handle invoking method 0
+        let handle: MethodHandleItem = [4u8, 0, 0, 0, 0, 0, 0, 0]
+            .pread_with(0, &dex)
+            .expect("bad method handle");
+        // an annotation of type 0 with a single int element named by string 0
+        let annotation: EncodedValue = [0x1du8, 0, 1, 0, 0x04, 1]
+            .pread_with(0, &dex)
+            .expect("bad annotation");
+        let annotation_string = format!("{} {}=1", jtype, dex.get_string(0).expect("bad string"));
+        let cases = vec![
+            (EncodedValue::Byte(-3), "-3".to_string()),
+            (EncodedValue::Short(-300), "-300".to_string()),
+            (EncodedValue::Char(0xffff), "65535".to_string()),
+            (EncodedValue::Int(i32::MIN), "-2147483648".to_string()),
+            (EncodedValue::Long(1 << 40), "1099511627776".to_string()),
+            (EncodedValue::Float(2.5), "2.5".to_string()),
+            (EncodedValue::Double(-1e-7), "-1e-07".to_string()),
+            (
+                EncodedValue::String {
+                    idx: 0,
+                    value: "a\"b\\c\td\ne\rf".to_string().into(),
+                },
+                "\"a\\\"b\\\\c\\td\\ne\\rf\"".to_string(),
+            ),
+            (EncodedValue::Type(jtype.clone()), jtype.to_string()),
+            (
+                EncodedValue::Field(dex.get_field_item(0).expect("bad field")),
+                field_name.clone(),
+            ),
+            (
+                EncodedValue::Enum(dex.get_field_item(0).expect("bad field")),
+                field_name,
+            ),
+            (
+                EncodedValue::Method(dex.get_method_item(0).expect("bad method")),
+                method_name,
+            ),
+            (
+                EncodedValue::MethodType {
+                    idx: 0,
+                    value: proto,
+                },
+                signature,
+            ),
+            (
+                EncodedValue::MethodHandle {
+                    idx: 7,
+                    value: handle,
+                },
+                "method_handle@7".to_string(),
+            ),
+            (
+                EncodedValue::Array(vec![EncodedValue::Int(1), EncodedValue::Null]),
+                "{ 1 null }".to_string(),
+            ),
+            (EncodedValue::Array(vec![]), "{ }".to_string()),
+            (annotation, annotation_string),
+            (EncodedValue::Null, "null".to_string()),
+            (EncodedValue::Boolean(true), "true".to_string()),
+            (EncodedValue::Boolean(false), "false".to_string()),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                value.to_dexdump_string(&dex).expect("cannot render value"),
+                expected
+            );
+        }
+    }
+}
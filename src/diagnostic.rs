@@ -0,0 +1,452 @@
+//! Problems found in a file which don't prevent reading it.
+//!
+//! The passes checking a file report what they find as `Diagnostic`s: a severity, a stable
+//! `DiagnosticCode`, where in the file the problem is, which item it concerns and a message.
+//! `Dex::diagnostics` holds the ones found while reading the file, and the records of the
+//! other passes, such as `MisalignedItem` and `InvalidName`, convert to them, so that they
+//! can be gathered in one `Diagnostics` collection and filtered or exported together.
+use std::{fmt, iter::FromIterator, ops::Deref};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    alignment::MisalignedItem,
+    uint,
+    validate::{self, InvalidName},
+};
+
+/// How bad a problem is. Severities are ordered from the least to the most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth knowing, but the file is as the format requires.
+    Info,
+    /// The file breaks a rule of the format which ART tolerates.
+    Warning,
+    /// The file breaks a rule of the format which ART enforces.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The kinds of `Diagnostic`, each with a numeric code which is stable across versions.
+///
+/// Codes are never reused or renumbered once assigned. They are grouped in the ranges of
+/// `ErrorKind`:
+///
+/// | Range | Category |
+/// |-------|----------|
+/// | 100-199 | header and map list |
+/// | 200-299 | string, type, proto, field and method pools |
+/// | 300-399 | annotations |
+/// | 400-499 | code items |
+///
+/// Assigned codes:
+///
+/// | Code | Kind |
+/// |------|------|
+/// | 100 | `EmptySection` |
+/// | 101 | `EmptyMapItem` |
+/// | 102 | `MisalignedItem` |
+/// | 200 | `InvalidName` |
+/// | 201 | `ArtToleratedName` |
+/// | 202 | `DuplicateClassDef` |
+/// | 300 | `ParameterAnnotationCount` |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DiagnosticCode {
+    /// An id section of the header is empty but has a nonzero offset.
+    EmptySection,
+    /// An item of the map list is empty but has a nonzero offset.
+    EmptyMapItem,
+    /// An item doesn't start at an offset aligned for its type.
+    MisalignedItem,
+    /// A name or descriptor doesn't match its grammar.
+    InvalidName,
+    /// A name uses characters which the version of the file doesn't allow yet.
+    ArtToleratedName,
+    /// A class is defined by several class_defs.
+    DuplicateClassDef,
+    /// The parameter annotations of a method list more or fewer sets than the method has
+    /// parameters.
+    ParameterAnnotationCount,
+}
+
+impl DiagnosticCode {
+    /// All the diagnostic codes, in the order of their numbers.
+    pub const ALL: [DiagnosticCode; 7] = [
+        DiagnosticCode::EmptySection,
+        DiagnosticCode::EmptyMapItem,
+        DiagnosticCode::MisalignedItem,
+        DiagnosticCode::InvalidName,
+        DiagnosticCode::ArtToleratedName,
+        DiagnosticCode::DuplicateClassDef,
+        DiagnosticCode::ParameterAnnotationCount,
+    ];
+
+    /// The stable numeric code.
+    pub fn code(self) -> u32 {
+        match self {
+            DiagnosticCode::EmptySection => 100,
+            DiagnosticCode::EmptyMapItem => 101,
+            DiagnosticCode::MisalignedItem => 102,
+            DiagnosticCode::InvalidName => 200,
+            DiagnosticCode::ArtToleratedName => 201,
+            DiagnosticCode::DuplicateClassDef => 202,
+            DiagnosticCode::ParameterAnnotationCount => 300,
+        }
+    }
+
+    /// Returns the diagnostic code with the number, if any.
+    pub fn from_code(code: u32) -> Option<DiagnosticCode> {
+        Self::ALL.iter().cloned().find(|kind| kind.code() == code)
+    }
+}
+
+/// A range of bytes of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, CopyGetters)]
+#[get_copy = "pub"]
+pub struct Span {
+    /// Offset of the first byte from the start of the file.
+    offset: uint,
+    /// Number of bytes, if known.
+    size: Option<uint>,
+}
+
+impl Span {
+    /// A span of `size` bytes starting at `offset`.
+    pub fn new(offset: uint, size: uint) -> Self {
+        Self {
+            offset,
+            size: Some(size),
+        }
+    }
+
+    /// A span starting at `offset` whose length is unknown.
+    pub fn at(offset: uint) -> Self {
+        Self { offset, size: None }
+    }
+}
+
+/// A problem found in a file.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct Diagnostic {
+    /// How bad the problem is.
+    #[get_copy = "pub"]
+    severity: Severity,
+    /// What kind of problem it is.
+    #[get_copy = "pub"]
+    code: DiagnosticCode,
+    /// Where the problem is in the file, if it is tied to bytes of the file.
+    #[get_copy = "pub"]
+    span: Option<Span>,
+    /// The item concerned, such as the descriptor of a class or the smali notation of a
+    /// method or field.
+    #[get = "pub"]
+    context: Option<String>,
+    /// What is wrong.
+    #[get = "pub"]
+    message: String,
+}
+
+impl Diagnostic {
+    /// A diagnostic without span or context.
+    pub fn new<S: Into<String>>(severity: Severity, code: DiagnosticCode, message: S) -> Self {
+        Self {
+            severity,
+            code,
+            span: None,
+            context: None,
+            message: message.into(),
+        }
+    }
+
+    /// Sets the bytes of the file the diagnostic is about.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Sets the item the diagnostic is about.
+    pub fn with_context<S: Into<String>>(mut self, context: S) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// The diagnostic as a JSON object with the fields `severity`, `code`, `kind`,
+    /// `offset`, `size`, `context` and `message`, the missing ones being `null`.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "severity": self.severity.to_string(),
+            "code": self.code.code(),
+            "kind": format!("{:?}", self.code),
+            "offset": self.span.map(|span| span.offset),
+            "size": self.span.and_then(|span| span.size),
+            "context": self.context,
+            "message": self.message,
+        })
+    }
+}
+
+/// Formats the diagnostic as `warning[101]: message at offset 112 in context`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.severity,
+            self.code.code(),
+            self.message
+        )?;
+        if let Some(span) = self.span {
+            write!(f, " at offset {}", span.offset)?;
+        }
+        if let Some(context) = &self.context {
+            write!(f, " in {}", context)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<MisalignedItem> for Diagnostic {
+    fn from(item: MisalignedItem) -> Self {
+        Diagnostic::new(
+            Severity::Warning,
+            DiagnosticCode::MisalignedItem,
+            item.to_string(),
+        )
+        .with_span(Span::at(item.offset()))
+    }
+}
+
+impl From<InvalidName> for Diagnostic {
+    fn from(name: InvalidName) -> Self {
+        let (severity, code) = match name.error().severity() {
+            validate::Severity::ArtTolerated => {
+                (Severity::Warning, DiagnosticCode::ArtToleratedName)
+            }
+            validate::Severity::Invalid => (Severity::Error, DiagnosticCode::InvalidName),
+        };
+        Diagnostic::new(
+            severity,
+            code,
+            format!("{:?}: {}", name.value(), name.error()),
+        )
+        .with_context(format!("{:?} {}", name.item_type(), name.index()))
+    }
+}
+
+/// A collection of diagnostics, in the order they were found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// An empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a diagnostic.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// The diagnostics of the severity.
+    pub fn with_severity(&self, severity: Severity) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.iter()
+            .filter(move |diagnostic| diagnostic.severity == severity)
+    }
+
+    /// The diagnostics of the severity or a more severe one.
+    pub fn at_least(&self, severity: Severity) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.iter()
+            .filter(move |diagnostic| diagnostic.severity >= severity)
+    }
+
+    /// The diagnostics with the code.
+    pub fn with_code(&self, code: DiagnosticCode) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.iter()
+            .filter(move |diagnostic| diagnostic.code == code)
+    }
+
+    /// Returns `true` if any diagnostic is an error.
+    pub fn has_errors(&self) -> bool {
+        self.at_least(Severity::Error).next().is_some()
+    }
+
+    /// The diagnostics as a JSON array of the objects of `Diagnostic::to_json`.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Array(self.iter().map(Diagnostic::to_json).collect())
+    }
+}
+
+impl Deref for Diagnostics {
+    type Target = [Diagnostic];
+
+    fn deref(&self) -> &Self::Target {
+        &self.diagnostics
+    }
+}
+
+impl Extend<Diagnostic> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        self.diagnostics.extend(iter);
+    }
+}
+
+impl FromIterator<Diagnostic> for Diagnostics {
+    fn from_iter<I: IntoIterator<Item = Diagnostic>>(iter: I) -> Self {
+        Self {
+            diagnostics: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, DiagnosticCode, Diagnostics, Severity, Span};
+    use crate::{alignment::MisalignedItem, ItemType};
+    use std::collections::HashSet;
+
+    /// Fails to compile when a code is added, as a reminder to add it to
+    /// `DiagnosticCode::ALL` and the registry in its documentation.
+    fn position(code: DiagnosticCode) -> usize {
+        match code {
+            DiagnosticCode::EmptySection => 0,
+            DiagnosticCode::EmptyMapItem => 1,
+            DiagnosticCode::MisalignedItem => 2,
+            DiagnosticCode::InvalidName => 3,
+            DiagnosticCode::ArtToleratedName => 4,
+            DiagnosticCode::DuplicateClassDef => 5,
+            DiagnosticCode::ParameterAnnotationCount => 6,
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_codes() {
+        let registry = include_str!("diagnostic.rs");
+        let mut codes = HashSet::new();
+        for (index, code) in DiagnosticCode::ALL.iter().enumerate() {
+            assert_eq!(position(*code), index);
+            assert!(codes.insert(code.code()), "duplicate code {}", code.code());
+            assert_eq!(DiagnosticCode::from_code(code.code()), Some(*code));
+            let row = format!("/// | {} | `{:?}` |", code.code(), code);
+            assert!(
+                registry.contains(&row),
+                "{:?} missing from the registry",
+                code
+            );
+        }
+        assert_eq!(DiagnosticCode::from_code(0), None);
+    }
+
+    fn sample() -> Diagnostics {
+        vec![
+            Diagnostic::new(
+                Severity::Warning,
+                DiagnosticCode::EmptySection,
+                "type_ids section is empty but has offset 112",
+            )
+            .with_span(Span::new(112, 0)),
+            Diagnostic::from(MisalignedItem::new(ItemType::CodeItem, 0x1002)),
+            Diagnostic::new(Severity::Error, DiagnosticCode::InvalidName, "bad name")
+                .with_context("MethodIdItem 3"),
+            Diagnostic::new(Severity::Info, DiagnosticCode::EmptyMapItem, "empty"),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let diagnostics = sample();
+        assert_eq!(diagnostics.len(), 4);
+        assert_eq!(diagnostics.with_severity(Severity::Warning).count(), 2);
+        let severe: Vec<_> = diagnostics
+            .at_least(Severity::Warning)
+            .map(Diagnostic::code)
+            .collect();
+        assert_eq!(
+            severe,
+            [
+                DiagnosticCode::EmptySection,
+                DiagnosticCode::MisalignedItem,
+                DiagnosticCode::InvalidName
+            ]
+        );
+        let misaligned: Vec<_> = diagnostics
+            .with_code(DiagnosticCode::MisalignedItem)
+            .collect();
+        assert_eq!(misaligned.len(), 1);
+        assert_eq!(misaligned[0].span(), Some(Span::at(0x1002)));
+        assert!(diagnostics.has_errors());
+        assert!(!diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity() < Severity::Error)
+            .collect::<Diagnostics>()
+            .has_errors());
+    }
+
+    #[test]
+    fn test_display() {
+        let diagnostics = sample();
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "warning[100]: type_ids section is empty but has offset 112 at offset 112"
+        );
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "error[200]: bad name in MethodIdItem 3"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_to_json() {
+        let json = sample().to_json();
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "severity": "warning",
+                "code": 100,
+                "kind": "EmptySection",
+                "offset": 112,
+                "size": 0,
+                "context": null,
+                "message": "type_ids section is empty but has offset 112",
+            })
+        );
+        assert_eq!(json[1]["size"], serde_json::Value::Null);
+        assert_eq!(json[2]["context"], "MethodIdItem 3");
+        assert_eq!(json.as_array().map(Vec::len), Some(4));
+    }
+}
@@ -7,7 +7,11 @@ use crate::{
     code::{CatchHandler, ExceptionType},
     error::Error,
     jtype::TypeId,
-    uint, ulong, ushort,
+    leb::read_uleb128_index,
+    limits::ParserLimits,
+    source::DexSource,
+    sync::Shared,
+    ulong, ushort,
 };
 
 pub trait EncodedItem {
@@ -35,31 +39,29 @@ impl<T: EncodedItem> EncodedItemArray<T> {
     }
 }
 
-pub(crate) struct EncodedItemArrayCtx<'a, S: AsRef<[u8]>> {
+pub(crate) struct EncodedItemArrayCtx<'a, S: DexSource> {
+    #[allow(unused)]
     dex: &'a super::Dex<S>,
     len: usize,
 }
 
-impl<'a, S: AsRef<[u8]>> EncodedItemArrayCtx<'a, S> {
+impl<'a, S: DexSource> EncodedItemArrayCtx<'a, S> {
     pub(crate) fn new(dex: &'a super::Dex<S>, len: usize) -> Self {
         Self { dex, len }
     }
 }
 
-impl<'a, S: AsRef<[u8]>> Copy for EncodedItemArrayCtx<'a, S> {}
+impl<'a, S: DexSource> Copy for EncodedItemArrayCtx<'a, S> {}
 
-impl<'a, S: AsRef<[u8]>> Clone for EncodedItemArrayCtx<'a, S> {
+impl<'a, S: DexSource> Clone for EncodedItemArrayCtx<'a, S> {
     fn clone(&self) -> Self {
-        Self {
-            dex: self.dex,
-            len: self.len,
-        }
+        *self
     }
 }
 
 impl<'a, S, T: 'a> ctx::TryFromCtx<'a, EncodedItemArrayCtx<'a, S>> for EncodedItemArray<T>
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
     T: EncodedItem + ctx::TryFromCtx<'a, ulong, Size = usize, Error = Error>,
 {
     type Error = Error;
@@ -82,20 +84,20 @@ where
     }
 }
 
+/// The catch handlers of a code item, each parsed once and shared by the try blocks using it.
 #[derive(Debug)]
 pub(crate) struct EncodedCatchHandlers {
-    inner: Vec<(usize, EncodedCatchHandler)>,
+    /// Handlers by their offset from the start of the list, in order.
+    inner: Vec<(usize, Shared<[CatchHandler]>)>,
 }
 
 impl EncodedCatchHandlers {
-    pub(crate) fn iter(&self) -> impl Iterator<Item = &(usize, EncodedCatchHandler)> {
-        self.inner.iter()
-    }
-
-    pub(crate) fn find(&self, handler_offset: ushort) -> Option<&EncodedCatchHandler> {
-        self.iter()
-            .find(|p| p.0 == handler_offset as usize)
-            .map(|p| &p.1)
+    /// Returns the handlers at `handler_offset` from the start of the list.
+    pub(crate) fn find(&self, handler_offset: ushort) -> Option<Shared<[CatchHandler]>> {
+        self.inner
+            .binary_search_by_key(&(handler_offset as usize), |p| p.0)
+            .ok()
+            .map(|index| self.inner[index].1.clone())
     }
 }
 
@@ -104,15 +106,9 @@ pub(crate) struct EncodedCatchHandler {
     handlers: Vec<CatchHandler>,
 }
 
-impl EncodedCatchHandler {
-    pub(crate) fn handlers(&self) -> Vec<CatchHandler> {
-        self.handlers.to_vec()
-    }
-}
-
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for EncodedCatchHandler
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = crate::error::Error;
     type Size = usize;
@@ -120,8 +116,13 @@ where
     fn try_from_ctx(source: &'a [u8], dex: &super::Dex<S>) -> super::Result<(Self, Self::Size)> {
         let offset = &mut 0;
         let size = Sleb128::read(source, offset)?;
+        ParserLimits::check(
+            "Catch handler type count",
+            size.unsigned_abs() as usize,
+            dex.limits.max_handler_types,
+        )?;
         let type_addr_pairs: Vec<EncodedTypeAddrPair> =
-            try_gread_vec_with!(source, offset, size.abs(), ());
+            try_gread_vec_with!(source, offset, size.unsigned_abs(), ());
         let mut handlers: Vec<CatchHandler> = type_addr_pairs
             .into_iter()
             .map(|type_addr_pair| {
@@ -144,7 +145,7 @@ where
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for EncodedCatchHandlers
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = crate::error::Error;
     type Size = usize;
@@ -155,8 +156,8 @@ where
         let mut encoded_catch_handlers = Vec::with_capacity(encoded_handler_size as usize);
         for _ in 0..encoded_handler_size {
             let off = *offset;
-            let encoded_catch_handler = source.gread_with(offset, dex)?;
-            encoded_catch_handlers.push((off, encoded_catch_handler));
+            let encoded_catch_handler: EncodedCatchHandler = source.gread_with(offset, dex)?;
+            encoded_catch_handlers.push((off, encoded_catch_handler.handlers.into()));
         }
         Ok((
             Self {
@@ -179,14 +180,8 @@ impl<'a> ctx::TryFromCtx<'a, ()> for EncodedTypeAddrPair {
 
     fn try_from_ctx(source: &'a [u8], _: ()) -> super::Result<(Self, Self::Size)> {
         let offset = &mut 0;
-        let type_id = Uleb128::read(source, offset)?;
+        let type_id = read_uleb128_index(source, offset)?;
         let addr = Uleb128::read(source, offset)?;
-        Ok((
-            Self {
-                type_id: type_id as uint,
-                addr,
-            },
-            *offset,
-        ))
+        Ok((Self { type_id, addr }, *offset))
     }
 }
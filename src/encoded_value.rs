@@ -1,8 +1,15 @@
 //! Contains structures defining values in a `Dex`.
+use std::borrow::Cow;
+#[cfg(feature = "annotations-extras")]
+use std::hash::Hash;
+use std::hash::Hasher;
+
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use scroll::{self, ctx, Pread, Uleb128, LE};
 
+#[cfg(feature = "annotations-extras")]
+use crate::method::{FieldOrMethodId, MethodRef};
 use crate::{
     annotation::EncodedAnnotation,
     byte,
@@ -10,11 +17,13 @@ use crate::{
     field::{FieldId, FieldIdItem},
     int,
     jtype::{Type, TypeId},
+    leb::LebScanner,
     long,
     method::{MethodHandleId, MethodHandleItem, MethodId, MethodIdItem, ProtoId, ProtoIdItem},
     short,
+    source::DexSource,
     string::{DexString, StringId},
-    ubyte, uint, ushort, Result,
+    ubyte, uint, ulong, ushort, Result,
 };
 
 /// Used to represent values of fields, annotations etc.
@@ -29,9 +38,21 @@ pub enum EncodedValue {
     Type(Type),
     Float(f32),
     Double(f64),
-    MethodType(ProtoIdItem),
-    MethodHandle(MethodHandleItem),
-    String(DexString),
+    /// A prototype and its index in the `ProtoId`s list.
+    MethodType {
+        idx: ProtoId,
+        value: ProtoIdItem,
+    },
+    /// A method handle and its index in the method handles list.
+    MethodHandle {
+        idx: MethodHandleId,
+        value: MethodHandleItem,
+    },
+    /// A string and its index in the `StringId`s list.
+    String {
+        idx: StringId,
+        value: DexString,
+    },
     Field(FieldIdItem),
     Method(MethodIdItem),
     Annotation(EncodedAnnotation),
@@ -125,7 +146,7 @@ impl PartialEq<Type> for EncodedValue {
 impl PartialEq<DexString> for EncodedValue {
     fn eq(&self, other: &DexString) -> bool {
         match self {
-            EncodedValue::String(s) => s == other,
+            EncodedValue::String { value, .. } => value == other,
             _ => false,
         }
     }
@@ -134,7 +155,7 @@ impl PartialEq<DexString> for EncodedValue {
 impl PartialEq<str> for EncodedValue {
     fn eq(&self, other: &str) -> bool {
         match self {
-            EncodedValue::String(s) => s == other,
+            EncodedValue::String { value, .. } => value == other,
             _ => false,
         }
     }
@@ -146,13 +167,95 @@ macro_rules! gen_is_type_method {
         pub fn $name(&self) -> bool {
             match self {
                 $match_value => true,
-                _ => false
+                _ => false,
             }
         }
-    }
+    };
 }
 
 impl EncodedValue {
+    /// Index of the value in the pool it refers to: the strings, types, fields, methods,
+    /// prototypes or method handles. `None` for values which don't refer to a pool.
+    pub fn raw_index(&self) -> Option<ulong> {
+        match self {
+            EncodedValue::String { idx, .. } => Some(ulong::from(*idx)),
+            EncodedValue::Type(t) => Some(ulong::from(t.id())),
+            EncodedValue::Field(f) | EncodedValue::Enum(f) => Some(f.id()),
+            EncodedValue::Method(m) => Some(m.id()),
+            EncodedValue::MethodType { idx, .. } => Some(*idx),
+            EncodedValue::MethodHandle { idx, .. } => Some(ulong::from(*idx)),
+            _ => None,
+        }
+    }
+
+    /// Feeds the value to `hasher` with the items it refers to resolved to their names and
+    /// descriptors, so that equal values hash the same whatever the indices of the items.
+    #[cfg(feature = "annotations-extras")]
+    pub(crate) fn hash_canonical<S, H>(&self, dex: &super::Dex<S>, hasher: &mut H) -> Result<()>
+    where
+        S: DexSource,
+        H: Hasher,
+    {
+        let field = |f: &FieldIdItem| -> Result<String> {
+            Ok(format!(
+                "{}->{}:{}",
+                dex.get_type(TypeId::from(f.class_idx()))?,
+                dex.get_string(f.name_idx())?,
+                dex.get_type(TypeId::from(f.type_idx()))?
+            ))
+        };
+        // tagged with the value types of the format
+        match self {
+            EncodedValue::Byte(v) => (0x00u8, v).hash(hasher),
+            EncodedValue::Short(v) => (0x02u8, v).hash(hasher),
+            EncodedValue::Char(v) => (0x03u8, v).hash(hasher),
+            EncodedValue::Int(v) => (0x04u8, v).hash(hasher),
+            EncodedValue::Long(v) => (0x06u8, v).hash(hasher),
+            EncodedValue::Float(v) => {
+                let bits = if v.is_nan() { f32::NAN } else { *v }.to_bits();
+                (0x10u8, bits).hash(hasher)
+            }
+            EncodedValue::Double(v) => {
+                let bits = if v.is_nan() { f64::NAN } else { *v }.to_bits();
+                (0x11u8, bits).hash(hasher)
+            }
+            EncodedValue::MethodType { value, .. } => {
+                0x15u8.hash(hasher);
+                for param in dex.get_proto_params(value)? {
+                    (**param.type_descriptor()).hash(hasher);
+                }
+                (**dex.get_type(value.return_type())?.type_descriptor()).hash(hasher);
+            }
+            EncodedValue::MethodHandle { value, .. } => {
+                (0x16u8, value.handle_type() as ushort).hash(hasher);
+                match value.id() {
+                    FieldOrMethodId::Field(id) => field(&dex.get_field_item(id)?)?.hash(hasher),
+                    FieldOrMethodId::Method(id) => dex.get_method_ref(id)?.to_string().hash(hasher),
+                }
+            }
+            EncodedValue::String { value, .. } => (0x17u8, &**value).hash(hasher),
+            EncodedValue::Type(t) => (0x18u8, &**t.type_descriptor()).hash(hasher),
+            EncodedValue::Field(f) => (0x19u8, field(f)?).hash(hasher),
+            EncodedValue::Method(m) => {
+                (0x1au8, MethodRef::try_from_dex(dex, m)?.to_string()).hash(hasher)
+            }
+            EncodedValue::Enum(f) => (0x1bu8, field(f)?).hash(hasher),
+            EncodedValue::Array(values) => {
+                (0x1cu8, values.len()).hash(hasher);
+                for value in values {
+                    value.hash_canonical(dex, hasher)?;
+                }
+            }
+            EncodedValue::Annotation(annotation) => {
+                0x1du8.hash(hasher);
+                annotation.hash_canonical(dex, hasher)?;
+            }
+            EncodedValue::Null => 0x1eu8.hash(hasher),
+            EncodedValue::Boolean(v) => (0x1fu8, v).hash(hasher),
+        }
+        Ok(())
+    }
+
     gen_is_type_method!(
         is_byte,
         EncodedValue::Byte(_),
@@ -195,17 +298,17 @@ impl EncodedValue {
     );
     gen_is_type_method!(
         is_method_handle,
-        EncodedValue::MethodHandle(_),
+        EncodedValue::MethodHandle { .. },
         "Returns `true` if the value is a method handle"
     );
     gen_is_type_method!(
         is_method_type,
-        EncodedValue::MethodType(_),
+        EncodedValue::MethodType { .. },
         "Returns `true` if the value is a method type"
     );
     gen_is_type_method!(
         is_string,
-        EncodedValue::String(_),
+        EncodedValue::String { .. },
         "Returns `true` if the value is a string"
     );
     gen_is_type_method!(
@@ -269,7 +372,7 @@ enum ValueType {
 }
 
 macro_rules! try_extended_gread {
-    ($source:expr,$offset:expr,$value_arg:expr,$size:expr,$sign_extended:literal) => {{
+    ($dex:expr,$source:expr,$offset:expr,$value_arg:expr,$size:expr,$sign_extended:literal) => {{
         if *$offset + $value_arg >= $source.len() {
             return Err(Error::Scroll(scroll::Error::TooBig {
                     size: *$offset + $value_arg,
@@ -294,26 +397,26 @@ macro_rules! try_extended_gread {
                 i += 1;
             }
         }
-        debug!(target: "encoded-value", "bytes: {:?}", bytes);
+        dex_debug!($dex.log_label(), target: "encoded-value", "bytes: {:?}", bytes);
         let value = bytes.pread_with(0, LE)?;
         *$offset += 1 + $value_arg;
         value
     }};
-    ($source:expr, $offset:expr, $value_arg:expr, $size:expr, ZERO) => {{
-        try_extended_gread!($source, $offset, $value_arg, $size, false)
+    ($dex:expr, $source:expr, $offset:expr, $value_arg:expr, $size:expr, ZERO) => {{
+        try_extended_gread!($dex, $source, $offset, $value_arg, $size, false)
     }};
-    ($source:expr, $offset:expr, $value_arg:expr, $size:expr, SIGN) => {{
-        try_extended_gread!($source, $offset, $value_arg, $size, true)
+    ($dex:expr, $source:expr, $offset:expr, $value_arg:expr, $size:expr, SIGN) => {{
+        try_extended_gread!($dex, $source, $offset, $value_arg, $size, true)
     }};
-    ($source:expr, $offset:expr, $value_arg:expr, $size:expr) => {{
-        try_extended_gread!($source, $offset, $value_arg, $size, ZERO)
+    ($dex:expr, $source:expr, $offset:expr, $value_arg:expr, $size:expr) => {{
+        try_extended_gread!($dex, $source, $offset, $value_arg, $size, ZERO)
     }};
 
 }
 
 impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for EncodedValue
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type Error = Error;
     type Size = usize;
@@ -326,75 +429,90 @@ where
         let value_type = 0b0001_1111 & header;
         let value_type = ValueType::from_u8(value_type)
             .ok_or_else(|| Error::InvalidId(format!("Invalid value type {}", value_type)))?;
-        debug!(target: "encoded-value", "encoded value type: {:?}, value_arg: {}", value_type, value_arg);
+        dex_debug!(dex.log_label(), target: "encoded-value", "encoded value type: {:?}, value_arg: {}", value_type, value_arg);
         let value = match value_type {
             ValueType::Byte => {
                 debug_assert_eq!(value_arg, 0);
-                EncodedValue::Byte(try_extended_gread!(source, offset, value_arg, 1))
+                EncodedValue::Byte(try_extended_gread!(dex, source, offset, value_arg, 1))
             }
             ValueType::Short => {
                 debug_assert!(value_arg < 2);
-                EncodedValue::Short(try_extended_gread!(source, offset, value_arg, 2, SIGN))
+                EncodedValue::Short(try_extended_gread!(dex, source, offset, value_arg, 2, SIGN))
             }
             ValueType::Char => {
                 debug_assert!(value_arg < 2);
-                EncodedValue::Char(try_extended_gread!(source, offset, value_arg, 2))
+                EncodedValue::Char(try_extended_gread!(dex, source, offset, value_arg, 2))
             }
             ValueType::Int => {
                 debug_assert!(value_arg < 4);
-                EncodedValue::Int(try_extended_gread!(source, offset, value_arg, 4, SIGN))
+                EncodedValue::Int(try_extended_gread!(dex, source, offset, value_arg, 4, SIGN))
             }
             ValueType::Long => {
                 debug_assert!(value_arg < 8);
-                EncodedValue::Long(try_extended_gread!(source, offset, value_arg, 8, SIGN))
+                EncodedValue::Long(try_extended_gread!(dex, source, offset, value_arg, 8, SIGN))
             }
             ValueType::Float => {
                 debug_assert!(value_arg < 4);
-                EncodedValue::Float(try_extended_gread!(source, offset, value_arg, 4))
+                EncodedValue::Float(try_extended_gread!(dex, source, offset, value_arg, 4))
             }
             ValueType::Double => {
                 debug_assert!(value_arg < 8);
-                EncodedValue::Double(try_extended_gread!(source, offset, value_arg, 8))
+                EncodedValue::Double(try_extended_gread!(dex, source, offset, value_arg, 8))
             }
             ValueType::MethodType => {
                 debug_assert!(value_arg < 4);
-                let proto_id: uint = try_extended_gread!(source, offset, value_arg, 4);
-                EncodedValue::MethodType(dex.get_proto_item(ProtoId::from(proto_id))?)
+                let proto_id: uint = try_extended_gread!(dex, source, offset, value_arg, 4);
+                let idx = ProtoId::from(proto_id);
+                EncodedValue::MethodType {
+                    idx,
+                    value: dex.get_proto_item(idx)?,
+                }
             }
             ValueType::MethodHandle => {
                 debug_assert!(value_arg < 4);
-                let index: MethodHandleId = try_extended_gread!(source, offset, value_arg, 4);
-                EncodedValue::MethodHandle(dex.get_method_handle_item(index)?)
+                let idx: MethodHandleId = try_extended_gread!(dex, source, offset, value_arg, 4);
+                EncodedValue::MethodHandle {
+                    idx,
+                    value: dex.get_method_handle_item(idx)?,
+                }
             }
             ValueType::String => {
                 debug_assert!(value_arg < 4);
-                let string_id: StringId = try_extended_gread!(source, offset, value_arg, 4);
-                EncodedValue::String(dex.get_string(string_id)?)
+                let idx: StringId = try_extended_gread!(dex, source, offset, value_arg, 4);
+                EncodedValue::String {
+                    idx,
+                    value: dex.get_string(idx)?,
+                }
             }
             ValueType::Type => {
                 debug_assert!(value_arg < 4);
-                let type_id: TypeId = try_extended_gread!(source, offset, value_arg, 4);
+                let type_id: TypeId = try_extended_gread!(dex, source, offset, value_arg, 4);
                 EncodedValue::Type(dex.get_type(type_id)?)
             }
             ValueType::Field => {
                 debug_assert!(value_arg < 4);
-                let index: uint = try_extended_gread!(source, offset, value_arg, 4);
+                let index: uint = try_extended_gread!(dex, source, offset, value_arg, 4);
                 EncodedValue::Field(dex.get_field_item(FieldId::from(index))?)
             }
             ValueType::Method => {
                 debug_assert!(value_arg < 4);
-                let index: uint = try_extended_gread!(source, offset, value_arg, 4);
+                let index: uint = try_extended_gread!(dex, source, offset, value_arg, 4);
                 EncodedValue::Method(dex.get_method_item(MethodId::from(index))?)
             }
             ValueType::Enum => {
                 debug_assert!(value_arg < 4);
-                let index: uint = try_extended_gread!(source, offset, value_arg, 4);
+                let index: uint = try_extended_gread!(dex, source, offset, value_arg, 4);
                 EncodedValue::Enum(dex.get_field_item(FieldId::from(index))?)
             }
             ValueType::Array => {
                 debug_assert!(value_arg == 0);
-                let encoded_array: EncodedArray = source.gread_with(offset, dex)?;
-                EncodedValue::Array(encoded_array.into_inner())
+                let size = Uleb128::read(source, offset)?;
+                dex_debug!(dex.log_label(), target: "encoded-array", "encoded array size: {}", size);
+                let mut values = Vec::with_capacity(size as usize);
+                for _ in 0..size {
+                    values.push(source.gread_with(offset, dex)?);
+                }
+                EncodedValue::Array(values)
             }
             ValueType::Annotation => {
                 debug_assert!(value_arg == 0);
@@ -413,34 +531,334 @@ where
     }
 }
 
-/// Array of `EncodedValue`s
-#[derive(Debug, Default)]
+/// Skips the encoded value at `offset` without decoding it.
+pub(crate) fn skip_encoded_value(source: &[u8], offset: &mut usize) -> Result<()> {
+    let header: ubyte = source.gread(offset)?;
+    let value_arg = (header >> 5) as usize;
+    let value_type = 0b0001_1111 & header;
+    match ValueType::from_u8(value_type) {
+        Some(ValueType::Array) => {
+            let size = Uleb128::read(source, offset)?;
+            for _ in 0..size {
+                skip_encoded_value(source, offset)?;
+            }
+        }
+        Some(ValueType::Annotation) => {
+            // type_idx, then the name_idx and value of each element
+            Uleb128::read(source, offset)?;
+            let size = Uleb128::read(source, offset)?;
+            for _ in 0..size {
+                Uleb128::read(source, offset)?;
+                skip_encoded_value(source, offset)?;
+            }
+        }
+        Some(ValueType::Null) | Some(ValueType::Boolean) => {}
+        Some(_) => {
+            *offset += value_arg + 1;
+            if *offset > source.len() {
+                return Err(scroll::Error::TooBig {
+                    size: *offset,
+                    len: source.len(),
+                }
+                .into());
+            }
+        }
+        None => {
+            return Err(Error::InvalidId(format!(
+                "Invalid value type {}",
+                value_type
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Skips the encoded value at `offset` like `skip_encoded_value`, reading the sizes and
+/// indices of arrays and annotations with `scanner`, see `Dex::nonminimal_leb_sites`.
+pub(crate) fn scan_encoded_value(scanner: &mut LebScanner<'_>, offset: &mut usize) -> Result<()> {
+    let header = scanner.byte(offset)?;
+    let value_arg = (header >> 5) as usize;
+    let value_type = 0b0001_1111 & header;
+    match ValueType::from_u8(value_type) {
+        Some(ValueType::Array) => scan_encoded_array(scanner, offset)?,
+        Some(ValueType::Annotation) => scan_encoded_annotation(scanner, offset)?,
+        Some(ValueType::Null) | Some(ValueType::Boolean) => {}
+        Some(_) => *offset += value_arg + 1,
+        None => {
+            return Err(Error::InvalidId(format!(
+                "Invalid value type {}",
+                value_type
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Skips the encoded array at `offset`, see `scan_encoded_value`.
+pub(crate) fn scan_encoded_array(scanner: &mut LebScanner<'_>, offset: &mut usize) -> Result<()> {
+    let size = scanner.uleb128(offset)?;
+    for _ in 0..size {
+        scan_encoded_value(scanner, offset)?;
+    }
+    Ok(())
+}
+
+/// Skips the encoded annotation at `offset`, see `scan_encoded_value`.
+pub(crate) fn scan_encoded_annotation(
+    scanner: &mut LebScanner<'_>,
+    offset: &mut usize,
+) -> Result<()> {
+    // type_idx, then the name_idx and value of each element
+    scanner.uleb128(offset)?;
+    let size = scanner.uleb128(offset)?;
+    for _ in 0..size {
+        scanner.uleb128(offset)?;
+        scan_encoded_value(scanner, offset)?;
+    }
+    Ok(())
+}
+
+/// Bytes of an encoded array, along with what is needed to decode its elements.
+pub(crate) trait ArraySource: Send + Sync {
+    /// Decodes the value at `offset`.
+    fn decode(&self, offset: usize) -> Result<EncodedValue>;
+
+    /// Returns the offset following the value at `offset`.
+    fn skip(&self, offset: usize) -> Result<usize>;
+
+    /// The bytes of the array, starting with its size. They can extend past its last
+    /// element.
+    fn bytes(&self) -> Cow<'_, [u8]>;
+}
+
+/// Array of `EncodedValue`s, such as the static values of a class or a call site. The
+/// elements are decoded on access, only the offsets of the elements accessed so far are
+/// kept.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#encoded-array)
+#[derive(Default)]
 pub struct EncodedArray {
-    values: Vec<EncodedValue>,
+    len: usize,
+    source: Option<crate::sync::Shared<dyn ArraySource>>,
+    /// Offsets of the first elements, always at least the offset of the first element.
+    offsets: crate::sync::RefCell<Vec<usize>>,
 }
 
 impl EncodedArray {
-    pub(crate) fn into_inner(self) -> Vec<EncodedValue> {
-        self.values
+    /// An array of `len` elements, the first of which starts at `first_offset`.
+    pub(crate) fn new(
+        len: usize,
+        first_offset: usize,
+        source: crate::sync::Shared<dyn ArraySource>,
+    ) -> Self {
+        Self {
+            len,
+            source: Some(source),
+            offsets: crate::sync::RefCell::new(vec![first_offset]),
+        }
+    }
+
+    /// Number of elements, as declared by the array.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the array has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decodes the element at `index`. Elements before it are skipped over, not decoded,
+    /// the first time an index is reached.
+    pub fn get(&self, index: usize) -> Result<Option<EncodedValue>> {
+        if index >= self.len {
+            return Ok(None);
+        }
+        let source = match self.source {
+            Some(ref source) => source,
+            None => return Ok(None),
+        };
+        let mut offsets = self.offsets.borrow_mut();
+        while offsets.len() <= index {
+            let next = source.skip(*offsets.last().expect("first offset is known"))?;
+            offsets.push(next);
+        }
+        let offset = offsets[index];
+        drop(offsets);
+        source.decode(offset).map(Some)
+    }
+
+    /// Iterator decoding the elements in order.
+    pub fn iter(&self) -> impl Iterator<Item = Result<EncodedValue>> + '_ {
+        (0..self.len).map(move |index| {
+            self.get(index)
+                .and_then(|value| value.ok_or_else(|| Error::MalFormed("Array is empty".into())))
+        })
+    }
+
+    /// Decodes all the elements.
+    pub fn to_vec(&self) -> Result<Vec<EncodedValue>> {
+        self.iter().collect()
+    }
+
+    /// Number of bytes of the array, from its size to the end of its last element, and a
+    /// hash of these bytes which is stable between runs. The elements are skipped over, not
+    /// decoded.
+    pub(crate) fn byte_len_and_hash(&self) -> Result<(usize, u64)> {
+        let source = match self.source {
+            Some(ref source) => source,
+            None => return Ok((0, 0)),
+        };
+        let mut offsets = self.offsets.borrow_mut();
+        while offsets.len() < self.len {
+            let next = source.skip(*offsets.last().expect("first offset is known"))?;
+            offsets.push(next);
+        }
+        let end = match self.len.checked_sub(1) {
+            Some(last) => source.skip(offsets[last])?,
+            None => offsets[0],
+        };
+        drop(offsets);
+        let mut hasher = crate::utils::StableHasher::new();
+        hasher.write(&source.bytes()[..end]);
+        Ok((end, hasher.finish()))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn known_offsets(&self) -> usize {
+        self.offsets.borrow().len()
     }
 }
 
-impl<'a, S> ctx::TryFromCtx<'a, &super::Dex<S>> for EncodedArray
-where
-    S: AsRef<[u8]>,
-{
-    type Error = Error;
-    type Size = usize;
+impl std::fmt::Debug for EncodedArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncodedArray")
+            .field("len", &self.len)
+            .finish()
+    }
+}
 
-    fn try_from_ctx(source: &'a [u8], ctx: &super::Dex<S>) -> super::Result<(Self, Self::Size)> {
-        let offset = &mut 0;
-        let size = Uleb128::read(source, offset)?;
-        // TODO: find out why try_gread_vec_with! doesn't work here: fails in scroll
-        debug!(target: "encoded-array", "encoded array size: {}", size);
-        let mut values = Vec::with_capacity(size as usize);
-        for _ in 0..size {
-            values.push(source.gread_with(offset, ctx)?);
+/// An encoded array within a bounded slice of a `Dex`.
+pub(crate) struct DexArraySource<S> {
+    pub(crate) dex: super::Dex<S>,
+    pub(crate) bounds: std::ops::Range<usize>,
+    /// The copy of the array, see `DexReaderBuilder::snapshot_on_access`.
+    pub(crate) snapshot: Option<crate::sync::Shared<[u8]>>,
+}
+
+impl<S: DexSource> ArraySource for DexArraySource<S> {
+    fn decode(&self, offset: usize) -> Result<EncodedValue> {
+        crate::bounds::bounded(
+            self.bytes().pread_with(offset, &self.dex),
+            "EncodedArrayItem",
+            self.bounds.clone(),
+        )
+    }
+
+    fn skip(&self, offset: usize) -> Result<usize> {
+        let offset = &mut { offset };
+        crate::bounds::bounded(
+            skip_encoded_value(&self.bytes(), offset),
+            "EncodedArrayItem",
+            self.bounds.clone(),
+        )?;
+        Ok(*offset)
+    }
+
+    /// The bytes of the array: its copy or a slice of the source.
+    fn bytes(&self) -> Cow<'_, [u8]> {
+        match self.snapshot {
+            Some(ref bytes) => Cow::Borrowed(bytes),
+            None => self.dex.source.slice(self.bounds.clone()),
         }
-        Ok((Self { values }, *offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{skip_encoded_value, ArraySource, EncodedArray, EncodedValue};
+    use crate::{Dex, DexReader, Result};
+    use scroll::Pread;
+    use std::borrow::Cow;
+
+    use crate::sync::Shared;
+
+    /// An encoded array held outside of the dex file.
+    struct DetachedArray {
+        dex: Dex<Vec<u8>>,
+        bytes: Vec<u8>,
+    }
+
+    impl ArraySource for DetachedArray {
+        fn decode(&self, offset: usize) -> Result<EncodedValue> {
+            self.bytes.pread_with(offset, &self.dex)
+        }
+
+        fn skip(&self, offset: usize) -> Result<usize> {
+            let offset = &mut { offset };
+            skip_encoded_value(&self.bytes, offset)?;
+            Ok(*offset)
+        }
+
+        fn bytes(&self) -> Cow<'_, [u8]> {
+            Cow::Borrowed(&self.bytes)
+        }
+    }
+
+    #[test]
+    fn test_lazy_encoded_array() {
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dex = DexReader::from_vec(data).expect("cannot open dex");
+        // 10000 ints, each encoded in two bytes: VALUE_INT with value_arg 1
+        let mut bytes = Vec::new();
+        for value in 0..10_000u16 {
+            bytes.push(0x04 | 1 << 5);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let array = EncodedArray::new(10_000, 0, Shared::new(DetachedArray { dex, bytes }));
+        assert_eq!(array.len(), 10_000);
+        assert_eq!(
+            array.get(10).expect("bad value"),
+            Some(EncodedValue::Int(10))
+        );
+        assert_eq!(array.known_offsets(), 11);
+        assert_eq!(array.get(3).expect("bad value"), Some(EncodedValue::Int(3)));
+        assert_eq!(array.known_offsets(), 11);
+        assert_eq!(
+            array.get(9999).expect("bad value"),
+            Some(EncodedValue::Int(9999))
+        );
+        assert_eq!(array.get(10_000).expect("bad value"), None);
+        assert_eq!(array.to_vec().expect("bad values").len(), 10_000);
+    }
+
+    #[test]
+    fn test_skip_encoded_value() {
+        // an array holding null, true, a byte, and an annotation with a single long element
+        let bytes = [
+            0x1c,
+            4,
+            0x1e,
+            0x3f,
+            0x00,
+            7,
+            0x1d,
+            1,
+            1,
+            2,
+            0x06 | 7 << 5,
+            1,
+            2,
+            3,
+            4,
+            5,
+            6,
+            7,
+            8,
+            0xff,
+        ];
+        let offset = &mut 0;
+        skip_encoded_value(&bytes, offset).expect("cannot skip value");
+        assert_eq!(*offset, bytes.len() - 1);
+        assert!(skip_encoded_value(&bytes[..5], &mut 0).is_err());
     }
 }
@@ -4,15 +4,174 @@ use std::{
     io,
 };
 
-use scroll;
+use crate::{dex::ItemType, jtype::TypeId, uint, validate::DescriptorError};
 
+/// Errors of the crate. New variants may be added, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     MalFormed(String),
     IO(io::Error),
     InvalidId(String),
     Scroll(scroll::Error),
     BadOffset(usize, String),
+    /// An item exceeds one of the `ParserLimits`.
+    LimitExceeded(String),
+    /// The code processing a file panicked, see `batch::scan_files`.
+    Panicked(String),
+    /// An item is not at an offset aligned as the format requires, see
+    /// `DexReaderBuilder::strict_alignment`.
+    Misaligned(ItemType, uint),
+    /// The deadline of an operation passed, after processing the first of the class
+    /// definitions, see `OperationOptions::deadline`.
+    DeadlineExceeded(usize, usize),
+    /// A profile is in a format that cannot be read, see `profile::ProfileVersion`.
+    UnsupportedProfile(String),
+    /// The descriptor of a type doesn't match the `TypeDescriptor` grammar, see
+    /// `DexReaderBuilder::strict_descriptors`.
+    InvalidDescriptor(TypeId, String, DescriptorError),
+    /// The class of the class definition at the index can't be loaded, because of the
+    /// error, see `Dex::classes`.
+    ClassDef(uint, Box<Error>),
+}
+
+/// The kinds of `Error`, each with a numeric code for use across FFI boundaries and in logs.
+///
+/// Codes are never reused or renumbered once assigned. They are grouped in ranges by the
+/// category of the problem, and a range is only declared along with its first kind.
+/// Errors specific to annotations will take 300-399, and errors specific to code items
+/// 400-499.
+///
+/// | Range | Category |
+/// |-------|----------|
+/// | 100-199 | reading the file, its header and its sections |
+/// | 200-299 | string, type, proto, field and method pools |
+/// | 500-599 | parser limits, deadlines and aborted processing |
+/// | 600-699 | profiles |
+///
+/// Assigned codes:
+///
+/// | Code | Kind |
+/// |------|------|
+/// | 100 | `Io` |
+/// | 101 | `Scroll` |
+/// | 102 | `MalFormed` |
+/// | 103 | `BadOffset` |
+/// | 104 | `Misaligned` |
+/// | 200 | `InvalidDescriptor` |
+/// | 201 | `InvalidId` |
+/// | 500 | `LimitExceeded` |
+/// | 501 | `DeadlineExceeded` |
+/// | 502 | `Panicked` |
+/// | 600 | `UnsupportedProfile` |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The file could not be read.
+    Io,
+    /// An item could not be read from the bytes at its offset.
+    Scroll,
+    /// An item is malformed.
+    MalFormed,
+    /// An id is out of the bounds of its pool.
+    InvalidId,
+    /// An offset points outside of the section it should be in.
+    BadOffset,
+    /// The code processing a file panicked.
+    Panicked,
+    /// An item is not aligned as the format requires.
+    Misaligned,
+    /// The deadline of an operation passed.
+    DeadlineExceeded,
+    /// The descriptor of a type is invalid.
+    InvalidDescriptor,
+    /// An item exceeds one of the `ParserLimits`.
+    LimitExceeded,
+    /// A profile is in a format that cannot be read.
+    UnsupportedProfile,
+}
+
+impl ErrorKind {
+    /// All the error kinds, in the order of their codes.
+    pub const ALL: [ErrorKind; 11] = [
+        ErrorKind::Io,
+        ErrorKind::Scroll,
+        ErrorKind::MalFormed,
+        ErrorKind::BadOffset,
+        ErrorKind::Misaligned,
+        ErrorKind::InvalidDescriptor,
+        ErrorKind::InvalidId,
+        ErrorKind::LimitExceeded,
+        ErrorKind::DeadlineExceeded,
+        ErrorKind::Panicked,
+        ErrorKind::UnsupportedProfile,
+    ];
+
+    /// The stable numeric code of the kind.
+    pub fn code(self) -> u32 {
+        match self {
+            ErrorKind::Io => 100,
+            ErrorKind::Scroll => 101,
+            ErrorKind::MalFormed => 102,
+            ErrorKind::BadOffset => 103,
+            ErrorKind::Misaligned => 104,
+            ErrorKind::InvalidDescriptor => 200,
+            ErrorKind::InvalidId => 201,
+            ErrorKind::LimitExceeded => 500,
+            ErrorKind::DeadlineExceeded => 501,
+            ErrorKind::Panicked => 502,
+            ErrorKind::UnsupportedProfile => 600,
+        }
+    }
+
+    /// Returns the kind with the code, if any.
+    pub fn from_code(code: u32) -> Option<ErrorKind> {
+        Self::ALL.iter().cloned().find(|kind| kind.code() == code)
+    }
+}
+
+impl Error {
+    /// The kind of the error, the kind of the underlying error for `Error::ClassDef`.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::IO(_) => ErrorKind::Io,
+            Error::Scroll(_) => ErrorKind::Scroll,
+            Error::MalFormed(_) => ErrorKind::MalFormed,
+            Error::InvalidId(_) => ErrorKind::InvalidId,
+            Error::BadOffset(_, _) => ErrorKind::BadOffset,
+            Error::LimitExceeded(_) => ErrorKind::LimitExceeded,
+            Error::Panicked(_) => ErrorKind::Panicked,
+            Error::Misaligned(_, _) => ErrorKind::Misaligned,
+            Error::DeadlineExceeded(_, _) => ErrorKind::DeadlineExceeded,
+            Error::UnsupportedProfile(_) => ErrorKind::UnsupportedProfile,
+            Error::InvalidDescriptor(_, _, _) => ErrorKind::InvalidDescriptor,
+            Error::ClassDef(_, ref error) => error.kind(),
+        }
+    }
+
+    /// The stable numeric code of the kind of the error, see `ErrorKind`.
+    pub fn code(&self) -> u32 {
+        self.kind().code()
+    }
+
+    /// Returns `true` if the error only affects the item being read, so the rest of the
+    /// file can still be read. I/O errors, panics, deadlines and unsupported profiles
+    /// affect the whole file.
+    pub fn is_recoverable(&self) -> bool {
+        match self.kind() {
+            ErrorKind::Io
+            | ErrorKind::Panicked
+            | ErrorKind::DeadlineExceeded
+            | ErrorKind::UnsupportedProfile => false,
+            ErrorKind::Scroll
+            | ErrorKind::MalFormed
+            | ErrorKind::InvalidId
+            | ErrorKind::BadOffset
+            | ErrorKind::Misaligned
+            | ErrorKind::InvalidDescriptor
+            | ErrorKind::LimitExceeded => true,
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -23,6 +182,13 @@ impl error::Error for Error {
             Error::Scroll(_) => "Scroll error",
             Error::InvalidId(_) => "Invalid index",
             Error::BadOffset(_, _) => "Invalid offset",
+            Error::LimitExceeded(_) => "Parser limit exceeded",
+            Error::Panicked(_) => "Panicked",
+            Error::Misaligned(_, _) => "Misaligned item",
+            Error::DeadlineExceeded(_, _) => "Deadline exceeded",
+            Error::UnsupportedProfile(_) => "Unsupported profile",
+            Error::InvalidDescriptor(_, _, _) => "Invalid type descriptor",
+            Error::ClassDef(_, _) => "Class cannot be loaded",
         }
     }
 
@@ -33,6 +199,13 @@ impl error::Error for Error {
             Error::MalFormed(_) => None,
             Error::InvalidId(_) => None,
             Error::BadOffset(_, _) => None,
+            Error::LimitExceeded(_) => None,
+            Error::Panicked(_) => None,
+            Error::Misaligned(_, _) => None,
+            Error::DeadlineExceeded(_, _) => None,
+            Error::UnsupportedProfile(_) => None,
+            Error::InvalidDescriptor(_, _, _) => None,
+            Error::ClassDef(_, ref error) => Some(error.as_ref()),
         }
     }
 }
@@ -43,6 +216,16 @@ impl From<io::Error> for Error {
     }
 }
 
+#[cfg(feature = "container-zip")]
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Error {
+        match err {
+            zip::result::ZipError::Io(err) => Error::IO(err),
+            err => Error::MalFormed(format!("Bad APK: {}", err)),
+        }
+    }
+}
+
 impl From<scroll::Error> for Error {
     fn from(err: scroll::Error) -> Error {
         Error::Scroll(err)
@@ -57,6 +240,113 @@ impl Display for Error {
             Error::MalFormed(ref msg) => write!(fmt, "Malformed entity: {}", msg),
             Error::InvalidId(ref msg) => write!(fmt, "{}", msg),
             Error::BadOffset(offset, ref msg) => write!(fmt, "{}: {}", msg, offset),
+            Error::LimitExceeded(ref msg) => write!(fmt, "Limit exceeded: {}", msg),
+            Error::Panicked(ref msg) => write!(fmt, "Panicked: {}", msg),
+            Error::Misaligned(item_type, offset) => write!(
+                fmt,
+                "{:?} at offset {} is not {} byte aligned",
+                item_type,
+                offset,
+                item_type.alignment()
+            ),
+            Error::DeadlineExceeded(processed, total) => write!(
+                fmt,
+                "Deadline exceeded after {} of {} class definitions",
+                processed, total
+            ),
+            Error::UnsupportedProfile(ref msg) => write!(fmt, "Unsupported profile: {}", msg),
+            Error::InvalidDescriptor(type_id, ref descriptor, error) => write!(
+                fmt,
+                "Invalid descriptor {:?} of type {}: {}",
+                descriptor, type_id, error
+            ),
+            Error::ClassDef(index, ref error) => {
+                write!(fmt, "Cannot load class definition {}: {}", index, error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorKind};
+    use std::collections::HashSet;
+
+    /// Fails to compile when a kind is added, as a reminder to add it to `ErrorKind::ALL`
+    /// and the registry in its documentation.
+    fn position(kind: ErrorKind) -> usize {
+        match kind {
+            ErrorKind::Io => 0,
+            ErrorKind::Scroll => 1,
+            ErrorKind::MalFormed => 2,
+            ErrorKind::BadOffset => 3,
+            ErrorKind::Misaligned => 4,
+            ErrorKind::InvalidDescriptor => 5,
+            ErrorKind::InvalidId => 6,
+            ErrorKind::LimitExceeded => 7,
+            ErrorKind::DeadlineExceeded => 8,
+            ErrorKind::Panicked => 9,
+            ErrorKind::UnsupportedProfile => 10,
         }
     }
+
+    #[test]
+    fn test_error_codes() {
+        let registry = include_str!("error.rs");
+        let mut codes = HashSet::new();
+        for (index, kind) in ErrorKind::ALL.iter().enumerate() {
+            assert_eq!(position(*kind), index);
+            assert!(codes.insert(kind.code()), "duplicate code {}", kind.code());
+            assert_eq!(ErrorKind::from_code(kind.code()), Some(*kind));
+            let row = format!("/// | {} | `{:?}` |", kind.code(), kind);
+            assert!(
+                registry.contains(&row),
+                "{:?} missing from the registry",
+                kind
+            );
+        }
+        assert_eq!(ErrorKind::from_code(0), None);
+
+        // each code is in a declared range, and each range has a code
+        let ranges: Vec<(u32, u32)> = registry
+            .lines()
+            .filter_map(|line| line.strip_prefix("/// | ")?.split(" | ").next())
+            .filter_map(|range| {
+                let (start, end) = range.split_once('-')?;
+                Some((start.parse().ok()?, end.parse().ok()?))
+            })
+            .collect();
+        assert_eq!(ranges.len(), 4);
+        for kind in ErrorKind::ALL.iter() {
+            let code = kind.code();
+            assert!(
+                ranges
+                    .iter()
+                    .any(|&(start, end)| (start..=end).contains(&code)),
+                "{:?} outside of the ranges",
+                kind
+            );
+        }
+        for (start, end) in ranges {
+            assert!(
+                ErrorKind::ALL
+                    .iter()
+                    .any(|kind| (start..=end).contains(&kind.code())),
+                "unused range {}-{}",
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let error = Error::BadOffset(1, "offset".to_string());
+        assert_eq!(error.kind(), ErrorKind::BadOffset);
+        assert_eq!(error.code(), 103);
+        assert!(error.is_recoverable());
+        let error = Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(error.code(), 100);
+        assert!(!error.is_recoverable());
+    }
 }
@@ -0,0 +1,1009 @@
+//! Line oriented CSV and JSON export of the contents of a `Dex`, and comparison of the
+//! contents of two files, see `DexDiff`.
+//!
+//! Records are emitted in a deterministic order so that the output of two exports of the
+//! same file can be compared byte by byte:
+//! * `class` records sorted by type descriptor, each followed by its
+//! * `field` records sorted by name and type and its
+//! * `method` records sorted by name and prototype.
+//! * `string` records after all the classes, sorted by `StringId`, or by value in canonical
+//!   mode.
+//!
+//! The JSON export writes each record as an object on a line of its own, with the kind of
+//! the record under `record` followed by the columns of the CSV export, in the same order.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    class::{Class, ClassDefItem},
+    encoded_value::EncodedValue,
+    error::Error,
+    field::{Field, FieldIdItem},
+    jtype::TypeId,
+    method::{FieldOrMethodId, Method, MethodHandleItem, MethodHandleType, MethodRef, ProtoIdItem},
+    source::DexSource,
+    Dex, Result,
+};
+
+/// Options controlling the output of the exporters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportOptions {
+    /// Produces output that depends only on the contents of the classes: floating point
+    /// values are written in their round-trip form, and the offsets of items and their
+    /// indexes in the pools are omitted, as they change whenever an unrelated part of the
+    /// file changes. Strings are sorted by value and `raw_indices` is ignored.
+    pub canonical: bool,
+    /// Appends `#` and the index in their pool to the values of fields which refer to
+    /// strings, types, fields, methods, prototypes or method handles, for tools which track
+    /// the order of the pools.
+    pub raw_indices: bool,
+    /// Loads the classes on the threads of the current rayon pool, with the `rayon` feature,
+    /// see `Dex::par_classes`. The output is the same. Ignored without the feature.
+    pub parallel: bool,
+}
+
+/// Writes the classes, fields, methods and strings of `dex` as CSV records.
+pub fn write_csv<T, W>(dex: &Dex<T>, writer: &mut W, options: ExportOptions) -> Result<()>
+where
+    T: DexSource + 'static,
+    W: Write,
+{
+    for record in Records::new(dex, options)?.iter() {
+        let mut fields = vec![record.kind.to_string()];
+        fields.extend(record.values.iter().map(|(_, value)| value.to_csv()));
+        write_record(writer, &fields)?;
+    }
+    Ok(())
+}
+
+/// Writes the classes, fields, methods and strings of `dex` as JSON objects, one per line.
+pub fn write_json<T, W>(dex: &Dex<T>, writer: &mut W, options: ExportOptions) -> Result<()>
+where
+    T: DexSource + 'static,
+    W: Write,
+{
+    for record in Records::new(dex, options)?.iter() {
+        let mut values = vec![("record", Value::Text(Some(record.kind.to_string())))];
+        values.extend(record.values.iter().cloned());
+        write_json_object(writer, &values)?;
+    }
+    Ok(())
+}
+
+/// A column of an exported record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    /// Empty in CSV and `null` in JSON when missing.
+    Text(Option<String>),
+    /// Empty in CSV and `null` in JSON when missing.
+    Number(Option<u64>),
+    /// Access flags, in hexadecimal in CSV.
+    Flags(u64),
+    Bool(bool),
+}
+
+impl Value {
+    fn to_csv(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone().unwrap_or_default(),
+            Value::Number(number) => number.map(|n| n.to_string()).unwrap_or_default(),
+            Value::Flags(flags) => format!("{:#x}", flags),
+            Value::Bool(value) => value.to_string(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Value::Text(Some(text)) => json_string(text),
+            Value::Number(Some(number)) => number.to_string(),
+            Value::Flags(flags) => flags.to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Text(None) | Value::Number(None) => "null".to_string(),
+        }
+    }
+}
+
+/// A line of the export: its kind and its named columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    kind: &'static str,
+    values: Vec<(&'static str, Value)>,
+}
+
+/// The records of a class, with its fields and methods identified by signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClassRecords {
+    descriptor: String,
+    class: Record,
+    fields: Vec<(String, Record)>,
+    methods: Vec<(String, Record)>,
+    /// Hash of the bytes of the static values, `None` without static values. Only compared,
+    /// not exported.
+    static_values: Option<u64>,
+}
+
+/// The records of a file, in the order of the module documentation.
+struct Records {
+    classes: Vec<ClassRecords>,
+    /// The strings with their record.
+    strings: Vec<(String, Record)>,
+}
+
+impl Records {
+    fn new<T: DexSource + 'static>(dex: &Dex<T>, options: ExportOptions) -> Result<Self> {
+        let class_defs = dex.class_defs().collect::<Result<Vec<_>>>()?;
+        let mut classes = load_classes(dex, &class_defs, options)?
+            .iter()
+            .zip(&class_defs)
+            .map(|(class, class_def)| class_records(dex, options, class, class_def))
+            .collect::<Result<Vec<_>>>()?;
+        classes.sort_by(|a, b| a.descriptor.cmp(&b.descriptor));
+
+        let mut strings = dex
+            .strings()
+            .map(|string| {
+                let (id, string) = string?;
+                let string = string.to_string();
+                let mut values = Vec::new();
+                if !options.canonical {
+                    values.push(("id", Value::Number(Some(id.into()))));
+                }
+                values.push(("value", Value::Text(Some(string.clone()))));
+                Ok((
+                    string,
+                    Record {
+                        kind: "string",
+                        values,
+                    },
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if options.canonical {
+            strings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+        Ok(Self { classes, strings })
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Record> {
+        self.classes
+            .iter()
+            .flat_map(|class| {
+                std::iter::once(&class.class)
+                    .chain(class.fields.iter().map(|(_, record)| record))
+                    .chain(class.methods.iter().map(|(_, record)| record))
+            })
+            .chain(self.strings.iter().map(|(_, record)| record))
+    }
+}
+
+#[cfg(feature = "rayon")]
+fn load_classes<T: DexSource + 'static>(
+    dex: &Dex<T>,
+    class_defs: &[ClassDefItem],
+    options: ExportOptions,
+) -> Result<Vec<Class>> {
+    use rayon::prelude::*;
+
+    if options.parallel {
+        dex.par_classes().collect()
+    } else {
+        class_defs
+            .iter()
+            .map(|class_def| Class::try_from_dex(dex, class_def))
+            .collect()
+    }
+}
+
+#[cfg(not(feature = "rayon"))]
+fn load_classes<T: DexSource + 'static>(
+    dex: &Dex<T>,
+    class_defs: &[ClassDefItem],
+    _: ExportOptions,
+) -> Result<Vec<Class>> {
+    class_defs
+        .iter()
+        .map(|class_def| Class::try_from_dex(dex, class_def))
+        .collect()
+}
+
+fn class_records<T: DexSource + 'static>(
+    dex: &Dex<T>,
+    options: ExportOptions,
+    class: &Class,
+    class_def: &ClassDefItem,
+) -> Result<ClassRecords> {
+    let descriptor = class.jtype().to_string();
+    let mut record = Record {
+        kind: "class",
+        values: vec![
+            ("descriptor", Value::Text(Some(descriptor.clone()))),
+            (
+                "access_flags",
+                Value::Flags(class.access_flags().bits().into()),
+            ),
+            (
+                "superclass",
+                Value::Text(class.superclass().map(|t| t.to_string())),
+            ),
+            (
+                "source_file",
+                Value::Text(class.source_file().map(|f| f.to_string())),
+            ),
+        ],
+    };
+    if !options.canonical {
+        record.values.extend(
+            [
+                ("id", class.id()),
+                ("annotations_off", class_def.annotations_off),
+                ("class_data_off", class_def.class_data_off),
+                ("static_values_off", class_def.static_values_off),
+            ]
+            .iter()
+            .map(|&(name, value)| (name, Value::Number(Some(value.into())))),
+        );
+    }
+
+    let mut fields: Vec<&Field> = class.fields().collect();
+    fields.sort_by(|a, b| {
+        (&**a.name(), a.jtype().type_descriptor()).cmp(&(&**b.name(), b.jtype().type_descriptor()))
+    });
+    let fields = fields
+        .into_iter()
+        .map(|field| {
+            let mut record = Record {
+                kind: "field",
+                values: vec![
+                    ("class", Value::Text(Some(descriptor.clone()))),
+                    ("name", Value::Text(Some(field.name().to_string()))),
+                    ("type", Value::Text(Some(field.jtype().to_string()))),
+                    ("access_flags", Value::Flags(field.access_flags().bits())),
+                    (
+                        "initial_value",
+                        Value::Text(
+                            field
+                                .initial_value()
+                                .map(|value| format_value(dex, value, options))
+                                .transpose()?,
+                        ),
+                    ),
+                ],
+            };
+            if !options.canonical {
+                record.values.push(("id", Value::Number(Some(field.id()))));
+            }
+            Ok((format!("{}:{}", field.name(), field.jtype()), record))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut methods: Vec<(&Method, String)> = class.methods().map(|m| (m, proto(m))).collect();
+    methods
+        .sort_by(|(a, a_proto), (b, b_proto)| (&**a.name(), a_proto).cmp(&(&**b.name(), b_proto)));
+    let methods = methods
+        .into_iter()
+        .map(|(method, proto)| {
+            let signature = format!("{}{}", method.name(), proto);
+            let mut record = Record {
+                kind: "method",
+                values: vec![
+                    ("class", Value::Text(Some(descriptor.clone()))),
+                    ("name", Value::Text(Some(method.name().to_string()))),
+                    ("proto", Value::Text(Some(proto))),
+                    ("access_flags", Value::Flags(method.access_flags().bits())),
+                    (
+                        "insns_len",
+                        Value::Number(method.code().map(|code| code.insns.len() as u64)),
+                    ),
+                ],
+            };
+            if !options.canonical {
+                record.values.push(("id", Value::Number(Some(method.id()))));
+            }
+            (signature, record)
+        })
+        .collect();
+
+    Ok(ClassRecords {
+        descriptor,
+        class: record,
+        fields,
+        methods,
+        static_values: static_values_hash(dex, class, class_def)?,
+    })
+}
+
+/// The `StaticValuesSpan::bytes_hash` of the static values of the class, computed if the
+/// `Dex` doesn't track spans.
+fn static_values_hash<T: DexSource + 'static>(
+    dex: &Dex<T>,
+    class: &Class,
+    class_def: &ClassDefItem,
+) -> Result<Option<u64>> {
+    if class_def.static_values_off == 0 {
+        return Ok(None);
+    }
+    let hash = match class.static_values_span() {
+        Some(span) => span.bytes_hash(),
+        None => {
+            dex.get_static_values(class_def.static_values_off)?
+                .byte_len_and_hash()?
+                .1
+        }
+    };
+    Ok(Some(hash))
+}
+
+/// The differences between the contents of two files, comparing their canonical exports,
+/// see `ExportOptions::canonical`: classes are matched by descriptor, fields by name and
+/// type, methods by name and prototype, and strings by value. Everything is sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters)]
+#[get = "pub"]
+pub struct DexDiff {
+    /// Descriptors of the classes only in the new file.
+    added_classes: Vec<String>,
+    /// Descriptors of the classes only in the old file.
+    removed_classes: Vec<String>,
+    /// The classes in both files whose contents differ.
+    changed_classes: Vec<ClassDiff>,
+    /// Strings only in the new file.
+    added_strings: Vec<String>,
+    /// Strings only in the old file.
+    removed_strings: Vec<String>,
+}
+
+/// The differences between the two versions of a class, see `DexDiff`. Fields are
+/// identified as `name:type` and methods as `name(params)return`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ClassDiff {
+    /// Descriptor of the class.
+    #[get = "pub"]
+    descriptor: String,
+    /// Whether the access flags, superclass or source file differ.
+    #[get_copy = "pub"]
+    header_changed: bool,
+    /// The `StaticValuesSpan::bytes_hash` of the static values of the old and the new
+    /// version, `None` for a version without static values, if they differ. The bytes
+    /// refer to strings, types and members by index, so the hashes also differ when these
+    /// were renumbered.
+    #[get_copy = "pub"]
+    static_values: Option<(Option<u64>, Option<u64>)>,
+    /// Fields only in the new version.
+    #[get = "pub"]
+    added_fields: Vec<String>,
+    /// Fields only in the old version.
+    #[get = "pub"]
+    removed_fields: Vec<String>,
+    /// Fields in both versions whose access flags or initial value differ.
+    #[get = "pub"]
+    changed_fields: Vec<String>,
+    /// Methods only in the new version.
+    #[get = "pub"]
+    added_methods: Vec<String>,
+    /// Methods only in the old version.
+    #[get = "pub"]
+    removed_methods: Vec<String>,
+    /// Methods in both versions whose access flags or code length differ.
+    #[get = "pub"]
+    changed_methods: Vec<String>,
+}
+
+impl DexDiff {
+    /// Compares the classes and strings of `old` and `new`.
+    pub fn new<T, U>(old: &Dex<T>, new: &Dex<U>) -> Result<Self>
+    where
+        T: DexSource + 'static,
+        U: DexSource + 'static,
+    {
+        let options = ExportOptions {
+            canonical: true,
+            ..Default::default()
+        };
+        let old = Records::new(old, options)?;
+        let new = Records::new(new, options)?;
+
+        let mut diff = Self::default();
+        let old_classes: BTreeMap<&str, &ClassRecords> = old
+            .classes
+            .iter()
+            .map(|class| (&*class.descriptor, class))
+            .collect();
+        for class in &new.classes {
+            match old_classes.get(&*class.descriptor) {
+                None => diff.added_classes.push(class.descriptor.clone()),
+                Some(old_class) if *old_class != class => {
+                    diff.changed_classes.push(ClassDiff::new(old_class, class))
+                }
+                Some(_) => {}
+            }
+        }
+        (diff.added_classes, diff.removed_classes) = added_and_removed(
+            old.classes.iter().map(|class| &class.descriptor),
+            new.classes.iter().map(|class| &class.descriptor),
+        );
+        (diff.added_strings, diff.removed_strings) = added_and_removed(
+            old.strings.iter().map(|(string, _)| string),
+            new.strings.iter().map(|(string, _)| string),
+        );
+        Ok(diff)
+    }
+
+    /// Whether the two files have the same contents.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Writes the differences as JSON objects, one per line, in the order of the getters:
+    /// each holds the kind of `change`, for example `added_class` or `changed_method`, the
+    /// `class` it concerns and, for fields and methods, the `member`. Strings have their
+    /// `value` instead. A `changed_class` also tells whether the `header_changed` and the
+    /// `static_values_changed`.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let text = |text: &str| Value::Text(Some(text.to_string()));
+        for (change, classes) in [
+            ("added_class", &self.added_classes),
+            ("removed_class", &self.removed_classes),
+        ] {
+            for class in classes {
+                write_json_object(writer, &[("change", text(change)), ("class", text(class))])?;
+            }
+        }
+        for class in &self.changed_classes {
+            write_json_object(
+                writer,
+                &[
+                    ("change", text("changed_class")),
+                    ("class", text(&class.descriptor)),
+                    ("header_changed", Value::Bool(class.header_changed)),
+                    (
+                        "static_values_changed",
+                        Value::Bool(class.static_values.is_some()),
+                    ),
+                ],
+            )?;
+            for (change, members) in [
+                ("added_field", &class.added_fields),
+                ("removed_field", &class.removed_fields),
+                ("changed_field", &class.changed_fields),
+                ("added_method", &class.added_methods),
+                ("removed_method", &class.removed_methods),
+                ("changed_method", &class.changed_methods),
+            ] {
+                for member in members {
+                    write_json_object(
+                        writer,
+                        &[
+                            ("change", text(change)),
+                            ("class", text(&class.descriptor)),
+                            ("member", text(member)),
+                        ],
+                    )?;
+                }
+            }
+        }
+        for (change, strings) in [
+            ("added_string", &self.added_strings),
+            ("removed_string", &self.removed_strings),
+        ] {
+            for string in strings {
+                write_json_object(writer, &[("change", text(change)), ("value", text(string))])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ClassDiff {
+    fn new(old: &ClassRecords, new: &ClassRecords) -> Self {
+        let mut diff = Self {
+            descriptor: new.descriptor.clone(),
+            header_changed: old.class != new.class,
+            static_values: Some((old.static_values, new.static_values))
+                .filter(|(old, new)| old != new),
+            ..Default::default()
+        };
+        (diff.added_fields, diff.removed_fields, diff.changed_fields) =
+            members_diff(&old.fields, &new.fields);
+        (
+            diff.added_methods,
+            diff.removed_methods,
+            diff.changed_methods,
+        ) = members_diff(&old.methods, &new.methods);
+        diff
+    }
+}
+
+/// The added, removed and changed members, by signature.
+fn members_diff(
+    old: &[(String, Record)],
+    new: &[(String, Record)],
+) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let old_members: BTreeMap<&String, &Record> = old
+        .iter()
+        .map(|(signature, record)| (signature, record))
+        .collect();
+    let changed = new
+        .iter()
+        .filter(|(signature, record)| old_members.get(signature).is_some_and(|old| *old != record))
+        .map(|(signature, _)| signature.clone())
+        .collect();
+    let (added, removed) = added_and_removed(
+        old.iter().map(|(signature, _)| signature),
+        new.iter().map(|(signature, _)| signature),
+    );
+    (added, removed, changed)
+}
+
+/// The items only in `new` and the items only in `old`, sorted.
+fn added_and_removed<'a, I, J>(old: I, new: J) -> (Vec<String>, Vec<String>)
+where
+    I: IntoIterator<Item = &'a String>,
+    J: IntoIterator<Item = &'a String>,
+{
+    let old: BTreeSet<&String> = old.into_iter().collect();
+    let new: BTreeSet<&String> = new.into_iter().collect();
+    (
+        new.difference(&old).map(|item| item.to_string()).collect(),
+        old.difference(&new).map(|item| item.to_string()).collect(),
+    )
+}
+
+/// Prototype descriptor of the method, for example `(Ljava/lang/String;I)V`.
+fn proto(method: &Method) -> String {
+    let params: String = method
+        .params()
+        .iter()
+        .map(|param| &**param.type_descriptor())
+        .collect();
+    format!("({}){}", params, method.return_type())
+}
+
+fn format_value<T: DexSource>(
+    dex: &Dex<T>,
+    value: &EncodedValue,
+    options: ExportOptions,
+) -> Result<String> {
+    let formatted = format_resolved_value(dex, value, options)?;
+    Ok(match value.raw_index() {
+        Some(idx) if options.raw_indices && !options.canonical => format!("{}#{}", formatted, idx),
+        _ => formatted,
+    })
+}
+
+fn format_resolved_value<T: DexSource>(
+    dex: &Dex<T>,
+    value: &EncodedValue,
+    options: ExportOptions,
+) -> Result<String> {
+    Ok(match value {
+        EncodedValue::Byte(v) => v.to_string(),
+        EncodedValue::Short(v) => v.to_string(),
+        EncodedValue::Char(v) => v.to_string(),
+        EncodedValue::Int(v) => v.to_string(),
+        EncodedValue::Long(v) => v.to_string(),
+        EncodedValue::Float(v) if options.canonical => format_float(*v, v.is_nan()),
+        EncodedValue::Double(v) if options.canonical => format_float(*v, v.is_nan()),
+        EncodedValue::Float(v) => v.to_string(),
+        EncodedValue::Double(v) => v.to_string(),
+        EncodedValue::Boolean(v) => v.to_string(),
+        EncodedValue::Null => "null".to_string(),
+        EncodedValue::String { value, .. } => format!("{:?}", &**value),
+        EncodedValue::Type(t) => t.to_string(),
+        EncodedValue::Field(f) | EncodedValue::Enum(f) => format_field(dex, f)?,
+        EncodedValue::Method(m) => MethodRef::try_from_dex(dex, m)?.to_string(),
+        EncodedValue::Annotation(a) => format!("@{}", a.jtype()),
+        EncodedValue::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(|v| format_value(dex, v, options))
+                .collect::<Result<Vec<_>>>()?
+                .join(", ")
+        ),
+        EncodedValue::MethodType { value, .. } => format_proto(dex, value)?,
+        EncodedValue::MethodHandle { value, .. } => format_method_handle(dex, value)?,
+    })
+}
+
+/// The field as `Lcom/example/Foo;->bar:I`.
+fn format_field<T: DexSource>(dex: &Dex<T>, field: &FieldIdItem) -> Result<String> {
+    Ok(format!(
+        "{}->{}:{}",
+        dex.get_type(TypeId::from(field.class_idx()))?,
+        dex.get_string(field.name_idx())?,
+        dex.get_type(TypeId::from(field.type_idx()))?
+    ))
+}
+
+/// The prototype as its descriptor, for example `(Ljava/lang/String;I)V`.
+fn format_proto<T: DexSource>(dex: &Dex<T>, proto: &ProtoIdItem) -> Result<String> {
+    let params: String = dex
+        .get_proto_params(proto)?
+        .iter()
+        .map(|param| param.to_string())
+        .collect();
+    Ok(format!(
+        "({}){}",
+        params,
+        dex.get_type(proto.return_type())?
+    ))
+}
+
+/// The method handle as its kind followed by its field or method, for example
+/// `invoke-static Lcom/example/Foo;->bar(I)V`.
+fn format_method_handle<T: DexSource>(dex: &Dex<T>, handle: &MethodHandleItem) -> Result<String> {
+    let kind = match handle.handle_type() {
+        MethodHandleType::StaticPut => "static-put",
+        MethodHandleType::StaticGet => "static-get",
+        MethodHandleType::InstancePut => "instance-put",
+        MethodHandleType::InstanceGet => "instance-get",
+        MethodHandleType::InvokeStatic => "invoke-static",
+        MethodHandleType::InvokeInstance => "invoke-instance",
+        MethodHandleType::InvokeConstructor => "invoke-constructor",
+        MethodHandleType::InvokeDirect => "invoke-direct",
+        MethodHandleType::InvokeInterface => "invoke-interface",
+    };
+    let member = match handle.id() {
+        FieldOrMethodId::Field(id) => format_field(dex, &dex.get_field_item(id)?)?,
+        FieldOrMethodId::Method(id) => dex.get_method_ref(id)?.to_string(),
+    };
+    Ok(format!("{} {}", kind, member))
+}
+
+/// Formats floating point values in their shortest round-trip form, with a single
+/// representation for all NaNs.
+fn format_float<F: std::fmt::Debug>(value: F, is_nan: bool) -> String {
+    if is_nan {
+        "NaN".to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Writes the fields as a single CSV line, quoting fields where needed.
+fn write_record<W: Write, S: AsRef<str>>(writer: &mut W, fields: &[S]) -> Result<()> {
+    let line = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(writer, "{}", line).map_err(Error::from)
+}
+
+/// Writes the values as a JSON object on a single line, with the keys in their order.
+fn write_json_object<W: Write>(writer: &mut W, values: &[(&str, Value)]) -> Result<()> {
+    let members = values
+        .iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), value.to_json()))
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(writer, "{{{}}}", members).map_err(Error::from)
+}
+
+/// The string as a JSON string literal.
+fn json_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_string, write_csv, write_json, DexDiff, ExportOptions};
+    use crate::{
+        test_utils::mutated_example_dex, CacheConfig, Dex, DexReader, DexReaderBuilder, Result,
+    };
+
+    /// Exports the example file with `export` from `Dex`es read independently: with the
+    /// default caches, with the string cache warmed in a different order than the export
+    /// visits it, and with caches too small to hold anything, loading the classes
+    /// sequentially and in parallel.
+    fn exports<F>(options: ExportOptions, export: F) -> Vec<Vec<u8>>
+    where
+        F: Fn(&Dex<Vec<u8>>, &mut Vec<u8>, ExportOptions) -> Result<()>,
+    {
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let cold = DexReader::from_vec(data.clone()).expect("cannot open dex");
+        let warmed = DexReader::from_vec(data.clone()).expect("cannot open dex");
+        for id in (0..warmed.header().string_ids_size()).rev() {
+            warmed.get_string(id).expect("bad string");
+        }
+        let uncached = DexReaderBuilder::new()
+            .cache_config(
+                CacheConfig::new()
+                    .strings(1)
+                    .annotations_directories(1)
+                    .max_bytes(Some(0)),
+            )
+            .read_vec(data)
+            .expect("cannot open dex");
+        let mut outputs = Vec::new();
+        for dex in [&cold, &warmed, &uncached] {
+            for parallel in [false, true] {
+                let mut output = Vec::new();
+                let options = ExportOptions {
+                    parallel,
+                    ..options
+                };
+                export(dex, &mut output, options).expect("export failed");
+                outputs.push(output);
+            }
+        }
+        outputs
+    }
+
+    fn export(canonical: bool) -> String {
+        let options = ExportOptions {
+            canonical,
+            ..Default::default()
+        };
+        let output = exports(options, write_csv).remove(0);
+        String::from_utf8(output).expect("export is not utf-8")
+    }
+
+    #[test]
+    fn test_export_is_deterministic() {
+        for canonical in [false, true] {
+            let options = ExportOptions {
+                canonical,
+                ..Default::default()
+            };
+            for outputs in [exports(options, write_csv), exports(options, write_json)] {
+                assert!(!outputs[0].is_empty());
+                assert!(outputs.iter().all(|output| output == &outputs[0]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical_export_order() {
+        let output = export(true);
+        let classes: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("class,"))
+            .map(|line| line.split(',').nth(1).unwrap())
+            .collect();
+        let mut sorted = classes.clone();
+        sorted.sort_unstable();
+        assert_eq!(classes.len(), 323);
+        assert_eq!(classes, sorted);
+        // canonical records carry no offsets nor ids
+        assert!(output
+            .lines()
+            .filter(|line| line.starts_with("class,"))
+            .all(|line| line.split(',').count() == 5));
+        assert!(output
+            .lines()
+            .filter(|line| line.starts_with("method,"))
+            .all(|line| line.split(',').count() == 6));
+        assert_eq!(
+            output
+                .lines()
+                .filter(|line| line.starts_with("string,"))
+                .count(),
+            6509
+        );
+
+        let output = export(false);
+        assert!(output.lines().last().unwrap().starts_with("string,6508,"));
+    }
+
+    #[test]
+    fn test_export_json() {
+        let options = ExportOptions {
+            canonical: true,
+            raw_indices: true,
+            ..Default::default()
+        };
+        let output = exports(options, write_json).remove(0);
+        let output = String::from_utf8(output).expect("export is not utf-8");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "{\"record\":\"class\",\"descriptor\":\"Lcom/devoteam/quickaction/QuickActionItem;\",\
+             \"access_flags\":1,\"superclass\":\"Landroid/widget/LinearLayout;\",\
+             \"source_file\":\"QuickActionItem.java\"}"
+        );
+        assert!(lines.iter().all(|line| !line.contains("\"id\":")));
+        // strings are sorted by value in canonical mode
+        let strings: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("{\"record\":\"string\",\"value\":"))
+            .collect();
+        assert_eq!(strings.len(), 6509);
+        assert_eq!(strings[0], "\"\"}");
+
+        let output = exports(ExportOptions::default(), write_json).remove(0);
+        let output = String::from_utf8(output).expect("export is not utf-8");
+        assert!(output
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("{\"record\":\"string\",\"id\":6508,\"value\":"));
+        assert_eq!(json_string("a\"\\\n\u{1}é"), "\"a\\\"\\\\\\n\\u0001é\"");
+    }
+
+    #[test]
+    fn test_dex_diff() {
+        use scroll::{Pread, Pwrite, LE};
+
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        assert!(DexDiff::new(&dex, &dex).expect("diff failed").is_empty());
+
+        let activity = dex
+            .get_type_from_descriptor("Landroid/app/Activity;")
+            .expect("bad type")
+            .expect("no activity type");
+        let changed = mutated_example_dex(|data| {
+            let class_defs_off = data.pread_with::<u32>(0x64, LE).unwrap() as usize;
+            // the first class becomes another one, the second one gets the final flag
+            data.pwrite_with(activity.id(), class_defs_off, LE).unwrap();
+            let flags = data.pread_with::<u32>(class_defs_off + 32 + 4, LE).unwrap();
+            data.pwrite_with(flags ^ 0x10, class_defs_off + 32 + 4, LE)
+                .unwrap();
+        });
+        let changed = DexReader::from_vec(changed).expect("cannot open dex");
+        let descriptor = |index: usize| {
+            let class_def = dex.class_defs().nth(index).unwrap().expect("bad class def");
+            let jtype = dex.get_type(class_def.class_idx()).expect("bad type");
+            jtype.to_string()
+        };
+
+        let diff = DexDiff::new(&dex, &changed).expect("diff failed");
+        assert_eq!(
+            diff.added_classes(),
+            &["Landroid/app/Activity;".to_string()]
+        );
+        assert_eq!(diff.removed_classes(), &[descriptor(0)]);
+        assert_eq!(diff.changed_classes().len(), 1);
+        let class = &diff.changed_classes()[0];
+        assert_eq!(class.descriptor(), &descriptor(1));
+        assert!(class.header_changed());
+        assert_eq!(class.static_values(), None);
+        assert!(class.added_methods().is_empty() && class.changed_fields().is_empty());
+        assert!(diff.added_strings().is_empty() && diff.removed_strings().is_empty());
+
+        let reverse = DexDiff::new(&changed, &dex).expect("diff failed");
+        assert_eq!(reverse.added_classes(), diff.removed_classes());
+        assert_eq!(reverse.removed_classes(), diff.added_classes());
+
+        let mut output = Vec::new();
+        diff.write_json(&mut output).expect("cannot write diff");
+        let output = String::from_utf8(output).expect("diff is not utf-8");
+        assert_eq!(
+            output,
+            format!(
+                "{{\"change\":\"added_class\",\"class\":\"Landroid/app/Activity;\"}}\n\
+                 {{\"change\":\"removed_class\",\"class\":\"{}\"}}\n\
+                 {{\"change\":\"changed_class\",\"class\":\"{}\",\"header_changed\":true,\
+                 \"static_values_changed\":false}}\n",
+                descriptor(0),
+                descriptor(1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_dex_diff_static_values() {
+        use crate::encoded_value::skip_encoded_value;
+        use scroll::Uleb128;
+
+        // the first class with a string among its static values, and the offset of the
+        // index of the string
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dex = DexReader::from_vec(data.clone()).expect("cannot open dex");
+        let (class_def, string_index_off) = dex
+            .class_defs()
+            .find_map(|class_def| {
+                let class_def = class_def.expect("bad class def");
+                if class_def.static_values_off == 0 {
+                    return None;
+                }
+                let offset = &mut { class_def.static_values_off as usize };
+                let len = Uleb128::read(&data, offset).expect("bad size");
+                for _ in 0..len {
+                    if data[*offset] & 0x1f == 0x17 {
+                        return Some((class_def, *offset + 1));
+                    }
+                    skip_encoded_value(&data, offset).expect("bad value");
+                }
+                None
+            })
+            .expect("no class with a static string");
+        let changed = mutated_example_dex(|data| data[string_index_off] ^= 0x1);
+        let changed = DexReader::from_vec(changed).expect("cannot open dex");
+
+        // the class is the only change, through its static values and the initial value of
+        // one of its fields
+        let diff = DexDiff::new(&dex, &changed).expect("diff failed");
+        let descriptor = dex.get_type(class_def.class_idx()).expect("bad type");
+        assert!(diff.added_classes().is_empty() && diff.removed_classes().is_empty());
+        assert!(diff.added_strings().is_empty() && diff.removed_strings().is_empty());
+        assert_eq!(diff.changed_classes().len(), 1);
+        let class = &diff.changed_classes()[0];
+        assert_eq!(class.descriptor(), &descriptor.to_string());
+        assert!(!class.header_changed());
+        let (old, new) = class.static_values().expect("no static values change");
+        assert!(old.is_some() && new.is_some() && old != new);
+        assert_eq!(class.changed_fields().len(), 1);
+        assert!(class.added_fields().is_empty() && class.removed_fields().is_empty());
+        assert!(class.added_methods().is_empty() && class.removed_methods().is_empty());
+        assert!(class.changed_methods().is_empty());
+
+        // the hashes are the ones of the spans, when they are tracked
+        let tracked = DexReaderBuilder::new()
+            .track_spans(true)
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        let span = tracked
+            .classes()
+            .map(|class| class.expect("cannot load class"))
+            .find(|class| class.jtype() == &descriptor)
+            .and_then(|class| class.static_values_span().copied())
+            .expect("no span");
+        assert_eq!(old, Some(span.bytes_hash()));
+        let diff = DexDiff::new(&tracked, &changed).expect("diff failed");
+        assert_eq!(diff.changed_classes()[0].static_values(), Some((old, new)));
+    }
+
+    #[test]
+    fn test_export_raw_indices() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut output = Vec::new();
+        let options = ExportOptions {
+            raw_indices: true,
+            ..Default::default()
+        };
+        write_csv(&dex, &mut output, options).expect("export failed");
+        let output = String::from_utf8(output).expect("export is not utf-8");
+        // string values are quoted, and so is the CSV field holding them
+        let value = output
+            .lines()
+            .filter(|line| line.starts_with("field,"))
+            .map(|line| line.split(',').nth(5).unwrap())
+            .find(|value| value.starts_with("\"\"\""))
+            .expect("no string field values");
+        let (string, idx) = value
+            .trim_end_matches('"')
+            .rsplit_once('#')
+            .expect("no raw index");
+        let idx = idx.parse().expect("bad raw index");
+        let resolved = dex.get_string(idx).expect("bad string");
+        assert_eq!(string, format!("\"\"\"{}\"\"", &*resolved));
+
+        // canonical exports hold no raw indices
+        let canonical = |raw_indices| {
+            let options = ExportOptions {
+                canonical: true,
+                raw_indices,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            write_csv(&dex, &mut output, options).expect("export failed");
+            output
+        };
+        assert_eq!(canonical(true), canonical(false));
+    }
+}
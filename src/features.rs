@@ -0,0 +1,281 @@
+//! The dex features a file uses, see `Dex::feature_report`.
+//!
+//! Re-assembling a modified file needs the same target as the original: a file using
+//! `invoke-custom` can't be written for an API level older than 26, one with default
+//! interface methods needs 24. `FeatureReport` lists the features found, each with the
+//! first places using it, and the oldest API level and dex version they require.
+use std::fmt;
+
+use getset::{CopyGetters, Getters};
+
+use crate::{method::MethodRef, opcode::Opcode, ubyte, uint};
+
+/// A feature of the dex format newer than the `035` version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DexFeature {
+    /// `invoke-polymorphic` and `invoke-polymorphic/range`.
+    InvokePolymorphic,
+    /// `invoke-custom` and `invoke-custom/range`, and so call sites.
+    InvokeCustom,
+    /// `const-method-handle`.
+    ConstMethodHandle,
+    /// `const-method-type`.
+    ConstMethodType,
+    /// A virtual method of an interface with code.
+    DefaultInterfaceMethod,
+    /// A static method of an interface other than its static initializer.
+    StaticInterfaceMethod,
+    /// A static synthetic method named `access$...`, which javac generates to let nested
+    /// classes reach private members. Dex files have no nest attributes, so these are only
+    /// a hint that the sources relied on nest-based access; they need no newer version.
+    SyntheticAccessor,
+    /// A `hiddenapi_class_data_item` section.
+    HiddenApiData,
+}
+
+impl DexFeature {
+    /// All the features, in the order they are reported.
+    pub const ALL: [DexFeature; 8] = [
+        DexFeature::InvokePolymorphic,
+        DexFeature::InvokeCustom,
+        DexFeature::ConstMethodHandle,
+        DexFeature::ConstMethodType,
+        DexFeature::DefaultInterfaceMethod,
+        DexFeature::StaticInterfaceMethod,
+        DexFeature::SyntheticAccessor,
+        DexFeature::HiddenApiData,
+    ];
+
+    /// Stable name of the feature, for example `invoke-custom`.
+    pub fn id(self) -> &'static str {
+        match self {
+            DexFeature::InvokePolymorphic => "invoke-polymorphic",
+            DexFeature::InvokeCustom => "invoke-custom",
+            DexFeature::ConstMethodHandle => "const-method-handle",
+            DexFeature::ConstMethodType => "const-method-type",
+            DexFeature::DefaultInterfaceMethod => "default-interface-method",
+            DexFeature::StaticInterfaceMethod => "static-interface-method",
+            DexFeature::SyntheticAccessor => "synthetic-accessor",
+            DexFeature::HiddenApiData => "hiddenapi-data",
+        }
+    }
+
+    /// The oldest API level which runs files using the feature, `None` if it needs no
+    /// particular one.
+    pub fn min_api(self) -> Option<uint> {
+        match self {
+            DexFeature::DefaultInterfaceMethod | DexFeature::StaticInterfaceMethod => Some(24),
+            DexFeature::InvokePolymorphic | DexFeature::InvokeCustom => Some(26),
+            DexFeature::ConstMethodHandle
+            | DexFeature::ConstMethodType
+            | DexFeature::HiddenApiData => Some(28),
+            DexFeature::SyntheticAccessor => None,
+        }
+    }
+
+    /// The oldest dex version allowing the feature, such as `038`.
+    pub fn min_version(self) -> &'static str {
+        match self {
+            DexFeature::DefaultInterfaceMethod | DexFeature::StaticInterfaceMethod => "037",
+            DexFeature::InvokePolymorphic | DexFeature::InvokeCustom => "038",
+            DexFeature::ConstMethodHandle
+            | DexFeature::ConstMethodType
+            | DexFeature::HiddenApiData => "039",
+            DexFeature::SyntheticAccessor => BASE_VERSION,
+        }
+    }
+
+    /// The feature an opcode belongs to, if any.
+    pub(crate) fn of_opcode(opcode: &Opcode) -> Option<Self> {
+        Some(match opcode.value {
+            0xfa | 0xfb => DexFeature::InvokePolymorphic,
+            0xfc | 0xfd => DexFeature::InvokeCustom,
+            0xfe => DexFeature::ConstMethodHandle,
+            0xff => DexFeature::ConstMethodType,
+            _ => return None,
+        })
+    }
+}
+
+/// The version every reader supports.
+const BASE_VERSION: &str = "035";
+
+/// A place using a feature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureLocation {
+    /// An instruction of a method, at an address in code units.
+    Instruction { method: MethodRef, addr: usize },
+    /// The definition of a method.
+    Method(MethodRef),
+    /// A section of the file, at an offset.
+    Section { offset: uint },
+}
+
+/// Formats the location in smali notation, with `@` and the address of an instruction.
+impl fmt::Display for FeatureLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureLocation::Instruction { method, addr } => write!(f, "{}@{:#x}", method, addr),
+            FeatureLocation::Method(method) => write!(f, "{}", method),
+            FeatureLocation::Section { offset } => write!(f, "{:#x}", offset),
+        }
+    }
+}
+
+/// The uses of a feature in a file.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct FeatureUsage {
+    #[get_copy = "pub"]
+    feature: DexFeature,
+    /// Number of places using the feature.
+    #[get_copy = "pub"]
+    count: usize,
+    /// The first places using the feature, in file order, at most as many as asked for.
+    #[get = "pub"]
+    examples: Vec<FeatureLocation>,
+}
+
+/// The features a file uses, see `Dex::feature_report`. The `Display` implementation writes
+/// one `key: value` line per observation and per feature used, in a stable order.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct FeatureReport {
+    /// The version in the magic, such as `035`.
+    #[get = "pub"]
+    version: String,
+    /// The lowest opcode of the instructions, `None` if there is no code.
+    #[get_copy = "pub"]
+    min_opcode: Option<ubyte>,
+    /// The highest opcode of the instructions, `None` if there is no code.
+    #[get_copy = "pub"]
+    max_opcode: Option<ubyte>,
+    /// The features used, in the order of `DexFeature::ALL`.
+    #[get = "pub"]
+    features: Vec<FeatureUsage>,
+    max_examples: usize,
+}
+
+impl FeatureReport {
+    pub(crate) fn new(version: String, max_examples: usize) -> Self {
+        Self {
+            version,
+            min_opcode: None,
+            max_opcode: None,
+            features: Vec::new(),
+            max_examples,
+        }
+    }
+
+    /// Records an instruction with the `opcode`.
+    pub(crate) fn add_opcode(&mut self, opcode: ubyte) {
+        self.min_opcode = Some(self.min_opcode.map_or(opcode, |min| min.min(opcode)));
+        self.max_opcode = Some(self.max_opcode.map_or(opcode, |max| max.max(opcode)));
+    }
+
+    /// Records a use of the `feature`; `location` is only called while examples are missing.
+    pub(crate) fn add<F>(&mut self, feature: DexFeature, location: F) -> crate::Result<()>
+    where
+        F: FnOnce() -> crate::Result<FeatureLocation>,
+    {
+        let index = match self
+            .features
+            .binary_search_by_key(&feature, |usage| usage.feature)
+        {
+            Ok(index) => index,
+            Err(index) => {
+                let usage = FeatureUsage {
+                    feature,
+                    count: 0,
+                    examples: Vec::new(),
+                };
+                self.features.insert(index, usage);
+                index
+            }
+        };
+        let usage = &mut self.features[index];
+        usage.count += 1;
+        if usage.examples.len() < self.max_examples {
+            usage.examples.push(location()?);
+        }
+        Ok(())
+    }
+
+    /// The uses of the `feature`, `None` if it isn't used.
+    pub fn usage(&self, feature: DexFeature) -> Option<&FeatureUsage> {
+        self.features.iter().find(|usage| usage.feature == feature)
+    }
+
+    /// Returns `true` if the file uses the `feature`.
+    pub fn uses(&self, feature: DexFeature) -> bool {
+        self.usage(feature).is_some()
+    }
+
+    /// The oldest API level which runs the file according to the features used, `None` if
+    /// they need no particular one.
+    pub fn min_api(&self) -> Option<uint> {
+        self.features
+            .iter()
+            .filter_map(|usage| usage.feature.min_api())
+            .max()
+    }
+
+    /// The oldest dex version allowing the features used. It may be older than `version`,
+    /// compilers write the version of their target rather than the one the file needs.
+    pub fn min_version(&self) -> &'static str {
+        self.features
+            .iter()
+            .map(|usage| usage.feature.min_version())
+            .max()
+            .unwrap_or(BASE_VERSION)
+    }
+
+    /// The report as a JSON object with the keys of the `Display` output.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        let features: serde_json::Map<String, serde_json::Value> = self
+            .features
+            .iter()
+            .map(|usage| {
+                let examples: Vec<String> =
+                    usage.examples.iter().map(ToString::to_string).collect();
+                let value = serde_json::json!({ "count": usage.count, "examples": examples });
+                (usage.feature.id().to_string(), value)
+            })
+            .collect();
+        serde_json::json!({
+            "version": self.version,
+            "min_opcode": self.min_opcode,
+            "max_opcode": self.max_opcode,
+            "min_api": self.min_api(),
+            "min_version": self.min_version(),
+            "features": features,
+        })
+    }
+}
+
+impl fmt::Display for FeatureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opcode = |opcode: Option<ubyte>| match opcode {
+            Some(opcode) => format!("{:#04x}", opcode),
+            None => "none".to_string(),
+        };
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "min_opcode: {}", opcode(self.min_opcode))?;
+        writeln!(f, "max_opcode: {}", opcode(self.max_opcode))?;
+        match self.min_api() {
+            Some(api) => writeln!(f, "min_api: {}", api)?,
+            None => writeln!(f, "min_api: none")?,
+        }
+        writeln!(f, "min_version: {}", self.min_version())?;
+        for usage in &self.features {
+            let examples: Vec<String> = usage.examples.iter().map(ToString::to_string).collect();
+            writeln!(
+                f,
+                "{}: {} [{}]",
+                usage.feature.id(),
+                usage.count,
+                examples.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
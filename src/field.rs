@@ -1,14 +1,18 @@
 //! Dex `Field` and supporting structures
-use scroll::{ctx, Pread, Uleb128};
+use std::fmt;
+
+use scroll::{ctx, Uleb128};
 
 use crate::{
-    annotation::AnnotationSetItem,
+    annotation::{Annotated, AnnotationSetItem, ClassAnnotations},
     class::ClassId,
     encoded_item::{EncodedItem, EncodedItemArray},
     encoded_value::EncodedValue,
     error::Error,
     jtype::{Type, TypeId},
+    source::DexSource,
     string::{DexString, StringId},
+    sync::Shared,
     ulong, ushort, utils,
 };
 use getset::{CopyGetters, Getters};
@@ -28,31 +32,43 @@ bitflags! {
     }
 }
 
+impl_access_flags!(
+    ulong,
+    PUBLIC => "public",
+    PRIVATE => "private",
+    PROTECTED => "protected",
+    STATIC => "static",
+    FINAL => "final",
+    VOLATILE => "volatile",
+    TRANSIENT => "transient",
+    SYNTHETIC => "synthetic",
+    ENUM => "enum",
+);
+
 /// Represents the field of a class
 #[derive(Debug, Getters, CopyGetters)]
 pub struct Field {
     /// Name of the field.
     #[get = "pub"]
-    name: DexString,
+    pub(crate) name: DexString,
     /// Type of the field.
     #[get = "pub"]
-    jtype: Type,
+    pub(crate) jtype: Type,
     /// Class which this field belongs to.
     #[get_copy = "pub"]
-    class: ClassId,
+    pub(crate) class: ClassId,
     /// Access flags for the field.
     #[get_copy = "pub"]
-    access_flags: AccessFlags,
+    pub(crate) access_flags: AccessFlags,
     /// Initial value of the field. Always `None` for non-static fields.
     /// If the value is `None`, it is not guaranteed that initial_value is `null`
     /// at runtime. The field might be initialized in `<clinit>` method.
-    initial_value: Option<EncodedValue>,
+    pub(crate) initial_value: Option<EncodedValue>,
     /// Annotations of the field.
-    #[get = "pub"]
-    annotations: AnnotationSetItem,
+    pub(crate) annotations: Shared<ClassAnnotations>,
     /// `FieldId` of the field.
     #[get_copy = "pub"]
-    id: FieldId,
+    pub(crate) id: FieldId,
 }
 
 impl Field {
@@ -75,28 +91,29 @@ impl Field {
 
     /// Returns the value of `dalvik.annotation.Signature`.
     pub fn signature(&self) -> super::Result<Option<String>> {
-        utils::get_signature(self.annotations())
+        utils::get_signature(self.annotations()?)
+    }
+
+    /// Annotations of the field. Read on first access unless the `Dex` was read with
+    /// eager annotations.
+    pub fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        self.annotations.field(self.id)
     }
 
-    pub(crate) fn try_from_dex<S: AsRef<[u8]>>(
+    pub(crate) fn try_from_dex<S: DexSource>(
         dex: &super::Dex<S>,
         encoded_field: &EncodedField,
         initial_value: Option<EncodedValue>,
-        annotations: AnnotationSetItem,
+        annotations: Shared<ClassAnnotations>,
     ) -> super::Result<Self> {
-        debug!(target: "field", "encoded field: {:?}", encoded_field);
+        dex_debug!(dex.log_label(), target: "field", "encoded field: {:?}", encoded_field);
         let field_item = dex.get_field_item(encoded_field.field_id)?;
-        debug!(target: "field", "field id item: {:?}", field_item);
+        dex_debug!(dex.log_label(), target: "field", "field id item: {:?}", field_item);
         Ok(Self {
             name: dex.get_string(field_item.name_idx)?,
             jtype: dex.get_type(TypeId::from(field_item.type_idx))?,
             class: ClassId::from(field_item.class_idx),
-            access_flags: AccessFlags::from_bits(encoded_field.access_flags).ok_or_else(|| {
-                Error::InvalidId(format!(
-                    "Invalid access flags when loading field {}",
-                    field_item.name_idx
-                ))
-            })?,
+            access_flags: AccessFlags::from_bits_retain(encoded_field.access_flags),
             initial_value,
             annotations,
             id: encoded_field.field_id,
@@ -104,6 +121,12 @@ impl Field {
     }
 }
 
+impl Annotated for Field {
+    fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        Field::annotations(self)
+    }
+}
+
 /// List of `EncodedField`s
 pub type EncodedFieldArray = EncodedItemArray<EncodedField>;
 
@@ -130,7 +153,7 @@ pub struct FieldIdItem {
 }
 
 impl FieldIdItem {
-    pub(crate) fn try_from_dex<T: AsRef<[u8]>>(
+    pub(crate) fn try_from_dex<T: DexSource>(
         dex: &super::Dex<T>,
         offset: ulong,
         field_id: FieldId,
@@ -146,6 +169,45 @@ impl FieldIdItem {
     }
 }
 
+/// A field reference from the `FieldId`s list with its class, name and type resolved.
+/// Unlike `Field`, this doesn't require the field to be defined in the `Dex`.
+#[derive(Debug, Clone, Getters, CopyGetters, PartialEq)]
+pub struct FieldRef {
+    /// `FieldId` of the field.
+    #[get_copy = "pub"]
+    id: FieldId,
+    /// Class which defines the field.
+    #[get = "pub"]
+    class: Type,
+    /// Name of the field.
+    #[get = "pub"]
+    name: DexString,
+    /// Type of the field.
+    #[get = "pub"]
+    jtype: Type,
+}
+
+impl FieldRef {
+    pub(crate) fn try_from_dex<T: DexSource>(
+        dex: &super::Dex<T>,
+        field_item: &FieldIdItem,
+    ) -> super::Result<Self> {
+        Ok(Self {
+            id: field_item.id,
+            class: dex.get_type(TypeId::from(field_item.class_idx))?,
+            name: dex.get_string(field_item.name_idx)?,
+            jtype: dex.get_type(TypeId::from(field_item.type_idx))?,
+        })
+    }
+}
+
+/// Formats the field in smali notation, for example `Lcom/example/Foo;->bar:I`.
+impl fmt::Display for FieldRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}->{}:{}", self.class, self.name, self.jtype)
+    }
+}
+
 /// Index into the `FieldId`s list.
 pub type FieldId = ulong;
 
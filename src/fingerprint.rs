@@ -0,0 +1,155 @@
+//! Observable quirks of the tool which produced a dex file.
+//!
+//! Compilers, shrinkers and packers fill the bits the format leaves free differently: the
+//! value of padding bytes, the order of the map list, reserved access flags, non-minimal
+//! LEB128 encodings. `Dex::producer_fingerprint` collects them into a `ProducerFingerprint`
+//! which classifiers can match against known toolchains. This module only provides the
+//! observations, it doesn't tell which tool produced a file.
+use std::{collections::BTreeMap, fmt};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{dex::ItemType, string::DexString, ubyte, uint};
+
+/// Quirks of a dex file, see `Dex::producer_fingerprint`. The `Display` implementation
+/// writes one `key: value` line per observation, in a stable order, so that fingerprints
+/// can be stored and compared as text.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ProducerFingerprint {
+    /// The version in the magic, such as `035`.
+    #[get = "pub"]
+    version: String,
+    /// Size of the header, always `0x70` in files written by known tools.
+    #[get_copy = "pub"]
+    header_size: uint,
+    /// The raw endian tag.
+    #[get_copy = "pub"]
+    endian_tag: [ubyte; 4],
+    /// Item types of the map list, in the order they are listed.
+    #[get = "pub"]
+    map_order: Vec<ItemType>,
+    /// `true` if the map list is ordered by offset, as the format requires.
+    #[get_copy = "pub"]
+    map_sorted: bool,
+    /// Number of occurrences of each value of the alignment padding: the bytes between
+    /// the sections whose size is known and the next section, and the padding of code
+    /// items with try blocks.
+    #[get = "pub"]
+    padding_bytes: BTreeMap<ubyte, usize>,
+    /// Number of occurrences of each raw visibility byte of the annotations.
+    #[get = "pub"]
+    visibilities: BTreeMap<ubyte, usize>,
+    /// Number of classes, fields and methods with access flags the format doesn't define.
+    #[get_copy = "pub"]
+    reserved_access_flags: usize,
+    /// Number of LEB128 values encoded with more bytes than needed.
+    #[get_copy = "pub"]
+    nonminimal_lebs: usize,
+    /// The strings starting with `~~`, where D8 and R8 record their version and options.
+    #[get = "pub"]
+    markers: Vec<DexString>,
+}
+
+impl ProducerFingerprint {
+    /// The map list order as the hexadecimal item type codes joined by `,`, which is
+    /// shorter to compare than the item types.
+    pub fn map_order_signature(&self) -> String {
+        self.map_order
+            .iter()
+            .map(|item_type| format!("{:x}", *item_type as u16))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl fmt::Display for ProducerFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let histogram = |values: &BTreeMap<ubyte, usize>| {
+            values
+                .iter()
+                .map(|(value, count)| format!("{:#04x}={}", value, count))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "header_size: {:#x}", self.header_size)?;
+        writeln!(f, "endian_tag: {:02x?}", self.endian_tag)?;
+        writeln!(f, "map_order: {}", self.map_order_signature())?;
+        writeln!(f, "map_sorted: {}", self.map_sorted)?;
+        writeln!(f, "padding_bytes: {}", histogram(&self.padding_bytes))?;
+        writeln!(f, "visibilities: {}", histogram(&self.visibilities))?;
+        writeln!(f, "reserved_access_flags: {}", self.reserved_access_flags)?;
+        writeln!(f, "nonminimal_lebs: {}", self.nonminimal_lebs)?;
+        for marker in &self.markers {
+            writeln!(f, "marker: {}", &**marker)?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates the observations of a walk over a dex file.
+#[derive(Default)]
+pub(crate) struct FingerprintRecorder {
+    pub(crate) padding_bytes: BTreeMap<ubyte, usize>,
+    pub(crate) visibilities: BTreeMap<ubyte, usize>,
+    pub(crate) reserved_access_flags: usize,
+    pub(crate) markers: Vec<DexString>,
+}
+
+impl FingerprintRecorder {
+    /// Records the values of padding bytes.
+    pub(crate) fn padding(&mut self, bytes: &[ubyte]) {
+        for byte in bytes {
+            *self.padding_bytes.entry(*byte).or_default() += 1;
+        }
+    }
+
+    /// Records the visibility byte of an annotation.
+    pub(crate) fn visibility(&mut self, visibility: ubyte) {
+        *self.visibilities.entry(visibility).or_default() += 1;
+    }
+
+    /// Records access flags, counting them if they have bits outside of `defined`.
+    pub(crate) fn access_flags(&mut self, flags: u64, defined: u64) {
+        if flags & !defined != 0 {
+            self.reserved_access_flags += 1;
+        }
+    }
+
+    pub(crate) fn into_fingerprint(
+        self,
+        magic: [ubyte; 8],
+        header_size: uint,
+        endian_tag: [ubyte; 4],
+        map_items: &[(ItemType, uint)],
+        nonminimal_lebs: usize,
+        log_label: Option<&str>,
+    ) -> ProducerFingerprint {
+        let version = magic[4..7].iter().map(|byte| char::from(*byte)).collect();
+        let map_sorted = map_items.windows(2).all(|pair| pair[0].1 <= pair[1].1);
+        dex_debug!(log_label, target: "fingerprint", "version: {}, map sorted: {}, reserved flags: {}", version, map_sorted, self.reserved_access_flags);
+        ProducerFingerprint {
+            version,
+            header_size,
+            endian_tag,
+            map_order: map_items.iter().map(|(item_type, _)| *item_type).collect(),
+            map_sorted,
+            padding_bytes: self.padding_bytes,
+            visibilities: self.visibilities,
+            reserved_access_flags: self.reserved_access_flags,
+            nonminimal_lebs,
+            markers: self.markers,
+        }
+    }
+}
+
+/// Size of the items of the sections whose items all have the same size.
+pub(crate) fn fixed_item_size(item_type: ItemType) -> Option<uint> {
+    Some(match item_type {
+        ItemType::StringIdItem | ItemType::TypeIdItem | ItemType::CallSiteIdItem => 4,
+        ItemType::FieldIdItem | ItemType::MethodIdItem | ItemType::MethodHandleItem => 8,
+        ItemType::ProtoIdItem => 12,
+        ItemType::ClassDefItem => 32,
+        _ => return None,
+    })
+}
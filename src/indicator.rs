@@ -0,0 +1,479 @@
+//! Indicators of compromise found in the string pool, see `Dex::extract_indicators`.
+//!
+//! The extractors favour precision over recall: except for URLs, which are found anywhere
+//! in a string, a string is an indicator only if the whole string has the expected form.
+//! A string such as `connect to 10.0.0.1` is not reported, nor are the ambiguous forms
+//! documented on each kind.
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    ops::Range,
+};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{method::MethodRef, string::StringId, uint};
+
+bitflags! {
+    /// The extractors to run, see `IndicatorOptions::kinds`.
+    pub struct IndicatorKinds: u8 {
+        /// See `IndicatorKind::Url`.
+        const URL = 0x1;
+        /// See `IndicatorKind::Ipv4`.
+        const IPV4 = 0x2;
+        /// See `IndicatorKind::Ipv6`.
+        const IPV6 = 0x4;
+        /// See `IndicatorKind::Domain`.
+        const DOMAIN = 0x8;
+        /// See `IndicatorKind::PackageName`.
+        const PACKAGE_NAME = 0x10;
+        /// See `IndicatorKind::FilePath`.
+        const FILE_PATH = 0x20;
+        /// See `IndicatorKind::IntentAction`.
+        const INTENT_ACTION = 0x40;
+    }
+}
+
+/// The kind of an indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IndicatorKind {
+    /// An `http`, `https`, `ftp`, `ftps`, `ws` or `wss` URL whose host is a domain name
+    /// with an alphabetic top-level domain or an IPv4 address, anywhere in a string. Only
+    /// extracted with the `regex` feature. Trailing punctuation is not part of the URL, and
+    /// templates such as `http://%s/` are not URLs.
+    Url,
+    /// An IPv4 address in dotted decimal notation, without leading zeros. Addresses whose
+    /// first octet is 0, and addresses whose four octets are single digits, which are more
+    /// likely version numbers such as `1.2.3.4`, are left out.
+    Ipv4,
+    /// An IPv6 address, other than the unspecified address `::`, with at least one decimal
+    /// digit, which leaves out strings such as `dead::beef`.
+    Ipv6,
+    /// A lowercase domain name whose last label is a common top-level domain, such as
+    /// `example.com`. Names starting with a top-level domain are package names instead,
+    /// and names starting with the root package of a platform library, such as `java.io`,
+    /// are neither.
+    Domain,
+    /// A lowercase Java package name starting with a common top-level domain, such as
+    /// `com.example.app`.
+    PackageName,
+    /// An absolute path with at least two components, such as `/system/bin/su`.
+    FilePath,
+    /// A constant in the form of an intent action: a lowercase package name of at least
+    /// two components, optionally followed by a class name, then an uppercase name such as
+    /// `android.intent.action.BOOT_COMPLETED`.
+    IntentAction,
+}
+
+impl IndicatorKind {
+    /// The flag of the kind in `IndicatorKinds`.
+    pub fn flag(self) -> IndicatorKinds {
+        match self {
+            IndicatorKind::Url => IndicatorKinds::URL,
+            IndicatorKind::Ipv4 => IndicatorKinds::IPV4,
+            IndicatorKind::Ipv6 => IndicatorKinds::IPV6,
+            IndicatorKind::Domain => IndicatorKinds::DOMAIN,
+            IndicatorKind::PackageName => IndicatorKinds::PACKAGE_NAME,
+            IndicatorKind::FilePath => IndicatorKinds::FILE_PATH,
+            IndicatorKind::IntentAction => IndicatorKinds::INTENT_ACTION,
+        }
+    }
+}
+
+/// Options of `Dex::extract_indicators`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndicatorOptions {
+    kinds: IndicatorKinds,
+    referencing_methods: bool,
+}
+
+impl Default for IndicatorOptions {
+    fn default() -> Self {
+        Self {
+            kinds: IndicatorKinds::all(),
+            referencing_methods: false,
+        }
+    }
+}
+
+impl IndicatorOptions {
+    /// Returns the default options: every extractor, without the referencing methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The extractors to run. Defaults to all of them.
+    pub fn kinds(mut self, kinds: IndicatorKinds) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    /// Find the methods loading the strings of the indicators with `const-string`, which
+    /// scans the code of every method. Defaults to `false`, in which case the
+    /// `referencing_methods` of the indicators are empty.
+    pub fn referencing_methods(mut self, scan_code: bool) -> Self {
+        self.referencing_methods = scan_code;
+        self
+    }
+
+    pub(crate) fn selected_kinds(&self) -> IndicatorKinds {
+        self.kinds
+    }
+
+    pub(crate) fn scans_code(&self) -> bool {
+        self.referencing_methods
+    }
+}
+
+/// An indicator found in a string of the string pool.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct Indicator {
+    /// The kind of the indicator.
+    #[get_copy = "pub"]
+    kind: IndicatorKind,
+    /// The indicator, the part of the string in `range`.
+    #[get = "pub"]
+    value: String,
+    /// Index of the string in the `StringId`s list.
+    #[get_copy = "pub"]
+    string_id: StringId,
+    /// Offset of the string data item of the string in the file.
+    #[get_copy = "pub"]
+    string_data_off: uint,
+    /// Range of the indicator in the string, in bytes of its UTF-8 form.
+    #[get = "pub"]
+    range: Range<usize>,
+    /// The methods loading the string with `const-string` or `const-string/jumbo`, in the
+    /// order of the class definitions. Empty unless `IndicatorOptions::referencing_methods`
+    /// is set.
+    #[get = "pub"]
+    referencing_methods: Vec<MethodRef>,
+}
+
+impl Indicator {
+    pub(crate) fn new(
+        kind: IndicatorKind,
+        string: &str,
+        string_id: StringId,
+        string_data_off: uint,
+        range: Range<usize>,
+    ) -> Self {
+        Self {
+            kind,
+            value: string[range.clone()].to_string(),
+            string_id,
+            string_data_off,
+            range,
+            referencing_methods: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_referencing_methods(&mut self, methods: Vec<MethodRef>) {
+        self.referencing_methods = methods;
+    }
+}
+
+/// Top-level domains that a domain name must end with, and that a package name must start
+/// with.
+const TOP_LEVEL_DOMAINS: &[&str] = &[
+    "app", "biz", "br", "cc", "cf", "cn", "co", "com", "de", "dev", "eu", "fr", "ga", "gq", "in",
+    "info", "io", "it", "jp", "kr", "me", "ml", "net", "nl", "online", "org", "pl", "ru", "site",
+    "su", "tk", "top", "tv", "uk", "us", "ws", "xyz",
+];
+
+/// Root packages of the platform libraries, whose names look like domains such as
+/// `java.io`.
+const PLATFORM_PACKAGES: &[&str] = &[
+    "android", "androidx", "dalvik", "java", "javax", "jdk", "junit", "kotlin", "kotlinx", "sun",
+];
+
+/// Finds the indicators of `kinds` in a string, with their range in it, in the order of
+/// their kind then of their position.
+pub fn find_in_str(value: &str, kinds: IndicatorKinds) -> Vec<(IndicatorKind, Range<usize>)> {
+    let mut found = Vec::new();
+    if kinds.contains(IndicatorKinds::URL) {
+        found.extend(
+            find_urls(value)
+                .into_iter()
+                .map(|range| (IndicatorKind::Url, range)),
+        );
+    }
+    let whole = [
+        (IndicatorKind::Ipv4, is_ipv4 as fn(&str) -> bool),
+        (IndicatorKind::Ipv6, is_ipv6),
+        (IndicatorKind::Domain, is_domain),
+        (IndicatorKind::PackageName, is_package_name),
+        (IndicatorKind::FilePath, is_file_path),
+        (IndicatorKind::IntentAction, is_intent_action),
+    ];
+    for (kind, matches) in whole.iter() {
+        if kinds.contains(kind.flag()) && matches(value) {
+            found.push((*kind, 0..value.len()));
+        }
+    }
+    found
+}
+
+#[cfg(feature = "regex")]
+fn find_urls(value: &str) -> Vec<Range<usize>> {
+    use regex::Regex;
+    use std::sync::OnceLock;
+
+    static URL: OnceLock<Regex> = OnceLock::new();
+    // scheme, then a domain name with an alphabetic top-level domain or an IPv4 address,
+    // an optional port, and an optional path, query or fragment which doesn't end with
+    // punctuation
+    let url = URL.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)\b(?:https?|ftps?|wss?)://",
+            r"(?:(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,63}",
+            r"|(?:[0-9]{1,3}\.){3}[0-9]{1,3})",
+            r"(?::[0-9]{1,5})?",
+            r#"(?:[/?#](?:[^\s"'<>\\^`{|}]*[^\s"'<>\\^`{|}.,;:!?)])?)?"#,
+        ))
+        .expect("bad URL regex")
+    });
+    url.find_iter(value).map(|url| url.range()).collect()
+}
+
+#[cfg(not(feature = "regex"))]
+fn find_urls(_value: &str) -> Vec<Range<usize>> {
+    Vec::new()
+}
+
+fn is_ipv4(value: &str) -> bool {
+    match value.parse::<Ipv4Addr>() {
+        Ok(address) => {
+            let octets = address.octets();
+            octets[0] != 0 && octets.iter().any(|octet| *octet >= 10)
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_ipv6(value: &str) -> bool {
+    match value.parse::<Ipv6Addr>() {
+        Ok(address) => !address.is_unspecified() && value.bytes().any(|byte| byte.is_ascii_digit()),
+        Err(_) => false,
+    }
+}
+
+/// Splits a dotted name into at least two labels, all made of lowercase letters, digits
+/// and the characters of `extra`, not starting with one of `extra`.
+fn dotted_labels<'a>(value: &'a str, extra: &[u8]) -> Option<Vec<&'a str>> {
+    let labels: Vec<&str> = value.split('.').collect();
+    let is_label = |label: &&str| {
+        let bytes = label.as_bytes();
+        !bytes.is_empty()
+            && bytes.len() <= 63
+            && !extra.contains(&bytes[0])
+            && bytes.iter().all(|byte| {
+                byte.is_ascii_lowercase() || byte.is_ascii_digit() || extra.contains(byte)
+            })
+    };
+    if labels.len() >= 2 && labels.iter().all(is_label) {
+        Some(labels)
+    } else {
+        None
+    }
+}
+
+fn is_domain(value: &str) -> bool {
+    if value.len() > 253 {
+        return false;
+    }
+    match dotted_labels(value, b"-") {
+        Some(labels) => {
+            let first = labels[0];
+            let last = labels[labels.len() - 1];
+            labels.iter().all(|label| !label.ends_with('-'))
+                && TOP_LEVEL_DOMAINS.contains(&last)
+                && !TOP_LEVEL_DOMAINS.contains(&first)
+                && !PLATFORM_PACKAGES.contains(&first)
+        }
+        None => false,
+    }
+}
+
+fn is_package_name(value: &str) -> bool {
+    match dotted_labels(value, b"_") {
+        Some(labels) => {
+            TOP_LEVEL_DOMAINS.contains(&labels[0])
+                && labels[1..]
+                    .iter()
+                    .all(|label| !label.as_bytes()[0].is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+fn is_file_path(value: &str) -> bool {
+    let rest = match value.strip_prefix('/') {
+        Some(rest) => rest.strip_suffix('/').unwrap_or(rest),
+        None => return false,
+    };
+    let components: Vec<&str> = rest.split('/').collect();
+    components.len() >= 2
+        && components.iter().all(|component| {
+            component
+                .bytes()
+                .next()
+                .is_some_and(|byte| byte.is_ascii_alphanumeric())
+                && component
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"._-+@~".contains(&byte))
+        })
+}
+
+fn is_intent_action(value: &str) -> bool {
+    let mut labels: Vec<&str> = value.split('.').collect();
+    let action = match labels.pop() {
+        Some(action) => action,
+        None => return false,
+    };
+    let is_action = action.len() >= 3
+        && action.as_bytes()[0].is_ascii_uppercase()
+        && action
+            .bytes()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_')
+        && !action.ends_with('_');
+    let is_class = |label: &str| {
+        label.as_bytes().first().is_some_and(u8::is_ascii_uppercase)
+            && label.bytes().any(|byte| byte.is_ascii_lowercase())
+            && label.bytes().all(|byte| byte.is_ascii_alphanumeric())
+    };
+    if labels.last().is_some_and(|label| is_class(label)) {
+        labels.pop();
+    }
+    is_action
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            label.as_bytes().first().is_some_and(u8::is_ascii_lowercase)
+                && label
+                    .bytes()
+                    .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_in_str, IndicatorKind, IndicatorKinds};
+
+    fn kinds_of(value: &str) -> Vec<IndicatorKind> {
+        find_in_str(value, IndicatorKinds::all())
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_whole_string_extractors() {
+        use IndicatorKind::*;
+
+        let positives = [
+            ("10.0.2.2", Ipv4),
+            ("192.168.1.100", Ipv4),
+            ("::1", Ipv6),
+            ("2001:db8::ff00:42:8329", Ipv6),
+            ("example.com", Domain),
+            ("cdn.evil-host.xyz", Domain),
+            ("com.example.app", PackageName),
+            ("org.apache.http_client", PackageName),
+            ("/system/bin/su", FilePath),
+            ("/data/local/tmp/", FilePath),
+            ("android.intent.action.BOOT_COMPLETED", IntentAction),
+            ("android.provider.Telephony.SMS_RECEIVED", IntentAction),
+        ];
+        for (value, kind) in positives.iter() {
+            assert_eq!(kinds_of(value), [*kind], "{}", value);
+        }
+
+        let negatives = [
+            // versions
+            "1.2.3.4",
+            "4.0.3.1",
+            "1.2.3",
+            "v10.0.2.2",
+            "0.10.0.1",
+            "010.0.2.2",
+            "256.1.1.1",
+            // not only the indicator
+            "connect to 10.0.0.1",
+            " example.com",
+            "::",
+            "dead::beef",
+            "Foo::bar",
+            // class names, resources and files
+            "android.app.Activity",
+            "java.io",
+            "kotlin.io",
+            "R.id",
+            "config.json",
+            "Example.com",
+            "-bad.com",
+            "com",
+            "com.1example",
+            "/",
+            "/bin",
+            "//comment",
+            "/proc/%d/maps",
+            "/a b/c",
+            "Landroid/app/Activity;",
+            "com/example/Foo",
+            "android.intent.action.",
+            "ACTION_VIEW",
+            "intent.ACTION",
+            "com.example.Foo",
+            "",
+        ];
+        for value in negatives.iter() {
+            assert_eq!(kinds_of(value), [], "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_disabled_extractors() {
+        assert!(find_in_str("10.0.2.2", IndicatorKinds::all() - IndicatorKinds::IPV4).is_empty());
+        assert!(find_in_str("example.com", IndicatorKinds::empty()).is_empty());
+        assert_eq!(
+            find_in_str("example.com", IndicatorKinds::DOMAIN),
+            [(IndicatorKind::Domain, 0..11)]
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_urls() {
+        let urls = |value: &str| -> Vec<String> {
+            find_in_str(value, IndicatorKinds::URL)
+                .into_iter()
+                .map(|(_, range)| value[range].to_string())
+                .collect()
+        };
+        assert_eq!(urls("https://example.com"), ["https://example.com"]);
+        assert_eq!(
+            urls("see http://10.0.2.2:8080/api?id=1, or ftp://files.example.org/a.apk."),
+            [
+                "http://10.0.2.2:8080/api?id=1",
+                "ftp://files.example.org/a.apk"
+            ]
+        );
+        assert_eq!(urls("(wss://push.example.io/)"), ["wss://push.example.io/"]);
+        assert_eq!(urls("HTTP://EXAMPLE.COM/Path"), ["HTTP://EXAMPLE.COM/Path"]);
+        for value in [
+            "http://%s/",
+            "http://",
+            "https://localhost/",
+            "file:///sdcard/x",
+            "example.com",
+        ]
+        .iter()
+        {
+            assert_eq!(urls(value), Vec::<String>::new(), "{}", value);
+        }
+        // a URL is not also a domain or a path
+        assert_eq!(
+            find_in_str("https://example.com", IndicatorKinds::all()).len(),
+            1
+        );
+    }
+}
@@ -3,30 +3,130 @@ use std::{clone::Clone, fmt};
 
 use getset::{CopyGetters, Getters};
 
-use crate::{string::DexString, uint};
+use crate::{
+    string::DexString,
+    uint,
+    validate::{self, DescriptorError},
+};
 
 /// Dex representation of a boolean type
-pub const BOOLEAN: &'static str = "Z";
+pub const BOOLEAN: &str = "Z";
 /// Dex representation of a byte type
-pub const BYTE: &'static str = "B";
+pub const BYTE: &str = "B";
 /// Dex representation of a short type
-pub const SHORT: &'static str = "S";
+pub const SHORT: &str = "S";
 /// Dex representation of a char type
-pub const CHAR: &'static str = "C";
+pub const CHAR: &str = "C";
 /// Dex representation of an integer type
-pub const INT: &'static str = "I";
+pub const INT: &str = "I";
 /// Dex representation of a long type
-pub const LONG: &'static str = "J";
+pub const LONG: &str = "J";
 /// Dex representation of a float type
-pub const FLOAT: &'static str = "F";
+pub const FLOAT: &str = "F";
 /// Dex representation of a double type
-pub const DOUBLE: &'static str = "D";
+pub const DOUBLE: &str = "D";
 /// Dex representation of a void type
-pub const VOID: &'static str = "V";
+pub const VOID: &str = "V";
 
 /// Offset into the `TypeId`s section.
 pub type TypeId = uint;
 
+/// A primitive type, or `void`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveType {
+    Boolean,
+    Byte,
+    Short,
+    Char,
+    Int,
+    Long,
+    Float,
+    Double,
+    Void,
+}
+
+impl PrimitiveType {
+    /// Returns the primitive type of a one character descriptor.
+    pub fn from_descriptor(descriptor: &str) -> Option<Self> {
+        Some(match descriptor {
+            BOOLEAN => PrimitiveType::Boolean,
+            BYTE => PrimitiveType::Byte,
+            SHORT => PrimitiveType::Short,
+            CHAR => PrimitiveType::Char,
+            INT => PrimitiveType::Int,
+            LONG => PrimitiveType::Long,
+            FLOAT => PrimitiveType::Float,
+            DOUBLE => PrimitiveType::Double,
+            VOID => PrimitiveType::Void,
+            _ => return None,
+        })
+    }
+
+    /// Returns the descriptor of the type, such as `I` for `int`.
+    pub fn descriptor(self) -> &'static str {
+        match self {
+            PrimitiveType::Boolean => BOOLEAN,
+            PrimitiveType::Byte => BYTE,
+            PrimitiveType::Short => SHORT,
+            PrimitiveType::Char => CHAR,
+            PrimitiveType::Int => INT,
+            PrimitiveType::Long => LONG,
+            PrimitiveType::Float => FLOAT,
+            PrimitiveType::Double => DOUBLE,
+            PrimitiveType::Void => VOID,
+        }
+    }
+}
+
+/// The type of the elements of an array, once all its dimensions are removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementKind {
+    Primitive(PrimitiveType),
+    Class,
+}
+
+/// What a `Type` refers to, read from its descriptor once when the type is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeKind {
+    /// A primitive type, or `void`.
+    Primitive(PrimitiveType),
+    /// An array of `dims` dimensions of `element`s.
+    Array { dims: usize, element: ElementKind },
+    /// A class or an interface.
+    Class,
+    /// A descriptor which is neither a primitive type, an array nor a class, read without
+    /// checking it, see `DexReaderBuilder::strict_descriptors`.
+    Invalid,
+}
+
+impl TypeKind {
+    /// Returns the kind of a descriptor checked against the `TypeDescriptor` grammar.
+    pub fn parse(descriptor: &str) -> Result<Self, DescriptorError> {
+        validate::validate_type_descriptor(descriptor)?;
+        Ok(Self::classify(descriptor))
+    }
+
+    /// Returns the kind of a descriptor from its shape, without checking the class names
+    /// or the number of dimensions: a class is anything of the form `L…;`, and any other
+    /// descriptor which is not a primitive type or an array of one is invalid.
+    pub(crate) fn classify(descriptor: &str) -> Self {
+        let dims = descriptor.bytes().take_while(|byte| *byte == b'[').count();
+        let element = &descriptor[dims..];
+        let element = match PrimitiveType::from_descriptor(element) {
+            Some(primitive) => ElementKind::Primitive(primitive),
+            None if element.len() > 2 && element.starts_with('L') && element.ends_with(';') => {
+                ElementKind::Class
+            }
+            None => return TypeKind::Invalid,
+        };
+        match (dims, element) {
+            (0, ElementKind::Primitive(primitive)) => TypeKind::Primitive(primitive),
+            (0, ElementKind::Class) => TypeKind::Class,
+            (dims, element) => TypeKind::Array { dims, element },
+        }
+    }
+}
+
 /// Represents a Java type. The type descriptor conforms to
 /// the syntax described [here](https://source.android.com/devices/tech/dalvik/dex-format#typedescriptor)
 #[derive(Debug, Getters, CopyGetters)]
@@ -36,6 +136,9 @@ pub struct Type {
     /// The type descriptor string for this string.
     #[get = "pub"]
     pub(crate) type_descriptor: DexString,
+    /// What the type refers to.
+    #[get_copy = "pub"]
+    pub(crate) kind: TypeKind,
 }
 
 macro_rules! gen_is_type_method {
@@ -44,21 +147,21 @@ macro_rules! gen_is_type_method {
         pub fn $func_name(&self) -> bool {
             self.type_descriptor == $descriptor
         }
-    }
+    };
 }
 
 impl Type {
+    pub(crate) fn new(id: TypeId, type_descriptor: DexString, kind: TypeKind) -> Self {
+        Self {
+            id,
+            type_descriptor,
+            kind,
+        }
+    }
+
     /// Returns `true` if the type is primitive
     pub fn is_primitive(&self) -> bool {
-        self.is_bool()
-            || self.is_byte()
-            || self.is_short()
-            || self.is_char()
-            || self.is_int()
-            || self.is_long()
-            || self.is_float()
-            || self.is_double()
-            || self.is_void()
+        matches!(self.kind, TypeKind::Primitive(_))
     }
 
     /// Returns `true` if the type is an array or a class
@@ -68,32 +171,42 @@ impl Type {
 
     /// Returns `true` if the type is a class
     pub fn is_class(&self) -> bool {
-        self.type_descriptor.starts_with("L")
+        self.kind == TypeKind::Class
     }
 
     /// Returns `true` if the type is an array
     pub fn is_array(&self) -> bool {
-        self.type_descriptor.starts_with("[")
+        self.array_dimensions().is_some()
     }
 
     /// If the type represents an array, get it's dimensions,
     /// otherwise returns `None`
     pub fn array_dimensions(&self) -> Option<usize> {
+        match self.kind {
+            TypeKind::Array { dims, .. } => Some(dims),
+            _ => None,
+        }
+    }
+
+    /// Returns the descriptor of the type of the elements of the array, such as `[I` for
+    /// `[[I`, or `None` if the type is not an array. See `to_java_name` to present it.
+    pub fn component_type(&self) -> Option<&str> {
         if self.is_array() {
-            Some(
-                self.type_descriptor
-                    .chars()
-                    .take_while(|c| *c == '[')
-                    .count(),
-            )
+            self.type_descriptor.strip_prefix('[')
         } else {
             None
         }
     }
 
-    /// Returns the Java representation of the `Type`
+    /// Returns the name of the type in Java source, such as `java.lang.String`, `int[][]`
+    /// or `void`. Nested classes keep their binary name, `java.util.Map$Entry`.
+    pub fn to_java_name(&self) -> String {
+        to_java_name(&self.type_descriptor)
+    }
+
+    /// Returns the Java representation of the `Type`, same as `Type::to_java_name`.
     pub fn to_java_type(&self) -> String {
-        to_java_type(&*self.type_descriptor)
+        self.to_java_name()
     }
 
     gen_is_type_method!(is_bool, BOOLEAN, "Returns `true` if the type is a boolean");
@@ -107,8 +220,11 @@ impl Type {
     gen_is_type_method!(is_void, VOID, "Returns `true` if the type is void");
 }
 
-fn to_java_type(s: &str) -> String {
-    match s {
+/// Returns the name in Java source of the type with the `descriptor`, such as
+/// `java.lang.String` for `Ljava/lang/String;` or `int[][]` for `[[I`. A descriptor which
+/// is not valid is returned unchanged, apart from its array dimensions.
+pub fn to_java_name(descriptor: &str) -> String {
+    match descriptor {
         BOOLEAN => "boolean".to_string(),
         BYTE => "byte".to_string(),
         SHORT => "short".to_string(),
@@ -118,14 +234,16 @@ fn to_java_type(s: &str) -> String {
         FLOAT => "float".to_string(),
         DOUBLE => "double".to_string(),
         VOID => "void".to_string(),
-        s if s.starts_with('L') => s[1..].replace('/', ".").replace(';', ""),
+        s if s.starts_with('L') && s.ends_with(';') && s.len() > 2 => {
+            s[1..s.len() - 1].replace('/', ".")
+        }
         s if s.starts_with('[') => {
             let d = s.chars().take_while(|c| *c == '[').count();
-            let mut base_type = to_java_type(&s[d..]);
+            let mut base_type = to_java_name(&s[d..]);
             base_type.push_str(&"[]".repeat(d));
             base_type
         }
-        _ => unreachable!(),
+        s => s.to_string(),
     }
 }
 
@@ -134,6 +252,7 @@ impl Clone for Type {
         Type {
             id: self.id,
             type_descriptor: self.type_descriptor.clone(),
+            kind: self.kind,
         }
     }
 }
@@ -170,20 +289,121 @@ impl fmt::Display for Type {
 
 #[cfg(test)]
 mod tests {
+    use super::{ElementKind, PrimitiveType, TypeKind};
+    use crate::validate::MAX_ARRAY_DIMENSIONS;
+
     #[test]
-    fn test_to_java_type() {
-        use super::to_java_type;
-        assert_eq!(to_java_type(super::BOOLEAN), "boolean");
-        assert_eq!(to_java_type(super::BYTE), "byte");
-        assert_eq!(to_java_type(super::SHORT), "short");
-        assert_eq!(to_java_type(super::CHAR), "char");
-        assert_eq!(to_java_type(super::INT), "int");
-        assert_eq!(to_java_type(super::LONG), "long");
-        assert_eq!(to_java_type(super::FLOAT), "float");
-        assert_eq!(to_java_type(super::DOUBLE), "double");
-        assert_eq!(to_java_type(super::VOID), "void");
-        assert_eq!(to_java_type("Ljava/lang/String;"), "java.lang.String");
-        assert_eq!(to_java_type("[Ljava/lang/String;"), "java.lang.String[]");
-        assert_eq!(to_java_type("[[Ljava/lang/String;"), "java.lang.String[][]");
+    fn test_type_kinds() {
+        assert_eq!(
+            TypeKind::parse("V"),
+            Ok(TypeKind::Primitive(PrimitiveType::Void))
+        );
+        assert_eq!(
+            TypeKind::parse("J"),
+            Ok(TypeKind::Primitive(PrimitiveType::Long))
+        );
+        assert_eq!(TypeKind::parse("Lfoo/Bar;"), Ok(TypeKind::Class));
+        assert_eq!(
+            TypeKind::parse("[[Lfoo/Bar;"),
+            Ok(TypeKind::Array {
+                dims: 2,
+                element: ElementKind::Class
+            })
+        );
+        let deepest = format!("{}Z", "[".repeat(MAX_ARRAY_DIMENSIONS));
+        assert_eq!(
+            TypeKind::parse(&deepest),
+            Ok(TypeKind::Array {
+                dims: MAX_ARRAY_DIMENSIONS,
+                element: ElementKind::Primitive(PrimitiveType::Boolean)
+            })
+        );
+        let too_deep = format!("[{}", deepest);
+        assert!(TypeKind::parse(&too_deep).is_err());
+        for primitive in ["Z", "B", "S", "C", "I", "J", "F", "D", "V"] {
+            let kind = PrimitiveType::from_descriptor(primitive).unwrap();
+            assert_eq!(kind.descriptor(), primitive);
+        }
+    }
+
+    #[test]
+    fn test_invalid_type_kinds() {
+        for (descriptor, position) in [("X", 0), ("Q;", 0), ("", 0), ("[x", 1), ("[V", 1)] {
+            let error = TypeKind::parse(descriptor).unwrap_err();
+            assert_eq!(error.position(), position, "{:?}", descriptor);
+        }
+        // without the check, only the shape of the descriptor is looked at
+        assert_eq!(TypeKind::classify("La.b;"), TypeKind::Class);
+        assert_eq!(
+            TypeKind::classify("[[La.b;"),
+            TypeKind::Array {
+                dims: 2,
+                element: ElementKind::Class
+            }
+        );
+        for descriptor in ["X", "Q;", "", "L;", "Lfoo/Bar", "[x", "[[", "foo;"] {
+            assert_eq!(
+                TypeKind::classify(descriptor),
+                TypeKind::Invalid,
+                "{:?}",
+                descriptor
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_java_name() {
+        use super::to_java_name;
+        assert_eq!(to_java_name(super::BOOLEAN), "boolean");
+        assert_eq!(to_java_name(super::BYTE), "byte");
+        assert_eq!(to_java_name(super::SHORT), "short");
+        assert_eq!(to_java_name(super::CHAR), "char");
+        assert_eq!(to_java_name(super::INT), "int");
+        assert_eq!(to_java_name(super::LONG), "long");
+        assert_eq!(to_java_name(super::FLOAT), "float");
+        assert_eq!(to_java_name(super::DOUBLE), "double");
+        assert_eq!(to_java_name(super::VOID), "void");
+        assert_eq!(to_java_name("Ljava/lang/String;"), "java.lang.String");
+        assert_eq!(to_java_name("[Ljava/lang/String;"), "java.lang.String[]");
+        assert_eq!(to_java_name("[[Ljava/lang/String;"), "java.lang.String[][]");
+        assert_eq!(to_java_name("[[I"), "int[][]");
+        assert_eq!(to_java_name("Ljava/util/Map$Entry;"), "java.util.Map$Entry");
+        // descriptors which are only read with lenient checks
+        assert_eq!(to_java_name("X"), "X");
+        assert_eq!(to_java_name("[Lfoo"), "Lfoo[]");
+        assert_eq!(to_java_name(""), "");
+    }
+
+    #[test]
+    fn test_java_names_and_component_types() {
+        let dex = crate::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut arrays = 0;
+        for id in 0..dex.header().type_ids_size() {
+            let jtype = dex.get_type(id).expect("bad type");
+            let name = jtype.to_java_name();
+            match jtype.component_type() {
+                Some(component) => {
+                    arrays += 1;
+                    assert_eq!(name, format!("{}[]", super::to_java_name(component)));
+                    assert_eq!(
+                        component.starts_with('['),
+                        jtype.array_dimensions() > Some(1)
+                    );
+                }
+                None => {
+                    assert!(!jtype.is_array() && !name.ends_with("[]"));
+                    assert!(!name.contains('/') && !name.contains(';'), "{}", name);
+                }
+            }
+        }
+        assert!(arrays > 0);
+        let jtype = dex
+            .get_type_from_descriptor("[Ljava/lang/String;")
+            .unwrap()
+            .unwrap();
+        assert!(jtype.is_array());
+        assert_eq!(jtype.array_dimensions(), Some(1));
+        assert_eq!(jtype.component_type(), Some("Ljava/lang/String;"));
+        assert_eq!(jtype.to_java_name(), "java.lang.String[]");
     }
 }
@@ -0,0 +1,188 @@
+//! Detection of LEB128 values encoded with more bytes than needed.
+//!
+//! A value can be LEB128 encoded with any number of extra continuation bytes. Tools never
+//! produce such encodings, but some protectors do as parsers tend to disagree on them.
+use std::convert::TryFrom;
+
+use getset::CopyGetters;
+use scroll::{Pread, Uleb128};
+
+use crate::{error::Error, uint, Result};
+
+/// Reads a uleb128 encoded index into one of the pools at `offset`. Indices are 32-bit
+/// but uleb128 values can be wider: those are rejected instead of truncated, which would
+/// silently refer to another item.
+pub(crate) fn read_uleb128_index(source: &[u8], offset: &mut usize) -> Result<uint> {
+    let value = Uleb128::read(source, offset)?;
+    uint::try_from(value)
+        .map_err(|_| Error::InvalidId(format!("Index {} doesn't fit in 32 bits", value)))
+}
+
+/// A LEB128 value encoded with more bytes than its minimal encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct LebSite {
+    /// Offset of the value from the start of the file.
+    offset: usize,
+    /// Length of the encoding in the file.
+    encoded_len: usize,
+    /// Length of the minimal encoding of the value.
+    minimal_len: usize,
+    /// `true` for a signed (sleb128) value.
+    signed: bool,
+}
+
+/// Reads LEB128 values from `source`, recording the ones that are not minimally encoded.
+pub(crate) struct LebScanner<'a> {
+    source: &'a [u8],
+    sites: Vec<LebSite>,
+    /// See `Dex::log_label`.
+    log_label: Option<&'a str>,
+}
+
+impl<'a> LebScanner<'a> {
+    pub(crate) fn new(source: &'a [u8], log_label: Option<&'a str>) -> Self {
+        Self {
+            source,
+            sites: Vec::new(),
+            log_label,
+        }
+    }
+
+    /// Reads an unsigned value at `offset`, advancing it past the value.
+    pub(crate) fn uleb128(&mut self, offset: &mut usize) -> Result<u64> {
+        let start = *offset;
+        let (value, len) = self.read(offset)?;
+        let minimal_len = minimal_len(64 - value.leading_zeros());
+        self.record(start, len, minimal_len, false);
+        Ok(value)
+    }
+
+    /// Reads a signed value at `offset`, advancing it past the value.
+    pub(crate) fn sleb128(&mut self, offset: &mut usize) -> Result<i64> {
+        let start = *offset;
+        let (raw, len) = self.read(offset)?;
+        let shift = (7 * len).min(64) as u32;
+        let value = if shift < 64 && raw & (1 << (shift - 1)) != 0 {
+            (raw | (u64::MAX << shift)) as i64
+        } else {
+            raw as i64
+        };
+        // the sign bit has to be encoded as well
+        let bits = 65
+            - if value < 0 {
+                value.leading_ones()
+            } else {
+                value.leading_zeros()
+            };
+        self.record(start, len, minimal_len(bits), true);
+        Ok(value)
+    }
+
+    /// Reads the byte at `offset`, advancing it past the byte.
+    pub(crate) fn byte(&self, offset: &mut usize) -> Result<u8> {
+        Ok(self.source.gread(offset)?)
+    }
+
+    /// Non-minimal encodings found so far.
+    pub(crate) fn into_sites(self) -> Vec<LebSite> {
+        self.sites
+    }
+
+    fn read(&self, offset: &mut usize) -> Result<(u64, usize)> {
+        let start = *offset;
+        let mut value = 0u64;
+        // dex files only use LEB128 for 32 bit values, which take at most 5 bytes
+        for len in 1..=5 {
+            let byte = *self.source.get(*offset).ok_or_else(|| {
+                Error::BadOffset(start, "LEB128 value runs past the end of the file".into())
+            })?;
+            *offset += 1;
+            value |= u64::from(byte & 0x7f) << (7 * (len - 1));
+            if byte & 0x80 == 0 {
+                return Ok((value, len));
+            }
+        }
+        Err(Error::MalFormed(format!(
+            "LEB128 value at {} is longer than 5 bytes",
+            start
+        )))
+    }
+
+    fn record(&mut self, offset: usize, len: usize, minimal_len: usize, signed: bool) {
+        if len > minimal_len {
+            dex_debug!(self.log_label, target: "leb128", "non-minimal encoding at {}: {} bytes instead of {}", offset, len, minimal_len);
+            self.sites.push(LebSite {
+                offset,
+                encoded_len: len,
+                minimal_len,
+                signed,
+            });
+        }
+    }
+}
+
+/// Number of bytes needed to encode a value of `bits` significant bits.
+fn minimal_len(bits: u32) -> usize {
+    (bits.max(1) as usize).div_ceil(7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_uleb128_index, LebScanner};
+    use crate::ErrorKind;
+
+    fn scan(bytes: &[u8], signed: bool) -> (i64, Option<usize>) {
+        let mut scanner = LebScanner::new(bytes, None);
+        let offset = &mut 0;
+        let value = if signed {
+            scanner.sleb128(offset).expect("bad sleb128")
+        } else {
+            scanner.uleb128(offset).expect("bad uleb128") as i64
+        };
+        assert_eq!(*offset, bytes.len());
+        let sites = scanner.into_sites();
+        assert!(sites.iter().all(|site| site.signed() == signed));
+        (value, sites.first().map(|site| site.minimal_len()))
+    }
+
+    #[test]
+    fn test_minimal_encodings() {
+        assert_eq!(scan(&[0x00], false), (0, None));
+        assert_eq!(scan(&[0x7f], false), (127, None));
+        assert_eq!(scan(&[0x80, 0x01], false), (128, None));
+        assert_eq!(
+            scan(&[0xff, 0xff, 0xff, 0xff, 0x0f], false),
+            (0xffff_ffff, None)
+        );
+        assert_eq!(scan(&[0x7f], true), (-1, None));
+        assert_eq!(scan(&[0x3f], true), (63, None));
+        assert_eq!(scan(&[0xc0, 0x00], true), (64, None));
+        assert_eq!(scan(&[0x80, 0x7f], true), (-128, None));
+    }
+
+    #[test]
+    fn test_padded_encodings() {
+        assert_eq!(scan(&[0x80, 0x00], false), (0, Some(1)));
+        assert_eq!(scan(&[0x81, 0x80, 0x00], false), (1, Some(1)));
+        assert_eq!(scan(&[0x80, 0x81, 0x00], false), (128, Some(2)));
+        assert_eq!(scan(&[0xff, 0x7f], true), (-1, Some(1)));
+        assert_eq!(scan(&[0xbf, 0x00], true), (63, Some(1)));
+    }
+
+    #[test]
+    fn test_uleb128_index() {
+        let read = |bytes: &[u8]| read_uleb128_index(bytes, &mut 0);
+        assert_eq!(read(&[0xff, 0xff, 0x03]).unwrap(), 0xffff);
+        assert_eq!(read(&[0x80, 0x80, 0x04]).unwrap(), 0x1_0000);
+        assert_eq!(read(&[0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap(), u32::MAX);
+        // 2^32 and 2^32 + 1 would wrap to 0 and 1
+        for bytes in [
+            &[0x80, 0x80, 0x80, 0x80, 0x10],
+            &[0x81, 0x80, 0x80, 0x80, 0x10],
+        ] {
+            let error = read(bytes).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidId);
+        }
+    }
+}
@@ -1,7 +1,19 @@
 //! Dex is a library for reading Android's
 //! [dex](https://source.android.com/devices/tech/dalvik/dex-format) file format.
+//!
+//! # Stable hashes
+//!
+//! `StaticValuesSpan::bytes_hash`, `AnnotationItem::canonical_hash` and
+//! `AnnotationSetItem::content_hash` are 64-bit
+//! [FNV-1a](http://www.isthe.com/chongo/tech/comp/fnv/index.html) hashes, with integers
+//! written little endian. They only depend on the bytes hashed, so they can be stored and
+//! compared between runs, platforms and versions of this crate. They are fingerprints,
+//! not digests: contents crafted to collide are easy to find.
 // Silence warnings in error module for now
 #![allow(bare_trait_objects)]
+// Only the `mmap` module may use unsafe code, and without the `mmap` feature nothing may.
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
 
 pub extern crate scroll;
 
@@ -11,31 +23,103 @@ extern crate scroll_derive;
 #[macro_use]
 extern crate bitflags;
 
-#[macro_use]
-extern crate log;
-
 extern crate getset;
 
-pub use error::Error;
+pub use error::{Error, ErrorKind};
+
+pub use crate::{
+    cache::{CacheConfig, CacheStats, CacheUsage, CacheWeight},
+    dex::{
+        Dex, DexReader, DexReaderBuilder, DexStats, DexVersion, EagerSections, FileSource, Header,
+        ItemType, MapItem, MapItemType, MapList, VerifyMode,
+    },
+    encoded_item::{EncodedItem, EncodedItemArray},
+    leb::LebSite,
+    limits::ParserLimits,
+    snapshot::SnapshotKinds,
+    source::DexSource,
+};
+
+#[cfg(feature = "parse-timing")]
+pub use crate::timing::ParseTimings;
 
-pub use crate::dex::{Dex, DexReader, Header};
+#[cfg(feature = "cache-diagnostics")]
+pub use crate::cache::{CacheKind, CacheMismatch};
 
 #[macro_use]
 mod utils;
+pub mod alignment;
 pub mod annotation;
+#[cfg(feature = "rayon")]
+pub mod batch;
+mod bounds;
+#[cfg(feature = "decoder")]
+pub mod bytecode;
 mod cache;
+#[cfg(feature = "decoder")]
+pub mod cfg;
 pub mod class;
+pub mod class_kind;
+#[cfg(feature = "analysis")]
+pub mod clinit;
 pub mod code;
+pub mod container;
 mod dex;
+#[cfg(feature = "annotations-extras")]
+mod dexdump;
+pub mod diagnostic;
 mod encoded_item;
 pub mod encoded_value;
 mod error;
+#[cfg(feature = "analysis")]
+pub mod export;
+#[cfg(feature = "analysis")]
+pub mod features;
 pub mod field;
+#[cfg(feature = "analysis")]
+pub mod fingerprint;
+#[cfg(feature = "analysis")]
+pub mod indicator;
 pub mod jtype;
+mod leb;
+mod limits;
+#[cfg(feature = "analysis")]
+pub mod marker;
 pub mod method;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "test-util")]
+pub mod mock;
+mod mutf8;
+#[cfg(feature = "decoder")]
+pub mod opcode;
+pub mod operation;
+#[cfg(feature = "analysis")]
+mod permission;
+pub mod prelude;
+#[cfg(feature = "profile")]
+pub mod profile;
+#[cfg(feature = "decoder")]
+pub mod pseudocode;
 mod search;
-mod source;
+pub mod sharing;
+#[cfg(feature = "decoder")]
+mod smali;
+#[cfg(feature = "annotations-extras")]
+pub mod smap;
+mod snapshot;
+pub mod source;
 pub mod string;
+pub mod sync;
+#[cfg(test)]
+mod test_utils;
+mod timing;
+#[cfg(feature = "writer")]
+pub mod transform;
+#[cfg(feature = "analysis")]
+pub mod usage;
+pub mod validate;
+pub mod well_known;
 
 /// The constant NO_INDEX is used to indicate that an index value is absent.
 pub const NO_INDEX: uint = 0xffff_ffff;
@@ -0,0 +1,70 @@
+//! Limits on the size of items, to bound the cost of parsing crafted files.
+
+/// Limits checked while parsing, see `DexReaderBuilder::limits`. Items exceeding a limit fail
+/// to parse with `Error::LimitExceeded`. The defaults are the largest values allowed by the
+/// format, so that no well formed file is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    pub(crate) max_tries: usize,
+    pub(crate) max_handler_types: usize,
+    pub(crate) max_payload_entries: usize,
+    pub(crate) max_insns_units: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_tries: u16::MAX as usize,
+            max_handler_types: u16::MAX as usize,
+            max_payload_entries: u32::MAX as usize,
+            max_insns_units: u32::MAX as usize,
+        }
+    }
+}
+
+impl ParserLimits {
+    /// Returns the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of try blocks of a code item. Defaults to 65535.
+    pub fn max_tries(mut self, max: usize) -> Self {
+        self.max_tries = max;
+        self
+    }
+
+    /// Maximum number of exception types of a catch handler. Defaults to 65535, the
+    /// number of types a file can have.
+    pub fn max_handler_types(mut self, max: usize) -> Self {
+        self.max_handler_types = max;
+        self
+    }
+
+    /// Maximum number of entries of a switch payload or elements of a fill-array-data
+    /// payload read from the instructions of a method, see `CodeItem::resolve_switch` and
+    /// `CodeItem::fill_array_data`. Defaults to 4294967295, the largest count a payload
+    /// can declare.
+    pub fn max_payload_entries(mut self, max: usize) -> Self {
+        self.max_payload_entries = max;
+        self
+    }
+
+    /// Maximum number of 16-bit code units of the instructions of a code item. Defaults to
+    /// 4294967295, the largest size a code item can declare.
+    pub fn max_insns_units(mut self, max: usize) -> Self {
+        self.max_insns_units = max;
+        self
+    }
+
+    /// Returns an error if `value` exceeds `max`.
+    pub(crate) fn check(limit: &str, value: usize, max: usize) -> crate::Result<()> {
+        if value > max {
+            return Err(crate::error::Error::LimitExceeded(format!(
+                "{} {} exceeds the limit of {}",
+                limit, value, max
+            )));
+        }
+        Ok(())
+    }
+}
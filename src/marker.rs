@@ -0,0 +1,161 @@
+//! The marker strings which D8, R8 and L8 add to the string pool, see
+//! `Dex::compiler_markers`.
+//!
+//! A marker is `~~` followed by the name of the tool and a JSON object of its options, for
+//! example `~~R8{"backend":"dex","compilation-mode":"release","min-api":24,"version":"8.2.42"}`.
+//! The JSON is parsed with the `json` feature, otherwise only the raw string and the tool
+//! are available.
+use getset::{CopyGetters, Getters};
+
+/// The prefix of the marker strings.
+pub(crate) const MARKER_PREFIX: &str = "~~";
+
+/// A marker string of the tool which produced the file.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct CompilerMarker {
+    /// The whole marker string, including the `~~` prefix.
+    #[get = "pub"]
+    raw: String,
+    /// The name of the tool, between the prefix and the JSON object, such as `D8`, `R8` or
+    /// `L8`.
+    #[get = "pub"]
+    tool: String,
+    /// The `version` of the tool, such as `8.2.42`.
+    #[get = "pub"]
+    version: Option<String>,
+    /// The `min-api` the file was compiled for.
+    #[get_copy = "pub"]
+    min_api: Option<u32>,
+    /// The `compilation-mode`, `debug` or `release`.
+    #[get = "pub"]
+    compilation_mode: Option<String>,
+    /// The `backend` of R8, `dex` or `cf`.
+    #[get = "pub"]
+    backend: Option<String>,
+    /// The other members of the JSON object, and the known ones whose value doesn't have
+    /// the expected type, such as `has-checksums`, `pg-map-id` or `r8-mode`.
+    #[cfg(feature = "json")]
+    #[get = "pub"]
+    extra: serde_json::Map<String, serde_json::Value>,
+    /// Why the JSON object could not be parsed, in which case only `raw` and `tool` are
+    /// set. Always `None` without the `json` feature, which leaves the JSON unparsed.
+    #[get = "pub"]
+    parse_error: Option<String>,
+}
+
+impl CompilerMarker {
+    /// Parses a marker string. Returns `None` if the string doesn't start with `~~`. A
+    /// string with a malformed JSON object is returned with its `parse_error` set.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let rest = raw.strip_prefix(MARKER_PREFIX)?;
+        let (tool, json) = match rest.find('{') {
+            Some(start) => (&rest[..start], Some(&rest[start..])),
+            None => (rest, None),
+        };
+        let mut marker = Self {
+            raw: raw.to_string(),
+            tool: tool.to_string(),
+            version: None,
+            min_api: None,
+            compilation_mode: None,
+            backend: None,
+            #[cfg(feature = "json")]
+            extra: serde_json::Map::new(),
+            parse_error: None,
+        };
+        match json {
+            Some(json) => marker.parse_json(json),
+            None => marker.parse_error = Some("no JSON object after the tool".to_string()),
+        }
+        Some(marker)
+    }
+
+    #[cfg(feature = "json")]
+    fn parse_json(&mut self, json: &str) {
+        use serde_json::Value;
+
+        let members = match serde_json::from_str(json) {
+            Ok(Value::Object(members)) => members,
+            Ok(_) => {
+                self.parse_error = Some("the JSON value is not an object".to_string());
+                return;
+            }
+            Err(e) => {
+                self.parse_error = Some(e.to_string());
+                return;
+            }
+        };
+        for (key, value) in members {
+            match (key.as_str(), value) {
+                ("version", Value::String(value)) => self.version = Some(value),
+                ("compilation-mode", Value::String(value)) => self.compilation_mode = Some(value),
+                ("backend", Value::String(value)) => self.backend = Some(value),
+                ("min-api", Value::Number(value))
+                    if value.as_u64().is_some_and(|api| api <= u64::from(u32::MAX)) =>
+                {
+                    self.min_api = value.as_u64().map(|api| api as u32)
+                }
+                (_, value) => {
+                    self.extra.insert(key, value);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn parse_json(&mut self, _json: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompilerMarker;
+
+    const D8: &str = r#"~~D8{"compilation-mode":"debug","has-checksums":false,"min-api":14,"version":"1.5.13-q1"}"#;
+    const OLD_D8: &str = r#"~~D8{"compilation-mode":"release","min-api":21,"version":"1.2.48"}"#;
+    const R8: &str = r#"~~R8{"backend":"dex","compilation-mode":"release","has-checksums":false,"min-api":24,"pg-map-id":"8a1b6c3","r8-mode":"full","version":"8.2.42"}"#;
+
+    #[test]
+    fn test_parse_tool() {
+        for (raw, tool) in &[(D8, "D8"), (OLD_D8, "D8"), (R8, "R8")] {
+            let marker = CompilerMarker::parse(raw).expect("not a marker");
+            assert_eq!(marker.raw(), raw);
+            assert_eq!(marker.tool(), tool);
+            assert_eq!(marker.parse_error(), &None);
+        }
+        assert!(CompilerMarker::parse("~R8{}").is_none());
+        let bare = CompilerMarker::parse("~~L8").expect("not a marker");
+        assert_eq!(bare.tool(), "L8");
+        assert!(bare.parse_error().is_some());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_parse_json() {
+        let d8 = CompilerMarker::parse(D8).expect("not a marker");
+        assert_eq!(d8.version().as_deref(), Some("1.5.13-q1"));
+        assert_eq!(d8.min_api(), Some(14));
+        assert_eq!(d8.compilation_mode().as_deref(), Some("debug"));
+        assert_eq!(d8.backend(), &None);
+        assert_eq!(d8.extra().get("has-checksums"), Some(&false.into()));
+
+        let r8 = CompilerMarker::parse(R8).expect("not a marker");
+        assert_eq!(r8.version().as_deref(), Some("8.2.42"));
+        assert_eq!(r8.min_api(), Some(24));
+        assert_eq!(r8.compilation_mode().as_deref(), Some("release"));
+        assert_eq!(r8.backend().as_deref(), Some("dex"));
+        let extra: Vec<&str> = r8.extra().keys().map(|key| &**key).collect();
+        assert_eq!(extra, ["has-checksums", "pg-map-id", "r8-mode"]);
+
+        // a known member with an unexpected type is kept in `extra`
+        let odd = CompilerMarker::parse(r#"~~D8{"min-api":"21"}"#).expect("not a marker");
+        assert_eq!(odd.min_api(), None);
+        assert_eq!(odd.extra().get("min-api"), Some(&"21".into()));
+
+        for malformed in &[r#"~~R8{"version":"8.2.42""#, "~~D8{version}", "~~D8{}[]"] {
+            let marker = CompilerMarker::parse(malformed).expect("not a marker");
+            assert_eq!(marker.tool(), &malformed[2..4]);
+            assert!(marker.parse_error().is_some(), "{}", malformed);
+            assert_eq!(marker.version(), &None);
+        }
+    }
+}
@@ -1,17 +1,23 @@
 //! Dex `Method` and supporting structures
+use std::fmt;
+
 use getset::{CopyGetters, Getters};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use scroll::{ctx, Pread, Uleb128};
 
+#[cfg(feature = "debug-info")]
+use crate::code::{Locals, Parameter};
 use crate::{
-    annotation::{AnnotationSetItem, AnnotationSetRefList},
-    code::CodeItem,
+    annotation::{Annotated, AnnotationSetItem, AnnotationSetRefList, ClassAnnotations},
+    code::{CodeItem, ExceptionType},
     encoded_item::{EncodedItem, EncodedItemArray},
     error::Error,
     field::FieldId,
     jtype::{Type, TypeId},
+    source::DexSource,
     string::{DexString, StringId},
+    sync::Shared,
     uint, ulong, ushort, utils,
 };
 
@@ -35,29 +41,52 @@ bitflags! {
     }
 }
 
+impl_access_flags!(
+    ulong,
+    PUBLIC => "public",
+    PRIVATE => "private",
+    PROTECTED => "protected",
+    STATIC => "static",
+    FINAL => "final",
+    SYNCHRONIZED => "synchronized",
+    BRIDGE => "bridge",
+    VARARGS => "varargs",
+    NATIVE => "native",
+    ABSTRACT => "abstract",
+    STRICT => "strictfp",
+    SYNTHETIC => "synthetic",
+    CONSTRUCTOR => "constructor",
+    DECLARED_SYNCHRONIZED => "declared-synchronized",
+);
+
 /// Represents a `Class` method.
-#[derive(Debug)]
+#[derive(Debug, Getters, CopyGetters)]
 pub struct Method {
     /// Parent class of the method.
+    #[get = "pub"]
     pub class: Type,
     /// Name of the method.
+    #[get = "pub"]
     pub name: DexString,
     /// Access flags of the method.
+    #[get_copy = "pub"]
     pub access_flags: AccessFlags,
     /// Types of the parameters of the method.
+    #[get = "pub"]
     pub params: Vec<Type>,
     /// Shorty descriptor of the method, as described
     /// [here](https://source.android.com/devices/tech/dalvik/dex-format#shortydescriptor)
+    #[get = "pub"]
     pub shorty: DexString,
     /// Return type of the method.
+    #[get = "pub"]
     pub return_type: Type,
     /// Code and DebugInfo of the method.
     pub code: Option<CodeItem>,
-    /// Annotations of the method.
-    pub annotations: AnnotationSetItem,
-    /// Annotations of the params.
-    pub param_annotations: AnnotationSetRefList,
+    /// Annotations of the method and its params.
+    pub(crate) annotations: Shared<ClassAnnotations>,
     /// `MethodId` of the method.
+    #[get_copy = "pub"]
     pub id: MethodId,
 }
 
@@ -79,13 +108,117 @@ impl Method {
 
     /// Returns the value of `dalvik.annotation.Signature`.
     pub fn signature(&self) -> super::Result<Option<String>> {
-        utils::get_signature(&self.annotations)
+        utils::get_signature(self.annotations()?)
+    }
+
+    /// Annotations of the method. Read on first access unless the `Dex` was read with
+    /// eager annotations.
+    pub fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        self.annotations.method(self.id)
+    }
+
+    /// Annotations of the params. Read on first access unless the `Dex` was read with
+    /// eager annotations.
+    pub fn param_annotations(&self) -> super::Result<&AnnotationSetRefList> {
+        self.annotations.parameters(self.id)
+    }
+
+    /// The parameters of the method with their annotations. A parameter annotations list
+    /// shorter than the parameters leaves the trailing ones without annotations, the sets
+    /// of a longer one after the last parameter are dropped and logged, see
+    /// `AlignedParameterAnnotations`.
+    pub fn parameters(&self) -> super::Result<Vec<MethodParameter<'_>>> {
+        let aligned = self
+            .param_annotations()?
+            .aligned_to(self.id, self.params.len());
+        if !aligned.extras().is_empty() {
+            if let Some(diagnostic) = aligned.diagnostic() {
+                dex_warn!(target: "annotations", "{}", diagnostic);
+            }
+        }
+        Ok(self
+            .params
+            .iter()
+            .zip(aligned)
+            .map(|(jtype, annotations)| MethodParameter { jtype, annotations })
+            .collect())
+    }
+
+    /// Types of the parameters of the method, empty if it takes none. The same as
+    /// `params`, as a slice.
+    pub fn parameter_types(&self) -> &[Type] {
+        &self.params
+    }
+
+    /// Descriptor of the method in JNI notation, its parameter types in parentheses
+    /// followed by its return type, for example `(Ljava/lang/String;I)V`.
+    pub fn descriptor(&self) -> String {
+        proto_descriptor(&self.params, &self.return_type)
     }
 
     /// Code and DebugInfo of the method.
     pub fn code(&self) -> Option<&CodeItem> {
         self.code.as_ref()
     }
+
+    /// The distinct types of the exceptions caught by the method, see
+    /// `CodeItem::caught_exception_types`. Empty if the method has no code.
+    pub fn caught_exception_types(&self) -> Vec<ExceptionType> {
+        self.code
+            .as_ref()
+            .map(CodeItem::caught_exception_types)
+            .unwrap_or_default()
+    }
+
+    /// Local variables of the method, including `this` and the named parameters. `None` if
+    /// the method has no code or no debug info.
+    #[cfg(feature = "debug-info")]
+    pub fn locals(&self) -> Option<Locals> {
+        let code = self.code.as_ref()?;
+        let debug_info = code.debug_info_item()?;
+        // the parameters are in the last registers, `this` first
+        let mut register = ulong::from(code.registers_size.saturating_sub(code.ins_size));
+        let mut parameters = Vec::with_capacity(self.params.len() + 1);
+        if !self.is_static() {
+            parameters.push(Parameter {
+                register,
+                name: DexString::from("this".to_string()),
+                jtype: self.class.clone(),
+            });
+            register += 1;
+        }
+        for (index, jtype) in self.params.iter().enumerate() {
+            if let Some(Some(name)) = debug_info.parameter_names().get(index) {
+                parameters.push(Parameter {
+                    register,
+                    name: name.clone(),
+                    jtype: jtype.clone(),
+                });
+            }
+            register += if jtype.is_long() || jtype.is_double() {
+                2
+            } else {
+                1
+            };
+        }
+        Some(debug_info.locals(&parameters, code.insns.len() as ulong))
+    }
+}
+
+/// A parameter of a `Method`, see `Method::parameters`.
+#[derive(Debug, Clone, Copy, CopyGetters)]
+#[get_copy = "pub"]
+pub struct MethodParameter<'a> {
+    /// Type of the parameter.
+    jtype: &'a Type,
+    /// Annotations of the parameter, empty if it has none.
+    annotations: &'a AnnotationSetItem,
+}
+
+impl Annotated for Method {
+    fn annotations(&self) -> super::Result<&AnnotationSetItem> {
+        Method::annotations(self)
+    }
 }
 
 /// Index into the `ProtoId`s list.
@@ -93,7 +226,7 @@ pub type ProtoId = ulong;
 
 /// Method Prototypes.
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#proto-id-item)
-#[derive(Pread, Debug, CopyGetters, PartialEq)]
+#[derive(Pread, Debug, Clone, CopyGetters, PartialEq)]
 #[get_copy = "pub"]
 pub struct ProtoIdItem {
     /// Index into the string_ids list for the short-form descriptor string of this prototype
@@ -106,66 +239,80 @@ pub struct ProtoIdItem {
 }
 
 impl ProtoIdItem {
-    pub(crate) fn try_from_dex<S: AsRef<[u8]>>(
+    pub(crate) fn try_from_dex<S: DexSource>(
         dex: &super::Dex<S>,
         offset: ulong,
     ) -> super::Result<Self> {
-        let source = dex.source.as_ref();
-        Ok(source.pread_with(offset as usize, dex.get_endian())?)
+        Ok(dex.source.pread_with(offset as usize, dex.get_endian())?)
+    }
+}
+
+/// A prototype with its shorty, return type and parameter types resolved.
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[get = "pub"]
+pub struct Proto {
+    /// Shorty descriptor of the prototype, as described
+    /// [here](https://source.android.com/devices/tech/dalvik/dex-format#shortydescriptor)
+    shorty: DexString,
+    /// Return type of the prototype.
+    return_type: Type,
+    /// Types of the parameters, empty if the prototype has no type list.
+    params: Vec<Type>,
+}
+
+impl Proto {
+    /// Number of parameters of the prototype.
+    pub fn param_count(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Descriptor of the prototype in JNI notation, for example `(Ljava/lang/String;I)V`.
+    pub fn descriptor(&self) -> String {
+        proto_descriptor(&self.params, &self.return_type)
+    }
+
+    pub(crate) fn try_from_dex<S: DexSource>(
+        dex: &super::Dex<S>,
+        proto_item: &ProtoIdItem,
+    ) -> super::Result<Self> {
+        Ok(Self {
+            shorty: dex.get_string(proto_item.shorty)?,
+            return_type: dex.get_type(proto_item.return_type)?,
+            params: dex.get_proto_params(proto_item)?,
+        })
     }
 }
 
 impl Method {
-    pub(crate) fn try_from_dex<S: AsRef<[u8]>>(
+    pub(crate) fn try_from_dex<S: DexSource>(
         dex: &super::Dex<S>,
         encoded_method: &EncodedMethod,
-        annotations: AnnotationSetItem,
-        param_annotations: AnnotationSetRefList,
+        annotations: Shared<ClassAnnotations>,
     ) -> super::Result<Method> {
-        debug!(target: "method", "encoded method: {:?}", encoded_method);
-        let source = &dex.source;
+        dex_debug!(dex.log_label(), target: "method", "encoded method: {:?}", encoded_method);
         let method_item = dex.get_method_item(encoded_method.method_id)?;
         let name = dex.get_string(method_item.name_idx)?;
-        debug!(target: "method", "name: {}, method id item: {:?}", name, method_item);
+        dex_debug!(dex.log_label(), target: "method", "name: {}, method id item: {:?}", name, method_item);
         let proto_item = dex.get_proto_item(ProtoId::from(method_item.proto_idx))?;
-        debug!(target: "method", "method proto_item: {:?}", proto_item);
+        dex_debug!(dex.log_label(), target: "method", "method proto_item: {:?}", proto_item);
         let shorty = dex.get_string(proto_item.shorty)?;
         let return_type = dex.get_type(proto_item.return_type)?;
-        let params = if proto_item.params_off != 0 {
-            if !dex.is_offset_in_data_section(proto_item.params_off) {
-                return Err(Error::BadOffset(
-                    proto_item.params_off as usize,
-                    format!(
-                        "Params offset not in data section for proto_item: {:?}",
-                        proto_item
-                    ),
-                ));
-            }
-            let offset = &mut (proto_item.params_off as usize);
-            let endian = dex.get_endian();
-            let len = source.gread_with::<uint>(offset, endian)?;
-            let type_ids: Vec<ushort> = try_gread_vec_with!(source, offset, len, endian);
-            utils::get_types(dex, &type_ids)?
+        let params = dex.get_proto_params(&proto_item)?;
+        dex_debug!(dex.log_label(), target: "method", "code item offset: {}", encoded_method.code_offset);
+        let code = if dex.skip_code() {
+            None
         } else {
-            Default::default()
+            dex.get_code_item(encoded_method.code_offset)?
         };
-        debug!(target: "method", "code item offset: {}", encoded_method.code_offset);
-        let code = dex.get_code_item(encoded_method.code_offset)?;
         Ok(Self {
             name,
             class: dex.get_type(TypeId::from(method_item.class_idx))?,
-            access_flags: AccessFlags::from_bits(encoded_method.access_flags).ok_or_else(|| {
-                Error::InvalidId(format!(
-                    "Invalid access flags for method {}",
-                    method_item.name_idx
-                ))
-            })?,
+            access_flags: AccessFlags::from_bits_retain(encoded_method.access_flags),
             shorty,
             return_type,
             params,
             code,
             annotations,
-            param_annotations,
             id: encoded_method.method_id,
         })
     }
@@ -194,7 +341,7 @@ pub struct MethodIdItem {
 }
 
 impl MethodIdItem {
-    pub(crate) fn try_from_dex<S: AsRef<[u8]>>(
+    pub(crate) fn try_from_dex<S: DexSource>(
         dex: &super::Dex<S>,
         offset: ulong,
         method_id: MethodId,
@@ -210,7 +357,65 @@ impl MethodIdItem {
     }
 }
 
-/// Index into the `MethodId`s list.
+/// A method reference from the `MethodId`s list with its class, name and prototype resolved.
+/// Unlike `Method`, this doesn't require the method to be defined in the `Dex`.
+#[derive(Debug, Clone, Getters, CopyGetters, PartialEq)]
+pub struct MethodRef {
+    /// `MethodId` of the method.
+    #[get_copy = "pub"]
+    id: MethodId,
+    /// Class which defines the method.
+    #[get = "pub"]
+    class: Type,
+    /// Name of the method.
+    #[get = "pub"]
+    name: DexString,
+    /// Prototype descriptor of the method, for example `(Ljava/lang/String;I)V`.
+    #[get = "pub"]
+    proto: String,
+}
+
+impl MethodRef {
+    pub(crate) fn try_from_dex<S: DexSource>(
+        dex: &super::Dex<S>,
+        method_item: &MethodIdItem,
+    ) -> super::Result<Self> {
+        let proto_item = dex.get_proto_item(ProtoId::from(method_item.proto_idx))?;
+        let proto = proto_descriptor(
+            &dex.get_proto_params(&proto_item)?,
+            &dex.get_type(proto_item.return_type)?,
+        );
+        Ok(Self {
+            id: method_item.id,
+            class: dex.get_type(TypeId::from(method_item.class_idx))?,
+            name: dex.get_string(method_item.name_idx)?,
+            proto,
+        })
+    }
+}
+
+/// Builds the JNI descriptor of a prototype: `(` then the descriptors of the parameters,
+/// `)` and the descriptor of the return type.
+pub(crate) fn proto_descriptor(params: &[Type], return_type: &Type) -> String {
+    let mut descriptor = String::from("(");
+    for param in params {
+        descriptor.push_str(param.type_descriptor());
+    }
+    descriptor.push(')');
+    descriptor.push_str(return_type.type_descriptor());
+    descriptor
+}
+
+/// Formats the method in smali notation, for example
+/// `Ljava/lang/Object;->equals(Ljava/lang/Object;)Z`.
+impl fmt::Display for MethodRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}->{}{}", self.class, self.name, self.proto)
+    }
+}
+
+/// Index into the `MethodId`s list. ART profiles name a method by the checksum of its dex
+/// file and this index.
 pub type MethodId = ulong;
 
 /// Index into the `MethodHandleItem`s list.
@@ -295,7 +500,7 @@ pub struct MethodHandleItem {
     id: FieldOrMethodId,
 }
 
-impl<'a, S: AsRef<[u8]>> ctx::TryFromCtx<'a, &super::Dex<S>> for MethodHandleItem {
+impl<'a, S: DexSource> ctx::TryFromCtx<'a, &super::Dex<S>> for MethodHandleItem {
     type Error = Error;
     type Size = usize;
 
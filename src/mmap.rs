@@ -0,0 +1,44 @@
+//! Memory mapping of dex files, with the `mmap` feature.
+//!
+//! This is the only module of the crate allowed to use `unsafe`: the rest of the crate
+//! forbids it, see the attributes of the crate root.
+#![allow(unsafe_code)]
+
+use std::{fs::File, path::Path};
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::Result;
+
+/// Maps the file at `path` read-only into memory.
+pub(crate) fn map_file(path: &Path) -> Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the mapping is read-only and only ever viewed as a `&[u8]`. Mapping a file is
+    // unsafe because another process may truncate or modify it while it is mapped, which
+    // is undefined behavior for a `&[u8]` and may raise SIGBUS on truncation. The crate
+    // can't prevent that; callers reading files that may change concurrently should use
+    // `DexReader::from_vec` with the contents read into memory, or disable the `mmap`
+    // feature.
+    let map = unsafe { MmapOptions::new().map(&file)? };
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::map_file;
+
+    // Miri can't map files, so the mapping is only tested natively.
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_map_file() {
+        let path = Path::new("resources/classes.dex");
+        let map = map_file(path).expect("cannot map file");
+        assert_eq!(
+            &map[..],
+            &std::fs::read(path).expect("cannot read file")[..]
+        );
+        assert!(map_file(Path::new("resources/missing.dex")).is_err());
+    }
+}
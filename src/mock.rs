@@ -0,0 +1,470 @@
+//! In-memory construction of classes, fields and methods, with the `test-util` feature, to
+//! unit test code that takes them as input without a dex file:
+//!
+//! ```
+//! use dex::{annotation::Annotated, mock::{ClassBuilder, MethodBuilder}};
+//!
+//! # fn main() -> dex::Result<()> {
+//! let class = ClassBuilder::new("Lcom/example/Foo;")
+//!     .method(MethodBuilder::new("run", &[], "V").annotation("Lcom/example/Slow;"))
+//!     .build();
+//! let method = class.methods().next().unwrap();
+//! assert!(method.has_annotation("Lcom/example/Slow;")?);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The values are not tied to any file. The ids of types are assigned in order of first
+//! use by a builder, the ids of fields and methods in order of addition to their class,
+//! and the raw indices of strings are `NO_INDEX`.
+use std::collections::HashMap;
+
+use crate::{
+    annotation::{
+        AnnotationItem, AnnotationSetItem, AnnotationSetRefList, AnnotationsDirectoryItem,
+        ClassAnnotations, EncodedAnnotation, FieldAnnotations, MethodAnnotations,
+        ParameterAnnotations, Visibility,
+    },
+    class::{self, Class},
+    code::CodeItem,
+    encoded_value::EncodedValue,
+    field::{self, Field, FieldId},
+    jtype::{Type, TypeId, TypeKind},
+    method::{self, Method, MethodId},
+    string::DexString,
+    sync::Shared,
+    well_known,
+};
+
+/// Assigns ids to type descriptors, in order of first use.
+#[derive(Default)]
+struct Types {
+    ids: HashMap<String, TypeId>,
+}
+
+impl Types {
+    fn get(&mut self, descriptor: &str) -> Type {
+        let next = self.ids.len() as TypeId;
+        let id = *self.ids.entry(descriptor.to_string()).or_insert(next);
+        Type::new(
+            id,
+            DexString::from(descriptor.to_string()),
+            TypeKind::classify(descriptor),
+        )
+    }
+}
+
+/// A type outside of any file, whose id is `NO_INDEX`, for `EncodedAnnotation::new`.
+pub fn jtype(descriptor: &str) -> Type {
+    Type::new(
+        crate::NO_INDEX,
+        DexString::from(descriptor.to_string()),
+        TypeKind::classify(descriptor),
+    )
+}
+
+/// An annotation without elements, visible at runtime.
+pub fn annotation(descriptor: &str) -> AnnotationItem {
+    AnnotationItem::new(
+        Visibility::Runtime,
+        EncodedAnnotation::new(jtype(descriptor), Vec::new()),
+    )
+}
+
+/// Builds a `Field`, see `ClassBuilder::field`.
+#[derive(Debug)]
+pub struct FieldBuilder {
+    name: String,
+    descriptor: String,
+    access_flags: field::AccessFlags,
+    initial_value: Option<EncodedValue>,
+    annotations: Vec<AnnotationItem>,
+}
+
+impl FieldBuilder {
+    /// A public instance field of the type with the `descriptor`.
+    pub fn new(name: &str, descriptor: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            descriptor: descriptor.to_string(),
+            access_flags: field::AccessFlags::PUBLIC,
+            initial_value: None,
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Sets the access flags. Fields with `STATIC` are static fields of their class.
+    pub fn access_flags(mut self, access_flags: field::AccessFlags) -> Self {
+        self.access_flags = access_flags;
+        self
+    }
+
+    /// Sets the initial value of a static field.
+    pub fn initial_value(mut self, value: EncodedValue) -> Self {
+        self.initial_value = Some(value);
+        self
+    }
+
+    /// Adds an annotation without elements, visible at runtime.
+    pub fn annotation(self, descriptor: &str) -> Self {
+        self.annotation_item(annotation(descriptor))
+    }
+
+    /// Adds an annotation.
+    pub fn annotation_item(mut self, annotation: AnnotationItem) -> Self {
+        self.annotations.push(annotation);
+        self
+    }
+}
+
+/// Builds a `Method`, see `ClassBuilder::method`.
+#[derive(Debug)]
+pub struct MethodBuilder {
+    name: String,
+    params: Vec<String>,
+    return_type: String,
+    access_flags: method::AccessFlags,
+    code: Option<CodeItem>,
+    annotations: Vec<AnnotationItem>,
+    parameter_annotations: Vec<AnnotationSetItem>,
+}
+
+impl MethodBuilder {
+    /// A public virtual method with the descriptors of its parameters and return type,
+    /// without code.
+    pub fn new(name: &str, params: &[&str], return_type: &str) -> Self {
+        let mut access_flags = method::AccessFlags::PUBLIC;
+        if name == well_known::CONSTRUCTOR || name == well_known::STATIC_INITIALIZER {
+            access_flags |= method::AccessFlags::CONSTRUCTOR;
+        }
+        Self {
+            name: name.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            return_type: return_type.to_string(),
+            access_flags,
+            code: None,
+            annotations: Vec::new(),
+            parameter_annotations: Vec::new(),
+        }
+    }
+
+    /// Sets the access flags. Static, private and constructor methods are direct methods of
+    /// their class, the others virtual methods.
+    pub fn access_flags(mut self, access_flags: method::AccessFlags) -> Self {
+        self.access_flags = access_flags;
+        self
+    }
+
+    /// Sets the code of the method.
+    pub fn code(mut self, code: CodeItem) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Adds an annotation without elements, visible at runtime.
+    pub fn annotation(self, descriptor: &str) -> Self {
+        self.annotation_item(annotation(descriptor))
+    }
+
+    /// Adds an annotation.
+    pub fn annotation_item(mut self, annotation: AnnotationItem) -> Self {
+        self.annotations.push(annotation);
+        self
+    }
+
+    /// Sets the annotations of the parameters, one set per parameter.
+    pub fn parameter_annotations(mut self, annotations: Vec<AnnotationSetItem>) -> Self {
+        self.parameter_annotations = annotations;
+        self
+    }
+
+    /// Builds the method alone, as a method of the class with the `class` descriptor.
+    pub fn build(self, class: &str) -> Method {
+        let mut directory = AnnotationsDirectoryItem::default();
+        let mut types = Types::default();
+        let class = types.get(class);
+        let method = self.build_in(&class, 0, &mut types, &mut directory);
+        let annotations = Shared::new(ClassAnnotations::loaded(0, Shared::new(directory)));
+        Method {
+            annotations,
+            ..method
+        }
+    }
+
+    fn is_direct(&self) -> bool {
+        self.access_flags.intersects(
+            method::AccessFlags::STATIC
+                | method::AccessFlags::PRIVATE
+                | method::AccessFlags::CONSTRUCTOR,
+        )
+    }
+
+    /// Builds the method, adding its annotations to `directory`. The method gets the
+    /// annotations of `directory` once it is complete.
+    fn build_in(
+        self,
+        class: &Type,
+        id: MethodId,
+        types: &mut Types,
+        directory: &mut AnnotationsDirectoryItem,
+    ) -> Method {
+        let shorty = std::iter::once(&self.return_type)
+            .chain(&self.params)
+            .map(|descriptor| match &descriptor[..1] {
+                "[" => 'L',
+                c => c.chars().next().unwrap(),
+            })
+            .collect::<String>();
+        if !self.annotations.is_empty() {
+            directory.method_annotations.push(MethodAnnotations {
+                method_idx: id,
+                annotations: AnnotationSetItem::new(self.annotations),
+            });
+        }
+        if !self.parameter_annotations.is_empty() {
+            directory.parameter_annotations.push(ParameterAnnotations {
+                method_idx: id,
+                annotations: AnnotationSetRefList::new(self.parameter_annotations),
+            });
+        }
+        Method {
+            class: class.clone(),
+            name: DexString::from(self.name),
+            access_flags: self.access_flags,
+            params: self.params.iter().map(|param| types.get(param)).collect(),
+            shorty: DexString::from(shorty),
+            return_type: types.get(&self.return_type),
+            code: self.code,
+            annotations: Shared::new(ClassAnnotations::loaded(0, Shared::default())),
+            id,
+        }
+    }
+}
+
+/// Builds a `Class` with its fields and methods.
+#[derive(Debug)]
+pub struct ClassBuilder {
+    descriptor: String,
+    access_flags: class::AccessFlags,
+    super_class: Option<String>,
+    interfaces: Vec<String>,
+    source_file: Option<String>,
+    annotations: Vec<AnnotationItem>,
+    fields: Vec<FieldBuilder>,
+    methods: Vec<MethodBuilder>,
+}
+
+impl ClassBuilder {
+    /// A public class extending `java.lang.Object`.
+    pub fn new(descriptor: &str) -> Self {
+        Self {
+            descriptor: descriptor.to_string(),
+            access_flags: class::AccessFlags::PUBLIC,
+            super_class: Some(well_known::OBJECT.to_string()),
+            interfaces: Vec::new(),
+            source_file: None,
+            annotations: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+        }
+    }
+
+    /// Sets the access flags.
+    pub fn access_flags(mut self, access_flags: class::AccessFlags) -> Self {
+        self.access_flags = access_flags;
+        self
+    }
+
+    /// Sets the super class, `None` for `java.lang.Object` itself.
+    pub fn super_class(mut self, descriptor: Option<&str>) -> Self {
+        self.super_class = descriptor.map(str::to_string);
+        self
+    }
+
+    /// Adds an implemented interface.
+    pub fn interface(mut self, descriptor: &str) -> Self {
+        self.interfaces.push(descriptor.to_string());
+        self
+    }
+
+    /// Sets the name of the source file.
+    pub fn source_file(mut self, name: &str) -> Self {
+        self.source_file = Some(name.to_string());
+        self
+    }
+
+    /// Adds an annotation without elements, visible at runtime.
+    pub fn annotation(self, descriptor: &str) -> Self {
+        self.annotation_item(annotation(descriptor))
+    }
+
+    /// Adds an annotation.
+    pub fn annotation_item(mut self, annotation: AnnotationItem) -> Self {
+        self.annotations.push(annotation);
+        self
+    }
+
+    /// Adds a field.
+    pub fn field(mut self, field: FieldBuilder) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /// Adds a method.
+    pub fn method(mut self, method: MethodBuilder) -> Self {
+        self.methods.push(method);
+        self
+    }
+
+    /// Builds the class.
+    pub fn build(self) -> Class {
+        let mut types = Types::default();
+        let jtype = types.get(&self.descriptor);
+        let superclass = self
+            .super_class
+            .as_ref()
+            .map(|descriptor| types.get(descriptor));
+        let interfaces = self
+            .interfaces
+            .iter()
+            .map(|descriptor| types.get(descriptor))
+            .collect();
+        let mut directory = AnnotationsDirectoryItem {
+            class_annotations: AnnotationSetItem::new(self.annotations),
+            ..Default::default()
+        };
+
+        let mut fields = Vec::new();
+        for (id, builder) in self.fields.into_iter().enumerate() {
+            let id = id as FieldId;
+            if !builder.annotations.is_empty() {
+                directory.field_annotations.push(FieldAnnotations {
+                    field_idx: id,
+                    annotations: AnnotationSetItem::new(builder.annotations),
+                });
+            }
+            fields.push(Field {
+                name: DexString::from(builder.name),
+                jtype: types.get(&builder.descriptor),
+                class: jtype.id,
+                access_flags: builder.access_flags,
+                initial_value: builder.initial_value,
+                annotations: Shared::new(ClassAnnotations::loaded(0, Shared::default())),
+                id,
+            });
+        }
+        let mut methods = Vec::new();
+        for (id, builder) in self.methods.into_iter().enumerate() {
+            let direct = builder.is_direct();
+            let method = builder.build_in(&jtype, id as MethodId, &mut types, &mut directory);
+            methods.push((direct, method));
+        }
+
+        // every member shares the annotations of the class, as when read from a file
+        let annotations = Shared::new(ClassAnnotations::loaded(0, Shared::new(directory)));
+        let (static_fields, instance_fields) = fields
+            .into_iter()
+            .map(|field| Field {
+                annotations: annotations.clone(),
+                ..field
+            })
+            .partition(|field| field.access_flags.contains(field::AccessFlags::STATIC));
+        let (direct_methods, virtual_methods): (Vec<_>, Vec<_>) = methods
+            .into_iter()
+            .map(|(direct, method)| {
+                let method = Method {
+                    annotations: annotations.clone(),
+                    ..method
+                };
+                (direct, method)
+            })
+            .partition(|(direct, _)| *direct);
+        Class {
+            id: jtype.id,
+            jtype,
+            access_flags: self.access_flags,
+            super_class: superclass.as_ref().map(|jtype| jtype.id),
+            superclass,
+            interfaces,
+            source_file: self.source_file.map(DexString::from),
+            static_fields,
+            instance_fields,
+            direct_methods: direct_methods.into_iter().map(|(_, m)| m).collect(),
+            virtual_methods: virtual_methods.into_iter().map(|(_, m)| m).collect(),
+            annotations,
+            parse_completeness: Default::default(),
+            static_values_span: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{jtype, ClassBuilder, FieldBuilder, MethodBuilder};
+    use crate::{
+        annotation::{Annotated, AnnotationElement, AnnotationItem, EncodedAnnotation, Visibility},
+        encoded_value::EncodedValue,
+        field,
+        method::AccessFlags,
+        string::DexString,
+    };
+
+    #[test]
+    fn test_class_builder() {
+        let test = AnnotationItem::new(
+            Visibility::Runtime,
+            EncodedAnnotation::new(
+                jtype("Lorg/junit/Test;"),
+                vec![AnnotationElement::new("timeout", EncodedValue::Long(100))],
+            ),
+        );
+        let class = ClassBuilder::new("Lcom/example/FooTest;")
+            .annotation("Lorg/junit/runner/RunWith;")
+            .field(
+                FieldBuilder::new("TAG", "Ljava/lang/String;")
+                    .access_flags(field::AccessFlags::STATIC | field::AccessFlags::FINAL),
+            )
+            .method(MethodBuilder::new("<init>", &[], "V"))
+            .method(MethodBuilder::new("testA", &[], "V").annotation_item(test))
+            .method(
+                MethodBuilder::new("testB", &["I", "[Ljava/lang/String;"], "Z")
+                    .access_flags(AccessFlags::PUBLIC | AccessFlags::STATIC)
+                    .annotation("Lorg/junit/Test;")
+                    .annotation("Lorg/junit/Ignore;"),
+            )
+            .build();
+
+        assert!(class.has_annotation("Lorg/junit/runner/RunWith;").unwrap());
+        assert_eq!(class.static_fields().len(), 1);
+        assert!(!class.static_fields()[0]
+            .has_annotation("Lorg/junit/Test;")
+            .unwrap());
+        let tests: Vec<&DexString> = class
+            .methods()
+            .filter(|method| method.has_annotation("Lorg/junit/Test;").unwrap())
+            .map(|method| method.name())
+            .collect();
+        assert_eq!(tests, ["testB", "testA"]);
+        assert_eq!(class.direct_methods().len(), 2);
+        let test_b = &class.direct_methods()[1];
+        assert_eq!(test_b.shorty(), "ZIL");
+        assert!(test_b
+            .find_annotation("Lorg/junit/Ignore;")
+            .unwrap()
+            .is_some());
+        let test_a = &class.virtual_methods()[0];
+        let timeout = test_a
+            .find_annotation("Lorg/junit/Test;")
+            .unwrap()
+            .and_then(|annotation| annotation.find_element("timeout"))
+            .map(|element| element.value());
+        assert_eq!(timeout, Some(&EncodedValue::Long(100)));
+        assert!(class.direct_methods()[0].is_constructor());
+
+        let method = MethodBuilder::new("run", &[], "V")
+            .annotation("Ljava/lang/Deprecated;")
+            .build("Lcom/example/Foo;");
+        assert!(method.has_annotation("Ljava/lang/Deprecated;").unwrap());
+        assert_eq!(method.class(), "Lcom/example/Foo;");
+    }
+}
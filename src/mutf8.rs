@@ -0,0 +1,205 @@
+//! Decoding of the MUTF-8 strings of the string_data section.
+//!
+//! MUTF-8 is UTF-8 where the null character is encoded in two bytes, `0xc0 0x80`, and the
+//! characters outside the Basic Multilingual Plane are encoded as a surrogate pair, each
+//! surrogate in three bytes. Decoding follows the checks of the verifier of ART: a
+//! continuation byte must follow each lead byte, the only overlong encoding allowed is
+//! the one of the null character, and four-byte sequences are not allowed.
+//! [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#mutf-8)
+use std::{borrow::Cow, fmt};
+
+use crate::error::Error;
+
+/// Why a sequence of bytes can't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mutf8Error {
+    /// The byte can't start a character.
+    InvalidLeadByte(u8),
+    /// The byte following a lead byte is not a continuation byte.
+    InvalidContinuationByte(u8),
+    /// The string ends in the middle of a character.
+    Truncated,
+    /// The character is encoded with more bytes than needed.
+    Overlong(u32),
+    /// A surrogate which is not part of a pair, which a Rust string can't hold.
+    UnpairedSurrogate(u32),
+}
+
+impl fmt::Display for Mutf8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mutf8Error::InvalidLeadByte(byte) => write!(f, "invalid lead byte {:#04x}", byte),
+            Mutf8Error::InvalidContinuationByte(byte) => {
+                write!(f, "invalid continuation byte {:#04x}", byte)
+            }
+            Mutf8Error::Truncated => write!(f, "truncated character"),
+            Mutf8Error::Overlong(value) => write!(f, "overlong encoding of U+{:04X}", value),
+            Mutf8Error::UnpairedSurrogate(value) => write!(f, "unpaired surrogate U+{:04X}", value),
+        }
+    }
+}
+
+/// Decodes `bytes`, borrowing them when they are valid UTF-8 with the same meaning.
+/// `offset` is the offset of the bytes in the file, reported in the error.
+pub(crate) fn decode(bytes: &[u8], offset: usize) -> crate::Result<Cow<'_, str>> {
+    if let Some(string) = as_utf8(bytes) {
+        return Ok(Cow::Borrowed(string));
+    }
+    let mut string = String::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match decode_char(&bytes[index..]) {
+            Ok((c, len)) => {
+                string.push(c);
+                index += len;
+            }
+            Err((error, _)) => {
+                return Err(Error::MalFormed(format!(
+                    "Invalid MUTF-8 string at offset {}: {}",
+                    offset + index,
+                    error
+                )))
+            }
+        }
+    }
+    Ok(Cow::Owned(string))
+}
+
+/// Decodes `bytes`, replacing each sequence which can't be decoded with U+FFFD.
+pub(crate) fn decode_lossy(bytes: &[u8]) -> Cow<'_, str> {
+    if let Some(string) = as_utf8(bytes) {
+        return Cow::Borrowed(string);
+    }
+    let mut string = String::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let (c, len) = decode_char(&bytes[index..])
+            .unwrap_or_else(|(_, len)| (char::REPLACEMENT_CHARACTER, len));
+        string.push(c);
+        index += len;
+    }
+    Cow::Owned(string)
+}
+
+/// `bytes` as a string if they are UTF-8 without four-byte sequences, which is then
+/// valid MUTF-8 with the same meaning: UTF-8 rejects the surrogates and the overlong
+/// encodings, including the one of the null character.
+fn as_utf8(bytes: &[u8]) -> Option<&str> {
+    if bytes.iter().any(|byte| *byte >= 0xf0) {
+        return None;
+    }
+    std::str::from_utf8(bytes).ok()
+}
+
+/// Decodes the character at the start of `bytes`, which must not be empty, and returns it
+/// with the length of its encoding. On error, returns the number of bytes to skip.
+fn decode_char(bytes: &[u8]) -> Result<(char, usize), (Mutf8Error, usize)> {
+    let (value, len) = decode_unit(bytes)?;
+    if !(0xd800..0xe000).contains(&value) {
+        // not a surrogate, so a valid scalar value
+        return Ok((char::from_u32(value).unwrap_or_default(), len));
+    }
+    if value < 0xdc00 {
+        if let Ok((low, low_len)) = decode_unit(&bytes[len..]) {
+            if (0xdc00..0xe000).contains(&low) {
+                let value = 0x10000 + ((value - 0xd800) << 10) + (low - 0xdc00);
+                return Ok((char::from_u32(value).unwrap_or_default(), len + low_len));
+            }
+        }
+    }
+    Err((Mutf8Error::UnpairedSurrogate(value), len))
+}
+
+/// Decodes the UTF-16 code unit at the start of `bytes`, which must not be empty, and
+/// returns it with the length of its encoding.
+fn decode_unit(bytes: &[u8]) -> Result<(u32, usize), (Mutf8Error, usize)> {
+    let lead = match bytes.first() {
+        Some(lead) => *lead,
+        None => return Err((Mutf8Error::Truncated, 0)),
+    };
+    let (len, mut value, min) = match lead {
+        0x00..=0x7f => return Ok((u32::from(lead), 1)),
+        0xc0..=0xdf => (2, u32::from(lead & 0x1f), 0x80),
+        0xe0..=0xef => (3, u32::from(lead & 0x0f), 0x800),
+        _ => return Err((Mutf8Error::InvalidLeadByte(lead), 1)),
+    };
+    for index in 1..len {
+        match bytes.get(index) {
+            Some(byte) if byte & 0xc0 == 0x80 => value = (value << 6) | u32::from(byte & 0x3f),
+            Some(byte) => return Err((Mutf8Error::InvalidContinuationByte(*byte), index)),
+            None => return Err((Mutf8Error::Truncated, index)),
+        }
+    }
+    if (value < min && value != 0) || (value == 0 && len == 3) {
+        return Err((Mutf8Error::Overlong(value), len));
+    }
+    Ok((value, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, decode_lossy};
+
+    #[test]
+    fn test_decode() {
+        let valid: &[(&[u8], &str)] = &[
+            (b"Launcher", "Launcher"),
+            (b"", ""),
+            (b"a\xc0\x80b", "a\0b"),
+            (b"\xc3\xa9t\xc3\xa9", "été"),
+            (b"\xe2\x82\xac", "€"),
+            // U+1F600 as the surrogate pair D83D DE00
+            (b"<\xed\xa0\xbd\xed\xb8\x80>", "<\u{1f600}>"),
+        ];
+        for (bytes, string) in valid {
+            assert_eq!(decode(bytes, 0).unwrap(), *string);
+            assert_eq!(decode_lossy(bytes), *string);
+        }
+
+        let invalid: &[(&[u8], usize, &str, &str)] = &[
+            (
+                b"ab\xc3A",
+                102,
+                "invalid continuation byte 0x41",
+                "ab\u{fffd}A",
+            ),
+            (b"ab\xe2\x82", 102, "truncated character", "ab\u{fffd}"),
+            (b"\x80a", 100, "invalid lead byte 0x80", "\u{fffd}a"),
+            // four-byte UTF-8 encoding of U+1F600
+            (
+                b"a\xf0\x9f\x98\x80",
+                101,
+                "invalid lead byte 0xf0",
+                "a\u{fffd}\u{fffd}\u{fffd}\u{fffd}",
+            ),
+            (b"\xc1\x81", 100, "overlong encoding of U+0041", "\u{fffd}"),
+            (
+                b"\xe0\x80\x80",
+                100,
+                "overlong encoding of U+0000",
+                "\u{fffd}",
+            ),
+            (
+                b"a\xed\xa0\xbdb",
+                101,
+                "unpaired surrogate U+D83D",
+                "a\u{fffd}b",
+            ),
+            (
+                b"\xed\xb8\x80",
+                100,
+                "unpaired surrogate U+DE00",
+                "\u{fffd}",
+            ),
+        ];
+        for (bytes, offset, reason, lossy) in invalid {
+            let error = decode(bytes, 100).unwrap_err().to_string();
+            assert!(
+                error.contains(&format!("at offset {}: {}", offset, reason)),
+                "{}",
+                error
+            );
+            assert_eq!(decode_lossy(bytes), *lossy);
+        }
+    }
+}
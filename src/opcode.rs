@@ -0,0 +1,869 @@
+//! Dalvik opcodes and the formats of their instructions.
+//! [Android docs](https://source.android.com/devices/tech/dalvik/dalvik-bytecode)
+//!
+//! `OPCODES` is the single source of truth for opcodes: instruction lengths and the
+//! properties of decoded instructions are all looked up in it.
+use std::convert::TryFrom;
+
+use crate::{
+    code::{FILL_ARRAY_DATA_PAYLOAD, PACKED_SWITCH_PAYLOAD, SPARSE_SWITCH_PAYLOAD},
+    error::Error,
+    field::FieldId,
+    jtype::TypeId,
+    method::{MethodHandleId, MethodId, ProtoId},
+    string::StringId,
+    uint, ushort, Result,
+};
+
+/// Instruction formats, named after the ids used in the Dalvik documentation: the number
+/// of code units, the number of registers and the kind of extra data.
+/// [Android docs](https://source.android.com/devices/tech/dalvik/instruction-formats)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    F10x,
+    F12x,
+    F11n,
+    F11x,
+    F10t,
+    F20t,
+    F22x,
+    F21t,
+    F21s,
+    F21h,
+    F21c,
+    F23x,
+    F22b,
+    F22t,
+    F22s,
+    F22c,
+    F32x,
+    F30t,
+    F31t,
+    F31i,
+    F31c,
+    F35c,
+    F3rc,
+    F45cc,
+    F4rcc,
+    F51l,
+}
+
+impl Format {
+    /// Number of code units of instructions of this format.
+    pub const fn units(self) -> usize {
+        match self {
+            Format::F10x | Format::F12x | Format::F11n | Format::F11x | Format::F10t => 1,
+            Format::F20t
+            | Format::F22x
+            | Format::F21t
+            | Format::F21s
+            | Format::F21h
+            | Format::F21c
+            | Format::F23x
+            | Format::F22b
+            | Format::F22t
+            | Format::F22s
+            | Format::F22c => 2,
+            Format::F32x
+            | Format::F30t
+            | Format::F31t
+            | Format::F31i
+            | Format::F31c
+            | Format::F35c
+            | Format::F3rc => 3,
+            Format::F45cc | Format::F4rcc => 4,
+            Format::F51l => 5,
+        }
+    }
+
+    /// Name of the format in the Dalvik documentation, for example `22c`.
+    pub fn id(self) -> &'static str {
+        match self {
+            Format::F10x => "10x",
+            Format::F12x => "12x",
+            Format::F11n => "11n",
+            Format::F11x => "11x",
+            Format::F10t => "10t",
+            Format::F20t => "20t",
+            Format::F22x => "22x",
+            Format::F21t => "21t",
+            Format::F21s => "21s",
+            Format::F21h => "21h",
+            Format::F21c => "21c",
+            Format::F23x => "23x",
+            Format::F22b => "22b",
+            Format::F22t => "22t",
+            Format::F22s => "22s",
+            Format::F22c => "22c",
+            Format::F32x => "32x",
+            Format::F30t => "30t",
+            Format::F31t => "31t",
+            Format::F31i => "31i",
+            Format::F31c => "31c",
+            Format::F35c => "35c",
+            Format::F3rc => "3rc",
+            Format::F45cc => "45cc",
+            Format::F4rcc => "4rcc",
+            Format::F51l => "51l",
+        }
+    }
+}
+
+/// Kind of item an instruction refers to by index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    /// The instruction has no index operand.
+    None,
+    /// Unused opcode.
+    Unknown,
+    StringRef,
+    TypeRef,
+    FieldRef,
+    MethodRef,
+    /// A method and a prototype, used by `invoke-polymorphic`.
+    MethodAndProtoRef,
+    CallSiteRef,
+    MethodHandleRef,
+    ProtoRef,
+}
+
+bitflags! {
+    /// Control flow and other properties of an opcode.
+    pub struct OpcodeFlags: u8 {
+        /// Execution can continue with the next instruction.
+        const CONTINUE = 0x1;
+        /// The instruction can throw an exception.
+        const THROW = 0x2;
+        /// The instruction branches to a target.
+        const BRANCH = 0x4;
+        /// The instruction branches through a switch payload.
+        const SWITCH = 0x8;
+        /// The instruction returns from the method.
+        const RETURN = 0x10;
+        /// The instruction invokes a method.
+        const INVOKE = 0x20;
+        /// The opcode is not used.
+        const UNUSED = 0x0;
+    }
+}
+
+/// Properties of an opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    /// Value of the opcode, the low byte of the first code unit of an instruction.
+    pub value: u8,
+    /// Name of the opcode in the Dalvik documentation, for example `invoke-virtual/range`.
+    pub mnemonic: &'static str,
+    /// Format of instructions with this opcode.
+    pub format: Format,
+    /// Kind of item referred to by the index operand.
+    pub index: IndexKind,
+    /// Control flow and other properties.
+    pub flags: OpcodeFlags,
+}
+
+impl Opcode {
+    /// Returns `true` if the opcode is not used.
+    pub fn is_unused(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// Returns `true` if instructions with this opcode can throw an exception.
+    pub fn can_throw(&self) -> bool {
+        self.flags.contains(OpcodeFlags::THROW)
+    }
+
+    /// Returns `true` if instructions with this opcode branch to a target, unconditionally,
+    /// conditionally or through a switch.
+    pub fn is_branch(&self) -> bool {
+        self.flags
+            .intersects(OpcodeFlags::BRANCH | OpcodeFlags::SWITCH)
+    }
+
+    /// Returns `true` if execution can continue with the next instruction.
+    pub fn can_continue(&self) -> bool {
+        self.flags.contains(OpcodeFlags::CONTINUE)
+    }
+}
+
+macro_rules! opcodes {
+    ($($value:literal => $mnemonic:literal, $format:ident, $index:ident, $($flag:ident)|+;)*) => {
+        /// All the opcodes, indexed by value.
+        pub static OPCODES: [Opcode; 256] = TABLE;
+
+        const TABLE: [Opcode; 256] = [$(
+            Opcode {
+                value: $value,
+                mnemonic: $mnemonic,
+                format: Format::$format,
+                index: IndexKind::$index,
+                flags: OpcodeFlags::from_bits_truncate($(OpcodeFlags::$flag.bits())|+),
+            },
+        )*];
+    };
+}
+
+opcodes! {
+    0x00 => "nop", F10x, None, CONTINUE;
+    0x01 => "move", F12x, None, CONTINUE;
+    0x02 => "move/from16", F22x, None, CONTINUE;
+    0x03 => "move/16", F32x, None, CONTINUE;
+    0x04 => "move-wide", F12x, None, CONTINUE;
+    0x05 => "move-wide/from16", F22x, None, CONTINUE;
+    0x06 => "move-wide/16", F32x, None, CONTINUE;
+    0x07 => "move-object", F12x, None, CONTINUE;
+    0x08 => "move-object/from16", F22x, None, CONTINUE;
+    0x09 => "move-object/16", F32x, None, CONTINUE;
+    0x0a => "move-result", F11x, None, CONTINUE;
+    0x0b => "move-result-wide", F11x, None, CONTINUE;
+    0x0c => "move-result-object", F11x, None, CONTINUE;
+    0x0d => "move-exception", F11x, None, CONTINUE;
+    0x0e => "return-void", F10x, None, RETURN;
+    0x0f => "return", F11x, None, RETURN;
+    0x10 => "return-wide", F11x, None, RETURN;
+    0x11 => "return-object", F11x, None, RETURN;
+    0x12 => "const/4", F11n, None, CONTINUE;
+    0x13 => "const/16", F21s, None, CONTINUE;
+    0x14 => "const", F31i, None, CONTINUE;
+    0x15 => "const/high16", F21h, None, CONTINUE;
+    0x16 => "const-wide/16", F21s, None, CONTINUE;
+    0x17 => "const-wide/32", F31i, None, CONTINUE;
+    0x18 => "const-wide", F51l, None, CONTINUE;
+    0x19 => "const-wide/high16", F21h, None, CONTINUE;
+    0x1a => "const-string", F21c, StringRef, CONTINUE | THROW;
+    0x1b => "const-string/jumbo", F31c, StringRef, CONTINUE | THROW;
+    0x1c => "const-class", F21c, TypeRef, CONTINUE | THROW;
+    0x1d => "monitor-enter", F11x, None, CONTINUE | THROW;
+    0x1e => "monitor-exit", F11x, None, CONTINUE | THROW;
+    0x1f => "check-cast", F21c, TypeRef, CONTINUE | THROW;
+    0x20 => "instance-of", F22c, TypeRef, CONTINUE | THROW;
+    0x21 => "array-length", F12x, None, CONTINUE | THROW;
+    0x22 => "new-instance", F21c, TypeRef, CONTINUE | THROW;
+    0x23 => "new-array", F22c, TypeRef, CONTINUE | THROW;
+    0x24 => "filled-new-array", F35c, TypeRef, CONTINUE | THROW;
+    0x25 => "filled-new-array/range", F3rc, TypeRef, CONTINUE | THROW;
+    0x26 => "fill-array-data", F31t, None, CONTINUE | THROW;
+    0x27 => "throw", F11x, None, THROW;
+    0x28 => "goto", F10t, None, BRANCH;
+    0x29 => "goto/16", F20t, None, BRANCH;
+    0x2a => "goto/32", F30t, None, BRANCH;
+    0x2b => "packed-switch", F31t, None, CONTINUE | SWITCH;
+    0x2c => "sparse-switch", F31t, None, CONTINUE | SWITCH;
+    0x2d => "cmpl-float", F23x, None, CONTINUE;
+    0x2e => "cmpg-float", F23x, None, CONTINUE;
+    0x2f => "cmpl-double", F23x, None, CONTINUE;
+    0x30 => "cmpg-double", F23x, None, CONTINUE;
+    0x31 => "cmp-long", F23x, None, CONTINUE;
+    0x32 => "if-eq", F22t, None, CONTINUE | BRANCH;
+    0x33 => "if-ne", F22t, None, CONTINUE | BRANCH;
+    0x34 => "if-lt", F22t, None, CONTINUE | BRANCH;
+    0x35 => "if-ge", F22t, None, CONTINUE | BRANCH;
+    0x36 => "if-gt", F22t, None, CONTINUE | BRANCH;
+    0x37 => "if-le", F22t, None, CONTINUE | BRANCH;
+    0x38 => "if-eqz", F21t, None, CONTINUE | BRANCH;
+    0x39 => "if-nez", F21t, None, CONTINUE | BRANCH;
+    0x3a => "if-ltz", F21t, None, CONTINUE | BRANCH;
+    0x3b => "if-gez", F21t, None, CONTINUE | BRANCH;
+    0x3c => "if-gtz", F21t, None, CONTINUE | BRANCH;
+    0x3d => "if-lez", F21t, None, CONTINUE | BRANCH;
+    0x3e => "unused-3e", F10x, Unknown, UNUSED;
+    0x3f => "unused-3f", F10x, Unknown, UNUSED;
+    0x40 => "unused-40", F10x, Unknown, UNUSED;
+    0x41 => "unused-41", F10x, Unknown, UNUSED;
+    0x42 => "unused-42", F10x, Unknown, UNUSED;
+    0x43 => "unused-43", F10x, Unknown, UNUSED;
+    0x44 => "aget", F23x, None, CONTINUE | THROW;
+    0x45 => "aget-wide", F23x, None, CONTINUE | THROW;
+    0x46 => "aget-object", F23x, None, CONTINUE | THROW;
+    0x47 => "aget-boolean", F23x, None, CONTINUE | THROW;
+    0x48 => "aget-byte", F23x, None, CONTINUE | THROW;
+    0x49 => "aget-char", F23x, None, CONTINUE | THROW;
+    0x4a => "aget-short", F23x, None, CONTINUE | THROW;
+    0x4b => "aput", F23x, None, CONTINUE | THROW;
+    0x4c => "aput-wide", F23x, None, CONTINUE | THROW;
+    0x4d => "aput-object", F23x, None, CONTINUE | THROW;
+    0x4e => "aput-boolean", F23x, None, CONTINUE | THROW;
+    0x4f => "aput-byte", F23x, None, CONTINUE | THROW;
+    0x50 => "aput-char", F23x, None, CONTINUE | THROW;
+    0x51 => "aput-short", F23x, None, CONTINUE | THROW;
+    0x52 => "iget", F22c, FieldRef, CONTINUE | THROW;
+    0x53 => "iget-wide", F22c, FieldRef, CONTINUE | THROW;
+    0x54 => "iget-object", F22c, FieldRef, CONTINUE | THROW;
+    0x55 => "iget-boolean", F22c, FieldRef, CONTINUE | THROW;
+    0x56 => "iget-byte", F22c, FieldRef, CONTINUE | THROW;
+    0x57 => "iget-char", F22c, FieldRef, CONTINUE | THROW;
+    0x58 => "iget-short", F22c, FieldRef, CONTINUE | THROW;
+    0x59 => "iput", F22c, FieldRef, CONTINUE | THROW;
+    0x5a => "iput-wide", F22c, FieldRef, CONTINUE | THROW;
+    0x5b => "iput-object", F22c, FieldRef, CONTINUE | THROW;
+    0x5c => "iput-boolean", F22c, FieldRef, CONTINUE | THROW;
+    0x5d => "iput-byte", F22c, FieldRef, CONTINUE | THROW;
+    0x5e => "iput-char", F22c, FieldRef, CONTINUE | THROW;
+    0x5f => "iput-short", F22c, FieldRef, CONTINUE | THROW;
+    0x60 => "sget", F21c, FieldRef, CONTINUE | THROW;
+    0x61 => "sget-wide", F21c, FieldRef, CONTINUE | THROW;
+    0x62 => "sget-object", F21c, FieldRef, CONTINUE | THROW;
+    0x63 => "sget-boolean", F21c, FieldRef, CONTINUE | THROW;
+    0x64 => "sget-byte", F21c, FieldRef, CONTINUE | THROW;
+    0x65 => "sget-char", F21c, FieldRef, CONTINUE | THROW;
+    0x66 => "sget-short", F21c, FieldRef, CONTINUE | THROW;
+    0x67 => "sput", F21c, FieldRef, CONTINUE | THROW;
+    0x68 => "sput-wide", F21c, FieldRef, CONTINUE | THROW;
+    0x69 => "sput-object", F21c, FieldRef, CONTINUE | THROW;
+    0x6a => "sput-boolean", F21c, FieldRef, CONTINUE | THROW;
+    0x6b => "sput-byte", F21c, FieldRef, CONTINUE | THROW;
+    0x6c => "sput-char", F21c, FieldRef, CONTINUE | THROW;
+    0x6d => "sput-short", F21c, FieldRef, CONTINUE | THROW;
+    0x6e => "invoke-virtual", F35c, MethodRef, CONTINUE | THROW | INVOKE;
+    0x6f => "invoke-super", F35c, MethodRef, CONTINUE | THROW | INVOKE;
+    0x70 => "invoke-direct", F35c, MethodRef, CONTINUE | THROW | INVOKE;
+    0x71 => "invoke-static", F35c, MethodRef, CONTINUE | THROW | INVOKE;
+    0x72 => "invoke-interface", F35c, MethodRef, CONTINUE | THROW | INVOKE;
+    0x73 => "unused-73", F10x, Unknown, UNUSED;
+    0x74 => "invoke-virtual/range", F3rc, MethodRef, CONTINUE | THROW | INVOKE;
+    0x75 => "invoke-super/range", F3rc, MethodRef, CONTINUE | THROW | INVOKE;
+    0x76 => "invoke-direct/range", F3rc, MethodRef, CONTINUE | THROW | INVOKE;
+    0x77 => "invoke-static/range", F3rc, MethodRef, CONTINUE | THROW | INVOKE;
+    0x78 => "invoke-interface/range", F3rc, MethodRef, CONTINUE | THROW | INVOKE;
+    0x79 => "unused-79", F10x, Unknown, UNUSED;
+    0x7a => "unused-7a", F10x, Unknown, UNUSED;
+    0x7b => "neg-int", F12x, None, CONTINUE;
+    0x7c => "not-int", F12x, None, CONTINUE;
+    0x7d => "neg-long", F12x, None, CONTINUE;
+    0x7e => "not-long", F12x, None, CONTINUE;
+    0x7f => "neg-float", F12x, None, CONTINUE;
+    0x80 => "neg-double", F12x, None, CONTINUE;
+    0x81 => "int-to-long", F12x, None, CONTINUE;
+    0x82 => "int-to-float", F12x, None, CONTINUE;
+    0x83 => "int-to-double", F12x, None, CONTINUE;
+    0x84 => "long-to-int", F12x, None, CONTINUE;
+    0x85 => "long-to-float", F12x, None, CONTINUE;
+    0x86 => "long-to-double", F12x, None, CONTINUE;
+    0x87 => "float-to-int", F12x, None, CONTINUE;
+    0x88 => "float-to-long", F12x, None, CONTINUE;
+    0x89 => "float-to-double", F12x, None, CONTINUE;
+    0x8a => "double-to-int", F12x, None, CONTINUE;
+    0x8b => "double-to-long", F12x, None, CONTINUE;
+    0x8c => "double-to-float", F12x, None, CONTINUE;
+    0x8d => "int-to-byte", F12x, None, CONTINUE;
+    0x8e => "int-to-char", F12x, None, CONTINUE;
+    0x8f => "int-to-short", F12x, None, CONTINUE;
+    0x90 => "add-int", F23x, None, CONTINUE;
+    0x91 => "sub-int", F23x, None, CONTINUE;
+    0x92 => "mul-int", F23x, None, CONTINUE;
+    0x93 => "div-int", F23x, None, CONTINUE | THROW;
+    0x94 => "rem-int", F23x, None, CONTINUE | THROW;
+    0x95 => "and-int", F23x, None, CONTINUE;
+    0x96 => "or-int", F23x, None, CONTINUE;
+    0x97 => "xor-int", F23x, None, CONTINUE;
+    0x98 => "shl-int", F23x, None, CONTINUE;
+    0x99 => "shr-int", F23x, None, CONTINUE;
+    0x9a => "ushr-int", F23x, None, CONTINUE;
+    0x9b => "add-long", F23x, None, CONTINUE;
+    0x9c => "sub-long", F23x, None, CONTINUE;
+    0x9d => "mul-long", F23x, None, CONTINUE;
+    0x9e => "div-long", F23x, None, CONTINUE | THROW;
+    0x9f => "rem-long", F23x, None, CONTINUE | THROW;
+    0xa0 => "and-long", F23x, None, CONTINUE;
+    0xa1 => "or-long", F23x, None, CONTINUE;
+    0xa2 => "xor-long", F23x, None, CONTINUE;
+    0xa3 => "shl-long", F23x, None, CONTINUE;
+    0xa4 => "shr-long", F23x, None, CONTINUE;
+    0xa5 => "ushr-long", F23x, None, CONTINUE;
+    0xa6 => "add-float", F23x, None, CONTINUE;
+    0xa7 => "sub-float", F23x, None, CONTINUE;
+    0xa8 => "mul-float", F23x, None, CONTINUE;
+    0xa9 => "div-float", F23x, None, CONTINUE;
+    0xaa => "rem-float", F23x, None, CONTINUE;
+    0xab => "add-double", F23x, None, CONTINUE;
+    0xac => "sub-double", F23x, None, CONTINUE;
+    0xad => "mul-double", F23x, None, CONTINUE;
+    0xae => "div-double", F23x, None, CONTINUE;
+    0xaf => "rem-double", F23x, None, CONTINUE;
+    0xb0 => "add-int/2addr", F12x, None, CONTINUE;
+    0xb1 => "sub-int/2addr", F12x, None, CONTINUE;
+    0xb2 => "mul-int/2addr", F12x, None, CONTINUE;
+    0xb3 => "div-int/2addr", F12x, None, CONTINUE | THROW;
+    0xb4 => "rem-int/2addr", F12x, None, CONTINUE | THROW;
+    0xb5 => "and-int/2addr", F12x, None, CONTINUE;
+    0xb6 => "or-int/2addr", F12x, None, CONTINUE;
+    0xb7 => "xor-int/2addr", F12x, None, CONTINUE;
+    0xb8 => "shl-int/2addr", F12x, None, CONTINUE;
+    0xb9 => "shr-int/2addr", F12x, None, CONTINUE;
+    0xba => "ushr-int/2addr", F12x, None, CONTINUE;
+    0xbb => "add-long/2addr", F12x, None, CONTINUE;
+    0xbc => "sub-long/2addr", F12x, None, CONTINUE;
+    0xbd => "mul-long/2addr", F12x, None, CONTINUE;
+    0xbe => "div-long/2addr", F12x, None, CONTINUE | THROW;
+    0xbf => "rem-long/2addr", F12x, None, CONTINUE | THROW;
+    0xc0 => "and-long/2addr", F12x, None, CONTINUE;
+    0xc1 => "or-long/2addr", F12x, None, CONTINUE;
+    0xc2 => "xor-long/2addr", F12x, None, CONTINUE;
+    0xc3 => "shl-long/2addr", F12x, None, CONTINUE;
+    0xc4 => "shr-long/2addr", F12x, None, CONTINUE;
+    0xc5 => "ushr-long/2addr", F12x, None, CONTINUE;
+    0xc6 => "add-float/2addr", F12x, None, CONTINUE;
+    0xc7 => "sub-float/2addr", F12x, None, CONTINUE;
+    0xc8 => "mul-float/2addr", F12x, None, CONTINUE;
+    0xc9 => "div-float/2addr", F12x, None, CONTINUE;
+    0xca => "rem-float/2addr", F12x, None, CONTINUE;
+    0xcb => "add-double/2addr", F12x, None, CONTINUE;
+    0xcc => "sub-double/2addr", F12x, None, CONTINUE;
+    0xcd => "mul-double/2addr", F12x, None, CONTINUE;
+    0xce => "div-double/2addr", F12x, None, CONTINUE;
+    0xcf => "rem-double/2addr", F12x, None, CONTINUE;
+    0xd0 => "add-int/lit16", F22s, None, CONTINUE;
+    0xd1 => "rsub-int", F22s, None, CONTINUE;
+    0xd2 => "mul-int/lit16", F22s, None, CONTINUE;
+    0xd3 => "div-int/lit16", F22s, None, CONTINUE | THROW;
+    0xd4 => "rem-int/lit16", F22s, None, CONTINUE | THROW;
+    0xd5 => "and-int/lit16", F22s, None, CONTINUE;
+    0xd6 => "or-int/lit16", F22s, None, CONTINUE;
+    0xd7 => "xor-int/lit16", F22s, None, CONTINUE;
+    0xd8 => "add-int/lit8", F22b, None, CONTINUE;
+    0xd9 => "rsub-int/lit8", F22b, None, CONTINUE;
+    0xda => "mul-int/lit8", F22b, None, CONTINUE;
+    0xdb => "div-int/lit8", F22b, None, CONTINUE | THROW;
+    0xdc => "rem-int/lit8", F22b, None, CONTINUE | THROW;
+    0xdd => "and-int/lit8", F22b, None, CONTINUE;
+    0xde => "or-int/lit8", F22b, None, CONTINUE;
+    0xdf => "xor-int/lit8", F22b, None, CONTINUE;
+    0xe0 => "shl-int/lit8", F22b, None, CONTINUE;
+    0xe1 => "shr-int/lit8", F22b, None, CONTINUE;
+    0xe2 => "ushr-int/lit8", F22b, None, CONTINUE;
+    0xe3 => "unused-e3", F10x, Unknown, UNUSED;
+    0xe4 => "unused-e4", F10x, Unknown, UNUSED;
+    0xe5 => "unused-e5", F10x, Unknown, UNUSED;
+    0xe6 => "unused-e6", F10x, Unknown, UNUSED;
+    0xe7 => "unused-e7", F10x, Unknown, UNUSED;
+    0xe8 => "unused-e8", F10x, Unknown, UNUSED;
+    0xe9 => "unused-e9", F10x, Unknown, UNUSED;
+    0xea => "unused-ea", F10x, Unknown, UNUSED;
+    0xeb => "unused-eb", F10x, Unknown, UNUSED;
+    0xec => "unused-ec", F10x, Unknown, UNUSED;
+    0xed => "unused-ed", F10x, Unknown, UNUSED;
+    0xee => "unused-ee", F10x, Unknown, UNUSED;
+    0xef => "unused-ef", F10x, Unknown, UNUSED;
+    0xf0 => "unused-f0", F10x, Unknown, UNUSED;
+    0xf1 => "unused-f1", F10x, Unknown, UNUSED;
+    0xf2 => "unused-f2", F10x, Unknown, UNUSED;
+    0xf3 => "unused-f3", F10x, Unknown, UNUSED;
+    0xf4 => "unused-f4", F10x, Unknown, UNUSED;
+    0xf5 => "unused-f5", F10x, Unknown, UNUSED;
+    0xf6 => "unused-f6", F10x, Unknown, UNUSED;
+    0xf7 => "unused-f7", F10x, Unknown, UNUSED;
+    0xf8 => "unused-f8", F10x, Unknown, UNUSED;
+    0xf9 => "unused-f9", F10x, Unknown, UNUSED;
+    0xfa => "invoke-polymorphic", F45cc, MethodAndProtoRef, CONTINUE | THROW | INVOKE;
+    0xfb => "invoke-polymorphic/range", F4rcc, MethodAndProtoRef, CONTINUE | THROW | INVOKE;
+    0xfc => "invoke-custom", F35c, CallSiteRef, CONTINUE | THROW | INVOKE;
+    0xfd => "invoke-custom/range", F3rc, CallSiteRef, CONTINUE | THROW | INVOKE;
+    0xfe => "const-method-handle", F21c, MethodHandleRef, CONTINUE | THROW;
+    0xff => "const-method-type", F21c, ProtoRef, CONTINUE | THROW;
+}
+
+// every opcode is listed exactly once, in order
+const _: () = {
+    let mut value = 0;
+    while value < TABLE.len() {
+        assert!(TABLE[value].value as usize == value);
+        value += 1;
+    }
+};
+
+/// What a decoded instruction is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionKind {
+    /// An instruction with an opcode.
+    Op(&'static Opcode),
+    /// Data of a `packed-switch`.
+    PackedSwitchPayload,
+    /// Data of a `sparse-switch`.
+    SparseSwitchPayload,
+    /// Data of a `fill-array-data`.
+    FillArrayDataPayload,
+}
+
+/// An instruction and its code units.
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction<'a> {
+    /// Address of the instruction, in code units from the start of the method.
+    pub addr: usize,
+    /// The opcode or payload of the instruction.
+    pub kind: InstructionKind,
+    /// The code units of the instruction, including the opcode.
+    pub units: &'a [ushort],
+}
+
+/// The index operand of an instruction, see `Instruction::index_operand`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndexOperand {
+    String(StringId),
+    Type(TypeId),
+    Field(FieldId),
+    Method(MethodId),
+    /// The method and the prototype of the call site of `invoke-polymorphic`.
+    MethodAndProto(MethodId, ProtoId),
+    CallSite(uint),
+    MethodHandle(MethodHandleId),
+    Proto(ProtoId),
+}
+
+impl Instruction<'_> {
+    /// Decodes the index operand of the instruction, if it has one. Indices are 16 bits
+    /// wide, except for `const-string/jumbo` whose string index is 32 bits wide, so
+    /// instructions can only refer to the first 65536 items of most pools. The indices
+    /// are not checked against the pools, see `Dex::check_index_operand`.
+    pub fn index_operand(&self) -> Option<IndexOperand> {
+        let opcode = match self.kind {
+            InstructionKind::Op(opcode) => opcode,
+            _ => return None,
+        };
+        if self.units.len() < opcode.format.units() {
+            return None;
+        }
+        let index = match opcode.format {
+            Format::F31c => uint::from(self.units[1]) | uint::from(self.units[2]) << 16,
+            Format::F21c
+            | Format::F22c
+            | Format::F35c
+            | Format::F3rc
+            | Format::F45cc
+            | Format::F4rcc => uint::from(self.units[1]),
+            _ => return None,
+        };
+        Some(match opcode.index {
+            IndexKind::StringRef => IndexOperand::String(index),
+            IndexKind::TypeRef => IndexOperand::Type(index),
+            IndexKind::FieldRef => IndexOperand::Field(index.into()),
+            IndexKind::MethodRef => IndexOperand::Method(index.into()),
+            IndexKind::MethodAndProtoRef => {
+                IndexOperand::MethodAndProto(index.into(), self.units[3].into())
+            }
+            IndexKind::CallSiteRef => IndexOperand::CallSite(index),
+            IndexKind::MethodHandleRef => IndexOperand::MethodHandle(index),
+            IndexKind::ProtoRef => IndexOperand::Proto(index.into()),
+            IndexKind::None | IndexKind::Unknown => return None,
+        })
+    }
+
+    /// Decodes the signed offset, in code units from the instruction, of the target of a
+    /// branch or of the payload of a switch. Other instructions have none.
+    pub fn branch_offset(&self) -> Option<i32> {
+        let opcode = match self.kind {
+            InstructionKind::Op(opcode) if opcode.is_branch() => opcode,
+            _ => return None,
+        };
+        if self.units.len() < opcode.format.units() {
+            return None;
+        }
+        Some(match opcode.format {
+            Format::F10t => i32::from((self.units[0] >> 8) as i8),
+            Format::F20t | Format::F21t | Format::F22t => i32::from(self.units[1] as i16),
+            Format::F30t | Format::F31t => {
+                (uint::from(self.units[1]) | uint::from(self.units[2]) << 16) as i32
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Returns `true` if a branch or a switch of the instructions `insns` targets an
+/// instruction at or before its own address, the mark of a loop. The instructions are
+/// decoded in one pass, reading the payloads of the switches where they are, so a payload
+/// placed before its switch doesn't count as a backward branch, only its targets do.
+pub fn has_backward_branch(insns: &[ushort]) -> Result<bool> {
+    for instruction in Instructions::new(insns) {
+        let instruction = instruction?;
+        let offset = match instruction.branch_offset() {
+            Some(offset) => i64::from(offset),
+            None => continue,
+        };
+        let is_switch = match instruction.kind {
+            InstructionKind::Op(opcode) => opcode.flags.contains(OpcodeFlags::SWITCH),
+            _ => false,
+        };
+        if !is_switch {
+            if offset <= 0 {
+                return Ok(true);
+            }
+            continue;
+        }
+        let payload = usize::try_from(instruction.addr as i64 + offset)
+            .ok()
+            .filter(|payload| *payload < insns.len())
+            .ok_or_else(|| {
+                Error::MalFormed(format!(
+                    "Switch at {} has no payload at offset {}",
+                    instruction.addr, offset
+                ))
+            })?;
+        let len = instruction_len(insns, payload)?;
+        let units = insns
+            .get(payload..payload + len)
+            .ok_or_else(|| Error::MalFormed(format!("Truncated switch payload at {}", payload)))?;
+        let targets = match units[0] {
+            PACKED_SWITCH_PAYLOAD => &units[4..],
+            SPARSE_SWITCH_PAYLOAD => &units[2 + (len - 2) / 2..],
+            _ => {
+                return Err(Error::MalFormed(format!(
+                    "Switch at {} points to {} which is not a switch payload",
+                    instruction.addr, payload
+                )))
+            }
+        };
+        for target in targets.chunks_exact(2) {
+            if (uint::from(target[0]) | uint::from(target[1]) << 16) as i32 <= 0 {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Returns the number of code units of the instruction at `addr`.
+pub fn instruction_len(insns: &[ushort], addr: usize) -> Result<usize> {
+    let unit = *insns
+        .get(addr)
+        .ok_or_else(|| Error::MalFormed(format!("No instruction at {}", addr)))?;
+    match crate::code::payload_size(&insns[addr..]) {
+        Some(size) => size
+            .map(|(len, _)| len as usize)
+            .map_err(|_| Error::MalFormed(format!("Truncated payload at {}", addr))),
+        None => Ok(OPCODES[(unit & 0xff) as usize].format.units()),
+    }
+}
+
+/// Iterator over the instructions of a method.
+pub struct Instructions<'a> {
+    insns: &'a [ushort],
+    addr: usize,
+}
+
+impl<'a> Instructions<'a> {
+    /// Iterates over the instructions in `insns`, the code units of a method.
+    pub fn new(insns: &'a [ushort]) -> Self {
+        Self { insns, addr: 0 }
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.addr >= self.insns.len() {
+            return None;
+        }
+        let addr = self.addr;
+        let len = match instruction_len(self.insns, addr) {
+            Ok(len) => len,
+            Err(e) => {
+                self.addr = self.insns.len();
+                return Some(Err(e));
+            }
+        };
+        let units = match self.insns.get(addr..addr + len) {
+            Some(units) => units,
+            None => {
+                self.addr = self.insns.len();
+                return Some(Err(Error::MalFormed(format!(
+                    "Instruction at {} overruns the instructions",
+                    addr
+                ))));
+            }
+        };
+        self.addr += len;
+        let kind = match units[0] {
+            PACKED_SWITCH_PAYLOAD => InstructionKind::PackedSwitchPayload,
+            SPARSE_SWITCH_PAYLOAD => InstructionKind::SparseSwitchPayload,
+            FILL_ARRAY_DATA_PAYLOAD => InstructionKind::FillArrayDataPayload,
+            unit => InstructionKind::Op(&OPCODES[(unit & 0xff) as usize]),
+        };
+        Some(Ok(Instruction { addr, kind, units }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IndexOperand, Instruction, InstructionKind, OpcodeFlags, OPCODES};
+    use crate::DexReader;
+
+    #[test]
+    fn test_opcodes_match_snapshot() {
+        let snapshot = include_str!("../resources/dex_instruction_list.h");
+        let mut count = 0;
+        for line in snapshot
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("V("))
+        {
+            let line = line.trim_end_matches([' ', '\\']).trim_end_matches(')');
+            let columns: Vec<&str> = line.split(", ").collect();
+            assert_eq!(columns.len(), 6, "bad snapshot line: {}", line);
+            let value = usize::from_str_radix(&columns[0][2..], 16).unwrap();
+            let opcode = &OPCODES[value];
+            assert_eq!(
+                opcode.mnemonic,
+                columns[2].trim_matches('"'),
+                "{:#x}",
+                value
+            );
+            assert_eq!(
+                format!("k{}", opcode.format.id()),
+                columns[3],
+                "{}",
+                opcode.mnemonic
+            );
+            assert_eq!(
+                format!("kIndex{:?}", opcode.index),
+                columns[4],
+                "{}",
+                opcode.mnemonic
+            );
+            let flags = columns[5].split(" | ").filter(|flag| *flag != "0").fold(
+                OpcodeFlags::empty(),
+                |flags, flag| {
+                    flags
+                        | match flag {
+                            "kContinue" => OpcodeFlags::CONTINUE,
+                            "kThrow" => OpcodeFlags::THROW,
+                            "kBranch" => OpcodeFlags::BRANCH,
+                            "kSwitch" => OpcodeFlags::SWITCH,
+                            "kReturn" => OpcodeFlags::RETURN,
+                            "kInvoke" => OpcodeFlags::INVOKE,
+                            flag => panic!("unknown flag {}", flag),
+                        }
+                },
+            );
+            assert_eq!(opcode.flags, flags, "{}", opcode.mnemonic);
+            count += 1;
+        }
+        assert_eq!(count, 256);
+    }
+
+    #[test]
+    fn test_decode_example_methods() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut instructions = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let code = match method.code() {
+                    Some(code) => code,
+                    None => continue,
+                };
+                let mut len = 0;
+                for instruction in code.instructions() {
+                    let instruction = instruction.expect("bad instruction");
+                    assert_eq!(instruction.addr, len);
+                    if let InstructionKind::Op(opcode) = instruction.kind {
+                        assert!(!opcode.is_unused(), "{:?}", opcode);
+                    }
+                    if let Some(operand) = instruction.index_operand() {
+                        dex.check_index_operand(operand).expect("bad index");
+                    }
+                    len += instruction.units.len();
+                    instructions += 1;
+                }
+                assert_eq!(len, code.insns.len());
+            }
+        }
+        assert!(instructions > 10_000);
+    }
+
+    #[test]
+    fn test_index_operands() {
+        let operand = |units: &[u16]| {
+            Instruction {
+                addr: 0,
+                kind: InstructionKind::Op(&OPCODES[(units[0] & 0xff) as usize]),
+                units,
+            }
+            .index_operand()
+        };
+        // const-string v0, const-string/jumbo v0
+        assert_eq!(
+            operand(&[0x001a, 0xffff]),
+            Some(IndexOperand::String(0xffff))
+        );
+        assert_eq!(
+            operand(&[0x001b, 0x0000, 0x0001]),
+            Some(IndexOperand::String(0x1_0000))
+        );
+        // invoke-static {}, new-instance v0, iget v0, v1
+        assert_eq!(
+            operand(&[0x0071, 0xffff, 0]),
+            Some(IndexOperand::Method(0xffff))
+        );
+        assert_eq!(operand(&[0x0022, 0x1234]), Some(IndexOperand::Type(0x1234)));
+        assert_eq!(operand(&[0x1052, 0x0002]), Some(IndexOperand::Field(2)));
+        // invoke-polymorphic {v0}
+        assert_eq!(
+            operand(&[0x10fa, 0x0003, 0x0000, 0x0004]),
+            Some(IndexOperand::MethodAndProto(3, 4))
+        );
+        // nop, const/4 v0, and a truncated const-string
+        assert_eq!(operand(&[0x0000]), None);
+        assert_eq!(operand(&[0x0012]), None);
+        assert_eq!(operand(&[0x001a]), None);
+    }
+
+    #[test]
+    fn test_has_backward_branch() {
+        let cases: &[(&str, &[u16], bool)] = &[
+            (
+                // const/4 v0, 0; if-ge v0, v1, +5; add-int/lit8 v0, v0, 1; goto -4;
+                // return-void
+                "for loop",
+                &[0x0012, 0x1035, 0x0005, 0x00d8, 0x0100, 0xfc28, 0x000e],
+                true,
+            ),
+            (
+                // const/4 v0, 0; if-eqz v0, +3; nop; return-void
+                "forward branch",
+                &[0x0012, 0x0038, 0x0003, 0x0000, 0x000e],
+                false,
+            ),
+            (
+                // goto +8; nop; packed-switch-payload with a target of +3;
+                // packed-switch v0, -6; return-void
+                "payload before switch",
+                &[
+                    0x0828, 0x0000, 0x0100, 0x0001, 0x0000, 0x0000, 0x0003, 0x0000, 0x002b, 0xfffa,
+                    0xffff, 0x000e,
+                ],
+                false,
+            ),
+            (
+                // the same switch with a target of -8, the goto
+                "switch to goto",
+                &[
+                    0x0828, 0x0000, 0x0100, 0x0001, 0x0000, 0x0000, 0xfff8, 0xffff, 0x002b, 0xfffa,
+                    0xffff, 0x000e,
+                ],
+                true,
+            ),
+            (
+                // sparse-switch v0, +4; return-void; sparse-switch-payload with the key -1
+                // and the target +3
+                "sparse switch",
+                &[
+                    0x002c, 0x0004, 0x0000, 0x000e, 0x0200, 0x0001, 0xffff, 0xffff, 0x0003, 0x0000,
+                ],
+                false,
+            ),
+            // goto/32 +0
+            ("self loop", &[0x002a, 0x0000, 0x0000], true),
+        ];
+        for (name, insns, expected) in cases {
+            assert_eq!(
+                super::has_backward_branch(insns).expect("cannot decode"),
+                *expected,
+                "{}",
+                name
+            );
+        }
+        // packed-switch v0, +3 pointing past the instructions, or to a nop
+        assert!(super::has_backward_branch(&[0x002b, 0x0003, 0x0000]).is_err());
+        assert!(super::has_backward_branch(&[0x002b, 0x0003, 0x0000, 0x0000]).is_err());
+    }
+}
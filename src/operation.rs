@@ -0,0 +1,33 @@
+//! Options of long-running operations, see `Dex::run_with`.
+use std::time::{Duration, Instant};
+
+/// Options of the operations run with `Dex::run_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OperationOptions {
+    deadline: Option<Instant>,
+}
+
+impl OperationOptions {
+    /// Returns the default options, without a deadline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instant after which the operation fails with `Error::DeadlineExceeded`. The deadline
+    /// is checked before each class definition but the first an operation walks, so it is
+    /// overrun by at most the time spent on one class, and every walk makes progress.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline `timeout` from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// The deadline, if any.
+    pub(crate) fn get_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
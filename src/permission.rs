@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use crate::method::MethodRef;
+
+/// Compiled form of an API-to-permission mapping. Each entry of the mapping is a pair of
+/// a smali descriptor and the permission implied by referencing it. Supported descriptors:
+/// * `Lpkg/Class;->name(params)return` matches exactly that method.
+/// * `Lpkg/Class;` matches every method of the class.
+/// * `Lpkg/*` matches every method of every class whose descriptor starts with `Lpkg/`.
+pub(crate) struct PermissionMatcher<'a> {
+    methods: HashMap<&'a str, Vec<&'a str>>,
+    classes: HashMap<&'a str, Vec<&'a str>>,
+    prefixes: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> PermissionMatcher<'a> {
+    pub(crate) fn new(mapping: &[(&'a str, &'a str)]) -> Self {
+        let mut matcher = Self {
+            methods: HashMap::new(),
+            classes: HashMap::new(),
+            prefixes: Vec::new(),
+        };
+        for (descriptor, permission) in mapping {
+            if descriptor.contains("->") {
+                matcher
+                    .methods
+                    .entry(*descriptor)
+                    .or_default()
+                    .push(*permission);
+            } else if let Some(prefix) = descriptor.strip_suffix('*') {
+                matcher.prefixes.push((prefix, *permission));
+            } else {
+                matcher
+                    .classes
+                    .entry(*descriptor)
+                    .or_default()
+                    .push(*permission);
+            }
+        }
+        matcher
+    }
+
+    /// Returns the permissions implied by referencing the method.
+    pub(crate) fn permissions(&self, method: &MethodRef) -> Vec<&'a str> {
+        let class = method.class().type_descriptor();
+        let mut permissions: Vec<&'a str> = self
+            .prefixes
+            .iter()
+            .filter(|(prefix, _)| class.starts_with(prefix))
+            .map(|(_, permission)| *permission)
+            .collect();
+        if let Some(class_permissions) = self.classes.get(&**class) {
+            permissions.extend(class_permissions);
+        }
+        if !self.methods.is_empty() {
+            if let Some(method_permissions) = self.methods.get(method.to_string().as_str()) {
+                permissions.extend(method_permissions);
+            }
+        }
+        permissions.sort_unstable();
+        permissions.dedup();
+        permissions
+    }
+}
@@ -0,0 +1,29 @@
+//! Re-exports of the commonly needed types, meant to be glob imported:
+//!
+//! ```
+//! use dex::prelude::*;
+//!
+//! # fn main() -> dex::Result<()> {
+//! let dex: Dex<_> = DexReader::from_file("resources/classes.dex")?;
+//! for class in dex.classes() {
+//!     let class: Class = class?;
+//!     let _public = class.access_flags().contains(ClassAccessFlags::PUBLIC);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Items are only ever added to the prelude within a major version, never removed or
+//! renamed. The access flags of classes, fields and methods are renamed to tell them apart.
+//! `Result` is left out as it would shadow `std::result::Result`.
+pub use crate::{
+    annotation::{Annotated, AnnotationItem, AnnotationSetItem, EncodedAnnotation, Visibility},
+    class::{AccessFlags as ClassAccessFlags, Class},
+    container::DexContainer,
+    encoded_value::EncodedValue,
+    field::{AccessFlags as FieldAccessFlags, Field, FieldRef},
+    jtype::{Type, TypeKind},
+    method::{AccessFlags as MethodAccessFlags, Method, MethodRef},
+    string::DexString,
+    Dex, DexReader, DexReaderBuilder, EagerSections, Error, VerifyMode,
+};
@@ -0,0 +1,772 @@
+//! ART profiles, which list the methods and classes an application uses so that they are
+//! compiled ahead of time, see `Profile` and `DexContainer::annotate_hotness`.
+//!
+//! Binary profiles (`.prof` files) name a dex file by the checksum of its header, see
+//! `Dex::checksum`, and a method by its index in the method_ids of that file, which is its
+//! `MethodId`. Text profiles, such as the `baseline-prof.txt` of a library, name methods in
+//! smali notation, as `MethodRef` formats them.
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::Read,
+    path::Path,
+};
+
+use flate2::read::ZlibDecoder;
+use getset::{CopyGetters, Getters};
+use scroll::{Pread, LE};
+
+use crate::{
+    error::Error,
+    jtype::TypeId,
+    method::{MethodId, MethodRef},
+    source::DexSource,
+    uint, ushort, Dex, Result,
+};
+
+/// The magic of binary profiles, followed by their version.
+const MAGIC: &[u8] = b"pro\0";
+/// Replaces the receiver types of an inline cache whose types could not be recorded.
+const MISSING_TYPES: u8 = 6;
+/// Replaces the receiver types of an inline cache with too many types to list.
+const MEGAMORPHIC: u8 = 7;
+
+/// The sections of version 015 profiles. Sections of other types are ignored.
+const DEX_FILES_SECTION: uint = 0;
+const EXTRA_DESCRIPTORS_SECTION: uint = 1;
+const CLASSES_SECTION: uint = 2;
+const METHODS_SECTION: uint = 3;
+
+/// The formats of profiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfileVersion {
+    /// The text format, with a method or a class per line.
+    Text,
+    /// The binary format of Android 9 to 11.
+    V010,
+    /// The binary format of Android 12 and later.
+    V015,
+}
+
+impl ProfileVersion {
+    /// Detects the format of the profile. Binary profiles of other versions, such as the
+    /// boot image profiles of version 016, are `Error::UnsupportedProfile`.
+    pub fn detect(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(MAGIC) {
+            return match std::str::from_utf8(data) {
+                Ok(_) => Ok(ProfileVersion::Text),
+                Err(_) => Err(Error::UnsupportedProfile(
+                    "neither a binary nor a text profile".to_string(),
+                )),
+            };
+        }
+        match data.get(MAGIC.len()..MAGIC.len() + 4) {
+            Some(b"010\0") => Ok(ProfileVersion::V010),
+            Some(b"015\0") => Ok(ProfileVersion::V015),
+            Some(version) => Err(Error::UnsupportedProfile(format!(
+                "version {}",
+                String::from_utf8_lossy(version).trim_end_matches('\0')
+            ))),
+            None => Err(Error::MalFormed("Truncated profile header".to_string())),
+        }
+    }
+}
+
+bitflags! {
+    /// How a profile marks a method, with the values ART uses.
+    #[derive(Default)]
+    pub struct MethodFlags: u8 {
+        /// The method runs often enough to be compiled.
+        const HOT = 0x1;
+        /// The method runs during the startup of the application.
+        const STARTUP = 0x2;
+        /// The method runs after the startup of the application.
+        const POST_STARTUP = 0x4;
+    }
+}
+
+/// How a profile names a method or a class.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ProfileRef {
+    /// An item of the dex file with the checksum, by its `MethodId` for a method or its
+    /// `TypeId` for a class.
+    Index { checksum: uint, index: uint },
+    /// An item by its descriptor, such as `Lcom/example/Foo;->bar(I)V` for a method or
+    /// `Lcom/example/Foo;` for a class.
+    Descriptor(String),
+}
+
+/// A method of a profile with its flags.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ProfileMethod {
+    /// The method.
+    #[get = "pub"]
+    reference: ProfileRef,
+    /// How the profile marks the method. Empty for the methods of text profiles without
+    /// flags.
+    #[get_copy = "pub"]
+    flags: MethodFlags,
+}
+
+/// The methods and classes of a profile. The methods of binary profiles are listed by
+/// dex file, in the order of their indexes, with the flags of each method merged.
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct Profile {
+    /// The format the profile was read from.
+    #[get_copy = "pub"]
+    version: ProfileVersion,
+    /// The methods of the profile.
+    #[get = "pub"]
+    methods: Vec<ProfileMethod>,
+    /// The classes of the profile.
+    #[get = "pub"]
+    classes: Vec<ProfileRef>,
+}
+
+/// A dex file described by a binary profile.
+struct ProfileDex {
+    checksum: uint,
+    num_type_ids: uint,
+    num_method_ids: uint,
+}
+
+impl Profile {
+    /// Reads a profile in any of the `ProfileVersion` formats.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let version = ProfileVersion::detect(data)?;
+        let mut profile = Self {
+            version,
+            methods: Vec::new(),
+            classes: Vec::new(),
+        };
+        match version {
+            ProfileVersion::Text => profile.read_text(&String::from_utf8_lossy(data))?,
+            ProfileVersion::V010 => profile.read_v010(data)?,
+            ProfileVersion::V015 => profile.read_v015(data)?,
+        }
+        Ok(profile)
+    }
+
+    /// Reads the profile at `path`, see `parse`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Reads the lines of a text profile: a method prefixed by its `H`, `S` and `P` flags,
+    /// or a class. Empty lines and comments starting with `#` are skipped.
+    fn read_text(&mut self, text: &str) -> Result<()> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let descriptor = line.trim_start_matches(['H', 'S', 'P']);
+            if !descriptor.starts_with(['L', '[']) {
+                return Err(Error::MalFormed(format!(
+                    "Bad profile rule on line {}: {}",
+                    number + 1,
+                    line
+                )));
+            }
+            if !descriptor.contains("->") {
+                self.classes
+                    .push(ProfileRef::Descriptor(descriptor.to_string()));
+                continue;
+            }
+            let mut flags = MethodFlags::empty();
+            for flag in line[..line.len() - descriptor.len()].chars() {
+                flags |= match flag {
+                    'H' => MethodFlags::HOT,
+                    'S' => MethodFlags::STARTUP,
+                    _ => MethodFlags::POST_STARTUP,
+                };
+            }
+            self.methods.push(ProfileMethod {
+                reference: ProfileRef::Descriptor(descriptor.to_string()),
+                flags,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads a version 010 profile: the number of dex files and the sizes of the
+    /// compressed data, which holds the header of each dex file followed by their hot
+    /// methods, classes and method flags.
+    fn read_v010(&mut self, data: &[u8]) -> Result<()> {
+        let offset = &mut (MAGIC.len() + 4);
+        let dex_count: u8 = data.gread_with(offset, LE)?;
+        let size: uint = data.gread_with(offset, LE)?;
+        let compressed_size: uint = data.gread_with(offset, LE)?;
+        let compressed = region(
+            data,
+            *offset,
+            compressed_size as usize,
+            "Compressed profile data",
+        )?;
+        let data = inflate(compressed, size)?;
+
+        let offset = &mut 0;
+        let mut headers = Vec::with_capacity(usize::from(dex_count));
+        for _ in 0..dex_count {
+            let key_len: ushort = data.gread_with(offset, LE)?;
+            let class_count: ushort = data.gread_with(offset, LE)?;
+            let methods_size: uint = data.gread_with(offset, LE)?;
+            let checksum: uint = data.gread_with(offset, LE)?;
+            let num_method_ids: uint = data.gread_with(offset, LE)?;
+            // the profile key, such as `base.apk!classes2.dex`
+            *offset += usize::from(key_len);
+            headers.push((class_count, methods_size, checksum, num_method_ids));
+        }
+        for (class_count, methods_size, checksum, num_method_ids) in headers {
+            let mut methods = BTreeMap::new();
+            let hot_methods = region(&data, *offset, methods_size as usize, "Hot methods")?;
+            read_hot_methods(hot_methods, true, &mut methods)?;
+            *offset += hot_methods.len();
+            for index in read_class_indexes(&data, offset, class_count)? {
+                self.classes.push(ProfileRef::Index { checksum, index });
+            }
+            let bitmap = region(&data, *offset, bitmap_len(num_method_ids), "Method flags")?;
+            read_flag_bitmap(bitmap, num_method_ids, &mut methods);
+            *offset += bitmap.len();
+            self.push_methods(checksum, methods);
+        }
+        Ok(())
+    }
+
+    /// Reads a version 015 profile: a table of sections, each compressed or not, holding
+    /// the dex files, the descriptors of the classes they don't define, the classes and the
+    /// methods.
+    fn read_v015(&mut self, data: &[u8]) -> Result<()> {
+        let offset = &mut (MAGIC.len() + 4);
+        let section_count: uint = data.gread_with(offset, LE)?;
+        let mut sections = HashMap::new();
+        for _ in 0..section_count {
+            let kind: uint = data.gread_with(offset, LE)?;
+            let file_offset: uint = data.gread_with(offset, LE)?;
+            let file_size: uint = data.gread_with(offset, LE)?;
+            let inflated_size: uint = data.gread_with(offset, LE)?;
+            let section = region(
+                data,
+                file_offset as usize,
+                file_size as usize,
+                "Profile section",
+            )?;
+            let section = if inflated_size == 0 {
+                Cow::Borrowed(section)
+            } else {
+                Cow::Owned(inflate(section, inflated_size)?)
+            };
+            sections.insert(kind, section);
+        }
+
+        let section = sections
+            .get(&DEX_FILES_SECTION)
+            .ok_or_else(|| Error::MalFormed("Profile has no dex files section".to_string()))?;
+        let offset = &mut 0;
+        let dex_count: ushort = section.gread_with(offset, LE)?;
+        let mut dexes = Vec::with_capacity(usize::from(dex_count));
+        for _ in 0..dex_count {
+            dexes.push(ProfileDex {
+                checksum: section.gread_with(offset, LE)?,
+                num_type_ids: section.gread_with(offset, LE)?,
+                num_method_ids: section.gread_with(offset, LE)?,
+            });
+            let key_len: ushort = section.gread_with(offset, LE)?;
+            *offset += usize::from(key_len);
+        }
+        let dex = |index: ushort| {
+            dexes.get(usize::from(index)).ok_or_else(|| {
+                Error::MalFormed(format!("Profile has no dex file at index {}", index))
+            })
+        };
+
+        let mut extra_descriptors = Vec::new();
+        if let Some(section) = sections.get(&EXTRA_DESCRIPTORS_SECTION) {
+            let offset = &mut 0;
+            let count: ushort = section.gread_with(offset, LE)?;
+            for _ in 0..count {
+                let len: ushort = section.gread_with(offset, LE)?;
+                let descriptor = region(section, *offset, usize::from(len), "Extra descriptor")?;
+                extra_descriptors.push(String::from_utf8_lossy(descriptor).into_owned());
+                *offset += descriptor.len();
+            }
+        }
+
+        if let Some(section) = sections.get(&CLASSES_SECTION) {
+            let offset = &mut 0;
+            while *offset < section.len() {
+                let dex_index: ushort = section.gread_with(offset, LE)?;
+                let class_count: ushort = section.gread_with(offset, LE)?;
+                let dex = dex(dex_index)?;
+                for index in read_class_indexes(section, offset, class_count)? {
+                    // indexes past the types of the dex name the classes it doesn't define
+                    let class = match index.checked_sub(dex.num_type_ids) {
+                        None => ProfileRef::Index {
+                            checksum: dex.checksum,
+                            index,
+                        },
+                        Some(extra) => extra_descriptors
+                            .get(extra as usize)
+                            .cloned()
+                            .map(ProfileRef::Descriptor)
+                            .ok_or_else(|| {
+                                Error::MalFormed(format!("Bad profile class index: {}", index))
+                            })?,
+                    };
+                    self.classes.push(class);
+                }
+            }
+        }
+
+        let mut methods = vec![BTreeMap::new(); dexes.len()];
+        if let Some(section) = sections.get(&METHODS_SECTION) {
+            let offset = &mut 0;
+            while *offset < section.len() {
+                let dex_index: ushort = section.gread_with(offset, LE)?;
+                let size: uint = section.gread_with(offset, LE)?;
+                let num_method_ids = dex(dex_index)?.num_method_ids;
+                let data = region(section, *offset, size as usize, "Methods of a dex file")?;
+                *offset += data.len();
+                let methods = &mut methods[usize::from(dex_index)];
+                let bitmap = region(data, 0, bitmap_len(num_method_ids), "Method flags")?;
+                read_flag_bitmap(bitmap, num_method_ids, methods);
+                read_hot_methods(&data[bitmap.len()..], false, methods)?;
+            }
+        }
+        for (dex, methods) in dexes.iter().zip(methods) {
+            self.push_methods(dex.checksum, methods);
+        }
+        Ok(())
+    }
+
+    fn push_methods(&mut self, checksum: uint, methods: BTreeMap<uint, MethodFlags>) {
+        self.methods
+            .extend(methods.into_iter().map(|(index, flags)| ProfileMethod {
+                reference: ProfileRef::Index { checksum, index },
+                flags,
+            }));
+    }
+}
+
+/// The `len` bytes of `data` at `offset`.
+fn region<'a>(data: &'a [u8], offset: usize, len: usize, name: &str) -> Result<&'a [u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| {
+            Error::MalFormed(format!(
+                "{} at {} with {} bytes overruns the profile",
+                name, offset, len
+            ))
+        })
+}
+
+/// Inflates zlib compressed `data`, which must hold `size` bytes.
+fn inflate(data: &[u8], size: uint) -> Result<Vec<u8>> {
+    let mut inflated = Vec::new();
+    ZlibDecoder::new(data)
+        .take(u64::from(size) + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| Error::MalFormed(format!("Bad compressed profile data: {}", e)))?;
+    if inflated.len() != size as usize {
+        return Err(Error::MalFormed(format!(
+            "Compressed profile data holds {} bytes instead of {}",
+            inflated.len(),
+            size
+        )));
+    }
+    Ok(inflated)
+}
+
+/// Reads `count` class indexes, each stored as its difference with the previous one.
+fn read_class_indexes(data: &[u8], offset: &mut usize, count: ushort) -> Result<Vec<uint>> {
+    let mut index: uint = 0;
+    let mut indexes = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let delta: ushort = data.gread_with(offset, LE)?;
+        index += uint::from(delta);
+        indexes.push(index);
+    }
+    Ok(indexes)
+}
+
+/// Size of the bitmap of the startup and post-startup flags of the methods of a dex file.
+fn bitmap_len(num_method_ids: uint) -> usize {
+    (num_method_ids as usize * 2).div_ceil(8)
+}
+
+/// Adds the flags of the bitmap holding a bit per method for the startup flag, followed by
+/// a bit per method for the post-startup flag.
+fn read_flag_bitmap(
+    bitmap: &[u8],
+    num_method_ids: uint,
+    methods: &mut BTreeMap<uint, MethodFlags>,
+) {
+    let is_set = |bit: usize| bitmap[bit / 8] & (1 << (bit % 8)) != 0;
+    for (position, flag) in [MethodFlags::STARTUP, MethodFlags::POST_STARTUP]
+        .iter()
+        .enumerate()
+    {
+        for index in 0..num_method_ids {
+            if is_set(position * num_method_ids as usize + index as usize) {
+                *methods.entry(index).or_default() |= *flag;
+            }
+        }
+    }
+}
+
+/// Adds the hot methods filling `data`, each stored as the difference of its index with
+/// the previous one and followed by its inline caches, which are skipped. The receiver
+/// types of the caches of version 010 are grouped by dex file, those of version 015 are
+/// not.
+fn read_hot_methods(
+    data: &[u8],
+    grouped_types: bool,
+    methods: &mut BTreeMap<uint, MethodFlags>,
+) -> Result<()> {
+    let offset = &mut 0;
+    let mut index: uint = 0;
+    while *offset < data.len() {
+        let delta: ushort = data.gread_with(offset, LE)?;
+        index = index
+            .checked_add(uint::from(delta))
+            .ok_or_else(|| Error::MalFormed("Profile method index overflows".to_string()))?;
+        *methods.entry(index).or_default() |= MethodFlags::HOT;
+        let caches: ushort = data.gread_with(offset, LE)?;
+        for _ in 0..caches {
+            let _dex_pc: ushort = data.gread_with(offset, LE)?;
+            let count: u8 = data.gread_with(offset, LE)?;
+            if count == MISSING_TYPES || count == MEGAMORPHIC {
+                continue;
+            }
+            if !grouped_types {
+                *offset += usize::from(count) * 2;
+                continue;
+            }
+            for _ in 0..count {
+                let _dex_index: u8 = data.gread_with(offset, LE)?;
+                let types: u8 = data.gread_with(offset, LE)?;
+                *offset += usize::from(types) * 2;
+            }
+        }
+    }
+    if *offset != data.len() {
+        return Err(Error::MalFormed(
+            "Inline caches overrun the hot methods of the profile".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A method of a `DexContainer` listed in a profile, see `DexContainer::annotate_hotness`.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct MethodHotness {
+    /// Index of the dex of the method in `DexContainer::dexes`.
+    #[get_copy = "pub"]
+    dex_index: usize,
+    /// The method.
+    #[get = "pub"]
+    method: MethodRef,
+    /// How the profile marks the method.
+    #[get_copy = "pub"]
+    flags: MethodFlags,
+}
+
+impl MethodHotness {
+    pub(crate) fn new(dex_index: usize, method: MethodRef, flags: MethodFlags) -> Self {
+        Self {
+            dex_index,
+            method,
+            flags,
+        }
+    }
+}
+
+/// The methods of the classes defined in the dex file by their smali notation, to find
+/// the methods of text profiles.
+pub(crate) fn defined_methods<T: DexSource>(dex: &Dex<T>) -> Result<HashMap<String, MethodId>> {
+    let defined_classes = dex
+        .class_defs()
+        .map(|class_def| Ok(class_def?.class_idx))
+        .collect::<Result<HashSet<TypeId>>>()?;
+    let mut methods = HashMap::new();
+    for method_item in dex.method_ids() {
+        let method_item = method_item?;
+        if defined_classes.contains(&TypeId::from(method_item.class_idx())) {
+            let method_ref = MethodRef::try_from_dex(dex, &method_item)?;
+            methods.insert(method_ref.to_string(), method_ref.id());
+        }
+    }
+    Ok(methods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MethodFlags, Profile, ProfileRef, ProfileVersion};
+    use crate::{container::DexContainer, DexReader, ErrorKind};
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    const CHECKSUM: u32 = 0x1234_5678;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// The magic and version of a binary profile.
+    fn header(version: &str) -> Vec<u8> {
+        format!("pro\0{}\0", version).into_bytes()
+    }
+
+    fn units(values: &[u16]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    /// The flags of 10 methods: startup for 2 and 3, post-startup for 9.
+    const BITMAP: [u8; 3] = [0b1100, 0, 0b1000];
+
+    /// The methods of the example profiles, along with those of `BITMAP`.
+    fn expected_methods() -> Vec<(ProfileRef, MethodFlags)> {
+        let method = |index| ProfileRef::Index {
+            checksum: CHECKSUM,
+            index,
+        };
+        vec![
+            (method(2), MethodFlags::HOT | MethodFlags::STARTUP),
+            (method(3), MethodFlags::STARTUP),
+            (method(7), MethodFlags::HOT),
+            (method(9), MethodFlags::POST_STARTUP),
+        ]
+    }
+
+    fn methods(profile: &Profile) -> Vec<(ProfileRef, MethodFlags)> {
+        profile
+            .methods()
+            .iter()
+            .map(|method| (method.reference().clone(), method.flags()))
+            .collect()
+    }
+
+    fn v010_profile() -> Vec<u8> {
+        // method 2 with a cache of 2 types of dex 0, method 7 with a megamorphic cache
+        let mut hot_methods = units(&[2, 1, 5]);
+        hot_methods.extend_from_slice(&[1, 0, 2]);
+        hot_methods.extend(units(&[3, 4, 5, 1, 1]));
+        hot_methods.push(7);
+        let key = b"base.apk";
+        let mut body = units(&[key.len() as u16, 2]);
+        body.extend_from_slice(&(hot_methods.len() as u32).to_le_bytes());
+        body.extend_from_slice(&CHECKSUM.to_le_bytes());
+        body.extend_from_slice(&10u32.to_le_bytes());
+        body.extend_from_slice(key);
+        body.extend(hot_methods);
+        // classes 1 and 4
+        body.extend(units(&[1, 3]));
+        body.extend_from_slice(&BITMAP);
+
+        let compressed = compress(&body);
+        let mut data = header("010");
+        data.push(1);
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        data.extend(compressed);
+        data
+    }
+
+    /// A version 015 profile of a dex file with 3 types, with the given compressed methods
+    /// section, or the methods of `expected_methods`.
+    fn v015_profile(checksum: u32, methods: Option<Vec<u8>>) -> Vec<u8> {
+        let key = b"base.apk";
+        let mut dex_files = units(&[1]);
+        for value in &[checksum, 3, 10] {
+            dex_files.extend_from_slice(&value.to_le_bytes());
+        }
+        dex_files.extend(units(&[key.len() as u16]));
+        dex_files.extend_from_slice(key);
+        let descriptor = b"Lother/Missing;";
+        let mut extra_descriptors = units(&[1, descriptor.len() as u16]);
+        extra_descriptors.extend_from_slice(descriptor);
+        // class 2 of the dex and the first extra descriptor
+        let classes = units(&[0, 2, 2, 1]);
+        let methods = methods.unwrap_or_else(|| {
+            // method 2 with a cache of 2 types, method 7 with a cache of missing types
+            let mut hot_methods = units(&[2, 1, 5]);
+            hot_methods.push(2);
+            hot_methods.extend(units(&[3, 4, 5, 1, 1]));
+            hot_methods.push(6);
+            let mut methods = units(&[0]);
+            methods.extend_from_slice(&((BITMAP.len() + hot_methods.len()) as u32).to_le_bytes());
+            methods.extend_from_slice(&BITMAP);
+            methods.extend(hot_methods);
+            methods
+        });
+
+        let sections = [
+            (0u32, dex_files, false),
+            (1, extra_descriptors, false),
+            (2, classes, true),
+            (3, methods, true),
+        ];
+        let mut data = header("015");
+        data.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        let mut file_offset = data.len() + sections.len() * 16;
+        let mut contents = Vec::new();
+        for (kind, section, compressed) in &sections {
+            let (stored, inflated_size) = if *compressed {
+                (compress(section), section.len() as u32)
+            } else {
+                (section.clone(), 0)
+            };
+            for value in &[
+                *kind,
+                file_offset as u32,
+                stored.len() as u32,
+                inflated_size,
+            ] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            file_offset += stored.len();
+            contents.extend(stored);
+        }
+        data.extend(contents);
+        data
+    }
+
+    #[test]
+    fn test_detect_version() {
+        let detect = |data: &[u8]| ProfileVersion::detect(data);
+        assert_eq!(detect(b"HSPLa/B;->c()V\n").unwrap(), ProfileVersion::Text);
+        assert_eq!(detect(&header("010")).unwrap(), ProfileVersion::V010);
+        assert_eq!(detect(&header("015")).unwrap(), ProfileVersion::V015);
+        for data in [header("009"), header("016"), vec![0xff, 0xfe]].iter() {
+            let error = detect(data).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UnsupportedProfile);
+            assert_eq!(error.code(), 600);
+        }
+        assert_eq!(
+            detect(&header("009")).unwrap_err().to_string(),
+            "Unsupported profile: version 009"
+        );
+        assert_eq!(detect(b"pro\0").unwrap_err().kind(), ErrorKind::MalFormed);
+    }
+
+    #[test]
+    fn test_text_profile() {
+        let profile = Profile::parse(
+            b"# comment\n\nHSPLa/B;->c(I)V\nPLa/B;-><init>()V\nLa/B;->d()V\n  La/B;\n[La/B;\n",
+        )
+        .expect("bad profile");
+        assert_eq!(profile.version(), ProfileVersion::Text);
+        let descriptor = |value: &str| ProfileRef::Descriptor(value.to_string());
+        assert_eq!(
+            methods(&profile),
+            vec![
+                (descriptor("La/B;->c(I)V"), MethodFlags::all()),
+                (descriptor("La/B;-><init>()V"), MethodFlags::POST_STARTUP),
+                (descriptor("La/B;->d()V"), MethodFlags::empty()),
+            ]
+        );
+        assert_eq!(
+            profile.classes(),
+            &[descriptor("La/B;"), descriptor("[La/B;")]
+        );
+
+        let error = Profile::parse(b"La/B;\nHXLa/B;->c()V\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Malformed entity: Bad profile rule on line 2: HXLa/B;->c()V"
+        );
+    }
+
+    #[test]
+    fn test_binary_profiles() {
+        let profile = Profile::parse(&v010_profile()).expect("bad profile");
+        assert_eq!(profile.version(), ProfileVersion::V010);
+        assert_eq!(methods(&profile), expected_methods());
+        let class = |index| ProfileRef::Index {
+            checksum: CHECKSUM,
+            index,
+        };
+        assert_eq!(profile.classes(), &[class(1), class(4)]);
+
+        let profile = Profile::parse(&v015_profile(CHECKSUM, None)).expect("bad profile");
+        assert_eq!(profile.version(), ProfileVersion::V015);
+        assert_eq!(methods(&profile), expected_methods());
+        assert_eq!(
+            profile.classes(),
+            &[
+                class(2),
+                ProfileRef::Descriptor("Lother/Missing;".to_string())
+            ]
+        );
+
+        // truncated data
+        let data = v010_profile();
+        assert!(Profile::parse(&data[..data.len() - 1]).is_err());
+        // an inline cache overrunning the hot methods of its dex file
+        let mut methods = units(&[0]);
+        methods.extend_from_slice(&(BITMAP.len() as u32 + 6).to_le_bytes());
+        methods.extend_from_slice(&BITMAP);
+        methods.extend(units(&[2, 1, 5]));
+        let error = Profile::parse(&v015_profile(CHECKSUM, Some(methods))).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Scroll);
+    }
+
+    #[test]
+    fn test_annotate_hotness() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let checksum = dex.checksum();
+        let (descriptor, method_id) = super::defined_methods(&dex)
+            .expect("cannot list methods")
+            .into_iter()
+            .min_by_key(|(_, id)| *id)
+            .expect("no defined method");
+        let container = DexContainer::new(vec![dex]);
+        assert!(container.find_by_checksum(checksum).is_some());
+        assert!(container.find_by_checksum(CHECKSUM).is_none());
+
+        let text = format!("SP{}\nHLunknown/Foo;->bar()V\nLa/B;\n", descriptor);
+        let profile = Profile::parse(text.as_bytes()).expect("bad profile");
+        let annotated = container
+            .annotate_hotness(&profile)
+            .expect("cannot annotate");
+        assert_eq!(annotated.len(), 1);
+        assert_eq!(annotated[0].dex_index(), 0);
+        assert_eq!(annotated[0].method().id(), method_id);
+        assert_eq!(annotated[0].method().to_string(), descriptor);
+        assert_eq!(
+            annotated[0].flags(),
+            MethodFlags::STARTUP | MethodFlags::POST_STARTUP
+        );
+
+        // the methods of other dex files are skipped
+        let profile = Profile::parse(&v015_profile(checksum, None)).expect("bad profile");
+        let annotated = container
+            .annotate_hotness(&profile)
+            .expect("cannot annotate");
+        let found: Vec<_> = annotated
+            .iter()
+            .map(|hotness| (hotness.method().id(), hotness.flags()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, MethodFlags::HOT | MethodFlags::STARTUP),
+                (3, MethodFlags::STARTUP),
+                (7, MethodFlags::HOT),
+                (9, MethodFlags::POST_STARTUP),
+            ]
+        );
+        let profile = Profile::parse(&v010_profile()).expect("bad profile");
+        assert!(container
+            .annotate_hotness(&profile)
+            .expect("cannot annotate")
+            .is_empty());
+    }
+}
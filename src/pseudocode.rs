@@ -0,0 +1,662 @@
+//! Renders method bodies as pseudo-statements for a quick review, halfway between smali
+//! and a decompiler. Each instruction, or each invoke with the `move-result` following
+//! it, is written on one line with its operands resolved and a light sugar, e.g.
+//! `v0 = "https://example.com"`, `v1 = android.telephony.TelephonyManager.getDeviceId(v0)`
+//! or `if (v1 == 0) goto L2`. The control flow is not restructured: the targets of the
+//! branches, switches and catch handlers get labels `L0`, `L1`, ... in address order,
+//! and try blocks are written as `try {` and a closing line listing their handlers.
+//!
+//! The sugar never hides what an instruction does: the instructions without a
+//! statement reading unambiguously, such as `cmpl-float`, `invoke-super`,
+//! `monitor-enter` or `filled-new-array`, are written in smali instead, see
+//! `CodeItem::to_smali`. Zero and `null` are the same value in dex, `if-eqz` is written
+//! as a comparison with `0` whatever the type of its register.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    code::{CodeItem, ExceptionType, SwitchPayload, TryCatchHandlers},
+    error::Error,
+    jtype::{Type, TypeKind},
+    method::{Method, ProtoId},
+    opcode::{Format, IndexOperand, Instruction, InstructionKind, Instructions, Opcode},
+    smali::{self, reg, Renderer},
+    source::DexSource,
+    uint, Dex, Result,
+};
+
+/// Operators of the `if-test` and `if-testz` instructions, in opcode order.
+const COMPARISONS: [&str; 6] = ["==", "!=", "<", ">=", ">", "<="];
+
+/// Options of `Method::pseudocode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PseudocodeOptions {
+    simple_names: bool,
+    max_lines: Option<usize>,
+}
+
+impl PseudocodeOptions {
+    /// Returns the default options: qualified class names and no limit on the number of
+    /// lines.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the classes by their simple names, e.g. `TelephonyManager` instead of
+    /// `android.telephony.TelephonyManager`. Classes of different packages can then have
+    /// the same name.
+    pub fn simple_names(mut self, simple: bool) -> Self {
+        self.simple_names = simple;
+        self
+    }
+
+    /// Stop after `max_lines` statements, with a last line telling how many instructions
+    /// were left out.
+    pub fn max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+}
+
+impl Method {
+    /// Renders the body of the method as pseudo-statements, see `CodeItem::pseudocode`.
+    /// Methods without code render as an empty string.
+    pub fn pseudocode<S: DexSource>(
+        &self,
+        dex: &Dex<S>,
+        options: PseudocodeOptions,
+    ) -> Result<String> {
+        match &self.code {
+            Some(code) => code.pseudocode(dex, options),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+impl CodeItem {
+    /// Renders the code as pseudo-statements, one per line, indented by four spaces and
+    /// preceded by the labels of their address, e.g. `L0:`. Payloads and the `nop`s
+    /// aligning them are data, they are not written.
+    ///
+    /// Fails if an instruction can't be decoded, if an index operand can't be resolved
+    /// or if a branch target, a try block or a catch handler doesn't fall on an
+    /// instruction.
+    pub fn pseudocode<S: DexSource>(
+        &self,
+        dex: &Dex<S>,
+        options: PseudocodeOptions,
+    ) -> Result<String> {
+        let insns = Instructions::new(&self.insns).collect::<Result<Vec<_>>>()?;
+        let smali = Renderer::new(dex, self, &insns)?;
+        let addrs: BTreeSet<usize> = insns.iter().map(|insn| insn.addr).collect();
+        let targets = smali.labels.targets();
+        if let Some(target) = targets.iter().find(|addr| !addrs.contains(addr)) {
+            return Err(Error::MalFormed(format!(
+                "Target {} is not at an instruction",
+                target
+            )));
+        }
+        for try_block in self.tries.iter() {
+            let start = try_block.start_addr() as usize;
+            let end = start + usize::from(try_block.insn_count());
+            if !addrs.contains(&start) || !(addrs.contains(&end) || end == self.insns.len()) {
+                return Err(Error::MalFormed(format!(
+                    "Try block {}..{} doesn't fall on instructions",
+                    start, end
+                )));
+            }
+        }
+        let mut writer = Writer {
+            dex,
+            code: self,
+            smali,
+            labels: targets
+                .into_iter()
+                .enumerate()
+                .map(|(number, addr)| (addr, number))
+                .collect(),
+            options,
+            out: String::new(),
+            open_try: None,
+        };
+        writer.write(&insns)?;
+        Ok(writer.out)
+    }
+}
+
+struct Writer<'a, S> {
+    dex: &'a Dex<S>,
+    code: &'a CodeItem,
+    smali: Renderer<'a, S>,
+    /// Number of the label of each target address.
+    labels: BTreeMap<usize, usize>,
+    options: PseudocodeOptions,
+    out: String,
+    /// The try block covering the instructions being written.
+    open_try: Option<&'a TryCatchHandlers>,
+}
+
+impl<'a, S: DexSource> Writer<'a, S> {
+    fn write(&mut self, insns: &[Instruction<'_>]) -> Result<()> {
+        let mut lines = 0;
+        let mut index = 0;
+        while index < insns.len() {
+            let insn = &insns[index];
+            let opcode = match insn.kind {
+                InstructionKind::Op(opcode) => opcode,
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            };
+            if smali::is_padding(insns, index) && !self.labels.contains_key(&insn.addr) {
+                index += 1;
+                continue;
+            }
+            if self.options.max_lines == Some(lines) {
+                let left = insns[index..]
+                    .iter()
+                    .filter(|insn| matches!(insn.kind, InstructionKind::Op(_)))
+                    .count();
+                let _ = writeln!(self.out, "    // {} more instructions", left);
+                return Ok(());
+            }
+            self.write_boundaries(insn.addr);
+            let (statement, consumed) = self.statement(insns, index, opcode)?;
+            let indent = if self.open_try.is_some() { 8 } else { 4 };
+            let _ = writeln!(self.out, "{:indent$}{}", "", statement, indent = indent);
+            lines += 1;
+            index += consumed;
+        }
+        self.write_boundaries(self.code.insns.len());
+        Ok(())
+    }
+
+    /// Writes the end of the try block ending at or before `addr`, the label of `addr`
+    /// and the start of the try block starting at `addr`.
+    fn write_boundaries(&mut self, addr: usize) {
+        if let Some(try_block) = self.open_try {
+            let end = try_block.start_addr() as usize + usize::from(try_block.insn_count());
+            if end <= addr {
+                let handlers: Vec<String> = try_block
+                    .catch_handlers()
+                    .iter()
+                    .map(|handler| {
+                        let exception = match handler.exception() {
+                            ExceptionType::Ty(jtype) => self.type_name(jtype),
+                            ExceptionType::BaseException => String::from("..."),
+                        };
+                        format!(
+                            "catch ({}) goto {}",
+                            exception,
+                            self.label(handler.addr() as usize)
+                        )
+                    })
+                    .collect();
+                let _ = writeln!(self.out, "    }} {}", handlers.join(", "));
+                self.open_try = None;
+            }
+        }
+        if self.labels.contains_key(&addr) {
+            let _ = writeln!(self.out, "{}:", self.label(addr));
+        }
+        let code = self.code;
+        if let Some(try_block) = code
+            .tries
+            .iter()
+            .find(|t| t.start_addr() as usize == addr && t.insn_count() > 0)
+        {
+            self.out.push_str("    try {\n");
+            self.open_try = Some(try_block);
+        }
+    }
+
+    /// The statement of the instruction at `index` and the number of instructions it
+    /// covers.
+    fn statement(
+        &self,
+        insns: &[Instruction<'_>],
+        index: usize,
+        opcode: &Opcode,
+    ) -> Result<(String, usize)> {
+        let insn = &insns[index];
+        if opcode.is_unused() {
+            return Err(Error::MalFormed(format!(
+                "Unused opcode 0x{:02x} at {}",
+                opcode.value, insn.addr
+            )));
+        }
+        let units = insn.units;
+        let a = (units[0] >> 8) & 0xf;
+        let b = units[0] >> 12;
+        let aa = units[0] >> 8;
+        let statement = match opcode.value {
+            // move, const, const-string
+            0x01..=0x09 | 0x12..=0x1b => {
+                let operands = self.smali.operands(insn, opcode)?;
+                format!("{} = {}", operands[0], operands[1])
+            }
+            0x0d => format!("{} = exception", reg(aa)),
+            0x0e => String::from("return"),
+            0x0f..=0x11 => format!("return {}", reg(aa)),
+            0x1c => format!("{} = {}.class", reg(aa), self.type_operand(insn)?),
+            0x1f => format!("{0} = ({1}) {0}", reg(aa), self.type_operand(insn)?),
+            0x20 => format!(
+                "{} = {} instanceof {}",
+                reg(a),
+                reg(b),
+                self.type_operand(insn)?
+            ),
+            0x21 => format!("{} = {}.length", reg(a), reg(b)),
+            0x22 => format!("{} = new {}", reg(aa), self.type_operand(insn)?),
+            0x23 => {
+                let array = self.type_operand(insn)?;
+                let element = array.trim_end_matches("[]");
+                match (array.len() - element.len()) / 2 {
+                    0 => self.smali.insn_line(insn, opcode)?,
+                    dims => format!(
+                        "{} = new {}[{}]{}",
+                        reg(a),
+                        element,
+                        reg(b),
+                        "[]".repeat(dims - 1)
+                    ),
+                }
+            }
+            0x26 => {
+                let (_, values) = smali::array_literals(self.code, self.target(insn))?;
+                format!("fill-array-data {}, {{{}}}", reg(aa), values.join(", "))
+            }
+            0x27 => format!("throw {}", reg(aa)),
+            0x28..=0x2a => format!("goto {}", self.label(self.target(insn))),
+            0x2b | 0x2c => {
+                let entries = match self.code.resolve_switch(self.target(insn) as u32)? {
+                    SwitchPayload::Packed(payload) => payload.entries().collect::<Vec<_>>(),
+                    SwitchPayload::Sparse(payload) => payload.entries().collect(),
+                };
+                let cases: Vec<String> = entries
+                    .into_iter()
+                    .map(|(key, offset)| {
+                        let target = (insn.addr as i64 + i64::from(offset)) as usize;
+                        format!(
+                            "{} -> {}",
+                            smali::literal(i64::from(key), false),
+                            self.label(target)
+                        )
+                    })
+                    .collect();
+                format!("switch ({}) {{ {} }}", reg(aa), cases.join(", "))
+            }
+            0x32..=0x37 => format!(
+                "if ({} {} {}) goto {}",
+                reg(a),
+                COMPARISONS[usize::from(opcode.value - 0x32)],
+                reg(b),
+                self.label(self.target(insn))
+            ),
+            0x38..=0x3d => format!(
+                "if ({} {} 0) goto {}",
+                reg(aa),
+                COMPARISONS[usize::from(opcode.value - 0x38)],
+                self.label(self.target(insn))
+            ),
+            // aget, aput
+            0x44..=0x4a => format!(
+                "{} = {}[{}]",
+                reg(aa),
+                reg(units[1] & 0xff),
+                reg(units[1] >> 8)
+            ),
+            0x4b..=0x51 => format!(
+                "{}[{}] = {}",
+                reg(units[1] & 0xff),
+                reg(units[1] >> 8),
+                reg(aa)
+            ),
+            // iget, iput, sget, sput
+            0x52..=0x58 => format!("{} = {}.{}", reg(a), reg(b), self.field_name(insn)?.1),
+            0x59..=0x5f => format!("{}.{} = {}", reg(b), self.field_name(insn)?.1, reg(a)),
+            0x60..=0x66 => {
+                let (class, name) = self.field_name(insn)?;
+                format!("{} = {}.{}", reg(aa), class, name)
+            }
+            0x67..=0x6d => {
+                let (class, name) = self.field_name(insn)?;
+                format!("{}.{} = {}", class, name, reg(aa))
+            }
+            // invoke-virtual, -direct, -static and -interface, and their range forms
+            0x6e | 0x70..=0x72 | 0x74 | 0x76..=0x78 => return self.invoke(insns, index, opcode),
+            0x7b..=0x8f => match unary_operation(opcode.mnemonic) {
+                Some(operation) => format!("{} = {}{}", reg(a), operation, reg(b)),
+                None => self.smali.insn_line(insn, opcode)?,
+            },
+            0x90..=0xaf => match binary_operator(opcode.mnemonic) {
+                Some(operator) => format!(
+                    "{} = {} {} {}",
+                    reg(aa),
+                    reg(units[1] & 0xff),
+                    operator,
+                    reg(units[1] >> 8)
+                ),
+                None => self.smali.insn_line(insn, opcode)?,
+            },
+            0xb0..=0xcf => match binary_operator(opcode.mnemonic) {
+                Some(operator) => format!("{0} = {0} {1} {2}", reg(a), operator, reg(b)),
+                None => self.smali.insn_line(insn, opcode)?,
+            },
+            0xd0..=0xe2 => {
+                let (dest, source, literal) = if opcode.format == Format::F22s {
+                    (reg(a), reg(b), i64::from(units[1] as i16))
+                } else {
+                    let literal = i64::from((units[1] >> 8) as i8);
+                    (reg(aa), reg(units[1] & 0xff), literal)
+                };
+                let literal = smali::literal(literal, false);
+                if opcode.mnemonic.starts_with("rsub-int") {
+                    format!("{} = {} - {}", dest, literal, source)
+                } else {
+                    match binary_operator(opcode.mnemonic) {
+                        Some(operator) => {
+                            format!("{} = {} {} {}", dest, source, operator, literal)
+                        }
+                        None => self.smali.insn_line(insn, opcode)?,
+                    }
+                }
+            }
+            _ => self.smali.insn_line(insn, opcode)?,
+        };
+        Ok((statement, 1))
+    }
+
+    /// A call, merged with the `move-result` following it unless the `move-result` is
+    /// the target of a branch or starts a try block. Calls whose registers don't match
+    /// the prototype of the method are written in smali.
+    fn invoke(
+        &self,
+        insns: &[Instruction<'_>],
+        index: usize,
+        opcode: &Opcode,
+    ) -> Result<(String, usize)> {
+        let insn = &insns[index];
+        let units = insn.units;
+        let registers: Vec<uint> = if opcode.format == Format::F35c {
+            let count = usize::from(units[0] >> 12);
+            [
+                units[2] & 0xf,
+                (units[2] >> 4) & 0xf,
+                (units[2] >> 8) & 0xf,
+                units[2] >> 12,
+                (units[0] >> 8) & 0xf,
+            ]
+            .iter()
+            .take(count)
+            .map(|&register| uint::from(register))
+            .collect()
+        } else {
+            let first = uint::from(units[2]);
+            (first..first + uint::from(units[0] >> 8)).collect()
+        };
+        let method_id = match insn.index_operand() {
+            Some(IndexOperand::Method(id)) => id,
+            _ => return Err(Error::MalFormed(format!("No method at {}", insn.addr))),
+        };
+        let method = self.dex.get_method_ref(method_id)?;
+        let proto_id = ProtoId::from(self.dex.get_method_item(method_id)?.proto_idx());
+        let proto = self.dex.get_proto(proto_id)?;
+        // the receiver, then the parameters, longs and doubles using a pair of registers
+        let receiver = if opcode.value == 0x71 || opcode.value == 0x77 {
+            None
+        } else {
+            Some(1)
+        };
+        let widths = receiver
+            .into_iter()
+            .chain(proto.params().iter().map(|param| {
+                if param.is_long() || param.is_double() {
+                    2
+                } else {
+                    1
+                }
+            }));
+        let mut arguments = Vec::new();
+        let mut position = 0;
+        for width in widths {
+            match registers.get(position..position + width) {
+                Some(&[register]) => arguments.push(smali::reg_index(register)),
+                Some(&[low, high]) if high == low + 1 => arguments.push(smali::reg_index(low)),
+                _ => return Ok((self.smali.insn_line(insn, opcode)?, 1)),
+            }
+            position += width;
+        }
+        if position != registers.len() {
+            return Ok((self.smali.insn_line(insn, opcode)?, 1));
+        }
+        let call = format!(
+            "{}.{}({})",
+            self.type_name(method.class()),
+            method.name(),
+            arguments.join(", ")
+        );
+        let result = insns.get(index + 1).filter(|next| {
+            let code = self.code;
+            matches!(next.kind, InstructionKind::Op(op) if (0x0a..=0x0c).contains(&op.value))
+                && !proto.return_type().is_void()
+                && !self.labels.contains_key(&next.addr)
+                && !code
+                    .tries
+                    .iter()
+                    .any(|t| t.start_addr() as usize == next.addr)
+        });
+        Ok(match result {
+            Some(result) => (format!("{} = {}", reg(result.units[0] >> 8), call), 2),
+            None => (call, 1),
+        })
+    }
+
+    /// Address of the target of a branch, or of the payload of a switch or
+    /// `fill-array-data`, checked when the labels were collected.
+    fn target(&self, insn: &Instruction<'_>) -> usize {
+        let offset = match insn.branch_offset() {
+            Some(offset) => offset,
+            None => smali::int(insn.units[1], insn.units[2]),
+        };
+        (insn.addr as i64 + i64::from(offset)) as usize
+    }
+
+    fn label(&self, addr: usize) -> String {
+        // every target was collected
+        format!("L{}", self.labels[&addr])
+    }
+
+    fn type_operand(&self, insn: &Instruction<'_>) -> Result<String> {
+        match insn.index_operand() {
+            Some(IndexOperand::Type(id)) => Ok(self.type_name(&self.dex.get_type(id)?)),
+            _ => Err(Error::MalFormed(format!("No type at {}", insn.addr))),
+        }
+    }
+
+    /// The class and the name of the field operand of the instruction.
+    fn field_name(&self, insn: &Instruction<'_>) -> Result<(String, String)> {
+        match insn.index_operand() {
+            Some(IndexOperand::Field(id)) => {
+                let field = self.dex.get_field_ref(id)?;
+                Ok((self.type_name(field.class()), field.name().to_string()))
+            }
+            _ => Err(Error::MalFormed(format!("No field at {}", insn.addr))),
+        }
+    }
+
+    /// The Java name of the type, or its descriptor if it is not valid.
+    fn type_name(&self, jtype: &Type) -> String {
+        if TypeKind::parse(jtype.type_descriptor()).is_err() {
+            return jtype.type_descriptor().to_string();
+        }
+        let name = jtype.to_java_name();
+        match name.rfind('.') {
+            Some(dot) if self.options.simple_names => name[dot + 1..].to_string(),
+            _ => name,
+        }
+    }
+}
+
+/// The operator of a binary operation, e.g. `+` for `add-int/2addr`.
+fn binary_operator(mnemonic: &str) -> Option<&'static str> {
+    Some(match mnemonic.split('-').next()? {
+        "add" => "+",
+        "sub" => "-",
+        "mul" => "*",
+        "div" => "/",
+        "rem" => "%",
+        "and" => "&",
+        "or" => "|",
+        "xor" => "^",
+        "shl" => "<<",
+        "shr" => ">>",
+        "ushr" => ">>>",
+        _ => return None,
+    })
+}
+
+/// The prefix of a unary operation, e.g. `-` for `neg-int` or `(long) ` for
+/// `int-to-long`.
+fn unary_operation(mnemonic: &str) -> Option<String> {
+    if let Some((_, to)) = mnemonic.split_once("-to-") {
+        return Some(format!("({}) ", to));
+    }
+    match mnemonic.split('-').next()? {
+        "neg" => Some(String::from("-")),
+        "not" => Some(String::from("~")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scroll::Pread;
+
+    use super::PseudocodeOptions;
+    use crate::{code::CodeItem, test_utils::code_item, ushort, DexReader};
+
+    #[test]
+    fn test_code_pseudocode() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        // the code of the smali test: branches, a packed-switch and a try block with a
+        // handler of type 0 and a catch-all handler
+        let mut insns: Vec<ushort> = vec![0x1012, 0x0038, 7, 0x002b, 9, 0, 0xfa28, 0x000e];
+        insns.extend_from_slice(&[0x0019, 0x4000, 0x020f, 0]);
+        insns.extend_from_slice(&[0x0100, 2, 5, 0, 4, 0, 7, 0]);
+        let data = code_item(&insns, Some(&[1, 0x7f, 0, 7, 10]));
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        let catch_type = dex.get_type(0).expect("bad type").to_java_name();
+        let expected = format!(
+            "L0:
+    try {{
+        v0 = 0x1
+        if (v0 == 0) goto L2
+    }} catch ({}) goto L1, catch (...) goto L3
+    switch (v0) {{ 0x5 -> L1, 0x6 -> L3 }}
+    goto L0
+L1:
+    return
+L2:
+    v0 = 0x4000000000000000L
+L3:
+    return v2
+",
+            catch_type
+        );
+        let options = PseudocodeOptions::new();
+        assert_eq!(code.pseudocode(&dex, options).unwrap(), expected);
+
+        // add-int/lit8 v0, v1, 0x2; rsub-int v0, v1, 0x5; cmpl-float v0, v1, v2;
+        // int-to-long v0, v2; aget v0, v1, v2; xor-int/2addr v0, v1; return-void
+        let insns = [
+            0x00d8, 0x0201, 0x10d1, 5, 0x002d, 0x0201, 0x2081, 0x0044, 0x0201, 0x10b7, 0x000e,
+        ];
+        let data = code_item(&insns, None);
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        assert_eq!(
+            code.pseudocode(&dex, options).unwrap(),
+            "    v0 = v1 + 0x2
+    v0 = 0x5 - v1
+    cmpl-float v0, v1, v2
+    v0 = (long) v2
+    v0 = v1[v2]
+    v0 = v0 ^ v1
+    return
+"
+        );
+        assert_eq!(
+            code.pseudocode(&dex, options.max_lines(2)).unwrap(),
+            "    v0 = v1 + 0x2
+    v0 = 0x5 - v1
+    // 5 more instructions
+"
+        );
+
+        // goto +2 lands in the middle of const/16
+        let data = code_item(&[0x0228, 0x0013, 1, 0x000e], None);
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        assert_eq!(
+            code.pseudocode(&dex, options).unwrap_err().to_string(),
+            "Malformed entity: Target 2 is not at an instruction"
+        );
+    }
+
+    #[test]
+    fn test_method_pseudocode() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let options = PseudocodeOptions::new().simple_names(true);
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let pseudocode = method.pseudocode(&dex, options).expect("cannot render");
+                assert_eq!(method.code().is_some(), !pseudocode.is_empty());
+            }
+        }
+
+        let method = |class: &str, name: &str, params: usize, options| {
+            let class = dex
+                .find_class_by_name(class)
+                .expect("cannot read class")
+                .expect("no class");
+            let method = class
+                .methods()
+                .find(|method| method.name() == name && method.params().len() == params)
+                .expect("no method");
+            method.pseudocode(&dex, options).expect("cannot render")
+        };
+        let serializer = "Lmobi/intuitit/android/internal/utils/FastXmlSerializer;";
+        assert_eq!(
+            method(serializer, "setFeature", 2, PseudocodeOptions::new()),
+            "    v0 = \"http://xmlpull.org/v1/doc/features.html#indent-output\"
+    v0 = java.lang.String.equals(v2, v0)
+    if (v0 == 0) goto L0
+    return
+L0:
+    v0 = new java.lang.UnsupportedOperationException
+    java.lang.UnsupportedOperationException.<init>(v0)
+    throw v0
+"
+        );
+        assert_eq!(
+            method("Lorg/adw/launcher/CellLayout;", "addView", 3, options),
+            "    v0 = v6
+    v0 = (CellLayout$LayoutParams) v0
+    v1 = v0
+    v2 = 0x1
+    v1.regenerateId = v2
+    try {
+        invoke-super {v3, v4, v5, v6}, Lmobi/intuitit/android/widget/WidgetCellLayout;->addView(Landroid/view/View;ILandroid/view/ViewGroup$LayoutParams;)V
+    } catch (Exception) goto L1
+L0:
+    return
+L1:
+    v2 = exception
+    goto L0
+"
+        );
+    }
+}
@@ -1,14 +1,16 @@
 use crate::Result;
 use scroll::{ctx, Pread};
-use std::{cmp::Ordering, fmt::Debug};
+use std::{borrow::Cow, cmp::Ordering, fmt::Debug};
 
 pub(crate) struct Section<'a> {
-    inner: &'a [u8],
+    inner: Cow<'a, [u8]>,
+    /// See `Dex::log_label`.
+    log_label: Option<&'a str>,
 }
 
 impl<'a> Section<'a> {
-    pub(crate) fn new(inner: &'a [u8]) -> Self {
-        Section { inner }
+    pub(crate) fn new(inner: Cow<'a, [u8]>, log_label: Option<&'a str>) -> Self {
+        Section { inner, log_label }
     }
 
     /// Binary search the contents of this section.
@@ -23,7 +25,7 @@ impl<'a> Section<'a> {
     where
         S: std::fmt::Debug,
         F: Fn(&T, &S) -> Result<Ordering>,
-        T: ctx::TryFromCtx<'a, C, Size = usize, Error = scroll::Error> + Debug,
+        T: for<'c> ctx::TryFromCtx<'c, C, Size = usize, Error = scroll::Error> + Debug,
     {
         if self.inner.is_empty() {
             return Ok(None);
@@ -33,7 +35,7 @@ impl<'a> Section<'a> {
         let _: T = self.inner.gread_with(&mut size, ctx)?;
         // Number of elements  = Size of buffer / Item size
         let len = self.inner.len() / size;
-        debug!(target: "binary-search", "binary-search: item size: {}, buffer length: {}, array length: {}, element: {:?}",
+        dex_debug!(self.log_label, target: "binary-search", "binary-search: item size: {}, buffer length: {}, array length: {}, element: {:?}",
                 size, self.inner.len(), len, *element);
         let (mut start, mut end) = (0, len - 1);
         while start < end {
@@ -41,10 +43,10 @@ impl<'a> Section<'a> {
             let mid_offset = mid * size;
             let item = self.inner.pread_with(mid_offset, ctx)?;
             let result = predicate(&item, element)?;
-            debug!(target: "binary-search", "binary-search: index: {}, item: {:?}, result: {:?}", mid, item, result);
+            dex_debug!(self.log_label, target: "binary-search", "binary-search: index: {}, item: {:?}, result: {:?}", mid, item, result);
             match result {
                 Ordering::Equal => {
-                    debug!(target: "binary-search", "binary search: success! index: {}", mid);
+                    dex_debug!(self.log_label, target: "binary-search", "binary search: success! index: {}", mid);
                     return Ok(Some(mid));
                 }
                 Ordering::Less => end = mid - 1,
@@ -54,7 +56,7 @@ impl<'a> Section<'a> {
         let start_offset = start * size;
         let item = self.inner.pread_with(start_offset, ctx)?;
         Ok(if predicate(&item, element)? == Ordering::Equal {
-            debug!(target: "binary-search", "binary search: success! index: {}", start);
+            dex_debug!(self.log_label, target: "binary-search", "binary search: success! index: {}", start);
             Some(start)
         } else {
             None
@@ -64,6 +66,6 @@ impl<'a> Section<'a> {
 
 impl<'a> AsRef<[u8]> for Section<'a> {
     fn as_ref(&self) -> &[u8] {
-        self.inner
+        &self.inner
     }
 }
@@ -0,0 +1,220 @@
+//! Items of the data section shared by several classes, methods or fields.
+//!
+//! Compilers deduplicate identical items such as annotation sets and type lists, so sharing
+//! them is normal. Other items, like the code of a method, are never shared by the tools
+//! that build dex files across classes and point to a file that was patched by hand.
+use std::collections::BTreeMap;
+
+use getset::{CopyGetters, Getters};
+
+use crate::{dex::ItemType, field::FieldId, jtype::TypeId, method::MethodId, uint};
+
+/// Kinds of data section items referenced by offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SharedItemKind {
+    /// The interfaces of a class.
+    TypeList,
+    /// The annotations directory of a class.
+    AnnotationsDirectory,
+    /// The annotations of a class, field or method.
+    AnnotationSet,
+    /// The annotations of the parameters of a method.
+    AnnotationSetRefList,
+    /// The fields and methods of a class.
+    ClassData,
+    /// The initial values of the static fields of a class.
+    EncodedArray,
+    /// The code of a method.
+    Code,
+}
+
+impl SharedItemKind {
+    /// The type of the item in the map list.
+    pub fn item_type(self) -> ItemType {
+        match self {
+            SharedItemKind::TypeList => ItemType::TypeList,
+            SharedItemKind::AnnotationsDirectory => ItemType::AnnotationsDirectoryItem,
+            SharedItemKind::AnnotationSet => ItemType::AnnotationSetItem,
+            SharedItemKind::AnnotationSetRefList => ItemType::AnnotationSetRefList,
+            SharedItemKind::ClassData => ItemType::ClassDataItem,
+            SharedItemKind::EncodedArray => ItemType::EncodedArrayItem,
+            SharedItemKind::Code => ItemType::CodeItem,
+        }
+    }
+}
+
+/// The class, field or method which refers to an item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Owner {
+    /// A class, by its type.
+    Class(TypeId),
+    /// A field defined by `class`.
+    Field { class: TypeId, field: FieldId },
+    /// A method defined by `class`.
+    Method { class: TypeId, method: MethodId },
+}
+
+impl Owner {
+    /// The class which is or defines the owner.
+    pub fn class(&self) -> TypeId {
+        match *self {
+            Owner::Class(class) => class,
+            Owner::Field { class, .. } | Owner::Method { class, .. } => class,
+        }
+    }
+}
+
+/// How an item came to be shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sharing {
+    /// The item is of a kind that compilers deduplicate.
+    Deduplicated,
+    /// The item is of a kind that compilers never share between its owners, such as class
+    /// data or the code of methods of different classes.
+    Suspicious,
+}
+
+/// An item referred to by more than one owner.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct SharedOffset {
+    /// Offset of the item from the start of the file.
+    #[get_copy = "pub"]
+    offset: uint,
+    /// The kind of the item.
+    #[get_copy = "pub"]
+    kind: SharedItemKind,
+    /// The distinct owners of the item, in order.
+    #[get = "pub"]
+    owners: Vec<Owner>,
+    /// Whether the sharing is expected.
+    #[get_copy = "pub"]
+    sharing: Sharing,
+}
+
+/// Items of a dex file shared by several owners, see `Dex::shared_offsets_report`.
+#[derive(Debug, Default, Getters)]
+#[get = "pub"]
+pub struct SharedOffsetsReport {
+    /// The shared items, ordered by kind and offset.
+    entries: Vec<SharedOffset>,
+}
+
+impl SharedOffsetsReport {
+    /// The shared items that compilers don't produce.
+    pub fn suspicious(&self) -> impl Iterator<Item = &SharedOffset> + '_ {
+        self.entries
+            .iter()
+            .filter(|entry| entry.sharing == Sharing::Suspicious)
+    }
+
+    /// Returns the shared item of the kind at the offset, if any.
+    pub fn get(&self, kind: SharedItemKind, offset: uint) -> Option<&SharedOffset> {
+        self.entries
+            .binary_search_by_key(&(kind, offset), |entry| (entry.kind, entry.offset))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+}
+
+/// Annotation sets stored at different offsets with the same content, see
+/// `Dex::duplicate_annotation_sets`.
+#[cfg(feature = "analysis")]
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct DuplicateAnnotationSets {
+    /// The `AnnotationSetItem::content_hash` of the sets.
+    #[get_copy = "pub"]
+    content_hash: u64,
+    /// Offsets of the sets from the start of the file, in increasing order.
+    #[get = "pub"]
+    offsets: Vec<uint>,
+    /// The distinct classes, fields and methods annotated with one of the sets, in the
+    /// order of the offsets. The sets of parameter annotations have no owner here.
+    #[get = "pub"]
+    owners: Vec<Owner>,
+}
+
+#[cfg(feature = "analysis")]
+impl DuplicateAnnotationSets {
+    pub(crate) fn new(content_hash: u64, offsets: Vec<uint>, recorder: &OwnerRecorder) -> Self {
+        let mut owners = Vec::new();
+        for offset in &offsets {
+            for owner in recorder.owners(SharedItemKind::AnnotationSet, *offset) {
+                if !owners.contains(owner) {
+                    owners.push(*owner);
+                }
+            }
+        }
+        Self {
+            content_hash,
+            offsets,
+            owners,
+        }
+    }
+}
+
+/// Records the owner of each offset requested during a walk of the classes.
+#[derive(Default)]
+pub(crate) struct OwnerRecorder {
+    owners: BTreeMap<(SharedItemKind, uint), Vec<Owner>>,
+}
+
+impl OwnerRecorder {
+    /// Records that `owner` refers to the item at `offset`. Null offsets are ignored.
+    pub(crate) fn record(&mut self, kind: SharedItemKind, offset: uint, owner: Owner) {
+        if offset == 0 {
+            return;
+        }
+        let owners = self.owners.entry((kind, offset)).or_default();
+        if !owners.contains(&owner) {
+            owners.push(owner);
+        }
+    }
+
+    /// The recorded items, ordered by kind and offset.
+    pub(crate) fn items(&self) -> impl Iterator<Item = (SharedItemKind, uint)> + '_ {
+        self.owners.keys().cloned()
+    }
+
+    /// The owners of the item of the kind at the offset, in the order they were recorded.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn owners(&self, kind: SharedItemKind, offset: uint) -> &[Owner] {
+        self.owners
+            .get(&(kind, offset))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The items with more than one owner.
+    pub(crate) fn into_report(self, log_label: Option<&str>) -> SharedOffsetsReport {
+        let entries = self
+            .owners
+            .into_iter()
+            .filter(|(_, owners)| owners.len() > 1)
+            .map(|((kind, offset), owners)| {
+                let sharing = sharing(kind, &owners);
+                dex_debug!(log_label, target: "sharing", "{:?} at {} shared by {} owners: {:?}", kind, offset, owners.len(), sharing);
+                SharedOffset {
+                    offset,
+                    kind,
+                    owners,
+                    sharing,
+                }
+            })
+            .collect();
+        SharedOffsetsReport { entries }
+    }
+}
+
+fn sharing(kind: SharedItemKind, owners: &[Owner]) -> Sharing {
+    match kind {
+        SharedItemKind::ClassData => Sharing::Suspicious,
+        SharedItemKind::Code
+            if owners
+                .iter()
+                .any(|owner| owner.class() != owners[0].class()) =>
+        {
+            Sharing::Suspicious
+        }
+        _ => Sharing::Deduplicated,
+    }
+}
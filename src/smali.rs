@@ -0,0 +1,745 @@
+//! Renders methods and their code in the smali syntax of baksmali. The operands are
+//! decoded from the code units by instruction format, index operands are resolved to
+//! their descriptors, branch targets, switch payloads and try blocks get labels, and the
+//! catch handlers are written as `.catch` and `.catchall` directives.
+//!
+//! The output differs from baksmali's defaults in a few ways: registers are always
+//! written as `vN`, like `baksmali --no-parameter-registers`, debug info (`.line`,
+//! `.local`, ...) isn't written, and call sites are written by index, e.g.
+//! `call_site_0`, without their bootstrap arguments.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    code::{ArrayValues, CodeItem, ExceptionType, SwitchPayload},
+    error::Error,
+    jtype::Type,
+    method::{AccessFlags, FieldOrMethodId, Method, MethodHandleType, Proto},
+    opcode::{Format, IndexOperand, Instruction, InstructionKind, Instructions, Opcode},
+    source::DexSource,
+    uint, ushort, Dex, Result,
+};
+
+/// Opcodes of the instructions referring to a payload.
+const FILL_ARRAY_DATA: u8 = 0x26;
+const PACKED_SWITCH: u8 = 0x2b;
+const SPARSE_SWITCH: u8 = 0x2c;
+
+impl Method {
+    /// Renders the method in smali: the `.method` line with the access flags, name and
+    /// prototype, the code if the method has any, see `CodeItem::to_smali`, and
+    /// `.end method`.
+    pub fn to_smali<S: DexSource>(&self, dex: &Dex<S>) -> Result<String> {
+        let mut out = String::from(".method ");
+        for (flag, name) in AccessFlags::MODIFIERS {
+            if self.access_flags.contains(*flag) {
+                out.push_str(name);
+                out.push(' ');
+            }
+        }
+        out.push_str(&self.name.to_string());
+        write_proto(self.params.iter(), &self.return_type, &mut out);
+        out.push('\n');
+        if let Some(code) = &self.code {
+            out.push_str(&code.to_smali(dex)?);
+        }
+        out.push_str(".end method\n");
+        Ok(out)
+    }
+}
+
+impl CodeItem {
+    /// Renders the code in smali, indented to be the body of a `.method`: the
+    /// `.registers` directive, then the instructions separated by empty lines. Labels
+    /// are numbered by kind in address order, e.g. `:goto_0`, `:cond_1`, `:catch_0`, so
+    /// the output only depends on the code. The alignment `nop` before a payload is
+    /// dropped, and switch payloads no switch refers to are replaced by a comment.
+    ///
+    /// Fails if an instruction can't be decoded, if an index operand can't be resolved
+    /// or if a branch target, a try block or a catch handler doesn't fall on an
+    /// instruction.
+    pub fn to_smali<S: DexSource>(&self, dex: &Dex<S>) -> Result<String> {
+        let insns = Instructions::new(&self.insns).collect::<Result<Vec<_>>>()?;
+        let mut renderer = Renderer::new(dex, self, &insns)?;
+        let _ = writeln!(renderer.out, "    .registers {}", self.registers_size);
+        for (i, insn) in insns.iter().enumerate() {
+            renderer.write_try_ends(insn.addr)?;
+            if is_padding(&insns, i) && !renderer.labels.has_labels(insn.addr) {
+                renderer.labels.written.insert(insn.addr);
+                continue;
+            }
+            renderer.out.push('\n');
+            renderer.write_labels(insn.addr);
+            renderer.write_insn(insn)?;
+        }
+        let end = self.insns.len();
+        renderer.write_try_ends(end)?;
+        renderer.write_labels(end);
+        renderer.labels.check_written()?;
+        Ok(renderer.out)
+    }
+}
+
+/// Kinds of labels, in the order they are written before an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    TryStart,
+    Catch,
+    CatchAll,
+    Goto,
+    Cond,
+    PackedSwitch,
+    SparseSwitch,
+    PackedSwitchData,
+    SparseSwitchData,
+    Array,
+    TryEnd,
+}
+
+impl LabelKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            LabelKind::TryStart => "try_start",
+            LabelKind::Catch => "catch",
+            LabelKind::CatchAll => "catchall",
+            LabelKind::Goto => "goto",
+            LabelKind::Cond => "cond",
+            LabelKind::PackedSwitch => "pswitch",
+            LabelKind::SparseSwitch => "sswitch",
+            LabelKind::PackedSwitchData => "pswitch_data",
+            LabelKind::SparseSwitchData => "sswitch_data",
+            LabelKind::Array => "array",
+            LabelKind::TryEnd => "try_end",
+        }
+    }
+}
+
+/// The labels of a code item, numbered by kind in address order.
+pub(crate) struct Labels {
+    /// Number of each label, by kind and address.
+    numbers: BTreeMap<(LabelKind, usize), usize>,
+    /// Address of the switch referring to each switch payload.
+    switches: BTreeMap<usize, usize>,
+    /// Addresses whose labels were written.
+    written: BTreeSet<usize>,
+}
+
+impl Labels {
+    fn collect(code: &CodeItem, insns: &[Instruction<'_>]) -> Result<Self> {
+        let len = code.insns.len();
+        let target = |addr: usize, offset: i64| {
+            let target = addr as i64 + offset;
+            if target < 0 || target > len as i64 {
+                return Err(Error::MalFormed(format!(
+                    "Target {} of the instruction at {} is out of bounds",
+                    target, addr
+                )));
+            }
+            Ok(target as usize)
+        };
+        let mut labels = BTreeSet::new();
+        let mut switches = BTreeMap::new();
+        for insn in insns {
+            let (kind, offset) = match insn_target(insn) {
+                Some(target) => target,
+                None => continue,
+            };
+            let addr = target(insn.addr, offset)?;
+            if kind == LabelKind::PackedSwitchData || kind == LabelKind::SparseSwitchData {
+                switches.entry(addr).or_insert(insn.addr);
+            }
+            labels.insert((kind, addr));
+        }
+        for (&payload, &switch) in &switches {
+            let (payload, kind) = match code.resolve_switch(payload as u32)? {
+                SwitchPayload::Packed(payload) => {
+                    (payload.entries().collect(), LabelKind::PackedSwitch)
+                }
+                SwitchPayload::Sparse(payload) => (
+                    payload.entries().collect::<Vec<_>>(),
+                    LabelKind::SparseSwitch,
+                ),
+            };
+            for (_, offset) in payload {
+                labels.insert((kind, target(switch, i64::from(offset))?));
+            }
+        }
+        for try_block in code.tries.iter() {
+            let start = try_block.start_addr() as usize;
+            labels.insert((LabelKind::TryStart, start));
+            labels.insert((
+                LabelKind::TryEnd,
+                start + usize::from(try_block.insn_count()),
+            ));
+            for handler in try_block.catch_handlers() {
+                let kind = match handler.exception() {
+                    ExceptionType::BaseException => LabelKind::CatchAll,
+                    ExceptionType::Ty(_) => LabelKind::Catch,
+                };
+                labels.insert((kind, handler.addr() as usize));
+            }
+        }
+        let mut numbers = BTreeMap::new();
+        let mut counts: BTreeMap<LabelKind, usize> = BTreeMap::new();
+        for (kind, addr) in labels {
+            let count = counts.entry(kind).or_default();
+            numbers.insert((kind, addr), *count);
+            *count += 1;
+        }
+        Ok(Self {
+            numbers,
+            switches,
+            written: BTreeSet::new(),
+        })
+    }
+
+    fn name(&self, kind: LabelKind, addr: usize) -> String {
+        // every label used by an instruction or a try block was collected
+        format!(":{}_{}", kind.prefix(), self.numbers[&(kind, addr)])
+    }
+
+    /// Addresses of the targets of the branches, switch cases and catch handlers.
+    pub(crate) fn targets(&self) -> BTreeSet<usize> {
+        self.numbers
+            .keys()
+            .filter(|(kind, _)| match kind {
+                LabelKind::Goto
+                | LabelKind::Cond
+                | LabelKind::PackedSwitch
+                | LabelKind::SparseSwitch
+                | LabelKind::Catch
+                | LabelKind::CatchAll => true,
+                LabelKind::TryStart
+                | LabelKind::TryEnd
+                | LabelKind::PackedSwitchData
+                | LabelKind::SparseSwitchData
+                | LabelKind::Array => false,
+            })
+            .map(|&(_, addr)| addr)
+            .collect()
+    }
+
+    fn has_labels(&self, addr: usize) -> bool {
+        self.numbers
+            .keys()
+            .any(|&(kind, label_addr)| label_addr == addr && kind != LabelKind::TryEnd)
+    }
+
+    /// Fails if a label was not written, because its address is not the address of an
+    /// instruction.
+    fn check_written(&self) -> Result<()> {
+        match self
+            .numbers
+            .keys()
+            .find(|(_, addr)| !self.written.contains(addr))
+        {
+            Some(&(kind, addr)) => Err(Error::MalFormed(format!(
+                "Label {} at {} is not at an instruction",
+                self.name(kind, addr),
+                addr
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Renders the instructions of a code item in smali.
+pub(crate) struct Renderer<'a, S> {
+    dex: &'a Dex<S>,
+    code: &'a CodeItem,
+    pub(crate) labels: Labels,
+    out: String,
+}
+
+impl<'a, S: DexSource> Renderer<'a, S> {
+    /// A renderer of the decoded instructions of `code`. Fails if a branch target is out
+    /// of bounds or a switch payload can't be read.
+    pub(crate) fn new(
+        dex: &'a Dex<S>,
+        code: &'a CodeItem,
+        insns: &[Instruction<'_>],
+    ) -> Result<Self> {
+        Ok(Self {
+            dex,
+            code,
+            labels: Labels::collect(code, insns)?,
+            out: String::new(),
+        })
+    }
+
+    /// Writes the end labels of the try blocks ending at `addr` and their catch
+    /// directives, right after the last instruction of the blocks.
+    fn write_try_ends(&mut self, addr: usize) -> Result<()> {
+        if !self.labels.numbers.contains_key(&(LabelKind::TryEnd, addr)) {
+            return Ok(());
+        }
+        let end = self.labels.name(LabelKind::TryEnd, addr);
+        let _ = writeln!(self.out, "    {}", end);
+        for try_block in self.code.tries.iter() {
+            let start = try_block.start_addr() as usize;
+            if start + usize::from(try_block.insn_count()) != addr {
+                continue;
+            }
+            let start = self.labels.name(LabelKind::TryStart, start);
+            for handler in try_block.catch_handlers() {
+                let handler_addr = handler.addr() as usize;
+                let _ = match handler.exception() {
+                    ExceptionType::Ty(jtype) => writeln!(
+                        self.out,
+                        "    .catch {} {{{} .. {}}} {}",
+                        jtype,
+                        start,
+                        end,
+                        self.labels.name(LabelKind::Catch, handler_addr)
+                    ),
+                    ExceptionType::BaseException => writeln!(
+                        self.out,
+                        "    .catchall {{{} .. {}}} {}",
+                        start,
+                        end,
+                        self.labels.name(LabelKind::CatchAll, handler_addr)
+                    ),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn write_labels(&mut self, addr: usize) {
+        let names: Vec<String> = self
+            .labels
+            .numbers
+            .keys()
+            .filter(|&&(kind, label_addr)| label_addr == addr && kind != LabelKind::TryEnd)
+            .map(|&(kind, _)| self.labels.name(kind, addr))
+            .collect();
+        for name in names {
+            let _ = writeln!(self.out, "    {}", name);
+        }
+        self.labels.written.insert(addr);
+    }
+
+    fn write_insn(&mut self, insn: &Instruction<'_>) -> Result<()> {
+        match insn.kind {
+            InstructionKind::Op(opcode) => {
+                if opcode.is_unused() {
+                    return Err(Error::MalFormed(format!(
+                        "Unused opcode 0x{:02x} at {}",
+                        opcode.value, insn.addr
+                    )));
+                }
+                let line = self.insn_line(insn, opcode)?;
+                let _ = writeln!(self.out, "    {}", line);
+            }
+            InstructionKind::PackedSwitchPayload | InstructionKind::SparseSwitchPayload => {
+                self.write_switch_payload(insn.addr)?
+            }
+            InstructionKind::FillArrayDataPayload => self.write_array_payload(insn.addr)?,
+        }
+        Ok(())
+    }
+
+    /// The mnemonic of the instruction followed by its operands.
+    pub(crate) fn insn_line(&self, insn: &Instruction<'_>, opcode: &Opcode) -> Result<String> {
+        let operands = self.operands(insn, opcode)?;
+        if operands.is_empty() {
+            return Ok(opcode.mnemonic.to_string());
+        }
+        Ok(format!("{} {}", opcode.mnemonic, operands.join(", ")))
+    }
+
+    /// The operands of the instruction in smali, registers as `vN`.
+    pub(crate) fn operands(&self, insn: &Instruction<'_>, opcode: &Opcode) -> Result<Vec<String>> {
+        let units = insn.units;
+        let a = (units[0] >> 8) & 0xf;
+        let b = units[0] >> 12;
+        let aa = units[0] >> 8;
+        let wide = opcode.mnemonic.starts_with("const-wide");
+        let lit = |value: i64| literal(value, wide);
+        Ok(match opcode.format {
+            Format::F10x => vec![],
+            Format::F12x => vec![reg(a), reg(b)],
+            Format::F11n => vec![reg(a), lit(i64::from((units[0] >> 8) as i8 >> 4))],
+            Format::F11x => vec![reg(aa)],
+            Format::F10t | Format::F20t | Format::F30t => vec![self.branch_label(insn)],
+            Format::F22x => vec![reg(aa), reg(units[1])],
+            Format::F21t => vec![reg(aa), self.branch_label(insn)],
+            Format::F21s => vec![reg(aa), lit(i64::from(units[1] as i16))],
+            Format::F21h => {
+                let shift = if wide { 48 } else { 16 };
+                vec![reg(aa), lit(i64::from(units[1] as i16) << shift)]
+            }
+            Format::F21c | Format::F31c => vec![reg(aa), self.index(insn)?],
+            Format::F23x => vec![reg(aa), reg(units[1] & 0xff), reg(units[1] >> 8)],
+            Format::F22b => vec![
+                reg(aa),
+                reg(units[1] & 0xff),
+                lit(i64::from((units[1] >> 8) as i8)),
+            ],
+            Format::F22t => vec![reg(a), reg(b), self.branch_label(insn)],
+            Format::F22s => vec![reg(a), reg(b), lit(i64::from(units[1] as i16))],
+            Format::F22c => vec![reg(a), reg(b), self.index(insn)?],
+            Format::F32x => vec![reg(units[1]), reg(units[2])],
+            Format::F31t => vec![reg(aa), self.branch_label(insn)],
+            Format::F31i => vec![reg(aa), lit(i64::from(int(units[1], units[2])))],
+            Format::F35c | Format::F45cc => {
+                let registers = [
+                    units[2] & 0xf,
+                    (units[2] >> 4) & 0xf,
+                    (units[2] >> 8) & 0xf,
+                    units[2] >> 12,
+                    a,
+                ];
+                let registers: Vec<String> = registers
+                    .iter()
+                    .take(usize::from(b))
+                    .map(|&r| reg(r))
+                    .collect();
+                vec![format!("{{{}}}", registers.join(", ")), self.index(insn)?]
+            }
+            Format::F3rc | Format::F4rcc => {
+                let registers = match aa {
+                    0 => String::from("{}"),
+                    count => format!(
+                        "{{{} .. {}}}",
+                        reg(units[2]),
+                        reg_index(uint::from(units[2]) + uint::from(count) - 1)
+                    ),
+                };
+                vec![registers, self.index(insn)?]
+            }
+            Format::F51l => {
+                let low = int(units[1], units[2]) as u32;
+                let high = int(units[3], units[4]) as u32;
+                vec![
+                    reg(aa),
+                    lit((u64::from(high) << 32 | u64::from(low)) as i64),
+                ]
+            }
+        })
+    }
+
+    fn branch_label(&self, insn: &Instruction<'_>) -> String {
+        // the targets were checked when collecting the labels
+        let (kind, offset) = insn_target(insn).expect("not a branch");
+        self.labels.name(kind, (insn.addr as i64 + offset) as usize)
+    }
+
+    /// Resolves the index operand of the instruction to its smali form.
+    pub(crate) fn index(&self, insn: &Instruction<'_>) -> Result<String> {
+        let dex = self.dex;
+        let operand = insn
+            .index_operand()
+            .ok_or_else(|| Error::MalFormed(format!("No index operand at {}", insn.addr)))?;
+        Ok(match operand {
+            IndexOperand::String(id) => escape(&dex.get_string(id)?.to_string()),
+            IndexOperand::Type(id) => dex.get_type(id)?.to_string(),
+            IndexOperand::Field(id) => dex.get_field_ref(id)?.to_string(),
+            IndexOperand::Method(id) => dex.get_method_ref(id)?.to_string(),
+            IndexOperand::MethodAndProto(method, proto) => format!(
+                "{}, {}",
+                dex.get_method_ref(method)?,
+                proto_descriptor(&dex.get_proto(proto)?)
+            ),
+            IndexOperand::CallSite(id) => format!("call_site_{}", id),
+            IndexOperand::MethodHandle(id) => {
+                let handle = dex.get_method_handle_item(id)?;
+                let target = match handle.id() {
+                    FieldOrMethodId::Field(id) => dex.get_field_ref(id)?.to_string(),
+                    FieldOrMethodId::Method(id) => dex.get_method_ref(id)?.to_string(),
+                };
+                format!("{}@{}", handle_type(handle.handle_type()), target)
+            }
+            IndexOperand::Proto(id) => proto_descriptor(&dex.get_proto(id)?),
+        })
+    }
+
+    fn write_switch_payload(&mut self, addr: usize) -> Result<()> {
+        let switch = match self.labels.switches.get(&addr) {
+            Some(&switch) => switch,
+            None => {
+                let _ = writeln!(self.out, "    # switch payload without a switch");
+                return Ok(());
+            }
+        };
+        match self.code.resolve_switch(addr as u32)? {
+            SwitchPayload::Packed(payload) => {
+                let _ = writeln!(
+                    self.out,
+                    "    .packed-switch {}",
+                    literal(i64::from(payload.first_key()), false)
+                );
+                for (_, offset) in payload.entries() {
+                    let target = (switch as i64 + i64::from(offset)) as usize;
+                    let label = self.labels.name(LabelKind::PackedSwitch, target);
+                    let _ = writeln!(self.out, "        {}", label);
+                }
+                self.out.push_str("    .end packed-switch\n");
+            }
+            SwitchPayload::Sparse(payload) => {
+                self.out.push_str("    .sparse-switch\n");
+                for (key, offset) in payload.entries() {
+                    let target = (switch as i64 + i64::from(offset)) as usize;
+                    let label = self.labels.name(LabelKind::SparseSwitch, target);
+                    let _ = writeln!(
+                        self.out,
+                        "        {} -> {}",
+                        literal(i64::from(key), false),
+                        label
+                    );
+                }
+                self.out.push_str("    .end sparse-switch\n");
+            }
+        }
+        Ok(())
+    }
+
+    fn write_array_payload(&mut self, addr: usize) -> Result<()> {
+        let (width, values) = array_literals(self.code, addr)?;
+        let _ = writeln!(self.out, "    .array-data {}", width);
+        for value in values {
+            let _ = writeln!(self.out, "        {}", value);
+        }
+        self.out.push_str("    .end array-data\n");
+        Ok(())
+    }
+}
+
+/// The element width of the `fill-array-data` payload at `addr` and its elements as
+/// literals, suffixed by their width like baksmali, e.g. `0x1t` for a byte.
+pub(crate) fn array_literals(code: &CodeItem, addr: usize) -> Result<(ushort, Vec<String>)> {
+    let payload = code.fill_array_data(addr as u32)?;
+    let width = payload.element_width();
+    let (values, suffix): (Vec<i64>, &str) = match payload.as_values(width)? {
+        ArrayValues::Bytes(values) => (values.into_iter().map(i64::from).collect(), "t"),
+        ArrayValues::Shorts(values) => (values.into_iter().map(i64::from).collect(), "s"),
+        ArrayValues::Ints(values) => (values.into_iter().map(i64::from).collect(), ""),
+        ArrayValues::Longs(values) => (values, "L"),
+    };
+    let values = values
+        .into_iter()
+        .map(|value| format!("{}{}", literal(value, false), suffix))
+        .collect();
+    Ok((width, values))
+}
+
+/// The kind of label and the offset of the target of a branch, or of the payload of a
+/// switch or `fill-array-data`.
+fn insn_target(insn: &Instruction<'_>) -> Option<(LabelKind, i64)> {
+    let opcode = match insn.kind {
+        InstructionKind::Op(opcode) => opcode,
+        _ => return None,
+    };
+    let offset = match insn.branch_offset() {
+        Some(offset) => i64::from(offset),
+        None if opcode.value == FILL_ARRAY_DATA && insn.units.len() == 3 => {
+            i64::from(int(insn.units[1], insn.units[2]))
+        }
+        None => return None,
+    };
+    let kind = match (opcode.value, opcode.format) {
+        (PACKED_SWITCH, _) => LabelKind::PackedSwitchData,
+        (SPARSE_SWITCH, _) => LabelKind::SparseSwitchData,
+        (FILL_ARRAY_DATA, _) => LabelKind::Array,
+        (_, Format::F21t) | (_, Format::F22t) => LabelKind::Cond,
+        _ => LabelKind::Goto,
+    };
+    Some((kind, offset))
+}
+
+/// Returns `true` if the instruction at `index` is the `nop` aligning the payload which
+/// follows it.
+pub(crate) fn is_padding(insns: &[Instruction<'_>], index: usize) -> bool {
+    insns[index].units[0] == 0
+        && insns
+            .get(index + 1)
+            .is_some_and(|next| !matches!(next.kind, InstructionKind::Op(_)))
+}
+
+pub(crate) fn int(low: ushort, high: ushort) -> i32 {
+    (uint::from(low) | uint::from(high) << 16) as i32
+}
+
+pub(crate) fn reg(register: ushort) -> String {
+    reg_index(uint::from(register))
+}
+
+pub(crate) fn reg_index(register: uint) -> String {
+    format!("v{}", register)
+}
+
+/// Formats a literal in hexadecimal like baksmali, e.g. `0x10`, `-0x1` or `0x1L`.
+pub(crate) fn literal(value: i64, wide: bool) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let suffix = if wide { "L" } else { "" };
+    format!("{}0x{:x}{}", sign, value.unsigned_abs(), suffix)
+}
+
+/// Quotes the string, escaping quotes, backslashes and control characters, and
+/// characters outside of printable ASCII as UTF-16 `\uXXXX` escapes.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\'' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(out, "\\u{:04x}", unit);
+                }
+            }
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn write_proto<'a>(params: impl Iterator<Item = &'a Type>, return_type: &Type, out: &mut String) {
+    out.push('(');
+    for param in params {
+        out.push_str(param.type_descriptor());
+    }
+    out.push(')');
+    out.push_str(return_type.type_descriptor());
+}
+
+fn proto_descriptor(proto: &Proto) -> String {
+    let mut out = String::new();
+    write_proto(proto.params().iter(), proto.return_type(), &mut out);
+    out
+}
+
+fn handle_type(handle_type: MethodHandleType) -> &'static str {
+    match handle_type {
+        MethodHandleType::StaticPut => "static-put",
+        MethodHandleType::StaticGet => "static-get",
+        MethodHandleType::InstancePut => "instance-put",
+        MethodHandleType::InstanceGet => "instance-get",
+        MethodHandleType::InvokeStatic => "invoke-static",
+        MethodHandleType::InvokeInstance => "invoke-instance",
+        MethodHandleType::InvokeConstructor => "invoke-constructor",
+        MethodHandleType::InvokeDirect => "invoke-direct",
+        MethodHandleType::InvokeInterface => "invoke-interface",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scroll::Pread;
+
+    use super::{escape, literal};
+    use crate::{code::CodeItem, test_utils::code_item, ushort, DexReader};
+
+    #[test]
+    fn test_code_to_smali() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        // const/4 v0, 0x1; if-eqz v0, +7; packed-switch v0, +9; goto -6; return-void;
+        // const-wide/high16 v0, 0x4000; return v2; then a nop and the payload of the
+        // switch, with the targets 7 and 10 for the keys 5 and 6
+        let mut insns: Vec<ushort> = vec![0x1012, 0x0038, 7, 0x002b, 9, 0, 0xfa28, 0x000e];
+        insns.extend_from_slice(&[0x0019, 0x4000, 0x020f, 0]);
+        insns.extend_from_slice(&[0x0100, 2, 5, 0, 4, 0, 7, 0]);
+        // a handler of type 0 at 7 and a catch-all handler at 10
+        let data = code_item(&insns, Some(&[1, 0x7f, 0, 7, 10]));
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        let catch_type = dex.get_type(0).expect("bad type");
+        let expected = format!(
+            "    .registers 3
+
+    :try_start_0
+    :goto_0
+    const/4 v0, 0x1
+
+    if-eqz v0, :cond_0
+    :try_end_0
+    .catch {} {{:try_start_0 .. :try_end_0}} :catch_0
+    .catchall {{:try_start_0 .. :try_end_0}} :catchall_0
+
+    packed-switch v0, :pswitch_data_0
+
+    goto :goto_0
+
+    :catch_0
+    :pswitch_0
+    return-void
+
+    :cond_0
+    const-wide/high16 v0, 0x4000000000000000L
+
+    :catchall_0
+    :pswitch_1
+    return v2
+
+    :pswitch_data_0
+    .packed-switch 0x5
+        :pswitch_0
+        :pswitch_1
+    .end packed-switch
+",
+            catch_type
+        );
+        assert_eq!(code.to_smali(&dex).expect("cannot render code"), expected);
+        // the labels only depend on the code
+        assert_eq!(code.to_smali(&dex).expect("cannot render code"), expected);
+
+        // goto +2 lands in the middle of const/16
+        let data = code_item(&[0x0228, 0x0013, 1, 0x000e], None);
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        assert_eq!(
+            code.to_smali(&dex).unwrap_err().to_string(),
+            "Malformed entity: Label :goto_0 at 2 is not at an instruction"
+        );
+        let data = code_item(&[0x0a28], None);
+        let code: CodeItem = data.pread_with(0, &dex).expect("bad code item");
+        assert!(code.to_smali(&dex).is_err());
+    }
+
+    #[test]
+    fn test_method_to_smali() {
+        let dex = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let mut methods = 0;
+        for class in dex.classes() {
+            let class = class.expect("bad class");
+            for method in class.methods() {
+                let smali = method.to_smali(&dex).expect("cannot render method");
+                assert!(smali.starts_with(".method "), "{}", smali);
+                assert!(smali.ends_with("\n.end method\n"), "{}", smali);
+                assert_eq!(method.code().is_some(), smali.contains(".registers"));
+                methods += 1;
+            }
+        }
+        assert!(methods > 1000);
+
+        let class = dex
+            .find_class_by_name("Lorg/adw/launcher/Launcher;")
+            .expect("cannot read class")
+            .expect("no class");
+        let method = class
+            .methods()
+            .find(|method| method.name() == "<init>")
+            .expect("no constructor");
+        let smali = method.to_smali(&dex).expect("cannot render method");
+        assert!(smali.starts_with(".method public constructor <init>()V\n"));
+        assert!(smali.contains("invoke-direct {v"));
+    }
+
+    #[test]
+    fn test_smali_literals() {
+        assert_eq!(literal(0, false), "0x0");
+        assert_eq!(literal(-1, false), "-0x1");
+        assert_eq!(literal(i64::MIN, true), "-0x8000000000000000L");
+        assert_eq!(
+            escape("a\"b'c\\\n\u{7f}é😀"),
+            "\"a\\\"b\\'c\\\\\\n\\u007f\\u00e9\\ud83d\\ude00\""
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! Parser for the source maps (SMAP) of [JSR-45](https://jcp.org/en/jsr/detail?id=45), as
+//! found in the `dalvik.annotation.SourceDebugExtension` of a class.
+//!
+//! Kotlin stores the lines of inlined functions past the end of the file that calls them. The
+//! `Kotlin` stratum maps these lines back to the file and line of the inlined function, and
+//! the `KotlinDebug` stratum maps them to the line of the call site.
+use std::{error, fmt, ops::Range};
+
+use getset::{CopyGetters, Getters};
+
+/// A parsed source map.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[get = "pub"]
+pub struct Smap {
+    /// Name of the generated file, such as `MainActivity.kt`.
+    output_file_name: String,
+    /// Name of the stratum used when none is requested.
+    default_stratum: String,
+    /// The strata, in order.
+    strata: Vec<Stratum>,
+}
+
+/// A stratum of a source map, mapping the lines of the generated file to the lines of its
+/// sources.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[get = "pub"]
+pub struct Stratum {
+    /// Name of the stratum, such as `Kotlin`.
+    name: String,
+    /// The source files.
+    files: Vec<SmapFile>,
+    /// The line mappings, in order.
+    lines: Vec<LineMapping>,
+}
+
+/// A source file of a stratum.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct SmapFile {
+    /// Id of the file, referred to by the line mappings.
+    #[get_copy = "pub"]
+    id: u32,
+    /// Name of the file, such as `Collections.kt`.
+    #[get = "pub"]
+    name: String,
+    /// Path of the file, such as `kotlin/collections/CollectionsKt___CollectionsKt`.
+    #[get = "pub"]
+    path: Option<String>,
+}
+
+/// Maps `repeat_count` consecutive input lines, starting at `input_start_line` of the file
+/// `file_id`, to `output_line_increment` output lines each, starting at `output_start_line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[get_copy = "pub"]
+pub struct LineMapping {
+    input_start_line: u32,
+    file_id: u32,
+    repeat_count: u32,
+    output_start_line: u32,
+    output_line_increment: u32,
+}
+
+impl LineMapping {
+    /// The input lines of the mapping.
+    pub fn input_range(&self) -> Range<u32> {
+        self.input_start_line..self.input_start_line + self.repeat_count
+    }
+
+    /// The output lines of the mapping.
+    pub fn output_range(&self) -> Range<u32> {
+        self.output_start_line
+            ..self.output_start_line + self.repeat_count * self.output_line_increment
+    }
+
+    /// Returns the input line which `output_line` is generated from, if it's in the mapping.
+    pub fn input_line(&self, output_line: u32) -> Option<u32> {
+        if !self.output_range().contains(&output_line) {
+            return None;
+        }
+        Some(
+            self.input_start_line
+                + (output_line - self.output_start_line) / self.output_line_increment,
+        )
+    }
+}
+
+impl Smap {
+    /// Parses a source map.
+    pub fn parse(smap: &str) -> Result<Smap, SmapError> {
+        Parser::new(smap).parse()
+    }
+
+    /// Returns the stratum named `name`.
+    pub fn stratum(&self, name: &str) -> Option<&Stratum> {
+        self.strata.iter().find(|stratum| stratum.name == name)
+    }
+}
+
+impl Stratum {
+    /// Returns the file with the id.
+    pub fn file(&self, id: u32) -> Option<&SmapFile> {
+        self.files.iter().find(|file| file.id == id)
+    }
+
+    /// Returns the source file and line which `output_line` is generated from. The first
+    /// mapping containing the line is used.
+    pub fn resolve(&self, output_line: u32) -> Option<(&SmapFile, u32)> {
+        self.lines.iter().find_map(|mapping| {
+            let line = mapping.input_line(output_line)?;
+            Some((self.file(mapping.file_id)?, line))
+        })
+    }
+}
+
+/// Error returned when a source map cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, CopyGetters, Getters)]
+pub struct SmapError {
+    /// Line of the source map at which the error was found, starting from 1.
+    #[get_copy = "pub"]
+    line: usize,
+    /// What is wrong with the line.
+    #[get = "pub"]
+    kind: SmapErrorKind,
+}
+
+/// Kinds of `SmapError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SmapErrorKind {
+    /// The first line is not `SMAP`.
+    MissingHeader,
+    /// The source map ends before its `*E` line.
+    UnexpectedEnd,
+    /// A line of a file section is not a file.
+    BadFileInfo(String),
+    /// A line of a line section is not a line mapping.
+    BadLineInfo(String),
+    /// A line mapping refers to a file which isn't in the stratum.
+    UnknownFileId(u32),
+    /// A file or line section is outside of a stratum.
+    NoStratum,
+    /// Embedded source maps are not supported.
+    EmbeddedSmap,
+}
+
+impl fmt::Display for SmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Malformed SMAP at line {}: ", self.line)?;
+        match self.kind {
+            SmapErrorKind::MissingHeader => write!(f, "expected SMAP header"),
+            SmapErrorKind::UnexpectedEnd => write!(f, "missing *E"),
+            SmapErrorKind::BadFileInfo(ref line) => write!(f, "bad file info: {}", line),
+            SmapErrorKind::BadLineInfo(ref line) => write!(f, "bad line info: {}", line),
+            SmapErrorKind::UnknownFileId(id) => write!(f, "unknown file id: {}", id),
+            SmapErrorKind::NoStratum => write!(f, "section outside of a stratum"),
+            SmapErrorKind::EmbeddedSmap => write!(f, "embedded SMAPs are not supported"),
+        }
+    }
+}
+
+impl error::Error for SmapError {}
+
+/// The section of a source map being parsed.
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Files,
+    Lines,
+    /// Vendor and unknown sections, which are skipped.
+    Other,
+}
+
+struct Parser<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    /// Number of the last line read, starting from 1.
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(smap: &'a str) -> Self {
+        Self {
+            lines: smap.lines().enumerate(),
+            line: 0,
+        }
+    }
+
+    fn error(&self, kind: SmapErrorKind) -> SmapError {
+        SmapError {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn next_line(&mut self) -> Result<&'a str, SmapError> {
+        match self.lines.next() {
+            Some((index, line)) => {
+                self.line = index + 1;
+                Ok(line.trim_end_matches('\r'))
+            }
+            None => Err(self.error(SmapErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    fn parse(mut self) -> Result<Smap, SmapError> {
+        if self.next_line().ok() != Some("SMAP") {
+            return Err(self.error(SmapErrorKind::MissingHeader));
+        }
+        let output_file_name = self.next_line()?.to_string();
+        let default_stratum = self.next_line()?.to_string();
+        let mut strata: Vec<Stratum> = Vec::new();
+        let mut section = Section::Other;
+        // line mappings without a file id use the one of the previous mapping
+        let mut file_id = 0;
+        loop {
+            let line = self.next_line()?;
+            if let Some(header) = line.strip_prefix('*') {
+                section = match header.chars().next() {
+                    Some('E') => break,
+                    Some('S') => {
+                        strata.push(Stratum {
+                            name: header[1..].trim().to_string(),
+                            files: Vec::new(),
+                            lines: Vec::new(),
+                        });
+                        file_id = 0;
+                        Section::Other
+                    }
+                    Some('F') => Section::Files,
+                    Some('L') => Section::Lines,
+                    Some('O') | Some('C') => return Err(self.error(SmapErrorKind::EmbeddedSmap)),
+                    _ => Section::Other,
+                };
+                if section != Section::Other && strata.is_empty() {
+                    return Err(self.error(SmapErrorKind::NoStratum));
+                }
+                continue;
+            }
+            let stratum = match strata.last_mut() {
+                Some(stratum) => stratum,
+                None => continue,
+            };
+            match section {
+                Section::Files => {
+                    let file = self.file_info(line)?;
+                    stratum.files.push(file);
+                }
+                Section::Lines => {
+                    let mapping = self.line_info(line, file_id)?;
+                    file_id = mapping.file_id;
+                    stratum.lines.push(mapping);
+                }
+                Section::Other => {}
+            }
+        }
+        for stratum in &strata {
+            if let Some(mapping) = stratum
+                .lines
+                .iter()
+                .find(|mapping| stratum.file(mapping.file_id).is_none())
+            {
+                return Err(self.error(SmapErrorKind::UnknownFileId(mapping.file_id)));
+            }
+        }
+        Ok(Smap {
+            output_file_name,
+            default_stratum,
+            strata,
+        })
+    }
+
+    /// Parses `+ id name` followed by a path line, or `id name`.
+    fn file_info(&mut self, line: &str) -> Result<SmapFile, SmapError> {
+        let bad_file_info = |parser: &Self| parser.error(SmapErrorKind::BadFileInfo(line.into()));
+        let (has_path, info) = match line.strip_prefix('+') {
+            Some(info) => (true, info.trim_start()),
+            None => (false, line),
+        };
+        let (id, name) = info.split_once(' ').ok_or_else(|| bad_file_info(self))?;
+        let id = id.parse().map_err(|_| bad_file_info(self))?;
+        let path = if has_path {
+            Some(self.next_line()?.to_string())
+        } else {
+            None
+        };
+        Ok(SmapFile {
+            id,
+            name: name.trim().to_string(),
+            path,
+        })
+    }
+
+    /// Parses `InputStartLine[#LineFileID][,RepeatCount]:OutputStartLine[,OutputLineIncrement]`.
+    fn line_info(&self, line: &str, file_id: u32) -> Result<LineMapping, SmapError> {
+        let parse = || -> Option<LineMapping> {
+            let (input, output) = line.split_once(':')?;
+            let (input, repeat_count) = match input.split_once(',') {
+                Some((input, repeat_count)) => (input, repeat_count.parse().ok()?),
+                None => (input, 1),
+            };
+            let (input_start_line, file_id) = match input.split_once('#') {
+                Some((input, file_id)) => (input.parse().ok()?, file_id.parse().ok()?),
+                None => (input.parse().ok()?, file_id),
+            };
+            let (output_start_line, output_line_increment) = match output.split_once(',') {
+                Some((output, increment)) => (output.parse().ok()?, increment.parse().ok()?),
+                None => (output.parse().ok()?, 1),
+            };
+            Some(LineMapping {
+                input_start_line,
+                file_id,
+                repeat_count,
+                output_start_line,
+                output_line_increment,
+            })
+        };
+        parse().ok_or_else(|| self.error(SmapErrorKind::BadLineInfo(line.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Smap, SmapErrorKind};
+
+    #[test]
+    fn test_parse_kotlin_smap() {
+        let smap = Smap::parse(include_str!("../resources/MainActivity.smap")).expect("bad SMAP");
+        assert_eq!(smap.output_file_name(), "MainActivity.kt");
+        assert_eq!(smap.default_stratum(), "Kotlin");
+        assert_eq!(smap.strata().len(), 2);
+
+        let kotlin = smap.stratum("Kotlin").expect("no Kotlin stratum");
+        let names: Vec<_> = kotlin
+            .files()
+            .iter()
+            .map(|file| file.name().as_str())
+            .collect();
+        assert_eq!(names, ["MainActivity.kt", "_Collections.kt", "View.kt"]);
+        assert_eq!(
+            kotlin.file(2).and_then(|file| file.path().as_deref()),
+            Some("kotlin/collections/CollectionsKt___CollectionsKt")
+        );
+        assert_eq!(kotlin.lines().len(), 4);
+        let mapping = kotlin.lines()[1];
+        assert_eq!(mapping.input_range(), 1863..1865);
+        assert_eq!(mapping.output_range(), 58..60);
+        // the file id carries over to the next mapping
+        assert_eq!(kotlin.lines()[2].file_id(), 2);
+
+        // a crash in the inlined forEach resolves to the library and to the call site
+        let (file, line) = kotlin.resolve(59).expect("line not mapped");
+        assert_eq!((file.name().as_str(), line), ("_Collections.kt", 1864));
+        let debug = smap.stratum("KotlinDebug").expect("no KotlinDebug stratum");
+        let (file, line) = debug.resolve(59).expect("call site not mapped");
+        assert_eq!((file.name().as_str(), line), ("MainActivity.kt", 31));
+        assert_eq!(kotlin.resolve(20).map(|(_, line)| line), Some(20));
+        assert!(debug.resolve(20).is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let kind = |smap: &str| Smap::parse(smap).map(|_| ()).map_err(|e| e.kind().clone());
+        assert_eq!(
+            kind("Foo.kt\nKotlin\n*E"),
+            Err(SmapErrorKind::MissingHeader)
+        );
+        assert_eq!(
+            kind("SMAP\nFoo.kt\nKotlin\n*S Kotlin\n*F\n1 Foo.kt\n*L\n1:1"),
+            Err(SmapErrorKind::UnexpectedEnd)
+        );
+        assert_eq!(
+            kind("SMAP\nFoo.kt\nKotlin\n*S Kotlin\n*F\n1 Foo.kt\n*L\n1#2:1\n*E"),
+            Err(SmapErrorKind::UnknownFileId(2))
+        );
+        let error = Smap::parse("SMAP\nFoo.kt\nKotlin\n*S Kotlin\n*L\n1:x\n*E").unwrap_err();
+        assert_eq!(error.kind(), &SmapErrorKind::BadLineInfo("1:x".into()));
+        assert_eq!(error.line(), 6);
+    }
+}
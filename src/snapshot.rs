@@ -0,0 +1,102 @@
+//! Copies of the bytes of items taken on first access, for sources whose bytes can change
+//! between reads, see `DexReaderBuilder::snapshot_on_access`.
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::{Deref, Range},
+};
+
+use crate::{
+    cache::Bypass,
+    source::DexSource,
+    sync::{RefCell, Shared},
+};
+
+bitflags! {
+    /// Kinds of items whose bytes are copied on first access, see
+    /// `DexReaderBuilder::snapshot_on_access`. Only the kinds of items whose extent is
+    /// known before parsing them can be copied.
+    #[derive(Default)]
+    pub struct SnapshotKinds: u8 {
+        /// The string data items, read by `Dex::get_string`.
+        const STRING_DATA = 0x1;
+        /// The annotation items, read by `Dex::get_annotation_item`.
+        const ANNOTATION_ITEMS = 0x2;
+        /// The encoded array items, read by `Dex::get_static_values` and
+        /// `Dex::get_call_site`, and the values decoded from them later on.
+        const ENCODED_ARRAYS = 0x4;
+    }
+}
+
+/// The bytes of an item: read from the source, or the copy of the item.
+pub(crate) enum ItemBytes<'a> {
+    Borrowed(Cow<'a, [u8]>),
+    Snapshot(Shared<[u8]>),
+}
+
+impl ItemBytes<'_> {
+    /// The copy of the item, `None` if the bytes are borrowed from the source.
+    pub(crate) fn into_snapshot(self) -> Option<Shared<[u8]>> {
+        match self {
+            ItemBytes::Borrowed(_) => None,
+            ItemBytes::Snapshot(bytes) => Some(bytes),
+        }
+    }
+}
+
+impl Deref for ItemBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ItemBytes::Borrowed(bytes) => bytes,
+            ItemBytes::Snapshot(bytes) => bytes,
+        }
+    }
+}
+
+/// The copies of the items, by the bits of their kind and their offset.
+type Items = HashMap<(u8, usize), Shared<[u8]>>;
+
+/// The copies of the items of the kinds to snapshot, by kind and offset, shared by the
+/// shallow clones of a `Dex`.
+#[derive(Clone)]
+pub(crate) struct Snapshots {
+    kinds: SnapshotKinds,
+    items: Shared<RefCell<Items>>,
+    bypass: Bypass,
+}
+
+impl Snapshots {
+    /// Copies the items of `kinds`, unless `bypass` is enabled.
+    pub(crate) fn new(kinds: SnapshotKinds, bypass: Bypass) -> Self {
+        Self {
+            kinds,
+            items: Shared::default(),
+            bypass,
+        }
+    }
+
+    /// The bytes of the item of `kind` at `bounds` in `source`. If the kind is copied, the
+    /// bytes are copied on the first access to the offset, and later accesses return that
+    /// copy whatever the source holds by then. While the cache bypass is enabled the
+    /// source is read afresh and nothing is copied.
+    pub(crate) fn bytes<'a, T: DexSource>(
+        &self,
+        kind: SnapshotKinds,
+        source: &'a T,
+        bounds: Range<usize>,
+    ) -> ItemBytes<'a> {
+        let len = bounds.end - bounds.start;
+        if !self.kinds.contains(kind) || self.bypass.is_enabled() {
+            return ItemBytes::Borrowed(source.read_at(bounds.start, len));
+        }
+        let bytes = self
+            .items
+            .borrow_mut()
+            .entry((kind.bits(), bounds.start))
+            .or_insert_with(|| source.read_at(bounds.start, len).into())
+            .clone();
+        ItemBytes::Snapshot(bytes)
+    }
+}
@@ -1,55 +1,273 @@
-use std::{clone::Clone, convert::AsRef, ops::Index, rc::Rc};
+//! The bytes a `Dex` is read from, see `DexSource`.
+use std::{borrow::Cow, clone::Clone, ops::Range, result::Result};
 
-use crate::ubyte;
+use crate::sync::Shared;
+
+/// Storage a `Dex` can be read from. Every `AsRef<[u8]>` type, such as a `Vec<u8>` or a
+/// memory map, is a `DexSource` whose bytes are read in place; `ChunkedSource` reads a
+/// file split into chunks without joining them.
+///
+/// Parsing an item needs its bytes in one slice, so an item which spans several chunks of
+/// a non-contiguous source is copied. Items whose extent is not known before parsing them
+/// are parsed from the bytes up to the end of the chunk, then from twice as many bytes
+/// each time the parse runs out of them.
+///
+/// Sources are `Send` and `Sync`, as the items read from a `Dex` keep its source to read
+/// their lazily read parts, and can be sent to other threads, see the `sync` module.
+pub trait DexSource: Send + Sync {
+    /// The size of the file in bytes.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the file is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `len` bytes starting at `offset`, borrowed if they are stored contiguously and
+    /// copied otherwise. Panics if the range is out of the bounds of the file, like slicing.
+    fn read_at(&self, offset: usize, len: usize) -> Cow<'_, [u8]>;
+
+    /// The number of bytes starting at `offset` which are stored contiguously, and so can
+    /// be read without copying. Defaults to the rest of the file.
+    fn contiguous_len(&self, offset: usize) -> usize {
+        self.len().saturating_sub(offset)
+    }
+
+    /// The whole file, if it is stored contiguously. Defaults to `None`.
+    fn as_contiguous(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl<T: AsRef<[u8]> + Send + Sync> DexSource for T {
+    fn len(&self) -> usize {
+        self.as_ref().len()
+    }
+
+    fn read_at(&self, offset: usize, len: usize) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self.as_ref()[offset..offset + len])
+    }
+
+    fn as_contiguous(&self) -> Option<&[u8]> {
+        Some(self.as_ref())
+    }
+}
+
+/// A file stored as a sequence of chunks, such as the blocks of a content-addressed store.
+/// Chunks may have different sizes; empty chunks are ignored.
+#[derive(Debug, Clone)]
+pub struct ChunkedSource<C> {
+    chunks: Vec<C>,
+    /// Start offset of each chunk in the file.
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl<C: AsRef<[u8]>> ChunkedSource<C> {
+    /// A file made of `chunks`, in order.
+    pub fn new<I: IntoIterator<Item = C>>(chunks: I) -> Self {
+        let chunks: Vec<C> = chunks
+            .into_iter()
+            .filter(|chunk| !chunk.as_ref().is_empty())
+            .collect();
+        let mut starts = Vec::with_capacity(chunks.len());
+        let mut len = 0;
+        for chunk in &chunks {
+            starts.push(len);
+            len += chunk.as_ref().len();
+        }
+        Self {
+            chunks,
+            starts,
+            len,
+        }
+    }
+
+    /// The chunks of the file.
+    pub fn chunks(&self) -> &[C] {
+        &self.chunks
+    }
+
+    /// The index of the chunk containing `offset`, which must be in the file.
+    fn chunk_index(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        }
+    }
+}
+
+impl<C: AsRef<[u8]> + Send + Sync> DexSource for ChunkedSource<C> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn read_at(&self, offset: usize, len: usize) -> Cow<'_, [u8]> {
+        let end = offset + len;
+        assert!(
+            end <= self.len,
+            "range end index {} out of range for a source of length {}",
+            end,
+            self.len
+        );
+        if len == 0 {
+            return Cow::Borrowed(&[]);
+        }
+        let first = self.chunk_index(offset);
+        let start = offset - self.starts[first];
+        let chunk = self.chunks[first].as_ref();
+        if start + len <= chunk.len() {
+            return Cow::Borrowed(&chunk[start..start + len]);
+        }
+        let mut bytes = Vec::with_capacity(len);
+        bytes.extend_from_slice(&chunk[start..]);
+        for chunk in &self.chunks[first + 1..] {
+            let missing = len - bytes.len();
+            if missing == 0 {
+                break;
+            }
+            let chunk = chunk.as_ref();
+            bytes.extend_from_slice(&chunk[..missing.min(chunk.len())]);
+        }
+        Cow::Owned(bytes)
+    }
+
+    fn contiguous_len(&self, offset: usize) -> usize {
+        if offset >= self.len {
+            return 0;
+        }
+        let index = self.chunk_index(offset);
+        self.starts[index] + self.chunks[index].as_ref().len() - offset
+    }
+}
+
+/// The number of bytes an item of unknown extent is first parsed from when fewer are
+/// stored contiguously, see `Source::parse_at`.
+const MIN_WINDOW: usize = 256;
 
 /// Represents the source `Dex` file. This is a
 /// wrapper type that allows for shallow copies
 /// of the dex file's source.
 pub(crate) struct Source<T> {
-    inner: Rc<T>,
+    inner: Shared<T>,
 }
 
 impl<T> Source<T>
 where
-    T: AsRef<[u8]>,
+    T: DexSource,
 {
     pub(crate) fn new(inner: T) -> Self {
         Self {
-            inner: Rc::new(inner),
+            inner: Shared::new(inner),
         }
     }
-}
 
-impl<T> Index<usize> for Source<T>
-where
-    T: AsRef<[u8]>,
-{
-    type Output = ubyte;
+    /// The source, shared by the shallow copies.
+    pub(crate) fn inner(&self) -> &T {
+        &self.inner
+    }
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.as_ref()[index]
+    /// The size of the file in bytes.
+    pub(crate) fn len(&self) -> usize {
+        self.inner.len()
     }
-}
 
-impl<T> Index<std::ops::Range<usize>> for Source<T>
-where
-    T: AsRef<[u8]>,
-{
-    type Output = [ubyte];
+    /// The bytes in `range`. Panics if the range is out of bounds, like slicing.
+    pub(crate) fn slice(&self, range: Range<usize>) -> Cow<'_, [u8]> {
+        assert!(range.start <= range.end, "slice index starts after its end");
+        self.inner.read_at(range.start, range.end - range.start)
+    }
 
-    fn index(&self, index: std::ops::Range<usize>) -> &Self::Output {
-        &self.as_ref()[index]
+    /// The whole file, copied if it is not contiguous.
+    pub(crate) fn all(&self) -> Cow<'_, [u8]> {
+        self.slice(0..self.len())
     }
-}
 
-impl<T> Index<std::ops::RangeFrom<usize>> for Source<T>
-where
-    T: AsRef<[u8]>,
-{
-    type Output = [ubyte];
+    /// Runs `parse` on the bytes of an item starting at `offset`, with the offset of the
+    /// item in them. A contiguous source is parsed in place, so the offsets in the errors
+    /// are offsets in the file. Otherwise `parse` is given the bytes from `offset` to the
+    /// end of its chunk, and is run again on twice as many bytes each time it fails, until
+    /// it succeeds or is given the rest of the file.
+    pub(crate) fn parse_at<R, E, F>(&self, offset: usize, parse: F) -> Result<R, E>
+    where
+        F: Fn(&[u8], usize) -> Result<R, E>,
+    {
+        if let Some(bytes) = self.inner.as_contiguous() {
+            return parse(bytes, offset);
+        }
+        let remaining = self.len().saturating_sub(offset);
+        let mut window = self
+            .inner
+            .contiguous_len(offset)
+            .max(MIN_WINDOW)
+            .min(remaining);
+        loop {
+            let bytes = if remaining == 0 {
+                Cow::Borrowed(&[][..])
+            } else {
+                self.inner.read_at(offset, window)
+            };
+            match parse(&bytes, 0) {
+                Err(_) if window < remaining => window = (window * 2).min(remaining),
+                result => return result,
+            }
+        }
+    }
+
+    /// Reads a value at `offset`, like `Pread::pread_with` on the whole file.
+    pub(crate) fn pread_with<N, C, E>(&self, offset: usize, ctx: C) -> Result<N, E>
+    where
+        C: Copy,
+        N: for<'a> scroll::ctx::TryFromCtx<'a, C, Error = E, Size = usize>,
+        E: From<scroll::Error>,
+    {
+        use scroll::Pread;
+        self.parse_at(offset, |bytes, offset| {
+            Pread::<C, E>::pread_with(bytes, offset, ctx)
+        })
+    }
+
+    /// Reads a value at `offset` and moves the offset past it, like `Pread::gread_with` on
+    /// the whole file.
+    pub(crate) fn gread_with<N, C, E>(&self, offset: &mut usize, ctx: C) -> Result<N, E>
+    where
+        C: Copy,
+        N: for<'a> scroll::ctx::TryFromCtx<'a, C, Error = E, Size = usize>,
+        E: From<scroll::Error>,
+    {
+        use scroll::Pread;
+        let (value, size) = self.parse_at(*offset, |bytes, start| -> Result<(N, usize), E> {
+            let end = &mut start.clone();
+            let value = Pread::<C, E>::gread_with(bytes, end, ctx)?;
+            Ok((value, *end - start))
+        })?;
+        *offset += size;
+        Ok(value)
+    }
+
+    /// Reads a ULEB128 value at `offset` and moves the offset past it.
+    pub(crate) fn read_uleb128(&self, offset: &mut usize) -> Result<u64, scroll::Error> {
+        let (value, size) = self.parse_at(*offset, |bytes, start| -> Result<_, scroll::Error> {
+            let end = &mut start.clone();
+            let value = scroll::Uleb128::read(bytes, end)?;
+            Ok((value, *end - start))
+        })?;
+        *offset += size;
+        Ok(value)
+    }
 
-    fn index(&self, index: std::ops::RangeFrom<usize>) -> &Self::Output {
-        &self.as_ref()[index]
+    /// Feeds the bytes in `range` to `consume`, one contiguous piece at a time, without
+    /// copying them.
+    pub(crate) fn for_each_piece<F>(&self, range: Range<usize>, mut consume: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        let mut offset = range.start;
+        while offset < range.end {
+            let len = self.inner.contiguous_len(offset).min(range.end - offset);
+            consume(&self.inner.read_at(offset, len));
+            offset += len;
+        }
     }
 }
 
@@ -61,8 +279,36 @@ impl<T> Clone for Source<T> {
     }
 }
 
-impl<T: AsRef<[u8]>> AsRef<[u8]> for Source<T> {
-    fn as_ref(&self) -> &[ubyte] {
-        self.inner.as_ref().as_ref()
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedSource, DexSource};
+
+    #[test]
+    fn test_chunked_read_at() {
+        let file: Vec<u8> = (0..=255).collect();
+        let source = ChunkedSource::new(vec![
+            &file[..10],
+            &file[10..10],
+            &file[10..100],
+            &file[100..],
+        ]);
+        assert_eq!(source.len(), 256);
+        assert_eq!(source.chunks().len(), 3);
+        for (offset, len, borrowed) in &[
+            (0, 10, true),
+            (5, 5, true),
+            (10, 90, true),
+            (5, 10, false),
+            (0, 256, false),
+            (255, 1, true),
+            (256, 0, true),
+        ] {
+            let bytes = source.read_at(*offset, *len);
+            assert_eq!(&*bytes, &file[*offset..*offset + *len]);
+            assert_eq!(matches!(bytes, std::borrow::Cow::Borrowed(_)), *borrowed);
+        }
+        assert_eq!(source.contiguous_len(5), 5);
+        assert_eq!(source.contiguous_len(10), 90);
+        assert_eq!(source.contiguous_len(256), 0);
     }
 }
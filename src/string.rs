@@ -1,16 +1,27 @@
 //! Dex String utilities
 use std::{
-    convert::AsRef,
+    borrow::Cow,
     fmt,
-    num::NonZeroUsize,
+    hash::{Hash, Hasher},
     ops::{Deref, Range},
 };
 
-use cesu8::{from_java_cesu8, to_java_cesu8};
-use scroll::{self, ctx, Pread, Uleb128};
+use cesu8::to_java_cesu8;
+use scroll::{self, ctx, Uleb128};
 
-use crate::{cache::Cache, error, error::Error, source::Source, uint, Result};
-use std::rc::Rc;
+use crate::{
+    bounds::{bounded, ItemBoundaries},
+    cache::{Bypass, Cache, CacheConfig, CacheUsage},
+    dex::DexInner,
+    error,
+    error::Error,
+    mutf8,
+    snapshot::{ItemBytes, SnapshotKinds, Snapshots},
+    source::{DexSource, Source},
+    sync::Shared,
+    timing::{Category, Timers},
+    uint, ItemType, Result,
+};
 
 /// Index into the `StringId`s section.
 pub type StringId = uint;
@@ -20,7 +31,7 @@ pub type StringId = uint;
 /// [Android docs](https://source.android.com/devices/tech/dalvik/dex-format#mutf-8)
 #[derive(Debug, Hash, Eq, PartialEq, Clone, PartialOrd, Ord)]
 pub struct DexString {
-    string: Rc<String>,
+    string: Shared<String>,
 }
 
 impl PartialEq<str> for DexString {
@@ -44,7 +55,7 @@ impl fmt::Display for DexString {
 impl From<String> for DexString {
     fn from(string: String) -> Self {
         DexString {
-            string: Rc::new(string),
+            string: Shared::new(string),
         }
     }
 }
@@ -63,25 +74,117 @@ impl<'a> ctx::TryFromCtx<'a, scroll::Endian> for DexString {
 
     // https://source.android.com/devices/tech/dalvik/dex-format#string-data-item
     fn try_from_ctx(source: &'a [u8], _: scroll::Endian) -> Result<(Self, Self::Size)> {
-        let offset = &mut 0;
-        let _ = Uleb128::read(source, offset)?;
-        let count = source
-            .iter()
-            .skip(*offset)
-            .take_while(|c| **c != b'\0')
-            .count();
-        let bytes = &source[*offset..*offset + count];
-        let size = *offset + bytes.len();
-        Ok((
-            DexString {
-                string: Rc::new(
-                    from_java_cesu8(bytes)
-                        .map_err(|e| Error::MalFormed(format!("Malformed string: {:?}", e)))?
-                        .into_owned(),
-                ),
-            },
-            size,
-        ))
+        read_string(source, 0)
+    }
+}
+
+/// Reads the string data item at the start of `source`, which is at `offset` in the file.
+/// Fails with `Error::MalFormed` reporting the offset of the first byte which can't be
+/// decoded.
+fn read_string(source: &[u8], offset: usize) -> Result<(DexString, usize)> {
+    let (_, data) = string_data(source)?;
+    let size = data.end;
+    let string = mutf8::decode(&source[data.clone()], offset + data.start)?;
+    Ok((DexString::from(string.into_owned()), size))
+}
+
+/// Reads the length in UTF-16 code units of the string data item at the start of `source`
+/// and finds the range of its MUTF-8 bytes, without the null terminator.
+fn string_data(source: &[u8]) -> Result<(u64, Range<usize>)> {
+    let offset = &mut 0;
+    let utf16_len = Uleb128::read(source, offset)?;
+    let count = source
+        .iter()
+        .skip(*offset)
+        .position(|c| *c == b'\0')
+        .ok_or_else(|| Error::MalFormed("String data is not null terminated".to_string()))?;
+    Ok((utf16_len, *offset..*offset + count))
+}
+
+/// A string of a `Dex` file as its MUTF-8 bytes, read by `Dex::get_str_raw` without
+/// decoding it. The bytes are borrowed from the source of the file, unless the source
+/// doesn't store them contiguously or the string data items are copied on access, see
+/// `DexReaderBuilder::snapshot_on_access`.
+#[derive(Debug, Clone)]
+pub struct DexStr<'a> {
+    bytes: Cow<'a, [u8]>,
+    utf16_len: u64,
+    /// Offset of the bytes in the file, reported by decoding errors.
+    offset: usize,
+}
+
+impl<'a> DexStr<'a> {
+    /// The MUTF-8 bytes of the string, without the null terminator.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The length of the string in UTF-16 code units, as stored in the file.
+    pub fn utf16_len(&self) -> u64 {
+        self.utf16_len
+    }
+
+    /// Returns `true` if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Decodes the string, borrowing the bytes when they are valid UTF-8, which is the
+    /// case unless the string holds a null character or a character outside the Basic
+    /// Multilingual Plane.
+    ///
+    /// Fails with `Error::MalFormed` if the bytes are not valid MUTF-8, reporting the
+    /// offset in the file of the first byte which can't be decoded.
+    pub fn to_str(&self) -> Result<Cow<'_, str>> {
+        mutf8::decode(&self.bytes, self.offset)
+    }
+
+    /// Decodes the string, replacing each sequence of bytes which can't be decoded with
+    /// U+FFFD.
+    pub fn to_str_lossy(&self) -> Cow<'_, str> {
+        mutf8::decode_lossy(&self.bytes)
+    }
+
+    /// Decodes the string into the `DexString` `Dex::get_string` returns.
+    pub fn to_dex_string(&self) -> Result<DexString> {
+        self.to_str()
+            .map(|string| DexString::from(string.into_owned()))
+    }
+
+    /// Returns a copy of the string which doesn't borrow the source.
+    pub fn into_owned(self) -> DexStr<'static> {
+        DexStr {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+            utf16_len: self.utf16_len,
+            offset: self.offset,
+        }
+    }
+}
+
+impl PartialEq for DexStr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes && self.utf16_len == other.utf16_len
+    }
+}
+
+impl Eq for DexStr<'_> {}
+
+impl Hash for DexStr<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state);
+        self.utf16_len.hash(state);
+    }
+}
+
+impl PartialEq<str> for DexStr<'_> {
+    fn eq(&self, other: &str) -> bool {
+        *self.bytes == *to_java_cesu8(other)
+    }
+}
+
+impl<'a> PartialEq<&'a str> for DexStr<'_> {
+    fn eq(&self, other: &&'a str) -> bool {
+        *self == **other
     }
 }
 
@@ -97,32 +200,68 @@ pub(crate) struct Strings<T> {
     len: uint,
     cache: Cache<StringId, DexString>,
     data_section: Range<uint>,
+    /// Boundaries of the items in the string_data section.
+    string_data: Shared<ItemBoundaries>,
+    /// Copies of the string data items, see `DexReaderBuilder::snapshot_on_access`.
+    snapshots: Snapshots,
+    timers: Timers,
+    /// See `Dex::log_label`.
+    log_label: Option<Shared<str>>,
 }
 
 impl<T> Strings<T>
 where
-    T: AsRef<[u8]>,
+    T: DexSource,
 {
     /// Returns a new instance of the string cache
     pub(crate) fn new(
         source: Source<T>,
-        endian: super::Endian,
-        offset: uint,
-        len: uint,
-        cache_size: NonZeroUsize,
-        data_section: Range<uint>,
+        inner: &DexInner,
+        config: &CacheConfig,
+        bypass: Bypass,
+        snapshots: Snapshots,
+        timers: Timers,
     ) -> Self {
+        let file_end = source.len() as uint;
+        let string_data_section = inner.map_list().section(ItemType::StringDataItem, file_end);
         Self {
             source,
-            offset,
-            endian,
-            len,
-            cache: Cache::new(cache_size),
-            data_section,
+            offset: inner.strings_offset(),
+            endian: inner.endian(),
+            len: inner.strings_len(),
+            cache: Cache::new(config.strings, config.max_bytes, bypass),
+            data_section: inner.data_section(),
+            string_data: Shared::new(ItemBoundaries::new(string_data_section)),
+            snapshots,
+            timers,
+            log_label: inner.log_label().clone(),
         }
     }
 
-    fn parse(&self, id: StringId) -> Result<DexString> {
+    /// Start offsets of all the string data items.
+    pub(crate) fn string_data_offsets(&self) -> Result<Vec<uint>> {
+        let offset = &mut (self.offset as usize);
+        let mut offsets = Vec::with_capacity(self.len as usize);
+        for _ in 0..self.len {
+            offsets.push(self.source.gread_with(offset, self.endian)?);
+        }
+        Ok(offsets)
+    }
+
+    /// Computes the boundaries of the string data items, unless already computed.
+    pub(crate) fn build_string_data_boundaries(&self) -> Result<()> {
+        self.string_data
+            .init(|| self.string_data_offsets())
+            .map(|_| ())
+    }
+
+    /// Returns `true` if the boundaries of the string data items are computed.
+    pub(crate) fn has_string_data_boundaries(&self) -> bool {
+        self.string_data.is_initialized()
+    }
+
+    /// The boundaries of the string data item of the string at `id`.
+    fn data_bounds(&self, id: StringId) -> Result<Range<usize>> {
         let source = &self.source;
         let offset = self.offset as usize + id as usize * 4;
         let string_data_off: uint = source.pread_with(offset, self.endian)?;
@@ -132,7 +271,58 @@ where
                 format!("string_data_off not in data section for StringId: {}", id),
             ));
         }
-        source.pread(string_data_off as usize)
+        let limit = self.data_section.end.min(source.len() as uint);
+        let end = self
+            .string_data
+            .end(string_data_off, limit, || self.string_data_offsets())?;
+        Ok(string_data_off as usize..end as usize)
+    }
+
+    fn parse(&self, id: StringId) -> Result<DexString> {
+        let _timer = self.timers.start(Category::Strings);
+        let bounds = self.data_bounds(id)?;
+        let bytes = self.snapshots.bytes(
+            SnapshotKinds::STRING_DATA,
+            self.source.inner(),
+            bounds.clone(),
+        );
+        bounded(
+            read_string(&bytes, bounds.start).map(|(string, _)| string),
+            "StringDataItem",
+            bounds,
+        )
+    }
+
+    /// Get the bytes of the string at `id`, bypassing the cache.
+    pub(crate) fn get_raw(&self, id: StringId) -> Result<DexStr<'_>> {
+        if id >= self.len {
+            return Err(Error::InvalidId(format!("Invalid string id: {}", id)));
+        }
+        let _timer = self.timers.start(Category::Strings);
+        let bounds = self.data_bounds(id)?;
+        let bytes = match self.snapshots.bytes(
+            SnapshotKinds::STRING_DATA,
+            self.source.inner(),
+            bounds.clone(),
+        ) {
+            ItemBytes::Borrowed(bytes) => bytes,
+            ItemBytes::Snapshot(bytes) => Cow::Owned(bytes.to_vec()),
+        };
+        let (utf16_len, data) = bounded(string_data(&bytes), "StringDataItem", bounds.clone())?;
+        let offset = bounds.start + data.start;
+        let bytes = match bytes {
+            Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[data]),
+            Cow::Owned(mut bytes) => {
+                bytes.truncate(data.end);
+                bytes.drain(..data.start);
+                Cow::Owned(bytes)
+            }
+        };
+        Ok(DexStr {
+            bytes,
+            utf16_len,
+            offset,
+        })
     }
 
     /// Get the string at `id` updating the cache with the new item
@@ -143,27 +333,65 @@ where
         if let Some(string) = self.cache.get(&id) {
             Ok(string)
         } else {
-            self.cache.put(id, self.parse(id)?);
-            Ok(self.cache.get(&id).unwrap())
+            let string = self.parse(id)?;
+            self.cache.put(id, string.clone());
+            Ok(string)
         }
     }
 
+    /// Cached strings that differ from the strings read afresh.
+    #[cfg(feature = "cache-diagnostics")]
+    pub(crate) fn compare_cached_vs_fresh(&self) -> Vec<crate::cache::CacheMismatch> {
+        let mut mismatches = Vec::new();
+        for (id, cached) in self.cache.entries() {
+            let fresh = self.parse(id);
+            if fresh.as_ref().ok() != Some(&cached) {
+                mismatches.push(crate::cache::CacheMismatch {
+                    key: u64::from(id),
+                    cached: format!("{:?}", cached),
+                    fresh: format!("{:?}", fresh),
+                });
+            }
+        }
+        mismatches
+    }
+
+    /// The counters of the cache.
+    pub(crate) fn cache_usage(&self) -> CacheUsage {
+        self.cache.usage()
+    }
+
+    pub(crate) fn clear_cache(&self) {
+        self.cache.clear()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn cached_len(&self) -> usize {
+        self.cache.entries().len()
+    }
+
     pub(crate) fn get_id(&self, string: &str) -> Result<Option<StringId>> {
         use crate::search::Section;
+        if self.len == 0 {
+            return Ok(None);
+        }
         let java_string = to_java_cesu8(string);
         let (offset, len) = (self.offset as usize, self.len as usize);
-        let string_section = &self.source[offset..offset + len * std::mem::size_of::<StringId>()];
-        let section = Section::new(string_section);
+        let string_section = self
+            .source
+            .slice(offset..offset + len * std::mem::size_of::<StringId>());
+        let section = Section::new(string_section, self.log_label.as_deref());
         let source = self.source.clone();
         let index = section.binary_search(
             &java_string,
             self.endian,
             move |data_offset: &uint, element: &std::borrow::Cow<[u8]>| {
                 let mut data_offset = *data_offset as usize;
-                let _ = Uleb128::read(source.as_ref(), &mut data_offset)
+                let _ = source
+                    .read_uleb128(&mut data_offset)
                     .map_err(crate::error::Error::from)?;
-                let value = &source[data_offset..data_offset + element.len()];
-                Ok((**element).cmp(value))
+                let value = source.slice(data_offset..data_offset + element.len());
+                Ok((**element).cmp(&value))
             },
         )?;
         Ok(index.map(|i| i as StringId))
@@ -179,11 +407,18 @@ impl<T> Clone for Strings<T> {
             len: self.len,
             cache: self.cache.clone(),
             data_section: self.data_section.clone(),
+            string_data: self.string_data.clone(),
+            snapshots: self.snapshots.clone(),
+            timers: self.timers.clone(),
+            log_label: self.log_label.clone(),
         }
     }
 }
 
-/// Iterator over the strings in the strings section.
+/// Iterator over the strings in the strings section, with their ids.
+///
+/// A string that cannot be read is yielded as an `Err` and the iteration goes on with the
+/// next id.
 pub struct StringsIter<T> {
     /// String cache shared by the parent `Dex`
     cache: Strings<T>,
@@ -191,7 +426,7 @@ pub struct StringsIter<T> {
     len: usize,
 }
 
-impl<T: AsRef<[u8]>> StringsIter<T> {
+impl<T: DexSource> StringsIter<T> {
     pub(crate) fn new(cache: Strings<T>, len: usize) -> Self {
         Self {
             cache,
@@ -201,8 +436,8 @@ impl<T: AsRef<[u8]>> StringsIter<T> {
     }
 }
 
-impl<T: AsRef<[u8]>> Iterator for StringsIter<T> {
-    type Item = super::Result<DexString>;
+impl<T: DexSource> Iterator for StringsIter<T> {
+    type Item = super::Result<(StringId, DexString)>;
 
     // NOTE: iteration may cause cache thrashing, introduce a new
     // method to get but not update cache if needed
@@ -210,12 +445,19 @@ impl<T: AsRef<[u8]>> Iterator for StringsIter<T> {
         if self.current >= self.len {
             return None;
         }
-        let next = self.cache.get(self.current as uint);
+        let id = self.current as StringId;
         self.current += 1;
-        Some(next)
+        Some(self.cache.get(id).map(|string| (id, string)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.current;
+        (remaining, Some(remaining))
     }
 }
 
+impl<T: DexSource> ExactSizeIterator for StringsIter<T> {}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -233,4 +475,70 @@ mod tests {
             "Lorg/adw/launcher/Launcher;"
         );
     }
+
+    #[test]
+    fn test_get_str_raw() {
+        use std::borrow::Cow;
+
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let dex = crate::DexReader::from_vec(&data).expect("failed to open dex");
+        let chunked = crate::DexReader::builder()
+            .read_source(crate::source::ChunkedSource::new(data.chunks(1000)))
+            .expect("failed to open dex");
+        for string in dex.strings() {
+            let (id, string) = string.expect("bad string");
+            let raw = dex.get_str_raw(id).expect("bad string");
+            assert!(matches!(raw.bytes, Cow::Borrowed(_)));
+            assert_eq!(raw.to_dex_string().expect("bad string"), string);
+            assert_eq!(raw, *string);
+            assert_eq!(raw.utf16_len(), string.encode_utf16().count() as u64);
+            assert_eq!(chunked.get_str_raw(id).expect("bad string"), raw);
+        }
+        // scanning the raw strings leaves the cache alone
+        let dex = crate::DexReader::from_vec(&data).expect("failed to open dex");
+        let launcher = (0..dex.header().string_ids_size())
+            .filter(|id| {
+                let raw = dex.get_str_raw(*id).expect("bad string");
+                raw.as_bytes()
+                    .windows(8)
+                    .any(|window| window == b"Launcher")
+            })
+            .count();
+        assert!(launcher > 0);
+        assert_eq!(dex.strings.cached_len(), 0);
+        assert!(dex.get_str_raw(dex.header().string_ids_size()).is_err());
+    }
+
+    #[test]
+    fn test_malformed_string() {
+        use scroll::{Pread, LE};
+
+        use crate::test_utils::{mutated_example_dex, uleb128_len};
+
+        let dex = crate::DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+        let id = dex.strings.get_id("Launcher.java").unwrap().unwrap();
+        let mut start = 0;
+        let data = mutated_example_dex(|data| {
+            let string_ids_off: u32 = data.pread_with(0x3c, LE).unwrap();
+            let string_data_off: u32 = data
+                .pread_with(string_ids_off as usize + id as usize * 4, LE)
+                .unwrap();
+            start = string_data_off as usize + uleb128_len(data, string_data_off as usize);
+            // a lead byte followed by 'u'
+            data[start + 1] = 0xc3;
+        });
+        let dex = crate::DexReader::from_vec(data).expect("cannot open dex");
+        let error = dex.get_string(id).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!(
+                "at offset {}: invalid continuation byte 0x75",
+                start + 1
+            )),
+            "{}",
+            error
+        );
+        assert!(dex.get_str_raw(id).unwrap().to_str().is_err());
+        assert_eq!(dex.get_string_lossy(id).unwrap(), "L\u{fffd}uncher.java");
+        assert!(dex.get_string_lossy(dex.strings_count() as u32).is_err());
+    }
 }
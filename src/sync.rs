@@ -0,0 +1,111 @@
+//! Shared ownership and interior mutability for the caches, indexes and lazily read items
+//! of a `Dex`.
+//!
+//! A `Dex` and the items read from it are `Send` and `Sync` whatever the features, so that
+//! a `Dex` can be shared between threads, for instance to load its classes on a thread
+//! pool, see `Dex::par_classes`. They hold their shared parts in `Arc`s, their caches
+//! behind locks and their lazily read parts in `OnceLock`s. On a single thread, this costs
+//! an atomic update per clone of an item and an uncontended lock per cache lookup; threads
+//! reading the same caches contend on their locks.
+//!
+//! The deadline set by `Dex::run_with` and the bypass of `Dex::with_cache_bypassed` are
+//! per thread: they apply to the thread running the operation, not to the other threads
+//! using the same `Dex` or its clones meanwhile. `Dex::par_classes` passes them on to the
+//! threads loading the classes.
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError, RwLock,
+    },
+    thread::{self, ThreadId},
+};
+
+/// Shared ownership of the items read from a `Dex`.
+pub use std::sync::Arc as Shared;
+
+pub(crate) use std::sync::OnceLock as OnceCell;
+
+/// `RefCell` replacement which can be shared between threads, with the same methods. A
+/// panic while a lock is held leaves the value as it was written so far.
+#[derive(Debug, Default)]
+pub(crate) struct RefCell<T>(RwLock<T>);
+
+impl<T> RefCell<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(RwLock::new(value))
+    }
+
+    pub(crate) fn borrow(&self) -> impl Deref<Target = T> + '_ {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn borrow_mut(&self) -> impl DerefMut<Target = T> + '_ {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// `Cell` replacement holding a value per thread: the threads which didn't set a value
+/// read the default one.
+#[derive(Debug, Default)]
+pub(crate) struct PerThread<T> {
+    /// Number of threads with a value other than the default one, to read the default one
+    /// without taking the lock.
+    set: AtomicUsize,
+    values: Mutex<HashMap<ThreadId, T>>,
+}
+
+impl<T: Copy + Default + PartialEq> PerThread<T> {
+    /// The value of the current thread.
+    pub(crate) fn get(&self) -> T {
+        if self.set.load(Ordering::Acquire) == 0 {
+            return T::default();
+        }
+        let values = self.values.lock().unwrap_or_else(PoisonError::into_inner);
+        values
+            .get(&thread::current().id())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the value of the current thread.
+    pub(crate) fn set(&self, value: T) {
+        self.replace(value);
+    }
+
+    /// Sets the value of the current thread, returning the previous one.
+    pub(crate) fn replace(&self, value: T) -> T {
+        let mut values = self.values.lock().unwrap_or_else(PoisonError::into_inner);
+        let thread = thread::current().id();
+        let previous = if value == T::default() {
+            values.remove(&thread)
+        } else {
+            values.insert(thread, value)
+        };
+        self.set.store(values.len(), Ordering::Release);
+        previous.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PerThread;
+
+    #[test]
+    fn test_per_thread() {
+        let value = PerThread::default();
+        assert_eq!(value.replace(3), 0);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert_eq!(value.get(), 0);
+                value.set(5);
+                assert_eq!(value.get(), 5);
+                value.set(0);
+            });
+        });
+        assert_eq!(value.get(), 3);
+        assert_eq!(value.replace(0), 3);
+        assert!(value.values.lock().unwrap().is_empty());
+    }
+}
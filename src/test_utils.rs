@@ -0,0 +1,124 @@
+//! Helpers for tests that need to load deliberately corrupted dex files.
+use std::io::BufReader;
+
+use scroll::{Pwrite, LE};
+
+/// Loads the example dex file, applies `mutate` to its bytes and fixes up the checksum so that
+/// the result can be opened.
+pub(crate) fn mutated_example_dex<F: FnOnce(&mut [u8])>(mutate: F) -> Vec<u8> {
+    let mut data = std::fs::read("resources/classes.dex").expect("cannot read example dex file");
+    mutate(&mut data);
+    fix_checksum(&mut data);
+    data
+}
+
+/// Recomputes the checksum of a modified dex file.
+pub(crate) fn fix_checksum(data: &mut [u8]) {
+    let checksum = adler32::adler32(BufReader::new(&data[12..])).expect("cannot compute checksum");
+    data.pwrite_with(checksum, 8, LE)
+        .expect("cannot write checksum");
+}
+
+/// Returns the length of the uleb128 value at `offset`.
+pub(crate) fn uleb128_len(data: &[u8], offset: usize) -> usize {
+    data[offset..]
+        .iter()
+        .position(|byte| byte & 0x80 == 0)
+        .expect("unterminated uleb128")
+        + 1
+}
+
+/// Builds a dex file containing only `strings` and no types, protos, fields, methods or
+/// classes. When `leftover_offset` is nonzero it is written as the offset of the empty id
+/// sections, and the map list gets an empty `TypeIdItem` entry at that offset.
+pub(crate) fn minimal_dex(strings: &[&str], leftover_offset: u32) -> Vec<u8> {
+    const HEADER_SIZE: usize = 0x70;
+    let string_ids_off = HEADER_SIZE;
+    let data_off = string_ids_off + 4 * strings.len();
+    let mut data = Vec::new();
+    let mut string_data_offs = Vec::new();
+    for string in strings {
+        string_data_offs.push((data_off + data.len()) as u32);
+        assert!(string.len() < 0x80 && string.is_ascii());
+        data.push(string.len() as u8);
+        data.extend_from_slice(string.as_bytes());
+        data.push(0);
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+
+    let map_off = data_off + data.len();
+    // (type, size, offset)
+    let mut map_items = vec![(0x0u16, 1u32, 0u32)];
+    if !strings.is_empty() {
+        map_items.push((0x1, strings.len() as u32, string_ids_off as u32));
+    }
+    if leftover_offset != 0 {
+        map_items.push((0x2, 0, leftover_offset));
+    }
+    if !strings.is_empty() {
+        map_items.push((0x2002, strings.len() as u32, data_off as u32));
+    }
+    map_items.push((0x1000, 1, map_off as u32));
+    let mut map_list = vec![0u8; 4 + 12 * map_items.len()];
+    map_list.pwrite_with(map_items.len() as u32, 0, LE).unwrap();
+    for (index, (item_type, size, offset)) in map_items.into_iter().enumerate() {
+        let item = 4 + 12 * index;
+        map_list.pwrite_with(item_type, item, LE).unwrap();
+        map_list.pwrite_with(size, item + 4, LE).unwrap();
+        map_list.pwrite_with(offset, item + 8, LE).unwrap();
+    }
+    data.extend_from_slice(&map_list);
+
+    let file_size = data_off + data.len();
+    let mut dex = vec![0u8; data_off];
+    dex[..8].copy_from_slice(b"dex\n035\0");
+    let string_ids_off = if strings.is_empty() {
+        leftover_offset
+    } else {
+        string_ids_off as u32
+    };
+    let header = [
+        (32, file_size as u32),
+        (36, HEADER_SIZE as u32),
+        (40, 0x1234_5678),
+        (52, map_off as u32),
+        (56, strings.len() as u32),
+        (60, string_ids_off),
+        (68, leftover_offset),
+        (76, leftover_offset),
+        (84, leftover_offset),
+        (92, leftover_offset),
+        (100, leftover_offset),
+        (104, data.len() as u32),
+        (108, data_off as u32),
+    ];
+    for (offset, value) in header.iter() {
+        dex.pwrite_with(*value, *offset, LE).unwrap();
+    }
+    for (index, string_data_off) in string_data_offs.into_iter().enumerate() {
+        dex.pwrite_with(string_data_off, HEADER_SIZE + 4 * index, LE)
+            .unwrap();
+    }
+    dex.extend_from_slice(&data);
+    fix_checksum(&mut dex);
+    dex
+}
+
+/// A code item with 3 registers holding `insns`, with a try block over the first 3
+/// code units if `try_handlers` is given.
+#[cfg(feature = "decoder")]
+pub(crate) fn code_item(insns: &[crate::ushort], try_handlers: Option<&[u8]>) -> Vec<u8> {
+    let mut data = vec![0u8; 16];
+    data.pwrite_with(3u16, 0, LE).unwrap();
+    data.pwrite_with(insns.len() as u32, 12, LE).unwrap();
+    data.extend(insns.iter().flat_map(|unit| unit.to_le_bytes()));
+    if let Some(handlers) = try_handlers {
+        data.pwrite_with(1u16, 6, LE).unwrap();
+        // start_addr 0, insn_count 3, handler_off 1
+        data.extend_from_slice(&[0, 0, 0, 0, 3, 0, 1, 0]);
+        data.extend_from_slice(handlers);
+    }
+    data
+}
@@ -0,0 +1,185 @@
+//! Coarse timers of the time spent parsing each kind of item, enabled with the
+//! `parse-timing` feature. Without the feature the timers are zero sized and do nothing.
+#[cfg(feature = "parse-timing")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "parse-timing")]
+use crate::sync::{RefCell, Shared};
+
+/// Kinds of items timed separately.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Category {
+    Strings,
+    Types,
+    ClassData,
+    CodeItems,
+    Annotations,
+    #[cfg_attr(not(feature = "debug-info"), allow(dead_code))]
+    DebugInfo,
+}
+
+/// Cumulative time spent parsing each kind of item. The time spent parsing an item
+/// doesn't include the time spent parsing the items it refers to, so for example the
+/// strings read while loading a class count towards `strings` and not `class_data`.
+#[cfg(feature = "parse-timing")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParseTimings {
+    /// Time spent decoding string data.
+    pub strings: Duration,
+    /// Time spent resolving type ids.
+    pub types: Duration,
+    /// Time spent loading class definitions, class data, fields and methods.
+    pub class_data: Duration,
+    /// Time spent reading code items.
+    pub code_items: Duration,
+    /// Time spent reading annotations directories, sets and items.
+    pub annotations: Duration,
+    /// Time spent reading debug info items.
+    pub debug_info: Duration,
+}
+
+#[cfg(feature = "parse-timing")]
+impl ParseTimings {
+    /// Sum of the time spent in all the categories.
+    pub fn total(&self) -> Duration {
+        self.strings
+            + self.types
+            + self.class_data
+            + self.code_items
+            + self.annotations
+            + self.debug_info
+    }
+
+    fn get_mut(&mut self, category: Category) -> &mut Duration {
+        match category {
+            Category::Strings => &mut self.strings,
+            Category::Types => &mut self.types,
+            Category::ClassData => &mut self.class_data,
+            Category::CodeItems => &mut self.code_items,
+            Category::Annotations => &mut self.annotations,
+            Category::DebugInfo => &mut self.debug_info,
+        }
+    }
+}
+
+#[cfg(feature = "parse-timing")]
+#[derive(Default)]
+struct TimerState {
+    timings: ParseTimings,
+    /// Categories being timed, innermost last, with the instant each was last resumed.
+    running: Vec<(Category, Instant)>,
+}
+
+/// Timers shared by a `Dex` and everything that parses items from it.
+#[cfg(feature = "parse-timing")]
+#[derive(Clone, Default)]
+pub(crate) struct Timers {
+    state: Shared<RefCell<TimerState>>,
+}
+
+#[cfg(feature = "parse-timing")]
+impl Timers {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts timing `category`, pausing the enclosing category until the returned
+    /// guard is dropped.
+    pub(crate) fn start(&self, category: Category) -> TimerGuard {
+        let now = Instant::now();
+        let mut state = self.state.borrow_mut();
+        let TimerState { timings, running } = &mut *state;
+        if let Some((parent, resumed)) = running.last() {
+            *timings.get_mut(*parent) += now - *resumed;
+        }
+        running.push((category, now));
+        TimerGuard {
+            state: self.state.clone(),
+        }
+    }
+
+    pub(crate) fn timings(&self) -> ParseTimings {
+        self.state.borrow().timings
+    }
+}
+
+#[cfg(feature = "parse-timing")]
+pub(crate) struct TimerGuard {
+    state: Shared<RefCell<TimerState>>,
+}
+
+#[cfg(feature = "parse-timing")]
+impl Drop for TimerGuard {
+    fn drop(&mut self) {
+        let now = Instant::now();
+        let mut state = self.state.borrow_mut();
+        let TimerState { timings, running } = &mut *state;
+        if let Some((category, resumed)) = running.pop() {
+            *timings.get_mut(category) += now - resumed;
+        }
+        if let Some((_, resumed)) = running.last_mut() {
+            *resumed = now;
+        }
+    }
+}
+
+#[cfg(not(feature = "parse-timing"))]
+#[derive(Clone)]
+pub(crate) struct Timers;
+
+#[cfg(not(feature = "parse-timing"))]
+impl Timers {
+    pub(crate) fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn start(&self, _: Category) -> TimerGuard {
+        TimerGuard
+    }
+}
+
+#[cfg(not(feature = "parse-timing"))]
+pub(crate) struct TimerGuard;
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "parse-timing"))]
+    #[test]
+    fn test_timers_disabled() {
+        assert_eq!(std::mem::size_of::<super::Timers>(), 0);
+        assert_eq!(std::mem::size_of::<super::TimerGuard>(), 0);
+        assert!(!std::mem::needs_drop::<super::TimerGuard>());
+    }
+
+    #[cfg(feature = "parse-timing")]
+    #[test]
+    fn test_timings_cover_class_iteration() {
+        let dex = crate::DexReader::builder()
+            .eager_annotations(true)
+            .read_file("resources/classes.dex")
+            .expect("cannot open dex");
+        let start = std::time::Instant::now();
+        for class in dex.classes() {
+            class.expect("bad class");
+        }
+        let wall = start.elapsed();
+        let timings = dex.parse_timings();
+        assert!(timings.total() <= wall);
+        assert!(timings.total() >= wall / 2);
+        for category in [
+            timings.strings,
+            timings.types,
+            timings.class_data,
+            timings.code_items,
+            timings.annotations,
+        ] {
+            assert!(category > std::time::Duration::ZERO);
+        }
+        // debug info is only parsed with its feature
+        assert_eq!(
+            timings.debug_info > std::time::Duration::ZERO,
+            cfg!(feature = "debug-info")
+        );
+    }
+}
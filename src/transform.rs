@@ -0,0 +1,861 @@
+//! Transforms writing a modified copy of a dex file.
+//!
+//! A transform rebuilds the sections it changes and copies every other section verbatim,
+//! moved down by the bytes saved before it. The offsets pointing into moved sections are
+//! then fixed: in the header, the string, prototype, class definition and call site ids,
+//! the class data and the code items. Instructions are never changed and the id sections
+//! keep their order, so the indices used by the code stay valid.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::Range,
+};
+
+use scroll::{Pread, Pwrite, Uleb128};
+
+use crate::{
+    annotation::{AnnotationItem, EncodedAnnotation, Visibility},
+    dex::{ItemType, MapItemType},
+    encoded_value::scan_encoded_annotation,
+    error::Error,
+    leb::LebScanner,
+    source::DexSource,
+    uint, Dex, Endian, Result,
+};
+
+/// Selects the annotations removed by `strip_annotations`: the annotations whose
+/// visibility or type is selected.
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationFilter {
+    visibilities: Vec<Visibility>,
+    descriptors: HashSet<String>,
+}
+
+impl AnnotationFilter {
+    /// A filter which selects no annotations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects the annotations with the `visibility`.
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        if !self.visibilities.contains(&visibility) {
+            self.visibilities.push(visibility);
+        }
+        self
+    }
+
+    /// Selects the annotations of the type with the `descriptor`, such as
+    /// `Lkotlin/Metadata;`.
+    pub fn descriptor<S: Into<String>>(mut self, descriptor: S) -> Self {
+        self.descriptors.insert(descriptor.into());
+        self
+    }
+
+    /// Returns `true` if the annotation is selected.
+    pub fn matches(&self, annotation: &AnnotationItem) -> bool {
+        self.visibilities.contains(&annotation.visibility())
+            || self
+                .descriptors
+                .contains(&**annotation.jtype().type_descriptor())
+    }
+}
+
+/// Options controlling how transforms write the items they rebuild.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransformOptions {
+    /// Copies the bytes of the kept annotation items as they are. By default their LEB128
+    /// values are written in their minimal encoding, dropping the extra continuation bytes
+    /// some protectors add, see `Dex::nonminimal_leb_sites`. The sections which are not
+    /// rebuilt are always copied as they are.
+    pub preserve_raw_encodings: bool,
+}
+
+/// Returns a copy of the dex without the annotations selected by the `filter`, with the
+/// default `TransformOptions`.
+pub fn strip_annotations<T: DexSource>(dex: &Dex<T>, filter: &AnnotationFilter) -> Result<Vec<u8>> {
+    strip_annotations_with(dex, filter, TransformOptions::default())
+}
+
+/// Returns a copy of the dex without the annotations selected by the `filter`.
+///
+/// The annotations directories, annotation sets and annotation set ref lists are
+/// rebuilt. Annotation items, sets and lists which are no longer referenced are dropped,
+/// and so are the directories left empty. The strings and types only used by the removed
+/// annotations are kept: dropping them would renumber the ids used by the code.
+///
+/// The `code_off` of the methods are rewritten with the same number of bytes as before,
+/// which can leave them in a longer LEB128 encoding than needed, as the format allows.
+/// The `options` select how the kept annotation items are written. The checksum and the signature of the copy are recomputed.
+pub fn strip_annotations_with<T: DexSource>(
+    dex: &Dex<T>,
+    filter: &AnnotationFilter,
+    options: TransformOptions,
+) -> Result<Vec<u8>> {
+    let header = dex.header();
+    if header.link_size() != 0 {
+        return Err(Error::MalFormed(
+            "Cannot transform a statically linked dex file".to_string(),
+        ));
+    }
+    if header.data_off() + header.data_size() != header.file_size() {
+        return Err(Error::MalFormed(
+            "Cannot transform a dex file with data after the data section".to_string(),
+        ));
+    }
+    let source = dex.source.all();
+    let plan = StripPlan::new(dex, &source, filter)?;
+    let mut writer = Writer::new(dex, &source);
+    let mut sections: Vec<_> = dex.map_list().items().to_vec();
+    sections.sort_by_key(|section| section.offset());
+    for (index, section) in sections.iter().enumerate() {
+        let end = sections
+            .get(index + 1)
+            .map(|next| next.offset())
+            .unwrap_or_else(|| header.file_size());
+        match section.item_type().known() {
+            Some(ItemType::MapList) => {}
+            Some(ItemType::AnnotationItem) => {
+                writer.annotation_items(section.offset(), &plan, options)?
+            }
+            Some(ItemType::AnnotationSetItem) => writer.annotation_sets(section.offset(), &plan),
+            Some(ItemType::AnnotationSetRefList) => writer.ref_lists(section.offset(), &plan),
+            Some(ItemType::AnnotationsDirectoryItem) => writer.directories(section.offset(), &plan),
+            _ => writer.copy(section.item_type(), section.size(), section.offset()..end),
+        }
+    }
+    writer.finish(&plan)
+}
+
+/// An annotations directory, with the offsets of its sets and lists.
+#[derive(Debug, Default)]
+struct Directory {
+    class_annotations: uint,
+    fields: Vec<(uint, uint)>,
+    methods: Vec<(uint, uint)>,
+    parameters: Vec<(uint, uint)>,
+}
+
+impl Directory {
+    fn read(source: &[u8], offset: uint, endian: Endian) -> Result<Self> {
+        let offset = &mut (offset as usize);
+        let class_annotations = source.gread_with(offset, endian)?;
+        let fields_size: uint = source.gread_with(offset, endian)?;
+        let methods_size: uint = source.gread_with(offset, endian)?;
+        let parameters_size: uint = source.gread_with(offset, endian)?;
+        let mut entries = |size| -> Result<Vec<(uint, uint)>> {
+            (0..size)
+                .map(|_| {
+                    Ok((
+                        source.gread_with(offset, endian)?,
+                        source.gread_with(offset, endian)?,
+                    ))
+                })
+                .collect()
+        };
+        Ok(Self {
+            class_annotations,
+            fields: entries(fields_size)?,
+            methods: entries(methods_size)?,
+            parameters: entries(parameters_size)?,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.class_annotations == 0
+            && self.fields.is_empty()
+            && self.methods.is_empty()
+            && self.parameters.is_empty()
+    }
+}
+
+/// Reads a list of offsets preceded by its size: an annotation set or an annotation set
+/// ref list.
+fn read_offsets(source: &[u8], offset: uint, endian: Endian) -> Result<Vec<uint>> {
+    let offset = &mut (offset as usize);
+    let size: uint = source.gread_with(offset, endian)?;
+    (0..size)
+        .map(|_| Ok(source.gread_with(offset, endian)?))
+        .collect()
+}
+
+/// The annotation structures of the copy, keyed by their offsets in the original file.
+/// Each structure refers to the others by their original offsets.
+#[derive(Default)]
+struct StripPlan {
+    /// Kept annotation items and their lengths.
+    items: BTreeMap<uint, usize>,
+    /// Referenced annotation sets with the items they keep.
+    sets: BTreeMap<uint, Vec<uint>>,
+    /// Referenced annotation set ref lists.
+    ref_lists: BTreeMap<uint, Vec<uint>>,
+    /// Directories which are not empty.
+    directories: BTreeMap<uint, Directory>,
+}
+
+impl StripPlan {
+    fn new<T: DexSource>(dex: &Dex<T>, source: &[u8], filter: &AnnotationFilter) -> Result<Self> {
+        let endian = dex.get_endian();
+        let mut plan = Self::default();
+        let mut removed: HashMap<uint, bool> = HashMap::new();
+        let mut directories = BTreeSet::new();
+        for class_def in dex.class_defs() {
+            let offset = class_def?.annotations_off();
+            if offset != 0 {
+                directories.insert(offset);
+            }
+        }
+        for offset in directories {
+            let original = Directory::read(source, offset, endian)?;
+            let mut directory = Directory::default();
+            if original.class_annotations != 0
+                && plan.keep_set(
+                    dex,
+                    source,
+                    filter,
+                    original.class_annotations,
+                    &mut removed,
+                )?
+            {
+                directory.class_annotations = original.class_annotations;
+            }
+            for (index, set) in original.fields {
+                if plan.keep_set(dex, source, filter, set, &mut removed)? {
+                    directory.fields.push((index, set));
+                }
+            }
+            for (index, set) in original.methods {
+                if plan.keep_set(dex, source, filter, set, &mut removed)? {
+                    directory.methods.push((index, set));
+                }
+            }
+            for (index, list) in original.parameters {
+                if plan.keep_ref_list(dex, source, filter, list, &mut removed)? {
+                    directory.parameters.push((index, list));
+                }
+            }
+            if !directory.is_empty() {
+                plan.directories.insert(offset, directory);
+            }
+        }
+        for items in plan.sets.values() {
+            for item in items {
+                if !plan.items.contains_key(item) {
+                    let size = &mut 1;
+                    let _: EncodedAnnotation = source[*item as usize..].gread_with(size, dex)?;
+                    plan.items.insert(*item, *size);
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Adds the set at `offset` to the plan unless all its annotations are removed.
+    /// Returns `true` if the set is kept.
+    fn keep_set<T: DexSource>(
+        &mut self,
+        dex: &Dex<T>,
+        source: &[u8],
+        filter: &AnnotationFilter,
+        offset: uint,
+        removed: &mut HashMap<uint, bool>,
+    ) -> Result<bool> {
+        let items = self.filtered_set(dex, source, filter, offset, removed)?;
+        if items.is_empty() {
+            return Ok(false);
+        }
+        self.sets.insert(offset, items);
+        Ok(true)
+    }
+
+    /// Adds the ref list at `offset` and its sets to the plan unless all the annotations
+    /// of its sets are removed. Returns `true` if the list is kept.
+    fn keep_ref_list<T: DexSource>(
+        &mut self,
+        dex: &Dex<T>,
+        source: &[u8],
+        filter: &AnnotationFilter,
+        offset: uint,
+        removed: &mut HashMap<uint, bool>,
+    ) -> Result<bool> {
+        let sets = read_offsets(source, offset, dex.get_endian())?;
+        let mut filtered = Vec::with_capacity(sets.len());
+        for set in &sets {
+            let items = match set {
+                0 => Vec::new(),
+                set => self.filtered_set(dex, source, filter, *set, removed)?,
+            };
+            filtered.push(items);
+        }
+        if filtered.iter().all(Vec::is_empty) {
+            return Ok(false);
+        }
+        // sets left empty are kept, as the list needs an entry for each parameter
+        for (set, items) in sets.iter().zip(filtered) {
+            if *set != 0 {
+                self.sets.insert(*set, items);
+            }
+        }
+        self.ref_lists.insert(offset, sets);
+        Ok(true)
+    }
+
+    /// The items of the set at `offset` which are not removed.
+    fn filtered_set<T: DexSource>(
+        &self,
+        dex: &Dex<T>,
+        source: &[u8],
+        filter: &AnnotationFilter,
+        offset: uint,
+        removed: &mut HashMap<uint, bool>,
+    ) -> Result<Vec<uint>> {
+        let mut kept = Vec::new();
+        for item in read_offsets(source, offset, dex.get_endian())? {
+            let is_removed = match removed.get(&item) {
+                Some(is_removed) => *is_removed,
+                None => {
+                    let is_removed = filter.matches(&dex.get_annotation_item(item)?);
+                    removed.insert(item, is_removed);
+                    is_removed
+                }
+            };
+            if !is_removed {
+                kept.push(item);
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// Builds the copy section by section.
+struct Writer<'a, T> {
+    dex: &'a Dex<T>,
+    /// The whole original file.
+    source: &'a [u8],
+    endian: Endian,
+    out: Vec<u8>,
+    /// Original extents of the sections copied verbatim and their new offsets.
+    moves: Vec<(Range<uint>, uint)>,
+    /// New offsets of the rebuilt items and of the starts of all sections.
+    offsets: HashMap<uint, uint>,
+    /// Positions in the copy of offsets to rebuilt items, with the original offsets.
+    fixups: Vec<(usize, uint)>,
+    /// Map list entries of the copy.
+    map: Vec<(MapItemType, uint, uint)>,
+}
+
+impl<'a, T: DexSource> Writer<'a, T> {
+    fn new(dex: &'a Dex<T>, source: &'a [u8]) -> Self {
+        Self {
+            dex,
+            source,
+            endian: dex.get_endian(),
+            out: Vec::with_capacity(source.len()),
+            moves: Vec::new(),
+            offsets: HashMap::new(),
+            fixups: Vec::new(),
+            map: Vec::new(),
+        }
+    }
+
+    fn pad_to(&mut self, alignment: uint, remainder: uint) {
+        while self.out.len() as uint % alignment != remainder % alignment {
+            self.out.push(0);
+        }
+    }
+
+    fn push_uint(&mut self, value: uint) {
+        let offset = self.out.len();
+        self.out.extend_from_slice(&[0; 4]);
+        self.out
+            .pwrite_with(value, offset, self.endian)
+            .expect("space for the value is reserved");
+    }
+
+    /// Writes an offset to a rebuilt item, fixed once all the items are written.
+    fn push_fixup(&mut self, original: uint) {
+        self.fixups.push((self.out.len(), original));
+        self.push_uint(original);
+    }
+
+    /// Starts a rebuilt section which was at `original` in the original file.
+    fn start_section(&mut self, item_type: ItemType, original: uint) -> uint {
+        self.pad_to(item_type.alignment(), 0);
+        let start = self.out.len() as uint;
+        self.offsets.insert(original, start);
+        start
+    }
+
+    fn end_section(&mut self, item_type: ItemType, start: uint, count: usize) {
+        if count > 0 {
+            self.map.push((item_type.into(), count as uint, start));
+        }
+    }
+
+    fn copy(&mut self, item_type: MapItemType, count: uint, extent: Range<uint>) {
+        // keeping the offset modulo 4 keeps the alignment of the items of the section
+        self.pad_to(4, extent.start);
+        let start = self.out.len() as uint;
+        self.offsets.insert(extent.start, start);
+        let source = self.source;
+        self.out
+            .extend_from_slice(&source[extent.start as usize..extent.end as usize]);
+        self.map.push((item_type, count, start));
+        self.moves.push((extent, start));
+    }
+
+    fn annotation_items(
+        &mut self,
+        original: uint,
+        plan: &StripPlan,
+        options: TransformOptions,
+    ) -> Result<()> {
+        let start = self.start_section(ItemType::AnnotationItem, original);
+        let source = self.source;
+        for (offset, size) in &plan.items {
+            self.offsets.insert(*offset, self.out.len() as uint);
+            let bytes = source
+                .get(*offset as usize..*offset as usize + size)
+                .ok_or_else(|| {
+                    Error::BadOffset(
+                        *offset as usize,
+                        "Annotation item out of bounds".to_string(),
+                    )
+                })?;
+            if options.preserve_raw_encodings {
+                self.out.extend_from_slice(bytes);
+            } else {
+                self.push_minimal_annotation(bytes)?;
+            }
+        }
+        self.end_section(ItemType::AnnotationItem, start, plan.items.len());
+        Ok(())
+    }
+
+    /// Writes the annotation item in `bytes` with its LEB128 values minimally encoded.
+    fn push_minimal_annotation(&mut self, bytes: &[u8]) -> Result<()> {
+        let mut scanner = LebScanner::new(bytes, None);
+        // the encoded annotation follows the visibility
+        scan_encoded_annotation(&mut scanner, &mut 1)?;
+        let mut copied = 0;
+        // annotations only hold unsigned values, whose extra bytes are zeros
+        for site in scanner.into_sites() {
+            let minimal_end = site.offset() + site.minimal_len();
+            self.out.extend_from_slice(&bytes[copied..minimal_end]);
+            if let Some(last) = self.out.last_mut() {
+                *last &= 0x7f;
+            }
+            copied = site.offset() + site.encoded_len();
+        }
+        self.out.extend_from_slice(&bytes[copied..]);
+        Ok(())
+    }
+
+    fn annotation_sets(&mut self, original: uint, plan: &StripPlan) {
+        let start = self.start_section(ItemType::AnnotationSetItem, original);
+        for (offset, items) in &plan.sets {
+            self.offsets.insert(*offset, self.out.len() as uint);
+            self.push_uint(items.len() as uint);
+            for item in items {
+                self.push_fixup(*item);
+            }
+        }
+        self.end_section(ItemType::AnnotationSetItem, start, plan.sets.len());
+    }
+
+    fn ref_lists(&mut self, original: uint, plan: &StripPlan) {
+        let start = self.start_section(ItemType::AnnotationSetRefList, original);
+        for (offset, sets) in &plan.ref_lists {
+            self.offsets.insert(*offset, self.out.len() as uint);
+            self.push_uint(sets.len() as uint);
+            for set in sets {
+                match set {
+                    0 => self.push_uint(0),
+                    set => self.push_fixup(*set),
+                }
+            }
+        }
+        self.end_section(ItemType::AnnotationSetRefList, start, plan.ref_lists.len());
+    }
+
+    fn directories(&mut self, original: uint, plan: &StripPlan) {
+        let start = self.start_section(ItemType::AnnotationsDirectoryItem, original);
+        for (offset, directory) in &plan.directories {
+            self.offsets.insert(*offset, self.out.len() as uint);
+            match directory.class_annotations {
+                0 => self.push_uint(0),
+                set => self.push_fixup(set),
+            }
+            self.push_uint(directory.fields.len() as uint);
+            self.push_uint(directory.methods.len() as uint);
+            self.push_uint(directory.parameters.len() as uint);
+            for (index, target) in directory
+                .fields
+                .iter()
+                .chain(&directory.methods)
+                .chain(&directory.parameters)
+            {
+                self.push_uint(*index);
+                self.push_fixup(*target);
+            }
+        }
+        self.end_section(
+            ItemType::AnnotationsDirectoryItem,
+            start,
+            plan.directories.len(),
+        );
+    }
+
+    /// New offset of the item at `offset` in the original file.
+    fn relocate(&self, offset: uint) -> Result<uint> {
+        if let Some(new) = self.offsets.get(&offset) {
+            return Ok(*new);
+        }
+        self.moves
+            .iter()
+            .find(|(extent, _)| extent.contains(&offset))
+            .map(|(extent, start)| start + (offset - extent.start))
+            .ok_or_else(|| {
+                Error::BadOffset(
+                    offset as usize,
+                    "Offset does not point to a copied section".to_string(),
+                )
+            })
+    }
+
+    fn read_uint(&self, position: uint) -> Result<uint> {
+        Ok(self.out.pread_with(position as usize, self.endian)?)
+    }
+
+    fn write_uint(&mut self, position: uint, value: uint) -> Result<()> {
+        self.out
+            .pwrite_with(value, position as usize, self.endian)?;
+        Ok(())
+    }
+
+    /// Relocates the nonzero offset stored at `position` of the original file.
+    fn relocate_field(&mut self, position: uint) -> Result<()> {
+        let position = self.relocate(position)?;
+        let offset = self.read_uint(position)?;
+        if offset != 0 {
+            let offset = self.relocate(offset)?;
+            self.write_uint(position, offset)?;
+        }
+        Ok(())
+    }
+
+    /// Relocates the `code_off` of the methods of the class data at `offset` and the
+    /// `debug_info_off` of their code items.
+    fn relocate_class_data(&mut self, offset: uint, code_items: &mut HashSet<uint>) -> Result<()> {
+        let position = &mut (self.relocate(offset)? as usize);
+        let mut sizes = [0; 4];
+        for size in &mut sizes {
+            *size = Uleb128::read(&self.out, position)?;
+        }
+        let [static_fields, instance_fields, direct_methods, virtual_methods] = sizes;
+        for _ in 0..static_fields + instance_fields {
+            Uleb128::read(&self.out, position)?;
+            Uleb128::read(&self.out, position)?;
+        }
+        for _ in 0..direct_methods + virtual_methods {
+            Uleb128::read(&self.out, position)?;
+            Uleb128::read(&self.out, position)?;
+            let start = *position;
+            let code_off = Uleb128::read(&self.out, position)? as uint;
+            if code_off == 0 {
+                continue;
+            }
+            let relocated = self.relocate(code_off)?;
+            write_uleb128_padded(&mut self.out[start..*position], relocated)?;
+            if code_items.insert(code_off) {
+                self.relocate_field(code_off + 8)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, plan: &StripPlan) -> Result<Vec<u8>> {
+        for (position, original) in std::mem::take(&mut self.fixups) {
+            let offset = self.offsets.get(&original).cloned().ok_or_else(|| {
+                Error::BadOffset(original as usize, "Offset of a dropped item".to_string())
+            })?;
+            self.write_uint(position as uint, offset)?;
+        }
+
+        let dex = self.dex;
+        let header = dex.header();
+        let map_list = dex.map_list();
+        // header offsets of the id sections, at 0x3c, 0x44, ... 0x64
+        for (size, position) in [
+            (header.string_ids_size(), 0x3c),
+            (header.type_ids_size(), 0x44),
+            (header.proto_ids_size(), 0x4c),
+            (header.field_ids_size(), 0x54),
+            (header.method_ids_size(), 0x5c),
+            (header.class_defs_size(), 0x64),
+        ] {
+            if size != 0 {
+                self.relocate_field(position)?;
+            }
+        }
+        for index in 0..header.string_ids_size() {
+            self.relocate_field(header.string_ids_off() + index * 4)?;
+        }
+        for index in 0..header.proto_ids_size() {
+            self.relocate_field(header.proto_ids_off() + index * 12 + 8)?;
+        }
+        if let Some(call_sites) = map_list.get(ItemType::CallSiteIdItem) {
+            for index in 0..call_sites.size() {
+                self.relocate_field(call_sites.offset() + index * 4)?;
+            }
+        }
+        let mut code_items = HashSet::new();
+        for (index, class_def) in dex.class_defs().enumerate() {
+            let class_def = class_def?;
+            let position = header.class_defs_off() + index as uint * 32;
+            self.relocate_field(position + 12)?;
+            self.relocate_field(position + 24)?;
+            self.relocate_field(position + 28)?;
+            let annotations = match class_def.annotations_off() {
+                offset if plan.directories.contains_key(&offset) => self.relocate(offset)?,
+                _ => 0,
+            };
+            let position = self.relocate(position + 20)?;
+            self.write_uint(position, annotations)?;
+            if class_def.class_data_off() != 0 {
+                self.relocate_class_data(class_def.class_data_off(), &mut code_items)?;
+            }
+        }
+
+        let data_off = self.relocate(header.data_off())?;
+        self.pad_to(4, 0);
+        let map_off = self.out.len() as uint;
+        self.map.push((ItemType::MapList.into(), 1, map_off));
+        self.map.sort_by_key(|(_, _, offset)| *offset);
+        self.push_uint(self.map.len() as uint);
+        for (item_type, count, offset) in std::mem::take(&mut self.map) {
+            let position = self.out.len();
+            self.out.extend_from_slice(&[0; 12]);
+            let entry = &mut self.out[position..];
+            entry.pwrite_with(item_type.code(), 0, self.endian)?;
+            entry.pwrite_with(count, 4, self.endian)?;
+            entry.pwrite_with(offset, 8, self.endian)?;
+        }
+
+        let file_size = self.out.len() as uint;
+        self.write_uint(0x20, file_size)?;
+        self.write_uint(0x34, map_off)?;
+        self.write_uint(0x68, file_size - data_off)?;
+        self.write_uint(0x6c, data_off)?;
+        let signature = sha1_smol::Sha1::from(&self.out[32..]).digest().bytes();
+        self.out[12..32].copy_from_slice(&signature);
+        let checksum = adler32::RollingAdler32::from_buffer(&self.out[12..]).hash();
+        self.write_uint(8, checksum)?;
+        Ok(self.out)
+    }
+}
+
+/// Writes `value` as a LEB128 value filling `bytes`, padded with continuation bytes.
+fn write_uleb128_padded(bytes: &mut [u8], value: uint) -> Result<()> {
+    let mut rest = value;
+    let last = bytes.len() - 1;
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = (rest & 0x7f) as u8;
+        rest >>= 7;
+        if index != last {
+            *byte |= 0x80;
+        }
+    }
+    if rest != 0 {
+        return Err(Error::MalFormed(format!(
+            "{} does not fit in {} LEB128 bytes",
+            value,
+            bytes.len()
+        )));
+    }
+    Ok(())
+}
+
+// the comparisons go through the dexdump rendering and the debug info
+#[cfg(all(test, feature = "annotations-extras", feature = "debug-info"))]
+mod tests {
+    use super::{strip_annotations, strip_annotations_with, AnnotationFilter, TransformOptions};
+    use crate::{
+        annotation::{AnnotationSetItem, Visibility},
+        dex::ItemType,
+        encoded_value::scan_encoded_annotation,
+        leb::LebScanner,
+        test_utils::{fix_checksum, uleb128_len},
+        Dex, DexReader, DexSource,
+    };
+    use scroll::{Pread, Pwrite, LE};
+
+    /// Lines describing the classes, their members, code and annotations, keeping the
+    /// annotations for which `keep` returns `true`.
+    fn summary<T: DexSource + 'static>(dex: &Dex<T>, keep: &dyn Fn(&str) -> bool) -> Vec<String> {
+        let annotations = |set: &AnnotationSetItem| -> Vec<String> {
+            set.iter()
+                .map(|item| item.to_dexdump_string(dex).expect("cannot render"))
+                .filter(|line| keep(line))
+                .collect()
+        };
+        let mut lines = Vec::new();
+        for class in dex.classes() {
+            let class = class.expect("cannot load class");
+            lines.push(format!(
+                "class {} {:?} {:?} {:?}",
+                class.jtype(),
+                class.interfaces(),
+                class.source_file(),
+                annotations(class.annotations().expect("bad annotations"))
+            ));
+            for field in class.fields() {
+                lines.push(format!(
+                    "field {} {:?} {:?}",
+                    field.name(),
+                    field.initial_value(),
+                    annotations(field.annotations().expect("bad annotations"))
+                ));
+            }
+            for method in class.methods() {
+                let code = method.code().map(|code| {
+                    format!(
+                        "{:?} {:?} {:?}",
+                        code.insns, code.debug_info_item, code.tries
+                    )
+                });
+                lines.push(format!(
+                    "method {} {:?} {:?}",
+                    method.name(),
+                    code,
+                    annotations(method.annotations().expect("bad annotations"))
+                ));
+            }
+        }
+        lines
+    }
+
+    fn example() -> Dex<Vec<u8>> {
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        DexReader::from_vec(data).expect("cannot open dex")
+    }
+
+    #[test]
+    fn test_strip_annotations() {
+        let dex = example();
+        let filter = AnnotationFilter::new()
+            .visibility(Visibility::Runtime)
+            .descriptor("Ldalvik/annotation/Throws;");
+        let stripped = strip_annotations(&dex, &filter).expect("cannot strip annotations");
+        assert!(stripped.len() < dex.source.len());
+
+        let mut checked = stripped.clone();
+        crate::test_utils::fix_checksum(&mut checked);
+        assert_eq!(checked, stripped);
+        let signature = sha1_smol::Sha1::from(&stripped[32..]).digest().bytes();
+        assert_eq!(stripped[12..32], signature);
+
+        let copy = DexReader::from_vec(stripped).expect("cannot open stripped dex");
+        assert_eq!(
+            copy.header().class_defs_size(),
+            dex.header().class_defs_size()
+        );
+        assert!(copy.misaligned_items().expect("bad items").is_empty());
+        let removed = |line: &str| {
+            line.starts_with("VISIBILITY_RUNTIME") || line.contains("Ldalvik/annotation/Throws;")
+        };
+        let expected = summary(&dex, &|line| !removed(line));
+        let actual = summary(&copy, &|_| true);
+        assert_eq!(actual, expected);
+        assert!(expected.iter().any(|line| line.contains("Signature")));
+        assert_ne!(summary(&dex, &|_| true), expected);
+    }
+
+    #[test]
+    fn test_strip_all_annotations() {
+        let dex = example();
+        let filter = AnnotationFilter::new()
+            .visibility(Visibility::Build)
+            .visibility(Visibility::Runtime)
+            .visibility(Visibility::System);
+        let stripped = strip_annotations(&dex, &filter).expect("cannot strip annotations");
+        let copy = DexReader::from_vec(stripped).expect("cannot open stripped dex");
+        for class_def in copy.class_defs() {
+            assert_eq!(class_def.expect("bad class def").annotations_off(), 0);
+        }
+        for item_type in [
+            ItemType::AnnotationItem,
+            ItemType::AnnotationSetItem,
+            ItemType::AnnotationsDirectoryItem,
+        ] {
+            assert!(copy.map_list().get(item_type).is_none());
+        }
+        assert_eq!(summary(&copy, &|_| true), summary(&dex, &|_| false));
+
+        // nothing to remove gives the same file
+        let unchanged = strip_annotations(&dex, &AnnotationFilter::new()).expect("cannot copy");
+        assert!(unchanged == *dex.source.inner());
+    }
+
+    #[test]
+    fn test_strip_annotations_encodings() {
+        let original = example();
+        let map_list = original.map_list();
+        let items = map_list
+            .get(ItemType::AnnotationItem)
+            .expect("no annotation items");
+        let sets = map_list
+            .get(ItemType::AnnotationSetItem)
+            .expect("no annotation sets");
+        let mut data = original.source.inner().clone();
+        let mut start = items.offset() as usize;
+        let mut end = start;
+        for _ in 0..items.size() {
+            start = end;
+            end += 1;
+            let mut scanner = LebScanner::new(&data, None);
+            scan_encoded_annotation(&mut scanner, &mut end).expect("bad annotation");
+        }
+        // moves the last annotation item to the end of the file, with its type_idx padded
+        // by a continuation byte
+        let mut item = data[start..end].to_vec();
+        let type_len = uleb128_len(&item, 1);
+        item[type_len] |= 0x80;
+        item.insert(type_len + 1, 0);
+        let moved = data.len() as u32;
+        data.extend_from_slice(&item);
+        let mut set = sets.offset() as usize;
+        for _ in 0..sets.size() {
+            let size: u32 = data.pread_with(set, LE).expect("bad set");
+            for entry in 0..size as usize {
+                let position = set + 4 + 4 * entry;
+                if data.pread_with::<u32>(position, LE).expect("bad set") == start as u32 {
+                    data.pwrite_with(moved, position, LE).expect("bad set");
+                }
+            }
+            set += 4 + 4 * size as usize;
+        }
+        for position in [32, 104] {
+            let value: u32 = data.pread_with(position, LE).expect("bad header");
+            data.pwrite_with(value + item.len() as u32, position, LE)
+                .expect("bad header");
+        }
+        fix_checksum(&mut data);
+        let padded = DexReader::from_vec(data).expect("cannot open padded dex");
+
+        let filter = AnnotationFilter::new();
+        let normalized = strip_annotations(&padded, &filter).expect("cannot copy");
+        assert!(normalized == *original.source.inner());
+        let options = TransformOptions {
+            preserve_raw_encodings: true,
+        };
+        let preserved = strip_annotations_with(&padded, &filter, options).expect("cannot copy");
+        let preserved = DexReader::from_vec(preserved).expect("cannot open copy");
+        let sites = preserved.nonminimal_leb_sites().expect("bad sites");
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].encoded_len(), type_len + 1);
+        assert_eq!(
+            summary(&preserved, &|_| true),
+            summary(&original, &|_| true)
+        );
+    }
+}
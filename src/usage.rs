@@ -0,0 +1,371 @@
+//! Reverse lookup of the uses of a string value, see `Dex::string_usages`, and of the
+//! uses of the annotation types, see `Dex::annotation_usage_summary`.
+use std::fmt;
+
+use getset::{CopyGetters, Getters};
+
+use crate::{
+    annotation::Visibility, encoded_value::EncodedValue, jtype::TypeId, method::MethodId,
+    sharing::Owner, string::StringId, ulong,
+};
+
+/// A field or method whose name is the string.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct MemberUsage {
+    /// Index of the member in the `FieldId`s or `MethodId`s list.
+    #[get_copy = "pub"]
+    id: ulong,
+    /// Descriptor of the class which defines the member.
+    #[get = "pub"]
+    class: String,
+    /// The member in smali notation, for example `Lcom/example/Foo;->bar:I` for a field or
+    /// `Lcom/example/Foo;->bar(I)V` for a method.
+    #[get = "pub"]
+    reference: String,
+}
+
+impl MemberUsage {
+    pub(crate) fn new(id: ulong, class: String, reference: String) -> Self {
+        Self {
+            id,
+            class,
+            reference,
+        }
+    }
+}
+
+impl fmt::Display for MemberUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reference)
+    }
+}
+
+/// The part of an annotation element which is the string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementUse {
+    /// The name of the element.
+    Name,
+    /// The value of the element, or a string nested in it: an item of an array or an
+    /// element of an annotation.
+    Value,
+}
+
+/// An element of an annotation of a class, field, method or parameter which uses the
+/// string.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct AnnotationUsage {
+    /// The annotated class, field or method, or the method of the annotated parameter.
+    #[get_copy = "pub"]
+    owner: Owner,
+    /// Index of the annotated parameter, if the annotation is a parameter annotation.
+    #[get_copy = "pub"]
+    parameter: Option<usize>,
+    /// Descriptor of the annotated class, or the annotated member in smali notation.
+    #[get = "pub"]
+    location: String,
+    /// Descriptor of the type of the annotation.
+    #[get = "pub"]
+    annotation: String,
+    /// Name of the element.
+    #[get = "pub"]
+    element: String,
+    /// Whether the string is the name or the value of the element.
+    #[get_copy = "pub"]
+    element_use: ElementUse,
+}
+
+impl AnnotationUsage {
+    pub(crate) fn new(
+        owner: Owner,
+        parameter: Option<usize>,
+        location: String,
+        annotation: String,
+        element: String,
+        element_use: ElementUse,
+    ) -> Self {
+        Self {
+            owner,
+            parameter,
+            location,
+            annotation,
+            element,
+            element_use,
+        }
+    }
+}
+
+impl fmt::Display for AnnotationUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.location)?;
+        if let Some(parameter) = self.parameter {
+            write!(f, " parameter {}", parameter)?;
+        }
+        write!(
+            f,
+            " @{} {} {:?}",
+            self.annotation, self.element, self.element_use
+        )
+    }
+}
+
+/// A `const-string` or `const-string/jumbo` instruction loading the string.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ConstStringSite {
+    /// The method containing the instruction.
+    #[get_copy = "pub"]
+    method: MethodId,
+    /// The method in smali notation.
+    #[get = "pub"]
+    location: String,
+    /// Address of the instruction, in code units from the start of the method.
+    #[get_copy = "pub"]
+    addr: usize,
+}
+
+impl ConstStringSite {
+    pub(crate) fn new(method: MethodId, location: String, addr: usize) -> Self {
+        Self {
+            method,
+            location,
+            addr,
+        }
+    }
+}
+
+impl fmt::Display for ConstStringSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} @{:04x}", self.location, self.addr)
+    }
+}
+
+/// Everywhere a string value is used in a file, see `Dex::string_usages`.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct StringUsages {
+    /// Index of the string in the `StringId`s list, `None` if the file doesn't contain it,
+    /// in which case nothing uses it.
+    #[get_copy = "pub"]
+    string_id: Option<StringId>,
+    /// The type whose descriptor is the string. The `TypeId`s list has no duplicates, so
+    /// there is at most one.
+    #[get_copy = "pub"]
+    type_id: Option<TypeId>,
+    /// The fields named by the string, in the order of the `FieldId`s list.
+    #[get = "pub"]
+    fields: Vec<MemberUsage>,
+    /// The methods named by the string, in the order of the `MethodId`s list.
+    #[get = "pub"]
+    methods: Vec<MemberUsage>,
+    /// The annotation elements using the string, in the order of the class definitions.
+    #[get = "pub"]
+    annotations: Vec<AnnotationUsage>,
+    /// The instructions loading the string, in the order of the class definitions. `None`
+    /// if the code was not scanned.
+    #[get = "pub"]
+    const_strings: Option<Vec<ConstStringSite>>,
+}
+
+impl StringUsages {
+    pub(crate) fn new(string_id: Option<StringId>, type_id: Option<TypeId>) -> Self {
+        Self {
+            string_id,
+            type_id,
+            fields: Vec::new(),
+            methods: Vec::new(),
+            annotations: Vec::new(),
+            const_strings: None,
+        }
+    }
+
+    pub(crate) fn push_field(&mut self, usage: MemberUsage) {
+        self.fields.push(usage);
+    }
+
+    pub(crate) fn push_method(&mut self, usage: MemberUsage) {
+        self.methods.push(usage);
+    }
+
+    pub(crate) fn push_annotation(&mut self, usage: AnnotationUsage) {
+        self.annotations.push(usage);
+    }
+
+    pub(crate) fn set_const_strings(&mut self, sites: Vec<ConstStringSite>) {
+        self.const_strings = Some(sites);
+    }
+
+    /// Returns `true` if nothing found uses the string. The string may still be used by
+    /// items which are not looked up, such as debug info or the code when it was not
+    /// scanned.
+    pub fn is_unused(&self) -> bool {
+        self.type_id.is_none()
+            && self.fields.is_empty()
+            && self.methods.is_empty()
+            && self.annotations.is_empty()
+            && self.const_strings.as_ref().is_none_or(Vec::is_empty)
+    }
+}
+
+/// The retention of an annotation type, the values of
+/// `java.lang.annotation.RetentionPolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Retention {
+    /// Discarded by the compiler, so never found in dex files but in a declaration.
+    Source,
+    /// Kept in the file but not visible at runtime, the default of annotation types.
+    Class,
+    /// Visible at runtime.
+    Runtime,
+}
+
+impl Retention {
+    /// The name of the `RetentionPolicy` constant, such as `RUNTIME`.
+    pub fn id(self) -> &'static str {
+        match self {
+            Retention::Source => "SOURCE",
+            Retention::Class => "CLASS",
+            Retention::Runtime => "RUNTIME",
+        }
+    }
+
+    /// The retention named by a `RetentionPolicy` constant.
+    pub(crate) fn from_id(id: &str) -> Option<Self> {
+        [Retention::Source, Retention::Class, Retention::Runtime]
+            .iter()
+            .copied()
+            .find(|retention| retention.id() == id)
+    }
+
+    /// The retention implied by the visibility of an annotation. System annotations are
+    /// read by the virtual machine and have none.
+    pub(crate) fn of_visibility(visibility: Visibility) -> Option<Self> {
+        match visibility {
+            Visibility::Build => Some(Retention::Class),
+            Visibility::Runtime => Some(Retention::Runtime),
+            Visibility::System => None,
+        }
+    }
+}
+
+impl fmt::Display for Retention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// The kind of item an annotation is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AnnotationTarget {
+    Class,
+    Field,
+    Method,
+    Parameter,
+}
+
+/// Number of items an annotation type is attached to, by kind of item.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, CopyGetters)]
+#[get_copy = "pub"]
+pub struct UsageCounts {
+    class: usize,
+    method: usize,
+    field: usize,
+    parameter: usize,
+}
+
+impl UsageCounts {
+    pub(crate) fn add(&mut self, target: AnnotationTarget) {
+        match target {
+            AnnotationTarget::Class => self.class += 1,
+            AnnotationTarget::Field => self.field += 1,
+            AnnotationTarget::Method => self.method += 1,
+            AnnotationTarget::Parameter => self.parameter += 1,
+        }
+    }
+
+    /// Number of items of any kind.
+    pub fn total(&self) -> usize {
+        self.class + self.method + self.field + self.parameter
+    }
+}
+
+/// The uses of an annotation type in a file, see `Dex::annotation_usage_summary`.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct AnnotationTypeUsage {
+    /// Descriptor of the annotation type.
+    #[get = "pub"]
+    descriptor: String,
+    /// Whether the file defines the annotation type.
+    #[get_copy = "pub"]
+    defined_locally: bool,
+    /// The retention declared by the definition of the type, or else the one all its uses
+    /// agree on. `None` for system annotations and for types whose uses disagree.
+    #[get_copy = "pub"]
+    retention: Option<Retention>,
+    /// Number of items the type annotates.
+    #[get_copy = "pub"]
+    usage_counts: UsageCounts,
+}
+
+impl AnnotationTypeUsage {
+    pub(crate) fn new(
+        descriptor: String,
+        defined_locally: bool,
+        retention: Option<Retention>,
+        usage_counts: UsageCounts,
+    ) -> Self {
+        Self {
+            descriptor,
+            defined_locally,
+            retention,
+            usage_counts,
+        }
+    }
+
+    /// The usage as a JSON object with the keys of the getters.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        let counts = &self.usage_counts;
+        serde_json::json!({
+            "descriptor": self.descriptor,
+            "defined_locally": self.defined_locally,
+            "retention": self.retention.map(Retention::id),
+            "usage_counts": {
+                "class": counts.class,
+                "method": counts.method,
+                "field": counts.field,
+                "parameter": counts.parameter,
+            },
+        })
+    }
+}
+
+impl fmt::Display for AnnotationTypeUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = &self.usage_counts;
+        write!(
+            f,
+            "{} {} {} class: {} method: {} field: {} parameter: {}",
+            self.descriptor,
+            if self.defined_locally {
+                "local"
+            } else {
+                "external"
+            },
+            self.retention.map_or("unknown", Retention::id),
+            counts.class,
+            counts.method,
+            counts.field,
+            counts.parameter
+        )
+    }
+}
+
+/// Returns `true` if the value is the string with `string_id` or contains it.
+pub(crate) fn value_uses(value: &EncodedValue, string_id: StringId) -> bool {
+    match value {
+        EncodedValue::String { idx, .. } => *idx == string_id,
+        EncodedValue::Array(values) => values.iter().any(|value| value_uses(value, string_id)),
+        EncodedValue::Annotation(annotation) => annotation.elements().iter().any(|element| {
+            element.name_idx() == string_id || value_uses(element.value(), string_id)
+        }),
+        _ => false,
+    }
+}
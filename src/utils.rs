@@ -4,14 +4,65 @@ use crate::{
     encoded_value::EncodedValue,
     error::Error,
     jtype::{Type, TypeId},
-    ushort,
+    source::DexSource,
+    string::DexString,
+    ushort, well_known,
 };
 
+/// Logs a message at `$level`, prefixed with the label of the `Dex` being read if it has
+/// one, see `DexReaderBuilder::log_label`.
+#[cfg(not(feature = "tracing"))]
+macro_rules! labeled {
+    ($level:ident, $span:ident, $label:expr, target: $target:expr, $($arg:tt)+) => {
+        match $label {
+            Some(label) => log::$level!(target: $target, "[{}] {}", label, format_args!($($arg)+)),
+            None => log::$level!(target: $target, $($arg)+),
+        }
+    };
+}
+
+/// Emits a tracing event at `$level`, in a `dex` span with the label of the `Dex` being
+/// read as its `label` field if it has one, see `DexReaderBuilder::log_label`.
+#[cfg(feature = "tracing")]
+macro_rules! labeled {
+    ($level:ident, $span:ident, $label:expr, target: $target:expr, $($arg:tt)+) => {
+        match $label {
+            Some(label) => {
+                let _span = tracing::$span!(target: $target, "dex", label = %label).entered();
+                tracing::$level!(target: $target, $($arg)+)
+            }
+            None => tracing::$level!(target: $target, $($arg)+),
+        }
+    };
+}
+
+/// `debug!` with the label of a `Dex`, an `Option<&str>`, or without a label.
+macro_rules! dex_debug {
+    (target: $target:expr, $($arg:tt)+) => {
+        dex_debug!(None::<&str>, target: $target, $($arg)+)
+    };
+    ($label:expr, target: $target:expr, $($arg:tt)+) => {
+        labeled!(debug, debug_span, $label, target: $target, $($arg)+)
+    };
+}
+
+/// `warn!` with the label of a `Dex`, an `Option<&str>`, or without a label.
+macro_rules! dex_warn {
+    (target: $target:expr, $($arg:tt)+) => {
+        dex_warn!(None::<&str>, target: $target, $($arg)+)
+    };
+    ($label:expr, target: $target:expr, $($arg:tt)+) => {
+        labeled!(warn, warn_span, $label, target: $target, $($arg)+)
+    };
+}
+
 macro_rules! try_gread_vec_with {
     ($source:ident,$offset:ident,$cap:expr,$ctx:expr) => {{
         let cap = $cap as usize;
         let ctx = $ctx;
-        let mut vec = Vec::with_capacity(cap);
+        // the count is read from the file: every element takes at least a byte, so no more
+        // than the bytes left are reserved
+        let mut vec = Vec::with_capacity(cap.min($source.len().saturating_sub(*$offset)));
         // NOTE: gread_inout_with doesn't work when de-serializing encoded array
         // so using an explicit loop here.
         for _ in 0..cap {
@@ -55,7 +106,7 @@ macro_rules! try_from_item {
 
 pub(crate) fn get_types<S>(dex: &super::Dex<S>, type_ids: &[ushort]) -> super::Result<Vec<Type>>
 where
-    S: AsRef<[u8]>,
+    S: DexSource,
 {
     type_ids
         .iter()
@@ -69,15 +120,53 @@ macro_rules! gen_is_flag_set {
         pub fn $name(&self) -> bool {
             self.access_flags.contains(AccessFlags::$flag)
         }
-    }
+    };
+}
+
+/// Implements the methods shared by the access flags of classes, fields and methods,
+/// given the type of their bits and the modifier name of each flag, in the order of the
+/// bits. Expanded in the module of the flags, as it builds them from their bits.
+macro_rules! impl_access_flags {
+    ($bits:ty, $($flag:ident => $name:expr),+ $(,)?) => {
+        impl AccessFlags {
+            /// The flags with their modifier names, in the order of their bits.
+            pub(crate) const MODIFIERS: &'static [(AccessFlags, &'static str)] =
+                &[$((AccessFlags::$flag, $name)),+];
+
+            /// Flags with all of `bits`, including the reserved ones no flag is defined for,
+            /// which `from_bits` rejects and `from_bits_truncate` drops.
+            pub const fn from_bits_retain(bits: $bits) -> Self {
+                Self { bits }
+            }
+
+            /// The reserved bits set, which no flag is defined for.
+            pub const fn unknown_bits(&self) -> $bits {
+                self.bits & !Self::all().bits
+            }
+
+            /// The modifier names of the flags set, separated by spaces, for example
+            /// `public static final`, followed by the unknown bits in hexadecimal if any.
+            pub fn to_modifier_string(&self) -> String {
+                let mut modifiers: Vec<String> = Self::MODIFIERS
+                    .iter()
+                    .filter(|(flag, _)| self.contains(*flag))
+                    .map(|(_, name)| name.to_string())
+                    .collect();
+                if self.unknown_bits() != 0 {
+                    modifiers.push(format!("{:#x}", self.unknown_bits()));
+                }
+                modifiers.join(" ")
+            }
+        }
+    };
 }
 
 pub(crate) fn get_signature(annotations: &AnnotationSetItem) -> super::Result<Option<String>> {
     annotations
         .iter()
-        .find(|item| item.jtype() == "Ldalvik/annotation/Signature;")
+        .find(|item| item.jtype() == well_known::SIGNATURE)
         .map(|item| {
-            let element = item.annotation().find_element("value");
+            let element = item.annotation().find_element(well_known::VALUE_ELEMENT);
             if element.is_none() {
                 return Err(Error::MalFormed(
                     "Expected element with name value, but not found".to_string(),
@@ -89,7 +178,7 @@ pub(crate) fn get_signature(annotations: &AnnotationSetItem) -> super::Result<Op
                     let signature: super::Result<String> = v
                         .iter()
                         .map(|s| {
-                            if let EncodedValue::String(ref v) = s {
+                            if let EncodedValue::String { value: ref v, .. } = s {
                                 Ok(v.to_string())
                             } else {
                                 Err(Error::MalFormed(format!(
@@ -106,3 +195,143 @@ pub(crate) fn get_signature(annotations: &AnnotationSetItem) -> super::Result<Op
         })
         .unwrap_or_else(|| Ok(None))
 }
+
+pub(crate) fn get_source_debug_extension(
+    annotations: &AnnotationSetItem,
+) -> super::Result<Option<DexString>> {
+    annotations
+        .iter()
+        .find(|item| item.jtype() == well_known::SOURCE_DEBUG_EXTENSION)
+        .map(
+            |item| match item.annotation().find_element(well_known::VALUE_ELEMENT) {
+                Some(element) => match *element.value() {
+                    EncodedValue::String { ref value, .. } => Ok(value.clone()),
+                    ref e => Err(Error::MalFormed(format!("Expected string, found: {:?}", e))),
+                },
+                None => Err(Error::MalFormed(
+                    "Expected element with name value, but not found".to_string(),
+                )),
+            },
+        )
+        .transpose()
+}
+
+/// A `Hasher` whose output only depends on the bytes written, whatever the platform and
+/// the Rust version, so that hashes can be stored and compared between runs: integers are
+/// written little endian and the hash is the 64-bit FNV-1a of the bytes.
+///
+/// The hash is a fingerprint, not a digest: inputs crafted to collide are easy to find, so
+/// equal hashes only suggest equal content. A recording hasher also keeps the bytes, for
+/// comparisons which must be exact.
+pub(crate) struct StableHasher {
+    state: u64,
+    bytes: Option<Vec<u8>>,
+}
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: 0xcbf2_9ce4_8422_2325,
+            bytes: None,
+        }
+    }
+
+    /// A hasher which also keeps the bytes written, see `into_bytes`.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn recording() -> Self {
+        Self {
+            bytes: Some(Vec::new()),
+            ..Self::new()
+        }
+    }
+
+    /// The bytes written to a recording hasher, empty for others.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes.unwrap_or_default()
+    }
+}
+
+impl std::hash::Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state = (self.state ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        if let Some(recorded) = &mut self.bytes {
+            recorded.extend_from_slice(bytes);
+        }
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hasher;
+
+    use super::StableHasher;
+    use crate::DexReaderBuilder;
+
+    // Stored hashes must keep matching: a change here breaks them for every user. The
+    // values are the 64-bit FNV-1a of the bytes, as computed by any other implementation.
+    #[test]
+    fn test_stable_hashes_are_pinned() {
+        let data = std::fs::read("resources/classes.dex").expect("cannot read dex");
+        let mut hasher = StableHasher::new();
+        hasher.write(&data);
+        assert_eq!(hasher.finish(), 0x5902_237c_ee50_6550);
+        let mut hasher = StableHasher::new();
+        hasher.write_u32(0x0102_0304);
+        hasher.write_usize(5);
+        assert_eq!(hasher.finish(), 0x16fc_30a7_b2e7_83b0);
+
+        let dex = DexReaderBuilder::new()
+            .track_spans(true)
+            .read_vec(data)
+            .expect("cannot open dex");
+        let bytes_hash = dex
+            .classes()
+            .find_map(|class| class.expect("bad class").static_values_span().copied())
+            .expect("no class with static values")
+            .bytes_hash();
+        assert_eq!(bytes_hash, 0x2393_86a8_8cb5_97e0);
+
+        #[cfg(feature = "annotations-extras")]
+        {
+            let class = dex
+                .classes()
+                .map(|class| class.expect("bad class"))
+                .find(|class| class.annotations().is_ok_and(|set| !set.is_empty()))
+                .expect("no annotated class");
+            let set = class.annotations().expect("bad set");
+            assert_eq!(
+                set.content_hash(&dex).expect("bad set"),
+                0xfeba_7543_c0d0_1eed
+            );
+            assert_eq!(
+                set.annotations()[0]
+                    .canonical_hash(&dex)
+                    .expect("bad annotation"),
+                0x0fd3_e825_67dd_c109
+            );
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! Validation of names and descriptors against the grammars of the dex format.
+//!
+//! The `is_valid_*` functions answer yes or no, the `validate_*` functions tell where and
+//! why a string doesn't match its grammar. Both accept the characters added to
+//! `SimpleName`s in version `040` of the format, such as spaces. `Dex::invalid_names`
+//! applies them to the pools of a file.
+//!
+//! ref. <https://source.android.com/devices/tech/dalvik/dex-format#string-syntax>
+use std::{error, fmt};
+
+use getset::{CopyGetters, Getters};
+
+use crate::{dex::ItemType, uint};
+
+/// Maximum number of dimensions of an array type.
+pub const MAX_ARRAY_DIMENSIONS: usize = 255;
+
+/// How bad a violation of a grammar is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The string violates the grammar of the version of the file, but ART accepts it. This
+    /// is the case of the characters added to `SimpleName`s in version `040`, which ART
+    /// accepts in older files too and obfuscators use to make names hard to read.
+    ArtTolerated,
+    /// The string violates the grammar and ART rejects the file.
+    Invalid,
+}
+
+/// Where and why a string doesn't match its grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, CopyGetters)]
+#[get_copy = "pub"]
+pub struct DescriptorError {
+    /// Byte offset of the offending character in the string, or its length if the string
+    /// ends too early.
+    position: usize,
+    /// What is wrong at `position`.
+    reason: &'static str,
+    /// How bad the violation is.
+    severity: Severity,
+}
+
+impl DescriptorError {
+    fn new(position: usize, reason: &'static str) -> Self {
+        Self {
+            position,
+            reason,
+            severity: Severity::Invalid,
+        }
+    }
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.reason, self.position)
+    }
+}
+
+impl error::Error for DescriptorError {}
+
+/// A name or descriptor of one of the pools of a file which doesn't match its grammar, see
+/// `Dex::invalid_names`.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct InvalidName {
+    /// The pool of the item: `TypeIdItem`, `ClassDefItem`, `FieldIdItem`, `MethodIdItem` or
+    /// `ProtoIdItem`.
+    #[get_copy = "pub"]
+    item_type: ItemType,
+    /// Index of the item in its pool.
+    #[get_copy = "pub"]
+    index: uint,
+    /// The descriptor, name or shorty of the item.
+    #[get = "pub"]
+    value: String,
+    /// The violation.
+    #[get_copy = "pub"]
+    error: DescriptorError,
+}
+
+impl InvalidName {
+    pub(crate) fn new(
+        item_type: ItemType,
+        index: uint,
+        value: String,
+        error: DescriptorError,
+    ) -> Self {
+        Self {
+            item_type,
+            index,
+            value,
+            error,
+        }
+    }
+}
+
+impl fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} {:?}: {:?}, {}",
+            self.item_type, self.index, self.value, self.error.severity, self.error
+        )
+    }
+}
+
+/// Checks a `TypeDescriptor`: `V`, a primitive type, a class or an array.
+pub fn validate_type_descriptor(descriptor: &str) -> Result<(), DescriptorError> {
+    if descriptor == "V" {
+        return Ok(());
+    }
+    field_type_descriptor(descriptor)
+}
+
+/// Returns `true` if `descriptor` is a valid `TypeDescriptor`.
+pub fn is_valid_type_descriptor(descriptor: &str) -> bool {
+    validate_type_descriptor(descriptor).is_ok()
+}
+
+/// Checks the descriptor of a class, such as `Lcom/example/Foo;`: arrays and primitive
+/// types are not classes.
+pub fn validate_class_descriptor(descriptor: &str) -> Result<(), DescriptorError> {
+    match descriptor.chars().next() {
+        Some('L') => non_array_field_type(descriptor, 0),
+        Some('[') => Err(DescriptorError::new(0, "array type is not a class")),
+        Some('V' | 'Z' | 'B' | 'S' | 'C' | 'I' | 'J' | 'F' | 'D') if descriptor.len() == 1 => {
+            Err(DescriptorError::new(0, "primitive type is not a class"))
+        }
+        Some(_) => Err(DescriptorError::new(
+            0,
+            "class descriptor must start with 'L'",
+        )),
+        None => Err(DescriptorError::new(0, "empty descriptor")),
+    }
+}
+
+/// Returns `true` if `descriptor` is the descriptor of a class.
+pub fn is_valid_class_descriptor(descriptor: &str) -> bool {
+    validate_class_descriptor(descriptor).is_ok()
+}
+
+/// Checks a `MemberName`: a `SimpleName`, or a `SimpleName` between `<` and `>` such as
+/// the names of constructors.
+pub fn validate_member_name(name: &str) -> Result<(), DescriptorError> {
+    match name.strip_prefix('<') {
+        Some(inner) => match inner.strip_suffix('>') {
+            Some(inner) => simple_name(inner, 1),
+            None => Err(DescriptorError::new(
+                name.len(),
+                "'<' without a closing '>'",
+            )),
+        },
+        None => simple_name(name, 0),
+    }
+}
+
+/// Returns `true` if `name` is a valid `MemberName`.
+pub fn is_valid_member_name(name: &str) -> bool {
+    validate_member_name(name).is_ok()
+}
+
+/// Checks a `ShortyDescriptor`: the return type then the parameters, each as one of
+/// `ZBSCIJFDL`, with `V` allowed only as the return type.
+pub fn validate_shorty(shorty: &str) -> Result<(), DescriptorError> {
+    if shorty.is_empty() {
+        return Err(DescriptorError::new(0, "empty shorty"));
+    }
+    for (position, c) in shorty.char_indices() {
+        match c {
+            'Z' | 'B' | 'S' | 'C' | 'I' | 'J' | 'F' | 'D' | 'L' => {}
+            'V' if position == 0 => {}
+            'V' => {
+                return Err(DescriptorError::new(
+                    position,
+                    "'V' is only allowed as the return type",
+                ))
+            }
+            _ => return Err(DescriptorError::new(position, "not a shorty type")),
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if `shorty` is a valid `ShortyDescriptor`.
+pub fn is_valid_shorty(shorty: &str) -> bool {
+    validate_shorty(shorty).is_ok()
+}
+
+/// Finds the first character of `name` which is allowed in `SimpleName`s only since
+/// version `040`.
+pub(crate) fn since_040(name: &str) -> Option<DescriptorError> {
+    name.char_indices()
+        .find(|(_, c)| matches!(c, ' ' | '\u{a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}'))
+        .map(|(position, _)| DescriptorError {
+            position,
+            reason: "character only allowed since version 040",
+            severity: Severity::ArtTolerated,
+        })
+}
+
+fn is_simple_name_char(c: char) -> bool {
+    matches!(c,
+        'A'..='Z'
+        | 'a'..='z'
+        | '0'..='9'
+        | ' '
+        | '$'
+        | '-'
+        | '_'
+        | '\u{a0}'..='\u{1fff}'
+        | '\u{2000}'..='\u{200a}'
+        | '\u{2010}'..='\u{2027}'
+        | '\u{202f}'
+        | '\u{2030}'..='\u{d7ff}'
+        | '\u{e000}'..='\u{ffef}'
+        | '\u{10000}'..='\u{10ffff}')
+}
+
+/// Checks a `SimpleName` found at `offset` of the checked string.
+fn simple_name(name: &str, offset: usize) -> Result<(), DescriptorError> {
+    if name.is_empty() {
+        return Err(DescriptorError::new(offset, "empty name"));
+    }
+    match name.char_indices().find(|(_, c)| !is_simple_name_char(*c)) {
+        Some((position, _)) => Err(DescriptorError::new(
+            offset + position,
+            "character not allowed in a name",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Checks a `FieldTypeDescriptor`, a `TypeDescriptor` other than `V`.
+fn field_type_descriptor(descriptor: &str) -> Result<(), DescriptorError> {
+    let dimensions = descriptor.bytes().take_while(|byte| *byte == b'[').count();
+    if dimensions > MAX_ARRAY_DIMENSIONS {
+        return Err(DescriptorError::new(
+            MAX_ARRAY_DIMENSIONS,
+            "more than 255 array dimensions",
+        ));
+    }
+    match &descriptor[dimensions..] {
+        "" if dimensions > 0 => Err(DescriptorError::new(
+            dimensions,
+            "missing array element type",
+        )),
+        "V" if dimensions > 0 => Err(DescriptorError::new(dimensions, "array of void")),
+        element => non_array_field_type(element, dimensions),
+    }
+}
+
+/// Checks a `NonArrayFieldTypeDescriptor` found at `offset` of the checked string.
+fn non_array_field_type(descriptor: &str, offset: usize) -> Result<(), DescriptorError> {
+    match descriptor.chars().next() {
+        Some('Z' | 'B' | 'S' | 'C' | 'I' | 'J' | 'F' | 'D') if descriptor.len() > 1 => Err(
+            DescriptorError::new(offset + 1, "unexpected characters after a primitive type"),
+        ),
+        Some('Z' | 'B' | 'S' | 'C' | 'I' | 'J' | 'F' | 'D') => Ok(()),
+        Some('L') => match descriptor[1..].strip_suffix(';') {
+            Some(class_name) => full_class_name(class_name, offset + 1),
+            None => Err(DescriptorError::new(
+                offset + descriptor.len(),
+                "missing ';' at the end of a class",
+            )),
+        },
+        Some(_) => Err(DescriptorError::new(offset, "unknown type")),
+        None => Err(DescriptorError::new(offset, "empty descriptor")),
+    }
+}
+
+/// Checks a `FullClassName`, `SimpleName`s separated by `/`, found at `offset` of the
+/// checked string.
+fn full_class_name(name: &str, mut offset: usize) -> Result<(), DescriptorError> {
+    for segment in name.split('/') {
+        simple_name(segment, offset)?;
+        offset += segment.len() + 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that each validation fails at the position with the reason, or succeeds
+    /// when no position is given.
+    fn check(
+        validate: fn(&str) -> Result<(), DescriptorError>,
+        cases: &[(&str, Option<(usize, &str)>)],
+    ) {
+        for (input, expected) in cases {
+            let found = validate(input)
+                .err()
+                .map(|error| (error.position(), error.reason()));
+            assert_eq!(found, *expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_type_descriptors() {
+        let deep_array = format!("{}I", "[".repeat(MAX_ARRAY_DIMENSIONS));
+        let too_deep_array = format!("[{}", deep_array);
+        check(
+            validate_type_descriptor,
+            &[
+                ("V", None),
+                ("I", None),
+                ("[[D", None),
+                ("Lfoo;", None),
+                ("Lcom/example/Foo$Bar;", None),
+                ("[Lcom/example/Foo;", None),
+                ("La/b-c/$_0;", None),
+                ("Lcom/example/Ünïcödé;", None),
+                ("Lcom/example/with space;", None),
+                ("Lcom/example/\u{10400};", None),
+                (&deep_array, None),
+                ("", Some((0, "empty descriptor"))),
+                ("[", Some((1, "missing array element type"))),
+                ("[V", Some((1, "array of void"))),
+                (
+                    "II",
+                    Some((1, "unexpected characters after a primitive type")),
+                ),
+                ("X", Some((0, "unknown type"))),
+                ("Lfoo", Some((4, "missing ';' at the end of a class"))),
+                ("L;", Some((1, "empty name"))),
+                ("La//b;", Some((3, "empty name"))),
+                ("La/b/;", Some((5, "empty name"))),
+                ("L/a;", Some((1, "empty name"))),
+                ("La.b;", Some((2, "character not allowed in a name"))),
+                (
+                    "Lcom/example/Foo;;",
+                    Some((16, "character not allowed in a name")),
+                ),
+                ("La/b<c>;", Some((4, "character not allowed in a name"))),
+                ("La\u{2028}b;", Some((2, "character not allowed in a name"))),
+                ("La\u{fff0};", Some((2, "character not allowed in a name"))),
+                (
+                    &too_deep_array,
+                    Some((255, "more than 255 array dimensions")),
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_class_descriptors() {
+        check(
+            validate_class_descriptor,
+            &[
+                ("Lcom/example/Foo;", None),
+                ("LFoo;", None),
+                ("", Some((0, "empty descriptor"))),
+                ("[Lcom/example/Foo;", Some((0, "array type is not a class"))),
+                ("I", Some((0, "primitive type is not a class"))),
+                ("V", Some((0, "primitive type is not a class"))),
+                (
+                    "com/example/Foo",
+                    Some((0, "class descriptor must start with 'L'")),
+                ),
+                (
+                    "Lcom.example.Foo;",
+                    Some((4, "character not allowed in a name")),
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_member_names() {
+        check(
+            validate_member_name,
+            &[
+                ("foo", None),
+                ("$jacocoInit", None),
+                ("access$000", None),
+                ("lambda$main$0", None),
+                ("-$$Nest$mfoo", None),
+                ("<init>", None),
+                ("<clinit>", None),
+                ("<foo>", None),
+                ("日本語", None),
+                ("\u{a0}", None),
+                ("with space", None),
+                ("\u{feff}", None),
+                ("\u{1f600}", None),
+                ("", Some((0, "empty name"))),
+                ("<>", Some((1, "empty name"))),
+                ("<init", Some((5, "'<' without a closing '>'"))),
+                ("init>", Some((4, "character not allowed in a name"))),
+                ("a.b", Some((1, "character not allowed in a name"))),
+                ("a;", Some((1, "character not allowed in a name"))),
+                ("a/b", Some((1, "character not allowed in a name"))),
+                ("a\tb", Some((1, "character not allowed in a name"))),
+                ("\u{7f}", Some((0, "character not allowed in a name"))),
+                ("é\u{200b}", Some((2, "character not allowed in a name"))),
+                ("\u{fffd}", Some((0, "character not allowed in a name"))),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_shorties() {
+        check(
+            validate_shorty,
+            &[
+                ("V", None),
+                ("VL", None),
+                ("ZBSCIJFDL", None),
+                ("LVL", Some((1, "'V' is only allowed as the return type"))),
+                ("", Some((0, "empty shorty"))),
+                ("[I", Some((0, "not a shorty type"))),
+                ("Ll", Some((1, "not a shorty type"))),
+            ],
+        );
+        assert!(is_valid_shorty("IL"));
+        assert!(!is_valid_shorty("I["));
+    }
+
+    #[test]
+    fn test_since_040() {
+        assert_eq!(since_040("plain"), None);
+        for name in ["a b", "a\u{a0}", "a\u{2005}", "a\u{202f}"] {
+            let error = since_040(name).expect(name);
+            assert_eq!(error.position(), 1);
+            assert_eq!(error.severity(), Severity::ArtTolerated);
+            assert!(is_valid_member_name(name));
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Descriptors and names with a special meaning in dex files, such as the system
+//! annotations and the names of constructors.
+//!
+//! The crate only refers to these through the constants of this module.
+pub use crate::jtype::{BOOLEAN, BYTE, CHAR, DOUBLE, FLOAT, INT, LONG, SHORT, VOID};
+
+/// Default values of the elements of an annotation type.
+pub const ANNOTATION_DEFAULT: &str = "Ldalvik/annotation/AnnotationDefault;";
+/// The class enclosing a member class.
+pub const ENCLOSING_CLASS: &str = "Ldalvik/annotation/EnclosingClass;";
+/// The method enclosing a local or anonymous class.
+pub const ENCLOSING_METHOD: &str = "Ldalvik/annotation/EnclosingMethod;";
+/// Name and access flags of an inner class.
+pub const INNER_CLASS: &str = "Ldalvik/annotation/InnerClass;";
+/// The member classes of a class.
+pub const MEMBER_CLASSES: &str = "Ldalvik/annotation/MemberClasses;";
+/// Names and access flags of the parameters of a method.
+pub const METHOD_PARAMETERS: &str = "Ldalvik/annotation/MethodParameters;";
+/// Generic signature of a class, field or method.
+pub const SIGNATURE: &str = "Ldalvik/annotation/Signature;";
+/// Source map of a class, see `Class::source_debug_extension`.
+pub const SOURCE_DEBUG_EXTENSION: &str = "Ldalvik/annotation/SourceDebugExtension;";
+/// Exceptions declared to be thrown by a method.
+pub const THROWS: &str = "Ldalvik/annotation/Throws;";
+/// The nest host of a class.
+pub const NEST_HOST: &str = "Ldalvik/annotation/NestHost;";
+/// The nest members of a class.
+pub const NEST_MEMBERS: &str = "Ldalvik/annotation/NestMembers;";
+/// The permitted subclasses of a sealed class.
+pub const PERMITTED_SUBCLASSES: &str = "Ldalvik/annotation/PermittedSubclasses;";
+/// The components of a record class.
+pub const RECORD: &str = "Ldalvik/annotation/Record;";
+
+/// All the system annotations.
+pub const SYSTEM_ANNOTATIONS: [&str; 13] = [
+    ANNOTATION_DEFAULT,
+    ENCLOSING_CLASS,
+    ENCLOSING_METHOD,
+    INNER_CLASS,
+    MEMBER_CLASSES,
+    METHOD_PARAMETERS,
+    SIGNATURE,
+    SOURCE_DEBUG_EXTENSION,
+    THROWS,
+    NEST_HOST,
+    NEST_MEMBERS,
+    PERMITTED_SUBCLASSES,
+    RECORD,
+];
+
+/// Name of the element of the system annotations which have a single element.
+pub const VALUE_ELEMENT: &str = "value";
+
+/// `java.lang.Object`
+pub const OBJECT: &str = "Ljava/lang/Object;";
+/// `java.lang.String`
+pub const STRING: &str = "Ljava/lang/String;";
+/// `java.lang.Class`
+pub const CLASS: &str = "Ljava/lang/Class;";
+/// `java.lang.Enum`
+pub const ENUM: &str = "Ljava/lang/Enum;";
+/// `java.lang.Record`
+pub const JAVA_RECORD: &str = "Ljava/lang/Record;";
+/// `java.lang.annotation.Annotation`
+pub const ANNOTATION: &str = "Ljava/lang/annotation/Annotation;";
+/// `java.lang.annotation.Retention`, the retention of an annotation type.
+pub const RETENTION: &str = "Ljava/lang/annotation/Retention;";
+/// `java.lang.Throwable`
+pub const THROWABLE: &str = "Ljava/lang/Throwable;";
+/// `java.lang.Exception`
+pub const EXCEPTION: &str = "Ljava/lang/Exception;";
+/// `java.lang.RuntimeException`
+pub const RUNTIME_EXCEPTION: &str = "Ljava/lang/RuntimeException;";
+/// `java.lang.Error`
+pub const ERROR: &str = "Ljava/lang/Error;";
+/// `java.lang.Boolean`
+pub const BOXED_BOOLEAN: &str = "Ljava/lang/Boolean;";
+/// `java.lang.Byte`
+pub const BOXED_BYTE: &str = "Ljava/lang/Byte;";
+/// `java.lang.Short`
+pub const BOXED_SHORT: &str = "Ljava/lang/Short;";
+/// `java.lang.Character`
+pub const BOXED_CHAR: &str = "Ljava/lang/Character;";
+/// `java.lang.Integer`
+pub const BOXED_INT: &str = "Ljava/lang/Integer;";
+/// `java.lang.Long`
+pub const BOXED_LONG: &str = "Ljava/lang/Long;";
+/// `java.lang.Float`
+pub const BOXED_FLOAT: &str = "Ljava/lang/Float;";
+/// `java.lang.Double`
+pub const BOXED_DOUBLE: &str = "Ljava/lang/Double;";
+/// `java.lang.Void`
+pub const BOXED_VOID: &str = "Ljava/lang/Void;";
+
+/// `kotlin.Metadata`, the Kotlin declarations of a class compiled from Kotlin.
+pub const KOTLIN_METADATA: &str = "Lkotlin/Metadata;";
+
+/// Name of the constructors.
+pub const CONSTRUCTOR: &str = "<init>";
+/// Name of the static initializer.
+pub const STATIC_INITIALIZER: &str = "<clinit>";
+
+/// Returns `true` if `descriptor` is the descriptor of a system annotation.
+pub fn is_system_annotation(descriptor: &str) -> bool {
+    SYSTEM_ANNOTATIONS.contains(&descriptor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_system_annotation, SIGNATURE, SYSTEM_ANNOTATIONS};
+
+    #[test]
+    fn test_is_system_annotation() {
+        assert!(is_system_annotation(SIGNATURE));
+        assert!(!is_system_annotation("Ldalvik/annotation/Signature"));
+        assert!(!is_system_annotation("Ljava/lang/Deprecated;"));
+        assert!(SYSTEM_ANNOTATIONS
+            .iter()
+            .all(|descriptor| descriptor.starts_with("Ldalvik/annotation/")));
+    }
+
+    /// The crate must refer to well known descriptors and names through the constants of
+    /// this module only, outside of tests.
+    #[test]
+    fn test_no_well_known_literals() {
+        let patterns = ["\"Ldalvik/", "\"Ljava/lang/", "\"<init>\"", "\"<clinit>\""];
+        let mut dirs = vec![std::path::PathBuf::from("src")];
+        let mut files = Vec::new();
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).expect("cannot list sources") {
+                let path = entry.expect("cannot list sources").path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.file_name().and_then(|name| name.to_str()) != Some("well_known.rs") {
+                    files.push(path);
+                }
+            }
+        }
+        for path in files {
+            let source = std::fs::read_to_string(&path).expect("cannot read source");
+            // the test module may have a `cfg` of its own, with the features it needs
+            let code = source.split("\nmod tests {").next().unwrap();
+            for pattern in &patterns {
+                assert!(
+                    !code.contains(pattern),
+                    "{} has a literal starting with {} instead of a well_known constant",
+                    path.display(),
+                    pattern
+                );
+            }
+        }
+    }
+}
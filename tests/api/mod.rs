@@ -0,0 +1,709 @@
+//! Renders the public API of the crate from the JSON output of rustdoc, one line per item,
+//! for `tests/public_api.rs`.
+//!
+//! Each line names an item by the path it is reachable from, with its signature: modules,
+//! types with their public fields and variants, inherent methods and associated constants,
+//! trait implementations, traits with their items, functions, constants and statics.
+//! Paths of types in signatures are those of their definitions, so that changing how
+//! a type is imported doesn't change the snapshot.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde_json::Value;
+
+/// The version of the rustdoc JSON format the renderer understands.
+pub const FORMAT_VERSION: u64 = 57;
+
+/// Renders the public API in the rustdoc JSON `doc`, as sorted lines.
+pub fn render(doc: &Value) -> BTreeSet<String> {
+    let mut api = Api {
+        doc,
+        lines: BTreeSet::new(),
+        visited: HashSet::new(),
+        reexports: Vec::new(),
+        public_paths: HashMap::new(),
+    };
+    // the first walk finds the public path of the items, used in the signatures of the
+    // second one
+    api.walk();
+    api.lines.clear();
+    api.visited.clear();
+    api.walk();
+    api.lines
+}
+
+struct Api<'a> {
+    doc: &'a Value,
+    lines: BTreeSet<String>,
+    /// Items already rendered, by id.
+    visited: HashSet<String>,
+    /// Ids of the local items re-exported by `use`, with the module and the name they are
+    /// re-exported as.
+    reexports: Vec<(String, String, String)>,
+    /// Path under which each item is rendered, by id.
+    public_paths: HashMap<String, String>,
+}
+
+impl Api<'_> {
+    fn walk(&mut self) {
+        let root = self.doc["root"].to_string();
+        self.item(&root, "", None);
+        // items of private modules are only reachable through their re-exports, which are
+        // rendered with the shortest path
+        while !self.reexports.is_empty() {
+            let mut reexports = std::mem::take(&mut self.reexports);
+            reexports.sort_by_key(|(_, parent, _)| parent.matches("::").count());
+            for (id, parent, name) in reexports {
+                self.item(&id, &parent, Some(&name));
+            }
+        }
+    }
+
+    fn get(&self, id: &str) -> Option<&Value> {
+        self.doc["index"].get(id)
+    }
+
+    fn emit(&mut self, line: String) {
+        self.lines.insert(line);
+    }
+
+    /// Renders the item `id` as `parent::name`, with its own name unless renamed by a
+    /// re-export. The crate itself has an empty parent.
+    fn item(&mut self, id: &str, parent: &str, rename: Option<&str>) {
+        let item = match self.get(id) {
+            Some(item) => item.clone(),
+            None => return,
+        };
+        let name = rename.or_else(|| item["name"].as_str()).unwrap_or_default();
+        let path = if parent.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", parent, name)
+        };
+        let (kind, inner) = match item["inner"]
+            .as_object()
+            .and_then(|inner| inner.iter().next())
+        {
+            Some((kind, inner)) => (kind.as_str(), inner.clone()),
+            None => return,
+        };
+        if kind != "use" && !self.visited.insert(id.to_string()) {
+            return;
+        }
+        if kind != "use" {
+            self.public_paths
+                .entry(id.to_string())
+                .or_insert_with(|| path.clone());
+        }
+        match kind {
+            "module" => {
+                self.emit(format!("mod {}", path));
+                for child in inner["items"].as_array().into_iter().flatten() {
+                    let child = child.to_string();
+                    if self.get(&child).map(|item| item["visibility"] == "public") == Some(true) {
+                        self.item(&child, &path, None);
+                    }
+                }
+            }
+            "use" => {
+                let name = inner["name"].as_str().unwrap_or_default();
+                let target = inner["id"].to_string();
+                if inner["is_glob"] == true {
+                    if let Some(module) = self.get(&target).cloned() {
+                        let children = module["inner"]["module"]["items"].as_array();
+                        for child in children.into_iter().flatten() {
+                            if let Some(name) = self
+                                .get(&child.to_string())
+                                .and_then(|child| child["name"].as_str())
+                            {
+                                self.reexports.push((
+                                    child.to_string(),
+                                    parent.to_string(),
+                                    name.to_string(),
+                                ));
+                            }
+                        }
+                    }
+                    self.emit(format!("use {}::*", parent));
+                    return;
+                }
+                let source = match self.canonical_path(&target) {
+                    Some(path) => path,
+                    None => inner["source"].as_str().unwrap_or_default().to_string(),
+                };
+                // re-exports of private items are listed as the items themselves
+                let reexport = format!("{}::{}", parent, name);
+                if reexport != source {
+                    self.emit(format!("use {} = {}", reexport, source));
+                }
+                if self.get(&target).is_some() {
+                    self.reexports
+                        .push((target, parent.to_string(), name.to_string()));
+                }
+            }
+            "struct" => {
+                let generics = self.generics(&inner["generics"]);
+                let kind = &inner["kind"];
+                let fields: Vec<String> = match kind {
+                    Value::Object(object) if object.contains_key("tuple") => {
+                        let fields: Vec<String> = kind["tuple"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .map(|field| match field {
+                                Value::Null => "_".to_string(),
+                                field => self.field_type(&field.to_string()),
+                            })
+                            .collect();
+                        self.emit(format!(
+                            "struct {}{}({}){}",
+                            path,
+                            generics,
+                            fields.join(", "),
+                            self.where_clause(&inner["generics"])
+                        ));
+                        Vec::new()
+                    }
+                    Value::Object(object) if object.contains_key("plain") => {
+                        self.emit(format!(
+                            "struct {}{}{}",
+                            path,
+                            generics,
+                            self.where_clause(&inner["generics"])
+                        ));
+                        kind["plain"]["fields"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .map(|field| field.to_string())
+                            .collect()
+                    }
+                    _ => {
+                        self.emit(format!("struct {}{};", path, generics));
+                        Vec::new()
+                    }
+                };
+                for field in fields {
+                    if let Some(field) = self.get(&field).cloned() {
+                        if field["visibility"] == "public" {
+                            let jtype = self.ty(&field["inner"]["struct_field"]);
+                            self.emit(format!(
+                                "field {}::{}: {}",
+                                path,
+                                field["name"].as_str().unwrap_or_default(),
+                                jtype
+                            ));
+                        }
+                    }
+                }
+                self.impls(&path, &inner["impls"]);
+            }
+            "enum" => {
+                self.emit(format!(
+                    "enum {}{}{}",
+                    path,
+                    self.generics(&inner["generics"]),
+                    self.where_clause(&inner["generics"])
+                ));
+                for variant in inner["variants"].as_array().into_iter().flatten() {
+                    self.variant(&variant.to_string(), &path);
+                }
+                self.impls(&path, &inner["impls"]);
+            }
+            "union" => {
+                self.emit(format!("union {}", path));
+                self.impls(&path, &inner["impls"]);
+            }
+            "trait" => {
+                let bounds = self.bounds(&inner["bounds"]);
+                let bounds = if bounds.is_empty() {
+                    String::new()
+                } else {
+                    format!(": {}", bounds)
+                };
+                self.emit(format!(
+                    "{}trait {}{}{}",
+                    if inner["is_unsafe"] == true {
+                        "unsafe "
+                    } else {
+                        ""
+                    },
+                    path,
+                    self.generics(&inner["generics"]),
+                    bounds
+                ));
+                for child in inner["items"].as_array().into_iter().flatten() {
+                    if let Some(line) = self.assoc_item(&child.to_string(), &path) {
+                        self.emit(format!("trait item {}", line));
+                    }
+                }
+            }
+            "function" => {
+                let signature = self.function(&path, &inner);
+                self.emit(signature);
+            }
+            "constant" => {
+                let jtype = self.ty(&inner["type"]);
+                self.emit(format!("const {}: {}", path, jtype));
+            }
+            "static" => {
+                let jtype = self.ty(&inner["type"]);
+                let mutable = if inner["is_mutable"] == true {
+                    "mut "
+                } else {
+                    ""
+                };
+                self.emit(format!("static {}{}: {}", mutable, path, jtype));
+            }
+            "type_alias" => {
+                let jtype = self.ty(&inner["type"]);
+                let generics = self.generics(&inner["generics"]);
+                self.emit(format!("type {}{} = {}", path, generics, jtype));
+            }
+            "macro" => self.emit(format!("macro {}!", path)),
+            "extern_crate" => self.emit(format!("extern crate {}", path)),
+            _ => {}
+        }
+    }
+
+    fn field_type(&self, id: &str) -> String {
+        self.get(id)
+            .map(|field| self.ty(&field["inner"]["struct_field"]))
+            .unwrap_or_default()
+    }
+
+    fn variant(&mut self, id: &str, parent: &str) {
+        let variant = match self.get(id) {
+            Some(variant) => variant.clone(),
+            None => return,
+        };
+        let name = variant["name"].as_str().unwrap_or_default();
+        let kind = &variant["inner"]["variant"]["kind"];
+        let fields = match kind {
+            Value::Object(object) if object.contains_key("tuple") => {
+                let fields: Vec<String> = kind["tuple"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|field| match field {
+                        Value::Null => "_".to_string(),
+                        field => self.field_type(&field.to_string()),
+                    })
+                    .collect();
+                format!("({})", fields.join(", "))
+            }
+            Value::Object(object) if object.contains_key("struct") => {
+                let fields: Vec<String> = kind["struct"]["fields"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|field| self.get(&field.to_string()))
+                    .map(|field| {
+                        format!(
+                            "{}: {}",
+                            field["name"].as_str().unwrap_or_default(),
+                            self.ty(&field["inner"]["struct_field"])
+                        )
+                    })
+                    .collect();
+                format!(" {{ {} }}", fields.join(", "))
+            }
+            _ => String::new(),
+        };
+        let discriminant = match variant["inner"]["variant"]["discriminant"]["value"].as_str() {
+            Some(value) => format!(" = {}", value),
+            None => String::new(),
+        };
+        self.emit(format!(
+            "variant {}::{}{}{}",
+            parent, name, fields, discriminant
+        ));
+    }
+
+    /// Renders the inherent items and the trait implementations of a type, leaving out the
+    /// implementations generated by the compiler and the blanket implementations.
+    fn impls(&mut self, path: &str, impls: &Value) {
+        for id in impls.as_array().into_iter().flatten() {
+            let inner = match self.get(&id.to_string()) {
+                Some(item) => item["inner"]["impl"].clone(),
+                None => continue,
+            };
+            if inner["is_synthetic"] == true || !inner["blanket_impl"].is_null() {
+                continue;
+            }
+            let generics = self.generics(&inner["generics"]);
+            let where_clause = self.where_clause(&inner["generics"]);
+            let for_type = self.ty(&inner["for"]);
+            if inner["trait"].is_null() {
+                for item in inner["items"].as_array().into_iter().flatten() {
+                    let item = item.to_string();
+                    if self.get(&item).map(|item| item["visibility"] == "public") != Some(true) {
+                        continue;
+                    }
+                    if let Some(line) = self.assoc_item(&item, path) {
+                        let impl_generics = if generics.is_empty() && where_clause.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" (impl{} {}{})", generics, for_type, where_clause)
+                        };
+                        self.emit(format!("{}{}", line, impl_generics));
+                    }
+                }
+            } else {
+                let negative = if inner["is_negative"] == true {
+                    "!"
+                } else {
+                    ""
+                };
+                let line = format!(
+                    "impl{} {}{} for {}{}",
+                    generics,
+                    negative,
+                    self.path(&inner["trait"]),
+                    for_type,
+                    where_clause
+                );
+                self.emit(line);
+            }
+        }
+    }
+
+    /// Renders a function, associated constant or associated type of `parent`.
+    fn assoc_item(&self, id: &str, parent: &str) -> Option<String> {
+        let item = self.get(id)?;
+        let name = format!("{}::{}", parent, item["name"].as_str().unwrap_or_default());
+        let (kind, inner) = item["inner"].as_object()?.iter().next()?;
+        Some(match kind.as_str() {
+            "function" => self.function(&name, inner),
+            "assoc_const" => format!("const {}: {}", name, self.ty(&inner["type"])),
+            "assoc_type" => {
+                let bounds = self.bounds(&inner["bounds"]);
+                let mut line = format!("type {}{}", name, self.generics(&inner["generics"]));
+                if !bounds.is_empty() {
+                    line.push_str(&format!(": {}", bounds));
+                }
+                if !inner["type"].is_null() {
+                    line.push_str(&format!(" = {}", self.ty(&inner["type"])));
+                }
+                line
+            }
+            _ => return None,
+        })
+    }
+
+    fn function(&self, name: &str, inner: &Value) -> String {
+        let header = &inner["header"];
+        let mut qualifiers = String::new();
+        for (flag, keyword) in &[
+            ("is_const", "const "),
+            ("is_async", "async "),
+            ("is_unsafe", "unsafe "),
+        ] {
+            if header[flag] == true {
+                qualifiers.push_str(keyword);
+            }
+        }
+        let inputs: Vec<String> = inner["sig"]["inputs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|input| {
+                let name = input[0].as_str().unwrap_or_default();
+                let jtype = self.ty(&input[1]);
+                match (name, jtype.as_str()) {
+                    ("self", "Self") => "self".to_string(),
+                    ("self", "&Self") => "&self".to_string(),
+                    ("self", "&mut Self") => "&mut self".to_string(),
+                    _ => format!("{}: {}", name, jtype),
+                }
+            })
+            .collect();
+        let output = match &inner["sig"]["output"] {
+            Value::Null => String::new(),
+            output => format!(" -> {}", self.ty(output)),
+        };
+        format!(
+            "{}fn {}{}({}){}{}",
+            qualifiers,
+            name,
+            self.generics(&inner["generics"]),
+            inputs.join(", "),
+            output,
+            self.where_clause(&inner["generics"])
+        )
+    }
+
+    fn generics(&self, generics: &Value) -> String {
+        let params: Vec<String> = generics["params"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|param| {
+                let name = param["name"].as_str().unwrap_or_default();
+                let kind = &param["kind"];
+                if let Some(lifetime) = kind.get("lifetime") {
+                    let outlives: Vec<&str> = lifetime["outlives"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_str)
+                        .collect();
+                    return Some(if outlives.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}: {}", name, outlives.join(" + "))
+                    });
+                }
+                if let Some(jtype) = kind.get("type") {
+                    if jtype["is_synthetic"] == true {
+                        return None;
+                    }
+                    let bounds = self.bounds(&jtype["bounds"]);
+                    let mut param = name.to_string();
+                    if !bounds.is_empty() {
+                        param.push_str(&format!(": {}", bounds));
+                    }
+                    if !jtype["default"].is_null() {
+                        param.push_str(&format!(" = {}", self.ty(&jtype["default"])));
+                    }
+                    return Some(param);
+                }
+                let constant = &kind["const"];
+                Some(format!("const {}: {}", name, self.ty(&constant["type"])))
+            })
+            .collect();
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", params.join(", "))
+        }
+    }
+
+    fn where_clause(&self, generics: &Value) -> String {
+        let predicates: Vec<String> = generics["where_predicates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|predicate| {
+                if let Some(bound) = predicate.get("bound_predicate") {
+                    return Some(format!(
+                        "{}: {}",
+                        self.ty(&bound["type"]),
+                        self.bounds(&bound["bounds"])
+                    ));
+                }
+                if let Some(lifetime) = predicate.get("lifetime_predicate") {
+                    let outlives: Vec<&str> = lifetime["outlives"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_str)
+                        .collect();
+                    return Some(format!(
+                        "{}: {}",
+                        lifetime["lifetime"].as_str().unwrap_or_default(),
+                        outlives.join(" + ")
+                    ));
+                }
+                let eq = predicate.get("eq_predicate")?;
+                Some(format!(
+                    "{} = {}",
+                    self.ty(&eq["lhs"]),
+                    self.term(&eq["rhs"])
+                ))
+            })
+            .collect();
+        if predicates.is_empty() {
+            String::new()
+        } else {
+            format!(" where {}", predicates.join(", "))
+        }
+    }
+
+    fn bounds(&self, bounds: &Value) -> String {
+        let bounds: Vec<String> = bounds
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bound| {
+                if let Some(lifetime) = bound.get("outlives") {
+                    return lifetime.as_str().map(str::to_string);
+                }
+                let bound = bound.get("trait_bound")?;
+                let modifier = match bound["modifier"].as_str() {
+                    Some("maybe") => "?",
+                    Some("maybe_const") => "~const ",
+                    _ => "",
+                };
+                Some(format!("{}{}", modifier, self.path(&bound["trait"])))
+            })
+            .collect();
+        bounds.join(" + ")
+    }
+
+    fn term(&self, term: &Value) -> String {
+        match term.get("type") {
+            Some(jtype) => self.ty(jtype),
+            None => term["constant"]["expr"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    /// The public path of the item `id`, or the path of its definition.
+    fn canonical_path(&self, id: &str) -> Option<String> {
+        if let Some(path) = self.public_paths.get(id) {
+            return Some(path.clone());
+        }
+        let path = self.doc["paths"].get(id)?["path"].as_array()?;
+        Some(
+            path.iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join("::"),
+        )
+    }
+
+    /// Renders a path with its generic arguments.
+    fn path(&self, path: &Value) -> String {
+        let name = self
+            .canonical_path(&path["id"].to_string())
+            .unwrap_or_else(|| path["path"].as_str().unwrap_or_default().to_string());
+        format!("{}{}", name, self.generic_args(&path["args"]))
+    }
+
+    fn generic_args(&self, args: &Value) -> String {
+        if let Some(parenthesized) = args.get("parenthesized") {
+            let inputs: Vec<String> = parenthesized["inputs"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|input| self.ty(input))
+                .collect();
+            let output = match &parenthesized["output"] {
+                Value::Null => String::new(),
+                output => format!(" -> {}", self.ty(output)),
+            };
+            return format!("({}){}", inputs.join(", "), output);
+        }
+        let angle = match args.get("angle_bracketed") {
+            Some(angle) => angle,
+            None => return String::new(),
+        };
+        let mut rendered: Vec<String> = angle["args"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|arg| {
+                if let Some(lifetime) = arg.get("lifetime") {
+                    lifetime.as_str().unwrap_or_default().to_string()
+                } else if let Some(jtype) = arg.get("type") {
+                    self.ty(jtype)
+                } else if let Some(constant) = arg.get("const") {
+                    constant["expr"].as_str().unwrap_or_default().to_string()
+                } else {
+                    "_".to_string()
+                }
+            })
+            .collect();
+        for constraint in angle["constraints"].as_array().into_iter().flatten() {
+            let name = format!(
+                "{}{}",
+                constraint["name"].as_str().unwrap_or_default(),
+                self.generic_args(&constraint["args"])
+            );
+            let binding = &constraint["binding"];
+            if let Some(equality) = binding.get("equality") {
+                rendered.push(format!("{} = {}", name, self.term(equality)));
+            } else {
+                rendered.push(format!("{}: {}", name, self.bounds(&binding["constraint"])));
+            }
+        }
+        if rendered.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", rendered.join(", "))
+        }
+    }
+
+    fn ty(&self, jtype: &Value) -> String {
+        let (kind, inner) = match jtype.as_object().and_then(|object| object.iter().next()) {
+            Some(kind) => kind,
+            None => return "_".to_string(),
+        };
+        match kind.as_str() {
+            "resolved_path" => self.path(inner),
+            "generic" | "primitive" => inner.as_str().unwrap_or_default().to_string(),
+            "borrowed_ref" => {
+                let lifetime = match inner["lifetime"].as_str() {
+                    Some(lifetime) => format!("{} ", lifetime),
+                    None => String::new(),
+                };
+                let mutable = if inner["is_mutable"] == true {
+                    "mut "
+                } else {
+                    ""
+                };
+                format!("&{}{}{}", lifetime, mutable, self.ty(&inner["type"]))
+            }
+            "raw_pointer" => {
+                let mutability = if inner["is_mutable"] == true {
+                    "mut"
+                } else {
+                    "const"
+                };
+                format!("*{} {}", mutability, self.ty(&inner["type"]))
+            }
+            "slice" => format!("[{}]", self.ty(inner)),
+            "array" => format!(
+                "[{}; {}]",
+                self.ty(&inner["type"]),
+                inner["len"].as_str().unwrap_or_default()
+            ),
+            "tuple" => {
+                let types: Vec<String> = inner
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|jtype| self.ty(jtype))
+                    .collect();
+                format!("({})", types.join(", "))
+            }
+            "impl_trait" => format!("impl {}", self.bounds(inner)),
+            "dyn_trait" => {
+                let mut traits: Vec<String> = inner["traits"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|bound| self.path(&bound["trait"]))
+                    .collect();
+                if let Some(lifetime) = inner["lifetime"].as_str() {
+                    traits.push(lifetime.to_string());
+                }
+                format!("dyn {}", traits.join(" + "))
+            }
+            "qualified_path" => {
+                let self_type = self.ty(&inner["self_type"]);
+                let name = inner["name"].as_str().unwrap_or_default();
+                match &inner["trait"] {
+                    Value::Null => format!("{}::{}", self_type, name),
+                    jtrait => format!("<{} as {}>::{}", self_type, self.path(jtrait), name),
+                }
+            }
+            "function_pointer" => {
+                let inputs: Vec<String> = inner["sig"]["inputs"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|input| self.ty(&input[1]))
+                    .collect();
+                let output = match &inner["sig"]["output"] {
+                    Value::Null => String::new(),
+                    output => format!(" -> {}", self.ty(output)),
+                };
+                format!("fn({}){}", inputs.join(", "), output)
+            }
+            _ => "_".to_string(),
+        }
+    }
+}
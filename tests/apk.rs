@@ -0,0 +1,110 @@
+//! Reading APKs and files from async code, with the `async` feature.
+#![cfg(feature = "async")]
+
+use std::{io::Write, path::Path};
+
+use dex::{prelude::*, ErrorKind};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Writes an APK with the example dex as `classes.dex` and `classes2.dex`, and a
+/// `classes4.dex` which is not loaded as there is no `classes3.dex`.
+fn write_apk(path: &Path, dex: &[u8]) {
+    let mut apk = ZipWriter::new(std::fs::File::create(path).expect("cannot create APK"));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in [
+        ("AndroidManifest.xml", &b"<manifest/>"[..]),
+        ("classes.dex", dex),
+        ("classes2.dex", dex),
+        ("classes4.dex", &b"not a dex"[..]),
+    ] {
+        apk.start_file(name, options).expect("cannot add entry");
+        apk.write_all(data).expect("cannot write entry");
+    }
+    apk.finish().expect("cannot write APK");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_from_apk_async_concurrently() {
+    let dir = tempfile::TempDir::new().expect("cannot create temporary directory");
+    let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+    let path = dir.path().join("app.apk");
+    write_apk(&path, &example);
+
+    let tasks: Vec<_> = (0..100)
+        .map(|_| {
+            let path = path.clone();
+            tokio::spawn(async move {
+                let container = DexContainer::from_apk_async(&path).await?;
+                let classes: Vec<u32> = container
+                    .dexes()
+                    .iter()
+                    .map(|dex| dex.header().class_defs_size())
+                    .collect();
+                Ok::<_, dex::Error>(classes)
+            })
+        })
+        .collect();
+    for task in tasks {
+        let classes = task.await.expect("task failed").expect("cannot read APK");
+        // classes.dex and classes2.dex are identical, so they share a single dex
+        assert_eq!(classes, [323]);
+    }
+
+    let sync = DexContainer::from_apk(&path).expect("cannot read APK");
+    assert_eq!(sync.len(), 2);
+    assert_eq!(sync.unique_dex_count(), 1);
+    let names: Vec<_> = sync.entries().iter().map(|e| e.name().as_str()).collect();
+    assert_eq!(names, ["classes.dex", "classes2.dex"]);
+}
+
+#[tokio::test]
+async fn test_async_errors() {
+    let dir = tempfile::TempDir::new().expect("cannot create temporary directory");
+    let missing = dir.path().join("missing.apk");
+    let e = DexContainer::from_apk_async(&missing).await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::Io);
+
+    let not_zip = dir.path().join("not_zip.apk");
+    std::fs::write(&not_zip, b"not a zip").expect("cannot write file");
+    let e = DexContainer::from_apk_async(&not_zip).await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::MalFormed);
+
+    let corrupt = dir.path().join("corrupt.apk");
+    write_apk(&corrupt, b"dex\n035\0");
+    let e = DexContainer::from_apk_async(&corrupt).await.err().unwrap();
+    assert!(e.is_recoverable());
+
+    let dex = DexReader::from_file_async("resources/classes.dex")
+        .await
+        .expect("cannot read dex");
+    assert_eq!(dex.header().class_defs_size(), 323);
+    let e = DexReader::from_file_async(&missing).await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::Io);
+}
+
+#[test]
+fn test_split_apks_share_identical_dexes() {
+    let dir = tempfile::TempDir::new().expect("cannot create temporary directory");
+    let example = std::fs::read("resources/classes.dex").expect("cannot read dex");
+    let base = dir.path().join("base.apk");
+    let split = dir.path().join("split.apk");
+    write_apk(&base, &example);
+    write_apk(&split, &example);
+
+    let container = DexContainer::from_apks(&[&base, &split]).expect("cannot read APKs");
+    assert_eq!(container.entry_count(), 4);
+    assert_eq!(container.unique_dex_count(), 1);
+    let class = container
+        .find_class_by_name("Lorg/adw/launcher/Launcher;")
+        .expect("cannot find class")
+        .expect("no class");
+    let expected: Vec<_> = [&base, &split]
+        .iter()
+        .flat_map(|apk| {
+            ["classes.dex", "classes2.dex"]
+                .iter()
+                .map(move |name| format!("{}!{}", apk.display(), name))
+        })
+        .collect();
+    assert_eq!(class.sources(), &expected);
+}
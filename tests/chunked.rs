@@ -0,0 +1,73 @@
+//! Checks that a file read from chunks parses exactly like the same file read from one
+//! buffer.
+#[cfg(feature = "analysis")]
+use dex::export::{self, ExportOptions};
+use dex::{source::ChunkedSource, Dex, DexReader, DexSource};
+
+const FIXTURE: &str = "resources/classes.dex";
+
+/// Everything the parser reads from the file, in a comparable form.
+fn parse_output<S: DexSource + 'static>(dex: &Dex<S>) -> Vec<String> {
+    let mut output = Vec::new();
+    for class in dex.classes() {
+        let class = class.expect("bad class");
+        output.push(format!("{:?}", class));
+        output.push(format!(
+            "{:?}",
+            class.annotations().expect("bad annotations")
+        ));
+        for method in class.methods() {
+            output.push(format!(
+                "{:?}",
+                method.annotations().expect("bad method annotations")
+            ));
+        }
+        for field in class.fields() {
+            output.push(format!(
+                "{:?}",
+                field.annotations().expect("bad field annotations")
+            ));
+        }
+    }
+    #[cfg(feature = "analysis")]
+    {
+        let mut csv = Vec::new();
+        export::write_csv(dex, &mut csv, ExportOptions::default()).expect("cannot export");
+        output.push(String::from_utf8(csv).expect("CSV is not UTF-8"));
+        output.push(format!(
+            "{:?}",
+            dex.producer_fingerprint().expect("bad fingerprint")
+        ));
+    }
+    output.push(format!(
+        "{:?}",
+        dex.nonminimal_leb_sites().expect("bad LEB128 sites")
+    ));
+    output
+}
+
+#[test]
+fn test_chunked_matches_contiguous() {
+    let data = std::fs::read(FIXTURE).expect("cannot read dex");
+    let expected = parse_output(&DexReader::from_vec(data.clone()).expect("cannot open dex"));
+
+    for chunk_size in &[4096, 1000] {
+        let source = ChunkedSource::new(data.chunks(*chunk_size).map(<[u8]>::to_vec));
+        assert_eq!(source.len(), data.len());
+        let dex = DexReader::from_source(source).expect("cannot open chunked dex");
+        let actual = parse_output(&dex);
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert_eq!(actual, expected, "chunk size {}", chunk_size);
+        }
+    }
+}
+
+#[test]
+fn test_chunked_rejects_corrupted_file() {
+    let mut data = std::fs::read(FIXTURE).expect("cannot read dex");
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    let source = ChunkedSource::new(data.chunks(4096).map(<[u8]>::to_vec));
+    assert!(DexReader::from_source(source).is_err());
+}
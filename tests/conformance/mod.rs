@@ -0,0 +1,219 @@
+//! Summarizes what the crate reads from a fixture in the shape of the golden files, and
+//! tracks which parse paths the fixtures exercise.
+use std::collections::BTreeSet;
+
+use dex::{code::ExceptionType, method::Method, source::DexSource, Dex, ItemType};
+use serde_json::{json, Value};
+
+/// The fixtures and their golden files, relative to the root of the crate.
+pub const FIXTURES: &[(&str, &str)] = &[(
+    "resources/classes.dex",
+    "tests/conformance/golden/classes.json",
+)];
+
+/// Number of strings sampled from the string_ids section, evenly spaced.
+const STRING_SAMPLES: usize = 32;
+
+/// Parse paths that at least one fixture must exercise.
+pub const PARSE_PATHS: &[&str] = &[
+    "strings",
+    "class_defs",
+    "class_data",
+    "interfaces",
+    "code",
+    "try_catch",
+    "catch_all",
+    "debug_info",
+    "annotations",
+    "static_values",
+    "call_sites",
+    "method_handles",
+];
+
+/// Parse paths no fixture exercises yet, as the corpus has no file built from code using
+/// `invokedynamic`. Adding a fixture which exercises one of them fails the coverage check
+/// until it is removed from here.
+pub const UNCOVERED: &[&str] = &["call_sites", "method_handles"];
+
+/// The parse paths exercised by the fixtures checked so far.
+#[derive(Debug, Default)]
+pub struct Coverage(BTreeSet<&'static str>);
+
+impl Coverage {
+    fn hit(&mut self, path: &'static str) {
+        assert!(PARSE_PATHS.contains(&path), "unknown parse path {}", path);
+        self.0.insert(path);
+    }
+
+    /// The paths of `PARSE_PATHS` which are not exercised but should be, and the ones
+    /// listed in `UNCOVERED` which are.
+    pub fn mismatches(&self) -> (Vec<&'static str>, Vec<&'static str>) {
+        let missing = PARSE_PATHS
+            .iter()
+            .filter(|path| !self.0.contains(*path) && !UNCOVERED.contains(path))
+            .copied()
+            .collect();
+        let stale = UNCOVERED
+            .iter()
+            .filter(|path| self.0.contains(*path))
+            .copied()
+            .collect();
+        (missing, stale)
+    }
+}
+
+/// Summarizes the header, strings and classes of `dex`, recording the parse paths
+/// exercised in `coverage`.
+pub fn summarize<S: DexSource + 'static>(dex: &Dex<S>, coverage: &mut Coverage) -> Value {
+    let header = dex.header();
+    let strings = strings(dex, coverage);
+    let mut classes = Vec::new();
+    for (class_def, class) in dex.class_defs().zip(dex.classes()) {
+        coverage.hit("class_defs");
+        let (class_def, class) = (class_def.unwrap(), class.unwrap());
+        let super_class = class.superclass().map(ToString::to_string);
+        if !class.interfaces().is_empty() {
+            coverage.hit("interfaces");
+        }
+        let static_values = if class_def.static_values_off() != 0 {
+            coverage.hit("static_values");
+            dex.get_static_values(class_def.static_values_off())
+                .unwrap()
+                .len()
+        } else {
+            0
+        };
+        let annotations = if class_def.annotations_off() != 0 {
+            coverage.hit("annotations");
+            let directory = dex
+                .get_annotations_directory_item(class_def.annotations_off())
+                .unwrap();
+            Some(directory.to_dexdump_string(dex).unwrap())
+        } else {
+            None
+        };
+        if class_def.class_data_off() != 0 {
+            coverage.hit("class_data");
+        }
+        let methods: Vec<String> = class
+            .methods()
+            .map(|method| self::method(method, coverage))
+            .collect();
+        classes.push(json!({
+            "descriptor": class.jtype().to_string(),
+            "access_flags": format!("{:#x}", class.access_flags().bits()),
+            "super_class": super_class,
+            "interfaces": class.interfaces().iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "source_file": class.source_file().map(ToString::to_string),
+            "static_fields": class.static_fields().len(),
+            "instance_fields": class.instance_fields().len(),
+            "static_values": static_values,
+            "methods": methods,
+            "annotations": annotations,
+        }));
+    }
+    json!({
+        "header": {
+            "strings": header.string_ids_size(),
+            "types": header.type_ids_size(),
+            "protos": header.proto_ids_size(),
+            "fields": header.field_ids_size(),
+            "methods": header.method_ids_size(),
+            "class_defs": header.class_defs_size(),
+            "call_sites": call_sites(dex, coverage),
+            "method_handles": method_handles(dex, coverage),
+        },
+        "strings": strings,
+        "classes": classes,
+    })
+}
+
+fn strings<S: DexSource>(dex: &Dex<S>, coverage: &mut Coverage) -> Value {
+    let count = dex.header().string_ids_size() as usize;
+    let step = (count / STRING_SAMPLES).max(1);
+    let samples: Vec<Value> = dex
+        .strings()
+        .map(Result::unwrap)
+        .filter(|(index, _)| (*index as usize).is_multiple_of(step) || *index as usize == count - 1)
+        .map(|(index, string)| {
+            coverage.hit("strings");
+            json!([index, string.to_string()])
+        })
+        .collect();
+    json!({ "count": count, "samples": samples })
+}
+
+fn call_sites<S: DexSource + 'static>(dex: &Dex<S>, coverage: &mut Coverage) -> Value {
+    let count = dex
+        .map_list()
+        .get_len(ItemType::CallSiteIdItem)
+        .unwrap_or(0);
+    let arguments: Vec<usize> = (0..count)
+        .map(|id| {
+            coverage.hit("call_sites");
+            dex.get_call_site(id).unwrap().len()
+        })
+        .collect();
+    json!(arguments)
+}
+
+fn method_handles<S: DexSource>(dex: &Dex<S>, coverage: &mut Coverage) -> Value {
+    let handles: Vec<String> = dex
+        .method_handles()
+        .map(|handle| {
+            coverage.hit("method_handles");
+            format!("{:?}", handle.unwrap())
+        })
+        .collect();
+    json!(handles)
+}
+
+/// One line per method: its name and descriptor, flags, and the sizes of its code, try
+/// blocks and line table.
+fn method(method: &Method, coverage: &mut Coverage) -> String {
+    let params: String = method.params().iter().map(ToString::to_string).collect();
+    let mut line = format!(
+        "{}({}){} {:#x}",
+        method.name(),
+        params,
+        method.return_type(),
+        method.access_flags().bits()
+    );
+    let code = match method.code() {
+        Some(code) => code,
+        None => return line,
+    };
+    coverage.hit("code");
+    line.push_str(&format!(
+        " registers={} ins={} outs={} insns={}",
+        code.registers_size,
+        code.ins_size,
+        code.outs_size,
+        code.insns.len()
+    ));
+    for try_block in code.tries.iter() {
+        coverage.hit("try_catch");
+        let handlers: Vec<String> = try_block
+            .catch_handlers()
+            .iter()
+            .map(|handler| match handler.exception() {
+                ExceptionType::Ty(jtype) => format!("{}@{:#x}", jtype, handler.addr()),
+                ExceptionType::BaseException => {
+                    coverage.hit("catch_all");
+                    format!("<any>@{:#x}", handler.addr())
+                }
+            })
+            .collect();
+        line.push_str(&format!(
+            " try[{:#x}+{:#x}: {}]",
+            try_block.start_addr(),
+            try_block.insn_count(),
+            handlers.join(", ")
+        ));
+    }
+    if code.debug_info_item().is_some() {
+        coverage.hit("debug_info");
+        line.push_str(&format!(" lines={}", code.line_number_table().len()));
+    }
+    line
+}
@@ -0,0 +1,118 @@
+//! Checks that operations on large generated files stop at their deadline, and that index
+//! builds keep the work done before it.
+mod generator;
+
+#[cfg(feature = "analysis")]
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "analysis")]
+use dex::ErrorKind;
+use dex::{operation::OperationOptions, DexReader, EagerSections, Error};
+use generator::Preset;
+
+/// Options whose deadline has already passed.
+fn expired() -> OperationOptions {
+    OperationOptions::new().deadline(Instant::now())
+}
+
+#[test]
+#[cfg(feature = "analysis")]
+fn test_expired_deadline_processes_one_class() {
+    let dex = DexReader::from_vec(Preset::CodeHeavy.generate(1)).expect("cannot open dex");
+    let total = dex.header().class_defs_size() as usize;
+
+    let error = dex
+        .run_with(&expired(), |dex| dex.feature_report(1))
+        .expect_err("deadline not checked");
+    assert_eq!(error.kind(), ErrorKind::DeadlineExceeded);
+    assert!(matches!(error, Error::DeadlineExceeded(1, t) if t == total));
+    assert!(!error.is_recoverable());
+
+    let classes = dex.run_with(&expired(), |dex| Ok(dex.classes().collect::<Vec<_>>()));
+    let classes = classes.expect("iterator creation failed");
+    assert_eq!(classes.len(), 2);
+    assert!(classes[0].is_ok());
+    assert!(matches!(classes[1], Err(Error::DeadlineExceeded(1, _))));
+
+    // the lossy scan surfaces the deadline instead of dropping it
+    let classes = dex.run_with(&expired(), |dex| {
+        Ok(dex.classes_lossy().collect::<Vec<_>>())
+    });
+    let classes = classes.expect("iterator creation failed");
+    assert_eq!(classes.len(), 2);
+    assert!(matches!(
+        classes.last(),
+        Some(Err(Error::DeadlineExceeded(1, _)))
+    ));
+
+    // the size hint allows for the early end
+    let hint = dex.run_with(&expired(), |dex| Ok(dex.classes().size_hint()));
+    assert_eq!(hint.expect("iterator creation failed"), (1, Some(total)));
+
+    // the deadline only applies within `run_with`
+    assert!(dex.feature_report(1).is_ok());
+}
+
+#[test]
+#[cfg(feature = "analysis")]
+fn test_deadline_returns_in_time() {
+    let dex = DexReader::from_vec(Preset::CodeHeavy.generate(2)).expect("cannot open dex");
+    let total = dex.header().class_defs_size() as usize;
+    let start = Instant::now();
+    dex.feature_report(1).expect("bad report");
+    let per_class = start.elapsed() / total as u32;
+
+    let deadline = Instant::now() + Duration::from_millis(2);
+    let options = OperationOptions::new().deadline(deadline);
+    match dex.run_with(&options, |dex| dex.feature_report(1)) {
+        // a fast enough machine finishes before the deadline
+        Ok(_) => {}
+        Err(Error::DeadlineExceeded(processed, _)) => {
+            assert!(processed < total);
+            let overrun = Instant::now().saturating_duration_since(deadline);
+            assert!(
+                overrun < per_class * 20 + Duration::from_millis(200),
+                "returned {:?} after the deadline",
+                overrun
+            );
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_interrupted_index_build_resumes() {
+    let dex = DexReader::from_vec(Preset::AppLike.generate(3)).expect("cannot open dex");
+    let total = dex.header().class_defs_size() as usize;
+    let build = |options: &OperationOptions| {
+        dex.run_with(options, |dex| {
+            dex.build_indexes(EagerSections::CLASS_NAME_INDEX)
+        })
+    };
+
+    // each interrupted build adds at least the first class it walks
+    for processed in 1..=3 {
+        let error = build(&expired()).expect_err("deadline not checked");
+        assert!(matches!(error, Error::DeadlineExceeded(p, t) if p == processed && t == total));
+        assert_eq!(dex.interrupted_indexes(), EagerSections::CLASS_NAME_INDEX);
+        assert!(!dex
+            .built_indexes()
+            .contains(EagerSections::CLASS_NAME_INDEX));
+    }
+
+    build(&OperationOptions::new()).expect("cannot build index");
+    assert!(dex.interrupted_indexes().is_empty());
+    assert!(dex
+        .built_indexes()
+        .contains(EagerSections::CLASS_NAME_INDEX));
+    for descriptor in &[
+        "Lgen/p0/C0;",
+        "Lgen/p0/C1;",
+        "Lgen/p0/C2;",
+        "Lgen/p19/C1999;",
+    ] {
+        let class = dex.find_class_by_name(descriptor).expect("bad class");
+        assert!(class.is_some(), "{} not indexed", descriptor);
+    }
+}
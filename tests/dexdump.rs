@@ -0,0 +1,72 @@
+//! Diffs the rendering of the annotations of every class against the output of AOSP's
+//! `dexdump -a`. Only runs if `DEXDUMP_PATH` points to a dexdump binary.
+#![cfg(feature = "annotations-extras")]
+
+use std::{env, process::Command};
+
+use dex::DexReader;
+
+const FIXTURE: &str = "resources/classes.dex";
+
+/// The `Class #N annotations:` blocks of the dexdump output, each with its trailing
+/// empty line.
+fn dexdump_blocks(output: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<String> = None;
+    for line in output.lines() {
+        match current.as_mut() {
+            Some(block) => {
+                block.push_str(line);
+                block.push('\n');
+                if line.is_empty() {
+                    blocks.extend(current.take());
+                }
+            }
+            None if line.starts_with("Class #") && line.ends_with(" annotations:") => {
+                current = Some(format!("{}\n", line));
+            }
+            None => {}
+        }
+    }
+    blocks
+}
+
+#[test]
+fn test_matches_dexdump() {
+    let dexdump = match env::var_os("DEXDUMP_PATH") {
+        Some(path) => path,
+        None => {
+            eprintln!("DEXDUMP_PATH is not set, skipping");
+            return;
+        }
+    };
+    let output = Command::new(dexdump)
+        .arg("-a")
+        .arg(FIXTURE)
+        .output()
+        .expect("cannot run dexdump");
+    assert!(output.status.success(), "dexdump failed: {:?}", output);
+    let expected = dexdump_blocks(&String::from_utf8_lossy(&output.stdout));
+
+    let dex = DexReader::from_file(FIXTURE).expect("cannot open dex");
+    let mut actual = Vec::new();
+    for (index, class_def) in dex.class_defs().enumerate() {
+        let class_def = class_def.expect("bad class def");
+        if class_def.annotations_off() == 0 {
+            continue;
+        }
+        let directory = dex
+            .get_annotations_directory_item(class_def.annotations_off())
+            .expect("bad annotations directory");
+        actual.push(format!(
+            "Class #{} annotations:\n{}",
+            index,
+            directory.to_dexdump_string(&dex).expect("cannot render")
+        ));
+    }
+
+    assert_eq!(actual.len(), expected.len(), "annotated classes differ");
+    for (actual, expected) in actual.iter().zip(&expected) {
+        assert_eq!(actual, expected);
+    }
+}
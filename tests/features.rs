@@ -0,0 +1,46 @@
+//! Checks that the feature matrix of `scripts/feature-matrix.sh` builds every feature of
+//! `Cargo.toml` on its own, and that each feature is documented there.
+use std::{fs, path::Path};
+
+/// The features of the manifest, with the comment lines above each of them.
+fn manifest_features() -> Vec<(String, Vec<String>)> {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let manifest = fs::read_to_string(manifest).expect("cannot read Cargo.toml");
+    let mut features = Vec::new();
+    let mut comments = Vec::new();
+    let section = manifest
+        .lines()
+        .skip_while(|line| line.trim() != "[features]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['));
+    for line in section {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            comments.push(comment.trim().to_string());
+        } else if let Some((name, _)) = line.split_once('=') {
+            features.push((name.trim().to_string(), std::mem::take(&mut comments)));
+        }
+    }
+    features
+}
+
+#[test]
+fn test_matrix_covers_every_feature() {
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("scripts/feature-matrix.sh");
+    let script = fs::read_to_string(script).expect("cannot read feature matrix script");
+    let words: Vec<&str> = script
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .collect();
+    assert!(words.contains(&"--no-default-features"));
+    assert!(words.contains(&"--all-features"));
+
+    let features = manifest_features();
+    assert!(features.iter().any(|(name, _)| name == "decoder"));
+    for (name, comments) in &features {
+        if name == "default" {
+            continue;
+        }
+        assert!(words.contains(&name.as_str()), "{} not in the matrix", name);
+        assert!(!comments.is_empty(), "{} is not documented", name);
+    }
+}
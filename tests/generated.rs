@@ -0,0 +1,189 @@
+//! Checks that the files of the dex generator used by benches and stress tests are well
+//! formed and reproducible.
+mod generator;
+
+use std::{env, fs, process::Command};
+
+use dex::DexReader;
+#[cfg(feature = "decoder")]
+use dex::{
+    opcode::{IndexOperand, Instructions},
+    ErrorKind,
+};
+use generator::Preset;
+
+#[test]
+fn test_presets_parse() {
+    for preset in &Preset::ALL {
+        let config = preset.config(7);
+        let data = generator::generate(&config);
+        let dex = DexReader::from_vec(data)
+            .unwrap_or_else(|e| panic!("cannot open {:?} preset: {:?}", preset, e));
+        let mut classes = 0;
+        let mut methods = 0;
+        let mut annotated = 0;
+        for class in dex.classes() {
+            let class = class.expect("cannot read class");
+            classes += 1;
+            assert_eq!(class.fields().count(), config.fields_per_class);
+            annotated += !class
+                .annotations()
+                .expect("cannot read annotations")
+                .is_empty() as usize;
+            for field in class.fields() {
+                annotated += !field
+                    .annotations()
+                    .expect("cannot read annotations")
+                    .is_empty() as usize;
+            }
+            for method in class.methods() {
+                methods += 1;
+                annotated += !method
+                    .annotations()
+                    .expect("cannot read annotations")
+                    .is_empty() as usize;
+                let code = method.code().expect("method has no code");
+                #[cfg(feature = "decoder")]
+                {
+                    let units: usize = code
+                        .instructions()
+                        .map(|instruction| instruction.expect("bad instruction").units.len())
+                        .sum();
+                    assert_eq!(units, code.insns.len());
+                }
+                let units = code.insns.len();
+                assert!(units >= config.code_units.0.max(2) && units <= config.code_units.1);
+            }
+        }
+        assert_eq!(classes, config.classes, "{:?}", preset);
+        assert_eq!(methods, config.classes * config.methods_per_class);
+        assert!(annotated > 0, "{:?} preset has no annotations", preset);
+    }
+}
+
+#[test]
+fn test_generation_is_deterministic() {
+    for preset in &Preset::ALL {
+        assert_eq!(preset.generate(42), preset.generate(42), "{:?}", preset);
+    }
+    assert_ne!(Preset::Small.generate(1), Preset::Small.generate(2));
+}
+
+/// Also checks the generated file with `dexdump` when it is in the `PATH`.
+#[test]
+fn test_dexdump_accepts_generated() {
+    let found = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|path| path.join("dexdump").is_file()))
+        .unwrap_or(false);
+    if !found {
+        return;
+    }
+    let dir = tempfile::TempDir::new().expect("cannot create temporary directory");
+    let path = dir.path().join("generated.dex");
+    fs::write(&path, Preset::Small.generate(3)).expect("cannot write generated dex file");
+    let status = Command::new("dexdump")
+        .arg("-d")
+        .arg(&path)
+        .status()
+        .expect("cannot run dexdump");
+    assert!(status.success());
+}
+
+#[test]
+#[cfg(feature = "analysis")]
+fn test_generated_fingerprint() {
+    let dex = DexReader::from_vec(Preset::Small.generate(5)).expect("cannot open dex");
+    let fingerprint = dex.producer_fingerprint().expect("bad fingerprint");
+    assert!(fingerprint.map_sorted());
+    assert_eq!(fingerprint.visibilities().keys().collect::<Vec<_>>(), [&1]);
+    assert_eq!(fingerprint.reserved_access_flags(), 0);
+    let example = DexReader::from_file("resources/classes.dex").expect("cannot open dex");
+    let example = example.producer_fingerprint().expect("bad fingerprint");
+    assert_ne!(
+        fingerprint.map_order_signature(),
+        example.map_order_signature()
+    );
+}
+
+/// A file with `classes` classes of `methods_per_class` methods, and the minimal strings
+/// and code.
+#[cfg(feature = "decoder")]
+fn boundary_dex(classes: usize, methods_per_class: usize) -> dex::Dex<Vec<u8>> {
+    let mut config = Preset::Small.config(11);
+    config.classes = classes;
+    config.methods_per_class = methods_per_class;
+    config.fields_per_class = 0;
+    config.extra_strings = 0;
+    config.annotation_percent = 0;
+    config.code_units = (2, 2);
+    DexReader::from_vec(generator::generate(&config)).expect("cannot open dex")
+}
+
+/// `invoke-static {}, method`, with the 16-bit method index of the format.
+#[cfg(feature = "decoder")]
+fn invoke_static(method: u16) -> [u16; 3] {
+    [0x0071, method, 0]
+}
+
+#[test]
+#[cfg(feature = "decoder")]
+fn test_method_ids_boundary() {
+    for methods in [0xffff, 0x1_0000] {
+        let dex = boundary_dex(1, methods);
+        assert_eq!(dex.header().method_ids_size() as usize, methods);
+        let last = methods as u64 - 1;
+        let method = dex.get_method_item(last).expect("cannot read last method");
+        assert_eq!(method.id(), last);
+        let method_ref = dex.get_method_ref(last).expect("cannot read last method");
+        // methods are sorted by name
+        let name = (0..methods).map(|j| format!("m{}", j)).max();
+        assert_eq!(Some(method_ref.name().to_string()), name);
+        let error = dex.get_method_item(methods as u64).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidId);
+
+        let class = dex.classes().next().unwrap().expect("cannot load class");
+        assert_eq!(class.methods().count(), methods);
+        assert!(class.methods().any(|method| method.id() == last));
+
+        let units = invoke_static(0xffff);
+        let instruction = Instructions::new(&units).next().unwrap().unwrap();
+        let operand = instruction.index_operand().expect("no index operand");
+        assert_eq!(operand, IndexOperand::Method(0xffff));
+        let checked = dex.check_index_operand(operand);
+        if methods == 0xffff {
+            assert_eq!(checked.unwrap_err().kind(), ErrorKind::InvalidId);
+        } else {
+            checked.expect("bad index");
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "decoder")]
+fn test_type_ids_boundary() {
+    // each class adds its type to those of java.lang.Object, the annotation, int and void
+    for types in [0xffff, 0x1_0000] {
+        let dex = boundary_dex(types - 4, 0);
+        assert_eq!(dex.header().type_ids_size() as usize, types);
+        let last = types as u32 - 1;
+        let jtype = dex.get_type(last).expect("cannot read last type");
+        assert_eq!(jtype.id(), last);
+        let found = dex
+            .get_type_from_descriptor(&jtype.type_descriptor().to_string())
+            .expect("cannot find type")
+            .expect("type not found");
+        assert_eq!(found.id(), last);
+        assert_eq!(
+            dex.get_type(types as u32).unwrap_err().kind(),
+            ErrorKind::InvalidId
+        );
+        dex.check_index_operand(IndexOperand::Type(last))
+            .expect("bad index");
+        // the types of the classes are before java.lang.Object and void
+        let highest = dex
+            .classes()
+            .map(|class| class.expect("cannot load class").id())
+            .max();
+        assert_eq!(highest, Some(last - 2));
+    }
+}
@@ -0,0 +1,604 @@
+//! Deterministic generator of large, well formed dex files, so that benches and stress tests
+//! measure the same workload on every machine without checking big binaries in.
+//!
+//! The same `Config` always produces the same bytes. Every class extends `java.lang.Object`
+//! and has instance fields of type `int` and static methods of the protos `()V`, `(I)V` and
+//! `(I)I`. Their code is a random mix of `nop`, `const/4`, `add-int/lit8` and calls to other
+//! `()V` methods. Annotated classes, fields and methods get a runtime `@gen.Marker` whose
+//! `value` is one of the strings of the pool.
+#![allow(dead_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Options of the generated dex file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Seed of the pseudo-random choices.
+    pub seed: u64,
+    /// Number of classes.
+    pub classes: usize,
+    /// Number of methods of each class.
+    pub methods_per_class: usize,
+    /// Number of fields of each class.
+    pub fields_per_class: usize,
+    /// Number of random strings added to the strings of the names and types.
+    pub extra_strings: usize,
+    /// Percentage of the classes, fields and methods with an annotation.
+    pub annotation_percent: u64,
+    /// Bounds of the number of code units of the instructions of each method, picked
+    /// uniformly.
+    pub code_units: (usize, usize),
+}
+
+/// Typical shapes of dex files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// A few classes, for quick tests.
+    Small,
+    /// Proportions of a mid-sized application.
+    AppLike,
+    /// Most classes and members annotated.
+    AnnotationHeavy,
+    /// Few, long methods.
+    CodeHeavy,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [
+        Preset::Small,
+        Preset::AppLike,
+        Preset::AnnotationHeavy,
+        Preset::CodeHeavy,
+    ];
+
+    /// The options of the preset.
+    pub fn config(self, seed: u64) -> Config {
+        let (classes, methods_per_class, fields_per_class, extra_strings, annotation_percent) =
+            match self {
+                Preset::Small => (20, 4, 2, 100, 20),
+                Preset::AppLike => (2_000, 12, 4, 20_000, 10),
+                Preset::AnnotationHeavy => (1_000, 10, 6, 5_000, 80),
+                Preset::CodeHeavy => (500, 20, 2, 2_000, 5),
+            };
+        let code_units = match self {
+            Preset::Small => (4, 16),
+            Preset::AppLike => (8, 120),
+            Preset::AnnotationHeavy => (4, 24),
+            Preset::CodeHeavy => (200, 2_000),
+        };
+        Config {
+            seed,
+            classes,
+            methods_per_class,
+            fields_per_class,
+            extra_strings,
+            annotation_percent,
+            code_units,
+        }
+    }
+
+    /// Generates a dex file with the options of the preset.
+    pub fn generate(self, seed: u64) -> Vec<u8> {
+        generate(&self.config(seed))
+    }
+}
+
+/// splitmix64, which is enough to pick shapes and doesn't need a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `low..=high`.
+    fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next() % (high - low + 1)
+    }
+
+    fn percent(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+}
+
+const OBJECT: &str = "Ljava/lang/Object;";
+const MARKER: &str = "Lgen/Marker;";
+/// (shorty, return type, has an int parameter)
+const PROTOS: [(&str, &str, bool); 3] = [("II", "I", true), ("V", "V", false), ("VI", "V", true)];
+
+const ACC_PUBLIC: u64 = 0x1;
+const ACC_STATIC: u64 = 0x8;
+const NO_INDEX: u32 = 0xffff_ffff;
+const VISIBILITY_RUNTIME: u8 = 1;
+const VALUE_STRING: u8 = 0x17;
+
+struct MethodSpec {
+    class: usize,
+    name: String,
+    proto: usize,
+    annotation: Option<String>,
+}
+
+struct FieldSpec {
+    class: usize,
+    name: String,
+    annotation: Option<String>,
+}
+
+/// Generates a dex file with the options.
+pub fn generate(config: &Config) -> Vec<u8> {
+    let mut rng = Rng(config.seed);
+    let class_names: Vec<String> = (0..config.classes)
+        .map(|i| format!("Lgen/p{}/C{};", i / 100, i))
+        .collect();
+
+    let mut strings = BTreeSet::new();
+    for name in class_names.iter().map(String::as_str) {
+        strings.insert(name.to_string());
+    }
+    for name in &[OBJECT, MARKER, "I", "V", "value"] {
+        strings.insert(name.to_string());
+    }
+    for (shorty, _, _) in &PROTOS {
+        strings.insert(shorty.to_string());
+    }
+    let extra_strings: Vec<String> = (0..config.extra_strings)
+        .map(|i| format!("s{}_{:x}", i, rng.next()))
+        .collect();
+    strings.extend(extra_strings.iter().cloned());
+    let pick_value = |rng: &mut Rng| -> String {
+        if extra_strings.is_empty() {
+            "value".to_string()
+        } else {
+            extra_strings[rng.next() as usize % extra_strings.len()].clone()
+        }
+    };
+
+    let mut class_annotations = Vec::new();
+    let mut fields = Vec::new();
+    let mut methods = Vec::new();
+    for class in 0..config.classes {
+        class_annotations.push(if rng.percent(config.annotation_percent) {
+            Some(pick_value(&mut rng))
+        } else {
+            None
+        });
+        for j in 0..config.fields_per_class {
+            let annotation = if rng.percent(config.annotation_percent) {
+                Some(pick_value(&mut rng))
+            } else {
+                None
+            };
+            fields.push(FieldSpec {
+                class,
+                name: format!("f{}", j),
+                annotation,
+            });
+        }
+        for j in 0..config.methods_per_class {
+            let proto = rng.next() as usize % PROTOS.len();
+            let annotation = if rng.percent(config.annotation_percent) {
+                Some(pick_value(&mut rng))
+            } else {
+                None
+            };
+            methods.push(MethodSpec {
+                class,
+                name: format!("m{}", j),
+                proto,
+                annotation,
+            });
+        }
+    }
+    for spec in &fields {
+        strings.insert(spec.name.clone());
+    }
+    for spec in &methods {
+        strings.insert(spec.name.clone());
+    }
+
+    // the strings are ASCII, so byte order is the UTF-16 order required by the format
+    let strings: Vec<String> = strings.into_iter().collect();
+    let string_id = |s: &str| {
+        strings
+            .binary_search_by(|probe| probe.as_str().cmp(s))
+            .unwrap() as u32
+    };
+
+    // types are sorted by string id
+    let mut type_strings: Vec<u32> = class_names
+        .iter()
+        .map(String::as_str)
+        .chain(vec![OBJECT, MARKER, "I", "V"])
+        .map(string_id)
+        .collect();
+    type_strings.sort_unstable();
+    let type_id = |s: &str| type_strings.binary_search(&string_id(s)).unwrap() as u32;
+
+    // protos are sorted by return type, then parameters
+    let mut protos: Vec<usize> = (0..PROTOS.len()).collect();
+    protos.sort_by_key(|&p| (type_id(PROTOS[p].1), PROTOS[p].2));
+    let proto_id = |p: usize| protos.iter().position(|&q| q == p).unwrap() as u32;
+
+    // fields and methods are sorted by class, name and type or proto
+    let field_key = |f: &FieldSpec| (type_id(&class_names[f.class]), string_id(&f.name));
+    fields.sort_by_key(field_key);
+    let method_key = |m: &MethodSpec| {
+        (
+            type_id(&class_names[m.class]),
+            string_id(&m.name),
+            proto_id(m.proto),
+        )
+    };
+    methods.sort_by_key(method_key);
+    // classes are sorted by type, none extends another class of the file
+    let mut classes: Vec<usize> = (0..config.classes).collect();
+    classes.sort_by_key(|&c| type_id(&class_names[c]));
+
+    // invoke-static has a 16-bit method index
+    let void_methods: Vec<u32> = (0..methods.len().min(0x1_0000) as u32)
+        .filter(|&m| PROTOS[methods[m as usize].proto] == ("V", "V", false))
+        .collect();
+
+    let mut w = Writer::default();
+    w.bytes.resize(0x70, 0);
+    let string_ids_off = w.reserve(4 * strings.len());
+    let type_ids_off = w.reserve(4 * type_strings.len());
+    let proto_ids_off = w.reserve(12 * protos.len());
+    let field_ids_off = w.reserve(8 * fields.len());
+    let method_ids_off = w.reserve(8 * methods.len());
+    let class_defs_off = w.reserve(32 * classes.len());
+    let data_off = w.bytes.len();
+
+    let mut map = vec![
+        (0x0000u16, 1usize, 0usize),
+        (0x0001, strings.len(), string_ids_off),
+        (0x0002, type_strings.len(), type_ids_off),
+        (0x0003, protos.len(), proto_ids_off),
+        (0x0004, fields.len(), field_ids_off),
+        (0x0005, methods.len(), method_ids_off),
+        (0x0006, classes.len(), class_defs_off),
+    ];
+
+    // string_data_item
+    let start = w.bytes.len();
+    for (index, string) in strings.iter().enumerate() {
+        let offset = w.bytes.len() as u32;
+        w.put_u32(string_ids_off + 4 * index, offset);
+        w.uleb(string.len() as u64);
+        w.bytes.extend_from_slice(string.as_bytes());
+        w.bytes.push(0);
+    }
+    map.push((0x2002, strings.len(), start));
+
+    // type_list of the (I) protos
+    w.align();
+    let int_params_off = w.bytes.len();
+    w.u32(1);
+    w.u16(type_id("I") as u16);
+    map.push((0x1001, 1, int_params_off));
+
+    // annotation_item and annotation_set_item of each annotated item
+    let annotated: Vec<&String> = class_annotations
+        .iter()
+        .flatten()
+        .chain(fields.iter().filter_map(|f| f.annotation.as_ref()))
+        .chain(methods.iter().filter_map(|m| m.annotation.as_ref()))
+        .collect();
+    let start = w.bytes.len();
+    let mut annotation_items = Vec::with_capacity(annotated.len());
+    for value in &annotated {
+        annotation_items.push(w.bytes.len() as u32);
+        w.bytes.push(VISIBILITY_RUNTIME);
+        w.uleb(u64::from(type_id(MARKER)));
+        w.uleb(1);
+        w.uleb(u64::from(string_id("value")));
+        let index = string_id(value).to_le_bytes();
+        let len = 4 - index.iter().rev().take_while(|b| **b == 0).count().min(3);
+        w.bytes.push(((len as u8 - 1) << 5) | VALUE_STRING);
+        w.bytes.extend_from_slice(&index[..len]);
+    }
+    if !annotated.is_empty() {
+        map.push((0x2004, annotated.len(), start));
+    }
+    w.align();
+    let start = w.bytes.len();
+    let mut annotation_sets = annotation_items.into_iter().map(|item| {
+        w.align();
+        let offset = w.bytes.len() as u32;
+        w.u32(1);
+        w.u32(item);
+        offset
+    });
+    let class_sets: Vec<Option<u32>> = class_annotations
+        .iter()
+        .map(|a| a.as_ref().map(|_| annotation_sets.next().unwrap()))
+        .collect();
+    let field_sets: Vec<Option<u32>> = fields
+        .iter()
+        .map(|f| {
+            f.annotation
+                .as_ref()
+                .map(|_| annotation_sets.next().unwrap())
+        })
+        .collect();
+    let method_sets: Vec<Option<u32>> = methods
+        .iter()
+        .map(|m| {
+            m.annotation
+                .as_ref()
+                .map(|_| annotation_sets.next().unwrap())
+        })
+        .collect();
+    drop(annotation_sets);
+    if !annotated.is_empty() {
+        map.push((0x1003, annotated.len(), start));
+    }
+
+    // annotations_directory_item of the classes with any annotation
+    let mut directories = vec![0u32; config.classes];
+    let mut fields_by_class: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, field) in fields.iter().enumerate() {
+        fields_by_class.entry(field.class).or_default().push(index);
+    }
+    let mut methods_by_class: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, method) in methods.iter().enumerate() {
+        methods_by_class
+            .entry(method.class)
+            .or_default()
+            .push(index);
+    }
+    let no_members = Vec::new();
+    let start = w.bytes.len();
+    let mut directory_count = 0;
+    for &class in &classes {
+        let class_fields = fields_by_class.get(&class).unwrap_or(&no_members);
+        let class_methods = methods_by_class.get(&class).unwrap_or(&no_members);
+        let annotated_fields: Vec<usize> = class_fields
+            .iter()
+            .cloned()
+            .filter(|&f| field_sets[f].is_some())
+            .collect();
+        let annotated_methods: Vec<usize> = class_methods
+            .iter()
+            .cloned()
+            .filter(|&m| method_sets[m].is_some())
+            .collect();
+        if class_sets[class].is_none()
+            && annotated_fields.is_empty()
+            && annotated_methods.is_empty()
+        {
+            continue;
+        }
+        directory_count += 1;
+        directories[class] = w.bytes.len() as u32;
+        w.u32(class_sets[class].unwrap_or(0));
+        w.u32(annotated_fields.len() as u32);
+        w.u32(annotated_methods.len() as u32);
+        w.u32(0);
+        for f in annotated_fields {
+            w.u32(f as u32);
+            w.u32(field_sets[f].unwrap());
+        }
+        for m in annotated_methods {
+            w.u32(m as u32);
+            w.u32(method_sets[m].unwrap());
+        }
+    }
+    if directory_count > 0 {
+        map.push((0x2006, directory_count, start));
+    }
+
+    // code_item of each method
+    let start = w.bytes.len();
+    let mut code_offs = Vec::with_capacity(methods.len());
+    for method in &methods {
+        let (_, return_type, has_param) = PROTOS[method.proto];
+        let ins = has_param as u16;
+        w.align();
+        code_offs.push(w.bytes.len() as u64);
+        let insns = instructions(&mut rng, config.code_units, return_type, &void_methods);
+        w.u16(1 + ins);
+        w.u16(ins);
+        w.u16(0);
+        w.u16(0);
+        w.u32(0);
+        w.u32(insns.len() as u32);
+        for unit in insns {
+            w.u16(unit);
+        }
+    }
+    if !methods.is_empty() {
+        map.push((0x2001, methods.len(), start));
+    }
+
+    // class_data_item of each class
+    let start = w.bytes.len();
+    let mut class_datas = vec![0u32; config.classes];
+    for &class in &classes {
+        let class_fields = fields_by_class.get(&class).unwrap_or(&no_members);
+        let class_methods = methods_by_class.get(&class).unwrap_or(&no_members);
+        class_datas[class] = w.bytes.len() as u32;
+        w.uleb(0);
+        w.uleb(class_fields.len() as u64);
+        w.uleb(class_methods.len() as u64);
+        w.uleb(0);
+        let mut previous = 0;
+        for &f in class_fields {
+            w.uleb((f - previous) as u64);
+            w.uleb(ACC_PUBLIC);
+            previous = f;
+        }
+        let mut previous = 0;
+        for &m in class_methods {
+            w.uleb((m - previous) as u64);
+            w.uleb(ACC_PUBLIC | ACC_STATIC);
+            w.uleb(code_offs[m]);
+            previous = m;
+        }
+    }
+    if !classes.is_empty() {
+        map.push((0x2000, classes.len(), start));
+    }
+
+    // the id sections
+    for (index, &string) in type_strings.iter().enumerate() {
+        w.put_u32(type_ids_off + 4 * index, string);
+    }
+    for (index, &p) in protos.iter().enumerate() {
+        let (shorty, return_type, has_param) = PROTOS[p];
+        let offset = proto_ids_off + 12 * index;
+        w.put_u32(offset, string_id(shorty));
+        w.put_u32(offset + 4, type_id(return_type));
+        w.put_u32(
+            offset + 8,
+            if has_param { int_params_off as u32 } else { 0 },
+        );
+    }
+    for (index, field) in fields.iter().enumerate() {
+        let offset = field_ids_off + 8 * index;
+        w.put_u16(offset, type_id(&class_names[field.class]) as u16);
+        w.put_u16(offset + 2, type_id("I") as u16);
+        w.put_u32(offset + 4, string_id(&field.name));
+    }
+    for (index, method) in methods.iter().enumerate() {
+        let offset = method_ids_off + 8 * index;
+        w.put_u16(offset, type_id(&class_names[method.class]) as u16);
+        w.put_u16(offset + 2, proto_id(method.proto) as u16);
+        w.put_u32(offset + 4, string_id(&method.name));
+    }
+    for (index, &class) in classes.iter().enumerate() {
+        let offset = class_defs_off + 32 * index;
+        let values = [
+            type_id(&class_names[class]),
+            ACC_PUBLIC as u32,
+            type_id(OBJECT),
+            0,
+            NO_INDEX,
+            directories[class],
+            class_datas[class],
+            0,
+        ];
+        for (i, value) in values.iter().enumerate() {
+            w.put_u32(offset + 4 * i, *value);
+        }
+    }
+
+    // map_list
+    w.align();
+    let map_off = w.bytes.len();
+    map.push((0x1000, 1, map_off));
+    w.u32(map.len() as u32);
+    for (item_type, size, offset) in map {
+        w.u16(item_type);
+        w.u16(0);
+        w.u32(size as u32);
+        w.u32(offset as u32);
+    }
+
+    // header
+    let file_size = w.bytes.len();
+    w.bytes[..8].copy_from_slice(b"dex\n035\0");
+    let sections = [
+        (strings.len(), string_ids_off),
+        (type_strings.len(), type_ids_off),
+        (protos.len(), proto_ids_off),
+        (fields.len(), field_ids_off),
+        (methods.len(), method_ids_off),
+        (classes.len(), class_defs_off),
+    ];
+    w.put_u32(32, file_size as u32);
+    w.put_u32(36, 0x70);
+    w.put_u32(40, 0x1234_5678);
+    w.put_u32(52, map_off as u32);
+    for (index, &(size, offset)) in sections.iter().enumerate() {
+        w.put_u32(56 + 8 * index, size as u32);
+        w.put_u32(60 + 8 * index, if size == 0 { 0 } else { offset as u32 });
+    }
+    w.put_u32(104, (file_size - data_off) as u32);
+    w.put_u32(108, data_off as u32);
+    let signature = sha1_smol::Sha1::from(&w.bytes[32..]).digest().bytes();
+    w.bytes[12..32].copy_from_slice(&signature);
+    let checksum = adler32::adler32(&w.bytes[12..]).unwrap();
+    w.put_u32(8, checksum);
+    w.bytes
+}
+
+/// Returns the instructions of a method, ending with a return.
+fn instructions(
+    rng: &mut Rng,
+    (low, high): (usize, usize),
+    return_type: &str,
+    void_methods: &[u32],
+) -> Vec<u16> {
+    let len = rng.range(low.max(2) as u64, high.max(2) as u64) as usize;
+    // const/4 v0, #0 so that v0 is initialized
+    let mut insns = vec![0x0012];
+    while insns.len() < len - 1 {
+        let left = len - 1 - insns.len();
+        match rng.next() % 4 {
+            0 => insns.push(0x0000),
+            1 => insns.push(0x0012 | ((rng.next() as u16 & 0x7) << 12)),
+            2 if left >= 2 => insns.extend_from_slice(&[0x00d8, (rng.next() as u16 & 0x7f) << 8]),
+            3 if left >= 3 && !void_methods.is_empty() => {
+                let method = void_methods[rng.next() as usize % void_methods.len()];
+                // invoke-static {}, method
+                insns.extend_from_slice(&[0x0071, method as u16, 0]);
+            }
+            _ => insns.push(0x0000),
+        }
+    }
+    // return v0 or return-void
+    insns.push(if return_type == "V" { 0x000e } else { 0x000f });
+    insns
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn reserve(&mut self, len: usize) -> usize {
+        let offset = self.bytes.len();
+        self.bytes.resize(offset + len, 0);
+        offset
+    }
+
+    fn align(&mut self) {
+        let len = (self.bytes.len() + 3) & !3;
+        self.bytes.resize(len, 0);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u16(&mut self, offset: usize, value: u16) {
+        self.bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, offset: usize, value: u32) {
+        self.bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn uleb(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+}
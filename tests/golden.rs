@@ -0,0 +1,72 @@
+//! Checks what the crate reads from each fixture of the conformance corpus against the
+//! golden file of the fixture: the counts of the header, a sample of the strings, and
+//! for each class its flags, hierarchy, fields count, static values count, annotations
+//! rendered like `dexdump -a`, and the descriptor, flags, code sizes, try blocks and
+//! line table size of each method. It also checks that the fixtures exercise every parse
+//! path, see `conformance::PARSE_PATHS`.
+//!
+//! The golden files are checked in, so no tool is needed to run the suite. After adding
+//! a fixture or an intended change, regenerate them with
+//! `UPDATE_CONFORMANCE=1 cargo test --all-features --test golden`, and review the
+//! diff against the output of `dexdump -a -d` for the fixture.
+#![cfg(all(feature = "annotations-extras", feature = "debug-info"))]
+mod conformance;
+
+use conformance::{Coverage, FIXTURES};
+use dex::DexReader;
+use serde_json::Value;
+
+#[test]
+fn test_conformance() {
+    let update = std::env::var_os("UPDATE_CONFORMANCE").is_some();
+    let mut coverage = Coverage::default();
+    for (fixture, golden) in FIXTURES {
+        let dex = DexReader::from_file(fixture).expect("cannot open fixture");
+        let actual = conformance::summarize(&dex, &mut coverage);
+        if update {
+            let mut rendered = serde_json::to_string_pretty(&actual).unwrap();
+            rendered.push('\n');
+            std::fs::write(golden, rendered).expect("cannot write golden file");
+            continue;
+        }
+        let expected: Value = serde_json::from_str(
+            &std::fs::read_to_string(golden).expect("cannot read golden file"),
+        )
+        .expect("bad golden file");
+        for section in &["header", "strings"] {
+            assert_eq!(
+                actual[section], expected[section],
+                "{} of {} differ from {}",
+                section, fixture, golden
+            );
+        }
+        let (actual, expected) = (
+            actual["classes"].as_array().unwrap(),
+            expected["classes"].as_array().unwrap(),
+        );
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "classes of {} differ",
+            fixture
+        );
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert_eq!(
+                actual, expected,
+                "class {} of {} differs from {}",
+                expected["descriptor"], fixture, golden
+            );
+        }
+    }
+    let (missing, stale) = coverage.mismatches();
+    assert!(
+        missing.is_empty(),
+        "no fixture exercises {:?}, add one which does",
+        missing
+    );
+    assert!(
+        stale.is_empty(),
+        "{:?} are exercised now, remove them from `UNCOVERED`",
+        stale
+    );
+}
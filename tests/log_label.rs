@@ -0,0 +1,169 @@
+//! The label of a `Dex` in the logs emitted while reading it, see
+//! `DexReaderBuilder::log_label`.
+use std::{
+    sync::{Mutex, OnceLock},
+    thread,
+};
+
+use dex::prelude::*;
+
+/// Labels of the files read concurrently, also the names of the threads reading them.
+const LABELS: [&str; 2] = ["classes.dex", "classes2.dex"];
+
+/// Name of the thread emitting an event and the label of the event.
+type Event = (String, Option<String>);
+
+/// The events captured.
+static EVENTS: OnceLock<Mutex<Vec<Event>>> = OnceLock::new();
+
+fn record(label: Option<String>) {
+    if let Some(thread) = thread::current().name() {
+        if LABELS.contains(&thread) {
+            let events = EVENTS.get_or_init(Default::default);
+            events.lock().unwrap().push((thread.to_string(), label));
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod capture {
+    use log::{LevelFilter, Log, Metadata, Record};
+
+    struct TestLogger;
+
+    impl Log for TestLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let message = record.args().to_string();
+            let label = message
+                .strip_prefix('[')
+                .and_then(|message| message.split_once("] "))
+                .map(|(label, _)| label.to_string());
+            super::record(label);
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub(super) fn install() {
+        log::set_logger(&TestLogger).expect("logger already set");
+        log::set_max_level(LevelFilter::Debug);
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod capture {
+    use std::{cell::RefCell, collections::HashMap, fmt, sync::Mutex};
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    /// Records the `label` field of the spans and the label of the innermost entered span
+    /// of each event.
+    #[derive(Default)]
+    struct TestSubscriber {
+        labels: Mutex<HashMap<u64, String>>,
+    }
+
+    thread_local! {
+        static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct LabelVisitor(Option<String>);
+
+    impl Visit for LabelVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "label" {
+                self.0 = Some(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl Subscriber for TestSubscriber {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let mut labels = self.labels.lock().unwrap();
+            let id = labels.len() as u64 + 1;
+            let mut visitor = LabelVisitor(None);
+            span.record(&mut visitor);
+            labels.insert(id, visitor.0.unwrap_or_default());
+            span::Id::from_u64(id)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, _: &Event<'_>) {
+            let current = ENTERED.with(|entered| entered.borrow().last().cloned());
+            let label = current.map(|id| self.labels.lock().unwrap()[&id].clone());
+            super::record(label);
+        }
+
+        fn enter(&self, span: &span::Id) {
+            ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+        }
+
+        fn exit(&self, _: &span::Id) {
+            ENTERED.with(|entered| entered.borrow_mut().pop());
+        }
+    }
+
+    pub(super) fn install() {
+        tracing::subscriber::set_global_default(TestSubscriber::default())
+            .expect("subscriber already set");
+    }
+}
+
+/// Reads the file, its classes, methods and annotations.
+fn read(label: &str) -> usize {
+    let dex = DexReader::builder()
+        .log_label(label)
+        .read_file("resources/classes.dex")
+        .expect("cannot open dex");
+    assert_eq!(dex.log_label(), Some(label));
+    let mut methods = 0;
+    for class in dex.classes() {
+        let class = class.expect("cannot load class");
+        class.annotations().expect("bad annotations");
+        for method in class.methods() {
+            method.annotations().expect("bad annotations");
+            methods += 1;
+        }
+    }
+    methods
+}
+
+#[test]
+fn test_log_label() {
+    capture::install();
+    let threads: Vec<_> = LABELS
+        .iter()
+        .map(|label| {
+            thread::Builder::new()
+                .name(label.to_string())
+                .spawn(move || read(label))
+                .expect("cannot spawn thread")
+        })
+        .collect();
+    for thread in threads {
+        assert!(thread.join().expect("thread panicked") > 0);
+    }
+
+    let events = EVENTS.get().expect("no events").lock().unwrap();
+    for label in LABELS.iter() {
+        let count = events.iter().filter(|(thread, _)| thread == label).count();
+        assert!(count > 100, "only {} events for {}", count, label);
+    }
+    for (thread, label) in events.iter() {
+        assert_eq!(label.as_deref(), Some(thread.as_str()));
+    }
+}
@@ -0,0 +1,133 @@
+//! Builds classes in memory, as a crate depending on `dex` would in its own tests.
+#![cfg(feature = "test-util")]
+
+#[cfg(feature = "annotations-extras")]
+use dex::annotation::AnnotationElement;
+use dex::{
+    mock::{self, ClassBuilder, MethodBuilder},
+    prelude::*,
+};
+
+/// Test methods of a class, as a test runner would find them.
+fn test_methods(class: &Class) -> dex::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for method in class.methods() {
+        if method.has_annotation("Lorg/junit/Test;")? {
+            names.push(method.name().to_string());
+        }
+    }
+    Ok(names)
+}
+
+#[test]
+fn test_class_with_annotated_methods() -> dex::Result<()> {
+    let expected = AnnotationItem::new(
+        Visibility::Runtime,
+        EncodedAnnotation::new(mock::jtype("Lorg/junit/Test;"), Vec::new()),
+    );
+    let class = ClassBuilder::new("Lcom/example/CalculatorTest;")
+        .method(MethodBuilder::new("<init>", &[], "V"))
+        .method(MethodBuilder::new("testAdd", &[], "V").annotation_item(expected))
+        .method(
+            MethodBuilder::new("testDivide", &[], "V")
+                .annotation("Lorg/junit/Test;")
+                .annotation("Lorg/junit/Ignore;"),
+        )
+        .method(MethodBuilder::new("helper", &["I"], "I"))
+        .build();
+
+    assert_eq!(test_methods(&class)?, ["testAdd", "testDivide"]);
+    let divide = &class.virtual_methods()[1];
+    assert!(divide.find_annotation("Lorg/junit/Ignore;")?.is_some());
+    assert!(!class.has_annotation("Lorg/junit/Test;")?);
+    assert!(class.annotations()?.is_empty());
+    Ok(())
+}
+
+/// `@Author(name = ..., year = ...)`, with the elements in the reverse order if `reversed`.
+#[cfg(feature = "annotations-extras")]
+fn author(name: &str, year: i32, reversed: bool) -> AnnotationItem {
+    let name = EncodedValue::String {
+        idx: dex::NO_INDEX,
+        value: DexString::from(name.to_string()),
+    };
+    let mut elements = vec![
+        AnnotationElement::new("name", name),
+        AnnotationElement::new("year", EncodedValue::Int(year)),
+    ];
+    if reversed {
+        elements.reverse();
+    }
+    AnnotationItem::new(
+        Visibility::Runtime,
+        EncodedAnnotation::new(mock::jtype("Lcom/example/Author;"), elements),
+    )
+}
+
+#[test]
+#[cfg(feature = "annotations-extras")]
+fn test_content_hash_ignores_order() -> dex::Result<()> {
+    let dex = DexReader::from_file("resources/classes.dex")?;
+    let first = ClassBuilder::new("Lcom/example/First;")
+        .annotation("Lcom/example/Reviewed;")
+        .annotation_item(author("Ada", 1843, false))
+        .build();
+    let second = ClassBuilder::new("Lcom/example/Second;")
+        .annotation_item(author("Ada", 1843, true))
+        .annotation("Lcom/example/Reviewed;")
+        .build();
+    let other = ClassBuilder::new("Lcom/example/Other;")
+        .annotation("Lcom/example/Reviewed;")
+        .annotation_item(author("Ada", 1842, false))
+        .build();
+
+    let hash = |class: &Class| class.annotations()?.content_hash(&dex);
+    assert!(first.annotations()?.item_offsets().is_empty());
+    assert_eq!(hash(&first)?, hash(&second)?);
+    assert_ne!(hash(&first)?, hash(&other)?);
+    Ok(())
+}
+
+/// `@kotlin.Metadata(k = kind)`
+fn kotlin_metadata(kind: i32) -> AnnotationItem {
+    AnnotationItem::new(
+        Visibility::Runtime,
+        EncodedAnnotation::new(
+            mock::jtype("Lkotlin/Metadata;"),
+            vec![dex::annotation::AnnotationElement::new(
+                "k",
+                EncodedValue::Int(kind),
+            )],
+        ),
+    )
+}
+
+#[test]
+fn test_kotlin_facade_kind() -> dex::Result<()> {
+    use dex::class_kind::{ClassHeuristics, ClassKind};
+
+    // file facade, class, synthetic class, multi-file facade and multi-file class part
+    let fixtures = [
+        (2, ClassKind::KotlinFacade),
+        (1, ClassKind::Regular),
+        (3, ClassKind::Regular),
+        (4, ClassKind::KotlinFacade),
+        (5, ClassKind::KotlinFacade),
+    ];
+    for (kind, expected) in fixtures.iter() {
+        let class = ClassBuilder::new("Lcom/example/UtilsKt;")
+            .annotation_item(kotlin_metadata(*kind))
+            .build();
+        assert_eq!(class.kind()?, *expected, "k = {}", kind);
+        let without = ClassHeuristics::all() - ClassHeuristics::KOTLIN_FACADE;
+        assert_eq!(class.kind_with(without)?, ClassKind::Regular);
+    }
+    // the flags and the names come first
+    let class = ClassBuilder::new("Lcom/example/BuildConfig;")
+        .annotation_item(kotlin_metadata(2))
+        .build();
+    assert_eq!(class.kind()?, ClassKind::BuildConfig);
+    let class = ClassBuilder::new("Lcom/example/UtilsKt;").build();
+    assert_eq!(class.kind()?, ClassKind::Regular);
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Fails when the public API changes without `tests/api/public-api.txt` being updated.
+//!
+//! The API is read from the JSON output of rustdoc, which needs a nightly toolchain: the
+//! test is skipped without one, or when its JSON format isn't the one the renderer
+//! understands. After an intended change, update the snapshot with
+//! `UPDATE_API_SNAPSHOT=1 cargo test --all-features --test public_api` and review the diff.
+mod api;
+
+use std::{path::Path, process::Command};
+
+const SNAPSHOT: &str = "tests/api/public-api.txt";
+
+/// Builds the rustdoc JSON of the crate with all the features, if nightly is available.
+fn rustdoc_json() -> Option<serde_json::Value> {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // a target directory of its own, as the one of the tests is locked while they run
+    let target_dir = manifest_dir.join("target/public-api");
+    let status = Command::new("cargo")
+        .current_dir(manifest_dir)
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .env_remove("RUSTC")
+        .env_remove("RUSTDOC")
+        .args([
+            "+nightly",
+            "rustdoc",
+            "--lib",
+            "--all-features",
+            "--target-dir",
+        ])
+        .arg(&target_dir)
+        .args(["--", "-Zunstable-options", "--output-format", "json"])
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        _ => {
+            eprintln!("skipped: cannot build the rustdoc JSON with a nightly toolchain");
+            return None;
+        }
+    }
+    let json = std::fs::read(target_dir.join("doc/dex.json")).expect("cannot read rustdoc JSON");
+    let doc: serde_json::Value = serde_json::from_slice(&json).expect("bad rustdoc JSON");
+    if doc["format_version"] != api::FORMAT_VERSION {
+        eprintln!(
+            "skipped: rustdoc JSON format {} instead of {}",
+            doc["format_version"],
+            api::FORMAT_VERSION
+        );
+        return None;
+    }
+    Some(doc)
+}
+
+#[test]
+fn test_public_api_snapshot() {
+    let doc = match rustdoc_json() {
+        Some(doc) => doc,
+        None => return,
+    };
+    let mut rendered = String::new();
+    for line in api::render(&doc) {
+        rendered.push_str(&line);
+        rendered.push('\n');
+    }
+    if std::env::var_os("UPDATE_API_SNAPSHOT").is_some() {
+        std::fs::write(SNAPSHOT, &rendered).expect("cannot write snapshot");
+        return;
+    }
+    let snapshot = std::fs::read_to_string(SNAPSHOT).unwrap_or_default();
+    if rendered != snapshot {
+        let snapshot: Vec<&str> = snapshot.lines().collect();
+        let rendered: Vec<&str> = rendered.lines().collect();
+        let mut diff = String::new();
+        for line in snapshot.iter().filter(|line| !rendered.contains(line)) {
+            diff.push_str(&format!("- {}\n", line));
+        }
+        for line in rendered.iter().filter(|line| !snapshot.contains(line)) {
+            diff.push_str(&format!("+ {}\n", line));
+        }
+        panic!(
+            "the public API changed, update {} with UPDATE_API_SNAPSHOT=1 if intended:\n{}",
+            SNAPSHOT, diff
+        );
+    }
+}
@@ -4,9 +4,9 @@ use std::{
     process::Command,
 };
 
-use tempfile::TempDir;
 use dex::field::FieldId;
 use dex::method::MethodId;
+use tempfile::TempDir;
 
 struct TestBuilder {
     root: TempDir,
@@ -27,17 +27,20 @@ impl TestBuilder {
         let dest = self.root.path().join(dest);
         let src_display = src.as_ref().display();
         let dest_display: &Path = dest.as_ref();
-        fs::copy(&src, &dest).expect(&format!(
-            "unable to copy {} to {}",
-            src_display,
-            dest_display.display()
-        ));
+        fs::copy(&src, &dest).unwrap_or_else(|_| {
+            panic!(
+                "unable to copy {} to {}",
+                src_display,
+                dest_display.display()
+            )
+        });
         self.sources.push(dest);
     }
 
     pub fn add_src<P: AsRef<Path>>(&mut self, path: P, code: &str) {
         let dest = self.root.path().join(path);
-        fs::write(&dest, code).expect(&format!("unable to write code to path: {}", dest.display()));
+        fs::write(&dest, code)
+            .unwrap_or_else(|_| panic!("unable to write code to path: {}", dest.display()));
         self.sources.push(dest);
     }
 
@@ -64,14 +67,14 @@ impl TestBuilder {
             .status()
             .expect("javac failed");
         let classes = self.get_class_names();
-        assert!(classes.len() > 0);
+        assert!(!classes.is_empty());
         let _d8 = Command::new("d8")
             .args(&classes)
-            .args(&["--lib", &android_lib_path])
-            .args(&["--output", &self.root.path().display().to_string()])
+            .args(["--lib", &android_lib_path])
+            .args(["--output", &self.root.path().display().to_string()])
             .current_dir(self.root.path())
             .status()
-            .expect(&format!("'d8 {:?}' failed", &classes));
+            .unwrap_or_else(|_| panic!("'d8 {:?}' failed", &classes));
         self.root.path().join("classes.dex")
     }
 }
@@ -79,7 +82,7 @@ impl TestBuilder {
 macro_rules! assert_has_access_flags {
     ($item: ident, [ $($flag: ident),+ ], $msg:expr) => {
         $(
-            assert!($item.$flag(), $msg);
+            assert!($item.$flag(), "{}", $msg);
         )*
     };
 
@@ -240,7 +243,7 @@ test!(
         assert!(field.is_some());
         let field = field.unwrap();
 
-        let annotation_item = field.annotations().iter().find(|i| i.jtype() == "LAnnotation;");
+        let annotation_item = field.annotations().expect("bad annotations").iter().find(|i| i.jtype() == "LAnnotation;");
         assert!(annotation_item.is_some());
         let annotation_item = annotation_item.unwrap();
         assert_eq!(annotation_item.visibility(), Visibility::Build);
@@ -271,13 +274,13 @@ test!(
         let signature = signature.unwrap();
         assert_eq!(signature, "(ITT;)V".to_string());
 
-        let annotation_set_ref_list: Vec<_> = method.param_annotations().iter().collect();
+        let annotation_set_ref_list: Vec<_> = method.param_annotations().expect("bad annotations").iter().collect();
         assert_eq!(annotation_set_ref_list.len(), 2);
         let first = &annotation_set_ref_list[0].annotations()[0];
         assert_eq!(first.jtype(), runtime_annotation_class.jtype());
         assert!(&annotation_set_ref_list[1].annotations().is_empty());
 
-        let class_annotation = class.annotations().iter().find(|item| item.jtype() == "LRuntimeAnnotation;");
+        let class_annotation = class.annotations().expect("bad annotations").iter().find(|item| item.jtype() == "LRuntimeAnnotation;");
         assert!(class_annotation.is_some());
 
         let signature = class.signature();
@@ -290,6 +293,49 @@ test!(
     }
 );
 
+#[cfg(feature = "analysis")]
+test!(
+    test_annotation_usage_summary,
+    {
+        "Local.java" => r#"
+            import java.lang.annotation.Retention;
+            import java.lang.annotation.RetentionPolicy;
+            @Retention(RetentionPolicy.RUNTIME)
+            @interface Local {}
+        "#
+    };
+    {
+        "Main.java" => r#"
+            @Local
+            class Main {
+                @Local @Deprecated int first;
+                @Local int second;
+                @Local void annotated(@Local int first, @Local int second, int third) {}
+                @Deprecated void deprecated() {}
+            }
+        "#
+    },
+    |dex: dex::Dex<_>| {
+        use dex::usage::Retention;
+
+        let summary = dex.annotation_usage_summary().expect("bad summary");
+        let descriptors: Vec<&str> = summary.iter().map(|usage| usage.descriptor().as_str()).collect();
+        assert_eq!(descriptors, ["LLocal;", "Ljava/lang/Deprecated;", "Ljava/lang/annotation/Retention;"]);
+
+        let local = &summary[0];
+        assert!(local.defined_locally());
+        assert_eq!(local.retention(), Some(Retention::Runtime));
+        let counts = local.usage_counts();
+        assert_eq!((counts.class(), counts.field(), counts.method(), counts.parameter()), (1, 2, 1, 2));
+
+        let deprecated = &summary[1];
+        assert!(!deprecated.defined_locally());
+        assert_eq!(deprecated.retention(), Some(Retention::Runtime));
+        let counts = deprecated.usage_counts();
+        assert_eq!((counts.class(), counts.field(), counts.method(), counts.parameter()), (0, 1, 1, 0));
+    }
+);
+
 test!(
     test_fields,
     {
@@ -407,7 +453,7 @@ test!(
         let enum_field = find("enumField", "LDay;");
         assert!(enum_field.access_flags().is_empty());
         assert!(find_id_item(enum_field.id()).is_some());
-        
+
     }
 );
 
@@ -467,7 +513,9 @@ test!(
         assert_eq!(get_value("nbo"), Some(&EncodedValue::Boolean(false)));
         assert_eq!(get_value("c"), Some(&EncodedValue::Char(b'm'.into())));
         assert_eq!(get_value("nullString"), Some(&EncodedValue::Null));
-        assert_eq!(get_value("nonNullString"), Some(&EncodedValue::String(DexString::from("fjdljfdlj".to_string()))));
+        let non_null_string = get_value("nonNullString").expect("no value");
+        assert!(non_null_string.is_string());
+        assert!(*non_null_string == DexString::from("fjdljfdlj".to_string()));
         assert_eq!(get_value("remapper"), Some(&EncodedValue::Null));
         assert_eq!(get_value("r"), Some(&EncodedValue::Null));
         assert_eq!(get_value("array"), Some(&EncodedValue::Null));
@@ -707,13 +755,12 @@ test!(
 
         let find = |name, params: &[&str], return_type: &str| {
             let method = class.methods().find(|m| {
-                m.name() == name && 
+                m.name() == name &&
                     m.params().iter().map(|s| s.type_descriptor()).eq(params.iter()) &&
                     m.return_type() == return_type
             });
             assert!(method.is_some(), "method: {}, params: {:?}, return_type: {}", name, params, return_type);
-            let method = method.unwrap();
-            method
+            method.unwrap()
         };
 
         let find_id_item = |id: MethodId| {
@@ -798,7 +845,7 @@ test!(
         assert!(primitive_params_method.access_flags().is_empty());
         assert_eq!(primitive_params_method.shorty(), "ICSBIJZDF");
         assert!(find_id_item(primitive_params_method.id()).is_some());
-        
+
         let class_params_method = find("classParams", &["Ljava/lang/String;", "Ljava/lang/String;"], "Ljava/lang/String;");
         assert!(class_params_method.code().is_some());
         assert!(class_params_method.access_flags().is_empty());
@@ -810,7 +857,7 @@ test!(
         assert!(enum_params_method.access_flags().is_empty());
         assert_eq!(enum_params_method.shorty(), "VL");
         assert!(find_id_item(enum_params_method.id()).is_some());
-        
+
         let primitive_array_params_method = find("primitiveArrayParam", &["[J"], "V");
         assert!(primitive_array_params_method.code().is_some());
         assert!(primitive_array_params_method.access_flags().is_empty());
@@ -930,12 +977,12 @@ test!(
         "#
     },
     |dex: dex::Dex<_>| {
-        let builtin_class = dex.find_class_by_name("LBuiltInTypes;").unwrap().unwrap();
+        let _builtin_class = dex.find_class_by_name("LBuiltInTypes;").unwrap().unwrap();
 
         let find_type = |name: &str| {
             dex.types().find(|t| {
-                if let Ok(t) = t {
-                    t.to_string() == name
+                if let Ok((_, t)) = t {
+                    *t == name
                 } else {
                     false
                 }